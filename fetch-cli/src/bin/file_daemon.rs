@@ -1,36 +1,60 @@
-use std::{sync::Arc, time::Duration};
+use std::{collections::HashSet, future, sync::{Arc, Weak}, time::Duration};
 
 use camino::{Utf8Path, Utf8PathBuf};
-use crossbeam_channel::{unbounded, Receiver};
-use fetch_core::{app_config, files::{FileIndexer, index::IndexFiles}, index::provider::image::ImageIndexProvider, store::lancedb::LanceDBStore};
-use notify::{event::{CreateKind, DataChange, ModifyKind}, EventKind, RecursiveMode};
-use notify_debouncer_full::DebouncedEvent;
-use tokio::fs;
+use chrono::Local;
+use fetch_cli::{daemon, schedule, schema};
+use fetch_core::{app_config, run_sandbox_worker_if_requested, files::{FileIndexer, disk_space::{DiskSpace, DiskSpaceGuard}, governor::{ResourceGovernor, Throttle}, index::IndexFiles, journal::IndexJournal, links::{LinkResolver, ResolvedPath, SymlinkPolicy}, lock::{IndexLock, IndexLockError}, os_path::from_os_path, privacy::PrivacyMode, queue::IndexQueue, schedule::JobScheduler}, index::provider::registry::ProviderRegistry, store::lancedb::LanceDBStore, telemetry};
+use notify::{event::{CreateKind, DataChange, ModifyKind, RenameMode}, EventKind, RecursiveMode};
+use notify_debouncer_full::{DebounceEventResult, DebouncedEvent};
+use tokio::{fs, sync::{Mutex, mpsc::{self, UnboundedReceiver}}};
 use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, info, warn};
+
+fn main() -> Result<(), ()> {
+    // Before the runtime is started, as sandbox workers parse their file and exit without one
+    run_sandbox_worker_if_requested();
+    run_daemon()
+}
 
 #[tokio::main]
-async fn main() -> Result<(), ()>{
+async fn run_daemon() -> Result<(), ()> {
     let worker_count = 4;
 
-    // Create a channel to receive file change events
-    let (tx, rx) = unbounded();
+    if let Err(e) = telemetry::init() {
+        eprintln!("Failed to initialize logging: {e:?}");
+        return Err(());
+    }
+
+    // Machines querying a shared index must not write to it, the machine indexing into it runs the daemon
+    if app_config::is_index_read_only() {
+        error!("The index is read-only on this machine (read_only_index in data.toml), not starting the daemon");
+        return Err(());
+    }
+
+    // Create a channel to receive file change events. The workers wait on it without blocking the runtime, which
+    // also runs the query server and the other workers of the daemon.
+    let (tx, rx) = mpsc::unbounded_channel();
+    let rx = Arc::new(Mutex::new(rx));
 
     // Create a watcher object
-    let watcher_debouncer = notify_debouncer_full::new_debouncer(Duration::from_secs(2), None, tx);
+    let watcher_debouncer = notify_debouncer_full::new_debouncer(Duration::from_secs(2), None,
+        move |event_message: DebounceEventResult| {
+            let _ = tx.send(event_message);
+        });
     if watcher_debouncer.is_err() {
-        eprintln!("Failed to create watcher: {:?}", watcher_debouncer.err());
+        error!("Failed to create watcher: {:?}", watcher_debouncer.err());
         return Err(());
     }
     let mut watcher_debouncer = watcher_debouncer.unwrap();
 
     // Read paths from configuration file
     let watchlist_file = app_config::get_watchlist_file_path();
-    println!("Reading watchlist file from: {watchlist_file}");
+    info!("Reading watchlist file from: {watchlist_file}");
 
     // Read the watchlist file
     let watchlist = fs::read_to_string(watchlist_file).await;
     if watchlist.is_err() {
-        eprintln!("Failed to read watchlist file: {:?}", watchlist.err());
+        error!("Failed to read watchlist file: {:?}", watchlist.err());
         return Err(());
     }
     let watchlist = watchlist.unwrap();
@@ -43,133 +67,435 @@ async fn main() -> Result<(), ()>{
         let path = path.canonicalize_utf8()
             .unwrap_or_else(|e| panic!("Failed to canonicalize path: {path}, error: {e}"));
         watcher_debouncer.watch(path.as_std_path(), RecursiveMode::Recursive)
-            .unwrap_or_else(|e| eprintln!("Failed to watch path: {path}, error: {e}"));
+            .unwrap_or_else(|e| error!("Failed to watch path: {path}, error: {e}"));
     }
 
-    println!("File change tracking daemon is initiating workers...");
+    info!("File change tracking daemon is initiating workers...");
 
     let data_directory = app_config::get_default_index_directory();
-    let siglip_store = Arc::new(LanceDBStore::local_full(
+    // Files queued by other processes may be of any supported type, so the daemon uses every registered provider
+    let registry = ProviderRegistry::open(&data_directory).await
+        .unwrap_or_else(|e| panic!("Could not open index providers with data dir: ./data_dir. Error: {e:?}"));
+    let journal_store = LanceDBStore::local_with_filters(
         data_directory.as_str(),
-        "siglip2_chunkfile".to_owned()
+        "index_journal".to_owned()
     ).await
-    .unwrap_or_else(|e| panic!("Could not open lancedb store with data dir: ./data_dir. Error: {e:?}")));
-    let basic_image = ImageIndexProvider::using(siglip_store);
-    let file_indexer = FileIndexer::with(vec![Arc::new(basic_image)]);
+    .unwrap_or_else(|e| panic!("Could not open lancedb store for the index journal with data dir: ./data_dir. Error: {e:?}"));
+    let file_indexer = FileIndexer::with(registry.providers())
+        .with_journal(Arc::new(IndexJournal::using(journal_store)))
+        .with_provider_budgets(registry.budgets());
+
+    let symlink_policy = SymlinkPolicy::from_settings().unwrap_or_else(|e| {
+        warn!("Could not load symlink policy, following symlinks: {e:?}");
+        SymlinkPolicy::default()
+    });
+
+    let governor = Arc::new(ResourceGovernor::from_settings());
+    info!("Index throttle: {:?}", governor.settings().mode);
+    let disk_space = Arc::new(DiskSpaceGuard::from_settings());
 
     let mut handles = Vec::with_capacity(worker_count);
     let cancellation_token = CancellationToken::new();
+    let index_lock = DaemonIndexLock::new(data_directory.clone());
 
     for i in 0..worker_count {
-        println!("starting worker {i}...");
+        info!("starting worker {i}...");
         let rx_clone = rx.clone();
         let token_clone = cancellation_token.clone();
         let file_indexer_clone = file_indexer.clone();
-        let handle = tokio::spawn(worker_main(rx_clone, file_indexer_clone, token_clone));
+        let handle = tokio::spawn(worker_main(rx_clone, file_indexer_clone, symlink_policy, governor.clone(),
+            disk_space.clone(), index_lock.clone(), token_clone));
 
         handles.push(handle);
     }
+    info!("starting index queue worker...");
+    handles.push(tokio::spawn(queue_main(file_indexer.clone(), governor.clone(), disk_space.clone(),
+        index_lock.clone(), cancellation_token.clone())));
+    info!("starting backfill worker...");
+    handles.push(tokio::spawn(backfill_main(index_lock.clone(), cancellation_token.clone())));
 
-    match tokio::signal::ctrl_c().await {
-        Ok(_) => println!("Received Ctrl+C, shutting down..."),
-        Err(e) => eprintln!("Failed to listen for Ctrl+C: {e:?}"),
+    // Queries from the CLI are answered here so they do not have to load the index and models themselves
+    info!("starting query server...");
+    let query_server_token = cancellation_token.clone();
+    let query_server = tokio::spawn(async move {
+        if let Err(e) = daemon::serve_queries(&data_directory, query_server_token).await {
+            error!("Query server stopped, queries will open the index themselves: {e:?}");
+        }
+    });
+
+    // Maintenance jobs run at the times set in the settings while the daemon runs
+    let scheduler = JobScheduler::from_settings().unwrap_or_else(|e| {
+        warn!("Could not load scheduled jobs, running none: {e:?}");
+        JobScheduler::default()
+    });
+    for (job, next_run) in scheduler.next_runs(Local::now()) {
+        info!("Scheduled {} job ({}), next run: {next_run:?}", job.kind, job.schedule.expression());
+    }
+
+    tokio::select! {
+        signal = tokio::signal::ctrl_c() => match signal {
+            Ok(_) => info!("Received Ctrl+C, shutting down..."),
+            Err(e) => error!("Failed to listen for Ctrl+C: {e:?}"),
+        },
+        _ = scheduler_main(&scheduler, &file_indexer, &index_lock) => (),
+    }
+
+    // Stops the query server answering queries, so the CLI stops trying to query the daemon
+    cancellation_token.cancel();
+    if let Err(e) = query_server.await {
+        error!("Query server failed to stop: {e:?}");
     }
 
     Ok(())
 }
 
-async fn worker_main<I: IndexFiles>(rx: Receiver<Result<Vec<DebouncedEvent>, Vec<notify::Error>>>,
-    file_indexer: I, _cancellation_token: CancellationToken) {
-    while let Ok(event_message) = rx.recv() {
+/// How often workers check whether privacy mode has been turned off
+const PRIVACY_MODE_POLL_INTERVAL: Duration = Duration::from_secs(5);
+/// How often the queue worker checks for files queued by processes that found the index busy
+const INDEX_QUEUE_POLL_INTERVAL: Duration = Duration::from_secs(10);
+/// How often indexing paused by the resource governor checks whether it may resume
+const GOVERNOR_POLL_INTERVAL: Duration = Duration::from_secs(30);
+/// How often indexing paused for low disk space checks whether space has been freed
+const DISK_SPACE_POLL_INTERVAL: Duration = Duration::from_secs(60);
+/// How often the backfill worker checks for tag keys promoted with `fetch schema promote-tag`
+const BACKFILL_POLL_INTERVAL: Duration = Duration::from_secs(60);
+/// Longest the scheduler sleeps at once, so it catches up with clock changes, eg. after the computer wakes up
+const SCHEDULER_POLL_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The daemon's hold on the index lock, shared by its workers so they index alongside each other instead of taking
+/// turns with the lock. The lock is taken when the first worker needs it and released once the last worker holding
+/// it is done, so other processes can write to the index while the daemon is idle.
+#[derive(Clone)]
+struct DaemonIndexLock {
+    data_directory: Utf8PathBuf,
+    held: Arc<Mutex<Weak<IndexLock>>>,
+}
+
+impl DaemonIndexLock {
+    fn new(data_directory: Utf8PathBuf) -> DaemonIndexLock {
+        DaemonIndexLock { data_directory, held: Arc::new(Mutex::new(Weak::new())) }
+    }
+
+    /// Joins the workers holding the lock, or takes it if none does, waiting for any other process writing to the
+    /// index, eg. the GUI, to finish first
+    async fn acquire(&self) -> Result<Arc<IndexLock>, IndexLockError> {
+        let mut held = self.held.lock().await;
+        if let Some(index_lock) = held.upgrade() {
+            return Ok(index_lock);
+        }
+        let index_lock = Arc::new(IndexLock::acquire(&self.data_directory, "fetch daemon", None).await?);
+        *held = Arc::downgrade(&index_lock);
+        Ok(index_lock)
+    }
+}
+
+async fn worker_main<I: IndexFiles>(rx: Arc<Mutex<UnboundedReceiver<DebounceEventResult>>>,
+    file_indexer: I, symlink_policy: SymlinkPolicy, governor: Arc<ResourceGovernor>, disk_space: Arc<DiskSpaceGuard>,
+    index_lock: DaemonIndexLock, _cancellation_token: CancellationToken) {
+    // The receiver is only locked while waiting for the next message, so the workers handle messages in parallel
+    while let Some(event_message) = next_message(&rx).await {
         if event_message.is_err() {
-            eprintln!("Worker received error: {:?}", event_message.err());
+            warn!("Worker received error: {:?}", event_message.err());
             continue;
         }
         let events = event_message.unwrap();
 
-        for event in events {
-            handle_event(&file_indexer, event).await;
+        // Changes made while privacy mode is on are held until it is turned off, rather than dropped
+        if PrivacyMode::enabled() {
+            info!("Privacy mode is on, holding file changes until it is turned off");
+            while PrivacyMode::enabled() {
+                tokio::time::sleep(PRIVACY_MODE_POLL_INTERVAL).await;
+            }
+            info!("Privacy mode turned off, resuming indexing");
+        }
+        wait_for_governor(&governor).await;
+        wait_for_disk_space(&disk_space).await;
+
+        // Changes wait for any other process writing to the index, eg. the GUI, to finish first
+        let _index_lock = match index_lock.acquire().await {
+            Ok(index_lock) => index_lock,
+            Err(e) => {
+                error!("Could not lock index, dropping file changes: {e:?}");
+                continue;
+            },
+        };
+        for event in detect_renames(&file_indexer, events).await {
+            handle_event(&file_indexer, symlink_policy, event).await;
+        }
+    }
+}
+
+async fn next_message(rx: &Mutex<UnboundedReceiver<DebounceEventResult>>) -> Option<DebounceEventResult> {
+    rx.lock().await.recv().await
+}
+
+/// Indexes the files queued by processes that found the index busy, see IndexQueue
+async fn queue_main<I: IndexFiles>(file_indexer: I, governor: Arc<ResourceGovernor>, disk_space: Arc<DiskSpaceGuard>,
+    index_lock: DaemonIndexLock, _cancellation_token: CancellationToken) {
+    loop {
+        tokio::time::sleep(INDEX_QUEUE_POLL_INTERVAL).await;
+        if PrivacyMode::enabled() {
+            continue;
+        }
+        match IndexQueue::load() {
+            Ok(queue) if queue.is_empty() => continue,
+            Ok(_) => (),
+            Err(e) => {
+                warn!("Could not load index queue: {e:?}");
+                continue;
+            },
+        }
+
+        let _index_lock = match index_lock.acquire().await {
+            Ok(index_lock) => index_lock,
+            Err(e) => {
+                error!("Could not lock index, leaving files queued: {e:?}");
+                continue;
+            },
+        };
+        // The queue is loaded again once the lock is held, so files queued while waiting for it are included
+        let paths = match IndexQueue::load() {
+            Ok(queue) => queue.paths().to_vec(),
+            Err(e) => {
+                warn!("Could not load index queue: {e:?}");
+                continue;
+            },
+        };
+
+        // Each file stays queued until it has been indexed, so files are not lost if the daemon stops part way
+        info!("Indexing {} queued files", paths.len());
+        for path in paths {
+            wait_for_governor(&governor).await;
+            wait_for_disk_space(&disk_space).await;
+            match file_indexer.index(&path, None).await {
+                Ok(_) => info!("Queued file indexed successfully: {path}"),
+                Err(e) => error!("Error indexing queued file {path}: {e:?}"),
+            }
+            if let Err(e) = IndexQueue::dequeue([&path]) {
+                warn!("Could not remove {path} from index queue: {e:?}");
+            }
         }
     }
 }
 
-async fn handle_event<I: IndexFiles>(file_indexer: &I, debounced_event: DebouncedEvent) {
+/// Backfills tag keys promoted since the daemon last checked, see LanceDBSchema. Filters on a key search the tags
+/// until it is backfilled, so backfilling runs alongside indexing rather than holding it up.
+async fn backfill_main(index_lock: DaemonIndexLock, _cancellation_token: CancellationToken) {
+    let data_directory = app_config::get_default_index_directory();
+    loop {
+        tokio::time::sleep(BACKFILL_POLL_INTERVAL).await;
+        match schema::backfill(&data_directory, "fetch daemon (backfill)", || index_lock.acquire()).await {
+            Ok(0) => (),
+            Ok(num_rows) => info!("Backfilled {num_rows} rows of promoted tag keys"),
+            Err(e) => error!("Could not backfill promoted tag keys: {e:?}"),
+        }
+    }
+}
+
+/// Runs the scheduled jobs as they come due, see JobScheduler. Never returns, even if there are no jobs.
+async fn scheduler_main<I: IndexFiles>(scheduler: &JobScheduler, file_indexer: &I, index_lock: &DaemonIndexLock) {
+    let mut last_run = None;
+    loop {
+        // Jobs are not run twice in the same minute, however quickly they finish
+        let after = last_run.map_or(Local::now(), |last_run| Local::now().max(last_run));
+        let Some((next_run, jobs)) = scheduler.next_due(after) else {
+            return future::pending().await;
+        };
+        let wait = (next_run - Local::now()).to_std().unwrap_or_default();
+        if wait > SCHEDULER_POLL_INTERVAL {
+            tokio::time::sleep(SCHEDULER_POLL_INTERVAL).await;
+            continue;
+        }
+        tokio::time::sleep(wait).await;
+        last_run = Some(next_run);
+
+        if PrivacyMode::enabled() {
+            info!("Privacy mode is on, skipping scheduled jobs due at {next_run}");
+            continue;
+        }
+        for job in jobs {
+            match schedule::run_job(job, file_indexer, || index_lock.acquire()).await {
+                Ok(()) => info!("Scheduled {job} job finished"),
+                Err(e) => error!("Scheduled {job} job failed: {e:?}"),
+            }
+        }
+    }
+}
+
+/// Waits until the resource governor allows indexing, slowing down on battery or under load, or holding changes
+/// until the computer is idle and plugged in, depending on the index throttle settings
+async fn wait_for_governor(governor: &ResourceGovernor) {
+    match governor.check() {
+        Throttle::Run => (),
+        Throttle::SlowDown(delay) => {
+            debug!("On battery or under load, slowing down indexing by {delay:?}");
+            tokio::time::sleep(delay).await;
+        },
+        Throttle::Pause => {
+            info!("Holding file changes until the computer is idle and plugged in");
+            while governor.check() == Throttle::Pause {
+                tokio::time::sleep(GOVERNOR_POLL_INTERVAL).await;
+            }
+            info!("Computer is idle and plugged in, resuming indexing");
+        },
+    }
+}
+
+/// Holds file changes while the volume of the index or chunk directory is low on free space, until space is freed
+async fn wait_for_disk_space(disk_space: &DiskSpaceGuard) {
+    if let DiskSpace::Low { directory, free_bytes, .. } = disk_space.check() {
+        warn!("Only {free_bytes} bytes free for {directory}, holding file changes until disk space is freed");
+        while disk_space.check() != DiskSpace::Enough {
+            tokio::time::sleep(DISK_SPACE_POLL_INTERVAL).await;
+        }
+        info!("Disk space freed, resuming indexing");
+    }
+}
+
+async fn handle_event<I: IndexFiles>(file_indexer: &I, symlink_policy: SymlinkPolicy, debounced_event: DebouncedEvent) {
     match debounced_event.event.kind {
         EventKind::Create(CreateKind::File) => {
-            let file_path = <&Utf8Path>::try_from(debounced_event.event.paths.first()
-                .expect("Expected at least one path for create file event")
-                .as_path())
-                .expect("Expected path to be valid UTF-8");
-            println!("File created: {file_path}");
+            let file_path = &from_os_path(debounced_event.event.paths.first()
+                .expect("Expected at least one path for create file event"));
+            info!("File created: {file_path}");
+            let file_path = match resolve_event_path(file_path, symlink_policy) {
+                Some(file_path) => file_path,
+                None => return,
+            };
 
             // index file
-            let result = file_indexer.index(file_path, None).await;
+            let result = file_indexer.index(&file_path, None).await;
             match result {
-                Ok(_) => println!("File indexed successfully: {file_path}"),
-                Err(e) => eprintln!("Error indexing file {file_path}: {e:?}"),
+                Ok(_) => info!("File indexed successfully: {file_path}"),
+                Err(e) => error!("Error indexing file {file_path}: {e:?}"),
             }
         },
         EventKind::Modify(ModifyKind::Data(DataChange::Any)) => {
-            let file_path = <&Utf8Path>::try_from(debounced_event.event.paths.first()
-                .expect("Expected at least one path for modify data event")
-                .as_path())
-                .expect("Expected path to be valid UTF-8");
-            println!("File modified: {file_path:?}");
+            let file_path = &from_os_path(debounced_event.event.paths.first()
+                .expect("Expected at least one path for modify data event"));
+            info!("File modified: {file_path:?}");
+            let file_path = match resolve_event_path(file_path, symlink_policy) {
+                Some(file_path) => file_path,
+                None => return,
+            };
 
             // re-index file
-            let result = file_indexer.index(file_path, None).await;
+            let result = file_indexer.index(&file_path, None).await;
             match result {
-                Ok(_) => println!("File updated successfully: {file_path}"),
-                Err(e) => eprintln!("Error indexing file {file_path}: {e:?}"),
+                Ok(_) => info!("File updated successfully: {file_path}"),
+                Err(e) => error!("Error indexing file {file_path}: {e:?}"),
             }
         },
         EventKind::Modify(ModifyKind::Name(rename_mode)) => {
-            println!("File renamed: {:?} with mode: {:?}", debounced_event.event.paths, rename_mode);
-            let first_file_path = <&Utf8Path>::try_from(debounced_event.event.paths.first()
-                .expect("Expected at least one path for modify name event")
-                .as_path())
-                .expect("Expected path to be valid UTF-8");
+            info!("File renamed: {:?} with mode: {:?}", debounced_event.event.paths, rename_mode);
+            let first_file_path = &from_os_path(debounced_event.event.paths.first()
+                .expect("Expected at least one path for modify name event"));
             let second_file_path = debounced_event.event.paths.get(1)
-                .map(|p| <&Utf8Path>::try_from(p.as_path()).expect("Expected path to be valid UTF-8"));
+                .map(|p| from_os_path(p));
             if let Some(second_file_path) = second_file_path {
-                println!("Two paths found. File renamed: {:?} to {:?}", first_file_path, second_file_path);
-                let clear_future = file_indexer.clear(first_file_path, None);
-                let index_future = file_indexer.index(second_file_path, None);
-                match clear_future.await {
-                    Ok(_) => println!("File cleared from index: {first_file_path}"),
-                    Err(e) => eprintln!("Error clearing file {first_file_path}: {e:?}"),
-                }
-                match index_future.await {
-                    Ok(_) => println!("File indexed successfully: {:?}", second_file_path),
-                    Err(e) => eprintln!("Error indexing file {}: {:?}", second_file_path, e),
+                info!("Two paths found. File renamed: {:?} to {:?}", first_file_path, second_file_path);
+                match resolve_event_path(&second_file_path, symlink_policy) {
+                    Some(second_file_path) => match file_indexer.rename(first_file_path, &second_file_path).await {
+                        Ok(_) => info!("File index moved successfully: {} to {}", first_file_path, second_file_path),
+                        Err(e) => error!("Error moving index of file {} to {}: {:?}", first_file_path, second_file_path, e),
+                    },
+                    None => match file_indexer.clear(first_file_path, None).await {
+                        Ok(_) => info!("File cleared from index: {first_file_path}"),
+                        Err(e) => error!("Error clearing file {first_file_path}: {e:?}"),
+                    },
                 }
             } else {
-                println!("File renamed: {first_file_path:?}. Unknown whether this is the 'to' or 'from' name.");
+                info!("File renamed: {first_file_path:?}. Unknown whether this is the 'to' or 'from' name.");
                 let result = file_indexer.index(first_file_path, None).await;
                 match result {
-                    Ok(_) => println!("File updated successfully (could be delete): {first_file_path}"),
-                    Err(e) => eprintln!("Error indexing file {first_file_path}: {e:?}"),
+                    Ok(_) => info!("File updated successfully (could be delete): {first_file_path}"),
+                    Err(e) => error!("Error indexing file {first_file_path}: {e:?}"),
                 }
             }
         },
         EventKind::Remove(_) => {
-            let file_path = <&Utf8Path>::try_from(debounced_event.event.paths.first()
-                .expect("Expected at least one path for delete file event")
-                .as_path())
-                .expect("Expected path to be valid UTF-8");
-            println!("File removed: {file_path:?}");
+            let file_path = &from_os_path(debounced_event.event.paths.first()
+                .expect("Expected at least one path for delete file event"));
+            info!("File removed: {file_path:?}");
 
             let result = file_indexer.clear(file_path, None).await;
             match result {
-                Ok(_) => println!("File cleared from index: {file_path}"),
-                Err(e) => eprintln!("Error clearing file {file_path}: {e:?}"),
+                Ok(_) => info!("File cleared from index: {file_path}"),
+                Err(e) => error!("Error clearing file {file_path}: {e:?}"),
             }
         },
         EventKind::Access(_) => {
-            println!("File(s) accessed: {:?}, ignoring", debounced_event.event.paths);
+            debug!("File(s) accessed: {:?}, ignoring", debounced_event.event.paths);
         },
         _ => {
-            eprintln!("Unhandled event kind: {:?}", debounced_event.event.kind);
+            warn!("Unhandled event kind: {:?}", debounced_event.event.kind);
+        },
+    }
+}
+
+/// Pairs up the halves of renames reported as events of their own, eg. a file moved between watched directories is
+/// reported as removed from one and created in the other. Each file that appeared is looked up by its file id (see
+/// IndexFiles::indexed_path), and if it was indexed under a path that no longer exists, its index is moved to the new
+/// path rather than cleared and indexed again. Returns the events left to handle.
+async fn detect_renames<I: IndexFiles>(file_indexer: &I, events: Vec<DebouncedEvent>) -> Vec<DebouncedEvent> {
+    let mut renamed = HashSet::new();
+    for event in &events {
+        let is_arrival = matches!(event.event.kind,
+            EventKind::Create(CreateKind::File) | EventKind::Modify(ModifyKind::Name(RenameMode::To | RenameMode::Any)));
+        let path = match event.event.paths.as_slice() {
+            [path] if is_arrival => from_os_path(path),
+            _ => continue,
+        };
+        // Symlinks are left to handle_event, which resolves them according to the symlink policy
+        match fs::symlink_metadata(&event.event.paths[0]).await {
+            Ok(metadata) if metadata.is_file() => (),
+            _ => continue,
+        }
+
+        let indexed_path = match file_indexer.indexed_path(&path).await {
+            Ok(Some(indexed_path)) => indexed_path,
+            Ok(None) => continue,
+            Err(e) => {
+                warn!("Could not look up whether {path} was moved, handling it as a new file: {e:?}");
+                continue;
+            },
+        };
+        info!("File moved: {indexed_path} to {path}");
+        match file_indexer.rename(&indexed_path, &path).await {
+            Ok(_) => info!("File index moved successfully: {indexed_path} to {path}"),
+            Err(e) => error!("Error moving index of file {indexed_path} to {path}: {e:?}"),
+        }
+        renamed.insert(indexed_path);
+        renamed.insert(path);
+    }
+
+    events.into_iter()
+        .filter(|event| !matches!(event.event.kind,
+                EventKind::Create(_) | EventKind::Remove(_) | EventKind::Modify(ModifyKind::Name(_)))
+            || !event.event.paths.iter().all(|path| renamed.contains(&from_os_path(path))))
+        .collect()
+}
+
+/// Resolves the path of a changed file according to the symlink policy, returning None if it should not be indexed.
+/// Links that are followed resolve to the canonical path of their target, so a file changed through several links
+/// is always indexed under the same path.
+fn resolve_event_path(path: &Utf8Path, symlink_policy: SymlinkPolicy) -> Option<Utf8PathBuf> {
+    // Every change is resolved on its own, as the same file is expected to change more than once
+    let mut link_resolver = LinkResolver::using(symlink_policy);
+    match link_resolver.resolve(path) {
+        ResolvedPath::File(resolved) => {
+            if let Err(e) = link_resolver.record_aliases() {
+                warn!("Could not record the name of symlink {path} as a tag of its target: {e:?}");
+            }
+            Some(resolved)
+        },
+        ResolvedPath::Directory(_) => {
+            debug!("Path {path} is a directory, ignoring");
+            None
+        },
+        ResolvedPath::Skip { reason } => {
+            info!("{reason}, ignoring: {path}");
+            None
         },
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,110 @@
+use std::{error::Error, fs, path::{self, Path, PathBuf}};
+
+use camino::Utf8PathBuf;
+use fetch_core::{app_config, files::{collection::{CollectionItem, FileCollections}, os_path::from_os_path}, store::lancedb::LanceDBStore};
+use normalize_path::NormalizePath;
+
+pub enum CollectionArgs {
+    /// List every collection, or the files in a collection if a name is given
+    List { name: Option<String> },
+    /// Save a file into a collection
+    Add { name: String, path: PathBuf },
+    /// Remove a file from a collection
+    Remove { name: String, path: PathBuf },
+    /// Copy the files in a collection into a directory
+    Export { name: String, destination: PathBuf },
+}
+
+pub async fn collection(args: CollectionArgs) -> Result<(), Box<dyn Error>> {
+    let data_dir = app_config::get_default_index_directory();
+    let store = LanceDBStore::<CollectionItem>::local_with_filters(data_dir.as_str(), "collection".to_owned()).await
+        .unwrap_or_else(|e|
+            panic!("Could not open lancedb store for collections with data dir: {}. Error: {e:?}",
+            data_dir.as_str()));
+    let collections = FileCollections::using(store);
+
+    match args {
+        CollectionArgs::List { name: None } => {
+            let summaries = collections.list().await?;
+            if summaries.is_empty() {
+                println!("No collections have been created yet");
+            }
+            for summary in summaries {
+                println!("{} ({} file(s))", summary.name, summary.num_items);
+            }
+        },
+        CollectionArgs::List { name: Some(name) } => {
+            let items = collections.items(&name).await?;
+            if items.is_empty() {
+                println!("Collection {} is empty", name);
+            }
+            for item in items {
+                match item.query {
+                    Some(query) => println!("{} (found with \"{}\")", item.file, query),
+                    None => println!("{}", item.file),
+                }
+            }
+        },
+        CollectionArgs::Add { name, path } => {
+            let path = to_utf8_path(&path)?;
+            collections.add(&name, &path, None).await?;
+            println!("Saved {} into collection {}", path, name);
+        },
+        CollectionArgs::Remove { name, path } => {
+            let path = to_utf8_path(&path)?;
+            collections.remove(&name, &path).await?;
+            println!("Removed {} from collection {}", path, name);
+        },
+        CollectionArgs::Export { name, destination } => {
+            let items = collections.items(&name).await?;
+            if items.is_empty() {
+                println!("Collection {} is empty, nothing to export", name);
+                return Ok(());
+            }
+
+            let destination = to_utf8_path(&destination)?;
+            fs::create_dir_all(&destination)?;
+            let mut exported = 0;
+            for item in &items {
+                let file_name = match item.file.file_name() {
+                    Some(file_name) => file_name,
+                    None => continue,
+                };
+                if !item.file.is_file() {
+                    println!("Skipping {}, file no longer exists", item.file);
+                    continue;
+                }
+
+                let target = unique_target(&destination, file_name);
+                fs::copy(&item.file, &target)?;
+                exported += 1;
+            }
+            println!("Exported {} of {} file(s) from collection {} to {}", exported, items.len(), name, destination);
+        },
+    }
+
+    Ok(())
+}
+
+// Private functions
+
+fn to_utf8_path(path: &Path) -> Result<Utf8PathBuf, Box<dyn Error>> {
+    Ok(from_os_path(&path::absolute(path)?.normalize()))
+}
+
+/// Returns a path for the file name in the directory that does not exist yet, numbering the file if files with
+/// the same name were saved from different folders
+fn unique_target(directory: &Utf8PathBuf, file_name: &str) -> Utf8PathBuf {
+    let target = directory.join(file_name);
+    if !target.exists() {
+        return target;
+    }
+
+    let name = Utf8PathBuf::from(file_name);
+    let stem = name.file_stem().unwrap_or(file_name);
+    let extension = name.extension().map(|e| format!(".{}", e)).unwrap_or_default();
+    (1..)
+        .map(|i| directory.join(format!("{} ({}){}", stem, i, extension)))
+        .find(|target| !target.exists())
+        .expect("There should always be an unused numbered file name")
+}
@@ -0,0 +1,215 @@
+use std::{collections::hash_map::RandomState, hash::{BuildHasher, Hasher}, io, net::Ipv4Addr, sync::Arc, time::{Duration, SystemTime}};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use fetch_core::{app_config, files::{FileQueryer, pagination::QueryCursor, query::{MatchSource, QueryResult}}, store::lancedb::LanceDBStore};
+use serde::{Deserialize, Serialize};
+use tokio::{fs, io::{AsyncBufReadExt, AsyncWriteExt, BufReader}, net::{TcpListener, TcpStream}, time::timeout};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, warn};
+
+use crate::query::{CollapseMode, ResultGroup, aggregate_results, open_file_queryer};
+
+/// Results of a query answered by the daemon
+pub struct DaemonQueryResults {
+    pub results: Vec<ResultGroup>,
+    pub corrected_query: Option<String>,
+}
+
+/// Answers queries from the CLI until cancelled, so queries do not have to open the index and load the models
+/// themselves. Listens on a local port, which is written along with a token queries must present to the daemon port
+/// file (see app_config::get_daemon_port_file_path), and removed from it once cancelled.
+pub async fn serve_queries(data_dir: &Utf8Path, cancellation_token: CancellationToken) -> Result<(), io::Error> {
+    let queryer = Arc::new(open_file_queryer(data_dir.as_str()).await);
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await?;
+    let port = listener.local_addr()?.port();
+    let token = generate_token();
+
+    let port_file = app_config::get_daemon_port_file_path();
+    if let Some(parent) = port_file.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+    fs::write(&port_file, format!("{} {}", port, token)).await?;
+    info!("Answering queries on port {}", port);
+
+    loop {
+        tokio::select! {
+            _ = cancellation_token.cancelled() => break,
+            accepted = listener.accept() => match accepted {
+                Ok((stream, _)) => {
+                    let queryer = queryer.clone();
+                    let token = token.clone();
+                    tokio::spawn(async move {
+                        if let Err(e) = answer_query(stream, &queryer, &token).await {
+                            warn!("Could not answer query: {:?}", e);
+                        }
+                    });
+                },
+                Err(e) => warn!("Could not accept query connection: {:?}", e),
+            },
+        }
+    }
+
+    fs::remove_file(&port_file).await
+}
+
+/// Queries the daemon for the results of the query, returning None if the daemon is not running, or could not
+/// answer the query, so the caller can fall back to querying the index itself
+pub async fn query_daemon(query: &str, num_results: u32, chunks_per_query: u32, collapse: CollapseMode)
+    -> Option<DaemonQueryResults>
+{
+    let port_file = app_config::get_daemon_port_file_path();
+    let (port, token) = match read_port_file(&port_file).await {
+        Some(port_and_token) => port_and_token,
+        None => {
+            debug!("Daemon is not answering queries, no port in {}", port_file);
+            return None;
+        },
+    };
+
+    let request = QueryRequest { token, query: query.to_owned(), num_results, chunks_per_query, collapse };
+    match timeout(QUERY_TIMEOUT, send_query(port, &request)).await {
+        Ok(Ok(Ok(results))) => Some(DaemonQueryResults {
+            results: results.results.into_iter().map(ResultGroup::from).collect(),
+            corrected_query: results.corrected_query,
+        }),
+        Ok(Ok(Err(e))) => {
+            warn!("Daemon could not answer query, querying index instead: {}", e);
+            None
+        },
+        Ok(Err(e)) => {
+            debug!("Could not query daemon on port {}, querying index instead: {:?}", port, e);
+            None
+        },
+        Err(_) => {
+            warn!("Daemon did not answer query in time, querying index instead");
+            None
+        },
+    }
+}
+
+// Private functions and structs
+
+/// How long connecting to the daemon may take before querying the index instead. The daemon listens on the local
+/// machine, so this only runs out if the port file is stale and its port taken by another process.
+const CONNECT_TIMEOUT: Duration = Duration::from_millis(100);
+/// How long the daemon may take to answer a query before querying the index instead
+const QUERY_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Serialize, Deserialize)]
+struct QueryRequest {
+    token: String,
+    query: String,
+    num_results: u32,
+    chunks_per_query: u32,
+    collapse: CollapseMode,
+}
+
+#[derive(Serialize, Deserialize)]
+struct QueryResponse {
+    results: Vec<ResponseResult>,
+    corrected_query: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ResponseResult {
+    path: Utf8PathBuf,
+    score: f32,
+    rank: u32,
+    /// The providers and channels that found the result, highest scoring first
+    #[serde(default)]
+    sources: Vec<MatchSource>,
+    /// Results collapsed into this one, see CollapseMode
+    collapsed: Vec<ResponseResult>,
+}
+
+impl From<QueryResult> for ResponseResult {
+    fn from(result: QueryResult) -> Self {
+        ResponseResult {
+            path: result.path,
+            score: result.score,
+            rank: result.rank,
+            sources: result.sources,
+            collapsed: vec![],
+        }
+    }
+}
+
+impl From<ResponseResult> for QueryResult {
+    fn from(result: ResponseResult) -> Self {
+        QueryResult { old_rank: None, rank: result.rank, path: result.path, score: result.score, sources: result.sources }
+    }
+}
+
+impl From<ResultGroup> for ResponseResult {
+    fn from(group: ResultGroup) -> Self {
+        ResponseResult {
+            collapsed: group.collapsed.into_iter().map(ResponseResult::from).collect(),
+            ..ResponseResult::from(group.result)
+        }
+    }
+}
+
+impl From<ResponseResult> for ResultGroup {
+    fn from(mut result: ResponseResult) -> Self {
+        let collapsed = std::mem::take(&mut result.collapsed).into_iter().map(QueryResult::from).collect();
+        ResultGroup { result: QueryResult::from(result), collapsed }
+    }
+}
+
+/// Reads one request line from the connection and writes back the response line
+async fn answer_query(stream: TcpStream, queryer: &FileQueryer<LanceDBStore<QueryCursor>>, token: &str)
+    -> Result<(), io::Error>
+{
+    let (reader, mut writer) = stream.into_split();
+    let mut line = String::new();
+    BufReader::new(reader).read_line(&mut line).await?;
+    let request: QueryRequest = serde_json::from_str(&line).map_err(io::Error::other)?;
+    if request.token != token {
+        return Err(io::Error::new(io::ErrorKind::PermissionDenied, "Query does not have the daemon's token"));
+    }
+
+    debug!("Answering query: {}", request.query);
+    let response: Result<QueryResponse, String> =
+        match aggregate_results(queryer, &request.query, request.num_results, request.chunks_per_query,
+            request.collapse).await
+        {
+            Ok((results, corrected_query)) => Ok(QueryResponse {
+                results: results.into_iter().map(ResponseResult::from).collect(),
+                corrected_query,
+            }),
+            Err(e) => Err(e.to_string()),
+        };
+    let mut response_line = serde_json::to_string(&response).map_err(io::Error::other)?;
+    response_line.push('\n');
+    writer.write_all(response_line.as_bytes()).await
+}
+
+async fn send_query(port: u16, request: &QueryRequest) -> Result<Result<QueryResponse, String>, io::Error> {
+    let stream = timeout(CONNECT_TIMEOUT, TcpStream::connect((Ipv4Addr::LOCALHOST, port))).await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "Timed out connecting to daemon"))??;
+    let (reader, mut writer) = stream.into_split();
+    let mut request_line = serde_json::to_string(request).map_err(io::Error::other)?;
+    request_line.push('\n');
+    writer.write_all(request_line.as_bytes()).await?;
+
+    let mut line = String::new();
+    BufReader::new(reader).read_line(&mut line).await?;
+    serde_json::from_str(&line).map_err(io::Error::other)
+}
+
+/// The port and token written by serve_queries, or None if the daemon is not running
+async fn read_port_file(port_file: &Utf8Path) -> Option<(u16, String)> {
+    let contents = fs::read_to_string(port_file).await.ok()?;
+    let (port, token) = contents.trim().split_once(' ')?;
+    Some((port.parse().ok()?, token.to_owned()))
+}
+
+/// Token only processes that can read the port file know, so other users of the machine cannot query the index
+fn generate_token() -> String {
+    let nanos = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).map_or(0, |d| d.as_nanos());
+    let mut high = RandomState::new().build_hasher();
+    high.write_u128(nanos);
+    let mut low = RandomState::new().build_hasher();
+    low.write_u128(nanos);
+    format!("{:016x}{:016x}", high.finish(), low.finish())
+}
@@ -0,0 +1,61 @@
+use std::{error::Error, sync::Arc};
+
+use fetch_core::{app_config, files::dupes::{DuplicateFinder, DuplicateKind}, store::lancedb::LanceDBStore};
+
+use crate::utility::format_size;
+
+pub struct DupesArgs {
+    /// Cosine similarity (0.0 - 1.0) files must reach to be reported as near duplicates, default 0.95
+    pub threshold: f32,
+}
+
+pub async fn dupes(args: DupesArgs) -> Result<(), Box<dyn Error>> {
+    if !(0.0..=1.0).contains(&args.threshold) {
+        return Err(format!("Similarity threshold must be between 0.0 and 1.0, got {}", args.threshold).into());
+    }
+
+    let data_dir = app_config::get_default_index_directory();
+    let siglip_store = Arc::new(LanceDBStore::local_full(
+        data_dir.as_str(),
+        "siglip2_chunkfile".to_owned()
+    ).await
+    .unwrap_or_else(|e|
+        panic!("Could not open lancedb store with data dir: {}. Error: {e:?}",
+        data_dir.as_str())));
+    let gemma_store = Arc::new(LanceDBStore::local_full(
+        data_dir.as_str(),
+        "gemma_chunkfile".to_owned()
+    ).await
+    .unwrap_or_else(|e|
+        panic!("Could not open lancedb store with data dir: {}. Error: {e:?}",
+        data_dir.as_str())));
+
+    println!("Looking for duplicates among indexed files...");
+    let groups = DuplicateFinder::using(siglip_store, gemma_store)
+        .with_similarity_threshold(args.threshold)
+        .find()
+        .await?;
+
+    if groups.is_empty() {
+        println!("No duplicates found!");
+        return Ok(());
+    }
+
+    let mut reclaimable = 0;
+    for (i, group) in groups.iter().enumerate() {
+        let description = match &group.kind {
+            DuplicateKind::Identical => "identical".to_owned(),
+            DuplicateKind::Similar { channel, similarity } =>
+                format!("similar {} content, similarity {:.3}", channel, similarity),
+        };
+        println!("\n{}: {} files, {} ({} reclaimable)", i + 1, group.files.len(), description,
+            format_size(group.reclaimable_size()));
+        for file in &group.files {
+            println!("  {} ({})", file.path, format_size(file.size));
+        }
+        reclaimable += group.reclaimable_size();
+    }
+    println!("\nFound {} group(s) of duplicates, {} reclaimable", groups.len(), format_size(reclaimable));
+
+    Ok(())
+}
@@ -0,0 +1,263 @@
+use std::{collections::{HashMap, HashSet}, error::Error, fs, path::{self, Path, PathBuf}};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use fetch_core::{app_config, files::query::QueryFiles, index::{ChunkType, provider::{ChunkQueryFilters, registry::ProviderRegistry, score::{self, ScoreCalibration, ScoreRange}}}};
+use normalize_path::NormalizePath;
+use serde::Deserialize;
+
+use crate::query::{CollapseMode, aggregate_results, open_file_queryer, open_file_queryer_with_hybrid_search};
+
+pub struct EvalArgs {
+    /// Path to a YAML file of labeled queries
+    pub labels: PathBuf,
+    /// The number of file results to consider per query when computing metrics, default 10
+    pub k: u32,
+    /// The number of chunks to query per API call (higher = faster but more memory), default 100
+    pub chunks_per_query: u32,
+    /// Whether to evaluate the queries twice, with hybrid search turned off and then on for every provider, to see
+    /// whether full text search improves relevance, see ProviderSettings::hybrid_search
+    pub compare_hybrid: bool,
+    /// Whether to measure the range of the raw scores of every provider and chunk type on the labeled queries
+    /// instead, and save them as the score calibration the scores of the providers are normalized by, see
+    /// ScoreNormalizer
+    pub calibrate: bool,
+}
+
+/// A set of labeled queries, eg.
+/// ```yaml
+/// queries:
+///   - query: "dog playing in the snow"
+///     expected:
+///       - /home/august99us/pictures/dog.jpg
+///   - query: "quarterly tax filing"
+///     expected:
+///       - documents/taxes_2024.pdf
+/// ```
+/// Relative expected paths are resolved against the directory containing the labels file.
+#[derive(Debug, Deserialize)]
+pub struct LabeledQuerySet {
+    pub queries: Vec<LabeledQuery>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LabeledQuery {
+    /// String to query files with
+    pub query: String,
+    /// Files that are considered relevant results for the query
+    pub expected: Vec<PathBuf>,
+}
+
+/// Relevance metrics for a single labeled query
+#[derive(Debug, Clone, Copy)]
+pub struct QueryMetrics {
+    /// Fraction of the expected files found within the top k results
+    pub recall: f32,
+    /// Reciprocal of the rank of the first expected file within the top k results, or 0 if none were found
+    pub reciprocal_rank: f32,
+}
+
+pub async fn eval(args: EvalArgs) -> Result<(), Box<dyn Error>> {
+    let labels_contents = fs::read_to_string(&args.labels)
+        .map_err(|e| format!("Could not read labels file {}: {}", args.labels.display(), e))?;
+    let query_set: LabeledQuerySet = serde_yaml::from_str(&labels_contents)
+        .map_err(|e| format!("Could not parse labels file {}: {}", args.labels.display(), e))?;
+    if query_set.queries.is_empty() {
+        println!("No labeled queries found in {}. Nothing to do!", args.labels.display());
+        return Ok(());
+    }
+
+    let labels_dir = path::absolute(&args.labels)?
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_default();
+
+    let data_dir = app_config::get_default_index_directory();
+    if args.calibrate {
+        return calibrate(&query_set.queries, &labels_dir, &data_dir, &args).await;
+    }
+
+    println!("Evaluating {} labeled queries against file index at {} (k = {})",
+        query_set.queries.len(), data_dir.as_str(), args.k);
+
+    if !args.compare_hybrid {
+        let file_queryer = open_file_queryer(data_dir.as_str()).await;
+        let summary = eval_queries(&file_queryer, &query_set.queries, &labels_dir, &args).await?;
+        print_summary("Summary", &summary, args.k);
+        return Ok(());
+    }
+
+    println!("\nWithout hybrid search:");
+    let file_queryer = open_file_queryer_with_hybrid_search(data_dir.as_str(), Some(false)).await;
+    let vector_summary = eval_queries(&file_queryer, &query_set.queries, &labels_dir, &args).await?;
+    drop(file_queryer);
+
+    println!("\nWith hybrid search:");
+    let file_queryer = open_file_queryer_with_hybrid_search(data_dir.as_str(), Some(true)).await;
+    let hybrid_summary = eval_queries(&file_queryer, &query_set.queries, &labels_dir, &args).await?;
+
+    print_summary("Summary without hybrid search", &vector_summary, args.k);
+    print_summary("Summary with hybrid search", &hybrid_summary, args.k);
+
+    Ok(())
+}
+
+/// Computes recall@k and reciprocal rank for a ranked list of result paths against a set of expected paths.
+pub fn score_query(ranked: &[Utf8PathBuf], expected: &HashSet<Utf8PathBuf>, k: usize) -> QueryMetrics {
+    if expected.is_empty() {
+        return QueryMetrics { recall: 0.0, reciprocal_rank: 0.0 };
+    }
+
+    let top_k = &ranked[..ranked.len().min(k)];
+    let found = top_k.iter().filter(|r| expected.contains(*r)).count();
+    let reciprocal_rank = top_k.iter()
+        .position(|r| expected.contains(r))
+        .map(|i| 1.0 / (i + 1) as f32)
+        .unwrap_or(0.0);
+
+    QueryMetrics {
+        recall: found as f32 / expected.len() as f32,
+        reciprocal_rank,
+    }
+}
+
+// Private constants, functions and structs
+
+/// Fewest relevant and other chunks a range is calibrated from
+const MIN_CALIBRATION_SAMPLES: usize = 5;
+
+/// Mean relevance metrics over a set of labeled queries
+struct EvalSummary {
+    num_queries: usize,
+    mean_recall: f32,
+    mrr: f32,
+}
+
+/// Runs the labeled queries with the file queryer, printing the metrics of each
+async fn eval_queries(file_queryer: &impl QueryFiles, queries: &[LabeledQuery], labels_dir: &Path, args: &EvalArgs)
+    -> Result<EvalSummary, Box<dyn Error>>
+{
+    let mut all_metrics = Vec::with_capacity(queries.len());
+    for labeled in queries {
+        let expected = resolve_expected(labels_dir, labeled.expected.clone())?;
+        let (results, corrected_query) =
+            aggregate_results(file_queryer, &labeled.query, args.k, args.chunks_per_query, CollapseMode::None).await?;
+        if let Some(corrected_query) = corrected_query {
+            println!("Searched for: \"{}\"", corrected_query);
+        }
+        let ranked: Vec<Utf8PathBuf> = results.into_iter().map(|g| g.result.path).collect();
+
+        let metrics = score_query(&ranked, &expected, args.k as usize);
+        println!("\"{}\": recall@{} {:.3}, reciprocal rank {:.3}",
+            labeled.query, args.k, metrics.recall, metrics.reciprocal_rank);
+        for missing in expected.iter().filter(|e| !ranked.iter().take(args.k as usize).any(|r| r == *e)) {
+            println!("    missing: {}", missing);
+        }
+
+        all_metrics.push(metrics);
+    }
+
+    let num_queries = all_metrics.len() as f32;
+    Ok(EvalSummary {
+        num_queries: all_metrics.len(),
+        mean_recall: all_metrics.iter().map(|m| m.recall).sum::<f32>() / num_queries,
+        mrr: all_metrics.iter().map(|m| m.reciprocal_rank).sum::<f32>() / num_queries,
+    })
+}
+
+/// Raw scores of the chunks of one provider and chunk type found by the labeled queries
+#[derive(Default)]
+struct ScoreSamples {
+    /// Scores of chunks of the expected files
+    relevant: Vec<f32>,
+    /// Scores of chunks of every other file
+    other: Vec<f32>,
+}
+
+/// Queries every provider with the labeled queries, and sets the range of each provider and chunk type from the raw
+/// scores of the chunks found, from the median score of chunks of files not expected to the 90th percentile score
+/// of chunks of expected files. Ranges with too few samples to go by keep their current calibration.
+async fn calibrate(queries: &[LabeledQuery], labels_dir: &Path, data_dir: &Utf8Path, args: &EvalArgs)
+    -> Result<(), Box<dyn Error>>
+{
+    println!("Calibrating provider scores with {} labeled queries against file index at {}", queries.len(),
+        data_dir.as_str());
+    // Chunks scoring under the current ranges are needed too, as the ranges may be too high
+    score::keep_chunks_under_range(true);
+    let registry = ProviderRegistry::open(data_dir).await
+        .map_err(|e| format!("Could not open index providers with data dir: {}. Error: {e:?}", data_dir.as_str()))?;
+
+    let mut samples: HashMap<(&'static str, ChunkType), ScoreSamples> = HashMap::new();
+    for labeled in queries {
+        let expected = resolve_expected(labels_dir, labeled.expected.clone())?;
+        for provider in registry.providers() {
+            let results = match provider.query_n(&labeled.query, &ChunkQueryFilters::default(), args.chunks_per_query,
+                0).await
+            {
+                Ok(results) => results,
+                Err(e) => {
+                    println!("Could not query {} with \"{}\": {:?}", provider.provider_name(), labeled.query, e);
+                    continue;
+                },
+            };
+            for result in results {
+                let Some(raw_score) = result.raw_score() else { continue };
+                let chunkfile = result.chunkfile();
+                let provider_samples = samples.entry((provider.provider_name(), chunkfile.chunk_type)).or_default();
+                if expected.contains(&chunkfile.original_file) {
+                    provider_samples.relevant.push(raw_score);
+                } else {
+                    provider_samples.other.push(raw_score);
+                }
+            }
+        }
+    }
+
+    let mut calibration = ScoreCalibration::load()?;
+    let mut keys: Vec<_> = samples.keys().copied().collect();
+    keys.sort_by_key(|(provider_name, chunk_type)| (*provider_name, format!("{:?}", chunk_type)));
+    for (provider_name, chunk_type) in keys {
+        let provider_samples = samples.get_mut(&(provider_name, chunk_type)).expect("Key was taken from the map");
+        if provider_samples.relevant.len() < MIN_CALIBRATION_SAMPLES
+            || provider_samples.other.len() < MIN_CALIBRATION_SAMPLES
+        {
+            println!("{} {:?} chunks: too few samples ({} relevant, {} other), keeping the current range",
+                provider_name, chunk_type, provider_samples.relevant.len(), provider_samples.other.len());
+            continue;
+        }
+
+        let range = ScoreRange {
+            min: percentile(&mut provider_samples.other, 0.5),
+            max: percentile(&mut provider_samples.relevant, 0.9),
+        };
+        println!("{} {:?} chunks: {:.3} - {:.3} ({} relevant, {} other)", provider_name, chunk_type, range.min,
+            range.max, provider_samples.relevant.len(), provider_samples.other.len());
+        calibration.set_range(provider_name, chunk_type, range);
+    }
+
+    calibration.save()?;
+    println!("\nSaved score calibration to {}", app_config::get_score_calibration_file_path().as_str());
+    Ok(())
+}
+
+/// The score at the percentile (0-1) of the scores, which must not be empty
+fn percentile(scores: &mut [f32], percentile: f32) -> f32 {
+    scores.sort_by(f32::total_cmp);
+    scores[((scores.len() - 1) as f32 * percentile).round() as usize]
+}
+
+fn print_summary(title: &str, summary: &EvalSummary, k: u32) {
+    println!("\n{} ({} queries):", title, summary.num_queries);
+    println!("  recall@{}: {:.3}", k, summary.mean_recall);
+    println!("  MRR: {:.3}", summary.mrr);
+}
+
+/// Converts expected paths into the absolute, normalized form that paths are stored in the index with
+fn resolve_expected(labels_dir: &Path, expected: Vec<PathBuf>) -> Result<HashSet<Utf8PathBuf>, Box<dyn Error>> {
+    expected.into_iter()
+        .map(|p| {
+            let absolute = if p.is_absolute() { p } else { labels_dir.join(p) };
+            Utf8PathBuf::from_path_buf(absolute.normalize())
+                .map_err(|p| format!("Expected path is not valid UTF-8: {}", p.to_string_lossy()).into())
+        })
+        .collect()
+}
@@ -1,12 +1,15 @@
-use std::{collections::HashSet, error::Error, path::{self, PathBuf}, sync::Arc, time::Duration};
+use std::{error::Error, path::{self, PathBuf}, sync::Arc, time::Duration};
 
 use camino::Utf8PathBuf;
 use chrono::Utc;
-use fetch_core::{app_config, files::{FileIndexer, index::{FileIndexingErrorType, FileIndexingResult, FileIndexingResultType, IndexFiles}}, index::provider::{image::ImageIndexProvider, pdf::PdfIndexProvider}, store::lancedb::LanceDBStore};
+use fetch_core::{app_config, files::{FileIndexer, index::{FileIndexingErrorType, FileIndexingResult, FileIndexingResultType, IndexFiles}, journal::IndexJournal, links::{LinkResolver, ResolvedPath, SymlinkPolicy}, lock::{IndexLock, IndexLockError}, os_path::{from_os_path, to_os_path}, queue::IndexQueue}, index::provider::registry::ProviderRegistry, store::lancedb::LanceDBStore};
 use indicatif::ProgressBar;
 use normalize_path::NormalizePath;
 use tokio::{sync::Semaphore, task};
 
+/// How long to wait for another process to finish writing to the index before queuing files for the daemon instead
+const INDEX_LOCK_TIMEOUT: Duration = Duration::from_secs(10);
+
 pub struct IndexArgs {
     /// Number of parallel indexing jobs to run at once
     pub jobs: usize,
@@ -19,10 +22,23 @@ pub struct IndexArgs {
 }
 
 pub async fn index(args: IndexArgs) -> Result<(), Box<dyn Error>> {
+    if app_config::is_index_read_only() {
+        return Err("The index is read-only on this machine (read_only_index in data.toml), \
+            files are indexed by the machine that writes to it".into());
+    }
     let classified_paths = classify_paths(args.paths);
-    let mut files = classified_paths.files;
+    let symlink_policy = SymlinkPolicy::from_settings().unwrap_or_else(|e| {
+        eprintln!("Warning: could not load symlink policy, following symlinks: {e:?}");
+        SymlinkPolicy::default()
+    });
+    let mut link_resolver = LinkResolver::using(symlink_policy);
+    let mut files = vec![];
+    resolve_paths(classified_paths.files, &mut files, &mut link_resolver);
 
-    explore_directories(classified_paths.folders, &mut files, args.recursive);
+    explore_directories(classified_paths.folders, &mut files, args.recursive, &mut link_resolver);
+    if let Err(e) = link_resolver.record_aliases() {
+        eprintln!("Warning: could not record the names of symlinks as tags of their targets: {e:?}");
+    }
 
     let files = clean_paths(files);
     // files classified as unknown are likely paths that were deleted and need to be cleared
@@ -68,25 +84,29 @@ pub async fn index(args: IndexArgs) -> Result<(), Box<dyn Error>> {
 
     // Configure fetch components
     let data_dir = app_config::get_default_index_directory();
-    // image index provider
-    let siglip_store = Arc::new(LanceDBStore::local_full(
+    // Only one process writes to the index at a time, files are handed to the daemon if another one is
+    let _index_lock = match IndexLock::acquire(&data_dir, "fetch index", Some(INDEX_LOCK_TIMEOUT)).await {
+        Ok(index_lock) => index_lock,
+        Err(IndexLockError::Busy { holder }) => {
+            let queued = IndexQueue::enqueue(files.into_iter().chain(unknown))?;
+            println!("Index is busy, it is being written to by {holder}.\n\
+                Queued {queued} file(s) to be indexed by the file daemon once it is free.");
+            return Ok(());
+        },
+        Err(e) => return Err(e.into()),
+    };
+    let registry = ProviderRegistry::open(&data_dir).await
+        .unwrap_or_else(|e| panic!("Could not open index providers with data dir: {}. Error: {e:?}", data_dir.as_str()));
+    let journal_store = LanceDBStore::local_with_filters(
         data_dir.as_str(),
-        "siglip2_chunkfile".to_owned()
+        "index_journal".to_owned()
     ).await
     .unwrap_or_else(|e|
-        panic!("Could not open lancedb store with data dir: {}. Error: {e:?}",
-        data_dir.as_str())));
-    let basic_image = ImageIndexProvider::using(siglip_store.clone());
-    // pdf index provider
-    let gemma_store = Arc::new(LanceDBStore::local_full(
-        data_dir.as_str(),
-        "gemma_chunkfile".to_owned()
-    ).await
-    .unwrap_or_else(|e|
-        panic!("Could not open lancedb store with data dir: {}. Error: {e:?}",
-        data_dir.as_str())));
-    let pdf = PdfIndexProvider::using(gemma_store, siglip_store);
-    let file_indexer: Arc<FileIndexer> = Arc::new(FileIndexer::with(vec![Arc::new(basic_image), Arc::new(pdf)]));
+        panic!("Could not open lancedb store for the index journal with data dir: {}. Error: {e:?}",
+        data_dir.as_str()));
+    let file_indexer: Arc<FileIndexer> = Arc::new(FileIndexer::with(registry.providers())
+        .with_journal(Arc::new(IndexJournal::using(journal_store)))
+        .with_provider_budgets(registry.budgets()));
 
     println!("Indexing {} files into index stored in the directory {} with {} parallel jobs",
         files.len(),
@@ -140,9 +160,8 @@ fn clean_paths(paths: Vec<PathBuf>) -> Vec<Utf8PathBuf> {
         // is empty.
     paths.sort();
     paths.dedup();
-    paths.into_iter().map(Utf8PathBuf::from_path_buf) // Convert to Utf8PathBuf
-        .collect::<Result<Vec<Utf8PathBuf>, PathBuf>>() // collect results
-        .unwrap_or_else(|e| panic!("Error verifying utf8 validity of path: {e:?}"))
+    // Convert to Utf8PathBuf, escaping paths that are not valid UTF-8 (see os_path::from_os_path)
+    paths.iter().map(|path| from_os_path(path)).collect()
 }
 
 /// Explores (io call) the paths given in "paths" vector and classifies them into one of three categories:
@@ -171,43 +190,59 @@ fn classify_paths(paths: Vec<PathBuf>) -> ClassifiedPaths {
 }
 
 /// Expands the directories given in "folders", adding the files found to the "files" vec. Will recursively
-/// explore directories found within those folders as well if recursive = true
-fn explore_directories(folders: Vec<PathBuf>, files: &mut Vec<PathBuf>, recursive: bool) {
-    let mut hashset: HashSet<PathBuf> = HashSet::new();
-    let mut queue = folders;
-    while let Some(folder) = queue.pop() {
-         // guaranteed to exist
-        if hashset.contains(&folder) {
-            eprintln!("Warning: Circled back to folder that was already seen before. Maybe there is a symlink creating a circular
-                directory structure somewhere? Folder: {}", folder.to_str().expect("error converting pathbuf to string"));
-                continue
+/// explore directories found within those folders as well if recursive = true. Symlinks are handled according to
+/// the link resolver's policy, and directories reached through more than one path (eg. a symlink creating a
+/// circular directory structure) are only explored once.
+fn explore_directories(folders: Vec<PathBuf>, files: &mut Vec<PathBuf>, recursive: bool, link_resolver: &mut LinkResolver) {
+    let mut queue = vec![];
+    for folder in folders {
+        if let ResolvedPath::Directory(folder) = resolve_path(&folder, link_resolver) {
+            queue.push(folder);
         }
-        for entry_result in folder.read_dir()
-            .unwrap_or_else(|_| panic!("failed reading directory: {}", folder.to_str().expect("error converting pathbuf to string"))) {
+    }
+    while let Some(folder) = queue.pop() {
+        for entry_result in to_os_path(&folder).read_dir()
+            .unwrap_or_else(|_| panic!("failed reading directory: {folder}")) {
             match entry_result {
                 Ok(entry) => {
-                    let entry_path = entry.path();
-                    if entry_path.is_file() {
-                        files.push(entry_path);
-                    } else if entry_path.is_dir() {
-                        if recursive {
-                            queue.push(entry_path);
-                        } else {
-                            eprintln!("Warning: subdirectory found when reading directory but recursive flag missing, ignoring: {}",
-                                entry_path.to_str().expect("error converting pathbuf to string"));
-                        }
-                    } else {
-                        eprintln!("Warning: directory entry that is not a file nor a directory found: {}", entry_path.to_str()
-                            .expect("error converting pathbuf to string"));
+                    match resolve_path(&entry.path(), link_resolver) {
+                        ResolvedPath::File(entry_path) => files.push(entry_path.into_std_path_buf()),
+                        ResolvedPath::Directory(entry_path) => {
+                            if recursive {
+                                queue.push(entry_path);
+                            } else {
+                                eprintln!("Warning: subdirectory found when reading directory but recursive flag missing, ignoring: {}",
+                                    entry_path);
+                            }
+                        },
+                        ResolvedPath::Skip { .. } => {},
                     }
                 },
                 Err(e) => panic!("Issue reading directory entry: {e:?}"),
             }
         }
-        hashset.insert(folder);
     }
 }
 
+/// Adds the files given in "paths" to the "files" vec, as resolved by the link resolver
+fn resolve_paths(paths: Vec<PathBuf>, files: &mut Vec<PathBuf>, link_resolver: &mut LinkResolver) {
+    for path in paths {
+        if let ResolvedPath::File(path) = resolve_path(&path, link_resolver) {
+            files.push(path.into_std_path_buf());
+        }
+    }
+}
+
+/// Resolves a path with the link resolver, warning about paths that are skipped
+fn resolve_path(path: &path::Path, link_resolver: &mut LinkResolver) -> ResolvedPath {
+    let utf8_path = from_os_path(path);
+    let resolved = link_resolver.resolve(&utf8_path);
+    if let ResolvedPath::Skip { reason } = &resolved {
+        eprintln!("Warning: {reason}, ignoring: {utf8_path}");
+    }
+    resolved
+}
+
 async fn spawn_index_jobs(file_indexer: Arc<impl IndexFiles + Sync + Send + Clone + 'static>,
     files: Vec<Utf8PathBuf>, jobs: usize) -> Vec<Result<(), ()>> {
     let semaphore = Arc::new(Semaphore::new(jobs));
@@ -232,6 +267,19 @@ async fn spawn_index_jobs(file_indexer: Arc<impl IndexFiles + Sync + Send + Clon
                     bar_clone.println(format!("File {path} successfully indexed"));
                     Ok(())
                 },
+                Ok(FileIndexingResult { path, r#type: FileIndexingResultType::PartiallyIndexed {
+                    indexed_providers, provider_errors } }) => {
+                    bar_clone.println(format!("File {path} partially indexed by providers: {indexed_providers:?}"));
+                    for (provider_name, provider_error) in provider_errors {
+                        bar_clone.println(format!(
+                            "Error from provider {} while processing file with path {:?}: {:?}",
+                            provider_name,
+                            path,
+                            provider_error
+                        ));
+                    }
+                    Err(())
+                },
                 Ok(FileIndexingResult { path, r#type: FileIndexingResultType::Skipped { reason } }) => {
                     bar_clone.println(format!("File {path} was skipped for reason: {reason}"));
                     Ok(())
@@ -240,6 +288,16 @@ async fn spawn_index_jobs(file_indexer: Arc<impl IndexFiles + Sync + Send + Clon
                     bar_clone.println(format!("File {path} not found or could not be previewed, successfully cleared from index"));
                     Ok(())
                 },
+                Ok(FileIndexingResult { path: _, r#type: FileIndexingResultType::Tagged }) => {
+                    unreachable!("Index will never return a Tagged result");
+                },
+                Ok(FileIndexingResult { path: _, r#type: FileIndexingResultType::Restored { .. } }) => {
+                    unreachable!("Index will never return a Restored result");
+                },
+                Ok(FileIndexingResult { path, r#type: FileIndexingResultType::Renamed { from, chunks } }) => {
+                    bar_clone.println(format!("File {path} was moved from {from}, moved its {chunks} indexed chunks"));
+                    Ok(())
+                },
                 Err(e) => {
                     match e.r#type {
                         FileIndexingErrorType::IndexProviders { provider_errors } => {
@@ -304,6 +362,18 @@ async fn spawn_clear_jobs(file_indexer: Arc<impl IndexFiles + Sync + Send + Clon
                 Ok(FileIndexingResult { path: _, r#type: FileIndexingResultType::Skipped { .. } }) => {
                     unreachable!("Clear will never return an Skipped result");
                 },
+                Ok(FileIndexingResult { path: _, r#type: FileIndexingResultType::PartiallyIndexed { .. } }) => {
+                    unreachable!("Clear will never return a PartiallyIndexed result");
+                },
+                Ok(FileIndexingResult { path: _, r#type: FileIndexingResultType::Tagged }) => {
+                    unreachable!("Clear will never return a Tagged result");
+                },
+                Ok(FileIndexingResult { path: _, r#type: FileIndexingResultType::Restored { .. } }) => {
+                    unreachable!("Clear will never return a Restored result");
+                },
+                Ok(FileIndexingResult { path: _, r#type: FileIndexingResultType::Renamed { .. } }) => {
+                    unreachable!("Clear will never return a Renamed result");
+                },
                 Ok(FileIndexingResult { path, r#type: FileIndexingResultType::Cleared  }) => {
                     bar_clone.println(format!("Path {path} successfully cleared from index"));
                     Ok(())
@@ -0,0 +1,174 @@
+use camino::Utf8Path;
+use fetch_core::previewable::{PreviewSize, cached_preview_path};
+use serde::Serialize;
+
+use crate::query::{QueryFormat, ResultGroup};
+
+/// Renders query results as the JSON the launcher reads results from. Results use their cached thumbnail as their
+/// icon if there is one, and the icon of their file type otherwise. Collapsed results are rendered as the best
+/// ranked result of their group, with the number of results collapsed into it in their subtitle.
+///
+/// * Alfred: the Script Filter JSON format, `{ "items": [...] }`
+/// * Raycast: the props of a List.Item for each result, `{ "items": [...] }`, for a script or extension to list
+/// * Wox: the JSON-RPC query response of a plugin, `{ "result": [...] }`
+///
+/// Returns an empty string for the text format, which is printed by query itself.
+pub fn render(format: QueryFormat, results: &[ResultGroup]) -> String {
+    let rendered = match format {
+        QueryFormat::Text => return String::new(),
+        QueryFormat::Alfred => serde_json::to_string(&AlfredResponse {
+            items: results.iter().map(alfred_item).collect(),
+        }),
+        QueryFormat::Raycast => serde_json::to_string(&RaycastResponse {
+            items: results.iter().map(raycast_item).collect(),
+        }),
+        QueryFormat::Wox => serde_json::to_string(&WoxResponse {
+            result: results.iter().map(|result| wox_result(result, results.len())).collect(),
+        }),
+    };
+    rendered.expect("Launcher results should always be serializable")
+}
+
+// Private functions and structs
+
+#[derive(Serialize)]
+struct AlfredResponse<'a> {
+    items: Vec<AlfredItem<'a>>,
+}
+
+#[derive(Serialize)]
+struct AlfredItem<'a> {
+    uid: &'a str,
+    #[serde(rename = "type")]
+    item_type: &'static str,
+    title: &'a str,
+    subtitle: String,
+    arg: &'a str,
+    autocomplete: &'a str,
+    icon: AlfredIcon,
+    quicklookurl: &'a str,
+}
+
+#[derive(Serialize)]
+struct AlfredIcon {
+    /// "fileicon" to use the icon of the file at the path, omitted to use the image at the path
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    icon_type: Option<&'static str>,
+    path: String,
+}
+
+#[derive(Serialize)]
+struct RaycastResponse<'a> {
+    items: Vec<RaycastItem<'a>>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RaycastItem<'a> {
+    id: &'a str,
+    title: &'a str,
+    subtitle: String,
+    icon: RaycastIcon,
+    arg: &'a str,
+    quick_look: RaycastQuickLook<'a>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+enum RaycastIcon {
+    /// Image at the path
+    Source(String),
+    /// Icon of the file at the path
+    FileIcon(String),
+}
+
+#[derive(Serialize)]
+struct RaycastQuickLook<'a> {
+    path: &'a str,
+}
+
+#[derive(Serialize)]
+struct WoxResponse<'a> {
+    result: Vec<WoxResult<'a>>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct WoxResult<'a> {
+    title: &'a str,
+    sub_title: String,
+    ico_path: String,
+    score: u32,
+    #[serde(rename = "JsonRPCAction")]
+    json_rpc_action: WoxAction<'a>,
+}
+
+#[derive(Serialize)]
+struct WoxAction<'a> {
+    method: &'static str,
+    parameters: [&'a str; 1],
+}
+
+fn alfred_item(group: &ResultGroup) -> AlfredItem<'_> {
+    let result = &group.result;
+    let path = result.path.as_str();
+    let icon = match cached_preview_path(&result.path, PreviewSize::Thumbnail) {
+        Some(preview) => AlfredIcon { icon_type: None, path: preview.into_string() },
+        None => AlfredIcon { icon_type: Some("fileicon"), path: path.to_owned() },
+    };
+    AlfredItem {
+        uid: path,
+        item_type: "file",
+        title: title(&result.path),
+        subtitle: subtitle(group),
+        arg: path,
+        autocomplete: title(&result.path),
+        icon,
+        quicklookurl: path,
+    }
+}
+
+fn raycast_item(group: &ResultGroup) -> RaycastItem<'_> {
+    let result = &group.result;
+    let path = result.path.as_str();
+    let icon = match cached_preview_path(&result.path, PreviewSize::Thumbnail) {
+        Some(preview) => RaycastIcon::Source(preview.into_string()),
+        None => RaycastIcon::FileIcon(path.to_owned()),
+    };
+    RaycastItem {
+        id: path,
+        title: title(&result.path),
+        subtitle: subtitle(group),
+        icon,
+        arg: path,
+        quick_look: RaycastQuickLook { path },
+    }
+}
+
+/// Wox sorts results by score, so the best result gets the highest
+fn wox_result(group: &ResultGroup, num_results: usize) -> WoxResult<'_> {
+    let result = &group.result;
+    let path = result.path.as_str();
+    WoxResult {
+        title: title(&result.path),
+        sub_title: subtitle(group),
+        // Wox shows the icon of the file's type itself when given the file
+        ico_path: cached_preview_path(&result.path, PreviewSize::Thumbnail)
+            .map_or_else(|| path.to_owned(), |preview| preview.into_string()),
+        score: (num_results as u32).saturating_sub(result.rank),
+        json_rpc_action: WoxAction { method: "open", parameters: [path] },
+    }
+}
+
+fn title(path: &Utf8Path) -> &str {
+    path.file_name().unwrap_or(path.as_str())
+}
+
+/// The folder of the result, and the number of results collapsed into it if any
+fn subtitle(group: &ResultGroup) -> String {
+    let folder = group.result.path.parent().map_or("", Utf8Path::as_str);
+    match group.collapsed.len() {
+        0 => folder.to_owned(),
+        num_collapsed => format!("{} (+{} more)", folder, num_collapsed),
+    }
+}
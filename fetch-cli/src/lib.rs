@@ -1,4 +1,21 @@
+pub mod collection;
+pub mod daemon;
+pub mod dupes;
+pub mod eval;
 pub mod index;
+pub mod launcher;
+pub mod log;
+pub mod migrate;
 pub mod query;
 pub mod query_by_file;
+pub mod repair;
+pub mod repl;
+pub mod restore;
+pub mod schedule;
+pub mod schema;
+pub mod snapshot;
+pub mod stats;
+pub mod summarize;
+pub mod tag;
+pub mod topics;
 pub mod utility;
\ No newline at end of file
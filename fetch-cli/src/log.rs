@@ -0,0 +1,44 @@
+use std::{error::Error, path::{self, PathBuf}};
+
+use fetch_core::{app_config, files::{journal::{IndexJournal, JournalEntry}, os_path::from_os_path}, store::lancedb::LanceDBStore};
+use normalize_path::NormalizePath;
+
+pub struct LogArgs {
+    /// Path of the file to show the journal entries of
+    pub path: PathBuf,
+    /// Number of the most recent entries to show
+    pub num_entries: usize,
+}
+
+/// Shows the most recent index, clear and skip decisions recorded in the index journal for a file, to find out why
+/// the file is, or is not, in the index.
+pub async fn log(args: LogArgs) -> Result<(), Box<dyn Error>> {
+    let path = from_os_path(&path::absolute(&args.path)?.normalize());
+
+    let data_dir = app_config::get_default_index_directory();
+    let store = LanceDBStore::<JournalEntry>::local_with_filters(data_dir.as_str(), "index_journal".to_owned()).await
+        .unwrap_or_else(|e|
+            panic!("Could not open lancedb store for the index journal with data dir: {}. Error: {e:?}",
+            data_dir.as_str()));
+    let journal = IndexJournal::using(store);
+
+    let entries = journal.entries(&path, args.num_entries).await?;
+    if entries.is_empty() {
+        println!("Nothing has been recorded for {} yet", path);
+        return Ok(());
+    }
+
+    for entry in entries {
+        print!("{} {:<8} {:<10} {:>6}ms",
+            entry.recorded.format("%Y-%m-%d %H:%M:%S"),
+            entry.action.as_str(),
+            entry.provider.as_deref().unwrap_or("-"),
+            entry.duration.as_millis());
+        match entry.reason {
+            Some(reason) => println!("  {}", reason),
+            None => println!(),
+        }
+    }
+
+    Ok(())
+}
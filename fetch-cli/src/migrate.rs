@@ -0,0 +1,118 @@
+use std::{error::Error, fs::{self, File}, io::{self, Read}, path::PathBuf};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use fetch_core::{app_config::{self, DataPath}, files::lock::IndexLock};
+
+pub struct MigrateDataArgs {
+    /// Directory to move the data to, which must not exist yet or be empty
+    pub to: PathBuf,
+}
+
+/// Moves the index, chunks, previews, cursors and the other data of Fetch into a new data directory, and points the
+/// data configuration file at it. Data paths that were overridden to somewhere outside the data directory are left
+/// where they are.
+///
+/// The data is copied first, and every copied file is compared with its original. The data configuration file is
+/// only updated once everything was copied, and the originals are only removed once it has been, so the data is
+/// never only half moved: if anything fails, Fetch keeps using the old data directory, and the new one can be
+/// removed.
+pub async fn migrate_data(args: MigrateDataArgs) -> Result<(), Box<dyn Error>> {
+    let to = Utf8PathBuf::from_path_buf(std::path::absolute(&args.to)?)
+        .map_err(|path| format!("Data directory {:?} is not a valid UTF-8 path", path))?;
+    let from = app_config::get_data_directory();
+    if to == from {
+        println!("Data is already in {}! Goodbye.", to);
+        return Ok(());
+    }
+    if to.starts_with(&from) || from.starts_with(&to) {
+        return Err(format!("Cannot move data from {} to {}, as one is inside the other", from, to).into());
+    }
+    if fs::read_dir(&to).is_ok_and(|mut entries| entries.next().is_some()) {
+        return Err(format!("Data directory {} is not empty", to).into());
+    }
+    if fs::exists(app_config::get_daemon_port_file_path())? {
+        return Err("The file daemon is running, stop it before moving the data".into());
+    }
+
+    // Keeps the GUI, the file daemon and other CLI processes from writing to the index while it is copied
+    let index_dir = app_config::get_default_index_directory();
+    let lock = IndexLock::try_acquire(&index_dir, "fetch migrate-data")
+        .map_err(|e| format!("Could not lock the index, close Fetch before moving the data: {}", e))?;
+
+    let mut moved: Vec<Utf8PathBuf> = vec![];
+    let mut settings = vec![("data_directory", to.to_string())];
+    for DataPath { setting, path } in app_config::get_data_paths() {
+        let Ok(relative) = path.strip_prefix(&from) else {
+            println!("Leaving {} where it is, as it is not in the data directory", path);
+            continue;
+        };
+        settings.push((setting, format!("%%DataDirectory%%/{}", relative)));
+
+        // Settings can share a path, eg. cursors are kept in the index directory by default
+        if !fs::exists(&path)? || moved.iter().any(|moved| path.starts_with(moved)) {
+            continue;
+        }
+        println!("Copying {} to {}", path, to.join(relative));
+        copy_verified(&path, &to.join(relative))?;
+        moved.push(path);
+    }
+
+    app_config::update_data_config(&settings)?;
+    println!("Data directory is now {}", to);
+
+    // The lock file is in the index directory, and cannot be removed while it is held on Windows
+    drop(lock);
+    for path in moved {
+        let removed = if path.is_dir() { fs::remove_dir_all(&path) } else { fs::remove_file(&path) };
+        if let Err(e) = removed {
+            println!("Could not remove {} from the old data directory, remove it by hand: {}", path, e);
+        }
+    }
+
+    Ok(())
+}
+
+// Private functions
+
+/// Copies the file or directory to the destination, and checks that every copied file matches its original
+fn copy_verified(source: &Utf8Path, destination: &Utf8Path) -> Result<(), Box<dyn Error>> {
+    if source.is_dir() {
+        fs::create_dir_all(destination)?;
+        for entry in source.read_dir_utf8()? {
+            let entry = entry?;
+            if IndexLock::is_lock_file(entry.file_name()) {
+                continue;
+            }
+            copy_verified(entry.path(), &destination.join(entry.file_name()))?;
+        }
+        return Ok(());
+    }
+
+    if let Some(parent) = destination.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::copy(source, destination)?;
+    if !files_match(source, destination)? {
+        return Err(format!("Copy of {} at {} does not match the original", source, destination).into());
+    }
+    Ok(())
+}
+
+fn files_match(a: &Utf8Path, b: &Utf8Path) -> Result<bool, io::Error> {
+    let (mut a, mut b) = (File::open(a)?, File::open(b)?);
+    if a.metadata()?.len() != b.metadata()?.len() {
+        return Ok(false);
+    }
+
+    let (mut buffer_a, mut buffer_b) = (vec![0; 64 * 1024], vec![0; 64 * 1024]);
+    loop {
+        let read = a.read(&mut buffer_a)?;
+        if read == 0 {
+            return Ok(true);
+        }
+        b.read_exact(&mut buffer_b[..read])?;
+        if buffer_a[..read] != buffer_b[..read] {
+            return Ok(false);
+        }
+    }
+}
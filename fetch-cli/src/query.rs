@@ -1,90 +1,224 @@
-use std::{collections::HashMap, error::Error, sync::Arc};
+use std::{collections::{HashMap, HashSet}, error::Error, str::FromStr};
 
-use camino::Utf8PathBuf;
-use fetch_core::{app_config, files::{FileQueryer, pagination::QueryCursor, query::{QueryFiles, QueryResult}}, index::provider::{image::ImageIndexProvider, pdf::PdfIndexProvider}, store::lancedb::LanceDBStore};
+use camino::{Utf8Path, Utf8PathBuf};
+use fetch_core::{app_config, files::{FileQueryer, pagination::QueryCursor, query::{MatchSource, MultiQuery, OsSearchFallback, QueryFiles, QueryPageCache, QueryPlanner, QueryPreprocessor, QueryResult, RankingBoosts, ScoreWeights}}, index::provider::registry::ProviderRegistry, store::lancedb::LanceDBStore};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+
+use crate::{daemon, launcher, repl};
 
 pub struct QueryArgs {
-    /// String to query files with
+    /// String to query files with. May be empty in interactive mode.
     pub query: String,
     /// The number of file results to return, default 20
     pub num_results: u32,
     /// The number of chunks to query per API call (higher = faster but more memory), default 100
     pub chunks_per_query: u32,
+    /// How the results are printed, default text
+    pub format: QueryFormat,
+    /// Keep querying from a prompt, see repl::interactive
+    pub interactive: bool,
+    /// Whether results from the same directory are collapsed into one, default none
+    pub collapse: CollapseMode,
 }
 
-pub async fn query(args: QueryArgs) -> Result<(), Box<dyn Error>> {
-    let data_dir = app_config::get_default_index_directory();
+/// How query results are printed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum QueryFormat {
+    /// A numbered list for people to read
+    #[default]
+    Text,
+    /// Script Filter JSON for Alfred
+    Alfred,
+    /// List items JSON for Raycast
+    Raycast,
+    /// Plugin query response JSON for Wox
+    Wox,
+}
 
-    // Create the image index store
-    let siglip_store = Arc::new(LanceDBStore::local_full(
-        data_dir.as_str(),
-        "siglip2_chunkfile".to_owned()
-    ).await
-    .unwrap_or_else(|e|
-        panic!("Could not open lancedb store for image index with data dir: {}. Error: {e:?}",
-        data_dir.as_str())));
+impl FromStr for QueryFormat {
+    type Err = String;
 
-    // Create the pdf index store
-    let gemma_store = Arc::new(LanceDBStore::local_full(
-        data_dir.as_str(),
-        "gemma_chunkfile".to_owned()
-    ).await
-    .unwrap_or_else(|e|
-        panic!("Could not open lancedb store for pdf index with data dir: {}. Error: {e:?}",
-        data_dir.as_str())));
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(QueryFormat::Text),
+            "alfred" => Ok(QueryFormat::Alfred),
+            "raycast" => Ok(QueryFormat::Raycast),
+            "wox" => Ok(QueryFormat::Wox),
+            _ => Err(format!("Unknown query format: {}, expected text, alfred, raycast or wox", s)),
+        }
+    }
+}
 
-    // Create the cursor store
-    let cursor_store = LanceDBStore::<QueryCursor>::local(
-        data_dir.as_str(),
-        "cursor".to_owned()
-    ).await
-    .unwrap_or_else(|e|
-        panic!("Could not open lancedb store for cursors with data dir: {}. Error: {e:?}",
-        data_dir.as_str()));
+/// How results from the same directory are collapsed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CollapseMode {
+    /// Every result is listed
+    #[default]
+    None,
+    /// Results in the same directory, eg. the frames of a photoshoot, are collapsed into the best ranked of them
+    Directory,
+}
+
+impl FromStr for CollapseMode {
+    type Err = String;
 
-    // Create index provider and file queryer
-    let basic_image = ImageIndexProvider::using(siglip_store.clone());
-    let pdf = PdfIndexProvider::using(gemma_store, siglip_store);
-    let file_queryer = FileQueryer::with(vec![Arc::new(basic_image), Arc::new(pdf)], cursor_store);
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(CollapseMode::None),
+            "directory" => Ok(CollapseMode::Directory),
+            _ => Err(format!("Unknown collapse mode: {}, expected none or directory", s)),
+        }
+    }
+}
 
-    println!("Querying file index at {} with query: \"{}\"", data_dir.as_str(), args.query);
+/// A result, and the results collapsed into it, best ranked first
+pub struct ResultGroup {
+    pub result: QueryResult,
+    pub collapsed: Vec<QueryResult>,
+}
 
-    // Aggregate results using cursor-based pagination
-    let final_results = aggregate_results(&file_queryer, &args.query, args.num_results, args.chunks_per_query).await?;
+pub async fn query(args: QueryArgs) -> Result<(), Box<dyn Error>> {
+    if args.interactive {
+        return repl::interactive(args).await;
+    }
 
+    let data_dir = app_config::get_default_index_directory();
+
+    if args.format == QueryFormat::Text {
+        println!("Querying file index at {} with query: \"{}\"", data_dir.as_str(), args.query);
+    }
+
+    // The daemon keeps the index open and the models loaded, so it answers much faster than opening the index here,
+    // which matters to launchers querying as the user types
+    let (final_results, corrected_query) =
+        match daemon::query_daemon(&args.query, args.num_results, args.chunks_per_query, args.collapse).await {
+            Some(answer) => (answer.results, answer.corrected_query),
+            None => {
+                let file_queryer = open_file_queryer(data_dir.as_str()).await;
+                // Aggregate results using cursor-based pagination
+                aggregate_results(&file_queryer, &args.query, args.num_results, args.chunks_per_query, args.collapse)
+                    .await?
+            },
+        };
+
+    if args.format != QueryFormat::Text {
+        println!("{}", launcher::render(args.format, &final_results));
+        return Ok(());
+    }
+    if let Some(corrected_query) = corrected_query {
+        println!("Searched for: \"{}\"", corrected_query);
+    }
     if final_results.is_empty() {
         println!("No results!");
     } else {
         println!("\nResults ({}):", final_results.len());
-        for (i, result) in final_results.iter().enumerate() {
-            println!("{}: {} (score: {:.2})", i + 1, result.path, result.score);
-            }
+        for (i, group) in final_results.iter().enumerate() {
+            println!("{}: {} (score: {:.2}){}", i + 1, group.result.path, group.result.score,
+                format_sources(&group.result));
+            print_collapsed(group);
+        }
     }
 
     Ok(())
 }
 
+/// Opens the index stores in the given data directory and creates a file queryer over them
+pub(crate) async fn open_file_queryer(data_dir: &str) -> FileQueryer<LanceDBStore<QueryCursor>> {
+    open_file_queryer_with_hybrid_search(data_dir, None).await
+}
+
+/// Opens a file queryer like open_file_queryer, with hybrid search turned on or off for every provider if given,
+/// instead of as set in the provider settings
+pub(crate) async fn open_file_queryer_with_hybrid_search(data_dir: &str, hybrid_search: Option<bool>)
+    -> FileQueryer<LanceDBStore<QueryCursor>>
+{
+    // Create the index providers
+    let registry = match hybrid_search {
+        Some(hybrid_search) => ProviderRegistry::open_with_hybrid_search(Utf8Path::new(data_dir), hybrid_search).await,
+        None => ProviderRegistry::open(Utf8Path::new(data_dir)).await,
+    }
+    .unwrap_or_else(|e| panic!("Could not open index providers with data dir: {}. Error: {e:?}", data_dir));
+
+    // Create the cursor store
+    let cursor_dir = app_config::get_default_cursor_directory();
+    let cursor_store = LanceDBStore::<QueryCursor>::local(
+        cursor_dir.as_str(),
+        "cursor".to_owned()
+    ).await
+    .unwrap_or_else(|e|
+        panic!("Could not open lancedb store for cursors with cursor dir: {}. Error: {e:?}",
+        cursor_dir));
+
+    // Create file queryer
+    let score_weights = ScoreWeights::from_settings().unwrap_or_else(|e| {
+        warn!("Could not load score weights from settings, weighting all results equally: {}", e);
+        ScoreWeights::default()
+    });
+    let ranking_boosts = RankingBoosts::from_settings().unwrap_or_else(|e| {
+        warn!("Could not load ranking boosts from settings, ranking by semantic score only: {}", e);
+        RankingBoosts::default()
+    });
+    let query_preprocessor = QueryPreprocessor::from_settings().unwrap_or_else(|e| {
+        warn!("Could not load query settings, using query terms as is: {}", e);
+        QueryPreprocessor::default()
+    });
+    let os_search_fallback = OsSearchFallback::from_settings().unwrap_or_else(|e| {
+        warn!("Could not load operating system search settings, not using it: {}", e);
+        OsSearchFallback::default()
+    });
+    let multi_query = MultiQuery::from_settings().unwrap_or_else(|e| {
+        warn!("Could not load multi query settings, using the defaults: {}", e);
+        MultiQuery::default()
+    });
+    let query_planner = QueryPlanner::from_settings().unwrap_or_else(|e| {
+        warn!("Could not load query planner settings, using the defaults: {}", e);
+        QueryPlanner::default()
+    });
+    let page_cache = QueryPageCache::from_settings().unwrap_or_else(|e| {
+        warn!("Could not load result cache settings, using the defaults: {}", e);
+        QueryPageCache::new()
+    });
+    FileQueryer::with(registry.providers(), cursor_store)
+        .with_score_weights(score_weights)
+        .with_ranking_boosts(ranking_boosts)
+        .with_query_preprocessor(query_preprocessor)
+        .with_os_search_fallback(os_search_fallback)
+        .with_multi_query(multi_query)
+        .with_query_planner(query_planner)
+        .with_page_cache(page_cache)
+}
+
 /// Aggregates results by repeatedly calling the query API with cursor until we have enough results
-/// or there are no more results available
-async fn aggregate_results(
+/// or there are no more results available. Results are collapsed as they are aggregated, so a directory of
+/// collapsed results only counts once towards the target number of results. Returns the results with the query
+/// terms after spelling correction, if they differ from the query terms given.
+pub(crate) async fn aggregate_results(
     queryer: &impl QueryFiles,
     query: &str,
     target_num_results: u32,
     chunks_per_query: u32,
-) -> Result<Vec<QueryResult>, Box<dyn Error>> {
+    collapse: CollapseMode,
+) -> Result<(Vec<ResultGroup>, Option<String>), Box<dyn Error>> {
     let mut cursor_id: Option<String> = None;
+    let mut corrected_query: Option<String> = None;
     let mut aggregated_results: HashMap<Utf8PathBuf, QueryResult> = HashMap::new();
     let mut iteration = 0;
 
     loop {
         iteration += 1;
-        log::debug!("Query iteration {}, cursor: {:?}", iteration, cursor_id);
+        debug!("Query iteration {}, cursor: {:?}", iteration, cursor_id);
 
         let result = queryer.query_n(query, chunks_per_query, cursor_id.as_deref()).await?;
 
-        log::debug!("  Received {} changed results, total list length: {}",
+        debug!("  Received {} changed results, total list length: {}",
             result.changed_results.len(), result.results_len);
 
+        if iteration == 1 {
+            corrected_query = result.corrected_query;
+        }
+
         // Update our aggregated results with the changed results
         for changed in result.changed_results {
             aggregated_results.insert(changed.path.clone(), changed);
@@ -92,24 +226,75 @@ async fn aggregate_results(
 
         // Check if we have enough results or if there's no more data
         if result.cursor_id.is_none() {
-            log::debug!("No more results available (cursor exhausted)");
+            debug!("No more results available (cursor exhausted)");
             break;
         }
 
-        if aggregated_results.len() >= target_num_results as usize {
-            log::debug!("Target number of results ({}) reached", target_num_results);
+        if count_groups(aggregated_results.values(), collapse) >= target_num_results as usize {
+            debug!("Target number of results ({}) reached", target_num_results);
             break;
         }
 
         cursor_id = result.cursor_id;
     }
 
-    // Convert to vec and sort by rank
-    let mut final_results: Vec<QueryResult> = aggregated_results.into_values().collect();
-    final_results.sort_by_key(|r| r.rank);
+    // Convert to vec, sort by rank and collapse
+    let mut final_results = collapse_results(aggregated_results.into_values().collect(), collapse);
 
     // Truncate to target number of results
     final_results.truncate(target_num_results as usize);
 
-    Ok(final_results)
+    Ok((final_results, corrected_query))
+}
+
+/// Groups the results as the collapse mode says, ordered by the rank of the best ranked result of each group
+pub(crate) fn collapse_results(mut results: Vec<QueryResult>, collapse: CollapseMode) -> Vec<ResultGroup> {
+    results.sort_by_key(|r| r.rank);
+    match collapse {
+        CollapseMode::None => results.into_iter()
+            .map(|result| ResultGroup { result, collapsed: vec![] })
+            .collect(),
+        CollapseMode::Directory => {
+            let mut groups: Vec<ResultGroup> = vec![];
+            let mut group_indices: HashMap<Utf8PathBuf, usize> = HashMap::new();
+            for result in results {
+                let directory = result.path.parent().map(Utf8Path::to_path_buf).unwrap_or_default();
+                match group_indices.get(&directory) {
+                    Some(&i) => groups[i].collapsed.push(result),
+                    None => {
+                        group_indices.insert(directory, groups.len());
+                        groups.push(ResultGroup { result, collapsed: vec![] });
+                    },
+                }
+            }
+            groups
+        },
+    }
+}
+
+/// Prints how many results were collapsed into the group, to be listed with `:expand` in interactive mode
+pub(crate) fn print_collapsed(group: &ResultGroup) {
+    if !group.collapsed.is_empty() {
+        println!("    +{} more in {}", group.collapsed.len(),
+            group.result.path.parent().map_or("", Utf8Path::as_str));
+    }
+}
+
+/// Lists the providers and channels that found the result, eg. " [pdf-text, pdf-image]", or nothing if there are
+/// none
+pub(crate) fn format_sources(result: &QueryResult) -> String {
+    if result.sources.is_empty() {
+        return String::new();
+    }
+    let labels: Vec<String> = result.sources.iter().map(MatchSource::label).collect();
+    format!(" [{}]", labels.join(", "))
+}
+
+// Private functions
+
+fn count_groups<'a>(results: impl Iterator<Item = &'a QueryResult>, collapse: CollapseMode) -> usize {
+    match collapse {
+        CollapseMode::None => results.count(),
+        CollapseMode::Directory => results.map(|r| r.path.parent()).collect::<HashSet<_>>().len(),
+    }
 }
\ No newline at end of file
@@ -2,7 +2,7 @@ use std::{error::Error, path::PathBuf, sync::Arc};
 
 use camino::Utf8PathBuf;
 use chrono::Utc;
-use fetch_core::{app_config, index::{ChunkFile, ChunkType, embedding::siglip2::{self, Siglip2EmbeddedChunkFile}}, store::{QueryByVector, lancedb::LanceDBStore}};
+use fetch_core::{app_config, index::{ChunkFile, ChunkType, embedding::siglip2::{self, Siglip2EmbeddedChunkFile}, language::UNDETERMINED_LANGUAGE}, store::{QueryByVector, lancedb::LanceDBStore}};
 use serde_json::Map;
 
 pub struct QueryByFileArgs {
@@ -28,13 +28,18 @@ pub async fn query_by_file(args: QueryByFileArgs) -> Result<(), Box<dyn Error>>
         original_file: Utf8PathBuf::default(),
         chunk_channel: "".to_owned(),
         chunk_sequence_id: 0.0,
+        original_file_id: None,
         chunkfile: Utf8PathBuf::from_path_buf(args.query).unwrap(),
         chunk_type: ChunkType::Image,
         chunk_length: 1.0,
         original_file_creation_date: Utc::now(),
         original_file_modified_date: Utc::now(),
         original_file_size: 1,
+        original_file_fingerprint: None,
         original_file_tags: Map::new(),
+        language: UNDETERMINED_LANGUAGE.to_owned(),
+        user_tags: vec![],
+        sequence_number: 0,
     };
 
     let vec = siglip2::embed_chunk(temp_chunkfile).await?.embedding;
@@ -0,0 +1,66 @@
+use std::{collections::BTreeSet, error::Error};
+
+use camino::Utf8PathBuf;
+use fetch_core::{app_config, index::{ChunkFile, embedding::{embeddinggemma::EmbeddingGemmaEmbeddedChunkFile, siglip2::Siglip2EmbeddedChunkFile}}, store::lancedb::{ArrowData, LanceDBStore, TableRecovery}};
+
+use crate::index::{self, IndexArgs};
+
+pub struct RepairArgs {
+    /// Number of parallel indexing jobs to run at once when indexing files again
+    pub jobs: usize,
+}
+
+/// Checks every chunkfile table in the index, and recovers the ones that are damaged. The original files of the
+/// chunkfiles that could still be read from a damaged table are indexed again afterwards, to rebuild what was lost.
+pub async fn repair(args: RepairArgs) -> Result<(), Box<dyn Error>> {
+    let data_dir = app_config::get_default_index_directory();
+    let mut original_files = BTreeSet::new();
+
+    repair_table::<Siglip2EmbeddedChunkFile>(data_dir.as_str(), "siglip2_chunkfile", &mut original_files).await?;
+    repair_table::<EmbeddingGemmaEmbeddedChunkFile>(data_dir.as_str(), "gemma_chunkfile", &mut original_files).await?;
+
+    if original_files.is_empty() {
+        println!("Nothing to index again! Goodbye.");
+        return Ok(());
+    }
+
+    println!("Indexing {} files again to rebuild recovered tables", original_files.len());
+    index::index(IndexArgs {
+        jobs: args.jobs,
+        recursive: false,
+        force: true,
+        paths: original_files.into_iter().map(Utf8PathBuf::into_std_path_buf).collect(),
+    }).await
+}
+
+// Private functions and variables
+
+/// Recovers the table if it is damaged, adding the original files of the chunkfiles that could still be read from
+/// it to original_files
+async fn repair_table<D: ArrowData>(
+    data_dir: &str,
+    table_name: &str,
+    original_files: &mut BTreeSet<Utf8PathBuf>,
+) -> Result<(), Box<dyn Error>> {
+    let store = LanceDBStore::<D>::local(data_dir, table_name.to_owned()).await
+        .map_err(|e| format!("Could not open table {} with data dir: {}. Error: {e:?}", table_name, data_dir))?;
+
+    let e = match store.verify().await {
+        Ok(()) => {
+            println!("Table {} is healthy", table_name);
+            return Ok(());
+        },
+        Err(e) => e,
+    };
+    println!("Table {} is damaged, recovering: {e:?}", table_name);
+
+    let recovery = store.recover().await?;
+    match &recovery {
+        TableRecovery::Restored { version, .. } => println!("Table {} was restored to version {}", table_name, version),
+        TableRecovery::Dropped { .. } => println!("Table {} had no readable version and was dropped", table_name),
+    }
+    original_files.extend(recovery.salvaged_keys().iter()
+        .filter_map(|key| ChunkFile::original_file_from_stored_key(key)));
+
+    Ok(())
+}
@@ -0,0 +1,208 @@
+use std::{collections::HashMap, error::Error, io::Write, iter};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use fetch_core::{app_config, files::{desktop::{open_with_default_app, reveal_in_file_manager}, query::{QueryFiles, QueryResult, parse_query}}, set_model_keepalive, warm_models, ModelId};
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tracing::debug;
+
+use crate::query::{CollapseMode, QueryArgs, ResultGroup, collapse_results, format_sources, open_file_queryer, print_collapsed};
+
+/// Runs queries typed at the prompt until `:quit` or the end of input. The index stays open and the models loaded
+/// between queries, and the cursor of the last query stays alive, so `:more` pulls its next batch of chunks instead
+/// of querying again. The query in the args, if not empty, is run first.
+///
+/// Besides query terms, the prompt accepts:
+/// * `:more` - query the next batch of chunks for the last query, and show more of its results
+/// * `:filter <terms>` - add filter terms, eg. `type:pdf` or `lang:ja`, to the last query and run it again. Without
+///   terms, removes the filters from the last query instead.
+/// * `:open <number>` - open the result with that number with its default application
+/// * `:reveal <number>` - show the folder of the result with that number, with the result selected
+/// * `:expand <number>` - list the results collapsed into the result with that number, see CollapseMode
+/// * `:help` - list the commands
+/// * `:quit` - stop querying
+pub async fn interactive(args: QueryArgs) -> Result<(), Box<dyn Error>> {
+    let data_dir = app_config::get_default_index_directory();
+    println!("Opening file index at {}", data_dir.as_str());
+    let file_queryer = open_file_queryer(data_dir.as_str()).await;
+
+    println!("Loading models...");
+    set_model_keepalive(None);
+    tokio::task::spawn_blocking(|| warm_models(&ModelId::QUERYING, |_| ())).await?;
+    println!("Type a query, or :help for the commands");
+
+    let mut session = Session { num_results: args.num_results, collapse: args.collapse, ..Session::default() };
+    if !args.query.trim().is_empty() {
+        session.start(&file_queryer, args.query.trim(), args.chunks_per_query).await;
+    }
+
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    loop {
+        print!("> ");
+        std::io::stdout().flush()?;
+        let Some(line) = lines.next_line().await? else {
+            break;
+        };
+        let line = line.trim();
+
+        match line.split_once(' ').map_or((line, ""), |(command, rest)| (command, rest.trim())) {
+            ("", _) => {},
+            (":quit" | ":q", _) => break,
+            (":help", _) => print_help(),
+            (":more", _) => session.more(&file_queryer, args.chunks_per_query).await,
+            (":filter", terms) => {
+                let Some(query) = &session.query else {
+                    println!("No query to filter, type a query first");
+                    continue;
+                };
+                let query = if terms.is_empty() {
+                    parse_query(query).text
+                } else {
+                    format!("{} {}", query, terms)
+                };
+                session.start(&file_queryer, &query, args.chunks_per_query).await;
+            },
+            (":open", number) => match session.shown(number) {
+                Some(group) => match open_with_default_app(&group.result.path) {
+                    Ok(()) => println!("Opened {}", group.result.path),
+                    Err(e) => println!("Could not open {}: {}", group.result.path, e),
+                },
+                None => println!("No result numbered \"{}\", expected a number between 1 and {}", number,
+                    session.num_shown()),
+            },
+            (":reveal", number) => match session.shown(number) {
+                Some(group) => match reveal_in_file_manager(&group.result.path) {
+                    Ok(()) => println!("Revealed {}", group.result.path),
+                    Err(e) => println!("Could not reveal {}: {}", group.result.path, e),
+                },
+                None => println!("No result numbered \"{}\", expected a number between 1 and {}", number,
+                    session.num_shown()),
+            },
+            (":expand", number) => match session.shown(number) {
+                Some(group) if group.collapsed.is_empty() => println!("No results were collapsed into {}",
+                    group.result.path),
+                Some(group) => {
+                    for result in &group.collapsed {
+                        println!("    {} (score: {:.2}){}", result.path, result.score, format_sources(result));
+                    }
+                },
+                None => println!("No result numbered \"{}\", expected a number between 1 and {}", number,
+                    session.num_shown()),
+            },
+            (command, _) if command.starts_with(':') => println!("Unknown command {}, type :help for the commands",
+                command),
+            _ => session.start(&file_queryer, line, args.chunks_per_query).await,
+        }
+    }
+
+    Ok(())
+}
+
+// Private functions and structs
+
+/// The last query and what has been found for it so far
+#[derive(Default)]
+struct Session {
+    /// The number of results shown per query, and added by each `:more`
+    num_results: u32,
+    collapse: CollapseMode,
+    query: Option<String>,
+    /// None once the last query has no more chunks to query
+    cursor_id: Option<String>,
+    /// Everything found for the last query, in the order the results are numbered
+    groups: Vec<ResultGroup>,
+    /// The number of results to show, which grows with each `:more`
+    limit: usize,
+}
+
+impl Session {
+    /// Starts a new query, dropping the results of the last one
+    async fn start(&mut self, queryer: &impl QueryFiles, query: &str, chunks_per_query: u32) {
+        self.query = Some(query.to_owned());
+        self.cursor_id = None;
+        self.groups.clear();
+        self.limit = self.num_results as usize;
+        println!("Querying: \"{}\"", query);
+        self.query_batch(queryer, chunks_per_query).await;
+    }
+
+    /// Queries the next batch of chunks of the last query with its cursor, and shows more results
+    async fn more(&mut self, queryer: &impl QueryFiles, chunks_per_query: u32) {
+        if self.query.is_none() {
+            println!("No query to continue, type a query first");
+            return;
+        }
+        if self.cursor_id.is_none() {
+            println!("No more results for this query");
+            return;
+        }
+        self.limit += self.num_results as usize;
+        self.query_batch(queryer, chunks_per_query).await;
+    }
+
+    async fn query_batch(&mut self, queryer: &impl QueryFiles, chunks_per_query: u32) {
+        let query = self.query.as_deref().unwrap_or_default();
+        debug!("Querying batch for query: {}, cursor: {:?}", query, self.cursor_id);
+        let result = match queryer.query_n(query, chunks_per_query, self.cursor_id.as_deref()).await {
+            Ok(result) => result,
+            Err(e) => {
+                println!("Query failed: {}", e);
+                return;
+            },
+        };
+
+        if self.cursor_id.is_none() {
+            if let Some(corrected_query) = &result.corrected_query {
+                println!("Searched for: \"{}\"", corrected_query);
+            }
+        }
+        // The changed results replace the ones found before for the same files, so the groups are collapsed again
+        let mut results: HashMap<Utf8PathBuf, QueryResult> = self.groups.drain(..)
+            .flat_map(|group| iter::once(group.result).chain(group.collapsed))
+            .map(|r| (r.path.clone(), r))
+            .collect();
+        for changed in result.changed_results {
+            results.insert(changed.path.clone(), changed);
+        }
+        self.groups = collapse_results(results.into_values().collect(), self.collapse);
+        self.cursor_id = result.cursor_id;
+        self.print_results();
+    }
+
+    fn print_results(&self) {
+        if self.groups.is_empty() {
+            println!("No results!");
+            return;
+        }
+        for (i, group) in self.groups.iter().take(self.limit).enumerate() {
+            println!("{}: {} (score: {:.2}){}", i + 1, group.result.path, group.result.score,
+                format_sources(&group.result));
+            print_collapsed(group);
+        }
+        if self.cursor_id.is_some() {
+            println!("(:more for more results)");
+        }
+    }
+
+    fn num_shown(&self) -> usize {
+        self.groups.len().min(self.limit)
+    }
+
+    /// The shown result with the number, as typed after a command
+    fn shown(&self, number: &str) -> Option<&ResultGroup> {
+        number.parse::<usize>().ok()
+            .filter(|n| (1..=self.num_shown()).contains(n))
+            .map(|n| &self.groups[n - 1])
+    }
+}
+
+fn print_help() {
+    println!("Type query terms to start a new query, or:");
+    println!("  :more             query more chunks for the last query and show more results");
+    println!("  :filter <terms>   add filter terms (eg. type:pdf, lang:ja, tag:taxes) to the last query");
+    println!("  :filter           remove the filters from the last query");
+    println!("  :open <number>    open a result with its default application");
+    println!("  :reveal <number>  show the folder of a result, with the result selected");
+    println!("  :expand <number>  list the results collapsed into a result");
+    println!("  :help             show this list");
+    println!("  :quit             stop querying");
+}
@@ -0,0 +1,43 @@
+use std::{error::Error, path::{self, PathBuf}, sync::Arc, time::Duration};
+
+use fetch_core::{app_config, files::{FileIndexer, index::{FileIndexingResultType, IndexFiles}, journal::IndexJournal, lock::IndexLock, os_path::from_os_path}, index::provider::registry::ProviderRegistry, store::lancedb::LanceDBStore};
+use normalize_path::NormalizePath;
+
+pub struct RestoreArgs {
+    /// Path of the file or directory to restore the cleared index entries of
+    pub path: PathBuf,
+}
+
+/// Puts the cleared index entries of a file, or of every file under a directory, back in the index. Entries can be
+/// restored until they are purged, some days after being cleared.
+pub async fn restore(args: RestoreArgs) -> Result<(), Box<dyn Error>> {
+    let path = from_os_path(&path::absolute(&args.path)?.normalize());
+
+    let data_dir = app_config::get_default_index_directory();
+    let _index_lock = IndexLock::acquire(&data_dir, "fetch restore", Some(INDEX_LOCK_TIMEOUT)).await?;
+    let registry = ProviderRegistry::open(&data_dir).await
+        .unwrap_or_else(|e| panic!("Could not open index providers with data dir: {}. Error: {e:?}", data_dir.as_str()));
+    let journal_store = LanceDBStore::local_with_filters(
+        data_dir.as_str(),
+        "index_journal".to_owned()
+    ).await
+    .unwrap_or_else(|e|
+        panic!("Could not open lancedb store for the index journal with data dir: {}. Error: {e:?}",
+        data_dir.as_str()));
+    let file_indexer = FileIndexer::with(registry.providers())
+        .with_journal(Arc::new(IndexJournal::using(journal_store)));
+
+    match file_indexer.restore(&path).await?.r#type {
+        FileIndexingResultType::Restored { chunks: 0 } => println!("Nothing cleared under {} could be restored. \
+            Entries are purged some days after being cleared.", path),
+        FileIndexingResultType::Restored { chunks } => println!("Restored {} index entries under {}", chunks, path),
+        _ => unreachable!("Restore will only ever return a Restored result"),
+    }
+
+    Ok(())
+}
+
+// Private functions and variables
+
+/// How long to wait for another process writing to the index to finish
+const INDEX_LOCK_TIMEOUT: Duration = Duration::from_secs(10);
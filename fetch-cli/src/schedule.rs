@@ -0,0 +1,147 @@
+use std::{collections::BTreeSet, error::Error, fs, future::Future};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use chrono::Utc;
+use fetch_core::{app_config, files::{index::IndexFiles, journal::JournalEntry, links::{LinkResolver, ResolvedPath, SymlinkPolicy}, lock::IndexLockError, os_path::{from_os_path, to_os_path}, schedule::ScheduledJobKind, volumes::{self, FileLocation, IndexedVolumes}}, index::embedding::{embeddinggemma::EmbeddingGemmaEmbeddedChunkFile, siglip2::Siglip2EmbeddedChunkFile}, store::{QueryByFilter, lancedb::{ArrowData, LanceDBStore}}};
+use futures::{StreamExt, stream};
+use tracing::{info, warn};
+
+/// Runs a scheduled job, see JobScheduler. Jobs run with the file indexer of the file daemon, holding the index lock
+/// taken with lock_index for as long as they write to the index, eg. the shared lock of the daemon's workers.
+pub async fn run_job<I, F, Fut, G>(job: ScheduledJobKind, file_indexer: &I, lock_index: F) -> Result<(), Box<dyn Error>>
+where
+    I: IndexFiles,
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<G, IndexLockError>>,
+{
+    info!("Running scheduled {} job", job);
+    match job {
+        ScheduledJobKind::Reindex => {
+            let _index_lock = lock_index().await?;
+            reindex(file_indexer).await
+        },
+        ScheduledJobKind::Gc => {
+            let _index_lock = lock_index().await?;
+            gc(file_indexer).await
+        },
+        ScheduledJobKind::Optimize => {
+            let _index_lock = lock_index().await?;
+            optimize().await
+        },
+    }
+}
+
+// Private constants and functions
+
+/// Number of parallel indexing jobs scheduled reindexing runs with
+const REINDEX_JOBS: usize = 4;
+
+/// Indexes every file in the watched folders again. Files that have not changed since they were indexed are
+/// skipped by the providers.
+async fn reindex(file_indexer: &impl IndexFiles) -> Result<(), Box<dyn Error>> {
+    let watchlist = fs::read_to_string(app_config::get_watchlist_file_path())?;
+    let symlink_policy = SymlinkPolicy::from_settings().unwrap_or_else(|e| {
+        warn!("Could not load symlink policy, following symlinks: {:?}", e);
+        SymlinkPolicy::default()
+    });
+    let mut link_resolver = LinkResolver::using(symlink_policy);
+    let files = watched_files(watchlist.lines().filter(|line| !line.trim().is_empty()).map(Utf8PathBuf::from),
+        &mut link_resolver);
+    if let Err(e) = link_resolver.record_aliases() {
+        warn!("Could not record the names of symlinks as tags of their targets: {:?}", e);
+    }
+
+    info!("Scheduled reindex found {} files in the watched folders", files.len());
+    let modified = Utc::now();
+    let num_failed = stream::iter(&files)
+        .map(|path| file_indexer.index(path, Some(modified)))
+        .buffer_unordered(REINDEX_JOBS)
+        .filter(|result| futures::future::ready(match result {
+            Ok(_) => false,
+            Err(e) => {
+                warn!("Could not index {}: {:?}", e.path, e);
+                true
+            },
+        }))
+        .count().await;
+    if num_failed > 0 {
+        return Err(format!("{} of {} files failed indexing", num_failed, files.len()).into());
+    }
+    Ok(())
+}
+
+/// Clears the index entries of indexed files that no longer exist. Files on a drive or share that is not connected,
+/// or that is now mounted somewhere else, are kept.
+async fn gc(file_indexer: &impl IndexFiles) -> Result<(), Box<dyn Error>> {
+    let data_dir = app_config::get_default_index_directory();
+
+    let indexed_volumes = IndexedVolumes::load().unwrap_or_else(|e| {
+        warn!("Could not load indexed volumes, keeping files on every volume: {:?}", e);
+        IndexedVolumes::default()
+    });
+    let mounted = volumes::mounted_volumes();
+    let mut num_cleared = 0;
+    for path in indexed_files(&data_dir).await? {
+        // Files whose existence cannot be checked, eg. for lack of permissions, are kept
+        if indexed_volumes.locate(&path, &mounted) != FileLocation::Missing
+            || fs::exists(to_os_path(&path)).unwrap_or(true)
+        {
+            continue;
+        }
+        match file_indexer.clear(&path, None).await {
+            Ok(_) => num_cleared += 1,
+            Err(e) => warn!("Could not clear missing file {} from the index: {:?}", path, e),
+        }
+    }
+    info!("Scheduled gc cleared {} missing files from the index", num_cleared);
+    Ok(())
+}
+
+/// Purges cleared index entries past their retention window and compacts the chunkfile and journal tables
+async fn optimize() -> Result<(), Box<dyn Error>> {
+    let data_dir = app_config::get_default_index_directory();
+
+    optimize_table::<Siglip2EmbeddedChunkFile>(&data_dir, "siglip2_chunkfile").await?;
+    optimize_table::<EmbeddingGemmaEmbeddedChunkFile>(&data_dir, "gemma_chunkfile").await?;
+    optimize_table::<JournalEntry>(&data_dir, "index_journal").await?;
+    Ok(())
+}
+
+async fn optimize_table<D: ArrowData>(data_dir: &Utf8Path, table_name: &str) -> Result<(), Box<dyn Error>> {
+    let store = LanceDBStore::<D>::local(data_dir.as_str(), table_name.to_owned()).await
+        .map_err(|e| format!("Could not open table {} with data dir: {}. Error: {e:?}", table_name, data_dir))?;
+    store.optimize().await?;
+    Ok(())
+}
+
+/// The original files of every chunk in the index
+async fn indexed_files(data_dir: &Utf8Path) -> Result<BTreeSet<Utf8PathBuf>, Box<dyn Error>> {
+    let mut files = BTreeSet::new();
+    let siglip_store = LanceDBStore::<Siglip2EmbeddedChunkFile>::local_full(data_dir.as_str(),
+        "siglip2_chunkfile".to_owned()).await?;
+    files.extend(siglip_store.query_filter(&[]).await?.into_iter().map(|chunk| chunk.chunkfile.original_file));
+    let gemma_store = LanceDBStore::<EmbeddingGemmaEmbeddedChunkFile>::local_full(data_dir.as_str(),
+        "gemma_chunkfile".to_owned()).await?;
+    files.extend(gemma_store.query_filter(&[]).await?.into_iter().map(|chunk| chunk.chunkfile.original_file));
+    Ok(files)
+}
+
+/// The files in the watched paths and every folder below them, as resolved by the link resolver. Folders that cannot
+/// be read are left out, and watched paths that no longer exist are kept so indexing clears them.
+fn watched_files(paths: impl IntoIterator<Item = Utf8PathBuf>, link_resolver: &mut LinkResolver) -> Vec<Utf8PathBuf> {
+    let mut files = vec![];
+    let mut folders: Vec<Utf8PathBuf> = paths.into_iter().collect();
+    while let Some(path) = folders.pop() {
+        match link_resolver.resolve(&path) {
+            ResolvedPath::File(path) => files.push(path),
+            ResolvedPath::Directory(path) => match fs::read_dir(to_os_path(&path)) {
+                Ok(entries) => folders.extend(entries.filter_map(Result::ok).map(|entry| from_os_path(&entry.path()))),
+                Err(e) => warn!("Could not read watched folder {}: {:?}", path, e),
+            },
+            ResolvedPath::Skip { reason } => info!("{}, not reindexing {}", reason, path),
+        }
+    }
+    files.sort();
+    files.dedup();
+    files
+}
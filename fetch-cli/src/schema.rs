@@ -0,0 +1,92 @@
+use std::{error::Error, future::Future, time::Duration};
+
+use camino::Utf8Path;
+use fetch_core::{app_config, files::lock::{IndexLock, IndexLockError}, index::ChunkFile, store::lancedb::{ArrowData, LanceDBSchema}};
+use tracing::info;
+
+pub enum SchemaArgs {
+    /// Promote a key of the original file tags of chunks to a column of its own, so filters on it are fast
+    PromoteTag { key: String },
+    /// Drop the column of a promoted key, so filters on it search the tags again
+    DemoteTag { key: String },
+    /// List every promoted key and whether it has been backfilled
+    List,
+    /// Backfill the promoted keys now rather than waiting for the file daemon to
+    Backfill,
+}
+
+/// Promotes, demotes, lists or backfills keys of the original file tags of chunks, see LanceDBSchema
+pub async fn schema(args: SchemaArgs) -> Result<(), Box<dyn Error>> {
+    let data_dir = app_config::get_default_index_directory();
+    let schema = LanceDBSchema::local(data_dir.as_str()).await?;
+    let tags_column = ChunkFile::attribute_to_column_name(ChunkFile::FILE_TAGS_ATTR);
+
+    match args {
+        SchemaArgs::PromoteTag { key } => {
+            let _index_lock = IndexLock::acquire(&data_dir, "fetch schema", Some(INDEX_LOCK_TIMEOUT)).await?;
+            let tables = schema.promote_key(tags_column, &key).await?;
+            if tables.is_empty() {
+                println!("Tag key {} is already promoted", key);
+            } else {
+                println!("Promoted tag key {} in {} tables. The file daemon backfills it in the background, run \
+                    `fetch schema backfill` to backfill it now.", key, tables.len());
+            }
+        },
+        SchemaArgs::DemoteTag { key } => {
+            let _index_lock = IndexLock::acquire(&data_dir, "fetch schema", Some(INDEX_LOCK_TIMEOUT)).await?;
+            let tables = schema.demote_key(tags_column, &key).await?;
+            if tables.is_empty() {
+                println!("Tag key {} is not promoted", key);
+            } else {
+                println!("Demoted tag key {} in {} tables", key, tables.len());
+            }
+        },
+        SchemaArgs::List => {
+            let promoted_keys = schema.promoted_keys().await?;
+            if promoted_keys.is_empty() {
+                println!("No tag keys have been promoted yet");
+            }
+            for promoted in promoted_keys {
+                println!("{} in {} ({})", promoted.key, promoted.table,
+                    if promoted.backfilled { "backfilled" } else { "pending backfill" });
+            }
+        },
+        SchemaArgs::Backfill => {
+            let num_rows = backfill(&data_dir, "fetch schema", || IndexLock::acquire(&data_dir, "fetch schema", None)).await
+                .map_err(|e| e as Box<dyn Error>)?;
+            println!("Backfilled {} rows", num_rows);
+        },
+    }
+
+    Ok(())
+}
+
+/// Backfills every promoted key that is pending backfill, returning the number of rows filled in. Each batch of rows
+/// is filled in holding the index lock taken with lock_batch, so batches take turns with indexing rather than racing
+/// it, eg. the shared lock of the file daemon's workers. Completing each key takes the lock for the holder on its
+/// own, as puts must not run at the same time.
+pub async fn backfill<F, Fut, G>(data_dir: &Utf8Path, holder: &str, lock_batch: F) -> Result<u64, Box<dyn Error + Send + Sync>>
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<G, IndexLockError>>,
+{
+    let schema = LanceDBSchema::local(data_dir.as_str()).await?;
+    let mut num_rows = 0;
+    for promoted in schema.promoted_keys().await?.into_iter().filter(|promoted| !promoted.backfilled) {
+        for batch in schema.backfill_batches(&promoted).await? {
+            let _index_lock = lock_batch().await?;
+            num_rows += schema.backfill_batch(&batch).await?;
+        }
+
+        let _index_lock = IndexLock::acquire(data_dir, holder, None).await?;
+        // Rows put before the lock was acquired were filled in by their puts
+        schema.complete_backfill(&promoted).await?;
+        info!("Backfilled tag key {} in {}", promoted.key, promoted.table);
+    }
+    Ok(num_rows)
+}
+
+// Private constants
+
+/// How long to wait for another process writing to the index to finish
+const INDEX_LOCK_TIMEOUT: Duration = Duration::from_secs(10);
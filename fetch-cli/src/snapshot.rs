@@ -0,0 +1,60 @@
+use std::{error::Error, time::Duration};
+
+use chrono::Local;
+use fetch_core::{app_config, files::{lock::IndexLock, snapshot::IndexSnapshots}};
+
+pub enum SnapshotArgs {
+    /// Take a snapshot of the index, named after the current time if no name is given
+    Create { name: Option<String> },
+    /// List every snapshot, oldest first
+    List,
+    /// Roll the index back to a snapshot
+    Restore { name: String },
+    /// Delete a snapshot, so the index data only it kept can be freed
+    Delete { name: String },
+}
+
+/// Takes, lists, restores or deletes snapshots of the index, see IndexSnapshots
+pub async fn snapshot(args: SnapshotArgs) -> Result<(), Box<dyn Error>> {
+    let snapshots = IndexSnapshots::default_directories();
+
+    if let SnapshotArgs::List = args {
+        let list = snapshots.list()?;
+        if list.is_empty() {
+            println!("No snapshots have been taken yet");
+        }
+        for snapshot in list {
+            println!("{} (taken {}, {} tables, {} chunkfiles)", snapshot.name,
+                snapshot.created_at.with_timezone(&Local).format("%Y-%m-%d %H:%M"), snapshot.tables.len(),
+                snapshot.num_chunkfiles);
+        }
+        return Ok(());
+    }
+
+    let data_dir = app_config::get_default_index_directory();
+    let _index_lock = IndexLock::acquire(&data_dir, "fetch snapshot", Some(INDEX_LOCK_TIMEOUT)).await?;
+    match args {
+        SnapshotArgs::Create { name } => {
+            let snapshot = snapshots.create(name.as_deref()).await?;
+            println!("Took snapshot {} of {} tables and {} chunkfiles", snapshot.name, snapshot.tables.len(),
+                snapshot.num_chunkfiles);
+        },
+        SnapshotArgs::Restore { name } => {
+            let snapshot = snapshots.restore(&name).await?;
+            println!("Rolled the index back to snapshot {}, taken {}", snapshot.name,
+                snapshot.created_at.with_timezone(&Local).format("%Y-%m-%d %H:%M"));
+        },
+        SnapshotArgs::Delete { name } => {
+            snapshots.delete(&name).await?;
+            println!("Deleted snapshot {}", name);
+        },
+        SnapshotArgs::List => unreachable!("Snapshots are listed without locking the index"),
+    }
+
+    Ok(())
+}
+
+// Private constants
+
+/// How long to wait for another process writing to the index to finish
+const INDEX_LOCK_TIMEOUT: Duration = Duration::from_secs(10);
@@ -0,0 +1,101 @@
+use std::{collections::HashMap, error::Error};
+
+use camino::Utf8PathBuf;
+use chrono::Local;
+use fetch_core::{app_config, files::{disk_space::{DiskSpaceSettings, directory_size, free_space}, schedule::JobScheduler}, index::embedding::{embeddinggemma::EmbeddingGemmaEmbeddedChunkFile, siglip2::Siglip2EmbeddedChunkFile}, store::{QueryByFilter, lancedb::LanceDBStore}};
+use tracing::warn;
+
+use crate::utility::format_size;
+
+/// Prints how much disk space the index takes up, how much it takes per megabyte of indexed files, how many more
+/// megabytes of files fit in the free space left before indexing pauses for low disk space, and when the scheduled
+/// jobs of the file daemon run next
+pub async fn stats() -> Result<(), Box<dyn Error>> {
+    print_disk_usage().await?;
+    print_scheduled_jobs();
+    Ok(())
+}
+
+// Private constants and functions
+
+const MB: f64 = 1024.0 * 1024.0;
+
+/// Prints how much disk space the index takes up, how much it takes per megabyte of indexed files, and how many
+/// more megabytes of files fit in the free space left before indexing pauses for low disk space
+async fn print_disk_usage() -> Result<(), Box<dyn Error>> {
+    let index_dir = app_config::get_default_index_directory();
+    let chunk_dir = app_config::get_default_chunk_directory();
+
+    let mut source_sizes: HashMap<Utf8PathBuf, u64> = HashMap::new();
+    let mut num_chunks: usize = 0;
+    let siglip_store = LanceDBStore::<Siglip2EmbeddedChunkFile>::local_full(index_dir.as_str(),
+        "siglip2_chunkfile".to_owned()).await?;
+    for chunk in siglip_store.query_filter(&[]).await? {
+        num_chunks += 1;
+        source_sizes.insert(chunk.chunkfile.original_file, chunk.chunkfile.original_file_size);
+    }
+    let gemma_store = LanceDBStore::<EmbeddingGemmaEmbeddedChunkFile>::local_full(index_dir.as_str(),
+        "gemma_chunkfile".to_owned()).await?;
+    for chunk in gemma_store.query_filter(&[]).await? {
+        num_chunks += 1;
+        source_sizes.insert(chunk.chunkfile.original_file, chunk.chunkfile.original_file_size);
+    }
+    let source_bytes: u64 = source_sizes.values().sum();
+
+    let (index_bytes, chunk_bytes, free_bytes) = {
+        let (index_dir, chunk_dir) = (index_dir.clone(), chunk_dir.clone());
+        tokio::task::spawn_blocking(move || {
+            (directory_size(&index_dir), directory_size(&chunk_dir), free_space(&index_dir))
+        }).await?
+    };
+    let data_bytes = index_bytes + chunk_bytes;
+
+    println!("Indexed files: {} ({}, {} chunks)", source_sizes.len(), format_size(source_bytes), num_chunks);
+    println!("Index: {} in {}", format_size(index_bytes), index_dir);
+    println!("Chunks: {} in {}", format_size(chunk_bytes), chunk_dir);
+
+    if source_bytes == 0 {
+        println!("Nothing indexed yet, index some files to project the space they take up");
+        return Ok(());
+    }
+    let bytes_per_source_mb = data_bytes as f64 / (source_bytes as f64 / MB);
+    println!("Average chunk bytes per source MB: {} ({:.1}% of the indexed files)",
+        format_size(bytes_per_source_mb as u64), data_bytes as f64 / source_bytes as f64 * 100.0);
+
+    let Some(free_bytes) = free_bytes else {
+        println!("Could not read the free space on the disk of the index, cannot project how much more fits");
+        return Ok(());
+    };
+    let min_free_bytes = DiskSpaceSettings::from_settings().unwrap_or_else(|e| {
+        warn!("Could not load low disk space settings, using defaults: {:?}", e);
+        DiskSpaceSettings::default()
+    }).min_free_mb.saturating_mul(1024 * 1024);
+    let usable_bytes = free_bytes.saturating_sub(min_free_bytes);
+    println!("Free: {} ({} before indexing pauses for low disk space)", format_size(free_bytes),
+        format_size(usable_bytes));
+    println!("Projected: about {} more of files can be indexed", format_size(
+        (usable_bytes as f64 / bytes_per_source_mb * MB) as u64));
+
+    Ok(())
+}
+
+/// Prints the scheduled jobs of the file daemon and when they run next, see JobScheduler
+fn print_scheduled_jobs() {
+    let scheduler = JobScheduler::from_settings().unwrap_or_else(|e| {
+        warn!("Could not load scheduled jobs: {:?}", e);
+        JobScheduler::default()
+    });
+    if scheduler.jobs().is_empty() {
+        println!("\nScheduled jobs: none, see scheduled_jobs in daemon.toml");
+        return;
+    }
+
+    println!("\nScheduled jobs (run by the file daemon while it runs):");
+    for (job, next_run) in scheduler.next_runs(Local::now()) {
+        match next_run {
+            Some(next_run) => println!("  {} ({}): next run {}", job.kind, job.schedule.expression(),
+                next_run.format("%Y-%m-%d %H:%M")),
+            None => println!("  {} ({}): never runs", job.kind, job.schedule.expression()),
+        }
+    }
+}
@@ -0,0 +1,31 @@
+use std::{error::Error, path::{self, PathBuf}, sync::Arc};
+
+use fetch_core::{app_config, files::{os_path::from_os_path, summary::FileSummarizer}, index::summarize::Summarizer, store::lancedb::LanceDBStore};
+use normalize_path::NormalizePath;
+
+pub struct SummarizeArgs {
+    /// Path to the indexed file to summarize
+    pub path: PathBuf,
+}
+
+pub async fn summarize(args: SummarizeArgs) -> Result<(), Box<dyn Error>> {
+    let path = from_os_path(&path::absolute(&args.path)?.normalize());
+
+    let summarizer = Summarizer::new()
+        .map_err(|e| format!("Summarization model is not available: {}", e))?;
+
+    let data_dir = app_config::get_default_index_directory();
+    let gemma_store = Arc::new(LanceDBStore::local_full(
+        data_dir.as_str(),
+        "gemma_chunkfile".to_owned()
+    ).await
+    .unwrap_or_else(|e|
+        panic!("Could not open lancedb store for pdf index with data dir: {}. Error: {e:?}",
+        data_dir.as_str())));
+
+    println!("Summarizing {}...", path);
+    let summary = FileSummarizer::using(gemma_store, summarizer).summarize(&path).await?;
+    println!("\n{}", summary);
+
+    Ok(())
+}
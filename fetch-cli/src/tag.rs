@@ -0,0 +1,86 @@
+use std::{error::Error, path::{self, Path, PathBuf}};
+
+use camino::Utf8PathBuf;
+use fetch_core::{app_config, files::{FileIndexer, index::IndexFiles, os_path::from_os_path, tags::{UserTags, normalize_tag}}, index::provider::registry::ProviderRegistry};
+use normalize_path::NormalizePath;
+
+pub enum TagArgs {
+    /// Add a tag to a file
+    Add { path: PathBuf, tag: String },
+    /// Remove a tag from a file
+    Remove { path: PathBuf, tag: String },
+    /// List the tags of a file, or every tag in use if no path is given
+    List { path: Option<PathBuf> },
+}
+
+pub async fn tag(args: TagArgs) -> Result<(), Box<dyn Error>> {
+    let mut user_tags = UserTags::load()?;
+
+    let (path, tag, added) = match args {
+        TagArgs::Add { path, tag } => {
+            let path = to_utf8_path(&path)?;
+            let tag = normalize_tag(&tag).ok_or_else(|| invalid_tag_message(&tag))?;
+            if !user_tags.add(&path, &tag) {
+                println!("{} is already tagged with {}", path, tag);
+                return Ok(());
+            }
+            (path, tag, true)
+        },
+        TagArgs::Remove { path, tag } => {
+            let path = to_utf8_path(&path)?;
+            let tag = normalize_tag(&tag).ok_or_else(|| invalid_tag_message(&tag))?;
+            if !user_tags.remove(&path, &tag) {
+                println!("{} is not tagged with {}", path, tag);
+                return Ok(());
+            }
+            (path, tag, false)
+        },
+        TagArgs::List { path: Some(path) } => {
+            let path = to_utf8_path(&path)?;
+            let tags = user_tags.tags(&path);
+            if tags.is_empty() {
+                println!("{} has no tags", path);
+            } else {
+                println!("{}", tags.join(" "));
+            }
+            return Ok(());
+        },
+        TagArgs::List { path: None } => {
+            let counts = user_tags.tag_counts();
+            if counts.is_empty() {
+                println!("No tags have been added yet");
+            }
+            for (tag, count) in counts {
+                println!("{} ({} file(s))", tag, count);
+            }
+            return Ok(());
+        },
+    };
+    user_tags.save()?;
+
+    // Copy the file's new tags onto its indexed chunks, so they take effect without reindexing
+    let data_dir = app_config::get_default_index_directory();
+    let registry = ProviderRegistry::open(&data_dir).await
+        .unwrap_or_else(|e| panic!("Could not open index providers with data dir: {}. Error: {e:?}", data_dir.as_str()));
+    let file_indexer = FileIndexer::with(registry.providers());
+
+    file_indexer.tag(&path, &user_tags.tags(&path)).await?;
+
+    if added {
+        println!("Tagged {} with {}", path, tag);
+    } else {
+        println!("Removed tag {} from {}", tag, path);
+    }
+
+    Ok(())
+}
+
+// Private functions
+
+fn to_utf8_path(path: &Path) -> Result<Utf8PathBuf, Box<dyn Error>> {
+    Ok(from_os_path(&path::absolute(path)?.normalize()))
+}
+
+fn invalid_tag_message(tag: &str) -> String {
+    format!("Invalid tag {:?}: tags may only contain letters, numbers, '-', '_' and '.'", tag)
+}
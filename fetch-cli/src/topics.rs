@@ -0,0 +1,51 @@
+use std::{error::Error, sync::Arc};
+
+use fetch_core::{app_config, files::topics::TopicClusterer, store::lancedb::LanceDBStore};
+
+pub struct TopicsArgs {
+    /// The number of topics to group indexed files into, default 10
+    pub num_topics: usize,
+    /// The number of files to list per topic, default 5
+    pub files_per_topic: usize,
+}
+
+pub async fn topics(args: TopicsArgs) -> Result<(), Box<dyn Error>> {
+    let data_dir = app_config::get_default_index_directory();
+    let siglip_store = Arc::new(LanceDBStore::local_full(
+        data_dir.as_str(),
+        "siglip2_chunkfile".to_owned()
+    ).await
+    .unwrap_or_else(|e|
+        panic!("Could not open lancedb store with data dir: {}. Error: {e:?}",
+        data_dir.as_str())));
+    let gemma_store = Arc::new(LanceDBStore::local_full(
+        data_dir.as_str(),
+        "gemma_chunkfile".to_owned()
+    ).await
+    .unwrap_or_else(|e|
+        panic!("Could not open lancedb store with data dir: {}. Error: {e:?}",
+        data_dir.as_str())));
+
+    println!("Grouping indexed files into topics...");
+    let topics = TopicClusterer::using(siglip_store, gemma_store)
+        .cluster_index(args.num_topics)
+        .await?;
+
+    if topics.is_empty() {
+        println!("No files have been indexed yet");
+        return Ok(());
+    }
+
+    for (i, topic) in topics.iter().enumerate() {
+        let terms = if topic.terms.is_empty() { "(miscellaneous)".to_owned() } else { topic.terms.join(", ") };
+        println!("\n{}: {} ({} file(s))", i + 1, terms, topic.files.len());
+        for file in topic.files.iter().take(args.files_per_topic) {
+            println!("  {}", file);
+        }
+        if topic.files.len() > args.files_per_topic {
+            println!("  ...and {} more", topic.files.len() - args.files_per_topic);
+        }
+    }
+
+    Ok(())
+}
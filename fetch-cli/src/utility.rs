@@ -36,4 +36,20 @@ pub fn print_metrics(metrics: &RuntimeMetrics) {
     // Thread Pool Metrics
     println!("  - Num blocking threads: {}", metrics.num_blocking_threads());
     println!("  - Num idle blocking threads: {}", metrics.num_idle_blocking_threads());
-}
\ No newline at end of file
+}
+
+/// The size in bytes in the largest unit it is at least one of, eg. "1.5 GB"
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[unit])
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
@@ -1,11 +1,25 @@
 use std::collections::HashSet;
 use std::error::Error;
+use std::fmt;
 use std::fs::File;
 use std::io::{self, Write};
 use std::{env, fs};
 use std::path::{Path, PathBuf};
 use reqwest::blocking::Client;
 use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+/// Manifest of the SHA-256 digests of the libraries in bundle/, recorded when they are extracted from a verified
+/// release archive or copied from a custom build. The digests of the libraries are compiled into fetch-core from a
+/// copy of it in OUT_DIR, see environment::LIBRARY_DIGESTS.
+const LIBRARY_MANIFEST_FILE: &str = "libraries.sha256";
+
+/// SHA-256 digests of the release archives the build downloads libraries from, in the format of sha256sum. The
+/// build fails when a downloaded archive is not listed or does not match.
+const RELEASE_DIGESTS: &str = include_str!("release-digests.sha256");
+
+/// Version of ONNX Runtime downloaded unless ONNX_RELEASE_VERSION_DOWNLOAD is set
+const ONNX_RELEASE_VERSION: &str = "1.22.0";
 
 fn main() {
     println!("cargo:rerun-if-changed=build.rs");
@@ -16,6 +30,7 @@ fn main() {
 
     match download_onnx_runtime(&out_dir) {
         Ok(_) => {},
+        Err(e) if e.is::<ReleaseDigestError>() => panic!("{}", e),
         Err(e) => {
             println!("cargo:error=Failed to load ONNX Runtime files: {}", e);
         }
@@ -24,11 +39,20 @@ fn main() {
     #[cfg(feature = "pdf")]
     match pdfium::download_pdfium_dylib(&out_dir) {
         Ok(_) => {},
+        Err(e) if e.is::<ReleaseDigestError>() => panic!("{}", e),
         Err(e) => {
             println!("cargo:error=Failed to load PDFium files: {}", e);
         }
     }
 
+    #[allow(unused_mut)] // only extended with the pdf feature
+    let mut lib_patterns = onnx_lib_patterns();
+    #[cfg(feature = "pdf")]
+    lib_patterns.extend(pdfium::PDFIUM_LIB_PATTERNS);
+    if let Err(e) = embed_library_digests(&out_dir, &lib_patterns) {
+        panic!("Failed to embed the digests of the libraries in bundle/: {}", e);
+    }
+
     // Skip model downloading in release builds - CI/CD workflows will download models
     // For Windows: models are packaged separately with the MSI installer
     // For macOS/Linux: models are downloaded by CI before building and included in the bundle
@@ -65,15 +89,26 @@ fn main() {
     }
 }
 
-#[derive(Deserialize)]
-struct VersionInfoJson {
-    tag_name: String,
+/// A downloaded release archive that is not pinned in RELEASE_DIGESTS or does not match its digest, which fails
+/// the build rather than bundling libraries that were not verified
+#[derive(Debug)]
+struct ReleaseDigestError(String);
+
+impl fmt::Display for ReleaseDigestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
 }
 
+impl Error for ReleaseDigestError {}
+
 #[cfg(feature = "pdf")]
 mod pdfium {
     use super::*;
 
+    /// Version of PDFium downloaded unless PDFIUM_RELEASE_VERSION_DOWNLOAD is set
+    const PDFIUM_RELEASE_VERSION: &str = "chromium/7520";
+
     // List of pdfium library files we want to extract
     #[cfg(target_os = "windows")]
     pub const PDFIUM_LIB_PATTERNS: [&str; 1] = [
@@ -110,8 +145,12 @@ mod pdfium {
             }
         };
         if lib_exists {
-            println!("cargo:warning=Pdfium libraries already exist in bundle/, skipping download");
-            return Ok(());
+            if libraries_match_manifest(out_dir, &PDFIUM_LIB_PATTERNS)? {
+                println!("cargo:warning=Pdfium libraries already exist in bundle/, skipping download");
+                return Ok(());
+            }
+            println!("cargo:warning=Pdfium libraries in bundle/ do not match their digests, downloading them again");
+            remove_libraries(out_dir, &PDFIUM_LIB_PATTERNS)?;
         }
 
         // Skip download if ONNX_BUILD_PATH is set (user is providing their own ONNX Runtime)
@@ -126,35 +165,14 @@ mod pdfium {
                 println!("cargo:error=Failed to copy dylibs from PDFIUM_BUILD_PATH: {}", e);
                 return Err(e);
             }
-            return Ok(());
+            return record_library_digests(out_dir, &PDFIUM_LIB_PATTERNS);
         }
 
-        // Resolve latest version
         let client = reqwest_client()?;
         println!("cargo:rerun-if-env-changed=PDFIUM_RELEASE_VERSION_DOWNLOAD");
-        // Should resolve to a string like "chromium/7520", no v prefix or anything
-        let pdfium_version = match env::var("PDFIUM_RELEASE_VERSION_DOWNLOAD") {
-            Ok(ver) => ver,
-            Err(_) => {
-                let response = client
-                    .get("https://api.github.com/repos/bblanchon/pdfium-binaries/releases/latest")
-                    .send()?;
-                if !response.status().is_success() {
-                    return Err(format!(
-                        "Failed to contact github for pdfium release info: HTTP {}, Response: {}",
-                        response.status(),
-                        response.text().unwrap_or_default()
-                    )
-                    .into());
-                }
-                let tag = response.json::<VersionInfoJson>().unwrap().tag_name;
-                if tag.chars().next().expect("tag is empty") == 'v' {
-                    tag[1..].to_owned()
-                } else {
-                    tag
-                }
-            }
-        };
+        // Should be a string like "chromium/7520", no v prefix or anything
+        let pdfium_version = env::var("PDFIUM_RELEASE_VERSION_DOWNLOAD")
+            .unwrap_or_else(|_| PDFIUM_RELEASE_VERSION.to_owned());
 
         // Construct download URL based on version, platform and architecture
         let filename = match (target_os.as_ref(), architecture.as_ref()) {
@@ -184,6 +202,7 @@ mod pdfium {
         }
 
         let bytes = response.bytes()?;
+        verify_archive_digest(&bytes, filename)?;
 
         // Create a temporary file for the archive
         #[allow(clippy::needless_borrows_for_generic_args)] // filename is used later
@@ -204,7 +223,7 @@ mod pdfium {
         // Clean up temp file
         fs::remove_file(temp_archive)?;
 
-        Ok(())
+        record_library_digests(out_dir, &PDFIUM_LIB_PATTERNS)
     }
 }
 
@@ -293,20 +312,26 @@ const ONNX_LIB_PATTERNS: [&str; 4] = [
     "libonnxruntime_providers_cuda.so",
     "libonnxruntime_providers_tensorrt.so",
 ];
+// QNN backend libraries, loaded by the QNN execution provider from a custom ONNX Runtime build
+#[cfg(feature = "qnn")]
+const QNN_LIB_PATTERNS: [&str; 1] = [
+    "QnnHtp.dll",
+];
+
+/// ONNX Runtime library files, along with the QNN backend with the qnn feature
+fn onnx_lib_patterns() -> Vec<&'static str> {
+    #[allow(unused_mut)] // only extended with the qnn feature
+    let mut lib_patterns = ONNX_LIB_PATTERNS.to_vec();
+    #[cfg(feature = "qnn")]
+    lib_patterns.extend(QNN_LIB_PATTERNS);
+    lib_patterns
+}
+
 fn download_onnx_runtime(out_dir: &Path) -> Result<(), Box<dyn Error>> {
     if !out_dir.exists() {
         fs::create_dir_all(out_dir)?;
     }
-
-    // Determine which ONNX Runtime variant to download
-    let variant = if cfg!(feature = "cuda") {
-        "gpu" // GPU variant includes CUDA support
-    } else if cfg!(feature = "qnn") {
-        // QNN requires custom build, error out for now
-        panic!("QNN feature requires building ONNX Runtime from source. Not yet supported in automated builds.");
-    } else {
-        "cpu" // Default CPU-only variant
-    };
+    let lib_patterns = onnx_lib_patterns();
 
     println!("cargo:rerun-if-changed=build.rs");
 
@@ -326,8 +351,12 @@ fn download_onnx_runtime(out_dir: &Path) -> Result<(), Box<dyn Error>> {
         }
     };
     if lib_exists {
-        println!("cargo:warning=ONNX Runtime libraries already exist in bundle/, skipping download");
-        return Ok(());
+        if libraries_match_manifest(out_dir, &lib_patterns)? {
+            println!("cargo:warning=ONNX Runtime libraries already exist in bundle/, skipping download");
+            return Ok(());
+        }
+        println!("cargo:warning=ONNX Runtime libraries in bundle/ do not match their digests, downloading them again");
+        remove_libraries(out_dir, &lib_patterns)?;
     }
 
     println!("cargo:rerun-if-env-changed=ONNX_BUILD_PATH");
@@ -337,14 +366,24 @@ fn download_onnx_runtime(out_dir: &Path) -> Result<(), Box<dyn Error>> {
         if let Err(e) = copy_libs_to_path_recursive(
             Path::new(&onnx_build_path),
             out_dir,
-            &ONNX_LIB_PATTERNS
+            &lib_patterns
         ) {
             println!("cargo:error=Failed to copy dylibs from ONNX_BUILD_PATH: {}", e);
             return Err(e);
         }
-        return Ok(());
+        return record_library_digests(out_dir, &lib_patterns);
     }
 
+    // Determine which ONNX Runtime variant to download
+    let variant = if cfg!(feature = "cuda") {
+        "gpu" // GPU variant includes CUDA support
+    } else if cfg!(feature = "qnn") {
+        // QNN requires custom build, error out for now
+        panic!("QNN feature requires building ONNX Runtime from source and setting ONNX_BUILD_PATH. Not yet supported in automated builds.");
+    } else {
+        "cpu" // Default CPU-only variant
+    };
+
     // Download and extract ONNX Runtime
     if let Err(e) = download_and_extract_onnx(variant, &target_os, out_dir) {
         println!("cargo:error=Failed to download ONNX Runtime: {}", e);
@@ -356,7 +395,7 @@ fn download_onnx_runtime(out_dir: &Path) -> Result<(), Box<dyn Error>> {
         variant
     );
 
-    Ok(())
+    record_library_digests(out_dir, &lib_patterns)
 }
 
 fn download_and_extract_onnx(
@@ -367,28 +406,8 @@ fn download_and_extract_onnx(
     let client = reqwest_client()?;
 
     println!("cargo:rerun-if-env-changed=ONNX_RELEASE_VERSION_DOWNLOAD");
-    let onnx_version = match env::var("ONNX_RELEASE_VERSION_DOWNLOAD") {
-        Ok(ver) => ver,
-        Err(_) => {
-            let response = client
-                .get("https://api.github.com/repos/microsoft/onnxruntime/releases/latest")
-                .send()?;
-            if !response.status().is_success() {
-                return Err(format!(
-                    "Failed to contact github for onnx release info: HTTP {}, Response: {}",
-                    response.status(),
-                    response.text().unwrap_or_default()
-                )
-                .into());
-            }
-            let tag = response.json::<VersionInfoJson>().unwrap().tag_name;
-            if tag.chars().next().expect("tag is empty") == 'v' {
-                tag[1..].to_owned()
-            } else {
-                tag
-            }
-        }
-    };
+    let onnx_version = env::var("ONNX_RELEASE_VERSION_DOWNLOAD")
+        .unwrap_or_else(|_| ONNX_RELEASE_VERSION.to_owned());
 
     // Construct download URL based on platform and variant
     let filename = match (target_os, variant) {
@@ -423,6 +442,7 @@ fn download_and_extract_onnx(
     }
 
     let bytes = response.bytes()?;
+    verify_archive_digest(&bytes, &filename)?;
 
     // Create a temporary file for the archive
     #[allow(clippy::needless_borrows_for_generic_args)] // filename is used later
@@ -435,9 +455,9 @@ fn download_and_extract_onnx(
     println!("cargo:warning=Extracting libraries to bundle/...");
 
     if filename.ends_with(".zip") {
-        extract_from_zip(&temp_archive, output_dir, &ONNX_LIB_PATTERNS)?;
+        extract_from_zip(&temp_archive, output_dir, &onnx_lib_patterns())?;
     } else if filename.ends_with(".tgz") {
-        extract_from_tar_gz(&temp_archive, output_dir, &ONNX_LIB_PATTERNS)?;
+        extract_from_tar_gz(&temp_archive, output_dir, &onnx_lib_patterns())?;
     }
 
     // Clean up temp file
@@ -559,6 +579,128 @@ fn extract_from_tar_gz(tar_gz_path: &Path, output_dir: &Path, lib_patterns: &[&s
     Ok(())
 }
 
+/// Checks the downloaded archive against its digest in RELEASE_DIGESTS, failing with a ReleaseDigestError if it is
+/// not listed there or does not match
+fn verify_archive_digest(bytes: &[u8], filename: &str) -> Result<(), Box<dyn Error>> {
+    let Some((expected, _)) = parse_digests(RELEASE_DIGESTS).into_iter().find(|(_, name)| name == filename) else {
+        return Err(ReleaseDigestError(format!(
+            "{} has no pinned digest in fetch-core/release-digests.sha256. Verify the release and add its digest there, \
+            or provide the libraries with ONNX_BUILD_PATH or PDFIUM_BUILD_PATH",
+            filename
+        ))
+        .into());
+    };
+    let actual = sha256_digest(&mut io::Cursor::new(bytes))?;
+    if actual != expected {
+        return Err(ReleaseDigestError(format!(
+            "Downloaded {} does not match its digest in fetch-core/release-digests.sha256 (expected {}, found {})",
+            filename, expected, actual
+        ))
+        .into());
+    }
+    println!("cargo:warning=Verified digest of {}", filename);
+    Ok(())
+}
+
+/// Whether the libraries in bundle/ matching the patterns all have digests in the manifest and match them
+fn libraries_match_manifest(out_dir: &Path, lib_patterns: &[&str]) -> Result<bool, Box<dyn Error>> {
+    let manifest = read_library_manifest(out_dir)?;
+    for pattern in lib_patterns {
+        let path = out_dir.join(pattern);
+        if !path.is_file() {
+            continue;
+        }
+        let Some((expected, _)) = manifest.iter().find(|(_, file_name)| file_name == pattern) else {
+            println!("cargo:warning={} has no digest in {}", pattern, LIBRARY_MANIFEST_FILE);
+            return Ok(false);
+        };
+        if sha256_digest(&mut File::open(&path)?)? != *expected {
+            println!("cargo:warning={} does not match its digest in {}", pattern, LIBRARY_MANIFEST_FILE);
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+fn remove_libraries(out_dir: &Path, lib_patterns: &[&str]) -> Result<(), Box<dyn Error>> {
+    for pattern in lib_patterns {
+        let path = out_dir.join(pattern);
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Records the digests of the libraries in bundle/ matching the patterns in the manifest, replacing their previous
+/// digests. Only called once the libraries were extracted from a verified archive or copied from a custom build.
+fn record_library_digests(out_dir: &Path, lib_patterns: &[&str]) -> Result<(), Box<dyn Error>> {
+    let mut manifest: Vec<(String, String)> = read_library_manifest(out_dir)?.into_iter()
+        .filter(|(_, file_name)| !lib_patterns.contains(&file_name.as_str()))
+        .collect();
+    for pattern in lib_patterns {
+        let path = out_dir.join(pattern);
+        if path.is_file() {
+            manifest.push((sha256_digest(&mut File::open(&path)?)?, pattern.to_string()));
+        }
+    }
+    fs::write(out_dir.join(LIBRARY_MANIFEST_FILE), format_digests(&manifest))?;
+    Ok(())
+}
+
+/// Writes the digests of the libraries in bundle/ matching the patterns to OUT_DIR, where fetch-core includes them
+/// to check the libraries against before loading them. Libraries that do not match their recorded digests, or have
+/// none, are left out, so fetch-core refuses to load them.
+fn embed_library_digests(out_dir: &Path, lib_patterns: &[&str]) -> Result<(), Box<dyn Error>> {
+    let mut digests = vec![];
+    for (expected, file_name) in read_library_manifest(out_dir)? {
+        let path = out_dir.join(&file_name);
+        if !lib_patterns.contains(&file_name.as_str()) || !path.is_file() {
+            continue;
+        }
+        if sha256_digest(&mut File::open(&path)?)? != expected {
+            println!("cargo:warning={} does not match its digest in {}, not bundling its digest", file_name,
+                LIBRARY_MANIFEST_FILE);
+            continue;
+        }
+        digests.push((expected, file_name));
+    }
+    fs::write(PathBuf::from(env::var("OUT_DIR")?).join(LIBRARY_MANIFEST_FILE), format_digests(&digests))?;
+    Ok(())
+}
+
+/// Digests and file names in the manifest, empty if there is no manifest yet
+fn read_library_manifest(out_dir: &Path) -> Result<Vec<(String, String)>, Box<dyn Error>> {
+    match fs::read_to_string(out_dir.join(LIBRARY_MANIFEST_FILE)) {
+        Ok(contents) => Ok(parse_digests(&contents)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(vec![]),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Digests and file names in lines in the format of sha256sum, skipping blank lines and # comments
+fn parse_digests(contents: &str) -> Vec<(String, String)> {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| line.split_once(char::is_whitespace))
+        // sha256sum marks digests of files read in binary mode with a *
+        .map(|(digest, file_name)| (digest.to_lowercase(), file_name.trim_start().trim_start_matches('*').to_owned()))
+        .collect()
+}
+
+fn format_digests(digests: &[(String, String)]) -> String {
+    // Two spaces between digest and file name, as written by sha256sum
+    digests.iter().map(|(digest, file_name)| format!("{}  {}\n", digest, file_name)).collect()
+}
+
+fn sha256_digest(reader: &mut impl io::Read) -> Result<String, Box<dyn Error>> {
+    let mut hasher = Sha256::new();
+    io::copy(reader, &mut hasher)?;
+    Ok(hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
 fn reqwest_client() -> Result<Client, Box<dyn Error>> {
     Ok(Client::builder()
         .user_agent(format!(
@@ -3,11 +3,33 @@ use std::{fs, sync::LazyLock};
 use camino::{Utf8Path, Utf8PathBuf};
 use config::{Config, ConfigError, File};
 
+/// Gets the directory Fetch keeps its data in, which the other data paths are relative to unless they are
+/// overridden in the data configuration file, see get_data_paths.
+/// 
+/// This function reads the data_directory setting from the data configuration file and replaces the
+/// `%%AppDataDirectory%%` placeholder with the actual application data directory path. Data configuration
+/// files created before this setting existed fall back to the data folder of the application data directory.
+/// 
+/// # Returns
+/// 
+/// A [`Utf8PathBuf`] representing the path to the data directory.
+/// 
+/// # Panics
+/// 
+/// Panics if the data configuration cannot be loaded.
+pub fn get_data_directory() -> Utf8PathBuf {
+    let data_config = get_data_config().expect("Failed to load data config");
+
+    Utf8PathBuf::from(data_config.get_string("data_directory")
+        .unwrap_or_else(|_| "%%AppDataDirectory%%/data".to_owned())
+        .replace("%%AppDataDirectory%%", get_app_folder().as_str()))
+}
+
 /// Gets the default directory path for storing file indices.
 /// 
-/// This function reads from the data configuration file and replaces the `%%AppDataDirectory%%`
-/// placeholder with the actual application data directory path. The directory will be created
-/// if it doesn't already exist.
+/// This function reads from the data configuration file and replaces the `%%DataDirectory%%` and
+/// `%%AppDataDirectory%%` placeholders with the data directory (see get_data_directory) and the actual
+/// application data directory paths. The directory will be created if it doesn't already exist.
 /// 
 /// # Returns
 /// 
@@ -15,14 +37,12 @@ use config::{Config, ConfigError, File};
 /// 
 /// # Panics
 /// 
-/// Panics if the data configuration cannot be loaded, the default_index_directory setting
-/// is missing, or if there are filesystem errors creating the directory.
+/// Panics if the data configuration cannot be loaded, or if there are filesystem errors creating the directory.
 pub fn get_default_index_directory() -> Utf8PathBuf {
     let data_config = get_data_config().expect("Failed to load data config");
 
-    let folder = Utf8PathBuf::from(data_config.get_string("default_index_directory")
-        .expect("Failed to get default table directory from data config")
-        .replace("%%AppDataDirectory%%", get_app_folder().as_str()));
+    let folder = expand_data_path(data_config.get_string("default_index_directory")
+        .unwrap_or_else(|_| "%%DataDirectory%%/default/index".to_owned()));
     // Create if it doesnt exist
     if !fs::exists(&folder).expect("Error while determining if index directory exists") {
             fs::create_dir_all(&folder).expect("Failed to create default index directory");
@@ -33,9 +53,9 @@ pub fn get_default_index_directory() -> Utf8PathBuf {
 
 /// Gets the default directory path for storing file chunks.
 /// 
-/// This function reads from the data configuration file and replaces the `%%AppDataDirectory%%`
-/// placeholder with the actual application data directory path. The directory will be created
-/// if it doesn't already exist.
+/// This function reads from the data configuration file and replaces the `%%DataDirectory%%` and
+/// `%%AppDataDirectory%%` placeholders with the data directory (see get_data_directory) and the actual
+/// application data directory paths. The directory will be created if it doesn't already exist.
 /// 
 /// # Returns
 /// 
@@ -43,14 +63,12 @@ pub fn get_default_index_directory() -> Utf8PathBuf {
 /// 
 /// # Panics
 /// 
-/// Panics if the data configuration cannot be loaded, the default_chunk_directory setting
-/// is missing, or if there are filesystem errors creating the directory.
+/// Panics if the data configuration cannot be loaded, or if there are filesystem errors creating the directory.
 pub fn get_default_chunk_directory() -> Utf8PathBuf {
     let data_config = get_data_config().expect("Failed to load data config");
 
-    let folder = Utf8PathBuf::from(data_config.get_string("default_chunk_directory")
-        .expect("Failed to get default chunk directory from data config")
-        .replace("%%AppDataDirectory%%", get_app_folder().as_str()));
+    let folder = expand_data_path(data_config.get_string("default_chunk_directory")
+        .unwrap_or_else(|_| "%%DataDirectory%%/default/chunk".to_owned()));
     // create if doesn't exist
     if !fs::exists(&folder).expect("Error while determining if chunk directory exists") {
             fs::create_dir_all(&folder).expect("Failed to create default chunk directory");
@@ -61,9 +79,9 @@ pub fn get_default_chunk_directory() -> Utf8PathBuf {
 
 /// Gets the default directory path for storing file previews.
 /// 
-/// This function reads from the data configuration file and replaces the `%%AppDataDirectory%%`
-/// placeholder with the actual application data directory path. The directory will be created
-/// if it doesn't already exist.
+/// This function reads from the data configuration file and replaces the `%%DataDirectory%%` and
+/// `%%AppDataDirectory%%` placeholders with the data directory (see get_data_directory) and the actual
+/// application data directory paths. The directory will be created if it doesn't already exist.
 /// 
 /// # Returns
 /// 
@@ -71,14 +89,12 @@ pub fn get_default_chunk_directory() -> Utf8PathBuf {
 /// 
 /// # Panics
 /// 
-/// Panics if the data configuration cannot be loaded, the default_preview_directory setting
-/// is missing, or if there are filesystem errors creating the directory.
+/// Panics if the data configuration cannot be loaded, or if there are filesystem errors creating the directory.
 pub fn get_default_preview_directory() -> Utf8PathBuf {
     let data_config = get_data_config().expect("Failed to load data config");
 
-    let folder = Utf8PathBuf::from(data_config.get_string("default_preview_directory")
-        .expect("Failed to get default preview directory from data config")
-        .replace("%%AppDataDirectory%%", get_app_folder().as_str()));
+    let folder = expand_data_path(data_config.get_string("default_preview_directory")
+        .unwrap_or_else(|_| "%%DataDirectory%%/default/preview".to_owned()));
     // create if doesn't exist
     if !fs::exists(&folder).expect("Error while determining if preview directory exists") {
             fs::create_dir_all(&folder).expect("Failed to create default preview directory");
@@ -87,6 +103,400 @@ pub fn get_default_preview_directory() -> Utf8PathBuf {
     folder
 }
 
+/// Gets the directory path for storing the cursors of paged queries.
+/// 
+/// This function reads from the data configuration file and replaces the `%%DataDirectory%%` and
+/// `%%AppDataDirectory%%` placeholders with the data directory (see get_data_directory) and the actual
+/// application data directory paths. The directory will be created if it doesn't already exist. Data
+/// configuration files created before this setting existed keep cursors in the index directory. While the index
+/// is read-only (see is_index_read_only), cursors are kept in a temporary directory on this machine instead, as
+/// the cursor directory may be shared along with the index.
+/// 
+/// # Returns
+/// 
+/// A [`Utf8PathBuf`] representing the path to the cursor directory.
+/// 
+/// # Panics
+/// 
+/// Panics if the data configuration cannot be loaded, or if there are filesystem errors creating the directory.
+pub fn get_default_cursor_directory() -> Utf8PathBuf {
+    let data_config = get_data_config().expect("Failed to load data config");
+
+    let folder = match data_config.get_string("default_cursor_directory") {
+        _ if is_index_read_only() => get_local_temp_directory().join("cursor"),
+        Ok(folder) => expand_data_path(folder),
+        Err(_) => get_default_index_directory(),
+    };
+    // create if doesn't exist
+    if !fs::exists(&folder).expect("Error while determining if cursor directory exists") {
+            fs::create_dir_all(&folder).expect("Failed to create default cursor directory");
+    }
+
+    folder
+}
+
+/// Whether the index is only queried on this machine, never written to, set by read_only_index in the data
+/// configuration file. Used when the index directory is on a network share that several machines query while a
+/// single one of them indexes into it: index stores are opened read-only (see LanceDBStore::read_only), so they are
+/// never optimized or migrated from here, and query cursors are kept on this machine (see
+/// get_default_cursor_directory).
+/// 
+/// # Panics
+/// 
+/// Panics if the data configuration cannot be loaded.
+pub fn is_index_read_only() -> bool {
+    let data_config = get_data_config().expect("Failed to load data config");
+
+    data_config.get_bool("read_only_index").unwrap_or(false)
+}
+
+/// Whether the full text of chunks is compressed with zstd, set by compress_full_text in the data configuration
+/// file. Only applies to tables created, or given a full text column, while it is set. Data configuration files
+/// created before this setting existed compress the full text.
+/// 
+/// # Panics
+/// 
+/// Panics if the data configuration cannot be loaded.
+pub fn is_full_text_compressed() -> bool {
+    let data_config = get_data_config().expect("Failed to load data config");
+
+    data_config.get_bool("compress_full_text").unwrap_or(true)
+}
+
+/// Whether chunks are keyed by the id of their original file rather than its path, set by key_chunks_by_file_id in
+/// the data configuration file, see index::ChunkKeyStrategy. Data configuration files created before this setting
+/// existed key chunks by file id.
+/// 
+/// # Panics
+/// 
+/// Panics if the data configuration cannot be loaded.
+pub fn is_keyed_by_file_id() -> bool {
+    let data_config = get_data_config().expect("Failed to load data config");
+
+    data_config.get_bool("key_chunks_by_file_id").unwrap_or(true)
+}
+
+/// Gets the directory path for storing index snapshots, see files::snapshot.
+/// 
+/// This function reads from the data configuration file and replaces the `%%DataDirectory%%` and
+/// `%%AppDataDirectory%%` placeholders with the data directory (see get_data_directory) and the actual
+/// application data directory paths. The directory will be created if it doesn't already exist. Data
+/// configuration files created before this setting existed fall back to the default location in the data
+/// directory. Snapshots share chunkfiles with the chunk directory by hard link, so the two should be kept on the
+/// same volume.
+/// 
+/// # Returns
+/// 
+/// A [`Utf8PathBuf`] representing the path to the snapshot directory.
+/// 
+/// # Panics
+/// 
+/// Panics if the data configuration cannot be loaded, or if there are filesystem errors creating the directory.
+pub fn get_default_snapshot_directory() -> Utf8PathBuf {
+    let data_config = get_data_config().expect("Failed to load data config");
+
+    let folder = expand_data_path(data_config.get_string("default_snapshot_directory")
+        .unwrap_or_else(|_| "%%DataDirectory%%/default/snapshot".to_owned()));
+    // create if doesn't exist
+    if !fs::exists(&folder).expect("Error while determining if snapshot directory exists") {
+            fs::create_dir_all(&folder).expect("Failed to create default snapshot directory");
+    }
+
+    folder
+}
+
+/// Gets the file path for the history of opened files, used to boost results in frequently used directories.
+/// 
+/// This function reads from the data configuration file and replaces the `%%DataDirectory%%` and
+/// `%%AppDataDirectory%%` placeholders with the data directory (see get_data_directory) and the actual
+/// application data directory paths. Data configuration files created before this setting existed fall back
+/// to the default location in the data directory.
+/// 
+/// # Returns
+/// 
+/// A [`Utf8PathBuf`] representing the path to the open history file.
+/// 
+/// # Panics
+/// 
+/// Panics if the data configuration cannot be loaded.
+pub fn get_open_history_file_path() -> Utf8PathBuf {
+    let data_config = get_data_config().expect("Failed to load data config");
+
+    expand_data_path(data_config.get_string("open_history_file")
+        .unwrap_or_else(|_| "%%DataDirectory%%/default/open_history.json".to_owned()))
+}
+
+/// Gets the file path for the recorded click feedback, which tracks the files opened from the results
+/// of each query.
+/// 
+/// This function reads from the data configuration file and replaces the `%%DataDirectory%%` and
+/// `%%AppDataDirectory%%` placeholders with the data directory (see get_data_directory) and the actual
+/// application data directory paths. Data configuration files created before this setting existed fall back
+/// to the default location in the data directory.
+/// 
+/// # Returns
+/// 
+/// A [`Utf8PathBuf`] representing the path to the click feedback file.
+/// 
+/// # Panics
+/// 
+/// Panics if the data configuration cannot be loaded.
+pub fn get_feedback_file_path() -> Utf8PathBuf {
+    let data_config = get_data_config().expect("Failed to load data config");
+
+    expand_data_path(data_config.get_string("feedback_file")
+        .unwrap_or_else(|_| "%%DataDirectory%%/default/feedback.json".to_owned()))
+}
+
+/// Gets the file path for the cache of generated file summaries.
+/// 
+/// This function reads from the data configuration file and replaces the `%%DataDirectory%%` and
+/// `%%AppDataDirectory%%` placeholders with the data directory (see get_data_directory) and the actual
+/// application data directory paths. Data configuration files created before this setting existed fall back
+/// to the default location in the data directory.
+/// 
+/// # Returns
+/// 
+/// A [`Utf8PathBuf`] representing the path to the summary cache file.
+/// 
+/// # Panics
+/// 
+/// Panics if the data configuration cannot be loaded.
+pub fn get_summary_cache_file_path() -> Utf8PathBuf {
+    let data_config = get_data_config().expect("Failed to load data config");
+
+    expand_data_path(data_config.get_string("summary_cache_file")
+        .unwrap_or_else(|_| "%%DataDirectory%%/default/summaries.json".to_owned()))
+}
+
+/// Gets the file path for the tags the user has manually added to files.
+/// 
+/// This function reads from the data configuration file and replaces the `%%DataDirectory%%` and
+/// `%%AppDataDirectory%%` placeholders with the data directory (see get_data_directory) and the actual
+/// application data directory paths. Data configuration files created before this setting existed fall back
+/// to the default location in the data directory.
+/// 
+/// # Returns
+/// 
+/// A [`Utf8PathBuf`] representing the path to the user tags file.
+/// 
+/// # Panics
+/// 
+/// Panics if the data configuration cannot be loaded.
+pub fn get_user_tags_file_path() -> Utf8PathBuf {
+    let data_config = get_data_config().expect("Failed to load data config");
+
+    expand_data_path(data_config.get_string("user_tags_file")
+        .unwrap_or_else(|_| "%%DataDirectory%%/default/user_tags.json".to_owned()))
+}
+
+/// Gets the file path for the files and directories the user has excluded from the index.
+/// 
+/// This function reads from the data configuration file and replaces the `%%DataDirectory%%` and
+/// `%%AppDataDirectory%%` placeholders with the data directory (see get_data_directory) and the actual
+/// application data directory paths. Data configuration files created before this setting existed fall back
+/// to the default location in the data directory.
+/// 
+/// # Returns
+/// 
+/// A [`Utf8PathBuf`] representing the path to the index exclusions file.
+/// 
+/// # Panics
+/// 
+/// Panics if the data configuration cannot be loaded.
+pub fn get_index_exclusions_file_path() -> Utf8PathBuf {
+    let data_config = get_data_config().expect("Failed to load data config");
+
+    expand_data_path(data_config.get_string("index_exclusions_file")
+        .unwrap_or_else(|_| "%%DataDirectory%%/default/index_exclusions.json".to_owned()))
+}
+
+/// Gets the file path for whether privacy mode is on, which suspends indexing and history recording.
+/// 
+/// This function reads from the data configuration file and replaces the `%%DataDirectory%%` and
+/// `%%AppDataDirectory%%` placeholders with the data directory (see get_data_directory) and the actual
+/// application data directory paths. Data configuration files created before this setting existed fall back
+/// to the default location in the data directory.
+/// 
+/// # Returns
+/// 
+/// A [`Utf8PathBuf`] representing the path to the privacy mode file.
+/// 
+/// # Panics
+/// 
+/// Panics if the data configuration cannot be loaded.
+pub fn get_privacy_mode_file_path() -> Utf8PathBuf {
+    let data_config = get_data_config().expect("Failed to load data config");
+
+    expand_data_path(data_config.get_string("privacy_mode_file")
+        .unwrap_or_else(|_| "%%DataDirectory%%/default/privacy_mode.json".to_owned()))
+}
+
+/// Gets the file path for the volumes (drives and network shares) indexed files have been found on, which is used to
+/// tell files on a disconnected volume apart from deleted files.
+/// 
+/// This function reads from the data configuration file and replaces the `%%DataDirectory%%` and
+/// `%%AppDataDirectory%%` placeholders with the data directory (see get_data_directory) and the actual
+/// application data directory paths. Data configuration files created before this setting existed fall back
+/// to the default location in the data directory.
+/// 
+/// # Returns
+/// 
+/// A [`Utf8PathBuf`] representing the path to the indexed volumes file.
+/// 
+/// # Panics
+/// 
+/// Panics if the data configuration cannot be loaded.
+pub fn get_indexed_volumes_file_path() -> Utf8PathBuf {
+    let data_config = get_data_config().expect("Failed to load data config");
+
+    expand_data_path(data_config.get_string("indexed_volumes_file")
+        .unwrap_or_else(|_| "%%DataDirectory%%/default/indexed_volumes.json".to_owned()))
+}
+
+/// Gets the file path for the original paths of files whose paths are not valid UTF-8, which Fetch refers to
+/// by an escaped display form.
+/// 
+/// This function reads from the data configuration file and replaces the `%%DataDirectory%%` and
+/// `%%AppDataDirectory%%` placeholders with the data directory (see get_data_directory) and the actual
+/// application data directory paths. Data configuration files created before this setting existed fall back
+/// to the default location in the data directory.
+/// 
+/// # Returns
+/// 
+/// A [`Utf8PathBuf`] representing the path to the raw paths file.
+/// 
+/// # Panics
+/// 
+/// Panics if the data configuration cannot be loaded.
+pub fn get_raw_paths_file_path() -> Utf8PathBuf {
+    let data_config = get_data_config().expect("Failed to load data config");
+
+    expand_data_path(data_config.get_string("raw_paths_file")
+        .unwrap_or_else(|_| "%%DataDirectory%%/default/raw_paths.json".to_owned()))
+}
+
+/// Gets the file path for the files queued to be indexed by the file daemon, which is where files go when they
+/// cannot be indexed right away because another process is writing to the index.
+/// 
+/// This function reads from the data configuration file and replaces the `%%DataDirectory%%` and
+/// `%%AppDataDirectory%%` placeholders with the data directory (see get_data_directory) and the actual
+/// application data directory paths. Data configuration files created before this setting existed fall back
+/// to the default location in the data directory.
+/// 
+/// # Returns
+/// 
+/// A [`Utf8PathBuf`] representing the path to the index queue file.
+/// 
+/// # Panics
+/// 
+/// Panics if the data configuration cannot be loaded.
+pub fn get_index_queue_file_path() -> Utf8PathBuf {
+    let data_config = get_data_config().expect("Failed to load data config");
+
+    expand_data_path(data_config.get_string("index_queue_file")
+        .unwrap_or_else(|_| "%%DataDirectory%%/default/index_queue.json".to_owned()))
+}
+
+/// Gets the file path for the links between notes in markdown vaults, see NoteLinks.
+/// 
+/// This function reads from the data configuration file and replaces the `%%DataDirectory%%` and
+/// `%%AppDataDirectory%%` placeholders with the data directory (see get_data_directory) and the actual
+/// application data directory paths. Data configuration files created before this setting existed fall back
+/// to the default location in the data directory.
+/// 
+/// # Returns
+/// 
+/// A [`Utf8PathBuf`] representing the path to the note links file.
+/// 
+/// # Panics
+/// 
+/// Panics if the data configuration cannot be loaded.
+pub fn get_note_links_file_path() -> Utf8PathBuf {
+    let data_config = get_data_config().expect("Failed to load data config");
+
+    expand_data_path(data_config.get_string("note_links_file")
+        .unwrap_or_else(|_| "%%DataDirectory%%/default/note_links.json".to_owned()))
+}
+
+/// Gets the file path for the score ranges calibrated by `fetch eval --calibrate`, see ScoreCalibration.
+/// 
+/// This function reads from the data configuration file and replaces the `%%DataDirectory%%` and
+/// `%%AppDataDirectory%%` placeholders with the data directory (see get_data_directory) and the actual
+/// application data directory paths. Data configuration files created before this setting existed fall back
+/// to the default location in the data directory.
+/// 
+/// # Returns
+/// 
+/// A [`Utf8PathBuf`] representing the path to the score calibration file.
+/// 
+/// # Panics
+/// 
+/// Panics if the data configuration cannot be loaded.
+pub fn get_score_calibration_file_path() -> Utf8PathBuf {
+    let data_config = get_data_config().expect("Failed to load data config");
+
+    expand_data_path(data_config.get_string("score_calibration_file")
+        .unwrap_or_else(|_| "%%DataDirectory%%/default/score_calibration.json".to_owned()))
+}
+
+/// A directory or file Fetch keeps data in, see get_data_paths
+#[derive(Debug, Clone)]
+pub struct DataPath {
+    /// Setting of the path in the data configuration file, eg. default_index_directory
+    pub setting: &'static str,
+    pub path: Utf8PathBuf,
+}
+
+/// Gets every directory and file Fetch keeps data in, as set in the data configuration file. These are what
+/// `fetch migrate-data` moves to a new data directory.
+pub fn get_data_paths() -> Vec<DataPath> {
+    [
+        ("default_index_directory", get_default_index_directory()),
+        ("default_chunk_directory", get_default_chunk_directory()),
+        ("default_preview_directory", get_default_preview_directory()),
+        ("default_cursor_directory", get_default_cursor_directory()),
+        ("default_snapshot_directory", get_default_snapshot_directory()),
+        ("open_history_file", get_open_history_file_path()),
+        ("feedback_file", get_feedback_file_path()),
+        ("summary_cache_file", get_summary_cache_file_path()),
+        ("user_tags_file", get_user_tags_file_path()),
+        ("index_exclusions_file", get_index_exclusions_file_path()),
+        ("privacy_mode_file", get_privacy_mode_file_path()),
+        ("indexed_volumes_file", get_indexed_volumes_file_path()),
+        ("raw_paths_file", get_raw_paths_file_path()),
+        ("index_queue_file", get_index_queue_file_path()),
+        ("note_links_file", get_note_links_file_path()),
+        ("score_calibration_file", get_score_calibration_file_path()),
+    ].into_iter().map(|(setting, path)| DataPath { setting, path }).collect()
+}
+
+/// Sets the settings in the data configuration file, keeping its other settings and comments as they are. The
+/// file is written next to the old one and then renamed over it, so it is never left half written.
+pub fn update_data_config(settings: &[(&str, String)]) -> Result<(), std::io::Error> {
+    let config_file_path = get_app_folder().join("data.toml");
+    // Make sure the defaults are written first if there is no data.toml yet
+    get_data_config().map_err(std::io::Error::other)?;
+    let contents = fs::read_to_string(&config_file_path)?;
+
+    let mut remaining: Vec<&(&str, String)> = settings.iter().collect();
+    let mut lines: Vec<String> = contents.lines().map(|line| {
+        let key = line.split_once('=').map(|(key, _)| key.trim());
+        match remaining.iter().position(|(setting, _)| Some(*setting) == key) {
+            Some(index) => {
+                let (setting, value) = remaining.remove(index);
+                format!("{} = {}", setting, toml_string(value))
+            },
+            None => line.to_owned(),
+        }
+    }).collect();
+    lines.extend(remaining.into_iter().map(|(setting, value)| format!("{} = {}", setting, toml_string(value))));
+
+    let temp_file_path = config_file_path.with_extension("toml.tmp");
+    fs::write(&temp_file_path, lines.join("\n") + "\n")?;
+    fs::rename(&temp_file_path, &config_file_path)
+}
+
 /// Gets the file path for the configuration file defining the configuration settings 
 /// for the daemon process that watches for changes in the filesystem.
 /// 
@@ -109,7 +519,98 @@ pub fn get_watchlist_file_path() -> Utf8PathBuf {
         .replace("%%AppDataDirectory%%", get_app_folder().as_str()))
 }
 
-fn get_daemon_config() -> Result<Config, ConfigError> {
+/// Gets the file path the daemon writes the port it answers queries on to, so the CLI can query it instead of
+/// opening the index itself.
+/// 
+/// This function reads from the daemon configuration file and replaces the `%%AppDataDirectory%%`
+/// placeholder with the actual application data directory path. Daemon configuration files created
+/// before this setting existed fall back to the default location in the application data directory.
+/// 
+/// # Returns
+/// 
+/// A [`Utf8PathBuf`] representing the path to the daemon port file.
+/// 
+/// # Panics
+/// 
+/// Panics if the daemon configuration cannot be loaded.
+pub fn get_daemon_port_file_path() -> Utf8PathBuf {
+    let daemon_config = get_daemon_config().expect("Failed to load daemon config");
+
+    Utf8PathBuf::from(daemon_config.get_string("port_file")
+        .unwrap_or_else(|_| "%%AppDataDirectory%%/daemon/port".to_owned())
+        .replace("%%AppDataDirectory%%", get_app_folder().as_str()))
+}
+
+/// Gets the telemetry configuration, which defines how logs and tracing spans are emitted.
+/// 
+/// The telemetry.toml file in the application data directory will be created with default values
+/// if it doesn't already exist.
+pub(crate) fn get_telemetry_config() -> Result<Config, ConfigError> {
+    let config_file_path = get_app_folder().join("telemetry.toml");
+    if !fs::exists(&config_file_path).expect("Error while checking if telemetry config file exists") {
+        // If the telemetry.toml file does not exist, create it with default values
+        fs::write(&config_file_path, DEFAULT_TELEMETRY_CONFIG_BYTES).expect("Failed to create default telemetry.toml");
+    }
+
+    Config::builder()
+        .add_source(File::with_name(config_file_path.as_str()))
+        .build()
+}
+
+/// Gets the ranking configuration, which defines how chunk scores are weighted when they are aggregated
+/// into file scores.
+/// 
+/// The ranking.toml file in the application data directory will be created with default values
+/// if it doesn't already exist.
+pub(crate) fn get_ranking_config() -> Result<Config, ConfigError> {
+    let config_file_path = get_app_folder().join("ranking.toml");
+    if !fs::exists(&config_file_path).expect("Error while checking if ranking config file exists") {
+        // If the ranking.toml file does not exist, create it with default values
+        fs::write(&config_file_path, DEFAULT_RANKING_CONFIG_BYTES).expect("Failed to create default ranking.toml");
+    }
+
+    Config::builder()
+        .add_source(File::with_name(config_file_path.as_str()))
+        .build()
+}
+
+/// Gets the query configuration, which defines how query terms are preprocessed before they are embedded.
+/// 
+/// The query.toml file in the application data directory will be created with default values
+/// if it doesn't already exist.
+pub(crate) fn get_query_config() -> Result<Config, ConfigError> {
+    let config_file_path = get_app_folder().join("query.toml");
+    if !fs::exists(&config_file_path).expect("Error while checking if query config file exists") {
+        // If the query.toml file does not exist, create it with default values
+        fs::write(&config_file_path, DEFAULT_QUERY_CONFIG_BYTES).expect("Failed to create default query.toml");
+    }
+
+    Config::builder()
+        .add_source(File::with_name(config_file_path.as_str()))
+        .build()
+}
+
+/// Gets the providers configuration, which defines which index providers are used and in which order.
+/// 
+/// The providers.toml file in the application data directory will be created with default values
+/// if it doesn't already exist.
+pub(crate) fn get_providers_config() -> Result<Config, ConfigError> {
+    let config_file_path = get_app_folder().join("providers.toml");
+    if !fs::exists(&config_file_path).expect("Error while checking if providers config file exists") {
+        // If the providers.toml file does not exist, create it with default values
+        fs::write(&config_file_path, DEFAULT_PROVIDERS_CONFIG_BYTES).expect("Failed to create default providers.toml");
+    }
+
+    Config::builder()
+        .add_source(File::with_name(config_file_path.as_str()))
+        .build()
+}
+
+/// Gets the daemon configuration, which defines which folders are watched and how files are found in them.
+/// 
+/// The daemon.toml file in the application data directory will be created with default values
+/// if it doesn't already exist.
+pub(crate) fn get_daemon_config() -> Result<Config, ConfigError> {
     let config_file_path = get_app_folder().join("daemon.toml");
     if !fs::exists(&config_file_path).expect("Error while checking if data config file exists") {
         // If the daemon.toml file does not exist, create it with default values
@@ -133,7 +634,8 @@ fn get_data_config() -> Result<Config, ConfigError> {
         .build()
 }
 
-fn get_app_folder() -> &'static Utf8Path {
+/// The application data directory, holding the settings files, created if it does not exist yet
+pub(crate) fn get_app_folder() -> &'static Utf8Path {
     let folder: &'static Utf8PathBuf = &APP_FOLDER;
     if !fs::exists(folder).expect("Error while determining if app data directory exists") {
             fs::create_dir_all(folder).expect("Failed to create local data directory");
@@ -142,6 +644,26 @@ fn get_app_folder() -> &'static Utf8Path {
 }
 
 // Private constants and functions
+
+/// Replaces the placeholders in a path from the data configuration file
+fn expand_data_path(path: String) -> Utf8PathBuf {
+    Utf8PathBuf::from(path
+        .replace("%%DataDirectory%%", get_data_directory().as_str())
+        .replace("%%AppDataDirectory%%", get_app_folder().as_str()))
+}
+
+/// Directory for data that is only kept while Fetch runs on this machine, under the temporary directory of the OS
+fn get_local_temp_directory() -> Utf8PathBuf {
+    Utf8PathBuf::from_path_buf(std::env::temp_dir())
+        .expect("Temporary directory is not a valid UTF-8 path")
+        .join("fetch")
+}
+
+/// The value as a TOML basic string
+fn toml_string(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
 #[cfg(target_family = "unix")]
 const DEFAULT_DAEMON_CONFIG_BYTES: &[u8] = include_bytes!("../artifacts/defaults/daemon.toml");
 #[cfg(target_family = "windows")]
@@ -150,6 +672,10 @@ const DEFAULT_DAEMON_CONFIG_BYTES: &[u8] = include_bytes!("../artifacts/defaults
 const DEFAULT_DATA_CONFIG_BYTES: &[u8] = include_bytes!("../artifacts/defaults/data.toml");
 #[cfg(target_family = "windows")]
 const DEFAULT_DATA_CONFIG_BYTES: &[u8] = include_bytes!("../artifacts/defaults/windows/data.toml");
+const DEFAULT_QUERY_CONFIG_BYTES: &[u8] = include_bytes!("../artifacts/defaults/query.toml");
+const DEFAULT_PROVIDERS_CONFIG_BYTES: &[u8] = include_bytes!("../artifacts/defaults/providers.toml");
+const DEFAULT_RANKING_CONFIG_BYTES: &[u8] = include_bytes!("../artifacts/defaults/ranking.toml");
+const DEFAULT_TELEMETRY_CONFIG_BYTES: &[u8] = include_bytes!("../artifacts/defaults/telemetry.toml");
 
 static APP_FOLDER: LazyLock<Utf8PathBuf> = LazyLock::new(|| Utf8PathBuf::from_path_buf(dirs::data_local_dir()
             .expect("Failed to get local data directory"))
@@ -0,0 +1,284 @@
+use std::{collections::HashSet, sync::Arc, time::Duration};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use notify::RecursiveMode;
+use notify_debouncer_full::{DebounceEventResult, Debouncer, RecommendedCache, new_debouncer};
+use tokio::{sync::mpsc, task::JoinHandle};
+use tracing::{debug, error, info, warn};
+
+use crate::{app_config, files::{FileIndexer, FileQueryer, index::{FileIndexingError, FileIndexingResultType, IndexFiles}, journal::IndexJournal, links::{LinkResolver, ResolvedPath, SymlinkPolicy}, lock::{IndexLock, IndexLockError}, os_path::{from_os_path, to_os_path}, pagination::QueryCursor, query::{FileQueryingError, OsSearchFallback, QueryFiles, QueryPlanner, QueryPreprocessor, QueryResult, RankingBoosts, ScoreWeights}}, index::provider::registry::{ProviderRegistry, ProviderRegistryError}, store::lancedb::{LanceDBError, LanceDBStore}};
+
+/// Errors that can occur while using the Fetch engine
+#[derive(thiserror::Error, Debug)]
+pub enum FetchError {
+    #[error("Could not open the index providers")]
+    Providers(#[from] ProviderRegistryError),
+    #[error("Could not open store {table}")]
+    Store { table: &'static str, #[source] source: LanceDBError },
+    #[error("Could not lock the index")]
+    Lock(#[from] IndexLockError),
+    #[error("Error while querying")]
+    Query(#[from] FileQueryingError),
+    #[error("Could not watch {path}")]
+    Watch { path: Utf8PathBuf, #[source] source: notify::Error },
+    #[error("The index was opened read-only, files cannot be indexed")]
+    ReadOnly,
+}
+
+/// Where the engine keeps its index and how it handles files, see Fetch::open
+#[derive(Debug, Clone)]
+pub struct FetchConfig {
+    /// Directory the index is stored in, defaults to the index directory of the Fetch app, so the engine shares its
+    /// index with the app and the CLI
+    pub data_dir: Utf8PathBuf,
+    /// Directory the cursors of paged queries are stored in, defaults to the data directory, or to the cursor
+    /// directory of the Fetch app if the index is opened read-only, as the data directory may be shared
+    pub cursor_dir: Option<Utf8PathBuf>,
+    /// Whether the index is only queried, eg. an index on a network share that another machine indexes into. Stores
+    /// are opened read-only (see LanceDBStore::read_only), and indexing and watching fail with FetchError::ReadOnly.
+    /// Defaults to the read_only_index setting, see app_config::is_index_read_only.
+    pub read_only: bool,
+    /// Describes the process to others that find the index locked while it is indexing, eg. "my app"
+    pub holder: String,
+    /// How symlinks found while indexing or watching are handled, defaults to the symlink policy in the settings
+    pub symlink_policy: SymlinkPolicy,
+}
+
+impl Default for FetchConfig {
+    fn default() -> Self {
+        FetchConfig {
+            data_dir: app_config::get_default_index_directory(),
+            cursor_dir: None,
+            read_only: app_config::is_index_read_only(),
+            holder: "Fetch engine".to_owned(),
+            symlink_policy: SymlinkPolicy::from_settings().unwrap_or_else(|e| {
+                warn!("Could not load symlink policy, following symlinks: {:?}", e);
+                SymlinkPolicy::default()
+            }),
+        }
+    }
+}
+
+/// Outcome of indexing files with Fetch::index
+#[derive(Debug, Default)]
+pub struct IndexReport {
+    /// Files indexed by every provider that handles them, or by some of them
+    pub indexed: Vec<Utf8PathBuf>,
+    /// Files that were not indexed, eg. because they are excluded or not supported, with the reason why
+    pub skipped: Vec<(Utf8PathBuf, String)>,
+    /// Files every provider failed to index
+    pub failed: Vec<(Utf8PathBuf, FileIndexingError)>,
+}
+
+/// High level entry point to the Fetch engine, for embedding it in other applications. Opens the stores and index
+/// providers of an index and wires up the indexer and queryer, so files can be indexed, queried and watched without
+/// constructing them by hand.
+///
+/// init_resources must be called once before opening the engine so the models can be found.
+pub struct Fetch {
+    config: FetchConfig,
+    indexer: FileIndexer,
+    queryer: FileQueryer<LanceDBStore<QueryCursor>>,
+}
+
+impl Fetch {
+    /// Opens the index in the data directory, creating it if it does not exist yet, unless it is opened read-only. Query settings that cannot be
+    /// loaded are logged, and their defaults are used instead.
+    pub async fn open(config: FetchConfig) -> Result<Fetch, FetchError> {
+        let (registry, journal_store) = match config.read_only {
+            true => (ProviderRegistry::open_read_only(&config.data_dir).await?,
+                LanceDBStore::read_only(config.data_dir.as_str(), "index_journal".to_owned()).await),
+            false => (ProviderRegistry::open(&config.data_dir).await?,
+                LanceDBStore::local_with_filters(config.data_dir.as_str(), "index_journal".to_owned()).await),
+        };
+        let journal_store = journal_store.map_err(|e| FetchError::Store { table: "index_journal", source: e })?;
+        let cursor_dir = match &config.cursor_dir {
+            Some(cursor_dir) => cursor_dir.clone(),
+            None if config.read_only => app_config::get_default_cursor_directory(),
+            None => config.data_dir.clone(),
+        };
+        let cursor_store = LanceDBStore::<QueryCursor>::local(cursor_dir.as_str(), "cursor".to_owned()).await
+            .map_err(|e| FetchError::Store { table: "cursor", source: e })?;
+
+        let indexer = FileIndexer::with(registry.providers())
+            .with_journal(Arc::new(IndexJournal::using(journal_store)))
+            .with_provider_budgets(registry.budgets());
+        let queryer = FileQueryer::with(registry.providers(), cursor_store)
+            .with_score_weights(ScoreWeights::from_settings().unwrap_or_else(|e| {
+                warn!("Could not load score weights from settings, weighting all results equally: {}", e);
+                ScoreWeights::default()
+            }))
+            .with_ranking_boosts(RankingBoosts::from_settings().unwrap_or_else(|e| {
+                warn!("Could not load ranking boosts from settings, ranking by semantic score only: {}", e);
+                RankingBoosts::default()
+            }))
+            .with_query_preprocessor(QueryPreprocessor::from_settings().unwrap_or_else(|e| {
+                warn!("Could not load query settings, using query terms as is: {}", e);
+                QueryPreprocessor::default()
+            }))
+            .with_os_search_fallback(OsSearchFallback::from_settings().unwrap_or_else(|e| {
+                warn!("Could not load operating system search settings, not using it: {}", e);
+                OsSearchFallback::default()
+            }))
+            .with_query_planner(QueryPlanner::from_settings().unwrap_or_else(|e| {
+                warn!("Could not load query planner settings, using the defaults: {}", e);
+                QueryPlanner::default()
+            }));
+
+        Ok(Fetch { config, indexer, queryer })
+    }
+
+    /// Indexes the files given and every file under the directories given. Waits for any other process writing to
+    /// the index, eg. the Fetch app, to finish first. Files that fail to index do not stop the others from being
+    /// indexed, and are listed in the report instead.
+    pub async fn index(&self, paths: &[Utf8PathBuf]) -> Result<IndexReport, FetchError> {
+        if self.config.read_only {
+            return Err(FetchError::ReadOnly);
+        }
+        let files = explore_paths(paths, self.config.symlink_policy);
+        let _index_lock = IndexLock::acquire(&self.config.data_dir, &self.config.holder, None).await?;
+
+        let mut report = IndexReport::default();
+        for file in files {
+            match self.indexer.index(&file, None).await.map(|result| result.r#type) {
+                Ok(FileIndexingResultType::Skipped { reason }) => report.skipped.push((file, reason)),
+                Ok(_) => report.indexed.push(file),
+                Err(e) => report.failed.push((file, e)),
+            }
+        }
+        Ok(report)
+    }
+
+    /// Queries the index, returning the best matching files in order, best first. Only the first page of results
+    /// is returned, see queryer to page through more of them.
+    pub async fn query(&self, query: &str) -> Result<Vec<QueryResult>, FetchError> {
+        let mut results = self.queryer.query(query, None).await?.changed_results;
+        results.sort_by_key(|result| result.rank);
+        Ok(results)
+    }
+
+    /// Watches the directories given, indexing files as they are created or changed and clearing them from the
+    /// index as they are removed, until the returned watcher is dropped. Must be called from within a tokio runtime.
+    pub fn watch(&self, roots: &[Utf8PathBuf]) -> Result<FetchWatcher, FetchError> {
+        if self.config.read_only {
+            return Err(FetchError::ReadOnly);
+        }
+        let (tx, rx) = mpsc::unbounded_channel();
+        let mut debouncer = new_debouncer(WATCH_DEBOUNCE, None, move |result: DebounceEventResult| {
+            // The receiver is only gone once the watcher is dropped
+            let _ = tx.send(result);
+        }).map_err(|e| FetchError::Watch { path: Utf8PathBuf::new(), source: e })?;
+        for root in roots {
+            debouncer.watch(to_os_path(root), RecursiveMode::Recursive)
+                .map_err(|e| FetchError::Watch { path: root.clone(), source: e })?;
+        }
+
+        let task = tokio::spawn(watch_main(rx, self.indexer.clone(), self.config.clone()));
+        Ok(FetchWatcher { _debouncer: debouncer, task })
+    }
+
+    /// The indexer used by the engine, eg. to clear or tag files
+    pub fn indexer(&self) -> &FileIndexer {
+        &self.indexer
+    }
+
+    /// The queryer used by the engine, eg. to page through results with a cursor or refine queries as they are typed
+    pub fn queryer(&self) -> &FileQueryer<LanceDBStore<QueryCursor>> {
+        &self.queryer
+    }
+}
+
+/// Keeps watching directories for changes while it is alive, see Fetch::watch
+pub struct FetchWatcher {
+    _debouncer: Debouncer<notify::RecommendedWatcher, RecommendedCache>,
+    task: JoinHandle<()>,
+}
+
+impl Drop for FetchWatcher {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+// Private functions and variables
+
+/// How long changes to a file are collected before it is indexed, so a file being written is indexed once
+const WATCH_DEBOUNCE: Duration = Duration::from_secs(2);
+
+/// Expands the paths given into the files found under them, following symlinks according to the symlink policy.
+/// Paths that do not exist are ignored.
+fn explore_paths(paths: &[Utf8PathBuf], symlink_policy: SymlinkPolicy) -> Vec<Utf8PathBuf> {
+    let mut link_resolver = LinkResolver::using(symlink_policy);
+    let mut files: HashSet<Utf8PathBuf> = HashSet::new();
+    let mut queue = paths.to_vec();
+    while let Some(path) = queue.pop() {
+        match link_resolver.resolve(&path) {
+            ResolvedPath::File(file) => {
+                files.insert(file);
+            },
+            ResolvedPath::Directory(directory) => match to_os_path(&directory).read_dir() {
+                // Paths that are not valid UTF-8 are escaped, see os_path::from_os_path
+                Ok(entries) => queue.extend(entries.flatten().map(|entry| from_os_path(&entry.path()))),
+                Err(e) => warn!("Could not read directory {}, ignoring: {:?}", directory, e),
+            },
+            ResolvedPath::Skip { reason } => debug!("{}, ignoring: {}", reason, path),
+        }
+    }
+    if let Err(e) = link_resolver.record_aliases() {
+        warn!("Could not record the names of symlinks as tags of their targets: {:?}", e);
+    }
+    files.into_iter().collect()
+}
+
+/// Indexes the files changed under the watched directories, and clears the files removed from them
+async fn watch_main(mut rx: mpsc::UnboundedReceiver<DebounceEventResult>, indexer: FileIndexer, config: FetchConfig) {
+    while let Some(result) = rx.recv().await {
+        let events = match result {
+            Ok(events) => events,
+            Err(errors) => {
+                warn!("Errors while watching for changes: {:?}", errors);
+                continue;
+            },
+        };
+        let changed: HashSet<Utf8PathBuf> = events.iter()
+            .filter(|event| !event.event.kind.is_access())
+            .flat_map(|event| event.event.paths.iter().map(|path| from_os_path(path)))
+            .collect();
+        if changed.is_empty() {
+            continue;
+        }
+
+        let _index_lock = match IndexLock::acquire(&config.data_dir, &config.holder, None).await {
+            Ok(index_lock) => index_lock,
+            Err(e) => {
+                error!("Could not lock index, dropping file changes: {:?}", e);
+                continue;
+            },
+        };
+        for path in changed {
+            handle_change(&indexer, &path, config.symlink_policy).await;
+        }
+    }
+}
+
+/// Indexes a changed file, or clears it from the index if it no longer exists
+async fn handle_change(indexer: &FileIndexer, path: &Utf8Path, symlink_policy: SymlinkPolicy) {
+    let os_path = to_os_path(path);
+    if !os_path.exists() && !os_path.is_symlink() {
+        match indexer.clear(path, None).await {
+            Ok(_) => info!("File cleared from index: {}", path),
+            Err(e) => error!("Error clearing file {}: {:?}", path, e),
+        }
+        return;
+    }
+
+    let mut link_resolver = LinkResolver::using(symlink_policy);
+    match link_resolver.resolve(path) {
+        ResolvedPath::File(file) => match indexer.index(&file, None).await {
+            Ok(_) => info!("File indexed: {}", file),
+            Err(e) => error!("Error indexing file {}: {:?}", file, e),
+        },
+        ResolvedPath::Directory(_) => debug!("Path {} is a directory, ignoring", path),
+        ResolvedPath::Skip { reason } => debug!("{}, ignoring: {}", reason, path),
+    }
+}
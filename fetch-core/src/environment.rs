@@ -1,23 +1,45 @@
-use std::sync::OnceLock;
+use std::{fs::File, io, sync::OnceLock, time::Duration};
 
 use camino::{Utf8Path, Utf8PathBuf};
-use log::{debug, error, info};
 use ort::execution_providers::*;
 use pdfium_render::prelude::Pdfium;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::{debug, error, info};
 
-use crate::index::embedding::{embeddinggemma, sessions::init_model_resource_directory, siglip2};
+use crate::index::embedding::{embeddinggemma, sessions::{PrioritySessionPool, init_model_resource_directory, set_keepalive}, siglip2};
 
-/// Initialize dynamic libraries and other dynamic resource paths.
-/// Must be called before init_indexing or init_querying
+/// Errors verifying the dynamic libraries in the resource directory against the digests compiled into Fetch
+#[derive(thiserror::Error, Debug)]
+pub enum LibraryVerificationError {
+    #[error("Library {path} does not match the digest of the library bundled with this build of Fetch (expected \
+        {expected}, found {actual}). It may be corrupted or have been replaced: reinstall Fetch, or when building from \
+        source, delete it from fetch-core/bundle/ and build again to download it again")]
+    Mismatch { path: Utf8PathBuf, expected: String, actual: String },
+    #[error("Library {path} was not bundled with this build of Fetch, so it cannot be verified. When building from \
+        source, make sure the build downloads the library or copies it from ONNX_BUILD_PATH or PDFIUM_BUILD_PATH")]
+    NotBundled { path: Utf8PathBuf },
+    #[error("Error reading {path} to verify the libraries")]
+    Io { path: Utf8PathBuf, #[source] source: io::Error },
+}
+
+/// Initialize dynamic libraries and other dynamic resource paths. With a resource directory, every library bundled
+/// in it is checked against the digests compiled into Fetch before any is loaded, and none is loaded if one does not
+/// match. Libraries loaded from the standard library paths, without a resource directory, are not checked.
+/// Must be called before warm_models or any use of the models
 pub fn init_resources(path: Option<&Utf8Path>) -> Result<(), anyhow::Error> {
     let default_path = Utf8PathBuf::default();
     let resource_path = path.unwrap_or(&default_path);
 
+    if let Some(lib_dir) = path {
+        info!("Verifying bundled libraries...");
+        verify_libraries(lib_dir, LOADED_LIBRARIES)?;
+    }
+
     #[cfg(feature = "pdf")]
     {
         info!("Initializing PDFium...");
-        PDFIUM_LIB_PATH.set(resource_path.to_owned())
-            .map_err(|_| anyhow::anyhow!("PDFium library path has already been set"))?;
+        init_pdfium(resource_path)?;
     }
 
     info!("Initializing ONNX Runtime...");
@@ -30,20 +52,82 @@ pub fn init_resources(path: Option<&Utf8Path>) -> Result<(), anyhow::Error> {
     Ok(())
 }
 
-// TODO: implement functionality to init for specific models
-pub fn init_indexing(_models: Vec<&str>) {
-    // do init for models
-    siglip2::init_indexing();
-    embeddinggemma::init();
+/// Models that can be warmed up ahead of use, see warm_models
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ModelId {
+    /// Text embedding model, used for indexing text and querying
+    EmbeddingGemma,
+    /// Image embedding model, used for indexing images
+    Siglip2Image,
+    /// Text embedding model matching Siglip2Image, used for querying
+    Siglip2Text,
+}
+
+impl ModelId {
+    /// Models used when indexing files
+    pub const INDEXING: [ModelId; 2] = [ModelId::Siglip2Image, ModelId::EmbeddingGemma];
+    /// Models used when querying
+    pub const QUERYING: [ModelId; 2] = [ModelId::Siglip2Text, ModelId::EmbeddingGemma];
+
+    /// Whether the model is loaded and ready to use without waiting
+    pub fn is_warm(&self) -> bool {
+        self.session_pool().is_warm()
+    }
+
+    fn session_pool(&self) -> &'static PrioritySessionPool {
+        match self {
+            ModelId::EmbeddingGemma => embeddinggemma::session_pool(),
+            ModelId::Siglip2Image => siglip2::image_session_pool(),
+            ModelId::Siglip2Text => siglip2::text_session_pool(),
+        }
+    }
+}
+
+/// Progress of warm_models, reported after each model is loaded
+#[derive(Debug, Clone, Serialize)]
+pub struct WarmupProgress {
+    pub model: ModelId,
+    pub warmed: usize,
+    pub total: usize,
+}
+
+/// Loads the models so their first use does not wait for them, reporting progress after each one. Models that are
+/// already loaded, or listed more than once, are only counted. This blocks until every model is loaded.
+///
+/// init_resources must be called before this function or all models will be initialized from a binary relative
+/// models/ path
+pub fn warm_models(models: &[ModelId], mut on_progress: impl FnMut(WarmupProgress)) {
+    let mut unique: Vec<ModelId> = Vec::with_capacity(models.len());
+    for model in models {
+        if !unique.contains(model) {
+            unique.push(*model);
+        }
+    }
+
+    let total = unique.len();
+    for (warmed, model) in unique.into_iter().enumerate() {
+        debug!("Warming up model: {:?}", model);
+        model.session_pool().warm();
+        on_progress(WarmupProgress { model, warmed: warmed + 1, total });
+    }
 }
-pub fn init_querying(_models: Vec<&str>) {
-    // do init for models
-    siglip2::init_querying();
-    embeddinggemma::init();
+
+/// Sets how long models may go unused before they are unloaded to reclaim memory. They are loaded again when next
+/// needed. With None, models stay loaded once they have been used.
+pub fn set_model_keepalive(keepalive: Option<Duration>) {
+    set_keepalive(keepalive);
 }
 
 // Private initialization functions
 
+/// Loads PDFium from the directory when first used, see get_pdfium
+#[cfg(feature = "pdf")]
+fn init_pdfium(pdfium_lib_path: &Utf8Path) -> Result<(), anyhow::Error> {
+    PDFIUM_LIB_PATH.set(pdfium_lib_path.to_owned())
+        .map_err(|_| anyhow::anyhow!("PDFium library path has already been set"))
+}
+
 /// Initialize ONNX Runtime with optional library path
 ///
 /// If `onnx_lib_path` is provided, ONNX Runtime will load its dynamic library
@@ -77,16 +161,7 @@ fn init_ort(onnx_lib_path: Option<&Utf8Path>) -> Result<(), anyhow::Error> {
 
     let result = if let Some(lib_dir) = onnx_lib_path {
         // Construct the full path to the ONNX Runtime library
-        #[cfg(windows)]
-        let lib_name = "onnxruntime.dll";
-
-        #[cfg(target_os = "macos")]
-        let lib_name = "libonnxruntime.dylib";
-
-        #[cfg(all(not(windows), not(target_os = "macos")))]
-        let lib_name = "libonnxruntime.so";
-
-        let lib_path = lib_dir.join(lib_name);
+        let lib_path = lib_dir.join(ONNX_LIB_NAME);
 
         // Use init_from to load from the specific path
         ort::init_from(&lib_path)
@@ -111,6 +186,81 @@ fn init_ort(onnx_lib_path: Option<&Utf8Path>) -> Result<(), anyhow::Error> {
     }
 }
 
+/// Checks every library the build bundled that is in the directory against its digest, and that the libraries
+/// that are loaded directly were bundled. Bundled libraries that are not in the directory, eg. the CUDA provider of
+/// ONNX Runtime when it was not installed, are skipped unless they are loaded directly.
+fn verify_libraries(lib_dir: &Utf8Path, loaded: &[&str]) -> Result<(), LibraryVerificationError> {
+    let digests = library_digests();
+    if let Some(file_name) = loaded.iter().find(|file_name| !digests.iter().any(|(_, bundled)| bundled == *file_name)) {
+        return Err(LibraryVerificationError::NotBundled { path: lib_dir.join(file_name) });
+    }
+
+    for (expected, file_name) in digests {
+        let path = lib_dir.join(file_name);
+        let actual = match sha256_digest(&path) {
+            Ok(actual) => actual,
+            Err(e) if e.kind() == io::ErrorKind::NotFound && !loaded.contains(&file_name) => {
+                debug!("Bundled library {} is not installed, skipping it", path);
+                continue;
+            },
+            Err(e) => return Err(LibraryVerificationError::Io { path, source: e }),
+        };
+        if actual != expected {
+            error!("Library {} does not match its digest, refusing to load it", path);
+            return Err(LibraryVerificationError::Mismatch { path, expected: expected.to_owned(), actual });
+        }
+        debug!("Verified library {}", path);
+    }
+
+    Ok(())
+}
+
+/// Digests and file names of the libraries the build bundled, see LIBRARY_DIGESTS
+fn library_digests() -> Vec<(&'static str, &'static str)> {
+    LIBRARY_DIGESTS.lines()
+        .filter_map(|line| line.split_once("  "))
+        .collect()
+}
+
+/// The SHA-256 digest of the file, in lowercase hex
+fn sha256_digest(path: &Utf8Path) -> Result<String, io::Error> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+// Private variables
+
+/// SHA-256 digests of the libraries the build placed in fetch-core/bundle/, in the format of sha256sum. Compiled in
+/// rather than read from the resource directory, so replacing a library along with a manifest next to it does not
+/// get it loaded.
+const LIBRARY_DIGESTS: &str = include_str!(concat!(env!("OUT_DIR"), "/libraries.sha256"));
+
+/// The ONNX Runtime library, loaded from the resource directory by init_ort
+#[cfg(windows)]
+const ONNX_LIB_NAME: &str = "onnxruntime.dll";
+#[cfg(target_os = "macos")]
+const ONNX_LIB_NAME: &str = "libonnxruntime.dylib";
+#[cfg(all(not(windows), not(target_os = "macos")))]
+const ONNX_LIB_NAME: &str = "libonnxruntime.so";
+
+/// The PDFium library, loaded from the resource directory by get_pdfium
+#[cfg(all(feature = "pdf", windows))]
+const PDFIUM_LIB_NAME: &str = "pdfium.dll";
+#[cfg(all(feature = "pdf", target_os = "macos"))]
+const PDFIUM_LIB_NAME: &str = "libpdfium.dylib";
+#[cfg(all(feature = "pdf", not(windows), not(target_os = "macos")))]
+const PDFIUM_LIB_NAME: &str = "libpdfium.so";
+
+/// Libraries loaded directly from the resource directory, which must have been bundled by the build
+const LOADED_LIBRARIES: &[&str] = &[
+    ONNX_LIB_NAME,
+    #[cfg(feature = "pdf")]
+    PDFIUM_LIB_NAME,
+    #[cfg(feature = "qnn")]
+    "QnnHtp.dll",
+];
+
 // crate-wide environment and utilities
 
 #[cfg(feature = "pdf")]
@@ -1,8 +1,9 @@
-use std::{error::Error, future::Future, sync::Arc};
+use std::{collections::HashMap, error::Error, future::Future, sync::Arc};
 
+use camino::{Utf8Path, Utf8PathBuf};
 use tokio::task::JoinSet;
 
-use crate::{files::pagination::QueryCursor, index::provider::{ChunkingIndexProvider, image::ImageIndexProvider}, store::{ClearByFilter, KeyedSequencedStore, lancedb::LanceDBStore}};
+use crate::{files::{journal::{IndexJournal, JournalEntry}, pagination::QueryCursor, query::{DEFAULT_MIN_SCORE, DEFAULT_NUM_CHUNKS, DEFAULT_STALE_SCORE_DECAY, MultiQuery, OsSearchFallback, QueryPageCache, QueryPlanner, QueryPreprocessor, RankingBoosts, ScoreWeights}}, index::{embedding::siglip2::Siglip2EmbeddedChunkFile, provider::{ChunkingIndexProvider, image::ImageIndexProvider, registry::{self, ProviderBudget}}}, store::{ClearByFilter, KeyedSequencedStore, lancedb::LanceDBStore}};
 
 /// Errors that can occur related to the file indexer object itself.
 #[derive(thiserror::Error, Debug)]
@@ -19,6 +20,9 @@ pub enum FileIndexError {
 pub struct FileIndexer
 {
     index_providers: Vec<Arc<dyn ChunkingIndexProvider>>,
+    journal: Option<Arc<IndexJournal<LanceDBStore<JournalEntry>>>>,
+    provider_limiters: HashMap<&'static str, Arc<index::ProviderLimiter>>,
+    chunk_directory: Option<Utf8PathBuf>,
 }
 
 impl FileIndexer
@@ -27,7 +31,7 @@ impl FileIndexer
     #[allow(dead_code)]
     async fn new() -> Result<FileIndexer, FileIndexError> {
         let basic_image = ImageIndexProvider::using(Arc::new(
-            LanceDBStore::local("./data_dir", "basic_image_index".to_owned()).await
+            LanceDBStore::<Siglip2EmbeddedChunkFile>::local("./data_dir", "basic_image_index".to_owned()).await
             .map_err(|e| FileIndexError::DependencyError {
                 dependency: "Lance Db Vector Store", 
                 source: Box::new(e)
@@ -38,7 +42,34 @@ impl FileIndexer
     }
 
     pub fn with(providers: Vec<Arc<dyn ChunkingIndexProvider>>) -> FileIndexer {
-        FileIndexer { index_providers: providers }
+        FileIndexer { index_providers: providers, journal: None, provider_limiters: HashMap::new(), chunk_directory: None }
+    }
+
+    /// Starts configuring a FileIndexer, with no providers
+    pub fn builder() -> FileIndexerBuilder {
+        FileIndexerBuilder::default()
+    }
+
+    /// Sets the journal every index, clear and skip decision is recorded into. By default decisions are not
+    /// recorded.
+    pub fn with_journal(mut self, journal: Arc<IndexJournal<LanceDBStore<JournalEntry>>>) -> FileIndexer {
+        self.journal = Some(journal);
+        self
+    }
+
+    /// Sets the limits on the files each provider indexes at once, by provider name (see
+    /// ProviderRegistry::budgets). Files wait for their turn with a provider without holding up the other providers
+    /// indexing them. By default providers are not limited.
+    pub fn with_provider_budgets(mut self, budgets: HashMap<&'static str, ProviderBudget>) -> FileIndexer {
+        self.provider_limiters = budgets.into_iter()
+            .map(|(name, budget)| (name, Arc::new(index::ProviderLimiter::new(budget))))
+            .collect();
+        self
+    }
+
+    /// Names of the providers that index the file at the path, see registry::route
+    pub fn providers_for(&self, path: &Utf8Path) -> Vec<&'static str> {
+        registry::route(&self.index_providers, path)
     }
 }
 
@@ -51,6 +82,16 @@ where
 {
     index_providers: Vec<Arc<dyn ChunkingIndexProvider>>,
     cursor_store: C,
+    score_weights: ScoreWeights,
+    ranking_boosts: RankingBoosts,
+    query_preprocessor: QueryPreprocessor,
+    os_search_fallback: OsSearchFallback,
+    multi_query: MultiQuery,
+    query_planner: QueryPlanner,
+    page_cache: Option<Arc<QueryPageCache>>,
+    min_score: f32,
+    default_num_chunks: u32,
+    stale_score_decay: f32,
 }
 
 impl<C> FileQueryer<C>
@@ -69,7 +110,7 @@ where
             })?;
 
         let basic_image = ImageIndexProvider::using(Arc::new(
-            LanceDBStore::local("./data_dir", "basic_image_index".to_owned()).await
+            LanceDBStore::<Siglip2EmbeddedChunkFile>::local("./data_dir", "basic_image_index".to_owned()).await
             .map_err(|e| FileIndexError::DependencyError {
                 dependency: "Lance Db Vector Store", 
                 source: Box::new(e)
@@ -80,7 +121,65 @@ where
     }
 
     pub fn with(providers: Vec<Arc<dyn ChunkingIndexProvider>>, cursor_store: C) -> FileQueryer<C> {
-        FileQueryer { index_providers: providers, cursor_store }
+        FileQueryer { index_providers: providers, cursor_store, score_weights: ScoreWeights::default(),
+            ranking_boosts: RankingBoosts::default(), query_preprocessor: QueryPreprocessor::default(),
+            os_search_fallback: OsSearchFallback::default(), multi_query: MultiQuery::default(),
+            query_planner: QueryPlanner::default(), page_cache: None, min_score: DEFAULT_MIN_SCORE,
+            default_num_chunks: DEFAULT_NUM_CHUNKS, stale_score_decay: DEFAULT_STALE_SCORE_DECAY }
+    }
+
+    /// Starts configuring a FileQueryer that keeps its cursors in the cursor store, with no providers
+    pub fn builder(cursor_store: C) -> FileQueryerBuilder<C> {
+        FileQueryerBuilder::using(cursor_store)
+    }
+
+    /// Sets the weights applied to chunk scores before they are aggregated into file scores. By default
+    /// every provider and chunk type is weighted equally.
+    pub fn with_score_weights(mut self, score_weights: ScoreWeights) -> FileQueryer<C> {
+        self.score_weights = score_weights;
+        self
+    }
+
+    /// Sets the recency and directory affinity boosts combined with the semantic score of each result. By
+    /// default no boosts are applied.
+    pub fn with_ranking_boosts(mut self, ranking_boosts: RankingBoosts) -> FileQueryer<C> {
+        self.ranking_boosts = ranking_boosts;
+        self
+    }
+
+    /// Sets the spelling correction and synonym expansion applied to query terms before they are embedded.
+    /// By default query terms are used as is.
+    pub fn with_query_preprocessor(mut self, query_preprocessor: QueryPreprocessor) -> FileQueryer<C> {
+        self.query_preprocessor = query_preprocessor;
+        self
+    }
+
+    /// Sets the fallback that also searches the operating system's search index for files whose names or
+    /// metadata match the query. By default the operating system's search index is not used.
+    pub fn with_os_search_fallback(mut self, os_search_fallback: OsSearchFallback) -> FileQueryer<C> {
+        self.os_search_fallback = os_search_fallback;
+        self
+    }
+
+    /// Sets how long queries are split into sub-queries, whose results are fused with those of the whole query. By
+    /// default queries of 12 words or more are split, see MultiQuery.
+    pub fn with_multi_query(mut self, multi_query: MultiQuery) -> FileQueryer<C> {
+        self.multi_query = multi_query;
+        self
+    }
+
+    /// Sets how the providers are queried together for each page of a query. By default providers share identical
+    /// table queries, see QueryPlanner.
+    pub fn with_query_planner(mut self, query_planner: QueryPlanner) -> FileQueryer<C> {
+        self.query_planner = query_planner;
+        self
+    }
+
+    /// Sets the cache the pages of chunks found by queries are kept in, so pages queried again, eg. when flipping
+    /// back to an earlier page, are not queried from the providers again. By default pages are not cached.
+    pub fn with_page_cache(mut self, page_cache: Arc<QueryPageCache>) -> FileQueryer<C> {
+        self.page_cache = Some(page_cache);
+        self
     }
 }
 
@@ -130,6 +229,33 @@ impl ChunkingIndexProviderConcurrent for Vec<Arc<dyn ChunkingIndexProvider>> {
     }
 }
 
+pub use builder::{FileIndexerBuilder, FileQueryerBuilder};
+
+pub mod affinity;
+pub mod batch;
+pub mod builder;
+pub mod collection;
+pub mod desktop;
+pub mod details;
+pub mod disk_space;
+pub mod dupes;
+pub mod exclusions;
+pub mod feedback;
+pub mod governor;
 pub mod index;
+pub mod journal;
+pub mod links;
+pub mod lock;
+pub mod notes;
+pub mod os_path;
 pub mod pagination;
-pub mod query;
\ No newline at end of file
+pub mod privacy;
+pub mod query;
+pub mod queue;
+pub mod schedule;
+pub mod snapshot;
+pub mod summary;
+pub mod tags;
+pub mod timeline;
+pub mod topics;
+pub mod volumes;
\ No newline at end of file
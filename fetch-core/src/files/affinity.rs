@@ -0,0 +1,64 @@
+use std::{collections::HashMap, fs};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use serde::{Deserialize, Serialize};
+
+use crate::app_config;
+
+/// Tracks how many times files have been opened from each directory, so that results located under the
+/// user's frequently used directories can be boosted.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DirectoryAffinity {
+    open_counts: HashMap<Utf8PathBuf, u32>,
+}
+
+impl DirectoryAffinity {
+    /// Loads the open history from the application data directory. Returns an empty history if nothing has
+    /// been recorded yet.
+    pub fn load() -> Result<DirectoryAffinity, anyhow::Error> {
+        let history_file = app_config::get_open_history_file_path();
+        if !fs::exists(&history_file)? {
+            return Ok(DirectoryAffinity::default());
+        }
+
+        let contents = fs::read_to_string(&history_file)?;
+        Ok(serde_json::from_str(&contents)?)
+    }
+
+    /// Saves the open history to the application data directory.
+    pub fn save(&self) -> Result<(), anyhow::Error> {
+        let history_file = app_config::get_open_history_file_path();
+        if let Some(parent) = history_file.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        fs::write(&history_file, serde_json::to_string(self)?)?;
+        Ok(())
+    }
+
+    /// Records that a file was opened, incrementing the open count of its directory.
+    pub fn record_open(&mut self, file: &Utf8Path) -> &mut Self {
+        if let Some(directory) = file.parent() {
+            *self.open_counts.entry(directory.to_owned()).or_insert(0) += 1;
+        }
+        self
+    }
+
+    /// Returns the affinity of a file, between 0.0 and 1.0. This is the open count of the most opened directory
+    /// containing the file, relative to the most opened directory overall.
+    pub fn affinity(&self, file: &Utf8Path) -> f32 {
+        let max_count = self.open_counts.values().copied().max().unwrap_or(0);
+        if max_count == 0 {
+            return 0.0;
+        }
+
+        let file_count = file.ancestors()
+            .skip(1)
+            .filter_map(|ancestor| self.open_counts.get(ancestor))
+            .copied()
+            .max()
+            .unwrap_or(0);
+
+        file_count as f32 / max_count as f32
+    }
+}
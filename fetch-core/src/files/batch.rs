@@ -0,0 +1,155 @@
+use std::{io, sync::Arc};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use chrono::Utc;
+use tokio::fs;
+use tracing::{debug, instrument, warn};
+
+use crate::{files::{FileIndexer, collection::{CollectionError, CollectionItem, FileCollections}, index::{FileIndexingError, IndexFiles}, tags::{UserTags, normalize_tag}}, store::{ClearByFilter, KeyedSequencedStore, QueryByFilter}};
+
+/// An action applied to every file in a batch, eg. to results the user has multi-selected
+#[derive(Debug, Clone)]
+pub enum BatchAction {
+    /// Adds a user tag to each file
+    Tag { tag: String },
+    /// Saves each file into a collection, along with the query it was found with
+    AddToCollection { collection: String, query: Option<String> },
+    /// Moves each file into a directory, carrying its index records and tags over to its new path
+    MoveTo { directory: Utf8PathBuf },
+    /// Indexes each file again
+    Reindex,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum BatchActionError {
+    #[error("Invalid tag {tag:?}: tags may only contain letters, numbers, '-', '_' and '.'")]
+    InvalidTag { tag: String },
+    #[error("Error loading or saving user tags")]
+    Tags { #[source] source: anyhow::Error },
+    #[error("Error updating the index")]
+    Index { #[source] source: FileIndexingError },
+    #[error("Error saving file into collection")]
+    Collection { #[source] source: CollectionError },
+    #[error("A file already exists at {destination}")]
+    DestinationExists { destination: Utf8PathBuf },
+    #[error("Error moving file to {destination}")]
+    Move { destination: Utf8PathBuf, #[source] source: io::Error },
+}
+
+/// Reported after each file in a batch is processed
+#[derive(Debug)]
+pub struct BatchProgress<'a> {
+    /// Number of files processed so far, including this one
+    pub completed: usize,
+    pub total: usize,
+    pub path: &'a Utf8Path,
+    /// Why the action failed for this file, if it did
+    pub error: Option<&'a BatchActionError>,
+}
+
+#[derive(Debug, Default)]
+pub struct BatchResult {
+    /// Paths of the files the action succeeded for. Moved files are listed at their new path.
+    pub succeeded: Vec<Utf8PathBuf>,
+    pub failed: Vec<(Utf8PathBuf, BatchActionError)>,
+}
+
+/// Applies actions to many files at once, continuing past files the action fails for
+pub struct FileBatch<S>
+where
+    S: KeyedSequencedStore<String, CollectionItem> +
+        QueryByFilter<CollectionItem> +
+        ClearByFilter<CollectionItem> +
+        Send + Sync
+{
+    indexer: Arc<FileIndexer>,
+    collections: Arc<FileCollections<S>>,
+}
+
+impl<S> FileBatch<S>
+where
+    S: KeyedSequencedStore<String, CollectionItem> +
+        QueryByFilter<CollectionItem> +
+        ClearByFilter<CollectionItem> +
+        Send + Sync
+{
+    pub fn using(indexer: Arc<FileIndexer>, collections: Arc<FileCollections<S>>) -> FileBatch<S> {
+        FileBatch { indexer, collections }
+    }
+
+    /// Applies the action to each file in turn, calling on_progress after each one.
+    #[instrument(skip(self, on_progress))]
+    pub async fn run(&self, paths: &[Utf8PathBuf], action: &BatchAction,
+        mut on_progress: impl FnMut(BatchProgress) + Send) -> BatchResult {
+        let mut result = BatchResult::default();
+        for (i, path) in paths.iter().enumerate() {
+            debug!("Applying batch action {:?} to file {}", action, path);
+            let outcome = self.apply(path, action).await;
+            on_progress(BatchProgress {
+                completed: i + 1,
+                total: paths.len(),
+                path,
+                error: outcome.as_ref().err(),
+            });
+            match outcome {
+                Ok(new_path) => result.succeeded.push(new_path),
+                Err(e) => {
+                    warn!("Batch action {:?} failed for file {}: {:?}", action, path, e);
+                    result.failed.push((path.clone(), e));
+                },
+            }
+        }
+        result
+    }
+
+    /// Applies the action to a single file, returning the path of the file afterwards
+    async fn apply(&self, path: &Utf8Path, action: &BatchAction) -> Result<Utf8PathBuf, BatchActionError> {
+        match action {
+            BatchAction::Tag { tag } => {
+                let tag = normalize_tag(tag).ok_or_else(|| BatchActionError::InvalidTag { tag: tag.clone() })?;
+                let mut user_tags = UserTags::load().map_err(|e| BatchActionError::Tags { source: e })?;
+                if user_tags.add(path, &tag) {
+                    user_tags.save().map_err(|e| BatchActionError::Tags { source: e })?;
+                    self.indexer.tag(path, &user_tags.tags(path)).await
+                        .map_err(|e| BatchActionError::Index { source: e })?;
+                }
+            },
+            BatchAction::AddToCollection { collection, query } => {
+                self.collections.add(collection, path, query.as_deref()).await
+                    .map_err(|e| BatchActionError::Collection { source: e })?;
+            },
+            BatchAction::MoveTo { directory } => {
+                return self.move_file(path, directory).await;
+            },
+            BatchAction::Reindex => {
+                self.indexer.index(path, Some(Utc::now())).await
+                    .map_err(|e| BatchActionError::Index { source: e })?;
+            },
+        }
+        Ok(path.to_owned())
+    }
+
+    async fn move_file(&self, path: &Utf8Path, directory: &Utf8Path) -> Result<Utf8PathBuf, BatchActionError> {
+        let destination = directory.join(path.file_name().unwrap_or(path.as_str()));
+        if destination == path {
+            return Ok(destination);
+        }
+        if fs::try_exists(&destination).await.unwrap_or(true) {
+            return Err(BatchActionError::DestinationExists { destination });
+        }
+
+        // Renaming fails across file systems, in which case the file is copied over instead
+        if fs::rename(path, &destination).await.is_err() {
+            fs::copy(path, &destination).await
+                .map_err(|e| BatchActionError::Move { destination: destination.clone(), source: e })?;
+            fs::remove_file(path).await
+                .map_err(|e| BatchActionError::Move { destination: destination.clone(), source: e })?;
+        }
+
+        // The file keeps its chunks and tags at its new path
+        self.indexer.rename(path, &destination).await
+            .map_err(|e| BatchActionError::Index { source: e })?;
+
+        Ok(destination)
+    }
+}
@@ -0,0 +1,173 @@
+use std::{collections::HashMap, sync::Arc};
+
+use camino::Utf8PathBuf;
+
+use crate::{files::{FileIndexer, FileQueryer, journal::{IndexJournal, JournalEntry}, pagination::QueryCursor, query::{MultiQuery, OsSearchFallback, QueryPageCache, QueryPlanner, QueryPreprocessor, RankingBoosts, ScoreWeights}}, index::provider::{ChunkingIndexProvider, registry::{ProviderBudget, ProviderRegistry}}, store::{ClearByFilter, KeyedSequencedStore, lancedb::LanceDBStore}};
+
+/// Configures a FileIndexer, see FileIndexer::builder. Everything left unset keeps the defaults of FileIndexer::with.
+#[derive(Default)]
+pub struct FileIndexerBuilder {
+    index_providers: Vec<Arc<dyn ChunkingIndexProvider>>,
+    journal: Option<Arc<IndexJournal<LanceDBStore<JournalEntry>>>>,
+    provider_budgets: HashMap<&'static str, ProviderBudget>,
+    chunk_directory: Option<Utf8PathBuf>,
+}
+
+impl FileIndexerBuilder {
+    /// Sets the providers files are indexed with, replacing any set before
+    pub fn providers(mut self, providers: Vec<Arc<dyn ChunkingIndexProvider>>) -> FileIndexerBuilder {
+        self.index_providers = providers;
+        self
+    }
+
+    /// Adds a provider files are indexed with
+    pub fn provider(mut self, provider: Arc<dyn ChunkingIndexProvider>) -> FileIndexerBuilder {
+        self.index_providers.push(provider);
+        self
+    }
+
+    /// Sets the providers, and the limits on the files each of them indexes at once, to those of the registry. The
+    /// registry opens the stores of the providers, see ProviderRegistry::open.
+    pub fn registry(mut self, registry: &ProviderRegistry) -> FileIndexerBuilder {
+        self.index_providers = registry.providers();
+        self.provider_budgets = registry.budgets();
+        self
+    }
+
+    /// Sets the journal every index, clear and skip decision is recorded into, see FileIndexer::with_journal
+    pub fn journal(mut self, journal: Arc<IndexJournal<LanceDBStore<JournalEntry>>>) -> FileIndexerBuilder {
+        self.journal = Some(journal);
+        self
+    }
+
+    /// Limits the files the provider indexes at once, see FileIndexer::with_provider_budgets
+    pub fn provider_budget(mut self, provider_name: &'static str, budget: ProviderBudget) -> FileIndexerBuilder {
+        self.provider_budgets.insert(provider_name, budget);
+        self
+    }
+
+    /// Limits the number of files the provider indexes at once, keeping the rest of its budget
+    pub fn max_concurrent(mut self, provider_name: &'static str, max_concurrent: usize) -> FileIndexerBuilder {
+        self.provider_budgets.entry(provider_name).or_default().max_concurrent = Some(max_concurrent);
+        self
+    }
+
+    /// Sets the directory the chunkfiles of indexed files are kept in. By default they are kept in the chunk
+    /// directory of the Fetch app, see app_config::get_default_chunk_directory.
+    pub fn chunk_directory(mut self, chunk_directory: Utf8PathBuf) -> FileIndexerBuilder {
+        self.chunk_directory = Some(chunk_directory);
+        self
+    }
+
+    pub fn build(self) -> FileIndexer {
+        let mut indexer = FileIndexer::with(self.index_providers).with_provider_budgets(self.provider_budgets);
+        indexer.journal = self.journal;
+        indexer.chunk_directory = self.chunk_directory;
+        indexer
+    }
+}
+
+/// Configures a FileQueryer, see FileQueryer::builder. Everything left unset keeps the defaults of FileQueryer::with.
+pub struct FileQueryerBuilder<C>
+where
+    C: KeyedSequencedStore<String, QueryCursor> +
+        ClearByFilter<QueryCursor> +
+        Send + Sync
+{
+    queryer: FileQueryer<C>,
+}
+
+impl<C> FileQueryerBuilder<C>
+where
+    C: KeyedSequencedStore<String, QueryCursor> +
+        ClearByFilter<QueryCursor> +
+        Send + Sync
+{
+    pub(crate) fn using(cursor_store: C) -> FileQueryerBuilder<C> {
+        FileQueryerBuilder { queryer: FileQueryer::with(vec![], cursor_store) }
+    }
+
+    /// Sets the providers files are queried from, replacing any set before
+    pub fn providers(mut self, providers: Vec<Arc<dyn ChunkingIndexProvider>>) -> FileQueryerBuilder<C> {
+        self.queryer.index_providers = providers;
+        self
+    }
+
+    /// Adds a provider files are queried from
+    pub fn provider(mut self, provider: Arc<dyn ChunkingIndexProvider>) -> FileQueryerBuilder<C> {
+        self.queryer.index_providers.push(provider);
+        self
+    }
+
+    /// Sets the providers to those of the registry, which opens their stores, see ProviderRegistry::open
+    pub fn registry(mut self, registry: &ProviderRegistry) -> FileQueryerBuilder<C> {
+        self.queryer.index_providers = registry.providers();
+        self
+    }
+
+    /// See FileQueryer::with_score_weights
+    pub fn score_weights(mut self, score_weights: ScoreWeights) -> FileQueryerBuilder<C> {
+        self.queryer.score_weights = score_weights;
+        self
+    }
+
+    /// See FileQueryer::with_ranking_boosts
+    pub fn ranking_boosts(mut self, ranking_boosts: RankingBoosts) -> FileQueryerBuilder<C> {
+        self.queryer.ranking_boosts = ranking_boosts;
+        self
+    }
+
+    /// See FileQueryer::with_query_preprocessor
+    pub fn query_preprocessor(mut self, query_preprocessor: QueryPreprocessor) -> FileQueryerBuilder<C> {
+        self.queryer.query_preprocessor = query_preprocessor;
+        self
+    }
+
+    /// See FileQueryer::with_os_search_fallback
+    pub fn os_search_fallback(mut self, os_search_fallback: OsSearchFallback) -> FileQueryerBuilder<C> {
+        self.queryer.os_search_fallback = os_search_fallback;
+        self
+    }
+
+    /// See FileQueryer::with_multi_query
+    pub fn multi_query(mut self, multi_query: MultiQuery) -> FileQueryerBuilder<C> {
+        self.queryer.multi_query = multi_query;
+        self
+    }
+
+    /// See FileQueryer::with_query_planner
+    pub fn query_planner(mut self, query_planner: QueryPlanner) -> FileQueryerBuilder<C> {
+        self.queryer.query_planner = query_planner;
+        self
+    }
+
+    /// See FileQueryer::with_page_cache
+    pub fn page_cache(mut self, page_cache: Arc<QueryPageCache>) -> FileQueryerBuilder<C> {
+        self.queryer.page_cache = Some(page_cache);
+        self
+    }
+
+    /// Sets the score a chunk found by a provider needs, before it is weighted, to count towards the score of its
+    /// file. By default every chunk found counts.
+    pub fn min_score(mut self, min_score: f32) -> FileQueryerBuilder<C> {
+        self.queryer.min_score = min_score;
+        self
+    }
+
+    /// Sets the number of chunks QueryFiles::query aggregates into the cursor per page. Defaults to 20.
+    pub fn default_num_chunks(mut self, num_chunks: u32) -> FileQueryerBuilder<C> {
+        self.queryer.default_num_chunks = num_chunks;
+        self
+    }
+
+    /// Sets how much the scores of files found by the previous query are multiplied by when a refined query does
+    /// not find them again, see QueryFiles::refine_n. Defaults to 0.5.
+    pub fn stale_score_decay(mut self, stale_score_decay: f32) -> FileQueryerBuilder<C> {
+        self.queryer.stale_score_decay = stale_score_decay;
+        self
+    }
+
+    pub fn build(self) -> FileQueryer<C> {
+        self.queryer
+    }
+}
@@ -0,0 +1,145 @@
+use std::collections::BTreeMap;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use chrono::{DateTime, Utc};
+use tracing::{debug, instrument};
+
+use crate::{sequence, store::{ClearByFilter, Filter, FilterRelation, FilterStoreError, FilterValue, KeyedSequencedStore, KeyedSequencedStoreError, QueryByFilter}};
+
+/// A file saved into a named collection, eg. while assembling research material across many searches
+#[derive(Debug, Clone)]
+pub struct CollectionItem {
+    pub collection: String,
+    pub file: Utf8PathBuf,
+    /// The query the file was found with, if it was saved from search results
+    pub query: Option<String>,
+    pub added: DateTime<Utc>,
+    /// Orders writes of the item to the store, see sequence::next. Items saved before sequence numbers were issued
+    /// by the sequence clock have the date they were added, in milliseconds, instead.
+    pub sequence_number: u64,
+}
+
+/// A named collection and the number of files saved into it
+#[derive(Debug, Clone)]
+pub struct CollectionSummary {
+    pub name: String,
+    pub num_items: usize,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum CollectionError {
+    #[error("Invalid collection name {name:?}, collection names must not be empty")]
+    InvalidName { name: String },
+    #[error("Error saving file {file} into collection {collection}")]
+    Save { collection: String, file: Utf8PathBuf, #[source] source: KeyedSequencedStoreError },
+    #[error("Error removing file {file} from collection {collection}")]
+    Remove { collection: String, file: Utf8PathBuf, #[source] source: KeyedSequencedStoreError },
+    #[error("Error deleting collection {collection}")]
+    Delete { collection: String, #[source] source: FilterStoreError },
+    #[error("Error retrieving collections")]
+    Query { #[source] source: FilterStoreError },
+}
+
+/// Manages named collections of files, stored one item per row in the given store
+pub struct FileCollections<S>
+where
+    S: KeyedSequencedStore<String, CollectionItem> +
+        QueryByFilter<CollectionItem> +
+        ClearByFilter<CollectionItem> +
+        Send + Sync
+{
+    store: S,
+}
+
+impl<S> FileCollections<S>
+where
+    S: KeyedSequencedStore<String, CollectionItem> +
+        QueryByFilter<CollectionItem> +
+        ClearByFilter<CollectionItem> +
+        Send + Sync
+{
+    pub fn using(store: S) -> FileCollections<S> {
+        FileCollections { store }
+    }
+
+    /// Saves a file into a collection, creating the collection if it does not exist yet. Saving a file that is
+    /// already in the collection updates the query it was saved from.
+    #[instrument(skip(self))]
+    pub async fn add(&self, collection: &str, file: &Utf8Path, query: Option<&str>) -> Result<(), CollectionError> {
+        let collection = normalize_collection_name(collection)?;
+        debug!("Saving file {} into collection {}", file, collection);
+        self.store.put(vec![CollectionItem {
+            collection: collection.clone(),
+            file: file.to_owned(),
+            query: query.map(str::to_owned),
+            added: Utc::now(),
+            sequence_number: sequence::next(),
+        }]).await
+            .map_err(|e| CollectionError::Save { collection, file: file.to_owned(), source: e })
+    }
+
+    /// Removes a file from a collection. Collections are removed along with their last file.
+    #[instrument(skip(self))]
+    pub async fn remove(&self, collection: &str, file: &Utf8Path) -> Result<(), CollectionError> {
+        let collection = normalize_collection_name(collection)?;
+        debug!("Removing file {} from collection {}", file, collection);
+        self.store.clear(collection_item_key(&collection, file), None).await
+            .map_err(|e| CollectionError::Remove { collection, file: file.to_owned(), source: e })
+    }
+
+    /// Deletes a collection and every item saved into it. The saved files themselves are left untouched.
+    #[instrument(skip(self))]
+    pub async fn delete(&self, collection: &str) -> Result<(), CollectionError> {
+        let collection = normalize_collection_name(collection)?;
+        self.store.clear_filter(&[Filter {
+            attribute: CollectionItem::COLLECTION_ATTR,
+            filter: FilterValue::String(&collection),
+            relation: FilterRelation::Eq,
+        }]).await
+            .map_err(|e| CollectionError::Delete { collection: collection.clone(), source: e })
+    }
+
+    /// Lists every collection in alphabetical order
+    pub async fn list(&self) -> Result<Vec<CollectionSummary>, CollectionError> {
+        let items = self.store.query_filter(&[]).await
+            .map_err(|e| CollectionError::Query { source: e })?;
+
+        let mut counts = BTreeMap::new();
+        for item in items {
+            *counts.entry(item.collection).or_insert(0) += 1;
+        }
+        Ok(counts.into_iter().map(|(name, num_items)| CollectionSummary { name, num_items }).collect())
+    }
+
+    /// Lists the items of a collection, in the order they were saved
+    pub async fn items(&self, collection: &str) -> Result<Vec<CollectionItem>, CollectionError> {
+        let collection = normalize_collection_name(collection)?;
+        let mut items = self.store.query_filter(&[Filter {
+            attribute: CollectionItem::COLLECTION_ATTR,
+            filter: FilterValue::String(&collection),
+            relation: FilterRelation::Eq,
+        }]).await
+            .map_err(|e| CollectionError::Query { source: e })?;
+
+        items.sort_by_key(|item| item.added);
+        Ok(items)
+    }
+}
+
+pub use integrations::*;
+
+pub mod integrations;
+
+// Private functions
+
+fn normalize_collection_name(name: &str) -> Result<String, CollectionError> {
+    let normalized = name.trim();
+    if normalized.is_empty() {
+        return Err(CollectionError::InvalidName { name: name.to_owned() });
+    }
+    Ok(normalized.to_owned())
+}
+
+fn collection_item_key(collection: &str, file: &Utf8Path) -> String {
+    format!("{}::{}", collection, file)
+}
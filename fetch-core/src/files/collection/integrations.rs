@@ -0,0 +1,168 @@
+use std::sync::{Arc, LazyLock};
+
+use arrow::array::{AsArray, StringBuilder, TimestampMillisecondBuilder};
+use arrow::datatypes::{TimestampMillisecondType, UInt64Type};
+use arrow_array::{Array, ArrayRef, RecordBatch};
+use arrow_schema::{DataType, Field, Schema, TimeUnit};
+use camino::Utf8PathBuf;
+use chrono::{TimeZone, Utc};
+
+use crate::files::collection::{CollectionItem, collection_item_key};
+use crate::store::lancedb::{ArrowData, RowBuilder, SEQUENCE_NUMBER_COLUMN};
+use crate::store::{Filterable, KeyedSequencedData};
+
+impl CollectionItem {
+    // Attribute names (field names on the CollectionItem struct)
+    pub const COLLECTION_ATTR: &str = "collection";
+    pub const FILE_ATTR: &str = "file";
+    pub const QUERY_ATTR: &str = "query";
+    pub const ADDED_ATTR: &str = "added";
+
+    // Column names (Arrow schema column names)
+    const COLLECTION_COLUMN_NAME: &str = "collection";
+    const FILE_COLUMN_NAME: &str = "file";
+    const QUERY_COLUMN_NAME: &str = "query";
+    const ADDED_COLUMN_NAME: &str = "added";
+}
+
+static COLLECTION_FIELD: LazyLock<Arc<Field>> = LazyLock::new(|| {
+    Arc::new(Field::new(CollectionItem::COLLECTION_COLUMN_NAME, DataType::Utf8, false))
+});
+static FILE_FIELD: LazyLock<Arc<Field>> = LazyLock::new(|| {
+    Arc::new(Field::new(CollectionItem::FILE_COLUMN_NAME, DataType::Utf8, false))
+});
+static QUERY_FIELD: LazyLock<Arc<Field>> = LazyLock::new(|| {
+    Arc::new(Field::new(CollectionItem::QUERY_COLUMN_NAME, DataType::Utf8, true))
+});
+static ADDED_FIELD: LazyLock<Arc<Field>> = LazyLock::new(|| {
+    Arc::new(Field::new(CollectionItem::ADDED_COLUMN_NAME, DataType::Timestamp(TimeUnit::Millisecond, Some("UTC".into())), false))
+});
+
+static COLLECTION_ITEM_SCHEMA: LazyLock<Schema> = LazyLock::new(|| {
+    Schema::new(vec![
+        COLLECTION_FIELD.clone(),
+        FILE_FIELD.clone(),
+        QUERY_FIELD.clone(),
+        ADDED_FIELD.clone(),
+    ])
+});
+
+impl KeyedSequencedData<String> for CollectionItem {
+    fn get_key(&self) -> String {
+        // A file can only be saved once per collection
+        collection_item_key(&self.collection, &self.file)
+    }
+
+    fn get_sequence_num(&self) -> u64 {
+        self.sequence_number
+    }
+}
+
+pub struct CollectionItemRowBuilder {
+    collection: StringBuilder,
+    file: StringBuilder,
+    query: StringBuilder,
+    added: TimestampMillisecondBuilder,
+}
+
+impl Default for CollectionItemRowBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CollectionItemRowBuilder {
+    pub fn new() -> Self {
+        Self {
+            collection: StringBuilder::new(),
+            file: StringBuilder::new(),
+            query: StringBuilder::new(),
+            added: TimestampMillisecondBuilder::new().with_timezone("UTC"),
+        }
+    }
+}
+
+impl RowBuilder<CollectionItem> for CollectionItemRowBuilder {
+    fn append(&mut self, row: CollectionItem) {
+        self.collection.append_value(&row.collection);
+        self.file.append_value(row.file.as_str());
+        self.query.append_option(row.query);
+        self.added.append_value(row.added.timestamp_millis());
+    }
+
+    fn finish(mut self) -> Vec<(Arc<Field>, ArrayRef)> {
+        vec![
+            (COLLECTION_FIELD.clone(), Arc::new(self.collection.finish())),
+            (FILE_FIELD.clone(), Arc::new(self.file.finish())),
+            (QUERY_FIELD.clone(), Arc::new(self.query.finish())),
+            (ADDED_FIELD.clone(), Arc::new(self.added.finish())),
+        ]
+    }
+}
+
+impl ArrowData for CollectionItem {
+    type RowBuilder = CollectionItemRowBuilder;
+
+    fn schema() -> Schema {
+        COLLECTION_ITEM_SCHEMA.clone()
+    }
+
+    fn row_builder() -> Self::RowBuilder {
+        CollectionItemRowBuilder::new()
+    }
+
+    fn batch_to_iter(record_batch: RecordBatch) -> impl IntoIterator<Item = Self> {
+        let num_rows = record_batch.num_rows();
+
+        (0..num_rows).map(move |i| {
+            let collection = record_batch.column_by_name(CollectionItem::COLLECTION_COLUMN_NAME)
+                .expect("collection column not found")
+                .as_string::<i32>()
+                .value(i)
+                .to_string();
+            let file = record_batch.column_by_name(CollectionItem::FILE_COLUMN_NAME)
+                .expect("file column not found")
+                .as_string::<i32>()
+                .value(i);
+            let query_array = record_batch.column_by_name(CollectionItem::QUERY_COLUMN_NAME)
+                .expect("query column not found")
+                .as_string::<i32>();
+            let query = (!query_array.is_null(i)).then(|| query_array.value(i).to_string());
+            let added = record_batch.column_by_name(CollectionItem::ADDED_COLUMN_NAME)
+                .expect("added column not found")
+                .as_primitive::<TimestampMillisecondType>()
+                .value(i);
+            // The sequence number is stored by the store itself, so it is missing from batches not read from one
+            let sequence_number = record_batch.column_by_name(SEQUENCE_NUMBER_COLUMN)
+                .map(|column| column.as_primitive::<UInt64Type>().value(i))
+                .unwrap_or(added as u64);
+
+            CollectionItem {
+                collection,
+                file: Utf8PathBuf::from(file),
+                query,
+                added: Utc.timestamp_millis_opt(added).unwrap(),
+                sequence_number,
+            }
+        })
+    }
+
+    fn attribute_to_column_name(attr: &str) -> &'static str {
+        match attr {
+            CollectionItem::COLLECTION_ATTR => CollectionItem::COLLECTION_COLUMN_NAME,
+            CollectionItem::FILE_ATTR => CollectionItem::FILE_COLUMN_NAME,
+            CollectionItem::QUERY_ATTR => CollectionItem::QUERY_COLUMN_NAME,
+            CollectionItem::ADDED_ATTR => CollectionItem::ADDED_COLUMN_NAME,
+            _ => panic!("Unknown CollectionItem attribute: {}", attr),
+        }
+    }
+}
+
+impl Filterable for CollectionItem {
+    fn filterable_attributes() -> Vec<&'static str> {
+        [
+            CollectionItem::COLLECTION_ATTR,
+            CollectionItem::FILE_ATTR,
+        ].to_vec()
+    }
+}
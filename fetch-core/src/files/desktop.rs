@@ -0,0 +1,131 @@
+use std::{io, process::{Command, Stdio}};
+#[cfg(target_os = "windows")]
+use std::os::windows::process::CommandExt;
+
+use camino::Utf8Path;
+use tracing::{debug, warn};
+
+use crate::files::os_path::to_os_path;
+
+/// Opens the file with the default application for its type
+pub fn open_with_default_app(path: &Utf8Path) -> Result<(), io::Error> {
+    #[cfg(target_os = "windows")]
+    let mut command = {
+        let mut command = Command::new("cmd");
+        command.args(["/c", "start", ""]).arg(to_os_path(path));
+        command
+    };
+
+    #[cfg(target_os = "macos")]
+    let mut command = {
+        let mut command = Command::new("open");
+        command.arg(to_os_path(path));
+        command
+    };
+
+    #[cfg(target_os = "linux")]
+    let mut command = {
+        let mut command = Command::new("xdg-open");
+        command.arg(to_os_path(path));
+        command
+    };
+
+    spawn_detached(&mut command)
+}
+
+/// Shows the folder the file is in with the file selected: in Explorer on Windows, Finder on macOS, and on Linux
+/// the file manager that implements the org.freedesktop.FileManager1 DBus interface (Nautilus, Dolphin, Nemo,
+/// Thunar, ...). If the file cannot be selected, eg. because it no longer exists or no file manager implements the
+/// interface, the closest folder of it that exists is opened instead.
+pub fn reveal_in_file_manager(path: &Utf8Path) -> Result<(), io::Error> {
+    if !to_os_path(path).exists() {
+        debug!("File to reveal {} does not exist, opening its folder instead", path);
+        return open_closest_folder(path);
+    }
+
+    match select_in_file_manager(path) {
+        Ok(()) => Ok(()),
+        Err(e) => {
+            warn!("Could not select {} in the file manager, opening its folder instead: {:?}", path, e);
+            open_closest_folder(path)
+        },
+    }
+}
+
+// Private functions
+
+#[cfg(target_os = "windows")]
+fn select_in_file_manager(path: &Utf8Path) -> Result<(), io::Error> {
+    // Explorer parses its arguments itself, so the path is quoted rather than escaped. It does not understand
+    // extended-length paths, so the path is given as is rather than through to_os_path. Its exit code does not say
+    // whether it selected the file, so it is not waited for.
+    spawn_detached(Command::new("explorer.exe").raw_arg(format!("/select,\"{}\"", path)))
+}
+
+#[cfg(target_os = "macos")]
+fn select_in_file_manager(path: &Utf8Path) -> Result<(), io::Error> {
+    // open -R reveals the file through NSWorkspace, which brings Finder to the front with the file selected
+    wait_for_success(Command::new("open").arg("-R").arg(to_os_path(path)))
+}
+
+#[cfg(target_os = "linux")]
+fn select_in_file_manager(path: &Utf8Path) -> Result<(), io::Error> {
+    let uri = file_uri(path);
+    // dbus-send only succeeds if a file manager answered the call, so the folder can be opened if none did
+    wait_for_success(Command::new("dbus-send")
+        .args([
+            "--session",
+            "--print-reply",
+            "--dest=org.freedesktop.FileManager1",
+            "--type=method_call",
+            "/org/freedesktop/FileManager1",
+            "org.freedesktop.FileManager1.ShowItems",
+        ])
+        .arg(format!("array:string:{}", uri))
+        .arg("string:"))
+}
+
+/// Opens the closest folder of the path that exists, which is the folder of the file unless it was removed too
+fn open_closest_folder(path: &Utf8Path) -> Result<(), io::Error> {
+    let folder = path.ancestors()
+        .skip(1)
+        .find(|ancestor| !ancestor.as_str().is_empty() && to_os_path(ancestor).is_dir())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("No folder of {} exists", path)))?;
+    open_with_default_app(folder)
+}
+
+fn spawn_detached(command: &mut Command) -> Result<(), io::Error> {
+    command
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+    Ok(())
+}
+
+#[cfg(any(target_os = "macos", target_os = "linux"))]
+fn wait_for_success(command: &mut Command) -> Result<(), io::Error> {
+    let status = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!("{:?} exited with {}", command.get_program(), status)))
+    }
+}
+
+/// The file:// URI of the path, with everything but unreserved characters and separators percent-encoded
+#[cfg(target_os = "linux")]
+fn file_uri(path: &Utf8Path) -> String {
+    let mut uri = String::from("file://");
+    for byte in path.as_str().bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => uri.push(byte as char),
+            _ => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    uri
+}
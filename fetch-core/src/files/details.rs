@@ -0,0 +1,198 @@
+use std::{cmp::Ordering, sync::Arc};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use chrono::{DateTime, Utc};
+use tracing::instrument;
+
+use crate::{files::query::parse_query, index::{ChunkFile, ChunkType, embedding::{EmbeddingError, embeddinggemma::{self, EmbeddingGemmaEmbeddedChunkFile}, siglip2::{self, Siglip2EmbeddedChunkFile}}}, store::{Filter, FilterRelation, FilterStoreError, FilterValue, QueryByFilter}};
+
+#[derive(thiserror::Error, Debug)]
+pub enum FileDetailsError {
+    #[error("File {path} has not been indexed")]
+    NotIndexed { path: Utf8PathBuf },
+    #[error("Error retrieving indexed chunks for file {path} from the {store} store")]
+    Store { path: Utf8PathBuf, store: &'static str, #[source] source: FilterStoreError },
+    #[error("Error embedding query to match against the chunks of file {path}")]
+    Embedding { path: Utf8PathBuf, #[source] source: EmbeddingError },
+}
+
+/// What the index knows about a file
+#[derive(Debug, Clone)]
+pub struct FileDetails {
+    pub path: Utf8PathBuf,
+    pub size: u64,
+    pub created: DateTime<Utc>,
+    pub modified: DateTime<Utc>,
+    /// Tags the user has added to the file
+    pub user_tags: Vec<String>,
+    /// The chunks of the file that best match the query, most similar first, or the first chunks of the file in
+    /// reading order if there is no query
+    pub matched_chunks: Vec<MatchedChunk>,
+}
+
+#[derive(Debug, Clone)]
+pub struct MatchedChunk {
+    pub channel: String,
+    pub sequence_id: f32,
+    pub chunk_type: ChunkType,
+    /// The chunk content on disk, eg. an image of a pdf page
+    pub chunkfile: Utf8PathBuf,
+    /// Cosine similarity (0.0 - 1.0) of the chunk to the query, if there was a query
+    pub similarity: Option<f32>,
+    /// Excerpt of the chunk text around the first query term it contains, for text chunks
+    pub snippet: Option<String>,
+}
+
+/// Looks up the indexed metadata and chunks of files, eg. for inspecting a selected result
+pub struct FileInspector<IS, TS>
+where
+    IS: QueryByFilter<Siglip2EmbeddedChunkFile> + Send + Sync,
+    TS: QueryByFilter<EmbeddingGemmaEmbeddedChunkFile> + Send + Sync,
+{
+    image_store: Arc<IS>,
+    text_store: Arc<TS>,
+    num_chunks: usize,
+}
+
+impl<IS, TS> FileInspector<IS, TS>
+where
+    IS: QueryByFilter<Siglip2EmbeddedChunkFile> + Send + Sync,
+    TS: QueryByFilter<EmbeddingGemmaEmbeddedChunkFile> + Send + Sync,
+{
+    pub fn using(image_store: Arc<IS>, text_store: Arc<TS>) -> FileInspector<IS, TS> {
+        FileInspector { image_store, text_store, num_chunks: DEFAULT_NUM_CHUNKS }
+    }
+
+    /// Sets the maximum number of matched chunks returned. Defaults to 5.
+    pub fn with_num_chunks(mut self, num_chunks: usize) -> FileInspector<IS, TS> {
+        self.num_chunks = num_chunks;
+        self
+    }
+
+    /// Returns the details of an indexed file. If a query is given, the chunks of the file are ranked by their
+    /// similarity to the query, ignoring any filter terms in it.
+    #[instrument(skip(self))]
+    pub async fn details(&self, path: &Utf8Path, query: Option<&str>) -> Result<FileDetails, FileDetailsError> {
+        let filters = [Filter {
+            attribute: ChunkFile::ORIGINAL_FILE_ATTR,
+            filter: FilterValue::String(path.as_str()),
+            relation: FilterRelation::Eq,
+        }];
+        let image_chunks = self.image_store.query_filter(&filters).await
+            .map_err(|e| FileDetailsError::Store { path: path.to_owned(), store: "image", source: e })?;
+        let text_chunks = self.text_store.query_filter(&filters).await
+            .map_err(|e| FileDetailsError::Store { path: path.to_owned(), store: "text", source: e })?;
+
+        let first_chunk = match image_chunks.first().map(|c| &c.chunkfile)
+            .or_else(|| text_chunks.first().map(|c| &c.chunkfile)) {
+            Some(chunk) => chunk,
+            None => return Err(FileDetailsError::NotIndexed { path: path.to_owned() }),
+        };
+        let mut details = FileDetails {
+            path: path.to_owned(),
+            size: first_chunk.original_file_size,
+            created: first_chunk.original_file_creation_date,
+            modified: first_chunk.original_file_modified_date,
+            user_tags: first_chunk.user_tags.clone(),
+            matched_chunks: vec![],
+        };
+
+        let query_text = query.map(|q| parse_query(q).text).filter(|text| !text.trim().is_empty());
+        let mut scored: Vec<(ChunkFile, Option<f32>)> = match &query_text {
+            Some(text) => {
+                let mut scored = vec![];
+                if !image_chunks.is_empty() {
+                    let embedding = siglip2::embed_query(text).await
+                        .map_err(|e| FileDetailsError::Embedding { path: path.to_owned(), source: e })?;
+                    for chunk in image_chunks {
+                        let similarity = cosine_similarity(&embedding, &chunk.embedding);
+                        scored.push((chunk.chunkfile, Some(similarity)));
+                    }
+                }
+                if !text_chunks.is_empty() {
+                    let embedding = embeddinggemma::embed_query(text).await
+                        .map_err(|e| FileDetailsError::Embedding { path: path.to_owned(), source: e })?;
+                    for chunk in text_chunks {
+                        let similarity = cosine_similarity(&embedding, &chunk.embedding);
+                        scored.push((chunk.chunkfile, Some(similarity)));
+                    }
+                }
+                scored.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(Ordering::Equal));
+                scored
+            },
+            None => {
+                let mut chunks: Vec<(ChunkFile, Option<f32>)> = image_chunks.into_iter().map(|c| (c.chunkfile, None))
+                    .chain(text_chunks.into_iter().map(|c| (c.chunkfile, None)))
+                    .collect();
+                chunks.sort_by(|a, b| a.0.chunk_sequence_id.total_cmp(&b.0.chunk_sequence_id)
+                    .then_with(|| a.0.chunk_channel.cmp(&b.0.chunk_channel)));
+                chunks
+            },
+        };
+        scored.truncate(self.num_chunks);
+
+        let query_terms: Vec<String> = query_text.iter()
+            .flat_map(|text| text.split_whitespace())
+            .map(str::to_lowercase)
+            .collect();
+        for (chunk, similarity) in scored {
+            let snippet = match chunk.chunk_type {
+                ChunkType::Text => tokio::fs::read_to_string(&chunk.chunkfile).await.ok()
+                    .map(|text| snippet(&text, &query_terms)),
+                _ => None,
+            };
+            details.matched_chunks.push(MatchedChunk {
+                channel: chunk.chunk_channel,
+                sequence_id: chunk.chunk_sequence_id,
+                chunk_type: chunk.chunk_type,
+                chunkfile: chunk.chunkfile,
+                similarity,
+                snippet,
+            });
+        }
+
+        Ok(details)
+    }
+}
+
+// Private functions and variables
+
+const DEFAULT_NUM_CHUNKS: usize = 5;
+/// Maximum number of characters in a snippet
+const SNIPPET_LENGTH: usize = 200;
+/// Number of characters shown before the first query term in a snippet
+const SNIPPET_LEAD: usize = 40;
+
+fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(a, b)| a * b).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
+    (dot / (norm_a * norm_b)).max(0.0)
+}
+
+/// Excerpt of the text starting shortly before the first query term it contains, or the start of the text if it
+/// contains none. Whitespace is collapsed so the snippet reads as a single line.
+fn snippet(text: &str, query_terms: &[String]) -> String {
+    let chars: Vec<char> = text.split_whitespace().collect::<Vec<&str>>().join(" ").chars().collect();
+    let lowercase: String = chars.iter().collect::<String>().to_lowercase();
+    let first_match = query_terms.iter()
+        .filter_map(|term| lowercase.find(term.as_str()))
+        .min()
+        // Lowercasing rarely changes the number of characters, so the match is located by character count
+        .map(|byte_offset| lowercase[..byte_offset].chars().count())
+        .unwrap_or(0);
+
+    let start = first_match.saturating_sub(SNIPPET_LEAD).min(chars.len());
+    let end = (start + SNIPPET_LENGTH).min(chars.len());
+    let mut snippet: String = chars[start..end].iter().collect();
+    if start > 0 {
+        snippet.insert(0, '…');
+    }
+    if end < chars.len() {
+        snippet.push('…');
+    }
+    snippet
+}
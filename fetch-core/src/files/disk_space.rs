@@ -0,0 +1,124 @@
+use std::fs;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use serde::{Deserialize, Serialize};
+use sysinfo::Disks;
+use tracing::{debug, warn};
+
+use crate::app_config;
+
+/// Settings of the low disk space safeguard, the low_disk_space table of daemon.toml
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DiskSpaceSettings {
+    /// Free space, in megabytes, the volumes of the index and chunk directories must keep for indexing to go ahead
+    pub min_free_mb: u64,
+}
+
+impl Default for DiskSpaceSettings {
+    fn default() -> Self {
+        DiskSpaceSettings { min_free_mb: 2048 }
+    }
+}
+
+impl DiskSpaceSettings {
+    /// Loads the low disk space settings from the daemon.toml settings file.
+    pub fn from_settings() -> Result<DiskSpaceSettings, anyhow::Error> {
+        let settings = app_config::get_daemon_config()?;
+        match settings.get::<DiskSpaceSettings>("low_disk_space") {
+            Ok(disk_space_settings) => Ok(disk_space_settings),
+            Err(config::ConfigError::NotFound(_)) => Ok(DiskSpaceSettings::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// Free space on the volumes indexing writes to, see DiskSpaceGuard::check
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiskSpace {
+    /// Every volume has more than the minimum free space
+    Enough,
+    /// The volume of the directory has less than the minimum free space, so indexing should pause before it fills up
+    Low { directory: Utf8PathBuf, free_bytes: u64, min_free_bytes: u64 },
+}
+
+/// Checks the free space on the volumes of the index and chunk directories before files are chunked and embedded,
+/// so indexing pauses before it fills the disk up instead of failing halfway through writing the index. Volumes
+/// whose free space cannot be read are assumed to have enough.
+pub struct DiskSpaceGuard {
+    settings: DiskSpaceSettings,
+    directories: Vec<Utf8PathBuf>,
+}
+
+impl DiskSpaceGuard {
+    pub fn using(settings: DiskSpaceSettings, directories: Vec<Utf8PathBuf>) -> DiskSpaceGuard {
+        DiskSpaceGuard { settings, directories }
+    }
+
+    /// Creates a guard over the index and chunk directories from the daemon.toml settings file. If the settings
+    /// cannot be loaded, the defaults are used.
+    pub fn from_settings() -> DiskSpaceGuard {
+        let settings = DiskSpaceSettings::from_settings().unwrap_or_else(|e| {
+            warn!("Could not load low disk space settings, using defaults: {:?}", e);
+            DiskSpaceSettings::default()
+        });
+        DiskSpaceGuard::using(settings, vec![
+            app_config::get_default_index_directory(),
+            app_config::get_default_chunk_directory(),
+        ])
+    }
+
+    pub fn settings(&self) -> &DiskSpaceSettings {
+        &self.settings
+    }
+
+    /// Whether the volumes of the directories still have the minimum free space. Reports the first one that does
+    /// not.
+    pub fn check(&self) -> DiskSpace {
+        let min_free_bytes = self.settings.min_free_mb.saturating_mul(1024 * 1024);
+        let disks = Disks::new_with_refreshed_list();
+        for directory in &self.directories {
+            match free_space_on(&disks, directory) {
+                Some(free_bytes) if free_bytes < min_free_bytes => {
+                    debug!("Low disk space for {}: {} bytes free, {} required", directory, free_bytes, min_free_bytes);
+                    return DiskSpace::Low { directory: directory.clone(), free_bytes, min_free_bytes };
+                },
+                _ => (),
+            }
+        }
+        DiskSpace::Enough
+    }
+}
+
+/// Free space on the volume the path is on, in bytes, or None if it cannot be read
+pub fn free_space(path: &Utf8Path) -> Option<u64> {
+    free_space_on(&Disks::new_with_refreshed_list(), path)
+}
+
+/// Total size of the files under the directory, in bytes. Entries that cannot be read are not counted.
+pub fn directory_size(path: &Utf8Path) -> u64 {
+    let entries = match fs::read_dir(path) {
+        Ok(entries) => entries,
+        Err(_) => return 0,
+    };
+    entries
+        .flatten()
+        .map(|entry| match (entry.file_type(), Utf8PathBuf::from_path_buf(entry.path())) {
+            (Ok(file_type), Ok(entry_path)) if file_type.is_dir() => directory_size(&entry_path),
+            (Ok(file_type), _) if file_type.is_file() => entry.metadata().map(|m| m.len()).unwrap_or(0),
+            _ => 0,
+        })
+        .sum()
+}
+
+// private functions
+
+/// The disk mounted closest to the path, ie. with the longest mount point the path is under, holds the path
+fn free_space_on(disks: &Disks, path: &Utf8Path) -> Option<u64> {
+    // Mount points are compared to the real path, so that paths through symlinks find the disk they end up on
+    let path = fs::canonicalize(path).unwrap_or_else(|_| path.as_std_path().to_owned());
+    disks.list().iter()
+        .filter(|disk| path.starts_with(disk.mount_point()))
+        .max_by_key(|disk| disk.mount_point().as_os_str().len())
+        .map(|disk| disk.available_space())
+}
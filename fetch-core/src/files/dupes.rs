@@ -0,0 +1,281 @@
+use std::{collections::{BTreeMap, HashMap}, fs::File, hash::{DefaultHasher, Hasher}, io::{self, Read}, sync::Arc};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use sha2::{Digest, Sha256};
+use tracing::{debug, instrument, warn};
+
+use crate::{files::os_path::to_os_path, index::{ChunkFile, embedding::{embeddinggemma::EmbeddingGemmaEmbeddedChunkFile, siglip2::Siglip2EmbeddedChunkFile}}, store::{FilterStoreError, QueryByFilter}};
+
+#[derive(thiserror::Error, Debug)]
+pub enum DuplicateFinderError {
+    #[error("Error retrieving indexed chunks from the {store} store")]
+    Store { store: &'static str, #[source] source: FilterStoreError },
+    #[error("Join error occurred while comparing files")]
+    Join { #[source] source: tokio::task::JoinError },
+}
+
+/// A group of indexed files that are copies or near copies of each other
+#[derive(Debug, Clone)]
+pub struct DuplicateGroup {
+    pub kind: DuplicateKind,
+    /// The files in the group, in path order
+    pub files: Vec<DuplicateFile>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DuplicateKind {
+    /// The files have exactly the same content
+    Identical,
+    /// The files' embeddings in the chunk channel are at least as similar as the given cosine similarity
+    Similar { channel: String, similarity: f32 },
+}
+
+#[derive(Debug, Clone)]
+pub struct DuplicateFile {
+    pub path: Utf8PathBuf,
+    pub size: u64,
+}
+
+impl DuplicateGroup {
+    /// The number of bytes that would be freed by keeping only the largest file in the group
+    pub fn reclaimable_size(&self) -> u64 {
+        let total: u64 = self.files.iter().map(|f| f.size).sum();
+        total - self.files.iter().map(|f| f.size).max().unwrap_or(0)
+    }
+}
+
+/// Finds indexed files that are duplicates of each other, either by exact content or by the similarity of their
+/// embeddings within each chunk channel.
+pub struct DuplicateFinder<IS, TS>
+where
+    IS: QueryByFilter<Siglip2EmbeddedChunkFile> + Send + Sync,
+    TS: QueryByFilter<EmbeddingGemmaEmbeddedChunkFile> + Send + Sync,
+{
+    image_store: Arc<IS>,
+    text_store: Arc<TS>,
+    similarity_threshold: f32,
+}
+
+impl<IS, TS> DuplicateFinder<IS, TS>
+where
+    IS: QueryByFilter<Siglip2EmbeddedChunkFile> + Send + Sync,
+    TS: QueryByFilter<EmbeddingGemmaEmbeddedChunkFile> + Send + Sync,
+{
+    pub fn using(image_store: Arc<IS>, text_store: Arc<TS>) -> DuplicateFinder<IS, TS> {
+        DuplicateFinder { image_store, text_store, similarity_threshold: DEFAULT_SIMILARITY_THRESHOLD }
+    }
+
+    /// Sets the cosine similarity (0.0 - 1.0) two files' embeddings must reach to be reported as near duplicates.
+    /// Defaults to 0.95.
+    pub fn with_similarity_threshold(mut self, similarity_threshold: f32) -> DuplicateFinder<IS, TS> {
+        self.similarity_threshold = similarity_threshold;
+        self
+    }
+
+    /// Finds groups of duplicate files among every indexed file. Groups of identical files are listed first,
+    /// followed by groups of near duplicates, each ordered by descending reclaimable size. Near duplicate groups
+    /// are only reported if they contain files that are not identical to each other.
+    ///
+    /// Every pair of files in a channel is compared, so this may take a while on large indexes.
+    #[instrument(skip(self))]
+    pub async fn find(&self) -> Result<Vec<DuplicateGroup>, DuplicateFinderError> {
+        let image_chunks = self.image_store.query_filter(&[]).await
+            .map_err(|e| DuplicateFinderError::Store { store: "image", source: e })?
+            .into_iter()
+            .map(|c| (c.chunkfile, c.embedding));
+        let text_chunks = self.text_store.query_filter(&[]).await
+            .map_err(|e| DuplicateFinderError::Store { store: "text", source: e })?
+            .into_iter()
+            .map(|c| (c.chunkfile, c.embedding));
+
+        // Embeddings from different models can not be compared, so each store's channels are kept apart
+        let channels = [file_embeddings(image_chunks), file_embeddings(text_chunks)]
+            .into_iter()
+            .flatten()
+            .collect::<Vec<ChannelEmbeddings>>();
+        let similarity_threshold = self.similarity_threshold;
+
+        tokio::task::spawn_blocking(move || {
+            let mut sizes = BTreeMap::new();
+            for channel in &channels {
+                for (path, file) in &channel.files {
+                    sizes.insert(path.clone(), file.size);
+                }
+            }
+            debug!("Comparing {} indexed files for duplicates", sizes.len());
+
+            let mut identical = identical_groups(&sizes);
+            let identity: HashMap<&Utf8Path, usize> = identical.iter().enumerate()
+                .flat_map(|(i, g)| g.files.iter().map(move |f| (f.path.as_path(), i)))
+                .collect();
+
+            let mut similar = vec![];
+            for channel in &channels {
+                for group in similar_groups(channel, similarity_threshold) {
+                    // Skip groups that only contain copies of the same file, they are already reported as identical
+                    let first = identity.get(group.files[0].path.as_path());
+                    let all_identical = first.is_some() &&
+                        group.files.iter().all(|f| identity.get(f.path.as_path()) == first);
+                    if !all_identical {
+                        similar.push(group);
+                    }
+                }
+            }
+
+            identical.sort_by_key(|g| std::cmp::Reverse(g.reclaimable_size()));
+            similar.sort_by_key(|g| std::cmp::Reverse(g.reclaimable_size()));
+            identical.extend(similar);
+            identical
+        }).await.map_err(|e| DuplicateFinderError::Join { source: e })
+    }
+}
+
+/// Returns a fingerprint of the content of the file at the path, made of its size and the SHA-256 digest of all of its
+/// content, so files with the same fingerprint are identical. Returns None if the file cannot be read.
+pub fn content_fingerprint(path: &Utf8Path) -> Option<String> {
+    let mut file = File::open(to_os_path(path)).ok()?;
+    let mut hasher = Sha256::new();
+    let size = io::copy(&mut file, &mut hasher).ok()?;
+    let digest: String = hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect();
+    Some(format!("{:x}:{}", size, digest))
+}
+
+// Private functions and variables
+
+const DEFAULT_SIMILARITY_THRESHOLD: f32 = 0.95;
+
+/// The averaged embedding of each file indexed in a chunk channel
+struct ChannelEmbeddings {
+    channel: String,
+    files: BTreeMap<Utf8PathBuf, FileEmbedding>,
+}
+
+struct FileEmbedding {
+    size: u64,
+    embedding: Vec<f32>,
+}
+
+/// Averages the chunk embeddings of each file per channel, so multi page documents are compared as a whole. The
+/// averaged embeddings are normalized to unit length.
+fn file_embeddings(chunks: impl Iterator<Item = (ChunkFile, Vec<f32>)>) -> Vec<ChannelEmbeddings> {
+    let mut channels: BTreeMap<String, BTreeMap<Utf8PathBuf, FileEmbedding>> = BTreeMap::new();
+    for (chunkfile, embedding) in chunks {
+        let file = channels.entry(chunkfile.chunk_channel)
+            .or_default()
+            .entry(chunkfile.original_file)
+            .or_insert_with(|| FileEmbedding { size: chunkfile.original_file_size, embedding: vec![0.0; embedding.len()] });
+        for (sum, value) in file.embedding.iter_mut().zip(&embedding) {
+            *sum += value;
+        }
+    }
+
+    channels.into_iter()
+        .map(|(channel, mut files)| {
+            for file in files.values_mut() {
+                let norm = file.embedding.iter().map(|v| v * v).sum::<f32>().sqrt();
+                if norm > 0.0 {
+                    file.embedding.iter_mut().for_each(|v| *v /= norm);
+                }
+            }
+            ChannelEmbeddings { channel, files }
+        })
+        .collect()
+}
+
+/// Groups files with the same content. Only files of the same size are hashed and compared.
+fn identical_groups(sizes: &BTreeMap<Utf8PathBuf, u64>) -> Vec<DuplicateGroup> {
+    let mut by_size: HashMap<u64, Vec<&Utf8PathBuf>> = HashMap::new();
+    for (path, size) in sizes {
+        // Empty files are trivially identical, and not worth reporting
+        if *size > 0 {
+            by_size.entry(*size).or_default().push(path);
+        }
+    }
+
+    let mut groups = vec![];
+    for (size, paths) in by_size.into_iter().filter(|(_, paths)| paths.len() > 1) {
+        let mut by_hash: BTreeMap<u64, Vec<DuplicateFile>> = BTreeMap::new();
+        for path in paths {
+            match hash_file(path) {
+                Ok(hash) => by_hash.entry(hash).or_default().push(DuplicateFile { path: path.clone(), size }),
+                Err(e) => warn!("Could not read {} to compare its content, skipping: {:?}", path, e),
+            }
+        }
+        groups.extend(by_hash.into_values()
+            .filter(|files| files.len() > 1)
+            .map(|files| DuplicateGroup { kind: DuplicateKind::Identical, files }));
+    }
+    groups
+}
+
+fn hash_file(path: &Utf8Path) -> Result<u64, io::Error> {
+    let mut file = File::open(to_os_path(path))?;
+    let mut hasher = DefaultHasher::new();
+    let mut buffer = vec![0; HASH_BUFFER_SIZE];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.write(&buffer[..read]);
+    }
+    Ok(hasher.finish())
+}
+
+const HASH_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Clusters the files of a channel whose embeddings are at least as similar as the threshold, joining clusters
+/// transitively. The reported similarity of a group is the lowest similarity that joined its files.
+fn similar_groups(channel: &ChannelEmbeddings, threshold: f32) -> Vec<DuplicateGroup> {
+    let files: Vec<(&Utf8PathBuf, &FileEmbedding)> = channel.files.iter().collect();
+    let mut parents: Vec<usize> = (0..files.len()).collect();
+    let mut edges = vec![];
+    for i in 0..files.len() {
+        for j in (i + 1)..files.len() {
+            let similarity: f32 = files[i].1.embedding.iter().zip(&files[j].1.embedding).map(|(a, b)| a * b).sum();
+            if similarity >= threshold {
+                let (root_i, root_j) = (find_root(&mut parents, i), find_root(&mut parents, j));
+                parents[root_j] = root_i;
+                edges.push((i, similarity));
+            }
+        }
+    }
+
+    let mut min_similarity: HashMap<usize, f32> = HashMap::new();
+    for (i, similarity) in edges {
+        let root = find_root(&mut parents, i);
+        let min = min_similarity.entry(root).or_insert(similarity);
+        *min = min.min(similarity);
+    }
+    let mut clusters: BTreeMap<usize, Vec<DuplicateFile>> = BTreeMap::new();
+    for (i, (path, file)) in files.iter().enumerate() {
+        let root = find_root(&mut parents, i);
+        clusters.entry(root).or_default().push(DuplicateFile { path: (*path).clone(), size: file.size });
+    }
+
+    clusters.into_iter()
+        .filter(|(_, files)| files.len() > 1)
+        .map(|(root, files)| DuplicateGroup {
+            kind: DuplicateKind::Similar {
+                channel: channel.channel.clone(),
+                similarity: min_similarity.get(&root).copied().unwrap_or(threshold),
+            },
+            files,
+        })
+        .collect()
+}
+
+fn find_root(parents: &mut [usize], i: usize) -> usize {
+    let mut root = i;
+    while parents[root] != root {
+        root = parents[root];
+    }
+    // Compress the path so later lookups are quick
+    let mut node = i;
+    while parents[node] != root {
+        let next = parents[node];
+        parents[node] = root;
+        node = next;
+    }
+    root
+}
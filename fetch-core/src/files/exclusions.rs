@@ -0,0 +1,58 @@
+use std::{collections::BTreeSet, fs};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use serde::{Deserialize, Serialize};
+
+use crate::app_config;
+
+/// Files and directories the user has excluded from the index, eg. from the context menu of a result. Excluded
+/// files are skipped when indexing, as are files under an excluded directory. Exclusions are stored in the
+/// application data directory.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct IndexExclusions {
+    paths: BTreeSet<Utf8PathBuf>,
+}
+
+impl IndexExclusions {
+    /// Loads exclusions from the application data directory. Returns no exclusions if none have been added yet.
+    pub fn load() -> Result<IndexExclusions, anyhow::Error> {
+        let exclusions_file = app_config::get_index_exclusions_file_path();
+        if !fs::exists(&exclusions_file)? {
+            return Ok(IndexExclusions::default());
+        }
+
+        let contents = fs::read_to_string(&exclusions_file)?;
+        Ok(serde_json::from_str(&contents)?)
+    }
+
+    /// Saves exclusions to the application data directory.
+    pub fn save(&self) -> Result<(), anyhow::Error> {
+        let exclusions_file = app_config::get_index_exclusions_file_path();
+        if let Some(parent) = exclusions_file.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        fs::write(&exclusions_file, serde_json::to_string(self)?)?;
+        Ok(())
+    }
+
+    /// Excludes a file or directory from the index. Returns false if it was already excluded.
+    pub fn add(&mut self, path: &Utf8Path) -> bool {
+        self.paths.insert(path.to_owned())
+    }
+
+    /// Includes a previously excluded file or directory in the index again. Returns false if it was not excluded.
+    pub fn remove(&mut self, path: &Utf8Path) -> bool {
+        self.paths.remove(path)
+    }
+
+    /// Whether the file is excluded, either directly or by being under an excluded directory
+    pub fn is_excluded(&self, path: &Utf8Path) -> bool {
+        path.ancestors().any(|ancestor| self.paths.contains(ancestor))
+    }
+
+    /// Returns the excluded files and directories in alphabetical order
+    pub fn paths(&self) -> Vec<Utf8PathBuf> {
+        self.paths.iter().cloned().collect()
+    }
+}
@@ -0,0 +1,89 @@
+use std::{collections::{HashMap, HashSet}, fs};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use serde::{Deserialize, Serialize};
+
+use crate::app_config;
+
+/// Records which files the user opened from the results of each query, so that files frequently chosen for
+/// similar queries can be promoted in future results.
+///
+/// Queries are stored normalized (lowercased, with whitespace collapsed), and similarity between queries is
+/// the overlap of their words.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ClickFeedback {
+    clicks: HashMap<String, HashMap<Utf8PathBuf, u32>>,
+}
+
+impl ClickFeedback {
+    /// Loads recorded feedback from the application data directory. Returns empty feedback if nothing has
+    /// been recorded yet.
+    pub fn load() -> Result<ClickFeedback, anyhow::Error> {
+        let feedback_file = app_config::get_feedback_file_path();
+        if !fs::exists(&feedback_file)? {
+            return Ok(ClickFeedback::default());
+        }
+
+        let contents = fs::read_to_string(&feedback_file)?;
+        Ok(serde_json::from_str(&contents)?)
+    }
+
+    /// Saves recorded feedback to the application data directory.
+    pub fn save(&self) -> Result<(), anyhow::Error> {
+        let feedback_file = app_config::get_feedback_file_path();
+        if let Some(parent) = feedback_file.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        fs::write(&feedback_file, serde_json::to_string(self)?)?;
+        Ok(())
+    }
+
+    /// Records that a file was opened from the results of a query.
+    pub fn record_click(&mut self, query: &str, file: &Utf8Path) -> &mut Self {
+        let normalized = normalize_query(query);
+        if !normalized.is_empty() {
+            *self.clicks.entry(normalized).or_default().entry(file.to_owned()).or_insert(0) += 1;
+        }
+        self
+    }
+
+    /// Returns how strongly a file has been chosen for queries similar to the given query, between 0.0 and 1.0.
+    ///
+    /// For every recorded query, the file's share of that query's most clicked result is scaled by the
+    /// similarity of the recorded query to the given one, and the highest of these values is returned.
+    pub fn preference(&self, query: &str, file: &Utf8Path) -> f32 {
+        let normalized = normalize_query(query);
+        let words: HashSet<&str> = normalized.split(' ').collect();
+
+        self.clicks.iter()
+            .filter_map(|(recorded_query, files)| {
+                let file_clicks = *files.get(file)?;
+                let max_clicks = files.values().copied().max()?;
+                let similarity = word_similarity(&words, recorded_query);
+
+                Some(similarity * file_clicks as f32 / max_clicks as f32)
+            })
+            .fold(0.0, f32::max)
+    }
+}
+
+// Private functions
+
+fn normalize_query(query: &str) -> String {
+    query.split_whitespace()
+        .map(str::to_lowercase)
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/// Jaccard similarity between the words of two queries
+fn word_similarity(words: &HashSet<&str>, other_query: &str) -> f32 {
+    let other_words: HashSet<&str> = other_query.split(' ').collect();
+    let union = words.union(&other_words).count();
+    if union == 0 {
+        return 0.0;
+    }
+
+    words.intersection(&other_words).count() as f32 / union as f32
+}
@@ -0,0 +1,153 @@
+use std::{sync::Mutex, time::Duration};
+
+use serde::{Deserialize, Serialize};
+use starship_battery::State;
+use sysinfo::{ProcessesToUpdate, System};
+use tracing::warn;
+
+use crate::app_config;
+
+/// How background indexing responds to the computer's power source and how busy its processor is
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ThrottleMode {
+    /// Files are indexed as soon as they change
+    Off,
+    /// Indexing slows down while the computer runs on battery or other programs keep the processor busy
+    #[default]
+    OnBatteryOrLoad,
+    /// Files are only indexed while the computer is plugged in and the processor is otherwise idle
+    IdleAndPluggedIn,
+}
+
+/// Settings of the resource governor, the index_throttle table of daemon.toml
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ThrottleSettings {
+    pub mode: ThrottleMode,
+    /// Processor usage by other programs, in percent, above which the computer is considered under load
+    pub max_cpu_load: f32,
+    /// Processor usage by other programs, in percent, below which the computer is considered idle
+    pub idle_cpu_load: f32,
+    /// How long indexing waits before each batch of changes while it is slowed down, in seconds
+    pub delay_secs: u64,
+}
+
+impl Default for ThrottleSettings {
+    fn default() -> Self {
+        ThrottleSettings { mode: ThrottleMode::default(), max_cpu_load: 60.0, idle_cpu_load: 15.0, delay_secs: 30 }
+    }
+}
+
+impl ThrottleSettings {
+    /// Loads the throttle settings from the daemon.toml settings file.
+    pub fn from_settings() -> Result<ThrottleSettings, anyhow::Error> {
+        let settings = app_config::get_daemon_config()?;
+        match settings.get::<ThrottleSettings>("index_throttle") {
+            Ok(throttle_settings) => Ok(throttle_settings),
+            Err(config::ConfigError::NotFound(_)) => Ok(ThrottleSettings::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// What background indexing should do right now, see ResourceGovernor::check
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Throttle {
+    /// Index at full speed
+    Run,
+    /// Wait the given time before indexing the next batch of changes
+    SlowDown(Duration),
+    /// Hold changes until the governor allows indexing again
+    Pause,
+}
+
+/// Watches the battery state and processor load and decides whether background indexing should go ahead, slow
+/// down or pause, according to ThrottleSettings. The processor usage of this process is left out of the load, so
+/// indexing does not throttle itself.
+pub struct ResourceGovernor {
+    settings: ThrottleSettings,
+    system: Mutex<System>,
+}
+
+impl ResourceGovernor {
+    pub fn using(settings: ThrottleSettings) -> ResourceGovernor {
+        let mut system = System::new();
+        // Processor usage is measured between refreshes, so the first check measures from here
+        refresh_load(&mut system);
+        ResourceGovernor { settings, system: Mutex::new(system) }
+    }
+
+    /// Creates a governor from the daemon.toml settings file. If they cannot be loaded, the defaults are used.
+    pub fn from_settings() -> ResourceGovernor {
+        let settings = ThrottleSettings::from_settings().unwrap_or_else(|e| {
+            warn!("Could not load index throttle settings, using defaults: {:?}", e);
+            ThrottleSettings::default()
+        });
+        ResourceGovernor::using(settings)
+    }
+
+    pub fn settings(&self) -> &ThrottleSettings {
+        &self.settings
+    }
+
+    /// Decides what background indexing should do, from the battery state and the processor load by other
+    /// programs since the last check.
+    pub fn check(&self) -> Throttle {
+        if self.settings.mode == ThrottleMode::Off {
+            return Throttle::Run;
+        }
+
+        let on_battery = on_battery();
+        let load = self.other_cpu_load();
+        match self.settings.mode {
+            ThrottleMode::Off => Throttle::Run,
+            ThrottleMode::OnBatteryOrLoad if on_battery || load > self.settings.max_cpu_load =>
+                Throttle::SlowDown(Duration::from_secs(self.settings.delay_secs)),
+            ThrottleMode::OnBatteryOrLoad => Throttle::Run,
+            ThrottleMode::IdleAndPluggedIn if on_battery || load > self.settings.idle_cpu_load => Throttle::Pause,
+            ThrottleMode::IdleAndPluggedIn => Throttle::Run,
+        }
+    }
+
+    /// Processor usage in percent, across all cores, by programs other than this process
+    fn other_cpu_load(&self) -> f32 {
+        let mut system = self.system.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        refresh_load(&mut system);
+        let cpu_count = system.cpus().len().max(1) as f32;
+        // Process usage is in percent of a single core
+        let own_load = sysinfo::get_current_pid().ok()
+            .and_then(|pid| system.process(pid))
+            .map(|process| process.cpu_usage() / cpu_count)
+            .unwrap_or(0.0);
+        (system.global_cpu_usage() - own_load).max(0.0)
+    }
+}
+
+// private constants and functions
+
+fn refresh_load(system: &mut System) {
+    system.refresh_cpu_usage();
+    if let Ok(pid) = sysinfo::get_current_pid() {
+        system.refresh_processes(ProcessesToUpdate::Some(&[pid]), true);
+    }
+}
+
+/// Whether the computer is running on battery. Computers without batteries, or whose batteries cannot be read,
+/// are considered plugged in.
+fn on_battery() -> bool {
+    let manager = match starship_battery::Manager::new() {
+        Ok(manager) => manager,
+        Err(e) => {
+            warn!("Could not read battery state, assuming plugged in: {:?}", e);
+            return false;
+        },
+    };
+    match manager.batteries() {
+        Ok(mut batteries) => batteries.any(|battery| battery.is_ok_and(|battery| battery.state() == State::Discharging)),
+        Err(e) => {
+            warn!("Could not read battery state, assuming plugged in: {:?}", e);
+            false
+        },
+    }
+}
@@ -1,10 +1,11 @@
-use std::{collections::HashMap, future::Future};
+use std::{collections::HashMap, future::Future, sync::Arc, time::Instant};
 
-use camino::Utf8Path;
+use camino::{Utf8Path, Utf8PathBuf};
 use chrono::{DateTime, Utc};
-use log::{debug, info};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tracing::{debug, info, instrument, warn};
 
-use crate::{files::ChunkingIndexProviderConcurrent, index::provider::IndexProviderErrorType};
+use crate::{files::{ChunkingIndexProviderConcurrent, dupes, exclusions::IndexExclusions, journal::{JournalAction, JournalEntry}, os_path::to_os_path, query::invalidate_cached_pages, tags::{TagRules, UserTags}, volumes::{self, IndexedVolumes}}, index::provider::{IndexProviderError, IndexProviderErrorType, has_chunkfiles, move_chunkfiles, registry::ProviderBudget, with_chunk_directory}};
 
 use super::FileIndexer;
 
@@ -17,9 +18,33 @@ pub trait IndexFiles {
     /// finally indexing it into a vector store
     /// If the file does not exist or a preview is unable to be generated for the file, then the file is cleared
     /// from the index instead
+    /// Files excluded from the index (see IndexExclusions) are cleared from the index and Skipped
+    /// If multiple providers handle the file and only some of them fail, a PartiallyIndexed result is returned
+    /// listing the providers that succeeded. An error is only returned if every provider failed.
+    /// Files that were moved or renamed from a path that is still indexed (see indexed_path) have their index moved
+    /// instead (see rename), returning a Renamed result
     fn index<'a>(&self, path: &'a Utf8Path, opt_modified: Option<DateTime<Utc>>) -> impl Future<Output = Result<FileIndexingResult<'a>, FileIndexingError>> + Send;
     /// Clear the index for a file path. Does not check for the existence of the file
     fn clear<'a>(&self, path: &'a Utf8Path, opt_modified: Option<DateTime<Utc>>) -> impl Future<Output = Result<FileIndexingResult<'a>, FileIndexingError>> + Send;
+    /// Replace the user tags stored with the indexed chunks of a file, along with the tags of the tag rules the file
+    /// matches (see TagRules). Does not record the tags in the user tag store (see UserTags), which is where tags are
+    /// copied from when the file is next indexed. Files that are not indexed are left untouched.
+    fn tag<'a>(&self, path: &'a Utf8Path, tags: &[String]) -> impl Future<Output = Result<FileIndexingResult<'a>, FileIndexingError>> + Send;
+    /// Restore the cleared index entries of a file path, or of every file under a directory path, as long as they
+    /// have not been purged yet. Does not check for the existence of the path or files, so entries of deleted files
+    /// can be restored too.
+    fn restore<'a>(&self, path: &'a Utf8Path) -> impl Future<Output = Result<FileIndexingResult<'a>, FileIndexingError>> + Send;
+    /// Move the index of a file to the path it was moved or renamed to, keeping its chunks and embeddings rather than
+    /// indexing the file again. Its user tags (see UserTags) are moved along with it. Files whose new path is routed
+    /// to other providers (eg. its extension changed) or excluded from the index, and files that were not indexed
+    /// under their old path, are cleared and indexed at the new path instead.
+    fn rename<'a>(&self, from: &Utf8Path, to: &'a Utf8Path) -> impl Future<Output = Result<FileIndexingResult<'a>, FileIndexingError>> + Send;
+    /// Find the path the file at the path was indexed under before it was moved or renamed, by its file id (see
+    /// volumes::file_id), or by its content if no path was indexed with its id. Either way the file must have the
+    /// content it was indexed with (see dupes::content_fingerprint), which is read in full. Returns None if the file is
+    /// indexed under its own path, was not indexed under another path, was changed since, or that path still exists,
+    /// eg. a copy of the file.
+    fn indexed_path<'a>(&self, path: &'a Utf8Path) -> impl Future<Output = Result<Option<Utf8PathBuf>, FileIndexingError>> + Send;
     // Clears the index for all files currently indexed under a path. Does not check for existence of the path or files
     // EG. clear_fuzzy("/home/august99us/test") would clear "/home/august99us/test/dog.jpg" and "/home/august99us/test/cat.jpg"
     // as well as /home/august99us/test/testing/doc.pdf any other files that have /home/august99us/test in the path
@@ -31,14 +56,37 @@ pub trait IndexFiles {
 
 impl IndexFiles for FileIndexer
 {
+    #[instrument(name = "index_file", skip(self, opt_modified), fields(path = %path))]
     async fn index<'a>(&self, path: &'a Utf8Path, opt_modified: Option<DateTime<Utc>>) -> Result<FileIndexingResult<'a>, FileIndexingError> {
-        debug!("FileIndexer: Indexing file with path: {}", path);
+        // Files moved while nothing was watching them (eg. a reorganized photo library scanned again) keep their
+        // chunks rather than being embedded again, and are cleared from their old path. Only paths not indexed yet are
+        // looked up, so files indexed again at their path are not read an extra time.
+        if !with_chunk_directory(self.chunk_directory.clone(), has_chunkfiles(path)).await {
+            match self.indexed_path(path).await {
+                Ok(Some(from)) => {
+                    info!("FileIndexer: File {} was moved from {}, moving its index", path, from);
+                    return self.rename(&from, path).await;
+                },
+                Ok(None) => (),
+                Err(e) => warn!("FileIndexer: Could not check whether {} was moved, indexing it: {:?}", path, e),
+            }
+        }
 
+        self.index_file(path, opt_modified).await
+    }
+
+    #[instrument(name = "clear_file", skip(self, opt_modified), fields(path = %path))]
+    async fn clear<'a>(&self, path: &'a Utf8Path, opt_modified: Option<DateTime<Utc>>) -> Result<FileIndexingResult<'a>, FileIndexingError> {
+        debug!("FileIndexer: Clearing index of path: {}", path);
+
+        let routed = self.providers_for(path);
         let path_clone = path.to_owned();
+        let chunk_directory = self.chunk_directory.clone();
         let results = self.index_providers.distribute_calls(async move |p| {
-            let ext = path_clone.extension().unwrap_or("");
-            if p.provides_indexing_for_extension(ext) {
-                Some(p.index(&path_clone, opt_modified).await)
+            if routed.contains(&p.provider_name()) {
+                let provider_started = Instant::now();
+                let res = with_chunk_directory(chunk_directory, p.clear(&path_clone, opt_modified)).await;
+                Some((p.provider_name(), res, provider_started.elapsed()))
             } else {
                 None
             }
@@ -49,35 +97,44 @@ impl IndexFiles for FileIndexer
                 source: e,
             },
         })?;
+        invalidate_cached_pages(path);
 
-        let mut was_processed = false;
         let mut provider_error_map = HashMap::new();
-        for res_opt in results {
-            if let Some(res) = res_opt {
-                was_processed = true;
-                if let Err(e) = res {
-                    let provider_name = e.provider_name.clone();
-                    match e.r#type {
-                        IndexProviderErrorType::Sequencing { provided_datetime, stored_datetime } => {
-                            // Ignore sequencing errors.
-                            info!("FileIndexer: Attempted indexing on file: {} but the stored modified_date \
-                                ({}) was equal to or later than the file's modified_date ({}). Ignoring.",
-                                path,
-                                stored_datetime, provided_datetime
-                            );
-                        },
-                        _ => {
-                            provider_error_map.insert(provider_name, e);
-                        }
-                    }
+        let mut journal_entries = vec![];
+        for (provider_name, res, duration) in results.into_iter().flatten() {
+            let e = match res {
+                Ok(()) => {
+                    journal_entries.push(JournalEntry::now(path, Some(provider_name), JournalAction::Cleared, None, duration));
+                    continue;
+                },
+                Err(e) => e,
+            };
+            match e.r#type {
+                IndexProviderErrorType::InvalidExtension { path } => {
+                    // Ignore invalid extension errors on clear
+                    info!("FileIndexer: Attempted clear on file: {} but extension was invalid. Ignoring.",
+                        path,
+                    );
+                }
+                IndexProviderErrorType::Sequencing { provided_datetime, stored_datetime } => {
+                    // Ignore sequencing errors.
+                    info!("FileIndexer: Attempted clear on file: {} but the stored modified_date \
+                        ({}) was equal to or later than the file's modified_date ({}). Ignoring.",
+                        path,
+                        stored_datetime, provided_datetime
+                    );
+                    journal_entries.push(JournalEntry::now(path, Some(provider_name), JournalAction::Skipped,
+                        Some(format!("Stored data ({}) is newer than the clear ({})", stored_datetime, provided_datetime)),
+                        duration));
+                },
+                _ => {
+                    journal_entries.push(JournalEntry::now(path, Some(provider_name), JournalAction::Failed,
+                        Some(e.to_string()), duration));
+                    provider_error_map.insert(e.provider_name.clone(), e);
                 }
             }
         }
-
-        if !was_processed {
-            return Ok(FileIndexingResult { path, r#type: FileIndexingResultType::Skipped {
-                reason: "Extension not registered in any provider".to_string() } })
-        }
+        self.record_journal(journal_entries).await;
         
         if !provider_error_map.is_empty() {
             return Err(FileIndexingError { path: path.to_owned(), r#type: FileIndexingErrorType::IndexProviders {
@@ -85,20 +142,300 @@ impl IndexFiles for FileIndexer
             }});
         }
 
-        Ok(FileIndexingResult { path, r#type: FileIndexingResultType::Indexed })
+        Ok(FileIndexingResult { path, r#type: FileIndexingResultType::Cleared })
     }
 
-    async fn clear<'a>(&self, path: &'a Utf8Path, opt_modified: Option<DateTime<Utc>>) -> Result<FileIndexingResult<'a>, FileIndexingError> {
-        debug!("FileIndexer: Clearing index of path: {}", path);
+    #[instrument(name = "tag_file", skip(self), fields(path = %path))]
+    async fn tag<'a>(&self, path: &'a Utf8Path, tags: &[String]) -> Result<FileIndexingResult<'a>, FileIndexingError> {
+        debug!("FileIndexer: Tagging path: {} with tags: {:?}", path, tags);
 
+        let routed = self.providers_for(path);
         let path_clone = path.to_owned();
+        let tags = with_rule_tags(path, None, tags.to_vec());
         let results = self.index_providers.distribute_calls(async move |p| {
-            let ext = path_clone.extension().unwrap_or("");
-            if p.provides_indexing_for_extension(ext) {
-                p.clear(&path_clone, opt_modified).await
+            if routed.contains(&p.provider_name()) {
+                p.tag(&path_clone, &tags).await
             } else {
                 Ok(())
             }
+        }).await.map_err(|e| FileIndexingError {
+            path: path.to_owned(),
+            r#type: FileIndexingErrorType::Other {
+                msg: "Join error occurred while tagging file",
+                source: e,
+            },
+        })?;
+        invalidate_cached_pages(path);
+
+        let provider_error_map = results.into_iter()
+            .filter_map(Result::err)
+            .map(|e| (e.provider_name.clone(), e))
+            .collect::<HashMap<_, _>>();
+        if !provider_error_map.is_empty() {
+            return Err(FileIndexingError { path: path.to_owned(), r#type: FileIndexingErrorType::IndexProviders {
+                provider_errors: provider_error_map,
+            }});
+        }
+
+        Ok(FileIndexingResult { path, r#type: FileIndexingResultType::Tagged })
+    }
+
+    #[instrument(name = "restore_file", skip(self), fields(path = %path))]
+    async fn restore<'a>(&self, path: &'a Utf8Path) -> Result<FileIndexingResult<'a>, FileIndexingError> {
+        debug!("FileIndexer: Restoring cleared index entries under path: {}", path);
+
+        // Directories have no extension to pick providers by, so every provider is asked
+        let path_clone = path.to_owned();
+        let results = self.index_providers.distribute_calls(async move |p| {
+            let provider_started = Instant::now();
+            let res = p.restore(&path_clone).await;
+            (p.provider_name(), res, provider_started.elapsed())
+        }).await.map_err(|e| FileIndexingError {
+            path: path.to_owned(),
+            r#type: FileIndexingErrorType::Other {
+                msg: "Join error occurred while restoring file",
+                source: e,
+            },
+        })?;
+        invalidate_cached_pages(path);
+
+        let mut chunks = 0;
+        let mut provider_error_map = HashMap::new();
+        let mut journal_entries = vec![];
+        for (provider_name, res, duration) in results {
+            match res {
+                Ok(0) => (),
+                Ok(restored) => {
+                    chunks += restored;
+                    journal_entries.push(JournalEntry::now(path, Some(provider_name), JournalAction::Restored,
+                        Some(format!("{} chunks restored", restored)), duration));
+                },
+                Err(e) => {
+                    journal_entries.push(JournalEntry::now(path, Some(provider_name), JournalAction::Failed,
+                        Some(e.to_string()), duration));
+                    provider_error_map.insert(e.provider_name.clone(), e);
+                },
+            }
+        }
+        self.record_journal(journal_entries).await;
+        if !provider_error_map.is_empty() {
+            return Err(FileIndexingError { path: path.to_owned(), r#type: FileIndexingErrorType::IndexProviders {
+                provider_errors: provider_error_map,
+            }});
+        }
+
+        Ok(FileIndexingResult { path, r#type: FileIndexingResultType::Restored { chunks } })
+    }
+
+    #[instrument(name = "rename_file", skip(self), fields(from = %from, to = %to))]
+    async fn rename<'a>(&self, from: &Utf8Path, to: &'a Utf8Path) -> Result<FileIndexingResult<'a>, FileIndexingError> {
+        debug!("FileIndexer: Renaming index of path: {} to: {}", from, to);
+
+        let is_excluded = IndexExclusions::load()
+            .map(|exclusions| exclusions.is_excluded(to))
+            .unwrap_or_else(|e| {
+                warn!("FileIndexer: Could not load index exclusions, renaming {} anyway: {:?}", from, e);
+                false
+            });
+        if is_excluded || self.providers_for(from) != self.providers_for(to) {
+            self.clear(from, None).await?;
+            return self.index_file(to, None).await;
+        }
+
+        // Tags are keyed by path, so they follow the file to its new path
+        let moved_tags = UserTags::load().and_then(|mut user_tags| {
+            if user_tags.move_file(from, to) {
+                user_tags.save()?;
+            }
+            Ok(())
+        });
+        if let Err(e) = moved_tags {
+            warn!("FileIndexer: Could not move user tags of {} to {}: {:?}", from, to, e);
+        }
+
+        // Chunks of other files may be indexed under the file too (eg. subtitles under their video), so every
+        // provider is asked. The chunks are moved before their chunkfiles, see move_chunkfiles.
+        let chunk_directory = self.chunk_directory.clone();
+        let (from_clone, to_clone) = (from.to_owned(), to.to_owned());
+        let providers_chunk_directory = chunk_directory.clone();
+        let results = self.index_providers.distribute_calls(async move |p| {
+            let provider_started = Instant::now();
+            let res = with_chunk_directory(providers_chunk_directory, p.rename(&from_clone, &to_clone)).await;
+            (p.provider_name(), res, provider_started.elapsed())
+        }).await.map_err(|e| FileIndexingError {
+            path: to.to_owned(),
+            r#type: FileIndexingErrorType::Other {
+                msg: "Join error occurred while renaming file",
+                source: e,
+            },
+        })?;
+        invalidate_cached_pages(from);
+        invalidate_cached_pages(to);
+
+        let mut chunks = 0;
+        let mut provider_error_map = HashMap::new();
+        let mut journal_entries = vec![];
+        for (provider_name, res, duration) in results {
+            match res {
+                Ok(0) => (),
+                Ok(renamed) => {
+                    chunks += renamed;
+                    journal_entries.push(JournalEntry::now(to, Some(provider_name), JournalAction::Renamed,
+                        Some(format!("{} chunks moved from {}", renamed, from)), duration));
+                },
+                Err(e) => {
+                    journal_entries.push(JournalEntry::now(to, Some(provider_name), JournalAction::Failed,
+                        Some(e.to_string()), duration));
+                    provider_error_map.insert(e.provider_name.clone(), e);
+                },
+            }
+        }
+        self.record_journal(journal_entries).await;
+        if !provider_error_map.is_empty() {
+            // Other providers may have moved their chunks already, which point to chunkfiles that were not moved
+            self.discard_rename(from, to).await;
+            return Err(FileIndexingError { path: to.to_owned(), r#type: FileIndexingErrorType::IndexProviders {
+                provider_errors: provider_error_map,
+            }});
+        }
+
+        if chunks == 0 {
+            debug!("FileIndexer: Path {} was not indexed, indexing {} instead", from, to);
+            self.clear(from, None).await?;
+            return self.index_file(to, None).await;
+        }
+
+        if let Err(e) = with_chunk_directory(chunk_directory, move_chunkfiles(from, to)).await {
+            self.discard_rename(from, to).await;
+            return Err(FileIndexingError {
+                path: to.to_owned(),
+                r#type: FileIndexingErrorType::Other {
+                    msg: "Could not move chunkfiles of renamed file",
+                    source: e.into(),
+                },
+            });
+        }
+        record_volume(to);
+
+        Ok(FileIndexingResult { path: to, r#type: FileIndexingResultType::Renamed { from: from.to_owned(), chunks } })
+    }
+
+    #[instrument(name = "indexed_path", skip(self), fields(path = %path))]
+    async fn indexed_path<'a>(&self, path: &'a Utf8Path) -> Result<Option<Utf8PathBuf>, FileIndexingError> {
+        let routed = self.providers_for(path);
+        let mut indexed_files = vec![];
+        if let Some(file_id) = volumes::file_id(path) {
+            let routed = routed.clone();
+            let results = self.index_providers.distribute_calls(async move |p| {
+                if routed.contains(&p.provider_name()) {
+                    Some(p.indexed_file(&file_id).await.map(|indexed_file| indexed_file.into_iter().collect::<Vec<_>>()))
+                } else {
+                    None
+                }
+            }).await.map_err(|e| FileIndexingError {
+                path: path.to_owned(),
+                r#type: FileIndexingErrorType::Other {
+                    msg: "Join error occurred while finding indexed path of file",
+                    source: e,
+                },
+            })?;
+            indexed_files = collect_indexed(path, results)?;
+            if indexed_files.iter().any(|indexed_file| indexed_file.path.as_path() == path) {
+                return Ok(None);
+            }
+        }
+
+        // Ids of deleted files are reused, and files may be edited after being moved, so files found by their id must
+        // have the content they were indexed with too
+        let fingerprint = match dupes::content_fingerprint(path) {
+            Some(fingerprint) => fingerprint,
+            None => return Ok(None),
+        };
+        let indexed_paths = if indexed_files.is_empty() {
+            // Files without an id, and files given a new id by being moved to another volume, are found by their
+            // content instead
+            let results = self.index_providers.distribute_calls(async move |p| {
+                if routed.contains(&p.provider_name()) {
+                    Some(p.indexed_paths_by_content(&fingerprint).await)
+                } else {
+                    None
+                }
+            }).await.map_err(|e| FileIndexingError {
+                path: path.to_owned(),
+                r#type: FileIndexingErrorType::Other {
+                    msg: "Join error occurred while finding indexed path of file",
+                    source: e,
+                },
+            })?;
+            collect_indexed(path, results)?
+        } else {
+            indexed_files.into_iter()
+                .filter(|indexed_file| indexed_file.fingerprint.as_ref() == Some(&fingerprint))
+                .map(|indexed_file| indexed_file.path)
+                .collect()
+        };
+
+        // A file still indexed under its own path was not moved, even if copies of it were
+        if indexed_paths.iter().any(|indexed_path| indexed_path.as_path() == path) {
+            return Ok(None);
+        }
+        Ok(indexed_paths.into_iter().find(|indexed_path| !to_os_path(indexed_path).exists()))
+    }
+}
+
+impl FileIndexer {
+    /// Indexes the file at its path, see IndexFiles::index. Does not check whether the file was moved, so that
+    /// renaming can fall back to indexing.
+    async fn index_file<'a>(&self, path: &'a Utf8Path, opt_modified: Option<DateTime<Utc>>) -> Result<FileIndexingResult<'a>, FileIndexingError> {
+        debug!("FileIndexer: Indexing file with path: {}", path);
+        let started = Instant::now();
+
+        // Excluded files are cleared rather than skipped outright, in case they were indexed before being excluded
+        let is_excluded = IndexExclusions::load()
+            .map(|exclusions| exclusions.is_excluded(path))
+            .unwrap_or_else(|e| {
+                warn!("FileIndexer: Could not load index exclusions, indexing {} anyway: {:?}", path, e);
+                false
+            });
+        if is_excluded {
+            self.clear(path, opt_modified).await?;
+            let reason = "Excluded from the index".to_string();
+            self.record_journal(vec![JournalEntry::now(path, None, JournalAction::Skipped, Some(reason.clone()),
+                started.elapsed())]).await;
+            return Ok(FileIndexingResult { path, r#type: FileIndexingResultType::Skipped { reason } })
+        }
+
+        // Indexing replaces the stored chunks of the file, so its user tags are copied onto the new chunks, along
+        // with the tags of the tag rules it matches
+        let user_tags = UserTags::load()
+            .map(|tags| tags.tags(path))
+            .unwrap_or_else(|e| {
+                warn!("FileIndexer: Could not load user tags, indexing {} without tags: {:?}", path, e);
+                vec![]
+            });
+        let user_tags = with_rule_tags(path, opt_modified, user_tags);
+
+        let routed = self.providers_for(path);
+        let path_clone = path.to_owned();
+        let limiters = self.provider_limiters.clone();
+        let file_size = std::fs::metadata(to_os_path(path)).map(|m| m.len()).unwrap_or(0);
+        let chunk_directory = self.chunk_directory.clone();
+        let results = self.index_providers.distribute_calls(async move |p| {
+            if routed.contains(&p.provider_name()) {
+                // Waiting for the provider's budget counts towards the time the provider took
+                let provider_started = Instant::now();
+                let _permits = match limiters.get(p.provider_name()) {
+                    Some(limiter) => limiter.acquire(file_size).await,
+                    None => vec![],
+                };
+                let mut res = with_chunk_directory(chunk_directory, p.index(&path_clone, opt_modified)).await;
+                if res.is_ok() && !user_tags.is_empty() {
+                    res = p.tag(&path_clone, &user_tags).await;
+                }
+                Some((p.provider_name(), res, provider_started.elapsed()))
+            } else {
+                None
+            }
         }).await.map_err(|e| FileIndexingError {
             path: path.to_owned(),
             r#type: FileIndexingErrorType::Other {
@@ -106,47 +443,199 @@ impl IndexFiles for FileIndexer
                 source: e,
             },
         })?;
+        // Providers may have written some chunks of the file even if others failed
+        invalidate_cached_pages(path);
 
+        let mut was_processed = false;
+        let mut indexed_providers = vec![];
         let mut provider_error_map = HashMap::new();
-        for res in results {
-            if let Err(e) = res {
-                let provider_name = e.provider_name.clone();
-                match e.r#type {
-                    IndexProviderErrorType::InvalidExtension { path } => {
-                        // Ignore invalid extension errors on clear
-                        info!("FileIndexer: Attempted clear on file: {} but extension was invalid. Ignoring.",
-                            path,
-                        );
-                    }
-                    IndexProviderErrorType::Sequencing { provided_datetime, stored_datetime } => {
-                        // Ignore sequencing errors.
-                        info!("FileIndexer: Attempted clear on file: {} but the stored modified_date \
-                            ({}) was equal to or later than the file's modified_date ({}). Ignoring.",
-                            path,
-                            stored_datetime, provided_datetime
-                        );
-                    },
-                    _ => {
-                        provider_error_map.insert(provider_name, e);
+        let mut journal_entries = vec![];
+        for (provider_name, res, duration) in results.into_iter().flatten() {
+            was_processed = true;
+            match res {
+                Ok(()) => {
+                    indexed_providers.push(provider_name.to_owned());
+                    journal_entries.push(JournalEntry::now(path, Some(provider_name), JournalAction::Indexed, None, duration));
+                },
+                Err(e) => {
+                    match e.r#type {
+                        IndexProviderErrorType::Sequencing { provided_datetime, stored_datetime } => {
+                            // Ignore sequencing errors. The provider's stored data is already up to date.
+                            info!("FileIndexer: Attempted indexing on file: {} but the stored modified_date \
+                                ({}) was equal to or later than the file's modified_date ({}). Ignoring.",
+                                path,
+                                stored_datetime, provided_datetime
+                            );
+                            indexed_providers.push(provider_name.to_owned());
+                            journal_entries.push(JournalEntry::now(path, Some(provider_name), JournalAction::Skipped,
+                                Some(format!("Stored data ({}) is already up to date with the file ({})",
+                                    stored_datetime, provided_datetime)), duration));
+                        },
+                        _ => {
+                            journal_entries.push(JournalEntry::now(path, Some(provider_name), JournalAction::Failed,
+                                Some(e.to_string()), duration));
+                            provider_error_map.insert(e.provider_name.clone(), e);
+                        }
                     }
                 }
             }
         }
-        
+
+        if !was_processed {
+            let reason = "Extension not registered in any provider".to_string();
+            self.record_journal(vec![JournalEntry::now(path, None, JournalAction::Skipped, Some(reason.clone()),
+                started.elapsed())]).await;
+            return Ok(FileIndexingResult { path, r#type: FileIndexingResultType::Skipped { reason } })
+        }
+        self.record_journal(journal_entries).await;
+
+        if !indexed_providers.is_empty() {
+            record_volume(path);
+        }
+
         if !provider_error_map.is_empty() {
-            return Err(FileIndexingError { path: path.to_owned(), r#type: FileIndexingErrorType::IndexProviders {
+            if indexed_providers.is_empty() {
+                return Err(FileIndexingError { path: path.to_owned(), r#type: FileIndexingErrorType::IndexProviders {
+                    provider_errors: provider_error_map,
+                }});
+            }
+
+            // Leave the data from the successful providers in place rather than rolling it back, so the file
+            // remains at least partially searchable.
+            warn!("FileIndexer: File {} was only partially indexed. Succeeded: {:?}, failed: {:?}",
+                path, indexed_providers, provider_error_map.keys().collect::<Vec<_>>());
+            return Ok(FileIndexingResult { path, r#type: FileIndexingResultType::PartiallyIndexed {
+                indexed_providers,
                 provider_errors: provider_error_map,
             }});
         }
 
-        Ok(FileIndexingResult { path, r#type: FileIndexingResultType::Cleared })
+        Ok(FileIndexingResult { path, r#type: FileIndexingResultType::Indexed })
+    }
+
+    /// Clears both paths of a rename that failed part way, so no chunks are left pointing to chunkfiles that were not
+    /// moved. The file is indexed from scratch at its new path the next time it is indexed.
+    async fn discard_rename(&self, from: &Utf8Path, to: &Utf8Path) {
+        for path in [from, to] {
+            if let Err(e) = self.clear(path, None).await {
+                warn!("FileIndexer: Could not clear {} after failing to rename {} to {}: {:?}", path, from, to, e);
+            }
+        }
+    }
+
+    /// Records entries into the journal, if there is one. Failing to record is logged rather than returned, so it
+    /// does not fail the indexing itself.
+    async fn record_journal(&self, entries: Vec<JournalEntry>) {
+        if let Some(journal) = &self.journal {
+            if let Err(e) = journal.record(entries).await {
+                warn!("FileIndexer: Could not record journal entries: {:?}", e);
+            }
+        }
     }
 }
 
 pub use result::*;
 pub use error::*;
 
+/// Enforces the budget of a provider (see ProviderBudget) with a semaphore for each of its limits
+pub(super) struct ProviderLimiter {
+    concurrent: Option<Arc<Semaphore>>,
+    /// Permits are megabytes of memory, along with the number of them
+    memory: Option<(Arc<Semaphore>, u32)>,
+}
+
+impl ProviderLimiter {
+    pub(super) fn new(budget: ProviderBudget) -> ProviderLimiter {
+        ProviderLimiter {
+            concurrent: budget.max_concurrent.map(|max| Arc::new(Semaphore::new(max.max(1)))),
+            memory: budget.max_memory_mb.map(|max| (Arc::new(Semaphore::new(max.max(1) as usize)), max.max(1))),
+        }
+    }
+
+    /// Waits until the provider has room for a file of the size, returning the permits to hold while the file is
+    /// indexed. Files estimated to take up more than the whole memory budget still get indexed, on their own.
+    async fn acquire(&self, file_size: u64) -> Vec<OwnedSemaphorePermit> {
+        let mut permits = vec![];
+        // Limits are always acquired in the same order, so files waiting on each other cannot deadlock
+        if let Some(concurrent) = &self.concurrent {
+            permits.push(concurrent.clone().acquire_owned().await.expect("Provider semaphores are never closed"));
+        }
+        if let Some((memory, max_mb)) = &self.memory {
+            let file_mb = file_size.div_ceil(BYTES_PER_MB).clamp(1, *max_mb as u64) as u32;
+            permits.push(memory.clone().acquire_many_owned(file_mb).await.expect("Provider semaphores are never closed"));
+        }
+        permits
+    }
+}
+
 // private modules and functions
 
+const BYTES_PER_MB: u64 = 1024 * 1024;
+
+/// Records the volume the indexed file is on, so the file can be told apart from a deleted file while the volume is
+/// disconnected
+fn record_volume(path: &Utf8Path) {
+    let volume = match volumes::volume_of(path, &volumes::mounted_volumes()) {
+        Some(volume) => volume,
+        None => return,
+    };
+    let result = IndexedVolumes::load().and_then(|mut indexed_volumes| {
+        if indexed_volumes.record(&volume) {
+            indexed_volumes.save()?;
+        }
+        Ok(())
+    });
+    if let Err(e) = result {
+        warn!("FileIndexer: Could not record volume {} of {}: {:?}", volume.label, path, e);
+    }
+}
+
+/// Adds the tags of the tag rules the file matches to its tags, see TagRules. The modified date of the file is read
+/// from the file if not given. If the rules cannot be loaded, the file keeps only its tags.
+fn with_rule_tags(path: &Utf8Path, opt_modified: Option<DateTime<Utc>>, mut tags: Vec<String>) -> Vec<String> {
+    let rules = match TagRules::from_settings() {
+        Ok(rules) => rules,
+        Err(e) => {
+            warn!("FileIndexer: Could not load tag rules, tagging {} without them: {:?}", path, e);
+            return tags;
+        },
+    };
+    if rules.rules().is_empty() {
+        return tags;
+    }
+
+    let modified = opt_modified.or_else(|| std::fs::metadata(to_os_path(path)).and_then(|m| m.modified()).ok()
+        .map(DateTime::<Utc>::from));
+    for tag in rules.tags(path, modified) {
+        if !tags.contains(&tag) {
+            tags.push(tag);
+        }
+    }
+    tags
+}
+
+/// Collects what the providers found a file indexed as, see IndexFiles::indexed_path. Returns the errors of the
+/// providers that failed, if any did.
+fn collect_indexed<T>(path: &Utf8Path, results: Vec<Option<Result<Vec<T>, IndexProviderError>>>)
+    -> Result<Vec<T>, FileIndexingError>
+{
+    let mut indexed_paths = vec![];
+    let mut provider_error_map = HashMap::new();
+    for res in results.into_iter().flatten() {
+        match res {
+            Ok(paths) => indexed_paths.extend(paths),
+            Err(e) => {
+                provider_error_map.insert(e.provider_name.clone(), e);
+            },
+        }
+    }
+    if !provider_error_map.is_empty() {
+        return Err(FileIndexingError { path: path.to_owned(), r#type: FileIndexingErrorType::IndexProviders {
+            provider_errors: provider_error_map,
+        }});
+    }
+    Ok(indexed_paths)
+}
+
 mod result;
 mod error;
\ No newline at end of file
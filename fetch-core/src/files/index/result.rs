@@ -1,10 +1,23 @@
-use camino::Utf8Path;
+use std::collections::HashMap;
+
+use camino::{Utf8Path, Utf8PathBuf};
+
+use crate::index::provider::IndexProviderError;
 
 // Perhaps this needs to be a struct so path can be a common variable amongst all variants?
 pub enum FileIndexingResultType {
     Indexed,
+    /// Some of the providers registered for the file indexed it successfully, while others errored.
+    /// Data stored by the successful providers is kept, so the file is still searchable through them.
+    PartiallyIndexed { indexed_providers: Vec<String>, provider_errors: HashMap<String, IndexProviderError> },
     Skipped { reason: String },
     Cleared,
+    /// The user tags stored with the file's indexed chunks were replaced
+    Tagged,
+    /// Cleared chunks of the files under the path were put back in the index
+    Restored { chunks: u64 },
+    /// The chunks indexed for the file at its old path were moved to the path it was moved or renamed to
+    Renamed { from: Utf8PathBuf, chunks: u64 },
 }
 pub struct FileIndexingResult<'a> {
     pub path: &'a Utf8Path,
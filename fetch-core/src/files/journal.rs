@@ -0,0 +1,141 @@
+use std::time::Duration;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use chrono::{DateTime, Utc};
+use tracing::{debug, instrument};
+
+use crate::{sequence, store::{Filter, FilterRelation, FilterStoreError, FilterValue, KeyedSequencedStore, KeyedSequencedStoreError, QueryByFilter}};
+
+/// A decision made while indexing a file, eg. that a provider indexed it, or that it was skipped and why. Entries
+/// are only ever added to the journal, so together they tell the history of a file in the index.
+#[derive(Debug, Clone)]
+pub struct JournalEntry {
+    pub path: Utf8PathBuf,
+    /// Name of the provider the decision was made by, or None if it was made before any provider was asked
+    pub provider: Option<String>,
+    pub action: JournalAction,
+    /// Why the file was skipped or failed, if it was
+    pub reason: Option<String>,
+    /// How long the provider took, or how long the whole decision took if no provider was asked
+    pub duration: Duration,
+    pub recorded: DateTime<Utc>,
+    /// Orders entries recorded in the same millisecond, see sequence::next
+    pub sequence_number: u64,
+}
+
+/// What happened to a file in the index
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JournalAction {
+    Indexed,
+    Cleared,
+    Skipped,
+    Failed,
+    Restored,
+    Renamed,
+}
+
+impl JournalAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            JournalAction::Indexed => "indexed",
+            JournalAction::Cleared => "cleared",
+            JournalAction::Skipped => "skipped",
+            JournalAction::Failed => "failed",
+            JournalAction::Restored => "restored",
+            JournalAction::Renamed => "renamed",
+        }
+    }
+
+    pub fn parse(action: &str) -> Option<JournalAction> {
+        match action {
+            "indexed" => Some(JournalAction::Indexed),
+            "cleared" => Some(JournalAction::Cleared),
+            "skipped" => Some(JournalAction::Skipped),
+            "failed" => Some(JournalAction::Failed),
+            "restored" => Some(JournalAction::Restored),
+            "renamed" => Some(JournalAction::Renamed),
+            _ => None,
+        }
+    }
+}
+
+impl JournalEntry {
+    /// Creates an entry recorded now
+    pub fn now(path: &Utf8Path, provider: Option<&str>, action: JournalAction, reason: Option<String>, duration: Duration) -> JournalEntry {
+        JournalEntry {
+            path: path.to_owned(),
+            provider: provider.map(str::to_owned),
+            action,
+            reason,
+            duration,
+            recorded: Utc::now(),
+            sequence_number: sequence::next(),
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum JournalError {
+    #[error("Error recording journal entries")]
+    Record { #[source] source: KeyedSequencedStoreError },
+    #[error("Error retrieving journal entries for {path}")]
+    Query { path: Utf8PathBuf, #[source] source: FilterStoreError },
+}
+
+/// Append-only log of the decisions made while indexing files, to find out eg. why a file is not showing up in
+/// search results. Stored one entry per row in the given store.
+pub struct IndexJournal<S>
+where
+    S: KeyedSequencedStore<String, JournalEntry> +
+        QueryByFilter<JournalEntry> +
+        Send + Sync
+{
+    store: S,
+}
+
+impl<S> IndexJournal<S>
+where
+    S: KeyedSequencedStore<String, JournalEntry> +
+        QueryByFilter<JournalEntry> +
+        Send + Sync
+{
+    pub fn using(store: S) -> IndexJournal<S> {
+        IndexJournal { store }
+    }
+
+    /// Adds entries to the journal
+    #[instrument(skip_all, fields(num_entries = entries.len()))]
+    pub async fn record(&self, entries: Vec<JournalEntry>) -> Result<(), JournalError> {
+        if entries.is_empty() {
+            return Ok(());
+        }
+        debug!("Recording {} journal entries", entries.len());
+        self.store.put(entries).await
+            .map_err(|e| JournalError::Record { source: e })
+    }
+
+    /// Lists the most recent entries recorded for a path, newest first
+    pub async fn entries(&self, path: &Utf8Path, num_entries: usize) -> Result<Vec<JournalEntry>, JournalError> {
+        let mut entries = self.store.query_filter(&[Filter {
+            attribute: JournalEntry::PATH_ATTR,
+            filter: FilterValue::String(path.as_str()),
+            relation: FilterRelation::Eq,
+        }]).await
+            .map_err(|e| JournalError::Query { path: path.to_owned(), source: e })?;
+
+        entries.sort_by_key(|entry| std::cmp::Reverse(entry.sequence_number));
+        entries.truncate(num_entries);
+        Ok(entries)
+    }
+}
+
+pub use integrations::*;
+
+pub mod integrations;
+
+// Private functions
+
+fn journal_entry_key(path: &Utf8Path, sequence_number: u64) -> String {
+    // Sequence numbers are unique, so every entry gets a row of its own
+    format!("{}::{}", path, sequence_number)
+}
@@ -0,0 +1,198 @@
+use std::sync::{Arc, LazyLock};
+use std::time::Duration;
+
+use arrow::array::{AsArray, StringBuilder, TimestampMillisecondBuilder, UInt64Builder};
+use arrow::datatypes::{TimestampMillisecondType, UInt64Type};
+use arrow_array::{Array, ArrayRef, RecordBatch};
+use arrow_schema::{DataType, Field, Schema, TimeUnit};
+use camino::Utf8PathBuf;
+use chrono::{TimeZone, Utc};
+
+use crate::files::journal::{JournalAction, JournalEntry, journal_entry_key};
+use crate::store::lancedb::{ArrowData, RowBuilder, SEQUENCE_NUMBER_COLUMN};
+use crate::store::{Filterable, KeyedSequencedData};
+
+impl JournalEntry {
+    // Attribute names (field names on the JournalEntry struct)
+    pub const PATH_ATTR: &str = "path";
+    pub const PROVIDER_ATTR: &str = "provider";
+    pub const ACTION_ATTR: &str = "action";
+    pub const REASON_ATTR: &str = "reason";
+    pub const DURATION_ATTR: &str = "duration";
+    pub const RECORDED_ATTR: &str = "recorded";
+
+    // Column names (Arrow schema column names)
+    const PATH_COLUMN_NAME: &str = "path";
+    const PROVIDER_COLUMN_NAME: &str = "provider";
+    const ACTION_COLUMN_NAME: &str = "action";
+    const REASON_COLUMN_NAME: &str = "reason";
+    const DURATION_COLUMN_NAME: &str = "duration_ms";
+    const RECORDED_COLUMN_NAME: &str = "recorded";
+}
+
+static PATH_FIELD: LazyLock<Arc<Field>> = LazyLock::new(|| {
+    Arc::new(Field::new(JournalEntry::PATH_COLUMN_NAME, DataType::Utf8, false))
+});
+static PROVIDER_FIELD: LazyLock<Arc<Field>> = LazyLock::new(|| {
+    Arc::new(Field::new(JournalEntry::PROVIDER_COLUMN_NAME, DataType::Utf8, true))
+});
+static ACTION_FIELD: LazyLock<Arc<Field>> = LazyLock::new(|| {
+    Arc::new(Field::new(JournalEntry::ACTION_COLUMN_NAME, DataType::Utf8, false))
+});
+static REASON_FIELD: LazyLock<Arc<Field>> = LazyLock::new(|| {
+    Arc::new(Field::new(JournalEntry::REASON_COLUMN_NAME, DataType::Utf8, true))
+});
+static DURATION_FIELD: LazyLock<Arc<Field>> = LazyLock::new(|| {
+    Arc::new(Field::new(JournalEntry::DURATION_COLUMN_NAME, DataType::UInt64, false))
+});
+static RECORDED_FIELD: LazyLock<Arc<Field>> = LazyLock::new(|| {
+    Arc::new(Field::new(JournalEntry::RECORDED_COLUMN_NAME, DataType::Timestamp(TimeUnit::Millisecond, Some("UTC".into())), false))
+});
+
+static JOURNAL_ENTRY_SCHEMA: LazyLock<Schema> = LazyLock::new(|| {
+    Schema::new(vec![
+        PATH_FIELD.clone(),
+        PROVIDER_FIELD.clone(),
+        ACTION_FIELD.clone(),
+        REASON_FIELD.clone(),
+        DURATION_FIELD.clone(),
+        RECORDED_FIELD.clone(),
+    ])
+});
+
+impl KeyedSequencedData<String> for JournalEntry {
+    fn get_key(&self) -> String {
+        journal_entry_key(&self.path, self.sequence_number)
+    }
+
+    fn get_sequence_num(&self) -> u64 {
+        self.sequence_number
+    }
+}
+
+pub struct JournalEntryRowBuilder {
+    path: StringBuilder,
+    provider: StringBuilder,
+    action: StringBuilder,
+    reason: StringBuilder,
+    duration: UInt64Builder,
+    recorded: TimestampMillisecondBuilder,
+}
+
+impl Default for JournalEntryRowBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl JournalEntryRowBuilder {
+    pub fn new() -> Self {
+        Self {
+            path: StringBuilder::new(),
+            provider: StringBuilder::new(),
+            action: StringBuilder::new(),
+            reason: StringBuilder::new(),
+            duration: UInt64Builder::new(),
+            recorded: TimestampMillisecondBuilder::new().with_timezone("UTC"),
+        }
+    }
+}
+
+impl RowBuilder<JournalEntry> for JournalEntryRowBuilder {
+    fn append(&mut self, row: JournalEntry) {
+        self.path.append_value(row.path.as_str());
+        self.provider.append_option(row.provider);
+        self.action.append_value(row.action.as_str());
+        self.reason.append_option(row.reason);
+        self.duration.append_value(row.duration.as_millis() as u64);
+        self.recorded.append_value(row.recorded.timestamp_millis());
+    }
+
+    fn finish(mut self) -> Vec<(Arc<Field>, ArrayRef)> {
+        vec![
+            (PATH_FIELD.clone(), Arc::new(self.path.finish())),
+            (PROVIDER_FIELD.clone(), Arc::new(self.provider.finish())),
+            (ACTION_FIELD.clone(), Arc::new(self.action.finish())),
+            (REASON_FIELD.clone(), Arc::new(self.reason.finish())),
+            (DURATION_FIELD.clone(), Arc::new(self.duration.finish())),
+            (RECORDED_FIELD.clone(), Arc::new(self.recorded.finish())),
+        ]
+    }
+}
+
+impl ArrowData for JournalEntry {
+    type RowBuilder = JournalEntryRowBuilder;
+
+    fn schema() -> Schema {
+        JOURNAL_ENTRY_SCHEMA.clone()
+    }
+
+    fn row_builder() -> Self::RowBuilder {
+        JournalEntryRowBuilder::new()
+    }
+
+    fn batch_to_iter(record_batch: RecordBatch) -> impl IntoIterator<Item = Self> {
+        let num_rows = record_batch.num_rows();
+
+        (0..num_rows).map(move |i| {
+            let path = record_batch.column_by_name(JournalEntry::PATH_COLUMN_NAME)
+                .expect("path column not found")
+                .as_string::<i32>()
+                .value(i);
+            let provider_array = record_batch.column_by_name(JournalEntry::PROVIDER_COLUMN_NAME)
+                .expect("provider column not found")
+                .as_string::<i32>();
+            let provider = (!provider_array.is_null(i)).then(|| provider_array.value(i).to_string());
+            let action = record_batch.column_by_name(JournalEntry::ACTION_COLUMN_NAME)
+                .expect("action column not found")
+                .as_string::<i32>()
+                .value(i);
+            let reason_array = record_batch.column_by_name(JournalEntry::REASON_COLUMN_NAME)
+                .expect("reason column not found")
+                .as_string::<i32>();
+            let reason = (!reason_array.is_null(i)).then(|| reason_array.value(i).to_string());
+            let duration = record_batch.column_by_name(JournalEntry::DURATION_COLUMN_NAME)
+                .expect("duration column not found")
+                .as_primitive::<UInt64Type>()
+                .value(i);
+            let recorded = record_batch.column_by_name(JournalEntry::RECORDED_COLUMN_NAME)
+                .expect("recorded column not found")
+                .as_primitive::<TimestampMillisecondType>()
+                .value(i);
+            // The sequence number is stored by the store itself, so it is missing from batches not read from one
+            let sequence_number = record_batch.column_by_name(SEQUENCE_NUMBER_COLUMN)
+                .map(|column| column.as_primitive::<UInt64Type>().value(i))
+                .unwrap_or(recorded as u64);
+
+            JournalEntry {
+                path: Utf8PathBuf::from(path),
+                provider,
+                action: JournalAction::parse(action).expect("action column has an unknown action"),
+                reason,
+                duration: Duration::from_millis(duration),
+                recorded: Utc.timestamp_millis_opt(recorded).unwrap(),
+                sequence_number,
+            }
+        })
+    }
+
+    fn attribute_to_column_name(attr: &str) -> &'static str {
+        match attr {
+            JournalEntry::PATH_ATTR => JournalEntry::PATH_COLUMN_NAME,
+            JournalEntry::PROVIDER_ATTR => JournalEntry::PROVIDER_COLUMN_NAME,
+            JournalEntry::ACTION_ATTR => JournalEntry::ACTION_COLUMN_NAME,
+            JournalEntry::REASON_ATTR => JournalEntry::REASON_COLUMN_NAME,
+            JournalEntry::DURATION_ATTR => JournalEntry::DURATION_COLUMN_NAME,
+            JournalEntry::RECORDED_ATTR => JournalEntry::RECORDED_COLUMN_NAME,
+            _ => panic!("Unknown JournalEntry attribute: {}", attr),
+        }
+    }
+}
+
+impl Filterable for JournalEntry {
+    fn filterable_attributes() -> Vec<&'static str> {
+        [
+            JournalEntry::PATH_ATTR,
+        ].to_vec()
+    }
+}
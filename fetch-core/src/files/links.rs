@@ -0,0 +1,181 @@
+use std::{collections::{HashMap, HashSet}, fs};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+
+use crate::{app_config, files::{os_path::{from_os_path, to_os_path}, tags::{normalize_tag, UserTags}}};
+
+/// How symbolic links are handled when looking for files to index, configured by the symlink_policy setting in
+/// the daemon.toml settings file
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SymlinkPolicy {
+    /// Links are followed, and their targets are indexed under their canonical paths. Each link is recorded as an
+    /// alias of its target.
+    #[default]
+    Follow,
+    /// Links are ignored
+    Skip,
+    /// Links to files are indexed under the path of the link itself, as if they were regular files. Links to
+    /// directories are not followed.
+    IndexAsLink,
+}
+
+impl SymlinkPolicy {
+    /// Loads the symlink policy from the daemon.toml settings file.
+    pub fn from_settings() -> Result<SymlinkPolicy, anyhow::Error> {
+        let settings = app_config::get_daemon_config()?;
+        match settings.get::<SymlinkPolicy>("symlink_policy") {
+            Ok(policy) => Ok(policy),
+            Err(config::ConfigError::NotFound(_)) => Ok(SymlinkPolicy::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// What should be done with a path found while looking for files to index
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ResolvedPath {
+    /// Index the file under the given path
+    File(Utf8PathBuf),
+    /// Look for files in the directory at the given path
+    Directory(Utf8PathBuf),
+    /// Leave the path out, eg. because it is a link that should be skipped, or a file or directory that has been
+    /// seen before through another path
+    Skip { reason: &'static str },
+}
+
+/// Resolves the paths found while looking for files to index according to a SymlinkPolicy. Files and directories
+/// are keyed by their canonical paths, so the same file reached through several links, or the same inode reached
+/// through several hard links, is only indexed once, and directory cycles created by links are only explored once.
+/// Every other path a file is reached through is recorded as an alias, see record_aliases.
+#[derive(Debug, Default)]
+pub struct LinkResolver {
+    policy: SymlinkPolicy,
+    /// Identities of the files and directories resolved so far, see file_identity
+    seen: HashSet<FileIdentity>,
+    /// Paths resolved so far by their identity, to find what an alias is an alias of
+    keys: HashMap<FileIdentity, Utf8PathBuf>,
+    /// Other paths each indexed file was reached through, keyed by the path it is indexed under
+    aliases: HashMap<Utf8PathBuf, Vec<Utf8PathBuf>>,
+}
+
+impl LinkResolver {
+    pub fn using(policy: SymlinkPolicy) -> LinkResolver {
+        LinkResolver { policy, ..LinkResolver::default() }
+    }
+
+    /// Decides what to do with a path. Paths that do not exist are resolved to themselves as files, so that they
+    /// are cleared from the index.
+    pub fn resolve(&mut self, path: &Utf8Path) -> ResolvedPath {
+        let os_path = to_os_path(path);
+        let link_metadata = match fs::symlink_metadata(&os_path) {
+            Ok(metadata) => metadata,
+            Err(_) => return ResolvedPath::File(path.to_owned()),
+        };
+        let is_link = link_metadata.file_type().is_symlink();
+
+        let key = match (is_link, self.policy) {
+            (true, SymlinkPolicy::Skip) => return ResolvedPath::Skip { reason: "Symbolic links are skipped" },
+            (true, SymlinkPolicy::IndexAsLink) => {
+                return match fs::metadata(&os_path) {
+                    Ok(metadata) if metadata.is_file() => ResolvedPath::File(path.to_owned()),
+                    Ok(_) => ResolvedPath::Skip { reason: "Symbolic links to directories are not followed" },
+                    Err(_) => ResolvedPath::Skip { reason: "Symbolic link is broken" },
+                };
+            },
+            _ => match fs::canonicalize(&os_path) {
+                Ok(canonical) => from_os_path(&canonical),
+                Err(e) => {
+                    warn!("LinkResolver: Could not canonicalize {}, skipping: {:?}", path, e);
+                    return ResolvedPath::Skip { reason: "Path could not be canonicalized" };
+                },
+            },
+        };
+
+        let metadata = match fs::metadata(to_os_path(&key)) {
+            Ok(metadata) => metadata,
+            Err(_) => return ResolvedPath::Skip { reason: "Symbolic link is broken" },
+        };
+        let identity = file_identity(&key, &metadata);
+        if !self.seen.insert(identity.clone()) {
+            if metadata.is_dir() {
+                debug!("LinkResolver: Directory {} was already explored as {:?}, skipping", path,
+                    self.keys.get(&identity));
+                return ResolvedPath::Skip { reason: "Directory was already explored through another path" };
+            }
+            if let Some(indexed_as) = self.keys.get(&identity) {
+                if indexed_as != path {
+                    self.aliases.entry(indexed_as.clone()).or_default().push(path.to_owned());
+                }
+            }
+            return ResolvedPath::Skip { reason: "File was already found through another path" };
+        }
+        self.keys.insert(identity, key.clone());
+
+        if is_link && metadata.is_file() {
+            self.aliases.entry(key.clone()).or_default().push(path.to_owned());
+        }
+        if metadata.is_dir() {
+            ResolvedPath::Directory(key)
+        } else if metadata.is_file() {
+            ResolvedPath::File(key)
+        } else {
+            ResolvedPath::Skip { reason: "Path is neither a file nor a directory" }
+        }
+    }
+
+    /// Other paths each file was reached through, keyed by the path it is indexed under
+    pub fn aliases(&self) -> &HashMap<Utf8PathBuf, Vec<Utf8PathBuf>> {
+        &self.aliases
+    }
+
+    /// Records the names of the aliases of each file as user tags of the file, so files can also be found by the
+    /// names of the links pointing to them. Names that cannot be tags are left out.
+    pub fn record_aliases(&self) -> Result<(), anyhow::Error> {
+        if self.aliases.is_empty() {
+            return Ok(());
+        }
+
+        let mut user_tags = UserTags::load()?;
+        let mut changed = false;
+        for (file, aliases) in &self.aliases {
+            for alias in aliases {
+                let name = alias.file_name().unwrap_or_default().split_whitespace().collect::<Vec<_>>().join("-");
+                if let Some(tag) = normalize_tag(&name) {
+                    changed |= user_tags.add(file, &tag);
+                }
+            }
+        }
+        if changed {
+            user_tags.save()?;
+        }
+        Ok(())
+    }
+}
+
+// Private functions and variables
+
+/// Identifies a file regardless of the path it is reached through. Hard links share an inode, so on unix files are
+/// identified by their device and inode. Elsewhere, files are identified by their canonical path, which does not
+/// tell hard links apart.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum FileIdentity {
+    #[allow(dead_code)]
+    Inode { device: u64, inode: u64 },
+    #[allow(dead_code)]
+    Path(Utf8PathBuf),
+}
+
+#[cfg(unix)]
+fn file_identity(_canonical: &Utf8Path, metadata: &fs::Metadata) -> FileIdentity {
+    use std::os::unix::fs::MetadataExt;
+
+    FileIdentity::Inode { device: metadata.dev(), inode: metadata.ino() }
+}
+
+#[cfg(not(unix))]
+fn file_identity(canonical: &Utf8Path, _metadata: &fs::Metadata) -> FileIdentity {
+    FileIdentity::Path(canonical.to_owned())
+}
@@ -0,0 +1,92 @@
+use std::{fs::{self, File, OpenOptions, TryLockError}, io, process, time::{Duration, Instant}};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use tracing::debug;
+
+/// Errors that can occur while taking the lock on an index data directory.
+#[derive(thiserror::Error, Debug)]
+pub enum IndexLockError {
+    /// Another process holds the lock, eg. the GUI is indexing while `fetch index` is run.
+    #[error("Index is busy, it is being written to by {holder}")]
+    Busy { holder: String },
+    #[error("Error accessing index lock file {path}")]
+    Io { path: Utf8PathBuf, #[source] source: io::Error },
+}
+
+/// Advisory lock on an index data directory, held by a process for as long as it writes to the index. LanceDB does
+/// not coordinate writers across processes, so concurrent merge inserts and optimizations from eg. the GUI and the
+/// CLI can race each other. Every process that indexes takes this lock first, and processes that cannot get it
+/// can queue their files for the file daemon instead, see IndexQueue.
+///
+/// The lock is released when dropped, or when the process holding it exits.
+#[derive(Debug)]
+pub struct IndexLock {
+    _file: File,
+    holder_file: Utf8PathBuf,
+}
+
+impl IndexLock {
+    /// Takes the lock on the data directory without waiting. The holder describes the process taking the lock, eg.
+    /// "fetch index", and is shown to processes that find the lock busy.
+    pub fn try_acquire(data_dir: &Utf8Path, holder: &str) -> Result<IndexLock, IndexLockError> {
+        fs::create_dir_all(data_dir).map_err(|e| IndexLockError::Io { path: data_dir.to_owned(), source: e })?;
+
+        let lock_file = data_dir.join(LOCK_FILE_NAME);
+        let file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(&lock_file)
+            .map_err(|e| IndexLockError::Io { path: lock_file.clone(), source: e })?;
+
+        // The holder is written to a separate file, as on Windows the locked file cannot be read by other processes
+        let holder_file = data_dir.join(HOLDER_FILE_NAME);
+        match file.try_lock() {
+            Ok(()) => (),
+            Err(TryLockError::WouldBlock) => {
+                let holder = fs::read_to_string(&holder_file).unwrap_or_else(|_| "another process".to_owned());
+                return Err(IndexLockError::Busy { holder });
+            },
+            Err(TryLockError::Error(e)) => return Err(IndexLockError::Io { path: lock_file, source: e }),
+        }
+
+        fs::write(&holder_file, format!("{} (pid {})", holder, process::id()))
+            .map_err(|e| IndexLockError::Io { path: holder_file.clone(), source: e })?;
+        debug!("IndexLock: {} took the lock on {}", holder, data_dir);
+        Ok(IndexLock { _file: file, holder_file })
+    }
+
+    /// Takes the lock on the data directory, waiting for up to the timeout for the process holding it to release
+    /// it. With no timeout, waits for as long as it takes.
+    pub async fn acquire(data_dir: &Utf8Path, holder: &str, timeout: Option<Duration>) -> Result<IndexLock, IndexLockError> {
+        let started = Instant::now();
+        loop {
+            match IndexLock::try_acquire(data_dir, holder) {
+                Err(IndexLockError::Busy { holder: busy_holder }) => {
+                    if timeout.is_some_and(|timeout| started.elapsed() >= timeout) {
+                        return Err(IndexLockError::Busy { holder: busy_holder });
+                    }
+                    tokio::time::sleep(LOCK_POLL_INTERVAL).await;
+                },
+                res => return res,
+            }
+        }
+    }
+
+    /// Whether the file is one of the files of the lock in a data directory, which are not part of the index
+    /// itself, eg. when copying the index elsewhere
+    pub fn is_lock_file(file_name: &str) -> bool {
+        file_name == LOCK_FILE_NAME || file_name == HOLDER_FILE_NAME
+    }
+}
+
+impl Drop for IndexLock {
+    fn drop(&mut self) {
+        // The lock itself is released when the file is closed
+        let _ = fs::remove_file(&self.holder_file);
+    }
+}
+
+// Private functions and variables
+
+const LOCK_FILE_NAME: &str = "index.lock";
+const HOLDER_FILE_NAME: &str = "index.lock.holder";
+
+/// How often a process waiting for the lock checks whether it has been released
+const LOCK_POLL_INTERVAL: Duration = Duration::from_millis(500);
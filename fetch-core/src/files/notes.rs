@@ -0,0 +1,74 @@
+use std::{collections::{BTreeSet, HashMap}, fs};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use serde::{Deserialize, Serialize};
+
+use crate::app_config;
+
+/// Links between notes in markdown vaults, recorded from the [[wikilinks]] of each note when it is indexed (see
+/// MarkdownIndexProvider). Stored in the application data directory, so that the notes linking to a note can be
+/// found without reading every note in its vault.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct NoteLinks {
+    /// Notes each note links to, keyed by the note linking to them
+    links: HashMap<Utf8PathBuf, BTreeSet<Utf8PathBuf>>,
+}
+
+impl NoteLinks {
+    /// Loads note links from the application data directory. Returns no links if none have been recorded yet.
+    pub fn load() -> Result<NoteLinks, anyhow::Error> {
+        let note_links_file = app_config::get_note_links_file_path();
+        if !fs::exists(&note_links_file)? {
+            return Ok(NoteLinks::default());
+        }
+
+        let contents = fs::read_to_string(&note_links_file)?;
+        Ok(serde_json::from_str(&contents)?)
+    }
+
+    /// Saves note links to the application data directory.
+    pub fn save(&self) -> Result<(), anyhow::Error> {
+        let note_links_file = app_config::get_note_links_file_path();
+        if let Some(parent) = note_links_file.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        fs::write(&note_links_file, serde_json::to_string(self)?)?;
+        Ok(())
+    }
+
+    /// Replaces the notes a note links to. Returns false if they have not changed.
+    pub fn set(&mut self, note: &Utf8Path, links: BTreeSet<Utf8PathBuf>) -> bool {
+        if links.is_empty() {
+            return self.remove(note);
+        }
+        self.links.insert(note.to_owned(), links.clone()).is_none_or(|previous| previous != links)
+    }
+
+    /// Forgets the links of a note, eg. after it is cleared from the index. Links to the note from other notes are
+    /// kept, as they come back to life if the note is indexed again. Returns false if the note had no links.
+    pub fn remove(&mut self, note: &Utf8Path) -> bool {
+        self.links.remove(note).is_some()
+    }
+
+    /// Notes the note links to
+    pub fn outgoing(&self, note: &Utf8Path) -> Vec<Utf8PathBuf> {
+        self.links.get(note).map(|links| links.iter().cloned().collect()).unwrap_or_default()
+    }
+
+    /// Notes linking to the note
+    pub fn backlinks(&self, note: &Utf8Path) -> Vec<Utf8PathBuf> {
+        let mut backlinks: Vec<_> = self.links.iter()
+            .filter(|(_, links)| links.contains(note))
+            .map(|(from, _)| from.clone())
+            .collect();
+        backlinks.sort();
+        backlinks
+    }
+
+    /// Notes linked with the note in either direction, without repeats
+    pub fn neighbors(&self, note: &Utf8Path) -> Vec<Utf8PathBuf> {
+        let neighbors: BTreeSet<_> = self.outgoing(note).into_iter().chain(self.backlinks(note)).collect();
+        neighbors.into_iter().filter(|n| n != note).collect()
+    }
+}
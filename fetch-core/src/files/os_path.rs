@@ -0,0 +1,212 @@
+use std::{collections::BTreeMap, fs, path::{Path, PathBuf}, sync::{LazyLock, RwLock}};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+
+use crate::app_config;
+
+/// Converts an OS path into the UTF-8 path Fetch indexes and refers to the file by. Paths that are valid UTF-8 are
+/// used as they are, apart from Windows extended-length prefixes (\\?\), which are removed. Paths that are not
+/// valid UTF-8 are given a display form, where each byte (or on Windows, each unpaired surrogate) that is not valid
+/// UTF-8 is written as %XX (or %uXXXX), and the original path is recorded in RawPaths so to_os_path can map the
+/// display form back to it losslessly.
+pub fn from_os_path(path: &Path) -> Utf8PathBuf {
+    if let Some(utf8_path) = path.to_str() {
+        return Utf8PathBuf::from(strip_verbatim_prefix(utf8_path));
+    }
+
+    let raw = RawOsPath::from_path(path);
+    let display = Utf8PathBuf::from(raw.display());
+    debug!("Path {:?} is not valid UTF-8, using {} for it", path, display);
+    record_raw_path(&display, raw);
+    display
+}
+
+/// Converts a path Fetch refers to a file by back into the OS path to open the file at. Display forms of paths that
+/// are not valid UTF-8 (see from_os_path) are mapped back to the original path. On Windows, absolute paths longer
+/// than MAX_PATH are given the extended-length prefix (\\?\), so they can be opened.
+pub fn to_os_path(path: &Utf8Path) -> PathBuf {
+    let raw = RAW_PATHS.read().expect("Raw paths lock should not be poisoned").paths.get(path).cloned();
+    let os_path = match raw {
+        Some(raw) => raw.to_path(),
+        None => path.as_std_path().to_owned(),
+    };
+    with_long_path_prefix(os_path)
+}
+
+/// The original OS paths of the files whose paths are not valid UTF-8, keyed by the display form Fetch refers to
+/// them by. Stored in the application data directory.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RawPaths {
+    paths: BTreeMap<Utf8PathBuf, RawOsPath>,
+}
+
+impl RawPaths {
+    /// Loads the raw paths from the application data directory. Returns no paths if none have been recorded yet.
+    pub fn load() -> Result<RawPaths, anyhow::Error> {
+        let raw_paths_file = app_config::get_raw_paths_file_path();
+        if !fs::exists(&raw_paths_file)? {
+            return Ok(RawPaths::default());
+        }
+
+        let contents = fs::read_to_string(&raw_paths_file)?;
+        Ok(serde_json::from_str(&contents)?)
+    }
+
+    /// Saves the raw paths to the application data directory.
+    pub fn save(&self) -> Result<(), anyhow::Error> {
+        let raw_paths_file = app_config::get_raw_paths_file_path();
+        if let Some(parent) = raw_paths_file.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        fs::write(&raw_paths_file, serde_json::to_string(self)?)?;
+        Ok(())
+    }
+}
+
+/// A path as the OS stores it: bytes on unix, UTF-16 code units on Windows
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RawOsPath {
+    Bytes(Vec<u8>),
+    Wide(Vec<u16>),
+}
+
+impl RawOsPath {
+    #[cfg(unix)]
+    fn from_path(path: &Path) -> RawOsPath {
+        use std::os::unix::ffi::OsStrExt;
+
+        RawOsPath::Bytes(path.as_os_str().as_bytes().to_vec())
+    }
+
+    #[cfg(windows)]
+    fn from_path(path: &Path) -> RawOsPath {
+        use std::os::windows::ffi::OsStrExt;
+
+        RawOsPath::Wide(path.as_os_str().encode_wide().collect())
+    }
+
+    #[cfg(not(any(unix, windows)))]
+    fn from_path(path: &Path) -> RawOsPath {
+        RawOsPath::Bytes(path.to_string_lossy().into_owned().into_bytes())
+    }
+
+    #[cfg(unix)]
+    fn to_path(&self) -> PathBuf {
+        use std::{ffi::OsString, os::unix::ffi::OsStringExt};
+
+        match self {
+            RawOsPath::Bytes(bytes) => PathBuf::from(OsString::from_vec(bytes.clone())),
+            RawOsPath::Wide(_) => PathBuf::from(self.display()),
+        }
+    }
+
+    #[cfg(windows)]
+    fn to_path(&self) -> PathBuf {
+        use std::{ffi::OsString, os::windows::ffi::OsStringExt};
+
+        match self {
+            RawOsPath::Wide(wide) => PathBuf::from(OsString::from_wide(wide)),
+            RawOsPath::Bytes(_) => PathBuf::from(self.display()),
+        }
+    }
+
+    #[cfg(not(any(unix, windows)))]
+    fn to_path(&self) -> PathBuf {
+        PathBuf::from(self.display())
+    }
+
+    /// The path with everything that is not valid UTF-8 escaped, see from_os_path
+    fn display(&self) -> String {
+        let mut display = String::new();
+        match self {
+            RawOsPath::Bytes(bytes) => {
+                for chunk in bytes.utf8_chunks() {
+                    display.push_str(chunk.valid());
+                    for byte in chunk.invalid() {
+                        display.push_str(&format!("%{:02X}", byte));
+                    }
+                }
+            },
+            RawOsPath::Wide(wide) => {
+                for unit in char::decode_utf16(wide.iter().copied()) {
+                    match unit {
+                        Ok(c) => display.push(c),
+                        Err(e) => display.push_str(&format!("%u{:04X}", e.unpaired_surrogate())),
+                    }
+                }
+            },
+        }
+        display
+    }
+}
+
+// Private functions and variables
+
+/// Longest path Windows APIs accept without the extended-length prefix
+#[cfg(windows)]
+const MAX_PATH: usize = 260;
+
+/// Loaded from disk the first time a path is converted
+static RAW_PATHS: LazyLock<RwLock<RawPaths>> = LazyLock::new(|| {
+    RwLock::new(RawPaths::load().unwrap_or_else(|e| {
+        warn!("Could not load raw paths, paths that are not valid UTF-8 may not be found: {:?}", e);
+        RawPaths::default()
+    }))
+});
+
+fn record_raw_path(display: &Utf8Path, raw: RawOsPath) {
+    let mut raw_paths = RAW_PATHS.write().expect("Raw paths lock should not be poisoned");
+    if raw_paths.paths.get(display) == Some(&raw) {
+        return;
+    }
+    raw_paths.paths.insert(display.to_owned(), raw);
+    if let Err(e) = raw_paths.save() {
+        warn!("Could not save raw path of {}: {:?}", display, e);
+    }
+}
+
+/// Removes the \\?\ prefix Windows adds to extended-length paths, eg. ones returned by canonicalize, so the same file
+/// is always referred to by the same path
+fn strip_verbatim_prefix(path: &str) -> String {
+    if let Some(unc) = path.strip_prefix(r"\\?\UNC\") {
+        format!(r"\\{}", unc)
+    } else if let Some(local) = path.strip_prefix(r"\\?\") {
+        local.to_owned()
+    } else {
+        path.to_owned()
+    }
+}
+
+#[cfg(windows)]
+fn with_long_path_prefix(path: PathBuf) -> PathBuf {
+    let path_str = path.as_os_str();
+    if path_str.len() < MAX_PATH || !path.is_absolute() {
+        return path;
+    }
+
+    let path_string = path.to_string_lossy();
+    if path_string.starts_with(r"\\?\") {
+        return path;
+    }
+    let mut prefixed = std::ffi::OsString::from(if path_string.starts_with(r"\\") { r"\\?\UNC\" } else { r"\\?\" });
+    match path_string.strip_prefix(r"\\") {
+        // UNC paths keep the part after their leading slashes
+        Some(_) => {
+            use std::os::windows::ffi::{OsStrExt, OsStringExt};
+
+            let wide: Vec<u16> = path_str.encode_wide().skip(2).collect();
+            prefixed.push(std::ffi::OsString::from_wide(&wide));
+        },
+        None => prefixed.push(path_str),
+    }
+    PathBuf::from(prefixed)
+}
+
+#[cfg(not(windows))]
+fn with_long_path_prefix(path: PathBuf) -> PathBuf {
+    path
+}
@@ -5,10 +5,16 @@ use chrono::{DateTime, TimeDelta, Utc};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+use crate::files::query::MatchSource;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AggregateFileScore {
     pub max_score: f32,
     pub num_chunks: u32,
+    /// The providers and channels that found chunks of the file, with the best score of each. Cursors saved before
+    /// sources were kept have none.
+    #[serde(default)]
+    pub sources: Vec<MatchSource>,
 }
 
 impl AggregateFileScore {
@@ -17,6 +23,21 @@ impl AggregateFileScore {
         self.num_chunks += 1;
     }
 
+    /// Keeps the source, or raises the score of the same provider and channel if it scored less
+    pub fn aggregate_source(&mut self, source: MatchSource) {
+        match self.sources.iter_mut().find(|s| s.provider == source.provider && s.channel == source.channel) {
+            Some(existing) => existing.score = existing.score.max(source.score),
+            None => self.sources.push(source),
+        }
+    }
+
+    /// The sources, highest scoring first
+    pub fn ranked_sources(&self) -> Vec<MatchSource> {
+        let mut sources = self.sources.clone();
+        sources.sort_by(|l, r| r.score.total_cmp(&l.score).then_with(|| l.provider.cmp(&r.provider)));
+        sources
+    }
+
     pub fn chunk_multiplier_score(&self) -> f32 {
         self.max_score
         // TODO: tune this chunk boosted score better.
@@ -34,7 +55,12 @@ impl AsRef<AggregateFileScore> for AggregateFileScore {
 pub struct QueryCursor {
     pub id: String,
     pub aggregate_scores: HashMap<Utf8PathBuf, AggregateFileScore>,
+    /// Number of chunks queried so far, across every provider
     pub curr_offset: u32,
+    /// Number of chunks taken so far from each provider's stream of chunks, by provider (and sub-query, see
+    /// query::MultiQuery). Cursors saved before chunks were streamed have none, and start every stream over.
+    #[serde(default)]
+    pub stream_offsets: HashMap<String, u32>,
     pub ttl: DateTime<Utc>,
 }
 
@@ -44,6 +70,7 @@ impl QueryCursor {
             id: Uuid::new_v4().to_string(),
             aggregate_scores: HashMap::new(),
             curr_offset: 0,
+            stream_offsets: HashMap::new(),
             ttl: Utc::now(),
         };
         cursor.touch_ttl();
@@ -63,11 +90,19 @@ impl QueryCursor {
             },
             None => {
                 self.aggregate_scores.insert(file_ref.to_owned(),
-                    AggregateFileScore { max_score: score, num_chunks: 1 });
+                    AggregateFileScore { max_score: score, num_chunks: 1, sources: vec![] });
             },
         }
         self
     }
+
+    /// Adds the source to a file already aggregated into the cursor. Sources of files that are not are ignored.
+    pub fn aggregate_source(&mut self, file_ref: &Utf8Path, source: MatchSource) -> &mut Self {
+        if let Some(ags) = self.aggregate_scores.get_mut(file_ref) {
+            ags.aggregate_source(source);
+        }
+        self
+    }
 }
 
 pub use integrations::*;
@@ -3,7 +3,7 @@ use std::sync::{Arc, LazyLock};
 
 use arrow::array::{AsArray, StringBuilder, TimestampMillisecondBuilder, UInt32Builder};
 use arrow::datatypes::{TimestampMillisecondType, UInt32Type};
-use arrow_array::{ArrayRef, RecordBatch};
+use arrow_array::{Array, ArrayRef, RecordBatch};
 use arrow_schema::{DataType, Field, Schema, TimeUnit};
 use camino::Utf8PathBuf;
 use chrono::{TimeZone, Utc};
@@ -18,11 +18,13 @@ use crate::store::{Filterable, KeyedSequencedData};
 pub const CURSOR_ID_ATTR: &str = "id";
 pub const AGGREGATE_SCORES_ATTR: &str = "aggregate_scores";
 pub const CURR_OFFSET_ATTR: &str = "curr_offset";
+pub const STREAM_OFFSETS_ATTR: &str = "stream_offsets";
 pub const TTL_ATTR: &str = "ttl";
 
 const CURSOR_ID_COLUMN_NAME: &str = "cursor_id";
 const AGGREGATE_SCORES_COLUMN_NAME: &str = "aggregate_scores";
 const CURR_OFFSET_COLUMN_NAME: &str = "curr_offset";
+const STREAM_OFFSETS_COLUMN_NAME: &str = "stream_offsets";
 const TTL_COLUMN_NAME: &str = "ttl";
 
 // ===========================
@@ -52,6 +54,14 @@ static CURR_OFFSET_FIELD: LazyLock<Arc<Field>> = LazyLock::new(|| {
     ))
 });
 
+static STREAM_OFFSETS_FIELD: LazyLock<Arc<Field>> = LazyLock::new(|| {
+    Arc::new(Field::new(
+        STREAM_OFFSETS_COLUMN_NAME,
+        DataType::Utf8, // JSON serialized as string
+        true,
+    ))
+});
+
 static TTL_FIELD: LazyLock<Arc<Field>> = LazyLock::new(|| {
     Arc::new(Field::new(
         TTL_COLUMN_NAME,
@@ -65,6 +75,7 @@ static CURSOR_SCHEMA: LazyLock<Schema> = LazyLock::new(|| {
         Arc::clone(&CURSOR_ID_FIELD),
         Arc::clone(&AGGREGATE_SCORES_FIELD),
         Arc::clone(&CURR_OFFSET_FIELD),
+        Arc::clone(&STREAM_OFFSETS_FIELD),
         Arc::clone(&TTL_FIELD),
     ])
 });
@@ -90,6 +101,7 @@ pub struct CursorRowBuilder {
     cursor_id: StringBuilder,
     aggregate_scores: StringBuilder,
     curr_offset: UInt32Builder,
+    stream_offsets: StringBuilder,
     ttl: TimestampMillisecondBuilder,
 }
 
@@ -99,6 +111,7 @@ impl CursorRowBuilder {
             cursor_id: StringBuilder::new(),
             aggregate_scores: StringBuilder::new(),
             curr_offset: UInt32Builder::new(),
+            stream_offsets: StringBuilder::new(),
             ttl: TimestampMillisecondBuilder::new(),
         }
     }
@@ -114,6 +127,12 @@ impl RowBuilder<QueryCursor> for CursorRowBuilder {
         self.aggregate_scores.append_value(&scores_json);
 
         self.curr_offset.append_value(row.curr_offset);
+
+        // Serialize stream_offsets as JSON
+        let offsets_json = serde_json::to_string(&row.stream_offsets)
+            .unwrap_or_else(|_| "{}".to_string());
+        self.stream_offsets.append_value(&offsets_json);
+
         self.ttl.append_value(row.ttl.timestamp_millis());
     }
 
@@ -128,6 +147,10 @@ impl RowBuilder<QueryCursor> for CursorRowBuilder {
                 Arc::clone(&CURR_OFFSET_FIELD),
                 Arc::new(self.curr_offset.finish()),
             ),
+            (
+                Arc::clone(&STREAM_OFFSETS_FIELD),
+                Arc::new(self.stream_offsets.finish()),
+            ),
             (Arc::clone(&TTL_FIELD), Arc::new(self.ttl.finish())),
         ]
     }
@@ -152,6 +175,7 @@ impl ArrowData for QueryCursor {
             CURSOR_ID_ATTR => CURSOR_ID_COLUMN_NAME,
             AGGREGATE_SCORES_ATTR => AGGREGATE_SCORES_COLUMN_NAME,
             CURR_OFFSET_ATTR => CURR_OFFSET_COLUMN_NAME,
+            STREAM_OFFSETS_ATTR => STREAM_OFFSETS_COLUMN_NAME,
             TTL_ATTR => TTL_COLUMN_NAME,
             _ => panic!("Unknown Cursor attribute: {}", attr),
         }
@@ -185,6 +209,18 @@ impl ArrowData for QueryCursor {
                 .as_primitive::<UInt32Type>()
                 .value(i);
 
+            // Cursors saved before chunks were streamed have no stream offsets
+            let stream_offsets_array = record_batch
+                .column_by_name(STREAM_OFFSETS_COLUMN_NAME)
+                .expect("stream_offsets column not found")
+                .as_string::<i32>();
+            let stream_offsets: HashMap<String, u32> = if stream_offsets_array.is_null(i) {
+                HashMap::new()
+            } else {
+                serde_json::from_str(stream_offsets_array.value(i))
+                    .unwrap_or_else(|_| HashMap::new())
+            };
+
             let ttl_value = record_batch
                 .column_by_name(TTL_COLUMN_NAME)
                 .expect("ttl column not found")
@@ -200,6 +236,7 @@ impl ArrowData for QueryCursor {
                 id: cursor_id,
                 aggregate_scores: scores,
                 curr_offset,
+                stream_offsets,
                 ttl: Utc.timestamp_millis_opt(ttl_value).unwrap(),
             }
         })
@@ -0,0 +1,57 @@
+use std::fs;
+
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::app_config;
+
+/// Privacy mode suspends background indexing and stops searches and opened files from being recorded in history,
+/// until it is turned off again. It is stored in the application data directory so every Fetch process, eg. the
+/// file daemon, sees it.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PrivacyMode {
+    enabled: bool,
+}
+
+impl PrivacyMode {
+    /// Loads privacy mode from the application data directory. Privacy mode is off if it has never been turned on.
+    pub fn load() -> Result<PrivacyMode, anyhow::Error> {
+        let privacy_mode_file = app_config::get_privacy_mode_file_path();
+        if !fs::exists(&privacy_mode_file)? {
+            return Ok(PrivacyMode::default());
+        }
+
+        let contents = fs::read_to_string(&privacy_mode_file)?;
+        Ok(serde_json::from_str(&contents)?)
+    }
+
+    /// Saves privacy mode to the application data directory.
+    pub fn save(&self) -> Result<(), anyhow::Error> {
+        let privacy_mode_file = app_config::get_privacy_mode_file_path();
+        if let Some(parent) = privacy_mode_file.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        fs::write(&privacy_mode_file, serde_json::to_string(self)?)?;
+        Ok(())
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) -> &mut PrivacyMode {
+        self.enabled = enabled;
+        self
+    }
+
+    /// Whether privacy mode is currently on. If it cannot be loaded, it is assumed to be off.
+    pub fn enabled() -> bool {
+        PrivacyMode::load()
+            .map(|privacy_mode| privacy_mode.is_enabled())
+            .unwrap_or_else(|e| {
+                warn!("Could not load privacy mode, assuming it is off: {:?}", e);
+                false
+            })
+    }
+}
@@ -1,12 +1,17 @@
-use std::{cmp::Ordering, collections::HashMap, future::Future};
+use std::{cmp::Ordering, collections::HashMap, future::Future, iter, sync::Arc};
 
 use camino::{Utf8Path, Utf8PathBuf};
 use chrono::Utc;
-use log::{debug, warn};
+use futures::future;
+use tracing::{debug, instrument, warn};
 
-use crate::{files::{ChunkingIndexProviderConcurrent, pagination::{AggregateFileScore, QueryCursor, TTL_ATTR}}, store::{ClearByFilter, Filter, FilterRelation, FilterValue, KeyedSequencedStore}};
+use crate::{files::{ChunkingIndexProviderConcurrent, pagination::{AggregateFileScore, QueryCursor, TTL_ATTR}}, index::provider::{ChunkQueryFilters, ChunkingIndexProvider, IndexProviderError}, store::{ClearByFilter, Filter, FilterRelation, FilterValue, KeyedSequencedStore, lancedb::LanceDBQueryBatch}};
 
 use super::FileQueryer;
+use cache::PageKey;
+use multi::fuse_ranked_lists;
+use planner::run_in_batch;
+use stream::{ChunkStream, StreamedChunk, merge_streams, stream_key};
 
 /// Describes an object that understands how to perform queries against indexed files.
 /// 
@@ -58,7 +63,7 @@ use super::FileQueryer;
 /// has reached the end of the list and should not query the cursor any further.
 pub trait QueryFiles {
     /// Query for files matching description provided, parsing through a default number of chunks
-    /// (currently 100) and aggregating them into the cursor. This API will only return new results
+    /// (20 unless configured otherwise, see FileQueryerBuilder::default_num_chunks) and aggregating them into the cursor. This API will only return new results
     /// not seen before in previous queries with the same cursor id. If a cursor id is not provided,
     /// then the query is a new query and a cursor id will be created and returned as a part of the
     /// result.
@@ -73,9 +78,10 @@ pub trait QueryFiles {
     /// been reached.
     fn query(&self, query_terms: &str, cursor_id: Option<&str>) -> impl Future<Output = Result<FileQueryingResult, FileQueryingError>> + Send;
     
-    /// Query for files matching description provided, parsing through a given number of chunks
-    /// per query and aggregating them into the cursor. This API will only return new results not
-    /// returned before in previous queries with the same cursor id.
+    /// Query for files matching description provided, taking the best num_chunks chunks across
+    /// providers per query, from where each provider left off with the cursor, and aggregating them
+    /// into the cursor. This API will only return new results not returned before in previous queries
+    /// with the same cursor id.
     /// 
     /// # Arguments
     /// * `query_terms` - The text description to search for
@@ -88,6 +94,36 @@ pub trait QueryFiles {
     /// If no cursor id is returned in the results, then that means the end of the list of chunks has
     /// been reached.
     fn query_n(&self, query_terms: &str, num_chunks: u32, cursor_id: Option<&str>) -> impl Future<Output = Result<FileQueryingResult, FileQueryingError>> + Send;
+
+    /// Refines a query the user is still typing, for search-as-you-type. If the query terms extend the previous
+    /// query terms, eg. "invoi" to "invoice 2023", the previous cursor is reused rather than starting over: files
+    /// found by the previous query stay in the list with their scores decayed, and are re-scored if the new query
+    /// finds them again, so the client updates its list in place instead of clearing it on every keystroke. Query
+    /// terms that only differ from the previous ones in whitespace return no changes without querying the index.
+    /// Otherwise, or if the cursor has expired, a new query is started and a new cursor id returned.
+    ///
+    /// # Arguments
+    /// * `query_terms` - The text description to query for
+    /// * `previous_query_terms` - The query terms the cursor was last queried with
+    /// * `num_chunks` - Number of chunks to aggregate into the cursor
+    /// * `cursor_id` - The cursor of the previous query
+    ///
+    /// # Returns
+    /// Returns the new list length and the change in results, relative to the previous query's list if the cursor
+    /// was reused. If the returned cursor id differs from the one given, the client should start a new list.
+    fn refine_n(&self, query_terms: &str, previous_query_terms: &str, num_chunks: u32, cursor_id: &str) -> impl Future<Output = Result<FileQueryingResult, FileQueryingError>> + Send;
+
+    /// Query for the files most similar to an indexed file, by the nearest neighbours of the file's chunk
+    /// embeddings. The file itself is not included in the results.
+    ///
+    /// # Arguments
+    /// * `path` - The indexed file to find related files for
+    /// * `num_results` - The maximum number of files to return
+    ///
+    /// # Returns
+    /// Returns the related files ranked by descending similarity. No previous ranks are returned, as related
+    /// queries are not aggregated into a cursor.
+    fn query_related(&self, path: &Utf8Path, num_results: u32) -> impl Future<Output = Result<Vec<QueryResult>, FileQueryingError>> + Send;
 }
 
 impl<C> QueryFiles for FileQueryer<C>
@@ -96,34 +132,188 @@ where
         ClearByFilter<QueryCursor> +
         Send + Sync
 {
-    // Query 20 results by default, starting from page 1 if no page specified
+    // Query the default number of chunks (see FileQueryerBuilder::default_num_chunks), starting from page 1 if no
+    // page specified
     fn query(&self, query_terms: &str, cursor_id: Option<&str>) -> impl Future<Output = Result<FileQueryingResult, FileQueryingError>> {
-        self.query_n(query_terms, 20, cursor_id)
+        self.query_n(query_terms, self.default_num_chunks, cursor_id)
     }
 
+    #[instrument(name = "query", skip(self))]
     async fn query_n(&self, query_terms: &str, num_chunks: u32, cursor_id: Option<&str>) -> Result<FileQueryingResult, FileQueryingError> {
         debug!("FileQueryer: Querying indexes with parameters: {}, num_chunks: {}, cursor_id: {:?}",
             query_terms, num_chunks, cursor_id);
         let mut cursor;
         if let Some(cur_id) = cursor_id {
-            debug!("FileQueryer: Retrieving cursor with id: {}", cur_id);
-            let o_cursor = self.cursor_store.get(cur_id.to_string()).await
-                .map_err(|e| FileQueryingError {
-                    query: query_terms.to_owned(),
-                    r#type: FileQueryingErrorType::CursorStore { source: e.into() },
-                })?;
-            if o_cursor.is_none() {
-                return Err(FileQueryingError {
+            cursor = self.get_cursor(query_terms, cur_id).await?
+                .ok_or_else(|| FileQueryingError {
                     query: query_terms.to_owned(),
                     r#type: FileQueryingErrorType::CursorNotFound,
-                });
-            }
-            cursor = o_cursor.unwrap();
+                })?;
         } else {
             cursor = QueryCursor::fresh();
             debug!("Initialized new cursor with id: {}", cursor.id);
         }
 
+        self.clear_expired_cursors(query_terms).await?;
+
+        let old_hash = cursor.aggregate_scores.clone();
+        let original_len = cursor.aggregate_scores.len() as u32;
+
+        let page = self.query_page(query_terms, num_chunks, cursor.curr_offset, &cursor.stream_offsets).await?;
+        for (path, score) in page.chunk_scores {
+            cursor.aggregate_chunk(&path, score);
+        }
+        for (path, source) in page.sources {
+            cursor.aggregate_source(&path, source);
+        }
+        cursor.stream_offsets = page.stream_offsets;
+        
+        if !page.has_results {
+            debug!("FileQueryer: Found no more results, returning empty result (same length, empty changed, empty cursor)");
+            return Ok(FileQueryingResult {
+                results_len: original_len,
+                changed_results: vec![],
+                cursor_id: None,
+                corrected_query: page.corrected_query,
+            })
+        }
+
+        self.save_page(query_terms, cursor, &old_hash, num_chunks, page.corrected_query).await
+    }
+
+    #[instrument(name = "refine", skip(self))]
+    async fn refine_n(&self, query_terms: &str, previous_query_terms: &str, num_chunks: u32, cursor_id: &str) -> Result<FileQueryingResult, FileQueryingError> {
+        if !is_refinement(previous_query_terms, query_terms) {
+            debug!("FileQueryer: Query {:?} does not refine {:?}, starting a new query", query_terms, previous_query_terms);
+            return self.query_n(query_terms, num_chunks, None).await;
+        }
+        let Some(mut cursor) = self.get_cursor(query_terms, cursor_id).await? else {
+            debug!("FileQueryer: Cursor {} has expired, starting a new query", cursor_id);
+            return self.query_n(query_terms, num_chunks, None).await;
+        };
+
+        self.clear_expired_cursors(query_terms).await?;
+
+        if normalize_whitespace(query_terms) == normalize_whitespace(previous_query_terms) {
+            return Ok(FileQueryingResult {
+                results_len: cursor.aggregate_scores.len() as u32,
+                changed_results: vec![],
+                cursor_id: Some(cursor.id),
+                corrected_query: None,
+            });
+        }
+
+        let old_hash = cursor.aggregate_scores.clone();
+        let page = self.query_page(query_terms, num_chunks, 0, &HashMap::new()).await?;
+
+        // Files found again by the refined query are re-scored, the rest sink below them but are kept, as clients
+        // cannot remove results from their lists
+        for score in cursor.aggregate_scores.values_mut() {
+            score.max_score *= self.stale_score_decay;
+        }
+        let mut refined = QueryCursor::fresh();
+        for (path, score) in page.chunk_scores {
+            refined.aggregate_chunk(&path, score);
+        }
+        for (path, source) in page.sources {
+            refined.aggregate_source(&path, source);
+        }
+        cursor.aggregate_scores.extend(refined.aggregate_scores);
+        cursor.curr_offset = 0;
+        cursor.stream_offsets = page.stream_offsets;
+
+        if cursor.aggregate_scores.is_empty() {
+            return Ok(FileQueryingResult {
+                results_len: 0,
+                changed_results: vec![],
+                cursor_id: None,
+                corrected_query: page.corrected_query,
+            })
+        }
+
+        self.save_page(query_terms, cursor, &old_hash, num_chunks, page.corrected_query).await
+    }
+
+    #[instrument(name = "query_related", skip(self), fields(path = %path))]
+    async fn query_related(&self, path: &Utf8Path, num_results: u32) -> Result<Vec<QueryResult>, FileQueryingError> {
+        debug!("FileQueryer: Querying files related to path: {}, num_results: {}", path, num_results);
+        let path_clone = path.to_owned();
+        let results = self.index_providers.distribute_calls(async move |p| {
+            (p.provider_name(), p.query_related_n(&path_clone, num_results).await)
+        }).await.map_err(|e| FileQueryingError {
+            query: path.to_string(),
+            r#type: FileQueryingErrorType::Other {
+                msg: "Join error occurred while querying indexes",
+                source: e,
+            },
+        })?;
+
+        // Files are as related as their most similar chunk. Providers can share stores, so the same chunk may be
+        // returned more than once.
+        let mut file_scores = QueryCursor::fresh();
+        let mut provider_error_map = HashMap::new();
+        for (provider_name, res) in results {
+            match res {
+                Ok(vec) => {
+                    for cqr in vec {
+                        let chunkfile = cqr.chunkfile();
+                        file_scores.aggregate_chunk(&chunkfile.original_file, cqr.score());
+                        file_scores.aggregate_source(&chunkfile.original_file, MatchSource {
+                            provider: provider_name.to_owned(),
+                            channel: chunkfile.chunk_channel.clone(),
+                            score: cqr.score(),
+                        });
+                    }
+                },
+                Err(e) => {
+                    provider_error_map.insert(e.provider_name.clone(), e);
+                }
+            }
+        }
+        if !provider_error_map.is_empty() {
+            if provider_error_map.len() == self.index_providers.len() {
+                return Err(FileQueryingError {
+                    query: path.to_string(),
+                    r#type: FileQueryingErrorType::IndexProviders { provider_errors: provider_error_map },
+                });
+            }
+            warn!("FileQueryer: Some index providers returned errors for related query: {}. Ignoring \
+                to allow other providers to return results", path);
+        }
+
+        let mut ranked: Vec<(Utf8PathBuf, AggregateFileScore)> = file_scores.aggregate_scores.into_iter().collect();
+        ranked.sort_by(|a, b| b.1.max_score.partial_cmp(&a.1.max_score).unwrap_or(Ordering::Equal)
+            .then_with(|| a.0.cmp(&b.0)));
+        Ok(ranked.into_iter()
+            .take(num_results as usize)
+            .enumerate()
+            .map(|(i, (path, score))| QueryResult {
+                old_rank: None,
+                rank: (i + 1) as u32,
+                path,
+                score: score.max_score,
+                sources: score.ranked_sources(),
+            })
+            .collect())
+    }
+}
+
+impl<C> FileQueryer<C>
+where
+    C: KeyedSequencedStore<String, QueryCursor> +
+        ClearByFilter<QueryCursor> +
+        Send + Sync
+{
+    async fn get_cursor(&self, query_terms: &str, cursor_id: &str) -> Result<Option<QueryCursor>, FileQueryingError> {
+        debug!("FileQueryer: Retrieving cursor with id: {}", cursor_id);
+        self.cursor_store.get(cursor_id.to_string()).await
+            .map_err(|e| FileQueryingError {
+                query: query_terms.to_owned(),
+                r#type: FileQueryingErrorType::CursorStore { source: e.into() },
+            })
+    }
+
+    async fn clear_expired_cursors(&self, query_terms: &str) -> Result<(), FileQueryingError> {
         // clear ttl (TODO: Build a database interface that supports automatically clearing ttl)
         debug!("FileQueryer: Clearing expired cursors from cursor store using clear_filter and ttl field");
         self.cursor_store.clear_filter(&[Filter {
@@ -135,41 +325,113 @@ where
                 query: query_terms.to_owned(),
                 r#type: FileQueryingErrorType::CursorStore { source: e.into() },
             })?;
+        Ok(())
+    }
 
-        let old_hash = cursor.aggregate_scores.clone();
-        let rankmap = produce_rankmap(&old_hash);
-        let original_len = cursor.aggregate_scores.len() as u32;
+    /// Queries the page of num_chunks chunks starting at offset, and at the stream offsets in each provider's stream
+    /// of chunks, from the page cache if it has the page, see FileQueryer::with_page_cache
+    async fn query_page(&self, query_terms: &str, num_chunks: u32, offset: u32, stream_offsets: &HashMap<String, u32>)
+        -> Result<QueryPage, FileQueryingError>
+    {
+        let Some(page_cache) = &self.page_cache else {
+            return self.query_providers(query_terms, num_chunks, offset, stream_offsets).await;
+        };
+        let key = PageKey::new(query_terms, num_chunks, offset, stream_offsets);
+        if let Some(page) = page_cache.get(&key) {
+            debug!("FileQueryer: Using cached page for query: {}, offset: {}", query_terms, offset);
+            return Ok(page);
+        }
 
-        debug!("FileQueryer: Performing provider queries for query: {}", query_terms);
-        let query_copy = query_terms.to_owned();
-        let results = self.index_providers.distribute_calls(async move |p| {
-            p.query_n(&query_copy, num_chunks, cursor.curr_offset).await
-        }).await.map_err(|e| FileQueryingError {
+        let page = self.query_providers(query_terms, num_chunks, offset, stream_offsets).await?;
+        // Pages some providers failed to answer are missing their chunks, so they are queried again next time
+        if page.is_complete {
+            page_cache.insert(key, &page);
+        }
+        Ok(page)
+    }
+
+    /// Queries every provider, and the operating system's search index for the first page, for the best num_chunks
+    /// chunks across providers, continuing each provider's stream of chunks from the stream offsets, and returning the
+    /// weighted score of every chunk found by the file it belongs to. offset is the number of chunks queried before
+    /// the page. Long queries are also queried by their sub-queries, and the files found fused by their ranks
+    /// instead, see MultiQuery. The providers share their table queries and query embeddings, see QueryPlanner.
+    async fn query_providers(&self, query_terms: &str, num_chunks: u32, offset: u32,
+        stream_offsets: &HashMap<String, u32>) -> Result<QueryPage, FileQueryingError>
+    {
+        let parsed = parse_query(query_terms);
+        let preprocessed = self.preprocess_query(&parsed.text).await;
+        let corrected_query = (preprocessed.corrected != parsed.text)
+            .then(|| parsed.with_text(&preprocessed.corrected));
+        let sub_queries = self.multi_query.sub_queries(&preprocessed.corrected);
+        if !sub_queries.is_empty() {
+            debug!("FileQueryer: Also querying sub-queries: {:?}", sub_queries);
+        }
+
+        debug!("FileQueryer: Performing provider queries for query: {}, filters: {:?}", preprocessed.expanded,
+            parsed.filters);
+        let batch = self.query_planner.batch();
+        let provider_queries = future::join_all(iter::once(&preprocessed.expanded).chain(&sub_queries).enumerate()
+            .map(|(query_index, query)| self.query_chunks(query, query_index, &parsed.filters,
+                &preprocessed.corrected, num_chunks, stream_offsets, batch.clone())));
+        // The operating system's search index is only queried for the first page, as it returns all of its
+        // matches at once
+        let os_search_query = async {
+            if offset == 0 {
+                self.os_search_fallback.search(&parsed.text).await
+            } else {
+                Ok(vec![])
+            }
+        };
+        let (results, os_search_results) = tokio::join!(provider_queries, os_search_query);
+        if let Some(batch) = batch {
+            debug!("FileQueryer: Providers ran {} distinct table queries", batch.num_queries());
+        }
+        let results = results.into_iter().collect::<Result<Vec<_>, _>>().map_err(|e| FileQueryingError {
             query: query_terms.to_owned(),
             r#type: FileQueryingErrorType::Other {
                 msg: "Join error occurred while querying indexes",
                 source: e,
             },
         })?;
-        let mut has_results = false;
+
+        let mut has_results = results.iter().any(|chunks| chunks.has_results);
+        let mut lists = vec![];
+        let mut sources = vec![];
         let mut provider_error_map = HashMap::new();
-        for res in results {
-            match res {
-                Ok(vec) => {
-                    if !vec.is_empty() {
-                        has_results = true;
+        // Streams of providers that failed continue where they were on the next page
+        let mut next_stream_offsets = stream_offsets.clone();
+        for chunks in results {
+            lists.push(chunks.chunk_scores);
+            sources.extend(chunks.sources);
+            provider_error_map.extend(chunks.provider_errors);
+            next_stream_offsets.extend(chunks.stream_offsets);
+        }
+        let mut chunk_scores = if lists.len() > 1 {
+            fuse_ranked_lists(lists, offset)
+        } else {
+            lists.pop().unwrap_or_default()
+        };
 
-                        for cqr in vec {
-                            cursor.aggregate_chunk(&cqr.chunkfile().original_file, cqr.score());
-                        }
-                    }
-                },
-                Err(e) => {
-                    let provider_name = e.provider_name.clone();
-                    provider_error_map.insert(provider_name, e);
+        match os_search_results {
+            Ok(vec) => {
+                let weight = self.score_weights.provider_weight(OS_SEARCH_PROVIDER_NAME);
+                for (path, score) in vec {
+                    has_results = true;
+                    let source = MatchSource {
+                        provider: OS_SEARCH_PROVIDER_NAME.to_owned(),
+                        channel: String::new(),
+                        score: score * weight,
+                    };
+                    sources.push((path.clone(), source));
+                    chunk_scores.push((path, score * weight));
                 }
-            }
+            },
+            Err(e) => warn!("FileQueryer: Operating system search index errored for query: {}, ignoring: {:?}",
+                query_terms, e),
         }
+        // Whether providers found anything decides whether there are more pages, so files of other types are only
+        // dropped once it is known
+        chunk_scores.retain(|(path, _)| parsed.matches_extension(path));
         if !provider_error_map.is_empty() {
             if provider_error_map.len() == self.index_providers.len() {
                 debug!("FileQueryer: All index providers returned errors for query: {}", query_terms);
@@ -182,16 +444,82 @@ where
                     to allow other providers to return results", query_terms);
             }
         }
-        
-        if !has_results {
-            debug!("FileQueryer: Found no more results, returning empty result (same length, empty changed, empty cursor)");
-            return Ok(FileQueryingResult {
-                results_len: original_len,
-                changed_results: vec![],
-                cursor_id: None,
-            })
+
+        let is_complete = provider_error_map.is_empty();
+        Ok(QueryPage { chunk_scores, sources, has_results, corrected_query, is_complete,
+            stream_offsets: next_stream_offsets })
+    }
+
+    /// Queries every provider with the query, in the batch if there is one, continuing each provider's stream of
+    /// chunks for the query from the stream offsets, and merges the streams into the best num_chunks chunks across
+    /// providers (see merge_streams). Returns the weighted score of every chunk taken by the file it belongs to,
+    /// along with the provider and channel that found it.
+    async fn query_chunks(&self, query: &str, query_index: usize, filters: &ChunkQueryFilters, corrected_query: &str,
+        num_chunks: u32, stream_offsets: &HashMap<String, u32>, batch: Option<Arc<LanceDBQueryBatch>>)
+        -> Result<ProviderChunks, anyhow::Error>
+    {
+        let query_copy = query.to_owned();
+        let filters = filters.clone();
+        let offsets = stream_offsets.clone();
+        let results = self.index_providers.distribute_calls(async move |p| {
+            let offset = offsets.get(&stream_key(p.provider_name(), query_index)).copied().unwrap_or(0);
+            let provider_query = p.query_n(&query_copy, &filters, num_chunks, offset);
+            (p.provider_name(), offset, run_in_batch(batch, provider_query).await)
+        }).await?;
+
+        let now = Utc::now();
+        let mut has_results = false;
+        let mut provider_errors = HashMap::new();
+        let mut streams = vec![];
+        for (provider_name, offset, res) in results {
+            match res {
+                Ok(vec) => {
+                    has_results |= !vec.is_empty();
+                    let chunks = vec.into_iter().map(|cqr| {
+                        let weight = self.score_weights.weight(provider_name, &cqr.chunkfile().chunk_type)
+                            * self.ranking_boosts.multiplier(corrected_query, cqr.chunkfile(), &now);
+                        let score = cqr.score() * weight;
+                        StreamedChunk {
+                            path: cqr.chunkfile().original_file.clone(),
+                            score,
+                            source: MatchSource {
+                                provider: provider_name.to_owned(),
+                                channel: cqr.chunkfile().chunk_channel.clone(),
+                                score,
+                            },
+                            counts: cqr.score() >= self.min_score,
+                        }
+                    }).collect();
+                    streams.push(ChunkStream { key: stream_key(provider_name, query_index), offset, chunks });
+                },
+                Err(e) => {
+                    provider_errors.insert(e.provider_name.clone(), e);
+                }
+            }
         }
 
+        let (merged, stream_offsets) = merge_streams(streams, num_chunks);
+        let mut chunks = ProviderChunks {
+            chunk_scores: Vec::with_capacity(merged.len()),
+            sources: Vec::with_capacity(merged.len()),
+            has_results,
+            provider_errors,
+            stream_offsets,
+        };
+        for chunk in merged {
+            chunks.chunk_scores.push((chunk.path.clone(), chunk.score));
+            chunks.sources.push((chunk.path, chunk.source));
+        }
+        Ok(chunks)
+    }
+
+    /// Works out how the results changed from the old aggregate scores, and saves the cursor, advanced by
+    /// num_chunks
+    async fn save_page(&self, query_terms: &str, mut cursor: QueryCursor,
+        old_hash: &HashMap<Utf8PathBuf, AggregateFileScore>, num_chunks: u32, corrected_query: Option<String>)
+        -> Result<FileQueryingResult, FileQueryingError> {
+        let rankmap = produce_rankmap(old_hash);
+
         debug!("FileQueryer: Calculating changed results from new and old aggregated cursor data");
         // borrow the cursor aggregate score hashmap's values to calculate result
         let mut new_list: Vec<_> = cursor.aggregate_scores.iter().collect();
@@ -219,6 +547,7 @@ where
                 rank,
                 path: entry.0.clone(),
                 score,
+                sources: entry.1.ranked_sources(),
             })
         }
         // drop immutable borrow on cursor aggregate score hashmap
@@ -242,15 +571,118 @@ where
             results_len: new_list_len,
             changed_results: changed_vec,
             cursor_id: Some(new_cursor_id),
+            corrected_query,
         })
     }
+
+    /// Applies spelling correction and synonym expansion to the query terms. If the spelling dictionary cannot
+    /// be built, the query terms are only expanded.
+    async fn preprocess_query(&self, query_terms: &str) -> PreprocessedQuery {
+        if !self.query_preprocessor.is_enabled() {
+            return PreprocessedQuery { corrected: query_terms.to_owned(), expanded: query_terms.to_owned() };
+        }
+
+        let mut dictionary = None;
+        if self.query_preprocessor.spelling_correction {
+            let providers = self.index_providers.clone();
+            match spelling::cached_dictionary(async move || collect_vocabulary(providers).await).await {
+                Ok(d) => dictionary = Some(d),
+                Err(e) => warn!("FileQueryer: Could not build spelling dictionary, skipping correction: {:?}", e),
+            }
+        }
+
+        let preprocessed = self.query_preprocessor.preprocess(query_terms, dictionary.as_deref());
+        if preprocessed.expanded != query_terms {
+            debug!("FileQueryer: Preprocessed query {:?} into {:?}", query_terms, preprocessed.expanded);
+        }
+        preprocessed
+    }
 }
 
 pub use result::*;
 pub use error::*;
+pub use weights::*;
+pub use boosts::*;
+pub use os_search::{OsSearchFallback, EXACT_NAME_MATCH_SCORE, OS_SEARCH_MATCH_SCORE, OS_SEARCH_PROVIDER_NAME};
+pub use spelling::{PreprocessedQuery, QueryPreprocessor, SpellingDictionary};
+pub use parse::{ParsedQuery, parse_query, parse_query_at};
+pub use cache::QueryPageCache;
+pub use multi::MultiQuery;
+pub use planner::QueryPlanner;
+pub(crate) use cache::invalidate_cached_pages;
 
 // private methods and modules
 
+/// How much the scores of files found by the previous query are reduced when a refined query does not find them
+/// again by default, see QueryFiles::refine_n and FileQueryerBuilder::stale_score_decay
+pub(crate) const DEFAULT_STALE_SCORE_DECAY: f32 = 0.5;
+
+/// Score a chunk needs to count towards the score of its file by default, see FileQueryerBuilder::min_score
+pub(crate) const DEFAULT_MIN_SCORE: f32 = 0.0;
+
+/// Number of chunks QueryFiles::query aggregates by default, see FileQueryerBuilder::default_num_chunks
+pub(crate) const DEFAULT_NUM_CHUNKS: u32 = 20;
+
+/// Chunks found by one page of a query
+#[derive(Clone)]
+struct QueryPage {
+    /// The weighted score of each chunk, by the file it belongs to
+    chunk_scores: Vec<(Utf8PathBuf, f32)>,
+    /// The provider and channel that found each chunk, by the file it belongs to
+    sources: Vec<(Utf8PathBuf, MatchSource)>,
+    has_results: bool,
+    corrected_query: Option<String>,
+    /// Whether every provider answered
+    is_complete: bool,
+    /// Where each provider's stream of chunks continues on the next page, by stream, see stream_key
+    stream_offsets: HashMap<String, u32>,
+}
+
+/// Chunks found by every provider for one query
+struct ProviderChunks {
+    /// The weighted score of each chunk, by the file it belongs to
+    chunk_scores: Vec<(Utf8PathBuf, f32)>,
+    /// The provider and channel that found each chunk, by the file it belongs to
+    sources: Vec<(Utf8PathBuf, MatchSource)>,
+    has_results: bool,
+    provider_errors: HashMap<String, IndexProviderError>,
+    /// Where each provider's stream of chunks continues on the next page, by stream, see stream_key
+    stream_offsets: HashMap<String, u32>,
+}
+
+/// Whether the query terms extend the previous query terms with the same filters, eg. while the user is still
+/// typing, so the previous query's results are a reasonable starting point
+fn is_refinement(previous_query_terms: &str, query_terms: &str) -> bool {
+    let previous = normalize_whitespace(previous_query_terms);
+    if previous.is_empty() || !normalize_whitespace(query_terms).starts_with(&previous) {
+        return false;
+    }
+    parse_query(previous_query_terms).filter_terms == parse_query(query_terms).filter_terms
+}
+
+fn normalize_whitespace(query_terms: &str) -> String {
+    query_terms.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+/// Collects the vocabulary of every provider. Providers that error are skipped, as long as at least one
+/// provider succeeds.
+async fn collect_vocabulary(providers: Vec<Arc<dyn ChunkingIndexProvider>>) -> Result<Vec<String>, anyhow::Error> {
+    let results = providers.distribute_calls(async move |p| p.vocabulary().await).await?;
+
+    let mut words = vec![];
+    let mut last_error = None;
+    for res in results {
+        match res {
+            Ok(vocabulary) => words.extend(vocabulary),
+            Err(e) => last_error = Some(e),
+        }
+    }
+    match last_error {
+        Some(e) if words.is_empty() => Err(anyhow::anyhow!("Could not collect vocabulary from providers: {}", e)),
+        _ => Ok(words),
+    }
+}
+
 fn produce_rankmap(original: &HashMap<Utf8PathBuf, AggregateFileScore>) -> HashMap<&Utf8Path, u32> {
     let mut original_list: Vec<_> = original.iter().collect();
     original_list.sort_by(cmp_score_entries_desc);
@@ -279,4 +711,13 @@ fn cmp_score_entries_desc(
 }
 
 mod result;
-mod error;
\ No newline at end of file
+mod error;
+mod weights;
+mod boosts;
+mod spelling;
+mod os_search;
+mod parse;
+mod cache;
+mod multi;
+mod planner;
+mod stream;
\ No newline at end of file
@@ -0,0 +1,112 @@
+use chrono::{DateTime, Utc};
+
+use crate::{app_config, files::{affinity::DirectoryAffinity, feedback::ClickFeedback}, index::ChunkFile};
+
+/// Optional ranking signals that are combined with the semantic score of each chunk:
+///
+/// `score * (1 + recency_weight * 0.5^(age_days / recency_half_life_days) + affinity_weight * affinity
+///            + feedback_weight * preference + tag_weight * tag_match)`
+///
+/// Where age_days is the time since the file was last modified, affinity is the DirectoryAffinity of the
+/// file (0.0 - 1.0), preference is the ClickFeedback preference for the file given the query (0.0 - 1.0) and
+/// tag_match is 1.0 if one of the words of the query is a user tag of the file, and 0.0 otherwise.
+/// With all weights at 0.0 (the default), scores are left untouched.
+#[derive(Debug, Clone)]
+pub struct RankingBoosts {
+    pub recency_weight: f32,
+    /// Number of days after which the recency boost of a file is halved
+    pub recency_half_life_days: f32,
+    pub affinity_weight: f32,
+    pub affinity: DirectoryAffinity,
+    pub feedback_weight: f32,
+    pub feedback: ClickFeedback,
+    pub tag_weight: f32,
+}
+
+impl Default for RankingBoosts {
+    fn default() -> Self {
+        RankingBoosts {
+            recency_weight: 0.0,
+            recency_half_life_days: 30.0,
+            affinity_weight: 0.0,
+            affinity: DirectoryAffinity::default(),
+            feedback_weight: 0.0,
+            feedback: ClickFeedback::default(),
+            tag_weight: 0.0,
+        }
+    }
+}
+
+impl RankingBoosts {
+    /// Loads ranking boosts from the boosts section of the ranking.toml settings file, along with the current
+    /// open history and click feedback if their boosts are enabled.
+    pub fn from_settings() -> Result<RankingBoosts, anyhow::Error> {
+        let settings = app_config::get_ranking_config()?;
+        let mut boosts = RankingBoosts::default();
+
+        if let Ok(recency_weight) = settings.get_float("boosts.recency_weight") {
+            boosts.recency_weight = recency_weight as f32;
+        }
+        if let Ok(recency_half_life_days) = settings.get_float("boosts.recency_half_life_days") {
+            if recency_half_life_days <= 0.0 {
+                return Err(anyhow::anyhow!("boosts.recency_half_life_days must be positive, got {}",
+                    recency_half_life_days));
+            }
+            boosts.recency_half_life_days = recency_half_life_days as f32;
+        }
+        if let Ok(affinity_weight) = settings.get_float("boosts.affinity_weight") {
+            boosts.affinity_weight = affinity_weight as f32;
+        }
+        if let Ok(feedback_weight) = settings.get_float("boosts.feedback_weight") {
+            boosts.feedback_weight = feedback_weight as f32;
+        }
+        if let Ok(tag_weight) = settings.get_float("boosts.tag_weight") {
+            boosts.tag_weight = tag_weight as f32;
+        }
+        if boosts.recency_weight < 0.0 || boosts.affinity_weight < 0.0 || boosts.feedback_weight < 0.0
+            || boosts.tag_weight < 0.0 {
+            return Err(anyhow::anyhow!("Ranking boost weights must not be negative"));
+        }
+
+        if boosts.affinity_weight > 0.0 {
+            boosts.affinity = DirectoryAffinity::load()?;
+        }
+        if boosts.feedback_weight > 0.0 {
+            boosts.feedback = ClickFeedback::load()?;
+        }
+
+        Ok(boosts)
+    }
+
+    /// Whether files opened from query results should be recorded as click feedback. Recording is opt-in,
+    /// and only happens while the feedback boost is enabled in the ranking.toml settings file.
+    pub fn feedback_recording_enabled() -> Result<bool, anyhow::Error> {
+        let settings = app_config::get_ranking_config()?;
+        Ok(settings.get_float("boosts.feedback_weight").map(|w| w > 0.0).unwrap_or(false))
+    }
+
+    /// Returns the multiplier to apply to the score of the given chunk, returned for the given query
+    pub fn multiplier(&self, query: &str, chunkfile: &ChunkFile, now: &DateTime<Utc>) -> f32 {
+        let mut multiplier = 1.0;
+
+        if self.recency_weight > 0.0 {
+            let age_days = (*now - chunkfile.original_file_modified_date).num_seconds().max(0) as f32 / 86400.0;
+            multiplier += self.recency_weight * 0.5f32.powf(age_days / self.recency_half_life_days);
+        }
+        if self.affinity_weight > 0.0 {
+            multiplier += self.affinity_weight * self.affinity.affinity(&chunkfile.original_file);
+        }
+        if self.feedback_weight > 0.0 {
+            multiplier += self.feedback_weight * self.feedback.preference(query, &chunkfile.original_file);
+        }
+        if self.tag_weight > 0.0 && !chunkfile.user_tags.is_empty() {
+            let tag_match = query.split_whitespace()
+                .any(|word| chunkfile.user_tags.contains(&word.to_lowercase()));
+            if tag_match {
+                multiplier += self.tag_weight;
+            }
+        }
+
+        multiplier
+    }
+}
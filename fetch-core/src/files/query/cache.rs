@@ -0,0 +1,143 @@
+use std::{collections::{HashMap, HashSet}, sync::{Arc, Mutex, Weak}, time::{Duration, Instant}};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use tracing::debug;
+
+use crate::app_config;
+
+use super::QueryPage;
+
+/// Recent pages of chunks found by queries, by their query terms (filters included), size and offset, so flipping
+/// back and forth between the pages of a query, or typing a query again, does not ask the providers again. A page
+/// is dropped as soon as a file in it is indexed, cleared, tagged or restored by a FileIndexer in the same process,
+/// see invalidate_cached_pages. Changes made by other processes, eg. the daemon, and files a query would newly find,
+/// only show once the page is older than max_age.
+///
+/// Configured by the query.toml settings file. FileQueryers only share pages if they are given the same cache, see
+/// FileQueryer::with_page_cache.
+pub struct QueryPageCache {
+    max_pages: usize,
+    max_age: Duration,
+    pages: Mutex<HashMap<PageKey, CachedPage>>,
+}
+
+impl QueryPageCache {
+    /// Creates a cache with the default size and age, see with_limits
+    pub fn new() -> Arc<QueryPageCache> {
+        QueryPageCache::with_limits(DEFAULT_MAX_PAGES, DEFAULT_MAX_AGE)
+    }
+
+    /// Creates a cache of up to max_pages pages, each kept for up to max_age. A max_pages of 0 caches nothing.
+    pub fn with_limits(max_pages: usize, max_age: Duration) -> Arc<QueryPageCache> {
+        let cache = Arc::new(QueryPageCache { max_pages, max_age, pages: Mutex::new(HashMap::new()) });
+        let mut caches = CACHES.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        caches.retain(|cache| cache.strong_count() > 0);
+        caches.push(Arc::downgrade(&cache));
+        cache
+    }
+
+    /// Creates a cache with the limits in the query.toml settings file, and the defaults for those not set
+    pub fn from_settings() -> Result<Arc<QueryPageCache>, anyhow::Error> {
+        let settings = app_config::get_query_config()?;
+        let mut max_pages = DEFAULT_MAX_PAGES;
+        let mut max_age = DEFAULT_MAX_AGE;
+
+        if let Ok(pages) = settings.get_int("result_cache_max_pages") {
+            max_pages = usize::try_from(pages)
+                .map_err(|_| anyhow::anyhow!("result_cache_max_pages must not be negative, got {}", pages))?;
+        }
+        if let Ok(secs) = settings.get_int("result_cache_max_age_secs") {
+            max_age = u64::try_from(secs).map(Duration::from_secs)
+                .map_err(|_| anyhow::anyhow!("result_cache_max_age_secs must not be negative, got {}", secs))?;
+        }
+
+        Ok(QueryPageCache::with_limits(max_pages, max_age))
+    }
+
+    /// The page cached for the key, if it is not older than max_age
+    pub(super) fn get(&self, key: &PageKey) -> Option<QueryPage> {
+        let mut pages = self.pages.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        match pages.get(key) {
+            Some(cached) if cached.cached_at.elapsed() < self.max_age => Some(cached.page.clone()),
+            Some(_) => {
+                pages.remove(key);
+                None
+            },
+            None => None,
+        }
+    }
+
+    /// Caches the page, dropping expired pages and then the oldest page if the cache is full
+    pub(super) fn insert(&self, key: PageKey, page: &QueryPage) {
+        if self.max_pages == 0 {
+            return;
+        }
+        let mut pages = self.pages.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        pages.retain(|_, cached| cached.cached_at.elapsed() < self.max_age);
+        if pages.len() >= self.max_pages && !pages.contains_key(&key) {
+            let oldest = pages.iter().min_by_key(|(_, cached)| cached.cached_at).map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                pages.remove(&oldest);
+            }
+        }
+
+        let files = page.chunk_scores.iter().map(|(path, _)| path.clone()).collect();
+        pages.insert(key, CachedPage { page: page.clone(), files, cached_at: Instant::now() });
+    }
+
+    fn invalidate(&self, path: &Utf8Path) {
+        let mut pages = self.pages.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let num_pages = pages.len();
+        pages.retain(|_, cached| !cached.files.iter().any(|file| file.starts_with(path)));
+        if pages.len() < num_pages {
+            debug!("QueryPageCache: Dropped {} cached pages with results under {}", num_pages - pages.len(), path);
+        }
+    }
+}
+
+/// Drops the pages of every cache in this process that have results at or under the path, which is a file or a
+/// directory. Called whenever providers write the chunks of a file.
+pub(crate) fn invalidate_cached_pages(path: &Utf8Path) {
+    let caches: Vec<Arc<QueryPageCache>> = CACHES.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+        .iter()
+        .filter_map(Weak::upgrade)
+        .collect();
+    for cache in caches {
+        cache.invalidate(path);
+    }
+}
+
+/// What a page was queried with
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(super) struct PageKey {
+    query_terms: String,
+    num_chunks: u32,
+    offset: u32,
+    /// Where the page started in each provider's stream of chunks, sorted by stream
+    stream_offsets: Vec<(String, u32)>,
+}
+
+impl PageKey {
+    pub(super) fn new(query_terms: &str, num_chunks: u32, offset: u32, stream_offsets: &HashMap<String, u32>)
+        -> PageKey
+    {
+        let mut stream_offsets: Vec<_> = stream_offsets.iter().map(|(k, v)| (k.clone(), *v)).collect();
+        stream_offsets.sort();
+        PageKey { query_terms: query_terms.to_owned(), num_chunks, offset, stream_offsets }
+    }
+}
+
+// Private constants, variables and structs
+
+const DEFAULT_MAX_PAGES: usize = 64;
+const DEFAULT_MAX_AGE: Duration = Duration::from_secs(60);
+
+/// Every cache created, so pages can be dropped when files are indexed
+static CACHES: Mutex<Vec<Weak<QueryPageCache>>> = Mutex::new(vec![]);
+
+struct CachedPage {
+    page: QueryPage,
+    /// Files the page has results for
+    files: HashSet<Utf8PathBuf>,
+    cached_at: Instant,
+}
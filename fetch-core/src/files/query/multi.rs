@@ -0,0 +1,117 @@
+use std::collections::HashMap;
+
+use camino::Utf8PathBuf;
+
+use crate::app_config;
+
+/// Splits long queries into sub-queries, eg. a verbose description of a half remembered document into its
+/// sentences, which are queried along with the whole query. A long query is embedded as a single vector, which
+/// blurs the details it describes together, while each sentence finds the chunks matching one of them. The lists of
+/// files found by each query are fused with reciprocal rank fusion (RRF), so files found by several of them rank
+/// first. Configured by the query.toml settings file.
+#[derive(Debug, Clone)]
+pub struct MultiQuery {
+    pub enabled: bool,
+    /// Number of words a query needs to be split into sub-queries
+    pub min_words: usize,
+    /// Maximum number of sub-queries queried along with the whole query
+    pub max_sub_queries: usize,
+}
+
+impl Default for MultiQuery {
+    fn default() -> Self {
+        MultiQuery { enabled: true, min_words: DEFAULT_MIN_WORDS, max_sub_queries: DEFAULT_MAX_SUB_QUERIES }
+    }
+}
+
+impl MultiQuery {
+    /// Loads the multi query settings from the query.toml settings file.
+    pub fn from_settings() -> Result<MultiQuery, anyhow::Error> {
+        let settings = app_config::get_query_config()?;
+        let mut multi_query = MultiQuery::default();
+
+        if let Ok(enabled) = settings.get_bool("multi_query") {
+            multi_query.enabled = enabled;
+        }
+        if let Ok(min_words) = settings.get_int("multi_query_min_words") {
+            multi_query.min_words = usize::try_from(min_words)
+                .map_err(|_| anyhow::anyhow!("multi_query_min_words must not be negative, got {}", min_words))?;
+        }
+        if let Ok(max_sub_queries) = settings.get_int("multi_query_max_sub_queries") {
+            multi_query.max_sub_queries = usize::try_from(max_sub_queries)
+                .map_err(|_| anyhow::anyhow!("multi_query_max_sub_queries must not be negative, got {}",
+                    max_sub_queries))?;
+        }
+
+        Ok(multi_query)
+    }
+
+    /// The sub-queries to query along with the query, or none if the query is too short or does not split. Queries
+    /// are split into sentences, and queries of a single sentence into its clauses. Parts too short to mean much on
+    /// their own are joined to the part before them.
+    pub fn sub_queries(&self, query: &str) -> Vec<String> {
+        if !self.enabled || self.max_sub_queries == 0 || query.split_whitespace().count() < self.min_words {
+            return vec![];
+        }
+
+        let mut parts = split_parts(query, |c| matches!(c, '.' | '!' | '?' | ';' | '\n' | '。' | '！' | '？'));
+        if parts.len() < 2 {
+            parts = split_parts(query, |c| matches!(c, ',' | ':' | '、' | '，'));
+        }
+        if parts.len() < 2 {
+            return vec![];
+        }
+
+        parts.truncate(self.max_sub_queries);
+        parts
+    }
+}
+
+/// Fuses the lists of files found by the whole query and its sub-queries with reciprocal rank fusion. Each list is
+/// the weighted score of every chunk found by one query, ranked from offset, the number of chunks skipped by the
+/// page. Files score the sum of 1 / (RRF_K + rank) over the lists, by their best ranked chunk in each, divided by
+/// the most a file can score and mapped onto the 0-100 range of provider scores, so a file ranked first by every
+/// query scores 100.
+pub(crate) fn fuse_ranked_lists(lists: Vec<Vec<(Utf8PathBuf, f32)>>, offset: u32) -> Vec<(Utf8PathBuf, f32)> {
+    let num_lists = lists.len();
+    let mut fused: HashMap<Utf8PathBuf, f32> = HashMap::new();
+    for mut list in lists {
+        list.sort_by(|l, r| r.1.total_cmp(&l.1));
+        let mut ranked: HashMap<Utf8PathBuf, u32> = HashMap::new();
+        for (i, (path, _)) in list.into_iter().enumerate() {
+            ranked.entry(path).or_insert(offset + i as u32 + 1);
+        }
+        for (path, rank) in ranked {
+            *fused.entry(path).or_insert(0.0) += 1.0 / (RRF_K + rank as f32);
+        }
+    }
+
+    let max_score = num_lists as f32 / (RRF_K + 1.0);
+    fused.into_iter()
+        .map(|(path, score)| (path, score / max_score * 100.0))
+        .collect()
+}
+
+// Private constants and functions
+
+const DEFAULT_MIN_WORDS: usize = 12;
+const DEFAULT_MAX_SUB_QUERIES: usize = 4;
+/// Damps the difference between the top ranks, so a file found near the top of several lists beats one found first
+/// by only one of them
+const RRF_K: f32 = 60.0;
+/// Parts with fewer words are joined to the part before them
+const MIN_PART_WORDS: usize = 3;
+
+fn split_parts(query: &str, is_separator: impl Fn(char) -> bool) -> Vec<String> {
+    let mut parts: Vec<String> = vec![];
+    for part in query.split(is_separator).map(str::trim).filter(|part| !part.is_empty()) {
+        match parts.last_mut() {
+            Some(last) if part.split_whitespace().count() < MIN_PART_WORDS => {
+                last.push(' ');
+                last.push_str(part);
+            },
+            _ => parts.push(part.to_owned()),
+        }
+    }
+    parts
+}
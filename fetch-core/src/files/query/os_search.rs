@@ -0,0 +1,145 @@
+use camino::{Utf8Path, Utf8PathBuf};
+use tracing::debug;
+
+use crate::app_config;
+
+/// Name under which results from the operating system's search index are weighted, eg. in the provider_weights
+/// of the ranking.toml settings file
+pub const OS_SEARCH_PROVIDER_NAME: &str = "os_search";
+
+/// Score given to files whose name is exactly the query, so they rank above semantic matches
+pub const EXACT_NAME_MATCH_SCORE: f32 = 1.0;
+/// Score given to files the operating system's search index matched by a part of their name or their metadata
+pub const OS_SEARCH_MATCH_SCORE: f32 = 0.5;
+
+/// Fallback that queries the operating system's own search index (Windows Search or Spotlight) for files whose
+/// names or metadata match the query. Those indexes are kept up to date by the operating system and answer
+/// filename queries instantly, so exact-name matches can be shown before (and regardless of whether) Fetch has
+/// indexed the file. Configured by the query.toml settings file, and off by default.
+#[derive(Debug, Clone)]
+pub struct OsSearchFallback {
+    pub enabled: bool,
+    /// Maximum number of files to take from the operating system's search index per query
+    pub max_results: u32,
+}
+
+impl Default for OsSearchFallback {
+    fn default() -> Self {
+        OsSearchFallback { enabled: false, max_results: DEFAULT_MAX_RESULTS }
+    }
+}
+
+impl OsSearchFallback {
+    /// Loads the fallback settings from the query.toml settings file.
+    pub fn from_settings() -> Result<OsSearchFallback, anyhow::Error> {
+        let settings = app_config::get_query_config()?;
+        let mut fallback = OsSearchFallback::default();
+
+        if let Ok(enabled) = settings.get_bool("os_search_fallback") {
+            fallback.enabled = enabled;
+        }
+        if let Ok(max_results) = settings.get_int("os_search_max_results") {
+            fallback.max_results = u32::try_from(max_results)
+                .map_err(|_| anyhow::anyhow!("os_search_max_results must not be negative, got {}", max_results))?;
+        }
+
+        Ok(fallback)
+    }
+
+    /// Queries the operating system's search index, returning the matching files along with their scores. Returns
+    /// no files if the fallback is disabled or the operating system has no supported search index.
+    pub async fn search(&self, query: &str) -> Result<Vec<(Utf8PathBuf, f32)>, anyhow::Error> {
+        let query = query.trim().to_owned();
+        if !self.enabled || query.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let max_results = self.max_results;
+        let query_copy = query.clone();
+        let paths = tokio::task::spawn_blocking(move || search_os_index(&query_copy, max_results)).await??;
+        debug!("OsSearchFallback: Operating system search index returned {} files for query: {}", paths.len(), query);
+
+        Ok(paths.into_iter()
+            .map(|path| {
+                let score = if is_exact_name_match(&path, &query) { EXACT_NAME_MATCH_SCORE } else { OS_SEARCH_MATCH_SCORE };
+                (path, score)
+            })
+            .collect())
+    }
+}
+
+// Private functions and variables
+
+const DEFAULT_MAX_RESULTS: u32 = 20;
+
+/// Environment variable the query is passed to PowerShell through, so that it never has to be escaped into the
+/// script
+#[cfg(target_os = "windows")]
+const QUERY_ENV_VAR: &str = "FETCH_OS_SEARCH_QUERY";
+
+fn is_exact_name_match(path: &Utf8Path, query: &str) -> bool {
+    let query = query.to_lowercase();
+    path.file_name().is_some_and(|name| name.to_lowercase() == query)
+        || path.file_stem().is_some_and(|stem| stem.to_lowercase() == query)
+}
+
+/// Searches Spotlight for files with the query in their name or metadata
+#[cfg(target_os = "macos")]
+fn search_os_index(query: &str, max_results: u32) -> Result<Vec<Utf8PathBuf>, anyhow::Error> {
+    // The query is compared case and diacritic insensitively (cd) against the name and the metadata text
+    // Spotlight extracted from the file. mdfind is run directly rather than through a shell, so the query only
+    // has to be escaped for the Spotlight query syntax.
+    let query = escape_mdfind(query);
+    let predicate = format!("kMDItemFSName == \"*{}*\"cd || kMDItemTextContent == \"*{}*\"cd", query, query);
+    let output = std::process::Command::new("mdfind")
+        .arg(predicate)
+        .output()?;
+    parse_output("mdfind", output, max_results)
+}
+
+/// Searches Windows Search for files with the query in their name or metadata, through its OLE DB provider
+#[cfg(target_os = "windows")]
+fn search_os_index(query: &str, max_results: u32) -> Result<Vec<Utf8PathBuf>, anyhow::Error> {
+    let script = format!(
+        "$q = $env:{} -replace \"'\", \"''\" -replace '[%_\\[]', '[$0]'; \
+        $c = New-Object -ComObject ADODB.Connection; \
+        $c.Open(\"Provider=Search.CollatorDSO;Extended Properties='Application=Windows';\"); \
+        $r = $c.Execute(\"SELECT TOP {} System.ItemPathDisplay FROM SYSTEMINDEX WHERE SCOPE='file:' AND \
+        (System.FileName LIKE '%$q%' OR System.Title LIKE '%$q%' OR System.Keywords = '$q')\"); \
+        while (-not $r.EOF) {{ $r.Fields.Item('System.ItemPathDisplay').Value; $r.MoveNext() }}; \
+        $c.Close()",
+        QUERY_ENV_VAR, max_results
+    );
+    let output = std::process::Command::new("powershell")
+        .args(["-NoProfile", "-NonInteractive", "-Command", &script])
+        .env(QUERY_ENV_VAR, query)
+        .output()?;
+    parse_output("Windows Search", output, max_results)
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+fn search_os_index(_query: &str, _max_results: u32) -> Result<Vec<Utf8PathBuf>, anyhow::Error> {
+    debug!("OsSearchFallback: No supported operating system search index on this platform");
+    Ok(vec![])
+}
+
+#[cfg(target_os = "macos")]
+fn escape_mdfind(query: &str) -> String {
+    query.replace('\\', "\\\\").replace('"', "\\\"").replace('*', "\\*")
+}
+
+#[cfg(any(target_os = "windows", target_os = "macos"))]
+fn parse_output(search: &str, output: std::process::Output, max_results: u32) -> Result<Vec<Utf8PathBuf>, anyhow::Error> {
+    if !output.status.success() {
+        return Err(anyhow::anyhow!("{} exited with {}: {}", search, output.status,
+            String::from_utf8_lossy(&output.stderr).trim()));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .take(max_results as usize)
+        .map(Utf8PathBuf::from)
+        .collect())
+}
@@ -0,0 +1,135 @@
+use camino::Utf8Path;
+use chrono::{Local, NaiveDate};
+use tracing::warn;
+
+use crate::{files::tags::normalize_tag, index::{language, provider::ChunkQueryFilters}};
+
+use dates::{DateAttribute, find_temporal_expression, normalize_word};
+
+/// Query terms separated into the text that is searched for and the filters written into the query, eg.
+/// `lang:ja meeting notes` is parsed into the text "meeting notes" and a filter for chunks written in Japanese.
+#[derive(Debug, Clone, Default)]
+pub struct ParsedQuery {
+    /// The query terms with filter terms removed
+    pub text: String,
+    /// The filter terms that were removed from the query, in the order they appeared
+    pub filter_terms: Vec<String>,
+    pub filters: ChunkQueryFilters,
+    /// Extension the files found must have, lowercase and without the dot. Providers do not store the type of the
+    /// files they index, so this is applied by the queryer to the files found rather than by the providers.
+    pub extension: Option<String>,
+}
+
+impl ParsedQuery {
+    /// Recombines the filter terms with the given text, eg. to show the user a corrected version of the query
+    pub fn with_text(&self, text: &str) -> String {
+        self.filter_terms.iter()
+            .map(String::as_str)
+            .chain([text])
+            .collect::<Vec<&str>>()
+            .join(" ")
+    }
+
+    /// Whether the file has the extension the query is filtered to, ignoring case. Every file matches queries that
+    /// are not filtered by extension.
+    pub fn matches_extension(&self, path: &Utf8Path) -> bool {
+        match &self.extension {
+            Some(extension) => path.extension().is_some_and(|e| e.eq_ignore_ascii_case(extension)),
+            None => true,
+        }
+    }
+}
+
+/// Parses filter terms out of the query terms, resolving relative dates against the current local date.
+/// Supported filters:
+/// * `lang:<language>` - only match chunks written in the language, given as an ISO 639-1 code, ISO 639-3 code or
+///   English name. Programming languages (eg. `lang:rust`, `lang:py`) only match code chunked in that language,
+///   and take precedence over natural languages with the same code.
+/// * `tag:<tag>` - only match files the user has tagged with the tag. Can be repeated to require several tags.
+/// * `type:<extension>` - only match files with the extension, eg. `type:pdf`
+/// * `meta:<key>=<value>` - only match files whose original file tags have the key set to the value, eg.
+///   `meta:camera=Canon`. Can be repeated to require several values. Keys promoted with `fetch schema promote-tag`
+///   are filtered on much faster.
+/// * Temporal expressions such as "last week's invoices" or "photos from summer 2022" - only match files modified
+///   (or created, eg. "created last month") within the described dates. See find_temporal_expression for the
+///   supported expressions.
+///
+/// Terms that look like filters but cannot be understood are left in the query text.
+pub fn parse_query(query_terms: &str) -> ParsedQuery {
+    parse_query_at(query_terms, Local::now().date_naive())
+}
+
+/// Parses filter terms out of the query terms, resolving relative dates against the given date. See parse_query.
+pub fn parse_query_at(query_terms: &str, today: NaiveDate) -> ParsedQuery {
+    let mut parsed = ParsedQuery::default();
+    let mut text_words = vec![];
+
+    for word in query_terms.split_whitespace() {
+        match word.split_once(':') {
+            Some((LANGUAGE_FILTER_KEY, value)) if language::resolve_code_language(value).is_some() => {
+                parsed.filters.code_language = language::resolve_code_language(value).map(str::to_owned);
+                parsed.filter_terms.push(word.to_owned());
+            },
+            Some((LANGUAGE_FILTER_KEY, value)) => match language::resolve_language_code(value) {
+                Some(code) => {
+                    parsed.filters.language = Some(code.to_owned());
+                    parsed.filter_terms.push(word.to_owned());
+                },
+                None => {
+                    warn!("Unrecognized language in query filter {:?}, searching for it as text", word);
+                    text_words.push(word);
+                },
+            },
+            Some((TAG_FILTER_KEY, value)) => match normalize_tag(value) {
+                Some(tag) => {
+                    parsed.filters.tags.push(tag);
+                    parsed.filter_terms.push(word.to_owned());
+                },
+                None => {
+                    warn!("Invalid tag in query filter {:?}, searching for it as text", word);
+                    text_words.push(word);
+                },
+            },
+            Some((META_FILTER_KEY, entry)) => match entry.split_once('=') {
+                Some((key, value)) if !key.is_empty() && !value.is_empty() => {
+                    parsed.filters.file_tags.push((key.to_owned(), value.to_owned()));
+                    parsed.filter_terms.push(word.to_owned());
+                },
+                _ => {
+                    warn!("Invalid file tag in query filter {:?}, searching for it as text", word);
+                    text_words.push(word);
+                },
+            },
+            Some((TYPE_FILTER_KEY, value)) if !value.trim_start_matches('.').is_empty() => {
+                parsed.extension = Some(value.trim_start_matches('.').to_lowercase());
+                parsed.filter_terms.push(word.to_owned());
+            },
+            _ => text_words.push(word),
+        }
+    }
+
+    // Remove temporal expressions one at a time until none are left
+    let mut normalized: Vec<String> = text_words.iter().map(|w| normalize_word(w)).collect();
+    while let Some(expression) = find_temporal_expression(&normalized, today) {
+        let range = expression.start..expression.start + expression.len;
+        parsed.filter_terms.push(text_words[range.clone()].join(" "));
+        match expression.attribute {
+            DateAttribute::Created => parsed.filters.created = Some(expression.range),
+            DateAttribute::Modified => parsed.filters.modified = Some(expression.range),
+        }
+        text_words.drain(range.clone());
+        normalized.drain(range);
+    }
+
+    parsed.text = text_words.join(" ");
+    parsed
+}
+
+// Private variables and functions
+
+const LANGUAGE_FILTER_KEY: &str = "lang";
+const TAG_FILTER_KEY: &str = "tag";
+const TYPE_FILTER_KEY: &str = "type";
+const META_FILTER_KEY: &str = "meta";
+
+mod dates;
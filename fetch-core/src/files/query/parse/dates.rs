@@ -0,0 +1,240 @@
+use chrono::{DateTime, Datelike, Days, Local, Months, NaiveDate, TimeZone, Utc};
+
+use crate::index::provider::DateRange;
+
+/// The date of a file that a temporal expression applies to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) enum DateAttribute {
+    Created,
+    Modified,
+}
+
+/// A temporal expression found in a list of query words
+#[derive(Debug)]
+pub(super) struct TemporalExpression {
+    /// Index of the first word of the expression
+    pub start: usize,
+    /// Number of words in the expression
+    pub len: usize,
+    pub attribute: DateAttribute,
+    pub range: DateRange,
+}
+
+/// Finds the first temporal expression in the query words, resolving relative expressions against today's date.
+/// Words are expected to be normalized with normalize_word.
+///
+/// Recognized expressions, optionally preceded by "created" or "modified" (modified dates are used by default)
+/// and a preposition ("from", "in", "during", "on", "since", "before", "after"):
+/// * `today`, `yesterday`
+/// * `this week`, `last month`, `past year`, etc.
+/// * `last 3 days`, `past two weeks`, etc.
+/// * `summer 2022`, `spring of 2021`, `last winter`, etc.
+/// * `march 2023`, `june of 2020`, `this april`, etc.
+///
+/// Seasons and months without a year, and bare years, are only recognized after a preposition or "this"/"last",
+/// since on their own they are more likely to be part of what is being searched for (eg. "may", "2022 taxes").
+pub(super) fn find_temporal_expression(words: &[String], today: NaiveDate) -> Option<TemporalExpression> {
+    (0..words.len()).find_map(|i| match_expression(words, i, today))
+}
+
+/// Lowercases a query word and strips surrounding punctuation and possessives, so "Week's" matches "week"
+pub(super) fn normalize_word(word: &str) -> String {
+    let word = word.to_lowercase();
+    let word = word.trim_matches(|c: char| !c.is_alphanumeric() && c != '\'' && c != '’');
+    let word = word.strip_suffix("'s").or_else(|| word.strip_suffix("’s")).unwrap_or(word);
+    word.trim_matches(|c: char| !c.is_alphanumeric()).to_owned()
+}
+
+// Private variables and functions
+
+/// A period of whole days, start inclusive and end exclusive
+type Period = (NaiveDate, NaiveDate);
+
+const MONTHS: [&str; 12] = ["january", "february", "march", "april", "may", "june", "july", "august", "september",
+    "october", "november", "december"];
+const NUMBER_WORDS: [&str; 12] = ["one", "two", "three", "four", "five", "six", "seven", "eight", "nine", "ten",
+    "eleven", "twelve"];
+const MIN_YEAR: i32 = 1900;
+const MAX_YEAR: i32 = 2100;
+
+fn match_expression(words: &[String], start: usize, today: NaiveDate) -> Option<TemporalExpression> {
+    let mut pos = start;
+    let attribute = match words.get(pos).map(String::as_str) {
+        Some("created") => Some(DateAttribute::Created),
+        Some("modified" | "edited" | "changed" | "updated") => Some(DateAttribute::Modified),
+        _ => None,
+    };
+    if attribute.is_some() {
+        pos += 1;
+    }
+    let attribute = attribute.unwrap_or(DateAttribute::Modified);
+
+    let preposition = match words.get(pos).map(String::as_str) {
+        Some(p @ ("from" | "in" | "during" | "on" | "since" | "before" | "after")) => {
+            pos += 1;
+            Some(p)
+        },
+        _ => None,
+    };
+
+    let (period, len) = match_period(&words[pos..], today, preposition.is_some())?;
+    let range = match preposition {
+        Some("since") => DateRange { start: Some(to_utc(period.0)), end: None },
+        Some("before") => DateRange { start: None, end: Some(to_utc(period.0)) },
+        Some("after") => DateRange { start: Some(to_utc(period.1)), end: None },
+        _ => DateRange { start: Some(to_utc(period.0)), end: Some(to_utc(period.1)) },
+    };
+
+    Some(TemporalExpression { start, len: pos - start + len, attribute, range })
+}
+
+/// Matches a period at the start of the words, returning it and the number of words it spans
+fn match_period(words: &[String], today: NaiveDate, has_preposition: bool) -> Option<(Period, usize)> {
+    let word = |i: usize| words.get(i).map(String::as_str);
+
+    match word(0)? {
+        "today" => return Some(((today, next_day(today)), 1)),
+        "yesterday" => return Some(((prev_day(today), today), 1)),
+        _ => {},
+    }
+
+    // this/last/past/previous followed by a unit, a count of units, a season or a month
+    if let Some(relation @ ("this" | "last" | "past" | "previous")) = word(0) {
+        let is_current = relation == "this";
+        if let Some(unit) = word(1).and_then(unit_of) {
+            let current = unit_period(unit, today);
+            return Some((if is_current { current } else { unit_period(unit, prev_day(current.0)) }, 2));
+        }
+        if !is_current {
+            if let (Some(count), Some(unit)) = (word(1).and_then(parse_count), word(2).and_then(unit_of)) {
+                return Some(((subtract_units(today, unit, count)?, next_day(today)), 3));
+            }
+        }
+        if let Some(named) = word(1).and_then(|w| named_period(w, today.year())) {
+            let period = if is_current { named } else { most_recent(word(1)?, today, |p| p.1 <= today)? };
+            return Some((period, 2));
+        }
+        return None;
+    }
+
+    // A season or month, optionally followed by a year
+    if named_period(word(0)?, today.year()).is_some() {
+        let (year, year_len) = match (word(1), word(2)) {
+            (Some("of"), Some(y)) => (parse_year(y), 2),
+            (Some(y), _) => (parse_year(y), 1),
+            _ => (None, 0),
+        };
+        return match year {
+            Some(year) => Some((named_period(word(0)?, year)?, 1 + year_len)),
+            None if has_preposition => Some((most_recent(word(0)?, today, |p| p.0 <= today)?, 1)),
+            None => None,
+        };
+    }
+
+    // A bare year
+    if has_preposition {
+        if let Some(year) = parse_year(word(0)?) {
+            return Some(((NaiveDate::from_ymd_opt(year, 1, 1)?, NaiveDate::from_ymd_opt(year + 1, 1, 1)?), 1));
+        }
+    }
+
+    None
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Unit {
+    Day,
+    Week,
+    Month,
+    Year,
+}
+
+fn unit_of(word: &str) -> Option<Unit> {
+    match word {
+        "day" | "days" => Some(Unit::Day),
+        "week" | "weeks" => Some(Unit::Week),
+        "month" | "months" => Some(Unit::Month),
+        "year" | "years" => Some(Unit::Year),
+        _ => None,
+    }
+}
+
+/// The calendar day, week (starting Monday), month or year containing the date
+fn unit_period(unit: Unit, date: NaiveDate) -> Period {
+    match unit {
+        Unit::Day => (date, next_day(date)),
+        Unit::Week => {
+            let start = date - Days::new(date.weekday().num_days_from_monday() as u64);
+            (start, start + Days::new(7))
+        },
+        Unit::Month => {
+            let start = date.with_day(1).expect("First of the month should always be valid");
+            (start, start + Months::new(1))
+        },
+        Unit::Year => {
+            let start = NaiveDate::from_ymd_opt(date.year(), 1, 1).expect("First of the year should always be valid");
+            (start, start + Months::new(12))
+        },
+    }
+}
+
+fn subtract_units(date: NaiveDate, unit: Unit, count: u32) -> Option<NaiveDate> {
+    match unit {
+        Unit::Day => date.checked_sub_days(Days::new(count as u64)),
+        Unit::Week => date.checked_sub_days(Days::new(count as u64 * 7)),
+        Unit::Month => date.checked_sub_months(Months::new(count)),
+        Unit::Year => date.checked_sub_months(Months::new(count * 12)),
+    }
+}
+
+/// The period covered by a season or month in the given year. Seasons are meteorological seasons in the
+/// northern hemisphere, and winter is the one starting in December of the year.
+fn named_period(word: &str, year: i32) -> Option<Period> {
+    let (start_month, num_months) = match word {
+        "spring" => (3, 3),
+        "summer" => (6, 3),
+        "fall" | "autumn" => (9, 3),
+        "winter" => (12, 3),
+        _ => (month_of(word)?, 1),
+    };
+    let start = NaiveDate::from_ymd_opt(year, start_month, 1)?;
+    Some((start, start + Months::new(num_months)))
+}
+
+/// The most recent occurrence of a season or month satisfying the predicate, looking back up to two years
+fn most_recent(word: &str, today: NaiveDate, predicate: impl Fn(&Period) -> bool) -> Option<Period> {
+    (0..=2).filter_map(|years_back| named_period(word, today.year() - years_back)).find(predicate)
+}
+
+fn month_of(word: &str) -> Option<u32> {
+    MONTHS.iter()
+        .position(|month| *month == word || (word.len() >= 3 && month.starts_with(word)))
+        .map(|i| i as u32 + 1)
+}
+
+fn parse_count(word: &str) -> Option<u32> {
+    word.parse::<u32>().ok()
+        .or_else(|| NUMBER_WORDS.iter().position(|n| *n == word).map(|i| i as u32 + 1))
+        .filter(|count| *count > 0)
+}
+
+fn parse_year(word: &str) -> Option<i32> {
+    word.parse::<i32>().ok().filter(|year| (MIN_YEAR..=MAX_YEAR).contains(year))
+}
+
+fn next_day(date: NaiveDate) -> NaiveDate {
+    date + Days::new(1)
+}
+
+fn prev_day(date: NaiveDate) -> NaiveDate {
+    date - Days::new(1)
+}
+
+/// Converts the start of a day in the local timezone to UTC
+fn to_utc(date: NaiveDate) -> DateTime<Utc> {
+    let midnight = date.and_hms_opt(0, 0, 0).expect("Midnight should always be a valid time");
+    Local.from_local_datetime(&midnight)
+        .earliest()
+        .map(|d| d.with_timezone(&Utc))
+        .unwrap_or_else(|| midnight.and_utc())
+}
@@ -0,0 +1,47 @@
+use std::{future::Future, sync::Arc};
+
+use crate::{app_config, store::lancedb::LanceDBQueryBatch};
+
+/// Plans how the providers are queried for a page of a query, instead of each provider querying its tables on its
+/// own. Providers querying the same table for the same query, eg. the image provider and the page images of the PDF
+/// provider both searching the siglip2 table, share a single table query (see LanceDBQueryBatch), and the query is
+/// only embedded once per model however many providers embed it (see embedding::cache::QueryEmbeddingCache).
+/// Configured by the query.toml settings file.
+#[derive(Debug, Clone)]
+pub struct QueryPlanner {
+    /// Whether identical table queries of the providers are shared
+    pub share_store_queries: bool,
+}
+
+impl Default for QueryPlanner {
+    fn default() -> Self {
+        QueryPlanner { share_store_queries: true }
+    }
+}
+
+impl QueryPlanner {
+    /// Loads the query planner settings from the query.toml settings file.
+    pub fn from_settings() -> Result<QueryPlanner, anyhow::Error> {
+        let settings = app_config::get_query_config()?;
+        let mut planner = QueryPlanner::default();
+
+        if let Ok(share_store_queries) = settings.get_bool("query_planner_share_store_queries") {
+            planner.share_store_queries = share_store_queries;
+        }
+
+        Ok(planner)
+    }
+
+    /// The batch the table queries of one page are shared in, if they are shared
+    pub(super) fn batch(&self) -> Option<Arc<LanceDBQueryBatch>> {
+        self.share_store_queries.then(LanceDBQueryBatch::new)
+    }
+}
+
+/// Runs the provider query in the batch, if there is one
+pub(super) async fn run_in_batch<F: Future>(batch: Option<Arc<LanceDBQueryBatch>>, query: F) -> F::Output {
+    match batch {
+        Some(batch) => batch.scope(query).await,
+        None => query.await,
+    }
+}
@@ -1,9 +1,12 @@
 use camino::Utf8PathBuf;
+use serde::{Deserialize, Serialize};
 
 pub struct FileQueryingResult {
     pub results_len: u32,
     pub changed_results: Vec<QueryResult>,
     pub cursor_id: Option<String>,
+    /// The query terms after spelling correction, if they differ from the query terms provided
+    pub corrected_query: Option<String>,
 }
 
 pub struct QueryResult {
@@ -11,4 +14,29 @@ pub struct QueryResult {
     pub rank: u32,
     pub path: Utf8PathBuf,
     pub score: f32,
-}
\ No newline at end of file
+    /// The providers and channels that found chunks of the file, highest scoring first, eg. the text and the
+    /// embedded pictures of a PDF
+    pub sources: Vec<MatchSource>,
+}
+
+/// A provider and channel that found chunks of a result file, with the best weighted score of those chunks
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MatchSource {
+    /// Name of the provider, eg. PdfIndexProvider
+    pub provider: String,
+    /// Channel of the chunks within the provider, eg. text or image for PDFs, see ChunkFile::chunk_channel
+    pub channel: String,
+    pub score: f32,
+}
+
+impl MatchSource {
+    /// Short name of the provider and channel, eg. pdf-text for the text chunks of PdfIndexProvider
+    pub fn label(&self) -> String {
+        let provider = self.provider.strip_suffix("IndexProvider").unwrap_or(&self.provider).to_lowercase();
+        if self.channel.is_empty() {
+            provider
+        } else {
+            format!("{}-{}", provider, self.channel)
+        }
+    }
+}
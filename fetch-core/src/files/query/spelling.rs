@@ -0,0 +1,209 @@
+use std::{collections::{HashMap, HashSet}, future::Future, sync::{Arc, LazyLock, RwLock}, time::{Duration, Instant}};
+
+use crate::app_config;
+
+/// Dictionary of known words used to correct typos in queries, using the symmetric delete algorithm
+/// (SymSpell). Every word is stored along with all of the strings that can be made by deleting up to
+/// MAX_EDIT_DISTANCE characters from it, so a misspelled word can be matched to candidates by generating
+/// its own deletes rather than by comparing it against every word in the dictionary.
+#[derive(Debug, Default)]
+pub struct SpellingDictionary {
+    word_counts: HashMap<String, u32>,
+    deletes: HashMap<String, Vec<String>>,
+}
+
+impl SpellingDictionary {
+    pub fn from_words(words: impl IntoIterator<Item = String>) -> SpellingDictionary {
+        let mut dictionary = SpellingDictionary::default();
+        for word in words {
+            let count = dictionary.word_counts.entry(word.clone()).or_insert(0);
+            *count += 1;
+            if *count > 1 {
+                continue;
+            }
+
+            for delete in generate_deletes(&word) {
+                dictionary.deletes.entry(delete).or_default().push(word.clone());
+            }
+        }
+        dictionary
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.word_counts.is_empty()
+    }
+
+    /// Returns the closest known word to the given word, or None if the word is already known or no known
+    /// word is within MAX_EDIT_DISTANCE. Ties in distance are broken by how often the word occurs.
+    pub fn correct_word(&self, word: &str) -> Option<&str> {
+        let word = word.to_lowercase();
+        if word.chars().count() < MIN_CORRECTABLE_LENGTH || self.word_counts.contains_key(&word) {
+            return None;
+        }
+
+        let mut candidates = HashSet::new();
+        for delete in generate_deletes(&word) {
+            if let Some(words) = self.deletes.get(&delete) {
+                candidates.extend(words.iter());
+            }
+        }
+
+        candidates.into_iter()
+            .map(|candidate| (candidate, edit_distance(&word, candidate)))
+            .filter(|(_, distance)| *distance <= MAX_EDIT_DISTANCE)
+            .min_by(|(l, l_dist), (r, r_dist)| l_dist.cmp(r_dist)
+                .then(self.word_counts[*r].cmp(&self.word_counts[*l]))
+                .then(l.cmp(r)))
+            .map(|(candidate, _)| candidate.as_str())
+    }
+
+    /// Corrects every word in the query, leaving words that are known or have no close match untouched.
+    pub fn correct(&self, query: &str) -> String {
+        query.split_whitespace()
+            .map(|word| self.correct_word(word).unwrap_or(word))
+            .collect::<Vec<&str>>()
+            .join(" ")
+    }
+}
+
+/// Preprocessing applied to query terms before they are embedded, configured by the query.toml settings file.
+#[derive(Debug, Clone, Default)]
+pub struct QueryPreprocessor {
+    /// Whether to correct typos using a dictionary built from the names and tags of indexed files
+    pub spelling_correction: bool,
+    /// Words that are appended to the query whenever the key word appears in it
+    pub synonyms: HashMap<String, Vec<String>>,
+}
+
+/// The query terms after preprocessing
+pub struct PreprocessedQuery {
+    /// The query after spelling correction, which is what the user should be told was searched for
+    pub corrected: String,
+    /// The corrected query expanded with synonyms, which is what is actually embedded
+    pub expanded: String,
+}
+
+impl QueryPreprocessor {
+    /// Loads query preprocessing settings from the query.toml settings file.
+    pub fn from_settings() -> Result<QueryPreprocessor, anyhow::Error> {
+        let settings = app_config::get_query_config()?;
+        let mut preprocessor = QueryPreprocessor::default();
+
+        if let Ok(spelling_correction) = settings.get_bool("spelling_correction") {
+            preprocessor.spelling_correction = spelling_correction;
+        }
+        if let Ok(synonyms) = settings.get::<HashMap<String, Vec<String>>>("synonyms") {
+            preprocessor.synonyms = synonyms.into_iter()
+                .map(|(word, synonyms)| (word.to_lowercase(), synonyms))
+                .collect();
+        }
+
+        Ok(preprocessor)
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.spelling_correction || !self.synonyms.is_empty()
+    }
+
+    pub fn preprocess(&self, query: &str, dictionary: Option<&SpellingDictionary>) -> PreprocessedQuery {
+        let corrected = match dictionary {
+            Some(dictionary) if self.spelling_correction => dictionary.correct(query),
+            _ => query.to_owned(),
+        };
+
+        let mut expanded = corrected.clone();
+        let mut added = HashSet::new();
+        for word in corrected.split_whitespace() {
+            for synonym in self.synonyms.get(&word.to_lowercase()).into_iter().flatten() {
+                if added.insert(synonym.as_str()) {
+                    expanded.push(' ');
+                    expanded.push_str(synonym);
+                }
+            }
+        }
+
+        PreprocessedQuery { corrected, expanded }
+    }
+}
+
+/// Returns the process-wide spelling dictionary if it was built recently enough, otherwise builds a new one
+/// from the given words. The dictionary is cached because collecting the vocabulary of every indexed file is
+/// too expensive to repeat for every query.
+pub(crate) async fn cached_dictionary<F, Fut>(build_words: F) -> Result<Arc<SpellingDictionary>, anyhow::Error>
+where
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = Result<Vec<String>, anyhow::Error>>,
+{
+    let cached = DICTIONARY_CACHE.read().expect("Spelling dictionary lock poisoned").clone();
+    if let Some((built, dictionary)) = cached {
+        if built.elapsed() < DICTIONARY_TTL {
+            return Ok(dictionary);
+        }
+    }
+
+    let dictionary = Arc::new(SpellingDictionary::from_words(build_words().await?));
+    *DICTIONARY_CACHE.write().expect("Spelling dictionary lock poisoned") = Some((Instant::now(), dictionary.clone()));
+
+    Ok(dictionary)
+}
+
+// Private variables and functions
+
+const MAX_EDIT_DISTANCE: usize = 2;
+// Short words have too many neighbors within the edit distance to be corrected reliably
+const MIN_CORRECTABLE_LENGTH: usize = 4;
+const DICTIONARY_TTL: Duration = Duration::from_secs(10 * 60);
+
+static DICTIONARY_CACHE: LazyLock<RwLock<Option<(Instant, Arc<SpellingDictionary>)>>> =
+    LazyLock::new(|| RwLock::new(None));
+
+/// Generates every string that can be made by deleting up to MAX_EDIT_DISTANCE characters from the word,
+/// including the word itself.
+fn generate_deletes(word: &str) -> HashSet<String> {
+    let mut deletes = HashSet::from([word.to_owned()]);
+    let mut frontier = vec![word.to_owned()];
+    for _ in 0..MAX_EDIT_DISTANCE {
+        let mut next = vec![];
+        for current in frontier {
+            let chars: Vec<char> = current.chars().collect();
+            if chars.len() <= 1 {
+                continue;
+            }
+            for i in 0..chars.len() {
+                let delete: String = chars[..i].iter().chain(&chars[i + 1..]).collect();
+                if deletes.insert(delete.clone()) {
+                    next.push(delete);
+                }
+            }
+        }
+        frontier = next;
+    }
+    deletes
+}
+
+/// Optimal string alignment distance (Levenshtein distance that also counts adjacent transpositions as a
+/// single edit)
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut rows = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for (i, row) in rows.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in rows[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+            rows[i][j] = (rows[i - 1][j] + 1)
+                .min(rows[i][j - 1] + 1)
+                .min(rows[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                rows[i][j] = rows[i][j].min(rows[i - 2][j - 2] + 1);
+            }
+        }
+    }
+    rows[a.len()][b.len()]
+}
@@ -0,0 +1,185 @@
+use std::{cmp::Ordering, collections::{BinaryHeap, HashMap}};
+
+use camino::Utf8PathBuf;
+
+use super::MatchSource;
+
+/// The chunks a provider found for one query on a page, in the order the provider ranked them, starting offset
+/// chunks into the provider's stream of chunks for the query
+pub(super) struct ChunkStream {
+    /// Key of the stream in QueryCursor::stream_offsets, see stream_key
+    pub(super) key: String,
+    pub(super) offset: u32,
+    pub(super) chunks: Vec<StreamedChunk>,
+}
+
+/// A chunk found by a provider, with its weighted score
+pub(super) struct StreamedChunk {
+    pub(super) path: Utf8PathBuf,
+    pub(super) score: f32,
+    pub(super) source: MatchSource,
+    /// Whether the chunk scored enough to count towards the score of its file, see FileQueryerBuilder::min_score
+    pub(super) counts: bool,
+}
+
+/// Key of the stream of chunks the provider finds for the query, the whole query being query 0 and its sub-queries
+/// (see MultiQuery) the ones after it
+pub(super) fn stream_key(provider_name: &str, query_index: usize) -> String {
+    if query_index == 0 {
+        provider_name.to_owned()
+    } else {
+        format!("{}#{}", provider_name, query_index)
+    }
+}
+
+/// Merges the streams by the weighted scores of their chunks, taking the best num_chunks chunks across every stream
+/// (or every chunk if num_chunks is 0), so a page holds the best chunks of any provider rather than num_chunks of
+/// each. Providers rank their chunks by their own scores, which the weights of their chunk types and the ranking
+/// boosts can reorder, so the chunks taken are only approximately the best. Chunks that do not count are taken from
+/// their streams but not returned.
+///
+/// Returns the chunks taken, best first, and where each stream continues on the next page, by stream key.
+pub(super) fn merge_streams(streams: Vec<ChunkStream>, num_chunks: u32) -> (Vec<StreamedChunk>, HashMap<String, u32>) {
+    let limit = if num_chunks > 0 { num_chunks as usize } else { usize::MAX };
+    let mut keys = Vec::with_capacity(streams.len());
+    let mut chunks: Vec<Vec<Option<StreamedChunk>>> = Vec::with_capacity(streams.len());
+    for stream in streams {
+        keys.push((stream.key, stream.offset));
+        chunks.push(stream.chunks.into_iter().map(Some).collect());
+    }
+
+    let mut heads: BinaryHeap<StreamHead> = chunks.iter().enumerate()
+        .filter_map(|(stream, stream_chunks)| stream_chunks.first().and_then(Option::as_ref)
+            .map(|chunk| StreamHead { score: chunk.score, stream, position: 0 }))
+        .collect();
+    let mut num_taken = vec![0u32; chunks.len()];
+    let mut merged = vec![];
+    for _ in 0..limit {
+        let Some(head) = heads.pop() else {
+            break;
+        };
+        num_taken[head.stream] += 1;
+        if let Some(chunk) = chunks[head.stream][head.position].take() {
+            if chunk.counts {
+                merged.push(chunk);
+            }
+        }
+        let next = head.position + 1;
+        if let Some(chunk) = chunks[head.stream].get(next).and_then(Option::as_ref) {
+            heads.push(StreamHead { score: chunk.score, stream: head.stream, position: next });
+        }
+    }
+
+    let next_offsets = keys.into_iter().zip(num_taken)
+        .map(|((key, offset), taken)| (key, offset + taken))
+        .collect();
+    (merged, next_offsets)
+}
+
+// Private structs
+
+/// The best chunk of a stream not taken yet
+struct StreamHead {
+    score: f32,
+    stream: usize,
+    position: usize,
+}
+
+impl PartialEq for StreamHead {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for StreamHead {}
+
+impl PartialOrd for StreamHead {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for StreamHead {
+    /// Higher scores first, and streams of earlier providers first among equal scores
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.score.total_cmp(&other.score).then_with(|| other.stream.cmp(&self.stream))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use serde_json::Map;
+
+    use crate::{index::{ChunkFile, ChunkType, language::UNDETERMINED_LANGUAGE, provider::score::ScoreNormalizer}, sequence};
+
+    use super::*;
+
+    const PROVIDER_NAME: &str = "TwoStoreTestProvider";
+
+    /// Raw scores of the text store, 90, 70, 50 and 30 once normalized
+    const TEXT_SCORES: [f32; 4] = [0.91, 0.73, 0.55, 0.37];
+    /// Raw scores of the image store, 85, 55, 25 and 5 once normalized
+    const IMAGE_SCORES: [f32; 4] = [0.2625, 0.1875, 0.1125, 0.0625];
+
+    fn chunkfile(name: &str, chunk_type: ChunkType) -> ChunkFile {
+        let now = Utc::now();
+        ChunkFile {
+            original_file: Utf8PathBuf::from(format!("/files/{}", name)),
+            chunk_channel: if chunk_type == ChunkType::Image { "image" } else { "text" }.to_owned(),
+            chunk_sequence_id: 0.0,
+            original_file_id: None,
+            chunkfile: Utf8PathBuf::from(format!("{}.chunk", name)),
+            chunk_type,
+            chunk_length: 1.0,
+            original_file_creation_date: now,
+            original_file_modified_date: now,
+            original_file_size: 1,
+            original_file_fingerprint: None,
+            original_file_tags: Map::new(),
+            language: UNDETERMINED_LANGUAGE.to_owned(),
+            user_tags: vec![],
+            sequence_number: sequence::next(),
+        }
+    }
+
+    /// Queries a provider keeping text chunks and image chunks in two stores the way the providers with two
+    /// stores do, each store returning its best store_results chunks by raw score
+    fn query_two_store_provider(num_results: u32, offset: u32) -> ChunkStream {
+        let store_results = offset.saturating_add(num_results) as usize;
+        let text_chunks = TEXT_SCORES.iter().enumerate().take(store_results)
+            .map(|(i, score)| (*score, chunkfile(&format!("text{}", i), ChunkType::Text)));
+        let image_chunks = IMAGE_SCORES.iter().enumerate().take(store_results)
+            .map(|(i, score)| (*score, chunkfile(&format!("image{}", i), ChunkType::Image)));
+
+        let results = ScoreNormalizer::for_provider(PROVIDER_NAME)
+            .normalize_page(text_chunks.chain(image_chunks), num_results, offset);
+        let chunks = results.iter().map(|cqr| StreamedChunk {
+            path: cqr.chunkfile().original_file.clone(),
+            score: cqr.score(),
+            source: MatchSource {
+                provider: PROVIDER_NAME.to_owned(),
+                channel: cqr.chunkfile().chunk_channel.clone(),
+                score: cqr.score(),
+            },
+            counts: true,
+        }).collect();
+        ChunkStream { key: stream_key(PROVIDER_NAME, 0), offset, chunks }
+    }
+
+    #[test]
+    fn pages_of_two_store_provider_continue_where_the_last_page_ended() {
+        let num_chunks = 3;
+        let (first_page, offsets) = merge_streams(vec![query_two_store_provider(num_chunks, 0)], num_chunks);
+        let offset = offsets[&stream_key(PROVIDER_NAME, 0)];
+        assert_eq!(offset, num_chunks);
+        let (second_page, _) = merge_streams(vec![query_two_store_provider(num_chunks, offset)], num_chunks);
+
+        let paths = |page: &[StreamedChunk]| page.iter().map(|c| c.path.file_name().unwrap().to_owned())
+            .collect::<Vec<_>>();
+        assert_eq!(paths(&first_page), ["text0", "image0", "text1"]);
+        assert_eq!(paths(&second_page), ["image1", "text2", "text3"]);
+        assert!(first_page.iter().chain(&second_page).map(|c| c.score).collect::<Vec<_>>()
+            .windows(2).all(|w| w[0] >= w[1]));
+    }
+}
@@ -0,0 +1,60 @@
+use std::collections::HashMap;
+
+use crate::{app_config, index::ChunkType};
+
+/// Multipliers applied to chunk scores before they are aggregated into file scores, so that results from
+/// some providers or chunk types can be favored over others.
+#[derive(Debug, Clone, Default)]
+pub struct ScoreWeights {
+    /// Weight per index provider name. Providers that are not present use a weight of 1.0
+    pub provider_weights: HashMap<String, f32>,
+    /// Weight per chunk type, eg. "text" or "image". Chunk types that are not present use a weight of 1.0
+    pub chunk_type_weights: HashMap<String, f32>,
+}
+
+impl ScoreWeights {
+    /// Loads score weights from the ranking.toml settings file.
+    pub fn from_settings() -> Result<ScoreWeights, anyhow::Error> {
+        let settings = app_config::get_ranking_config()?;
+        let mut weights = ScoreWeights::default();
+
+        if let Ok(provider_weights) = settings.get::<HashMap<String, f32>>("provider_weights") {
+            weights.provider_weights = provider_weights;
+        }
+        if let Ok(chunk_type_weights) = settings.get::<HashMap<String, f32>>("chunk_type_weights") {
+            weights.chunk_type_weights = chunk_type_weights;
+        }
+
+        for (name, weight) in weights.provider_weights.iter().chain(weights.chunk_type_weights.iter()) {
+            if *weight < 0.0 {
+                return Err(anyhow::anyhow!("Score weight for {} must not be negative, got {}", name, weight));
+            }
+        }
+
+        Ok(weights)
+    }
+
+    /// Returns the combined multiplier for a chunk returned by the given provider
+    pub fn weight(&self, provider_name: &str, chunk_type: &ChunkType) -> f32 {
+        let provider_weight = self.provider_weight(provider_name);
+        let chunk_type_weight = self.chunk_type_weights.get(chunk_type_key(chunk_type)).copied().unwrap_or(1.0);
+
+        provider_weight * chunk_type_weight
+    }
+
+    /// Returns the multiplier for results from the given provider, regardless of their chunk type
+    pub fn provider_weight(&self, provider_name: &str) -> f32 {
+        self.provider_weights.get(provider_name).copied().unwrap_or(1.0)
+    }
+}
+
+// Private functions
+
+fn chunk_type_key(chunk_type: &ChunkType) -> &'static str {
+    match chunk_type {
+        ChunkType::Text => "text",
+        ChunkType::Image => "image",
+        ChunkType::Video => "video",
+        ChunkType::Audio => "audio",
+    }
+}
@@ -0,0 +1,122 @@
+use std::{collections::HashSet, fs::{self, File, OpenOptions}};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use serde::{Deserialize, Serialize};
+
+use crate::app_config;
+
+/// Files waiting to be indexed by the file daemon. Processes that find the index busy (see IndexLock) queue their
+/// files here instead of writing to the index at the same time as the process holding the lock. Stored in the
+/// application data directory.
+///
+/// Changes to the stored queue (see enqueue and dequeue) hold a lock on the queue file from loading it to saving it,
+/// so processes queueing files at the same time do not drop each other's files, and are saved by replacing the file
+/// so a process exiting part way through saving does not leave it unreadable.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct IndexQueue {
+    paths: Vec<Utf8PathBuf>,
+}
+
+impl IndexQueue {
+    /// Loads the queue from the application data directory. The queue is empty if nothing has been queued yet.
+    pub fn load() -> Result<IndexQueue, anyhow::Error> {
+        load_from(&app_config::get_index_queue_file_path())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.paths.is_empty()
+    }
+
+    /// The queued paths, in the order they were queued
+    pub fn paths(&self) -> &[Utf8PathBuf] {
+        &self.paths
+    }
+
+    /// Adds paths to the end of the queue, leaving out paths that are already queued. Returns the number of paths
+    /// added.
+    pub fn push(&mut self, paths: impl IntoIterator<Item = Utf8PathBuf>) -> usize {
+        let queued = self.paths.len();
+        let mut seen: HashSet<Utf8PathBuf> = self.paths.iter().cloned().collect();
+        for path in paths {
+            if seen.insert(path.clone()) {
+                self.paths.push(path);
+            }
+        }
+        self.paths.len() - queued
+    }
+
+    /// Removes paths from the queue. Returns the number of paths removed.
+    pub fn remove<'a>(&mut self, paths: impl IntoIterator<Item = &'a Utf8PathBuf>) -> usize {
+        let removed: HashSet<&Utf8PathBuf> = paths.into_iter().collect();
+        let queued = self.paths.len();
+        self.paths.retain(|path| !removed.contains(path));
+        queued - self.paths.len()
+    }
+
+    /// Adds paths to the queue stored in the application data directory. Returns the number of paths added.
+    pub fn enqueue(paths: impl IntoIterator<Item = Utf8PathBuf>) -> Result<usize, anyhow::Error> {
+        update(&app_config::get_index_queue_file_path(), |queue| queue.push(paths))
+    }
+
+    /// Removes paths from the queue stored in the application data directory, once they have been indexed. Returns
+    /// the number of paths removed.
+    pub fn dequeue<'a>(paths: impl IntoIterator<Item = &'a Utf8PathBuf>) -> Result<usize, anyhow::Error> {
+        update(&app_config::get_index_queue_file_path(), |queue| queue.remove(paths))
+    }
+}
+
+// Private functions
+
+fn load_from(index_queue_file: &Utf8Path) -> Result<IndexQueue, anyhow::Error> {
+    if !fs::exists(index_queue_file)? {
+        return Ok(IndexQueue::default());
+    }
+
+    let contents = fs::read_to_string(index_queue_file)?;
+    Ok(serde_json::from_str(&contents)?)
+}
+
+/// Loads the queue, changes it and saves it, holding the lock on the queue file throughout
+fn update<R>(index_queue_file: &Utf8Path, change: impl FnOnce(&mut IndexQueue) -> R) -> Result<R, anyhow::Error> {
+    if let Some(parent) = index_queue_file.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let _lock = lock(index_queue_file)?;
+    let mut queue = load_from(index_queue_file)?;
+    let res = change(&mut queue);
+
+    // Renaming over the queue file replaces it in one step, so readers see either the old or the new queue
+    let temp_file_path = index_queue_file.with_extension("json.tmp");
+    fs::write(&temp_file_path, serde_json::to_string(&queue)?)?;
+    fs::rename(&temp_file_path, index_queue_file)?;
+    Ok(res)
+}
+
+/// Locks the queue file, waiting for any other process changing it. The queue file itself is replaced when saved,
+/// so the lock is taken on a separate file next to it. The lock is released when the returned file is closed.
+fn lock(index_queue_file: &Utf8Path) -> Result<File, anyhow::Error> {
+    let lock_file = OpenOptions::new().read(true).write(true).create(true).truncate(false)
+        .open(index_queue_file.with_extension("json.lock"))?;
+    lock_file.lock()?;
+    Ok(lock_file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn queued_files_are_kept_until_dequeued() {
+        let dir = tempfile::tempdir().unwrap();
+        let index_queue_file = Utf8PathBuf::from_path_buf(dir.path().join("index_queue.json")).unwrap();
+        let (a, b, c) = (Utf8PathBuf::from("/a"), Utf8PathBuf::from("/b"), Utf8PathBuf::from("/c"));
+
+        assert_eq!(update(&index_queue_file, |queue| queue.push([a.clone(), b.clone(), a.clone()])).unwrap(), 2);
+        assert_eq!(update(&index_queue_file, |queue| queue.push([b.clone(), c.clone()])).unwrap(), 1);
+        assert_eq!(load_from(&index_queue_file).unwrap().paths(), [a.clone(), b.clone(), c.clone()]);
+
+        assert_eq!(update(&index_queue_file, |queue| queue.remove([&b])).unwrap(), 1);
+        assert_eq!(load_from(&index_queue_file).unwrap().paths(), [a, c]);
+    }
+}
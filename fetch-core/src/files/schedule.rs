@@ -0,0 +1,257 @@
+use std::{fmt, str::FromStr};
+
+use chrono::{DateTime, Datelike, Local, NaiveDate, NaiveDateTime, TimeDelta, TimeZone, Timelike};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::app_config;
+
+/// Maintenance jobs the file daemon can run on a schedule, see JobScheduler
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ScheduledJobKind {
+    /// Indexes every file in the watched folders again, catching up on changes made while the daemon was not
+    /// running. Files that have not changed since they were indexed are skipped.
+    Reindex,
+    /// Clears the index entries of files that no longer exist. Files on drives that are not connected are kept.
+    Gc,
+    /// Purges cleared index entries past their retention window and compacts the index tables
+    Optimize,
+}
+
+impl fmt::Display for ScheduledJobKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScheduledJobKind::Reindex => write!(f, "reindex"),
+            ScheduledJobKind::Gc => write!(f, "gc"),
+            ScheduledJobKind::Optimize => write!(f, "optimize"),
+        }
+    }
+}
+
+/// A scheduled job as configured in the scheduled_jobs array of the daemon.toml settings file, eg.
+/// `{ job = "optimize", schedule = "0 3 * * 0" }`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScheduledJobSettings {
+    pub job: ScheduledJobKind,
+    /// When the job runs, as a cron expression, see CronSchedule
+    pub schedule: String,
+}
+
+/// A job and the times it runs at
+#[derive(Debug, Clone)]
+pub struct ScheduledJob {
+    pub kind: ScheduledJobKind,
+    pub schedule: CronSchedule,
+}
+
+/// Runs maintenance jobs at the times configured in the daemon.toml settings file. Jobs are run by the file daemon,
+/// so jobs due while it is not running are skipped until their next time.
+#[derive(Debug, Clone, Default)]
+pub struct JobScheduler {
+    jobs: Vec<ScheduledJob>,
+}
+
+impl JobScheduler {
+    pub fn using(jobs: Vec<ScheduledJob>) -> JobScheduler {
+        JobScheduler { jobs }
+    }
+
+    /// Loads the scheduled jobs from the daemon.toml settings file. Jobs whose schedule cannot be parsed are left
+    /// out. Returns no jobs if there are none.
+    pub fn from_settings() -> Result<JobScheduler, anyhow::Error> {
+        let settings = app_config::get_daemon_config()?;
+        let job_settings = match settings.get::<Vec<ScheduledJobSettings>>("scheduled_jobs") {
+            Ok(job_settings) => job_settings,
+            Err(config::ConfigError::NotFound(_)) => vec![],
+            Err(e) => return Err(e.into()),
+        };
+
+        let jobs = job_settings.into_iter()
+            .filter_map(|settings| match settings.schedule.parse::<CronSchedule>() {
+                Ok(schedule) => Some(ScheduledJob { kind: settings.job, schedule }),
+                Err(e) => {
+                    warn!("Ignoring scheduled {} job with invalid schedule {:?}: {}", settings.job, settings.schedule,
+                        e);
+                    None
+                },
+            })
+            .collect();
+        Ok(JobScheduler { jobs })
+    }
+
+    pub fn jobs(&self) -> &[ScheduledJob] {
+        &self.jobs
+    }
+
+    /// The next time each job runs after the given time, in the order of the jobs. Jobs whose schedule never comes
+    /// round, eg. on the 31st of February, have no next run.
+    pub fn next_runs(&self, after: DateTime<Local>) -> Vec<(&ScheduledJob, Option<DateTime<Local>>)> {
+        self.jobs.iter().map(|job| (job, job.schedule.next_after(after))).collect()
+    }
+
+    /// The jobs that run next after the given time, and when. Jobs scheduled for the same minute are run one after
+    /// the other, in the order of the jobs.
+    pub fn next_due(&self, after: DateTime<Local>) -> Option<(DateTime<Local>, Vec<ScheduledJobKind>)> {
+        let next_runs = self.next_runs(after);
+        let next = next_runs.iter().filter_map(|(_, run)| *run).min()?;
+        let kinds = next_runs.iter()
+            .filter(|(_, run)| *run == Some(next))
+            .map(|(job, _)| job.kind)
+            .collect();
+        Some((next, kinds))
+    }
+}
+
+/// Times of a cron expression, in local time: the minute, hour, day of the month, month and day of the week (0 or
+/// 7 for Sunday) separated by spaces, each of which is `*` for every value, a number, a range like `1-5`, a step
+/// like `*/15` or `0-30/10`, or a comma separated list of those. As in cron, a day matches if either the day of the
+/// month or the day of the week matches when both are restricted. `@hourly`, `@daily`, `@weekly` and `@monthly`
+/// stand for `0 * * * *`, `0 0 * * *`, `0 0 * * 0` and `0 0 1 * *`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronSchedule {
+    expression: String,
+    minutes: u64,
+    hours: u64,
+    days_of_month: u64,
+    months: u64,
+    days_of_week: u64,
+    days_of_month_restricted: bool,
+    days_of_week_restricted: bool,
+}
+
+impl CronSchedule {
+    pub fn expression(&self) -> &str {
+        &self.expression
+    }
+
+    /// The first time the schedule comes round after the given time, to the minute. Returns None if it does not
+    /// within the next few years.
+    pub fn next_after(&self, after: DateTime<Local>) -> Option<DateTime<Local>> {
+        let start = after.naive_local().with_second(0)?.with_nanosecond(0)? + TimeDelta::minutes(1);
+        let limit = start + TimeDelta::days(MAX_SEARCH_DAYS);
+        let mut time = start;
+        while time < limit {
+            if !has(self.months, time.month()) {
+                time = first_of_next_month(time.date())?.and_hms_opt(0, 0, 0)?;
+            } else if !self.matches_day(time.date()) {
+                time = time.date().succ_opt()?.and_hms_opt(0, 0, 0)?;
+            } else if !has(self.hours, time.hour()) {
+                time = time.with_minute(0)? + TimeDelta::hours(1);
+            } else if !has(self.minutes, time.minute()) {
+                time += TimeDelta::minutes(1);
+            } else {
+                // Times skipped when clocks go forward do not exist, and are moved past
+                match Local.from_local_datetime(&time).earliest() {
+                    Some(next) => return Some(next),
+                    None => time += TimeDelta::minutes(1),
+                }
+            }
+        }
+        None
+    }
+
+    fn matches_day(&self, date: NaiveDate) -> bool {
+        let day_of_month = has(self.days_of_month, date.day());
+        let day_of_week = has(self.days_of_week, date.weekday().num_days_from_sunday());
+        if self.days_of_month_restricted && self.days_of_week_restricted {
+            day_of_month || day_of_week
+        } else {
+            day_of_month && day_of_week
+        }
+    }
+}
+
+impl FromStr for CronSchedule {
+    type Err = anyhow::Error;
+
+    fn from_str(expression: &str) -> Result<Self, Self::Err> {
+        let expanded = match expression.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            other => other,
+        };
+        let fields: Vec<&str> = expanded.split_whitespace().collect();
+        let &[minutes, hours, days_of_month, months, days_of_week] = fields.as_slice() else {
+            return Err(anyhow::anyhow!("Expected 5 fields (minute, hour, day of month, month, day of week), got {}",
+                fields.len()));
+        };
+
+        let mut days_of_week_bits = parse_field(days_of_week, 0, 7, "day of week")?;
+        // Sunday is both 0 and 7
+        if has(days_of_week_bits, 7) {
+            days_of_week_bits |= 1;
+        }
+        Ok(CronSchedule {
+            expression: expression.trim().to_owned(),
+            minutes: parse_field(minutes, 0, 59, "minute")?,
+            hours: parse_field(hours, 0, 23, "hour")?,
+            days_of_month: parse_field(days_of_month, 1, 31, "day of month")?,
+            months: parse_field(months, 1, 12, "month")?,
+            days_of_week: days_of_week_bits,
+            days_of_month_restricted: !days_of_month.starts_with('*'),
+            days_of_week_restricted: !days_of_week.starts_with('*'),
+        })
+    }
+}
+
+// Private constants and functions
+
+/// How far ahead schedules are searched for their next time, long enough for the 29th of February to come round
+const MAX_SEARCH_DAYS: i64 = 366 * 5;
+
+fn has(bits: u64, value: u32) -> bool {
+    bits & (1 << value) != 0
+}
+
+fn first_of_next_month(date: NaiveDate) -> Option<NaiveDate> {
+    match date.month() {
+        12 => NaiveDate::from_ymd_opt(date.year() + 1, 1, 1),
+        month => NaiveDate::from_ymd_opt(date.year(), month + 1, 1),
+    }
+}
+
+/// Parses a field of a cron expression into a bit for each value it matches, between min and max inclusive
+fn parse_field(field: &str, min: u32, max: u32, name: &str) -> Result<u64, anyhow::Error> {
+    let parse_value = |value: &str| -> Result<u32, anyhow::Error> {
+        let value: u32 = value.parse()
+            .map_err(|_| anyhow::anyhow!("Invalid {} {:?}, expected a number", name, value))?;
+        if value < min || value > max {
+            return Err(anyhow::anyhow!("Invalid {} {}, expected {} to {}", name, value, min, max));
+        }
+        Ok(value)
+    };
+
+    let mut bits = 0;
+    for item in field.split(',') {
+        let (range, step) = match item.split_once('/') {
+            Some((range, step)) => {
+                let step: u32 = step.parse().ok().filter(|step| *step > 0)
+                    .ok_or_else(|| anyhow::anyhow!("Invalid {} step {:?}, expected a positive number", name, step))?;
+                (range, step)
+            },
+            None => (item, 1),
+        };
+        let (start, end) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((start, end)) => (parse_value(start)?, parse_value(end)?),
+                // A single value with a step, eg. 5/15, runs from the value to the end of the range
+                None if step > 1 => (parse_value(range)?, max),
+                None => {
+                    let value = parse_value(range)?;
+                    (value, value)
+                },
+            },
+        };
+        if start > end {
+            return Err(anyhow::anyhow!("Invalid {} range {:?}, the start is after the end", name, range));
+        }
+        for value in (start..=end).step_by(step as usize) {
+            bits |= 1 << value;
+        }
+    }
+    Ok(bits)
+}
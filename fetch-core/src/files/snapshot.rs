@@ -0,0 +1,212 @@
+use std::{collections::HashMap, fs, io};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use chrono::{DateTime, Local, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, warn};
+
+use crate::{app_config, store::lancedb::LanceDBTags};
+
+/// A point in time the index can be rolled back to, see IndexSnapshots
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexSnapshot {
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+    /// Tagged version of every index table, by table name
+    pub tables: HashMap<String, u64>,
+    pub num_chunkfiles: usize,
+}
+
+/// Warm backups of the index, which it can be rolled back to after a bad bulk operation (eg. clearing or re-tagging
+/// the wrong directory) without exporting and importing the whole index. A snapshot tags the current version of every
+/// index table, which keeps it from being pruned when the tables are optimized, and hard links every chunkfile into
+/// the snapshot directory, so it takes up little space until the index moves on from it. Chunkfiles are written as new
+/// files whenever a file is indexed again, so the linked chunkfiles keep the contents they had when the snapshot was
+/// taken.
+///
+/// Snapshots are kept in the snapshot directory (see app_config::get_default_snapshot_directory), one directory per
+/// snapshot, with the snapshot described in a snapshot.json file and the linked chunkfiles under chunk/. Only the
+/// index tables and chunkfiles are snapshotted, not tags, previews or the other data of Fetch.
+pub struct IndexSnapshots {
+    index_directory: Utf8PathBuf,
+    chunk_directory: Utf8PathBuf,
+    snapshot_directory: Utf8PathBuf,
+}
+
+impl IndexSnapshots {
+    pub fn using(index_directory: Utf8PathBuf, chunk_directory: Utf8PathBuf, snapshot_directory: Utf8PathBuf)
+        -> IndexSnapshots
+    {
+        IndexSnapshots { index_directory, chunk_directory, snapshot_directory }
+    }
+
+    /// Snapshots of the default index, chunk and snapshot directories
+    pub fn default_directories() -> IndexSnapshots {
+        IndexSnapshots::using(app_config::get_default_index_directory(), app_config::get_default_chunk_directory(),
+            app_config::get_default_snapshot_directory())
+    }
+
+    /// Takes a snapshot of the index under the name, or under the current time if no name is given. Names must be
+    /// made of letters, digits, '.', '-' and '_', and not start with '.'. Nothing else should write to the index while
+    /// the snapshot is taken, see lock::IndexLock.
+    pub async fn create(&self, name: Option<&str>) -> Result<IndexSnapshot, anyhow::Error> {
+        let name = match name {
+            Some(name) => name.to_owned(),
+            None => Local::now().format("%Y%m%d-%H%M%S").to_string(),
+        };
+        check_snapshot_name(&name)?;
+        let snapshot_dir = self.snapshot_directory.join(&name);
+        if fs::exists(&snapshot_dir)? {
+            anyhow::bail!("A snapshot named {} already exists", name);
+        }
+
+        let created = self.create_in(&name, &snapshot_dir).await;
+        if created.is_err() {
+            if let Err(e) = fs::remove_dir_all(&snapshot_dir) {
+                warn!("Could not remove the snapshot directory {} of a failed snapshot: {:?}", snapshot_dir, e);
+            }
+        }
+        created
+    }
+
+    /// Every snapshot, oldest first. Snapshot directories without a readable snapshot.json, eg. of snapshots still
+    /// being taken, are skipped.
+    pub fn list(&self) -> Result<Vec<IndexSnapshot>, anyhow::Error> {
+        let mut snapshots = vec![];
+        for entry in fs::read_dir(&self.snapshot_directory)? {
+            let entry = entry?;
+            let Ok(name) = entry.file_name().into_string() else {
+                continue;
+            };
+            match self.get(&name) {
+                Ok(Some(snapshot)) => snapshots.push(snapshot),
+                Ok(None) => debug!("Skipping snapshot directory {} without a snapshot.json", name),
+                Err(e) => warn!("Skipping snapshot {} that could not be read: {:?}", name, e),
+            }
+        }
+        snapshots.sort_by_key(|snapshot| snapshot.created_at);
+        Ok(snapshots)
+    }
+
+    /// The snapshot with the name, if there is one
+    pub fn get(&self, name: &str) -> Result<Option<IndexSnapshot>, anyhow::Error> {
+        let manifest = self.snapshot_directory.join(name).join(MANIFEST_FILE_NAME);
+        if !fs::exists(&manifest)? {
+            return Ok(None);
+        }
+        Ok(Some(serde_json::from_str(&fs::read_to_string(&manifest)?)?))
+    }
+
+    /// Rolls the index back to the snapshot. The index tables are restored to their tagged versions as new versions,
+    /// so the restore can be undone by taking a snapshot before it. Tables created after the snapshot was taken are
+    /// left as they are. The chunk directory is made to match the snapshot, removing chunkfiles written since. Nothing
+    /// else should write to the index while it is restored, see lock::IndexLock.
+    pub async fn restore(&self, name: &str) -> Result<IndexSnapshot, anyhow::Error> {
+        let snapshot = self.get(name)?
+            .ok_or_else(|| anyhow::anyhow!("There is no snapshot named {}", name))?;
+
+        let tags = LanceDBTags::local(self.index_directory.as_str()).await?;
+        let restored = tags.restore_all(&snapshot_tag(name)).await?;
+        info!("Restored {} index tables to snapshot {}", restored.len(), name);
+
+        let snapshot_chunks = self.snapshot_directory.join(name).join(CHUNK_DIRECTORY_NAME);
+        remove_unsnapshotted(&self.chunk_directory, &snapshot_chunks)?;
+        let num_linked = link_tree(&snapshot_chunks, &self.chunk_directory, true)?;
+        info!("Restored {} chunkfiles from snapshot {}", num_linked, name);
+
+        Ok(snapshot)
+    }
+
+    /// Deletes the snapshot, so the table versions and chunkfiles only it kept can be pruned
+    pub async fn delete(&self, name: &str) -> Result<(), anyhow::Error> {
+        if self.get(name)?.is_none() {
+            anyhow::bail!("There is no snapshot named {}", name);
+        }
+
+        let tags = LanceDBTags::local(self.index_directory.as_str()).await?;
+        tags.delete_all(&snapshot_tag(name)).await?;
+        fs::remove_dir_all(self.snapshot_directory.join(name))?;
+        Ok(())
+    }
+
+    async fn create_in(&self, name: &str, snapshot_dir: &Utf8Path) -> Result<IndexSnapshot, anyhow::Error> {
+        fs::create_dir_all(snapshot_dir)?;
+        let num_chunkfiles = link_tree(&self.chunk_directory, &snapshot_dir.join(CHUNK_DIRECTORY_NAME), false)?;
+        debug!("Linked {} chunkfiles into snapshot {}", num_chunkfiles, name);
+
+        let tags = LanceDBTags::local(self.index_directory.as_str()).await?;
+        let tables = tags.tag_all(&snapshot_tag(name)).await?;
+
+        let snapshot = IndexSnapshot { name: name.to_owned(), created_at: Utc::now(), tables, num_chunkfiles };
+        // Written last, as it marks the snapshot as complete
+        fs::write(snapshot_dir.join(MANIFEST_FILE_NAME), serde_json::to_string_pretty(&snapshot)?)?;
+        info!("Took snapshot {} of {} index tables and {} chunkfiles", name, snapshot.tables.len(), num_chunkfiles);
+        Ok(snapshot)
+    }
+}
+
+// Private constants and functions
+
+const MANIFEST_FILE_NAME: &str = "snapshot.json";
+const CHUNK_DIRECTORY_NAME: &str = "chunk";
+/// Prefix of the tags of snapshotted table versions, keeping them apart from tags made by anything else
+const SNAPSHOT_TAG_PREFIX: &str = "fetch-snapshot-";
+
+fn snapshot_tag(name: &str) -> String {
+    format!("{}{}", SNAPSHOT_TAG_PREFIX, name)
+}
+
+/// Snapshot names are used as directory names and in table tags, which only allow some characters
+fn check_snapshot_name(name: &str) -> Result<(), anyhow::Error> {
+    if name.is_empty() || name.starts_with('.')
+        || !name.chars().all(|c| c.is_alphanumeric() || matches!(c, '.' | '-' | '_')) {
+        anyhow::bail!("Snapshot name {:?} must be made of letters, digits, '.', '-' and '_', and not start with '.'",
+            name);
+    }
+    Ok(())
+}
+
+/// Hard links every file under the source directory to the same path under the destination directory, copying
+/// files that cannot be linked, eg. across volumes. Files already at the destination are replaced if replace is
+/// set. Returns the number of files linked or copied.
+fn link_tree(source: &Utf8Path, destination: &Utf8Path, replace: bool) -> Result<usize, io::Error> {
+    fs::create_dir_all(destination)?;
+    let mut num_files = 0;
+    for entry in source.read_dir_utf8()? {
+        let entry = entry?;
+        let target = destination.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            num_files += link_tree(entry.path(), &target, replace)?;
+            continue;
+        }
+
+        if replace {
+            match fs::remove_file(&target) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                _ => {},
+            }
+        }
+        if let Err(e) = fs::hard_link(entry.path(), &target) {
+            debug!("Could not link {} to {}, copying it instead: {:?}", entry.path(), target, e);
+            fs::copy(entry.path(), &target)?;
+        }
+        num_files += 1;
+    }
+    Ok(num_files)
+}
+
+/// Removes everything under the directory that is not under the same path in the snapshot directory
+fn remove_unsnapshotted(directory: &Utf8Path, snapshot_directory: &Utf8Path) -> Result<(), io::Error> {
+    for entry in directory.read_dir_utf8()? {
+        let entry = entry?;
+        let snapshotted = snapshot_directory.join(entry.file_name());
+        let is_dir = entry.file_type()?.is_dir();
+        match (is_dir, fs::exists(&snapshotted)?) {
+            (true, true) => remove_unsnapshotted(entry.path(), &snapshotted)?,
+            (true, false) => fs::remove_dir_all(entry.path())?,
+            (false, true) => {},
+            (false, false) => fs::remove_file(entry.path())?,
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,140 @@
+use std::{collections::HashMap, fs, sync::Arc};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, instrument, warn};
+
+use crate::{app_config, index::{ChunkFile, ChunkType, embedding::{EmbeddingError, embeddinggemma::EmbeddingGemmaEmbeddedChunkFile}, summarize::Summarizer}, store::{Filter, FilterRelation, FilterStoreError, FilterValue, QueryByFilter}};
+
+#[derive(thiserror::Error, Debug)]
+pub enum FileSummaryError {
+    #[error("No indexed text found to summarize for file {path}")]
+    NoText { path: Utf8PathBuf },
+    #[error("Error retrieving text chunks for file {path}")]
+    Store { path: Utf8PathBuf, #[source] source: FilterStoreError },
+    #[error("Error reading text chunk {chunkfile}")]
+    IO { chunkfile: Utf8PathBuf, #[source] source: std::io::Error },
+    #[error("Error summarizing file {path}")]
+    Summarizing { path: Utf8PathBuf, #[source] source: EmbeddingError },
+}
+
+/// Summarizes indexed files from their text chunks. Summaries are cached in the application data directory
+/// and regenerated when the file is reindexed with a new modified date.
+pub struct FileSummarizer<S>
+where
+    S: QueryByFilter<EmbeddingGemmaEmbeddedChunkFile> + Send + Sync
+{
+    text_store: Arc<S>,
+    summarizer: Summarizer,
+}
+
+impl<S> FileSummarizer<S>
+where
+    S: QueryByFilter<EmbeddingGemmaEmbeddedChunkFile> + Send + Sync
+{
+    pub fn using(text_store: Arc<S>, summarizer: Summarizer) -> FileSummarizer<S> {
+        FileSummarizer { text_store, summarizer }
+    }
+
+    /// Returns a few sentence summary of the file, generated from the start of its indexed text.
+    #[instrument(name = "summarize_file", skip(self))]
+    pub async fn summarize(&self, path: &Utf8Path) -> Result<String, FileSummaryError> {
+        let mut chunks = self.text_store.query_filter(&[Filter {
+            attribute: ChunkFile::ORIGINAL_FILE_ATTR,
+            filter: FilterValue::String(path.as_str()),
+            relation: FilterRelation::Eq,
+        }]).await
+            .map_err(|e| FileSummaryError::Store { path: path.to_owned(), source: e })?
+            .into_iter()
+            .map(|c| c.chunkfile)
+            .filter(|c| c.chunk_type == ChunkType::Text)
+            .collect::<Vec<ChunkFile>>();
+        let modified = match chunks.first() {
+            Some(chunk) => chunk.original_file_modified_date,
+            None => return Err(FileSummaryError::NoText { path: path.to_owned() }),
+        };
+
+        let mut cache = SummaryCache::load().unwrap_or_else(|e| {
+            warn!("Could not load summary cache, regenerating summary: {:?}", e);
+            SummaryCache::default()
+        });
+        if let Some(summary) = cache.get(path, &modified) {
+            debug!("Using cached summary for {}", path);
+            return Ok(summary.to_owned());
+        }
+
+        // Summarize the start of the document, in reading order
+        chunks.sort_by(|a, b| a.chunk_sequence_id.total_cmp(&b.chunk_sequence_id));
+        let mut text = String::new();
+        for chunk in chunks {
+            if text.len() >= MAX_SUMMARY_INPUT_BYTES {
+                break;
+            }
+            let chunk_text = tokio::fs::read_to_string(&chunk.chunkfile).await
+                .map_err(|e| FileSummaryError::IO { chunkfile: chunk.chunkfile.clone(), source: e })?;
+            text.push_str(&chunk_text);
+            text.push('\n');
+        }
+        if text.trim().is_empty() {
+            return Err(FileSummaryError::NoText { path: path.to_owned() });
+        }
+
+        let summary = self.summarizer.summarize(text).await
+            .map_err(|e| FileSummaryError::Summarizing { path: path.to_owned(), source: e })?;
+
+        cache.insert(path, modified, summary.clone());
+        cache.save().unwrap_or_else(|e| warn!("Could not save summary cache: {:?}", e));
+
+        Ok(summary)
+    }
+}
+
+// Private variables, structs and functions
+
+// The summarization model reads at most 1024 tokens, so there is no point reading much more text than that
+const MAX_SUMMARY_INPUT_BYTES: usize = 6000;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct SummaryCache {
+    summaries: HashMap<Utf8PathBuf, CachedSummary>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CachedSummary {
+    /// Modified date of the file when the summary was generated, in milliseconds since the epoch
+    modified_millis: i64,
+    summary: String,
+}
+
+impl SummaryCache {
+    fn load() -> Result<SummaryCache, anyhow::Error> {
+        let cache_file = app_config::get_summary_cache_file_path();
+        if !fs::exists(&cache_file)? {
+            return Ok(SummaryCache::default());
+        }
+
+        let contents = fs::read_to_string(&cache_file)?;
+        Ok(serde_json::from_str(&contents)?)
+    }
+
+    fn save(&self) -> Result<(), anyhow::Error> {
+        let cache_file = app_config::get_summary_cache_file_path();
+        if let Some(parent) = cache_file.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        fs::write(&cache_file, serde_json::to_string(self)?)?;
+        Ok(())
+    }
+
+    fn get(&self, path: &Utf8Path, modified: &DateTime<Utc>) -> Option<&str> {
+        self.summaries.get(path)
+            .filter(|cached| cached.modified_millis == modified.timestamp_millis())
+            .map(|cached| cached.summary.as_str())
+    }
+
+    fn insert(&mut self, path: &Utf8Path, modified: DateTime<Utc>, summary: String) {
+        self.summaries.insert(path.to_owned(), CachedSummary { modified_millis: modified.timestamp_millis(), summary });
+    }
+}
@@ -0,0 +1,92 @@
+use std::{collections::{BTreeMap, BTreeSet, HashMap}, fs};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use serde::{Deserialize, Serialize};
+
+use crate::app_config;
+
+/// Tags the user has manually added to files, eg. "taxes-2024". Tags are stored in the application data
+/// directory and copied onto the indexed chunks of each file, so they can be searched and filtered on. Since
+/// reindexing a file replaces its chunks, the tags recorded here are the source of truth.
+///
+/// Tags are stored normalized, see normalize_tag.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UserTags {
+    tags: HashMap<Utf8PathBuf, BTreeSet<String>>,
+}
+
+impl UserTags {
+    /// Loads user tags from the application data directory. Returns no tags if none have been added yet.
+    pub fn load() -> Result<UserTags, anyhow::Error> {
+        let tags_file = app_config::get_user_tags_file_path();
+        if !fs::exists(&tags_file)? {
+            return Ok(UserTags::default());
+        }
+
+        let contents = fs::read_to_string(&tags_file)?;
+        Ok(serde_json::from_str(&contents)?)
+    }
+
+    /// Saves user tags to the application data directory.
+    pub fn save(&self) -> Result<(), anyhow::Error> {
+        let tags_file = app_config::get_user_tags_file_path();
+        if let Some(parent) = tags_file.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        fs::write(&tags_file, serde_json::to_string(self)?)?;
+        Ok(())
+    }
+
+    /// Adds a normalized tag to a file. Returns false if the file already had the tag.
+    pub fn add(&mut self, file: &Utf8Path, tag: &str) -> bool {
+        self.tags.entry(file.to_owned()).or_default().insert(tag.to_owned())
+    }
+
+    /// Removes a normalized tag from a file. Returns false if the file did not have the tag.
+    pub fn remove(&mut self, file: &Utf8Path, tag: &str) -> bool {
+        let removed = self.tags.get_mut(file).is_some_and(|tags| tags.remove(tag));
+        if self.tags.get(file).is_some_and(BTreeSet::is_empty) {
+            self.tags.remove(file);
+        }
+        removed
+    }
+
+    /// Moves the tags of a file to its new path, eg. after the file is moved. Returns false if the file had no tags.
+    pub fn move_file(&mut self, from: &Utf8Path, to: &Utf8Path) -> bool {
+        match self.tags.remove(from) {
+            Some(tags) => {
+                self.tags.entry(to.to_owned()).or_default().extend(tags);
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Returns the tags of a file in alphabetical order
+    pub fn tags(&self, file: &Utf8Path) -> Vec<String> {
+        self.tags.get(file).map(|tags| tags.iter().cloned().collect()).unwrap_or_default()
+    }
+
+    /// Returns every tag in use, along with the number of files that have it, in alphabetical order
+    pub fn tag_counts(&self) -> BTreeMap<String, usize> {
+        let mut counts = BTreeMap::new();
+        for tag in self.tags.values().flatten() {
+            *counts.entry(tag.clone()).or_insert(0) += 1;
+        }
+        counts
+    }
+}
+
+/// Normalizes a tag entered by the user by trimming and lowercasing it. Returns None if the tag is empty or
+/// contains characters other than letters, numbers, '-', '_' and '.', since tags are single query words.
+pub fn normalize_tag(tag: &str) -> Option<String> {
+    let tag = tag.trim().to_lowercase();
+    let is_valid = !tag.is_empty() && tag.chars().all(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    is_valid.then_some(tag)
+}
+
+
+pub use rules::{TagRule, TagRules};
+
+mod rules;
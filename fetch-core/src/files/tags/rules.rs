@@ -0,0 +1,112 @@
+use camino::{Utf8Path, Utf8PathBuf};
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::{app_config, files::tags::normalize_tag};
+
+/// A rule tagging the files it matches as they are indexed, eg. every file under ~/Receipts with receipt, or every
+/// file in ~/Downloads not modified for 30 days with stale. Files match if they are under the path and older than
+/// the age the rule sets, whichever of the two it sets. Configured by the tag_rules array of the daemon.toml
+/// settings file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TagRule {
+    /// Tag given to matching files, normalized like user tags, see normalize_tag
+    pub tag: String,
+    /// Directory files must be under. A leading "~" stands for the home directory.
+    #[serde(default)]
+    pub path: Option<String>,
+    /// Days since files were last modified they must be older than
+    #[serde(default)]
+    pub older_than_days: Option<u32>,
+}
+
+impl TagRule {
+    /// Whether the file matches the rule, as of now. Files whose modified date is not known are not older than any
+    /// age.
+    pub fn matches(&self, path: &Utf8Path, modified: Option<DateTime<Utc>>, now: DateTime<Utc>) -> bool {
+        let is_under_path = match &self.path {
+            Some(directory) => expand_home(directory).is_some_and(|directory| path.starts_with(directory)),
+            None => true,
+        };
+        let is_old_enough = match self.older_than_days {
+            Some(days) => modified.is_some_and(|modified| now - modified > TimeDelta::days(days.into())),
+            None => true,
+        };
+        is_under_path && is_old_enough
+    }
+}
+
+/// The tag rules files are tagged by as they are indexed, see TagRule. Tags given by rules are stored on the
+/// indexed chunks of files along with their user tags, so they are searched and filtered on the same way, but they
+/// are not recorded as user tags (see UserTags). They are worked out again every time a file is indexed or tagged,
+/// so a file moved out of a rule's directory loses the rule's tag, and a file only gains a tag for its age once it
+/// is indexed again after reaching that age.
+#[derive(Debug, Clone, Default)]
+pub struct TagRules {
+    rules: Vec<TagRule>,
+}
+
+impl TagRules {
+    /// Uses the rules, leaving out those that are invalid, ie. whose tag is not a valid tag or that match every
+    /// file
+    pub fn using(rules: Vec<TagRule>) -> TagRules {
+        let rules = rules.into_iter()
+            .filter_map(|rule| {
+                if rule.path.is_none() && rule.older_than_days.is_none() {
+                    warn!("Ignoring tag rule for tag {:?} without a path or older_than_days, which would tag every \
+                        file", rule.tag);
+                    return None;
+                }
+                match normalize_tag(&rule.tag) {
+                    Some(tag) => Some(TagRule { tag, ..rule }),
+                    None => {
+                        warn!("Ignoring tag rule with invalid tag {:?}", rule.tag);
+                        None
+                    },
+                }
+            })
+            .collect();
+        TagRules { rules }
+    }
+
+    /// Loads the tag rules from the tag_rules array of the daemon.toml settings file. Returns no rules if there are
+    /// none.
+    pub fn from_settings() -> Result<TagRules, anyhow::Error> {
+        let settings = app_config::get_daemon_config()?;
+        match settings.get::<Vec<TagRule>>("tag_rules") {
+            Ok(rules) => Ok(TagRules::using(rules)),
+            Err(config::ConfigError::NotFound(_)) => Ok(TagRules::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn rules(&self) -> &[TagRule] {
+        &self.rules
+    }
+
+    /// The tags of the rules the file matches, in the order of the rules, without duplicates
+    pub fn tags(&self, path: &Utf8Path, modified: Option<DateTime<Utc>>) -> Vec<String> {
+        let now = Utc::now();
+        let mut tags: Vec<String> = vec![];
+        for rule in self.rules.iter().filter(|rule| rule.matches(path, modified, now)) {
+            if !tags.contains(&rule.tag) {
+                tags.push(rule.tag.clone());
+            }
+        }
+        tags
+    }
+}
+
+// Private functions
+
+/// Expands a leading "~" into the home directory. Returns None if the home directory is not known.
+fn expand_home(directory: &str) -> Option<Utf8PathBuf> {
+    match directory.strip_prefix('~') {
+        Some(rest) if rest.is_empty() || rest.starts_with(['/', '\\']) => {
+            let home = dirs::home_dir().and_then(|home| Utf8PathBuf::from_path_buf(home).ok())?;
+            Some(home.join(rest.trim_start_matches(['/', '\\'])))
+        },
+        _ => Some(Utf8PathBuf::from(directory)),
+    }
+}
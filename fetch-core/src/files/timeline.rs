@@ -0,0 +1,170 @@
+use std::{cmp::Ordering, collections::{BTreeMap, HashMap}, sync::Arc};
+
+use camino::Utf8PathBuf;
+use chrono::{DateTime, Datelike, Days, Local, Months, NaiveDate, TimeZone, Utc};
+use tracing::{debug, instrument, warn};
+
+use crate::{files::affinity::DirectoryAffinity, index::{ChunkFile, embedding::{embeddinggemma::EmbeddingGemmaEmbeddedChunkFile, siglip2::Siglip2EmbeddedChunkFile}, provider::{ChunkQueryFilters, DateRange}}, store::{FilterStoreError, QueryByFilter}};
+
+#[derive(thiserror::Error, Debug)]
+pub enum TimelineError {
+    #[error("Error retrieving indexed chunks from the {store} store")]
+    Store { store: &'static str, #[source] source: FilterStoreError },
+}
+
+/// The file date a timeline is built from
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimelineDate {
+    Created,
+    Modified,
+}
+
+/// The length of time covered by each bucket of a timeline. Buckets start at midnight in the local timezone,
+/// weeks start on Monday.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimelineGranularity {
+    Day,
+    Week,
+    Month,
+    Year,
+}
+
+/// The indexed files dated within a period of time
+#[derive(Debug, Clone)]
+pub struct TimelineBucket {
+    /// Inclusive start of the period
+    pub start: DateTime<Utc>,
+    /// Exclusive end of the period
+    pub end: DateTime<Utc>,
+    pub num_files: usize,
+    /// The files in the period the user is most likely to be looking for, from the directories they open files
+    /// from most often, then most recent first
+    pub top_files: Vec<TimelineFile>,
+}
+
+#[derive(Debug, Clone)]
+pub struct TimelineFile {
+    pub path: Utf8PathBuf,
+    pub date: DateTime<Utc>,
+}
+
+/// Buckets indexed files by their creation or modified dates, eg. to show what the user was working on in a month
+pub struct FileTimeline<IS, TS>
+where
+    IS: QueryByFilter<Siglip2EmbeddedChunkFile> + Send + Sync,
+    TS: QueryByFilter<EmbeddingGemmaEmbeddedChunkFile> + Send + Sync,
+{
+    image_store: Arc<IS>,
+    text_store: Arc<TS>,
+    top_files: usize,
+}
+
+impl<IS, TS> FileTimeline<IS, TS>
+where
+    IS: QueryByFilter<Siglip2EmbeddedChunkFile> + Send + Sync,
+    TS: QueryByFilter<EmbeddingGemmaEmbeddedChunkFile> + Send + Sync,
+{
+    pub fn using(image_store: Arc<IS>, text_store: Arc<TS>) -> FileTimeline<IS, TS> {
+        FileTimeline { image_store, text_store, top_files: DEFAULT_TOP_FILES }
+    }
+
+    /// Sets the number of top files returned with each bucket. Defaults to 5.
+    pub fn with_top_files(mut self, top_files: usize) -> FileTimeline<IS, TS> {
+        self.top_files = top_files;
+        self
+    }
+
+    /// Returns the buckets of the timeline that contain indexed files within the range, in chronological order.
+    /// Either end of the range may be open.
+    #[instrument(skip(self))]
+    pub async fn timeline(&self, date: TimelineDate, granularity: TimelineGranularity, range: DateRange)
+        -> Result<Vec<TimelineBucket>, TimelineError>
+    {
+        let filters = match date {
+            TimelineDate::Created => ChunkQueryFilters { created: Some(range), ..Default::default() },
+            TimelineDate::Modified => ChunkQueryFilters { modified: Some(range), ..Default::default() },
+        };
+        let store_filters = filters.store_filters();
+        let image_chunks = self.image_store.query_filter(&store_filters).await
+            .map_err(|e| TimelineError::Store { store: "image", source: e })?
+            .into_iter()
+            .map(|c| c.chunkfile);
+        let text_chunks = self.text_store.query_filter(&store_filters).await
+            .map_err(|e| TimelineError::Store { store: "text", source: e })?
+            .into_iter()
+            .map(|c| c.chunkfile);
+
+        let mut files: HashMap<Utf8PathBuf, DateTime<Utc>> = HashMap::new();
+        for chunkfile in image_chunks.chain(text_chunks) {
+            let file_date = file_date(&chunkfile, date);
+            files.insert(chunkfile.original_file, file_date);
+        }
+        debug!("Building timeline of {} indexed files", files.len());
+
+        let affinity = DirectoryAffinity::load().unwrap_or_else(|e| {
+            warn!("Could not load open history, ordering timeline files by date only: {:?}", e);
+            DirectoryAffinity::default()
+        });
+
+        let mut buckets: BTreeMap<NaiveDate, Vec<TimelineFile>> = BTreeMap::new();
+        for (path, date) in files {
+            let bucket_start = bucket_start(date.with_timezone(&Local).date_naive(), granularity);
+            buckets.entry(bucket_start).or_default().push(TimelineFile { path, date });
+        }
+
+        Ok(buckets.into_iter()
+            .map(|(start, mut files)| {
+                files.sort_by(|a, b| affinity.affinity(&b.path).partial_cmp(&affinity.affinity(&a.path))
+                    .unwrap_or(Ordering::Equal)
+                    .then(b.date.cmp(&a.date)));
+                let num_files = files.len();
+                files.truncate(self.top_files);
+                TimelineBucket {
+                    start: to_utc(start),
+                    end: to_utc(bucket_end(start, granularity)),
+                    num_files,
+                    top_files: files,
+                }
+            })
+            .collect())
+    }
+}
+
+// Private functions and variables
+
+const DEFAULT_TOP_FILES: usize = 5;
+
+fn file_date(chunkfile: &ChunkFile, date: TimelineDate) -> DateTime<Utc> {
+    match date {
+        TimelineDate::Created => chunkfile.original_file_creation_date,
+        TimelineDate::Modified => chunkfile.original_file_modified_date,
+    }
+}
+
+fn bucket_start(date: NaiveDate, granularity: TimelineGranularity) -> NaiveDate {
+    match granularity {
+        TimelineGranularity::Day => date,
+        TimelineGranularity::Week => date - Days::new(date.weekday().num_days_from_monday() as u64),
+        TimelineGranularity::Month => date.with_day(1).expect("First of the month should always be valid"),
+        TimelineGranularity::Year => NaiveDate::from_ymd_opt(date.year(), 1, 1)
+            .expect("First of the year should always be valid"),
+    }
+}
+
+fn bucket_end(start: NaiveDate, granularity: TimelineGranularity) -> NaiveDate {
+    match granularity {
+        TimelineGranularity::Day => start + Days::new(1),
+        TimelineGranularity::Week => start + Days::new(7),
+        TimelineGranularity::Month => start + Months::new(1),
+        TimelineGranularity::Year => start + Months::new(12),
+    }
+}
+
+/// Converts the start of a day in the local timezone to UTC
+fn to_utc(date: NaiveDate) -> DateTime<Utc> {
+    let midnight = date.and_hms_opt(0, 0, 0).expect("Midnight should always be a valid time");
+    Local.from_local_datetime(&midnight)
+        .earliest()
+        .map(|d| d.with_timezone(&Utc))
+        .unwrap_or_else(|| midnight.and_utc())
+}
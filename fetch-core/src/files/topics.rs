@@ -0,0 +1,242 @@
+use std::{cmp::Ordering, collections::{BTreeMap, HashMap, HashSet}, sync::Arc};
+
+use camino::Utf8PathBuf;
+use tracing::{debug, instrument};
+
+use crate::{index::{ChunkFile, embedding::{embeddinggemma::EmbeddingGemmaEmbeddedChunkFile, siglip2::Siglip2EmbeddedChunkFile}, provider::chunkfile_vocabulary}, store::{FilterStoreError, QueryByFilter}};
+
+#[derive(thiserror::Error, Debug)]
+pub enum TopicClusteringError {
+    #[error("Number of clusters must be at least 1")]
+    InvalidClusterCount,
+    #[error("Error retrieving indexed chunks from the {store} store")]
+    Store { store: &'static str, #[source] source: FilterStoreError },
+    #[error("Join error occurred while clustering files")]
+    Join { #[source] source: tokio::task::JoinError },
+}
+
+/// A group of indexed files about a similar topic
+#[derive(Debug, Clone)]
+pub struct Topic {
+    /// Words from the names and tags of the files that best describe the topic, most representative first. May
+    /// be empty if the files have nothing distinctive in common.
+    pub terms: Vec<String>,
+    /// The files in the topic, most representative first
+    pub files: Vec<Utf8PathBuf>,
+}
+
+/// Groups indexed files into topics by running k-means over their stored embeddings
+pub struct TopicClusterer<IS, TS>
+where
+    IS: QueryByFilter<Siglip2EmbeddedChunkFile> + Send + Sync,
+    TS: QueryByFilter<EmbeddingGemmaEmbeddedChunkFile> + Send + Sync,
+{
+    image_store: Arc<IS>,
+    text_store: Arc<TS>,
+}
+
+impl<IS, TS> TopicClusterer<IS, TS>
+where
+    IS: QueryByFilter<Siglip2EmbeddedChunkFile> + Send + Sync,
+    TS: QueryByFilter<EmbeddingGemmaEmbeddedChunkFile> + Send + Sync,
+{
+    pub fn using(image_store: Arc<IS>, text_store: Arc<TS>) -> TopicClusterer<IS, TS> {
+        TopicClusterer { image_store, text_store }
+    }
+
+    /// Clusters every indexed file into at most n_clusters topics, ordered by descending number of files.
+    ///
+    /// Image and text embeddings come from different models, so they are clustered separately and the clusters
+    /// are split between them in proportion to the number of files in each. Each file is represented by the
+    /// average of its chunk embeddings, and centroids are trained on a sample of the files before every file is
+    /// assigned to its nearest centroid.
+    #[instrument(skip(self))]
+    pub async fn cluster_index(&self, n_clusters: usize) -> Result<Vec<Topic>, TopicClusteringError> {
+        if n_clusters == 0 {
+            return Err(TopicClusteringError::InvalidClusterCount);
+        }
+
+        let image_chunks = self.image_store.query_filter(&[]).await
+            .map_err(|e| TopicClusteringError::Store { store: "image", source: e })?
+            .into_iter()
+            .map(|c| (c.chunkfile, c.embedding));
+        let text_chunks = self.text_store.query_filter(&[]).await
+            .map_err(|e| TopicClusteringError::Store { store: "text", source: e })?
+            .into_iter()
+            .map(|c| (c.chunkfile, c.embedding));
+        let spaces = [file_embeddings(image_chunks), file_embeddings(text_chunks)];
+
+        tokio::task::spawn_blocking(move || {
+            let total_files: usize = spaces.iter().map(Vec::len).sum();
+            debug!("Clustering {} indexed files into {} topics", total_files, n_clusters);
+
+            let mut topics = vec![];
+            for files in spaces.iter().filter(|files| !files.is_empty()) {
+                let share = (n_clusters * files.len()).div_ceil(total_files).max(1);
+                topics.extend(cluster(files, share));
+            }
+            topics.sort_by_key(|t| std::cmp::Reverse(t.files.len()));
+            topics.truncate(n_clusters);
+            topics
+        }).await.map_err(|e| TopicClusteringError::Join { source: e })
+    }
+}
+
+// Private functions and variables
+
+/// Maximum number of files centroids are trained on
+const MAX_SAMPLE_FILES: usize = 2000;
+const MAX_ITERATIONS: usize = 25;
+const NUM_TOPIC_TERMS: usize = 3;
+
+struct FileEmbedding {
+    path: Utf8PathBuf,
+    /// Unique vocabulary words of the file
+    words: HashSet<String>,
+    embedding: Vec<f32>,
+}
+
+/// Averages the chunk embeddings of each file and normalizes them to unit length
+fn file_embeddings(chunks: impl Iterator<Item = (ChunkFile, Vec<f32>)>) -> Vec<FileEmbedding> {
+    let mut files: BTreeMap<Utf8PathBuf, FileEmbedding> = BTreeMap::new();
+    for (chunkfile, embedding) in chunks {
+        let file = files.entry(chunkfile.original_file.clone())
+            .or_insert_with(|| FileEmbedding {
+                path: chunkfile.original_file.clone(),
+                words: chunkfile_vocabulary(&chunkfile).collect(),
+                embedding: vec![0.0; embedding.len()],
+            });
+        for (sum, value) in file.embedding.iter_mut().zip(&embedding) {
+            *sum += value;
+        }
+    }
+
+    let mut files: Vec<FileEmbedding> = files.into_values().collect();
+    for file in &mut files {
+        normalize(&mut file.embedding);
+    }
+    files
+}
+
+fn cluster(files: &[FileEmbedding], n_clusters: usize) -> Vec<Topic> {
+    // Evenly spaced sample, so the sample is spread across the directory tree
+    let step = files.len().div_ceil(MAX_SAMPLE_FILES).max(1);
+    let sample: Vec<&[f32]> = files.iter().step_by(step).map(|f| f.embedding.as_slice()).collect();
+    let centroids = train_centroids(&sample, n_clusters.min(sample.len()));
+
+    let mut clusters: Vec<Vec<(&FileEmbedding, f32)>> = vec![vec![]; centroids.len()];
+    for file in files {
+        let (nearest, similarity) = nearest_centroid(&centroids, &file.embedding);
+        clusters[nearest].push((file, similarity));
+    }
+
+    // Count how many files use each word, to favour words that are common in a cluster but rare overall
+    let mut document_frequency: HashMap<&str, usize> = HashMap::new();
+    for word in files.iter().flat_map(|f| &f.words) {
+        *document_frequency.entry(word.as_str()).or_insert(0) += 1;
+    }
+
+    clusters.into_iter()
+        .filter(|members| !members.is_empty())
+        .map(|mut members| {
+            members.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(Ordering::Equal));
+            Topic {
+                terms: topic_terms(&members, &document_frequency, files.len()),
+                files: members.into_iter().map(|(f, _)| f.path.clone()).collect(),
+            }
+        })
+        .collect()
+}
+
+/// Runs k-means with cosine similarity, seeding the centroids with the farthest point heuristic so results are
+/// stable between runs
+fn train_centroids(sample: &[&[f32]], k: usize) -> Vec<Vec<f32>> {
+    if k == 0 {
+        return vec![];
+    }
+
+    let mut centroids = vec![sample[0].to_vec()];
+    let mut closest: Vec<f32> = sample.iter().map(|v| dot(v, &centroids[0])).collect();
+    while centroids.len() < k {
+        let (farthest, _) = closest.iter().enumerate()
+            .min_by(|a, b| a.1.partial_cmp(b.1).unwrap_or(Ordering::Equal))
+            .expect("Sample should not be empty");
+        let centroid = sample[farthest].to_vec();
+        for (i, v) in sample.iter().enumerate() {
+            closest[i] = closest[i].max(dot(v, &centroid));
+        }
+        centroids.push(centroid);
+    }
+
+    let mut assignments = vec![usize::MAX; sample.len()];
+    for iteration in 0..MAX_ITERATIONS {
+        let mut changed = false;
+        for (i, v) in sample.iter().enumerate() {
+            let (nearest, _) = nearest_centroid(&centroids, v);
+            if assignments[i] != nearest {
+                assignments[i] = nearest;
+                changed = true;
+            }
+        }
+        if !changed {
+            debug!("K-means converged after {} iterations", iteration);
+            break;
+        }
+
+        let mut sums = vec![vec![0.0; centroids[0].len()]; centroids.len()];
+        for (v, &assignment) in sample.iter().zip(&assignments) {
+            for (sum, value) in sums[assignment].iter_mut().zip(v.iter()) {
+                *sum += value;
+            }
+        }
+        for (centroid, mut sum) in centroids.iter_mut().zip(sums) {
+            // Clusters that lost all of their files keep their previous centroid
+            if sum.iter().any(|v| *v != 0.0) {
+                normalize(&mut sum);
+                *centroid = sum;
+            }
+        }
+    }
+
+    centroids
+}
+
+fn topic_terms(members: &[(&FileEmbedding, f32)], document_frequency: &HashMap<&str, usize>, total_files: usize)
+    -> Vec<String>
+{
+    let mut cluster_frequency: HashMap<&str, usize> = HashMap::new();
+    for word in members.iter().flat_map(|(f, _)| &f.words) {
+        *cluster_frequency.entry(word.as_str()).or_insert(0) += 1;
+    }
+
+    let mut scored: Vec<(&str, f32)> = cluster_frequency.into_iter()
+        // A word used by a single file says little about the topic, unless the topic is a single file
+        .filter(|(_, count)| *count > 1 || members.len() == 1)
+        .map(|(word, count)| {
+            let idf = (total_files as f32 / document_frequency[word] as f32).ln();
+            (word, count as f32 / members.len() as f32 * idf)
+        })
+        .filter(|(_, score)| *score > 0.0)
+        .collect();
+    scored.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(Ordering::Equal).then(a.0.cmp(b.0)));
+    scored.into_iter().take(NUM_TOPIC_TERMS).map(|(word, _)| word.to_owned()).collect()
+}
+
+fn nearest_centroid(centroids: &[Vec<f32>], v: &[f32]) -> (usize, f32) {
+    centroids.iter()
+        .map(|c| dot(c, v))
+        .enumerate()
+        .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(Ordering::Equal))
+        .expect("There should be at least one centroid")
+}
+
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(a, b)| a * b).sum()
+}
+
+fn normalize(v: &mut [f32]) {
+    let norm = v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        v.iter_mut().for_each(|x| *x /= norm);
+    }
+}
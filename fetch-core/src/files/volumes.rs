@@ -0,0 +1,390 @@
+use std::{collections::BTreeMap, fs, sync::{LazyLock, RwLock}, time::{Duration, Instant}};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info};
+
+use crate::{app_config, files::os_path::to_os_path};
+
+/// A mounted volume, ie. a drive, partition or network share
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Volume {
+    /// Identity of the volume that stays the same when it is mounted somewhere else, eg. the filesystem UUID or the
+    /// volume serial number. Network shares are identified by their remote address.
+    pub id: String,
+    pub mount_point: Utf8PathBuf,
+    /// Name to show the user, eg. the volume label
+    pub label: String,
+}
+
+/// Where an indexed file can currently be found
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FileLocation {
+    /// The file is at its indexed path
+    Available,
+    /// The volume of the file is mounted somewhere else now, and the file is at the new path
+    Relinked { path: Utf8PathBuf },
+    /// The volume of the file is not connected, so whether the file still exists is unknown
+    Offline { volume_label: String },
+    /// The volume of the file is connected (or unknown), but the file is not there
+    Missing,
+}
+
+/// The volumes that indexed files have been found on, along with where each was last mounted. Used to tell files on
+/// an unplugged drive or disconnected network share apart from deleted files, and to find files again when their
+/// volume is mounted at a different mount point (eg. a different drive letter). Stored in the application data
+/// directory.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct IndexedVolumes {
+    /// Keyed by volume id
+    volumes: BTreeMap<String, Volume>,
+}
+
+impl IndexedVolumes {
+    /// Loads the indexed volumes from the application data directory. Returns no volumes if none have been recorded
+    /// yet.
+    pub fn load() -> Result<IndexedVolumes, anyhow::Error> {
+        let volumes_file = app_config::get_indexed_volumes_file_path();
+        if !fs::exists(&volumes_file)? {
+            return Ok(IndexedVolumes::default());
+        }
+
+        let contents = fs::read_to_string(&volumes_file)?;
+        Ok(serde_json::from_str(&contents)?)
+    }
+
+    /// Saves the indexed volumes to the application data directory.
+    pub fn save(&self) -> Result<(), anyhow::Error> {
+        let volumes_file = app_config::get_indexed_volumes_file_path();
+        if let Some(parent) = volumes_file.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        fs::write(&volumes_file, serde_json::to_string(self)?)?;
+        Ok(())
+    }
+
+    /// Records the volume an indexed file is on. Returns false if the volume was already recorded at the same mount
+    /// point.
+    pub fn record(&mut self, volume: &Volume) -> bool {
+        if self.volumes.get(&volume.id) == Some(volume) {
+            return false;
+        }
+        self.volumes.insert(volume.id.clone(), volume.clone());
+        true
+    }
+
+    /// Updates the mount points of recorded volumes that have been mounted somewhere else since they were recorded.
+    /// Returns the volumes that moved, with their previous mount points.
+    pub fn relink(&mut self, mounted: &[Volume]) -> Vec<(Utf8PathBuf, Volume)> {
+        let mut moved = vec![];
+        for volume in mounted {
+            if let Some(recorded) = self.volumes.get_mut(&volume.id) {
+                if recorded.mount_point != volume.mount_point {
+                    info!("IndexedVolumes: Volume {} moved from {} to {}", volume.label, recorded.mount_point,
+                        volume.mount_point);
+                    let previous = std::mem::replace(recorded, volume.clone());
+                    moved.push((previous.mount_point, volume.clone()));
+                }
+            }
+        }
+        moved
+    }
+
+    /// Works out where an indexed file can currently be found, given the currently mounted volumes
+    pub fn locate(&self, path: &Utf8Path, mounted: &[Volume]) -> FileLocation {
+        if fs::exists(to_os_path(path)).unwrap_or(false) {
+            return FileLocation::Available;
+        }
+
+        // The recorded volume with the longest mount point containing the path is the one the file was indexed on
+        let recorded = match self.volumes.values()
+            .filter(|volume| path.starts_with(&volume.mount_point))
+            .max_by_key(|volume| volume.mount_point.as_str().len()) {
+            Some(volume) => volume,
+            None => return FileLocation::Missing,
+        };
+
+        let current = mounted.iter().find(|volume| volume.id == recorded.id);
+        match current {
+            Some(current) if current.mount_point != recorded.mount_point => {
+                let relative = path.strip_prefix(&recorded.mount_point)
+                    .expect("Path should start with the mount point it was matched by");
+                let relinked = current.mount_point.join(relative);
+                if fs::exists(to_os_path(&relinked)).unwrap_or(false) {
+                    FileLocation::Relinked { path: relinked }
+                } else {
+                    FileLocation::Missing
+                }
+            },
+            Some(_) => FileLocation::Missing,
+            None if is_network_share(&recorded.mount_point) && fs::exists(&recorded.mount_point).unwrap_or(false) => {
+                // Network shares that are not mapped to a mount point are not listed among the mounted volumes
+                FileLocation::Missing
+            },
+            None => FileLocation::Offline { volume_label: recorded.label.clone() },
+        }
+    }
+}
+
+/// Returns the currently mounted volumes. The list is cached for a short while, as listing volumes can mean running
+/// system tools.
+pub fn mounted_volumes() -> Vec<Volume> {
+    if let Some((listed_at, volumes)) = MOUNTED_VOLUMES.read().expect("Mounted volumes lock should not be poisoned").as_ref() {
+        if listed_at.elapsed() < MOUNTED_VOLUMES_TTL {
+            return volumes.clone();
+        }
+    }
+
+    let volumes = list_mounted_volumes();
+    debug!("Listed {} mounted volumes", volumes.len());
+    *MOUNTED_VOLUMES.write().expect("Mounted volumes lock should not be poisoned") = Some((Instant::now(), volumes.clone()));
+    volumes
+}
+
+/// Returns the volume a path is on, out of the given mounted volumes. Paths on network shares that are not mapped to
+/// a mount point (eg. \\server\share on Windows) are on a volume of their own, identified by the share.
+pub fn volume_of(path: &Utf8Path, mounted: &[Volume]) -> Option<Volume> {
+    if let Some(share) = network_share_root(path) {
+        return Some(Volume { id: share.to_string(), mount_point: share.clone(), label: share.to_string() });
+    }
+
+    mounted.iter()
+        .filter(|volume| path.starts_with(&volume.mount_point))
+        .max_by_key(|volume| volume.mount_point.as_str().len())
+        .cloned()
+}
+
+/// Returns the stable id of the file at the path, made of the id of its volume and its file number on the volume (the
+/// inode on unix, the file index on Windows). Unlike the path, the id stays the same when the file is moved or renamed
+/// within its volume, and when the volume is mounted somewhere else. Returns None if the file cannot be read, its
+/// file system does not number its files, or it has more than one hard link, as every link would share its id.
+pub fn file_id(path: &Utf8Path) -> Option<String> {
+    let (device, file_number) = file_number(path)?;
+    let volume_id = volume_of(path, &mounted_volumes())
+        .map(|volume| volume.id)
+        .unwrap_or_else(|| format!("{:x}", device));
+    Some(format!("{}:{:x}", volume_id, file_number))
+}
+
+// Private functions and variables
+
+const MOUNTED_VOLUMES_TTL: Duration = Duration::from_secs(30);
+
+static MOUNTED_VOLUMES: LazyLock<RwLock<Option<(Instant, Vec<Volume>)>>> = LazyLock::new(|| RwLock::new(None));
+
+/// The \\server\share part of a UNC path
+fn network_share_root(path: &Utf8Path) -> Option<Utf8PathBuf> {
+    let path = path.as_str();
+    let rest = path.strip_prefix("\\\\")?;
+    let mut parts = rest.splitn(3, '\\');
+    match (parts.next(), parts.next()) {
+        (Some(server), Some(share)) if !server.is_empty() && !share.is_empty() && server != "?" && server != "." =>
+            Some(Utf8PathBuf::from(format!("\\\\{}\\{}", server, share))),
+        _ => None,
+    }
+}
+
+fn is_network_share(mount_point: &Utf8Path) -> bool {
+    network_share_root(mount_point).is_some()
+}
+
+/// Reads the mounted filesystems from /proc/self/mountinfo, identifying block devices by their filesystem UUID and
+/// network filesystems by their remote address. Pseudo filesystems are skipped.
+#[cfg(target_os = "linux")]
+fn list_mounted_volumes() -> Vec<Volume> {
+    let mountinfo = match fs::read_to_string("/proc/self/mountinfo") {
+        Ok(mountinfo) => mountinfo,
+        Err(e) => {
+            tracing::warn!("Could not read mounted volumes: {:?}", e);
+            return vec![];
+        },
+    };
+    let uuids = read_device_links("/dev/disk/by-uuid");
+    let labels = read_device_links("/dev/disk/by-label");
+
+    let mut volumes = vec![];
+    for line in mountinfo.lines() {
+        // Format: id parent major:minor root mount_point options [optional fields] - fs_type source super_options
+        let (before, after) = match line.split_once(" - ") {
+            Some(split) => split,
+            None => continue,
+        };
+        let mount_point = match before.split(' ').nth(4) {
+            Some(mount_point) => unescape_mountinfo(mount_point),
+            None => continue,
+        };
+        let mut after = after.split(' ');
+        let (fs_type, source) = match (after.next(), after.next()) {
+            (Some(fs_type), Some(source)) => (fs_type, unescape_mountinfo(source)),
+            _ => continue,
+        };
+
+        let (id, label) = if matches!(fs_type, "nfs" | "nfs4" | "cifs" | "smb3" | "sshfs" | "fuse.sshfs") {
+            (source.clone(), source.clone())
+        } else if source.starts_with("/dev/") {
+            let device = fs::canonicalize(&source).map(|p| p.to_string_lossy().into_owned()).unwrap_or(source.clone());
+            let id = uuids.get(&device).cloned().unwrap_or_else(|| device.clone());
+            let label = labels.get(&device).cloned().unwrap_or_else(|| mount_point.clone());
+            (id, label)
+        } else {
+            continue;
+        };
+        volumes.push(Volume { id, mount_point: Utf8PathBuf::from(mount_point), label });
+    }
+    volumes
+}
+
+/// Maps each device in the directory of links (eg. /dev/disk/by-uuid) to the name of its link
+#[cfg(target_os = "linux")]
+fn read_device_links(dir: &str) -> std::collections::HashMap<String, String> {
+    let mut links = std::collections::HashMap::new();
+    if let Ok(entries) = fs::read_dir(dir) {
+        for entry in entries.flatten() {
+            if let (Ok(device), Some(name)) = (fs::canonicalize(entry.path()), entry.file_name().to_str()) {
+                links.insert(device.to_string_lossy().into_owned(), name.replace("\\x20", " "));
+            }
+        }
+    }
+    links
+}
+
+/// Undoes the octal escaping of spaces and other special characters, eg. \040 for a space
+#[cfg(target_os = "linux")]
+fn unescape_mountinfo(field: &str) -> String {
+    let mut unescaped = String::with_capacity(field.len());
+    let mut rest = field;
+    while let Some(position) = rest.find('\\') {
+        unescaped.push_str(&rest[..position]);
+        let code = rest.get(position + 1..position + 4).and_then(|digits| u8::from_str_radix(digits, 8).ok());
+        match code {
+            Some(code) => {
+                unescaped.push(code as char);
+                rest = &rest[position + 4..];
+            },
+            None => {
+                unescaped.push('\\');
+                rest = &rest[position + 1..];
+            },
+        }
+    }
+    unescaped.push_str(rest);
+    unescaped
+}
+
+/// Lists the mounted filesystems with mount(8), identifying local volumes by their volume UUID and network shares by
+/// their remote address
+#[cfg(target_os = "macos")]
+fn list_mounted_volumes() -> Vec<Volume> {
+    let output = match std::process::Command::new("mount").output() {
+        Ok(output) if output.status.success() => output,
+        result => {
+            tracing::warn!("Could not list mounted volumes: {:?}", result);
+            return vec![];
+        },
+    };
+
+    let mut volumes = vec![];
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        // Format: source on mount_point (fs_type, options...)
+        let (source, rest) = match line.split_once(" on ") {
+            Some(split) => split,
+            None => continue,
+        };
+        let (mount_point, details) = match rest.rsplit_once(" (") {
+            Some(split) => split,
+            None => continue,
+        };
+        let fs_type = details.split(',').next().unwrap_or("");
+        let label = Utf8Path::new(mount_point).file_name().unwrap_or(mount_point).to_string();
+
+        let id = if matches!(fs_type, "smbfs" | "nfs" | "afpfs" | "webdav") {
+            source.to_string()
+        } else if source.starts_with("/dev/") {
+            volume_uuid(mount_point).unwrap_or_else(|| source.to_string())
+        } else {
+            continue;
+        };
+        volumes.push(Volume { id, mount_point: Utf8PathBuf::from(mount_point), label });
+    }
+    volumes
+}
+
+#[cfg(target_os = "macos")]
+fn volume_uuid(mount_point: &str) -> Option<String> {
+    let output = std::process::Command::new("diskutil").args(["info", mount_point]).output().ok()?;
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("Volume UUID:").map(|uuid| uuid.trim().to_string()))
+}
+
+/// Lists the drive letters in use, identifying each drive by its volume serial number
+#[cfg(target_os = "windows")]
+fn list_mounted_volumes() -> Vec<Volume> {
+    let mut volumes = vec![];
+    for letter in 'A'..='Z' {
+        let mount_point = format!("{}:\\", letter);
+        if !fs::exists(&mount_point).unwrap_or(false) {
+            continue;
+        }
+
+        let output = match std::process::Command::new("cmd").args(["/C", "vol", &format!("{}:", letter)]).output() {
+            Ok(output) => output,
+            Err(_) => continue,
+        };
+        let output = String::from_utf8_lossy(&output.stdout);
+        // The output is localized, so the serial number and label are taken as the last word of their lines
+        let mut lines = output.lines().map(str::trim).filter(|line| !line.is_empty());
+        let label_line = lines.next();
+        let serial = match lines.next().and_then(|line| line.rsplit(' ').next()) {
+            Some(serial) => serial.to_string(),
+            None => continue,
+        };
+        let label = label_line
+            .filter(|line| !line.ends_with('.'))
+            .and_then(|line| line.rsplit(' ').next())
+            .map(|label| format!("{} ({}:)", label, letter))
+            .unwrap_or_else(|| format!("Drive {}:", letter));
+        volumes.push(Volume { id: serial, mount_point: Utf8PathBuf::from(mount_point), label });
+    }
+    volumes
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+fn list_mounted_volumes() -> Vec<Volume> {
+    vec![]
+}
+
+/// The device and inode of the file at the path, None if it has more than one hard link
+#[cfg(unix)]
+fn file_number(path: &Utf8Path) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+
+    let metadata = fs::metadata(to_os_path(path)).ok()?;
+    if metadata.nlink() > 1 {
+        return None;
+    }
+    Some((metadata.dev(), metadata.ino()))
+}
+
+/// The volume serial number and file index of the file at the path, None if it has more than one hard link. File
+/// systems without file indexes, eg. some network shares, report a file index of 0.
+#[cfg(windows)]
+fn file_number(path: &Utf8Path) -> Option<(u64, u64)> {
+    use std::os::windows::io::AsRawHandle;
+    use windows::Win32::{Foundation::HANDLE, Storage::FileSystem::{BY_HANDLE_FILE_INFORMATION, GetFileInformationByHandle}};
+
+    let file = fs::File::open(to_os_path(path)).ok()?;
+    let mut information = BY_HANDLE_FILE_INFORMATION::default();
+    unsafe { GetFileInformationByHandle(HANDLE(file.as_raw_handle()), &mut information) }.ok()?;
+    let file_index = ((information.nFileIndexHigh as u64) << 32) | information.nFileIndexLow as u64;
+    if file_index == 0 || information.nNumberOfLinks > 1 {
+        return None;
+    }
+    Some((information.dwVolumeSerialNumber as u64, file_index))
+}
+
+#[cfg(not(any(unix, windows)))]
+fn file_number(_path: &Utf8Path) -> Option<(u64, u64)> {
+    None
+}
@@ -1,16 +1,21 @@
+use std::sync::LazyLock;
+
 use camino::Utf8PathBuf;
 use chrono::{DateTime, Utc};
 use serde_json::{Map, Value};
 
-use crate::store::KeyedSequencedData;
+use crate::{app_config, store::KeyedSequencedData};
 
-// TODO: update sequence number to separate value from file modified date - chunkfile creation date?
-// Will require complete regeneration of database
+#[derive(Clone)]
 pub struct ChunkFile {
     // Composite key
     pub original_file: Utf8PathBuf,
     pub chunk_channel: String,
     pub chunk_sequence_id: f32,
+    /// Stable id of the original file, see files::volumes::file_id. Takes the place of the original file in the key
+    /// when chunks are keyed by file id (see ChunkKeyStrategy), so moving or renaming the file only changes the
+    /// original_file of its chunks. None if the file had no id, or was indexed before file ids were stored.
+    pub original_file_id: Option<String>,
     // Other data pieces
     pub chunkfile: Utf8PathBuf,
     pub chunk_type: ChunkType,
@@ -18,10 +23,21 @@ pub struct ChunkFile {
     pub original_file_creation_date: DateTime<Utc>,
     pub original_file_modified_date: DateTime<Utc>,
     pub original_file_size: u64,
+    /// Fingerprint of the content of the original file, see files::dupes::content_fingerprint. Recognizes the file
+    /// after it is moved when its file id cannot, eg. it was moved to another volume. None if the file could not be
+    /// read, or was indexed before fingerprints were stored.
+    pub original_file_fingerprint: Option<String>,
     pub original_file_tags: Map<String, Value>,
+    /// ISO 639-3 code of the language the chunk is written in, or "und" if undetermined
+    pub language: String,
+    /// Tags the user has manually added to the original file, see files::tags::UserTags
+    pub user_tags: Vec<String>,
+    /// Orders writes of the chunk to stores, see sequence::next. Chunks indexed before sequence numbers were
+    /// issued by the sequence clock have the original file's modified date, in milliseconds, instead.
+    pub sequence_number: u64,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum ChunkType {
     Text,
     Image,
@@ -29,29 +45,82 @@ pub enum ChunkType {
     Audio,
 }
 
+/// What identifies the original file of a chunk in its key, set by key_chunks_by_file_id in the data configuration
+/// file. Chunks are cleared by their original_file rather than by key, so the strategy can change without leaving
+/// chunks behind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChunkKeyStrategy {
+    /// Keyed by the id of the original file, see ChunkFile::original_file_id. Chunks of files without an id are keyed
+    /// by path instead.
+    FileId,
+    /// Keyed by the path of the original file
+    Path,
+}
+
+impl ChunkKeyStrategy {
+    /// The strategy set in the data configuration file
+    pub fn current() -> ChunkKeyStrategy {
+        *CHUNK_KEY_STRATEGY
+    }
+}
+
 impl KeyedSequencedData<String> for ChunkFile {
     fn get_key(&self) -> String {
-        // Create a unique key from the composite key fields
-        format!("{}::{}::{}",
-            self.original_file,
-            self.chunk_channel,
-            self.chunk_sequence_id)
+        self.key_with(ChunkKeyStrategy::current())
     }
 
     fn get_sequence_num(&self) -> u64 {
-        // Use modification timestamp as sequence number for versioning
-        // Higher values = newer versions
-        // Note: timestamp_millis() returns i64, which overflows around year 292,277,026 CE
-        // (approximately 292 million years from Unix epoch). This cast to u64 is safe for
-        // all reasonable file modification dates after 1970-01-01.
-        self.original_file_modified_date.timestamp_millis() as u64
+        self.sequence_number
+    }
+}
+
+impl ChunkFile {
+    /// The key of the chunk under the strategy. Chunks are stored under their key with the current strategy, see
+    /// ChunkKeyStrategy::current.
+    pub fn key_with(&self, strategy: ChunkKeyStrategy) -> String {
+        // Create a unique key from the composite key fields
+        match (strategy, &self.original_file_id) {
+            (ChunkKeyStrategy::FileId, Some(original_file_id)) => format!("{}{}::{}::{}",
+                FILE_ID_KEY_PREFIX,
+                original_file_id,
+                self.chunk_channel,
+                self.chunk_sequence_id),
+            _ => format!("{}::{}::{}",
+                self.original_file,
+                self.chunk_channel,
+                self.chunk_sequence_id),
+        }
+    }
+
+    /// Parses the original file out of a key the chunk was stored under, eg. one salvaged from a damaged table.
+    /// Stores keep keys serialized as JSON. Returns None for keys made from the original file's id, see
+    /// ChunkKeyStrategy::FileId.
+    pub fn original_file_from_stored_key(stored_key: &str) -> Option<Utf8PathBuf> {
+        let key = serde_json::from_str::<String>(stored_key).ok()?;
+        if key.starts_with(FILE_ID_KEY_PREFIX) {
+            return None;
+        }
+        // The original file path may itself contain "::", so the channel and sequence id are split off the end
+        key.rsplitn(3, "::").nth(2).map(Utf8PathBuf::from)
     }
 }
 
 pub mod provider;
 pub mod embedding;
+pub mod rerank;
+pub mod language;
+pub mod summarize;
 
 pub use integrations::*;
 
 // Private variables and functions
-mod integrations;
\ No newline at end of file
+mod integrations;
+
+/// Marks keys made from the original file's id, which paths never start with
+const FILE_ID_KEY_PREFIX: &str = "id:";
+
+static CHUNK_KEY_STRATEGY: LazyLock<ChunkKeyStrategy> = LazyLock::new(|| if app_config::is_keyed_by_file_id() {
+    ChunkKeyStrategy::FileId
+} else {
+    ChunkKeyStrategy::Path
+});
\ No newline at end of file
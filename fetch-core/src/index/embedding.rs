@@ -16,6 +16,8 @@ pub enum EmbeddingError {
     Unknown { msg: &'static str, #[source] source: anyhow::Error },
 }
 
+pub mod cache;
+pub mod prompts;
 pub mod sessions;
 
 // model modules
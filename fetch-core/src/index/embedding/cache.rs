@@ -0,0 +1,84 @@
+use std::{collections::VecDeque, future::Future, sync::{Arc, Mutex}};
+
+/// Small least recently used cache of query embeddings, keyed by the model and the query text, so paging through
+/// results or re-running a recent query does not embed the query again. Providers embedding the same query with the
+/// same model at the same time, eg. the image provider and the page images of the PDF provider, share a single
+/// embedding, see get_or_embed.
+pub struct QueryEmbeddingCache {
+    capacity: usize,
+    entries: Mutex<VecDeque<(&'static str, String, Vec<f32>)>>,
+    /// Queries being embedded, each with the lock held while it is embedded
+    in_flight: Mutex<Vec<(&'static str, String, Arc<tokio::sync::Mutex<()>>)>>,
+}
+
+impl QueryEmbeddingCache {
+    pub const fn with_capacity(capacity: usize) -> QueryEmbeddingCache {
+        QueryEmbeddingCache { capacity, entries: Mutex::new(VecDeque::new()), in_flight: Mutex::new(Vec::new()) }
+    }
+
+    /// Returns the cached embedding of the query by the model, marking it as the most recently used
+    pub fn get(&self, model: &'static str, query: &str) -> Option<Vec<f32>> {
+        let mut entries = self.entries.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let position = entries.iter().position(|(m, q, _)| *m == model && q == query)?;
+        let entry = entries.remove(position)?;
+        let embedding = entry.2.clone();
+        entries.push_front(entry);
+        Some(embedding)
+    }
+
+    /// Caches the embedding of the query by the model, evicting the least recently used entry if the cache is full
+    pub fn put(&self, model: &'static str, query: &str, embedding: Vec<f32>) {
+        let mut entries = self.entries.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        entries.retain(|(m, q, _)| !(*m == model && q == query));
+        entries.push_front((model, query.to_owned(), embedding));
+        entries.truncate(self.capacity);
+    }
+
+    /// Returns the cached embedding of the query by the model, or embeds and caches it. If the query is already being
+    /// embedded by the model, waits for that embedding instead of embedding it again.
+    pub async fn get_or_embed<E, Fut>(&self, model: &'static str, query: &str, embed: Fut) -> Result<Vec<f32>, E>
+    where
+        Fut: Future<Output = Result<Vec<f32>, E>>,
+    {
+        if let Some(embedding) = self.get(model, query) {
+            return Ok(embedding);
+        }
+
+        let lock = self.in_flight_lock(model, query);
+        let guard = lock.lock().await;
+        // The query may have been embedded while waiting for the lock
+        let result = match self.get(model, query) {
+            Some(embedding) => Ok(embedding),
+            None => embed.await.inspect(|embedding| self.put(model, query, embedding.clone())),
+        };
+        drop(guard);
+        self.release_in_flight_lock(model, query, lock);
+        result
+    }
+
+    fn in_flight_lock(&self, model: &'static str, query: &str) -> Arc<tokio::sync::Mutex<()>> {
+        let mut in_flight = self.in_flight.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Some((_, _, lock)) = in_flight.iter().find(|(m, q, _)| *m == model && q == query) {
+            return lock.clone();
+        }
+        let lock = Arc::new(tokio::sync::Mutex::new(()));
+        in_flight.push((model, query.to_owned(), lock.clone()));
+        lock
+    }
+
+    /// Forgets the lock of the query once nothing else is waiting on it
+    fn release_in_flight_lock(&self, model: &'static str, query: &str, lock: Arc<tokio::sync::Mutex<()>>) {
+        let mut in_flight = self.in_flight.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        // One reference is held by the list and one by this caller
+        if Arc::strong_count(&lock) <= 2 {
+            in_flight.retain(|(m, q, _)| !(*m == model && q == query));
+        }
+    }
+}
+
+/// Query embeddings shared by every embedding model
+pub(crate) static QUERY_EMBEDDINGS: QueryEmbeddingCache = QueryEmbeddingCache::with_capacity(QUERY_CACHE_CAPACITY);
+
+// private constants and functions
+
+const QUERY_CACHE_CAPACITY: usize = 64;
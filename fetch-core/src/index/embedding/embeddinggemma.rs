@@ -1,12 +1,12 @@
 use std::sync::LazyLock;
 
-use log::debug;
 use ndarray::{Array, Axis};
 use ort::{inputs, value::TensorRef};
 use tokenizers::Tokenizer;
 use tokio::{fs, task};
+use tracing::{debug, instrument, warn};
 
-use crate::index::{ChunkFile, ChunkType, embedding::{EmbeddingError, sessions::{SessionPool, SessionPoolExt, create_session_pool, create_tokenizer}}};
+use crate::index::{ChunkFile, ChunkType, language, embedding::{EmbeddingError, cache::QUERY_EMBEDDINGS, prompts::{EmbeddingPrompts, PROMPT_VERSION_TAG}, sessions::{Priority, PrioritySessionPool, SessionPool, SessionPoolExt, create_session_pool, create_tokenizer}}};
 
 impl EmbeddingGemmaEmbeddedChunkFile {
     const VECTOR_LENGTH: u32 = 768;
@@ -17,7 +17,8 @@ pub struct EmbeddingGemmaEmbeddedChunkFile {
     pub embedding: Vec<f32>,
 }
 
-pub async fn embed_chunk(chunkfile: ChunkFile) -> Result<EmbeddingGemmaEmbeddedChunkFile, EmbeddingError> {
+#[instrument(name = "embed", level = "debug", skip_all, fields(chunkfile = %chunkfile.chunkfile))]
+pub async fn embed_chunk(mut chunkfile: ChunkFile) -> Result<EmbeddingGemmaEmbeddedChunkFile, EmbeddingError> {
     if chunkfile.chunk_type != ChunkType::Text {
         return Err(EmbeddingError::InvalidType {
             path: chunkfile.chunkfile.to_string(),
@@ -29,9 +30,10 @@ pub async fn embed_chunk(chunkfile: ChunkFile) -> Result<EmbeddingGemmaEmbeddedC
     let text = fs::read_to_string(&chunkfile.chunkfile).await
         .map_err(|e| EmbeddingError::IO { path: chunkfile.chunkfile.to_string(), source: e.into() })?;
 
-    let prompted_text = format!("title: none | text: {text}");
+    let prompted_text = PROMPTS.prompt_document(&text);
 
-    let embedding = embed_prompted_str(prompted_text).await?;
+    let embedding = embed_prompted_str(prompted_text, Priority::Background).await?;
+    chunkfile.original_file_tags.insert(PROMPT_VERSION_TAG.to_owned(), PROMPTS.version.into());
 
     Ok(EmbeddingGemmaEmbeddedChunkFile {
         chunkfile,
@@ -39,15 +41,16 @@ pub async fn embed_chunk(chunkfile: ChunkFile) -> Result<EmbeddingGemmaEmbeddedC
     })
 }
 
+#[instrument(name = "embed_query", level = "debug", skip_all)]
 pub async fn embed_query(query: &str) -> Result<Vec<f32>, EmbeddingError> {
-    let prompted_query = format!("task: search result | query: {query}");
-    embed_prompted_str(prompted_query).await
+    QUERY_EMBEDDINGS.get_or_embed(MODEL_PATH, query,
+        embed_prompted_str(PROMPTS.prompt_query(query), Priority::Interactive)).await
 }
 
-async fn embed_prompted_str(prompt_str: String) -> Result<Vec<f32>, EmbeddingError> {
+async fn embed_prompted_str(prompt_str: String, priority: Priority) -> Result<Vec<f32>, EmbeddingError> {
     let s = prompt_str.to_lowercase();
     let result = task::spawn_blocking(move || -> Result<Vec<f32>, EmbeddingError> {
-        let mut model = SESSION_POOL.get_session();
+        let mut model = SESSION_POOL.get_session(priority);
         let tokenizer = &TOKENIZER;
         
         let encoding = tokenizer.encode(s, false)
@@ -110,13 +113,31 @@ async fn embed_prompted_str(prompt_str: String) -> Result<Vec<f32>, EmbeddingErr
     result
 }
 
-/// Init function that retrieves querying resources and then immediately drops them to initialize lazy cells
+/// Counts the tokens of the text as the model reads it when the text is embedded as a chunk, so that chunks can be
+/// sized to fit in the model's input, see max_chunk_tokens
+pub(crate) fn count_tokens(text: &str) -> u32 {
+    match TOKENIZER.encode(text.to_lowercase(), false) {
+        Ok(encoding) => encoding.len() as u32,
+        Err(e) => {
+            warn!("Could not tokenize text to count its tokens, estimating them instead: {:?}", e);
+            language::estimate_tokens(text)
+        },
+    }
+}
+
+/// Most tokens a chunk can have, counted with count_tokens, for the model to read all of it along with the prompt
+/// it is embedded with. Tokens past this are cut off before embedding.
+pub(crate) fn max_chunk_tokens() -> u32 {
+    static PROMPT_TOKENS: LazyLock<u32> = LazyLock::new(|| count_tokens(&PROMPTS.prompt_document("")));
+    MODEL_INPUT_LENGTH as u32 - *PROMPT_TOKENS
+}
+
+/// Session pool of the model, used to warm it up ahead of use, see environment::warm_models
 /// 
 /// sessions::init_model_resource_directory must be called before this function or all models will be initialized
 /// from a binary relative models/ path
-pub fn init() {
-    // Instantiate and instantly drop the mutex guard to load the sessions
-    let _guard = SESSION_POOL.get_session();
+pub(crate) fn session_pool() -> &'static PrioritySessionPool {
+    &SESSION_POOL
 }
 
 pub use integrations::*;
@@ -133,6 +154,14 @@ static SESSION_POOL: LazyLock<SessionPool> = LazyLock::new(|| {
     create_session_pool(1, MODEL_PATH.into())
 });
 
+/// The prompts recommended for retrieval in the model card, unless set otherwise in the settings
+static PROMPTS: LazyLock<EmbeddingPrompts> = LazyLock::new(|| EmbeddingPrompts::from_settings_or("embeddinggemma",
+    EmbeddingPrompts {
+        version: 1,
+        query: "task: search result | query: {text}".to_owned(),
+        document: Some("title: none | text: {text}".to_owned()),
+    }));
+
 static TOKENIZER: LazyLock<Tokenizer> = LazyLock::new(|| {
     debug!("Initializing text tokenizer resources for EmbeddingGemma Embedder");
     create_tokenizer(TOKENIZER_PATH.into())
@@ -6,7 +6,7 @@ use arrow_array::{ArrayRef, FixedSizeListArray, RecordBatch};
 use arrow_schema::{DataType, Field, Schema};
 
 use crate::index::{ChunkFile, embedding::embeddinggemma::EmbeddingGemmaEmbeddedChunkFile};
-use crate::store::{FTSData, Filterable, lancedb::{ArrowData, RowBuilder}, KeyedSequencedData, VectorData};
+use crate::store::{FTSData, Filterable, FtsAnalyzer, lancedb::{ArrowData, RowBuilder}, KeyedSequencedData, VectorData};
 
 impl EmbeddingGemmaEmbeddedChunkFile {
     const VECTOR_ATTRIBUTE_NAME: &str = "embedding";
@@ -80,6 +80,14 @@ impl ArrowData for EmbeddingGemmaEmbeddedChunkFile {
                 could not be merged with ChunkFile schema"))
     }
 
+    fn table_schema() -> Schema {
+        let chunkfile_schema = ChunkFile::table_schema();
+        let extended_schema = Schema::new(vec![VECTOR_FIELD.clone()]);
+        Schema::try_merge([chunkfile_schema, extended_schema])
+            .unwrap_or_else(|_e| panic!("EmbeddingGemmaEmbeddedChunkFile extended table schema \
+                could not be merged with ChunkFile table schema"))
+    }
+
     fn row_builder() -> Self::RowBuilder {
         EmbeddingGemmaEmbeddedChunkFileRowBuilder::new()
     }
@@ -148,10 +156,18 @@ impl Filterable for EmbeddingGemmaEmbeddedChunkFile {
     fn filterable_attributes() -> Vec<&'static str> {
         ChunkFile::filterable_attributes()
     }
+
+    fn keyed_attributes() -> Vec<&'static str> {
+        ChunkFile::keyed_attributes()
+    }
 }
 
 impl FTSData for EmbeddingGemmaEmbeddedChunkFile {
     fn fts_attributes() -> Vec<&'static str> {
         ChunkFile::fts_attributes()
     }
+
+    fn fts_analyzer(attr: &str) -> FtsAnalyzer {
+        ChunkFile::fts_analyzer(attr)
+    }
 }
\ No newline at end of file
@@ -0,0 +1,82 @@
+use tracing::warn;
+
+use crate::app_config;
+
+/// Templates of the prompts a model embeds queries and documents with, where `{text}` stands for the query or the
+/// text of the document. Models are trained to embed text along with a prompt saying what it is for, and match
+/// queries to documents worse without it.
+///
+/// Documents are embedded when they are indexed, so a changed document prompt only applies to files indexed after
+/// the change. The version of the prompts is kept in the original_file_tags of every chunk as prompt_version (see
+/// PROMPT_VERSION_TAG), to tell which chunks were embedded with older prompts and need their files indexed again.
+/// Changing a prompt in the settings file should come with a new version.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EmbeddingPrompts {
+    pub version: u32,
+    pub query: String,
+    /// None for models that do not embed documents as text, eg. SigLIP 2, which embeds images
+    pub document: Option<String>,
+}
+
+impl EmbeddingPrompts {
+    /// Loads the prompts of the model from the `[embedding_prompts.<model>]` table of the providers.toml settings
+    /// file, keeping the defaults for the prompts not set there. Falls back to the defaults entirely if the settings
+    /// cannot be loaded, or a prompt set there has no `{text}`.
+    pub(crate) fn from_settings_or(model: &str, defaults: EmbeddingPrompts) -> EmbeddingPrompts {
+        let settings = match app_config::get_providers_config() {
+            Ok(settings) => settings,
+            Err(e) => {
+                warn!("Could not load embedding prompts of {}, using the defaults: {:?}", model, e);
+                return defaults;
+            },
+        };
+
+        let mut prompts = defaults.clone();
+        if let Ok(version) = settings.get_int(&format!("embedding_prompts.{}.version", model)) {
+            match u32::try_from(version) {
+                Ok(version) => prompts.version = version,
+                Err(_) => {
+                    warn!("Prompt version of {} must not be negative, got {}, using the default prompts", model,
+                        version);
+                    return defaults;
+                },
+            }
+        }
+        if let Ok(query) = settings.get_string(&format!("embedding_prompts.{}.query", model)) {
+            prompts.query = query;
+        }
+        if prompts.document.is_some() {
+            if let Ok(document) = settings.get_string(&format!("embedding_prompts.{}.document", model)) {
+                prompts.document = Some(document);
+            }
+        }
+
+        let templates = [Some(&prompts.query), prompts.document.as_ref()];
+        if let Some(template) = templates.into_iter().flatten().find(|t| !t.contains(TEXT_PLACEHOLDER)) {
+            warn!("Embedding prompt {:?} of {} has no {}, using the default prompts", template, model,
+                TEXT_PLACEHOLDER);
+            return defaults;
+        }
+        prompts
+    }
+
+    /// The query in the query prompt
+    pub fn prompt_query(&self, query: &str) -> String {
+        self.query.replacen(TEXT_PLACEHOLDER, query, 1)
+    }
+
+    /// The text in the document prompt, or the text as is if the model has no document prompt
+    pub fn prompt_document(&self, text: &str) -> String {
+        match &self.document {
+            Some(document) => document.replacen(TEXT_PLACEHOLDER, text, 1),
+            None => text.to_owned(),
+        }
+    }
+}
+
+/// Key of the prompt version in the original_file_tags of chunks, see EmbeddingPrompts
+pub const PROMPT_VERSION_TAG: &str = "prompt_version";
+
+// Private constants
+
+const TEXT_PLACEHOLDER: &str = "{text}";
@@ -1,46 +1,155 @@
-use std::sync::{Arc, Mutex, MutexGuard, OnceLock};
+use std::{ops::{Deref, DerefMut}, sync::{Arc, Condvar, Mutex, MutexGuard, OnceLock, Weak}, thread, time::{Duration, Instant}};
 use camino::{Utf8Path, Utf8PathBuf};
-use log::warn;
 use ort::session::{builder::GraphOptimizationLevel, Session};
 use tokenizers::Tokenizer;
+use tracing::{debug, warn};
 
-pub type SessionPool = Arc<Vec<Mutex<Session>>>;
+pub type SessionPool = Arc<PrioritySessionPool>;
+
+/// Who a session is needed for. Sessions are shared between indexing and querying, so when one is released it is
+/// handed to interactive work, eg. embedding a user's query, ahead of any background work, eg. embedding the chunks
+/// of files being indexed. A query then waits for at most one chunk embed rather than a whole queue of them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Priority {
+    Interactive,
+    Background,
+}
 
 pub trait SessionPoolExt {
-    fn get_session(&'_ self) -> MutexGuard<'_, Session>;
+    fn get_session(&'_ self, priority: Priority) -> PooledSession<'_>;
 }
 
 impl SessionPoolExt for SessionPool {
-    fn get_session(&'_ self) -> MutexGuard<'_, Session> {
-        for session_mutex in self.iter() {
-            if let Ok(session) = session_mutex.try_lock() {
-                return session;
+    fn get_session(&'_ self, priority: Priority) -> PooledSession<'_> {
+        let mut schedule = self.schedule.lock().unwrap();
+        if priority == Priority::Interactive {
+            schedule.interactive_waiting += 1;
+        }
+        // Background work waits while there is interactive work waiting, even if a session is free, so a
+        // released session always goes to the interactive work first
+        while schedule.free.is_empty()
+            || (priority == Priority::Background && schedule.interactive_waiting > 0) {
+            schedule = self.released.wait(schedule).unwrap();
+        }
+        if priority == Priority::Interactive {
+            schedule.interactive_waiting -= 1;
+        }
+        let index = schedule.free.pop().expect("Checked above that a session is free");
+        drop(schedule);
+
+        let mut session = self.sessions[index].lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if session.is_none() {
+            *session = Some(load_session(&self.model_path));
+        }
+        PooledSession { pool: self, index, session: Some(session) }
+    }
+}
+
+/// A pool of sessions of the same model, handed out by priority, see Priority. Sessions are loaded when first
+/// needed or when the pool is warmed, and unloaded again once the pool has been idle for longer than the keepalive,
+/// see set_keepalive.
+pub struct PrioritySessionPool {
+    model_path: Utf8PathBuf,
+    sessions: Vec<Mutex<Option<Session>>>,
+    schedule: Mutex<Schedule>,
+    released: Condvar,
+    last_used: Mutex<Instant>,
+}
+
+impl PrioritySessionPool {
+    /// Loads every session of the pool that is not loaded yet, so the next use does not wait for the model
+    pub fn warm(&self) {
+        for session in self.sessions.iter() {
+            let mut session = session.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            if session.is_none() {
+                *session = Some(load_session(&self.model_path));
             }
         }
-        // Fallback to waiting for any available session
-        self[0].lock().unwrap()
+        *self.last_used.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = Instant::now();
+    }
+
+    /// Whether every session of the pool is loaded
+    pub fn is_warm(&self) -> bool {
+        self.sessions.iter().all(|session| session.try_lock().map(|session| session.is_some()).unwrap_or(true))
+    }
+
+    /// Unloads the sessions of the pool if it has not been used for longer than keepalive. Sessions in use are
+    /// left loaded.
+    fn unload_if_idle(&self, keepalive: Duration) {
+        if self.last_used.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).elapsed() < keepalive {
+            return;
+        }
+        let mut unloaded = false;
+        for session in self.sessions.iter() {
+            if let Ok(mut session) = session.try_lock() {
+                unloaded |= session.take().is_some();
+            }
+        }
+        if unloaded {
+            debug!("Unloaded idle model: {}", self.model_path);
+        }
     }
 }
 
-pub fn create_session_pool(pool_size: u32, model_path: &Utf8Path) -> SessionPool {
-    Arc::new(
-        (0..pool_size)
-            .map(|_| {
-                let session_builder = Session::builder()
-                    .expect("Failed to create session builder")
-                    .with_optimization_level(GraphOptimizationLevel::Level3)
-                    .expect("Failed to set optimization level")
-                    .with_intra_threads(4)
-                    .expect("Failed to set intra threads");
+/// A session taken from a pool, returned to the pool when dropped
+pub struct PooledSession<'a> {
+    pool: &'a PrioritySessionPool,
+    index: usize,
+    session: Option<MutexGuard<'a, Option<Session>>>,
+}
 
-                let base_dir = get_base_resource_dir();
+impl Deref for PooledSession<'_> {
+    type Target = Session;
 
-                let session_result = session_builder.commit_from_file(base_dir.join(model_path));
+    fn deref(&self) -> &Session {
+        self.session.as_ref().and_then(|session| session.as_ref()).expect("Session is loaded when taken")
+    }
+}
 
-                Mutex::new(session_result.expect("Failed to commit model from memory"))
-            })
-            .collect()
-    )
+impl DerefMut for PooledSession<'_> {
+    fn deref_mut(&mut self) -> &mut Session {
+        self.session.as_mut().and_then(|session| session.as_mut()).expect("Session is loaded when taken")
+    }
+}
+
+impl Drop for PooledSession<'_> {
+    fn drop(&mut self) {
+        // Unlock the session before handing it back, so whoever is woken does not block on it
+        self.session.take();
+        *self.pool.last_used.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = Instant::now();
+        let mut schedule = self.pool.schedule.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        schedule.free.push(self.index);
+        drop(schedule);
+        self.pool.released.notify_all();
+    }
+}
+
+/// Creates a pool of pool_size sessions of the model. Sessions are not loaded until they are first needed, see
+/// PrioritySessionPool::warm.
+pub fn create_session_pool(pool_size: u32, model_path: &Utf8Path) -> SessionPool {
+    let pool = Arc::new(PrioritySessionPool {
+        model_path: get_base_resource_dir().join(model_path),
+        sessions: (0..pool_size).map(|_| Mutex::new(None)).collect(),
+        schedule: Mutex::new(Schedule { free: (0..pool_size as usize).collect(), interactive_waiting: 0 }),
+        released: Condvar::new(),
+        last_used: Mutex::new(Instant::now()),
+    });
+    POOLS.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).push(Arc::downgrade(&pool));
+    pool
+}
+
+/// Sets how long a model may go unused before its sessions are unloaded to reclaim memory. With None, models stay
+/// loaded once they have been used.
+pub fn set_keepalive(keepalive: Option<Duration>) {
+    *KEEPALIVE.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = keepalive;
+    if keepalive.is_some() {
+        REAPER.get_or_init(|| {
+            thread::Builder::new()
+                .name("model-keepalive".to_owned())
+                .spawn(reap_idle_pools)
+                .expect("Failed to spawn model keepalive thread");
+        });
+    }
 }
 
 pub fn create_tokenizer(tokenizer_path: &Utf8Path) -> Tokenizer {
@@ -65,4 +174,48 @@ pub(crate) fn get_base_resource_dir() -> Utf8PathBuf {
     BASE_RESOURCE_DIRECTORY
         .get_or_init(|| Utf8PathBuf::from("models"))
         .clone()
-}
\ No newline at end of file
+}
+
+// private constants and functions
+
+/// How often idle models are looked for once a keepalive is set
+const REAP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Every pool created, so idle ones can be unloaded
+static POOLS: Mutex<Vec<Weak<PrioritySessionPool>>> = Mutex::new(vec![]);
+static KEEPALIVE: Mutex<Option<Duration>> = Mutex::new(None);
+static REAPER: OnceLock<()> = OnceLock::new();
+
+fn reap_idle_pools() {
+    loop {
+        thread::sleep(REAP_INTERVAL);
+        let Some(keepalive) = *KEEPALIVE.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) else {
+            continue;
+        };
+        let pools: Vec<SessionPool> = POOLS.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+            .iter()
+            .filter_map(Weak::upgrade)
+            .collect();
+        for pool in pools {
+            pool.unload_if_idle(keepalive);
+        }
+    }
+}
+
+fn load_session(model_path: &Utf8Path) -> Session {
+    debug!("Loading model: {}", model_path);
+    Session::builder()
+        .expect("Failed to create session builder")
+        .with_optimization_level(GraphOptimizationLevel::Level3)
+        .expect("Failed to set optimization level")
+        .with_intra_threads(4)
+        .expect("Failed to set intra threads")
+        .commit_from_file(model_path)
+        .expect("Failed to commit model from memory")
+}
+
+/// Which sessions of a pool are free, and how much interactive work is waiting for one
+struct Schedule {
+    free: Vec<usize>,
+    interactive_waiting: usize,
+}
@@ -1,23 +1,25 @@
 use std::sync::LazyLock;
 
 use image::{GenericImageView, imageops::FilterType};
-use log::debug;
 use ndarray::{Array, Axis};
 use ort::{inputs, value::TensorRef};
 use tokenizers::Tokenizer;
 use tokio::task;
+use tracing::{debug, instrument};
 
-use crate::index::{ChunkFile, ChunkType, embedding::{EmbeddingError, sessions::{SessionPool, SessionPoolExt, create_session_pool, create_tokenizer}}};
+use crate::index::{ChunkFile, ChunkType, embedding::{EmbeddingError, cache::QUERY_EMBEDDINGS, prompts::EmbeddingPrompts, sessions::{Priority, PrioritySessionPool, SessionPool, SessionPoolExt, create_session_pool, create_tokenizer}}};
 
 impl Siglip2EmbeddedChunkFile {
     const VECTOR_LENGTH: u32 = 768;
 }
 
+#[derive(Clone)]
 pub struct Siglip2EmbeddedChunkFile {
     pub chunkfile: ChunkFile,
     pub embedding: Vec<f32>,
 }
 
+#[instrument(name = "embed", level = "debug", skip_all, fields(chunkfile = %chunkfile.chunkfile))]
 pub async fn embed_chunk(chunkfile: ChunkFile) -> Result<Siglip2EmbeddedChunkFile, EmbeddingError> {
     if chunkfile.chunk_type != ChunkType::Image {
         return Err(EmbeddingError::InvalidType {
@@ -30,7 +32,7 @@ pub async fn embed_chunk(chunkfile: ChunkFile) -> Result<Siglip2EmbeddedChunkFil
     let image_path = chunkfile.chunkfile.clone();
     let vector = task::spawn_blocking(move || -> Result<Vec<f32>, EmbeddingError> {
         // Get session from pool inside the blocking task
-        let mut model = IMAGE_SESSION_POOL.get_session();
+        let mut model = IMAGE_SESSION_POOL.get_session(Priority::Background);
         
         // load image
         let img = image::ImageReader::open(&image_path)
@@ -84,70 +86,71 @@ pub async fn embed_chunk(chunkfile: ChunkFile) -> Result<Siglip2EmbeddedChunkFil
     })
 }
 
+#[instrument(name = "embed_query", level = "debug", skip_all)]
 pub async fn embed_query(query: &str) -> Result<Vec<f32>, EmbeddingError> {
-    let query_copy = query.to_string();
-    let s = query.to_lowercase();
-    let result = task::spawn_blocking(move || -> Result<Vec<f32>, EmbeddingError> {
-        let mut model = TEXT_SESSION_POOL.get_session();
-        let tokenizer = &TEXT_TOKENIZER;
+    QUERY_EMBEDDINGS.get_or_embed(TEXT_MODEL_PATH, query, async {
+        let query_copy = query.to_string();
+        let s = PROMPTS.prompt_query(query).to_lowercase();
+        task::spawn_blocking(move || -> Result<Vec<f32>, EmbeddingError> {
+            let mut model = TEXT_SESSION_POOL.get_session(Priority::Interactive);
+            let tokenizer = &TEXT_TOKENIZER;
         
-        let encoding = tokenizer.encode(s, false)
-            .map_err(|e| EmbeddingError::Preprocessing { 
-                element: format!("Query: {}" , query_copy),
-                step: "tokenizing",
-                source: anyhow::anyhow!(e) })?;
-        let input_ids = encoding.get_ids().iter().map(|n| *n as i64).collect();
-
-        let input = Array::from_vec(input_ids)
-            .insert_axis(Axis(0));
-
-        let result = model.run(inputs![
-                "input_ids" => TensorRef::from_array_view(&input)
-                    .map_err(|e| EmbeddingError::Preprocessing { 
-                        element: format!("Query: {}" , query_copy),
-                        step: "Converting to tensor", 
-                        source: e.into(),
-                    })?
-            ])
-            .map_err(|e| EmbeddingError::Calculation {
-                element: format!("Query: {}" , query_copy),
-                step: "Performing text embedding", source: e.into()
-            })?
-            .get("pooler_output")
-            .expect("model should place output in 'pooler_output' key")
-            .try_extract_array::<f32>()
-            .map_err(|e| EmbeddingError::Unknown {
-                msg: "Error while extracting array from output as f32",
-                source: e.into(),
-            })?
-            .into_owned()
-            .into_shape_with_order((Siglip2EmbeddedChunkFile::VECTOR_LENGTH as usize,))
-            .expect("Model should return a (1, 768) shaped array which should be able to be reshaped into a vector")
-            .to_vec();
+            let encoding = tokenizer.encode(s, false)
+                .map_err(|e| EmbeddingError::Preprocessing { 
+                    element: format!("Query: {}" , query_copy),
+                    step: "tokenizing",
+                    source: anyhow::anyhow!(e) })?;
+            let input_ids = encoding.get_ids().iter().map(|n| *n as i64).collect();
+
+            let input = Array::from_vec(input_ids)
+                .insert_axis(Axis(0));
+
+            let result = model.run(inputs![
+                    "input_ids" => TensorRef::from_array_view(&input)
+                        .map_err(|e| EmbeddingError::Preprocessing { 
+                            element: format!("Query: {}" , query_copy),
+                            step: "Converting to tensor", 
+                            source: e.into(),
+                        })?
+                ])
+                .map_err(|e| EmbeddingError::Calculation {
+                    element: format!("Query: {}" , query_copy),
+                    step: "Performing text embedding", source: e.into()
+                })?
+                .get("pooler_output")
+                .expect("model should place output in 'pooler_output' key")
+                .try_extract_array::<f32>()
+                .map_err(|e| EmbeddingError::Unknown {
+                    msg: "Error while extracting array from output as f32",
+                    source: e.into(),
+                })?
+                .into_owned()
+                .into_shape_with_order((Siglip2EmbeddedChunkFile::VECTOR_LENGTH as usize,))
+                .expect("Model should return a (1, 768) shaped array which should be able to be reshaped into a vector")
+                .to_vec();
         
-        Ok(result)
-    })
-    .await
-    .map_err(|e| EmbeddingError::Unknown { msg: "Error while joining embedding blocking task",
-        source: e.into() })?;
-
-    result
+            Ok(result)
+        })
+        .await
+        .map_err(|e| EmbeddingError::Unknown { msg: "Error while joining embedding blocking task",
+            source: e.into() })?
+    }).await
 }
 
-/// Init function that retrieves indexing resources and then immediately drops them to initialize lazy cells
+/// Session pool of the image model, used for indexing, see environment::warm_models
 /// 
 /// sessions::init_model_resource_directory must be called before this function or all models will be initialized
 /// from a binary relative models/ path
-pub fn init_indexing() {
-    LazyLock::force(&IMAGE_SESSION_POOL);
+pub(crate) fn image_session_pool() -> &'static PrioritySessionPool {
+    &IMAGE_SESSION_POOL
 }
 
-/// Init function that retrieves querying resources and then immediately drops them to initialize lazy cells
+/// Session pool of the text model, used for querying, see environment::warm_models
 /// 
 /// sessions::init_model_resource_directory must be called before this function or all models will be initialized
 /// from a binary relative models/ path
-pub fn init_querying() {
-    LazyLock::force(&TEXT_SESSION_POOL);
+pub(crate) fn text_session_pool() -> &'static PrioritySessionPool {
+    &TEXT_SESSION_POOL
 }
 
 pub use integrations::*;
@@ -168,6 +171,15 @@ static TEXT_SESSION_POOL: LazyLock<SessionPool> = LazyLock::new(|| {
     create_session_pool(1, TEXT_MODEL_PATH.into())
 });
 
+/// The prompt SigLIP 2 was evaluated with for zero-shot classification, unless set otherwise in the settings. Images
+/// are embedded as they are, so only queries have a prompt.
+static PROMPTS: LazyLock<EmbeddingPrompts> = LazyLock::new(|| EmbeddingPrompts::from_settings_or("siglip2",
+    EmbeddingPrompts {
+        version: 1,
+        query: "this is a photo of {text}.".to_owned(),
+        document: None,
+    }));
+
 static TEXT_TOKENIZER: LazyLock<Tokenizer> = LazyLock::new(|| {
     debug!("Initializing text tokenizer resources for Siglip2 Embedder");
     create_tokenizer(TOKENIZER_PATH.into())
@@ -6,7 +6,7 @@ use arrow_array::{ArrayRef, FixedSizeListArray, RecordBatch};
 use arrow_schema::{DataType, Field, Schema};
 
 use crate::index::{ChunkFile, embedding::siglip2::Siglip2EmbeddedChunkFile};
-use crate::store::{FTSData, Filterable, lancedb::{ArrowData, RowBuilder}, KeyedSequencedData, VectorData};
+use crate::store::{FTSData, Filterable, FtsAnalyzer, lancedb::{ArrowData, RowBuilder}, KeyedSequencedData, VectorData};
 
 impl Siglip2EmbeddedChunkFile {
     const VECTOR_ATTRIBUTE_NAME: &str = "embedding";
@@ -80,6 +80,14 @@ impl ArrowData for Siglip2EmbeddedChunkFile {
                 could not be merged with ChunkFile schema"))
     }
 
+    fn table_schema() -> Schema {
+        let chunkfile_schema = ChunkFile::table_schema();
+        let extended_schema = Schema::new(vec![VECTOR_FIELD.clone()]);
+        Schema::try_merge([chunkfile_schema, extended_schema])
+            .unwrap_or_else(|_e| panic!("Siglip2EmbeddedChunkFile extended table schema \
+                could not be merged with ChunkFile table schema"))
+    }
+
     fn row_builder() -> Self::RowBuilder {
         Siglip2EmbeddedChunkFileRowBuilder::new()
     }
@@ -148,10 +156,18 @@ impl Filterable for Siglip2EmbeddedChunkFile {
     fn filterable_attributes() -> Vec<&'static str> {
         ChunkFile::filterable_attributes()
     }
+
+    fn keyed_attributes() -> Vec<&'static str> {
+        ChunkFile::keyed_attributes()
+    }
 }
 
 impl FTSData for Siglip2EmbeddedChunkFile {
     fn fts_attributes() -> Vec<&'static str> {
         ChunkFile::fts_attributes()
     }
+
+    fn fts_analyzer(attr: &str) -> FtsAnalyzer {
+        ChunkFile::fts_analyzer(attr)
+    }
 }
\ No newline at end of file
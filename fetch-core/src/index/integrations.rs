@@ -1,15 +1,16 @@
 use std::sync::{Arc, LazyLock};
-use arrow::array::{StringBuilder, Float32Builder, UInt64Builder, TimestampMillisecondBuilder, AsArray};
+use arrow::array::{StringBuilder, LargeStringBuilder, Float32Builder, UInt64Builder, TimestampMillisecondBuilder, ListBuilder, AsArray};
 use arrow::datatypes::{Float32Type, TimestampMillisecondType, UInt64Type};
-use arrow_array::{RecordBatch, ArrayRef};
+use arrow_array::{Array, RecordBatch, ArrayRef};
 use arrow_schema::{DataType, Field, Schema, TimeUnit};
 use camino::Utf8PathBuf;
 use chrono::{Utc, TimeZone};
 use serde_json::Value;
 use serde_json::Map;
 
-use crate::index::{ChunkFile, ChunkType};
-use crate::store::{FTSData, Filterable, lancedb::{ArrowData, RowBuilder}};
+use crate::app_config;
+use crate::index::{ChunkFile, ChunkType, language::{self, UNDETERMINED_LANGUAGE}};
+use crate::store::{FTSData, Filterable, FtsAnalyzer, lancedb::{ArrowData, RowBuilder, SEQUENCE_NUMBER_COLUMN}};
 
 // Chunkfile ArrowData integrations
 
@@ -25,6 +26,31 @@ impl ChunkFile {
     pub const FILE_MODIFIED_DATE_ATTR: &str = "original_file_modified_date";
     pub const FILE_SIZE_ATTR: &str = "original_file_size";
     pub const FILE_TAGS_ATTR: &str = "original_file_tags";
+    pub const LANGUAGE_ATTR: &str = "language";
+    pub const USER_TAGS_ATTR: &str = "user_tags";
+    /// Full text of chunks in languages written without spaces, see language::is_unspaced, and empty for others.
+    /// Only written, to be searched with the Bigrams analyzer.
+    pub const BIGRAM_TEXT_ATTR: &str = "bigram_text";
+    /// Full text of text chunks, which providers put in original_file_tags under FULL_TEXT_TAG. It is stored in a
+    /// column of its own rather than in the tags JSON, and put back into the tags when read, so queries that do not
+    /// select it (eg. those reading QUERY_RESULT_ATTRS) neither read nor parse it. Chunks stored before the column
+    /// existed keep their full text in the tags until they are indexed again.
+    pub const FULL_TEXT_ATTR: &str = "full_text";
+    /// Key of the full text of a chunk in its original_file_tags, see FULL_TEXT_ATTR
+    pub const FULL_TEXT_TAG: &str = "full_text";
+    pub const ORIGINAL_FILE_ID_ATTR: &str = "original_file_id";
+    pub const FILE_FINGERPRINT_ATTR: &str = "original_file_fingerprint";
+    /// Attributes of the chunks providers return that file queries read (see files::FileQueryer), along with score
+    /// normalizing and reranking. Providers that need no others query only these, so the full text kept in the
+    /// original_file_tags of chunks is not read and decoded for every result.
+    pub const QUERY_RESULT_ATTRS: &[&str] = &[
+        ChunkFile::ORIGINAL_FILE_ATTR,
+        ChunkFile::CHUNK_CHANNEL_ATTR,
+        ChunkFile::CHUNKFILE_ATTR,
+        ChunkFile::CHUNK_TYPE_ATTR,
+        ChunkFile::FILE_MODIFIED_DATE_ATTR,
+        ChunkFile::USER_TAGS_ATTR,
+    ];
 
     // Column names (Arrow schema column names)
     const ORIGINAL_FILE_COLUMN_NAME: &str = "original_file";
@@ -37,6 +63,12 @@ impl ChunkFile {
     const FILE_MODIFIED_DATE_COLUMN_NAME: &str = "original_file_modified_date";
     const FILE_SIZE_COLUMN_NAME: &str = "original_file_size";
     const FILE_TAGS_COLUMN_NAME: &str = "original_file_tags";
+    const LANGUAGE_COLUMN_NAME: &str = "language";
+    const USER_TAGS_COLUMN_NAME: &str = "user_tags";
+    const BIGRAM_TEXT_COLUMN_NAME: &str = "bigram_text";
+    const FULL_TEXT_COLUMN_NAME: &str = "full_text";
+    const ORIGINAL_FILE_ID_COLUMN_NAME: &str = "original_file_id";
+    const FILE_FINGERPRINT_COLUMN_NAME: &str = "original_file_fingerprint";
 }
 
 static ORIGINAL_FILE_FIELD: LazyLock<Arc<Field>> = LazyLock::new(|| {
@@ -69,6 +101,30 @@ static FILE_SIZE_FIELD: LazyLock<Arc<Field>> = LazyLock::new(|| {
 static FILE_TAGS_FIELD: LazyLock<Arc<Field>> = LazyLock::new(|| {
     Arc::new(Field::new(ChunkFile::FILE_TAGS_COLUMN_NAME, DataType::Utf8, false))
 });
+static LANGUAGE_FIELD: LazyLock<Arc<Field>> = LazyLock::new(|| {
+    Arc::new(Field::new(ChunkFile::LANGUAGE_COLUMN_NAME, DataType::Utf8, false))
+});
+static USER_TAGS_FIELD: LazyLock<Arc<Field>> = LazyLock::new(|| {
+    Arc::new(Field::new_list(ChunkFile::USER_TAGS_COLUMN_NAME, Field::new_list_field(DataType::Utf8, true), false))
+});
+static BIGRAM_TEXT_FIELD: LazyLock<Arc<Field>> = LazyLock::new(|| {
+    Arc::new(Field::new(ChunkFile::BIGRAM_TEXT_COLUMN_NAME, DataType::Utf8, false))
+});
+
+/// Null for chunks without full text. Compressed with zstd if compress_full_text is set in data.toml when the column
+/// is created, see ChunkFile::table_schema.
+static FULL_TEXT_FIELD: LazyLock<Arc<Field>> = LazyLock::new(|| {
+    Arc::new(Field::new(ChunkFile::FULL_TEXT_COLUMN_NAME, DataType::LargeUtf8, true))
+});
+
+/// Null for chunks of files without an id, and chunks indexed before file ids were stored
+static ORIGINAL_FILE_ID_FIELD: LazyLock<Arc<Field>> = LazyLock::new(|| {
+    Arc::new(Field::new(ChunkFile::ORIGINAL_FILE_ID_COLUMN_NAME, DataType::Utf8, true))
+});
+/// Null for chunks of files that could not be read, and chunks indexed before fingerprints were stored
+static FILE_FINGERPRINT_FIELD: LazyLock<Arc<Field>> = LazyLock::new(|| {
+    Arc::new(Field::new(ChunkFile::FILE_FINGERPRINT_COLUMN_NAME, DataType::Utf8, true))
+});
 
 static CHUNKFILE_SCHEMA: LazyLock<Schema> = LazyLock::new(|| {
     Schema::new(vec![
@@ -82,6 +138,12 @@ static CHUNKFILE_SCHEMA: LazyLock<Schema> = LazyLock::new(|| {
         FILE_MODIFIED_DATE_FIELD.clone(),
         FILE_SIZE_FIELD.clone(),
         FILE_TAGS_FIELD.clone(),
+        LANGUAGE_FIELD.clone(),
+        USER_TAGS_FIELD.clone(),
+        BIGRAM_TEXT_FIELD.clone(),
+        FULL_TEXT_FIELD.clone(),
+        ORIGINAL_FILE_ID_FIELD.clone(),
+        FILE_FINGERPRINT_FIELD.clone(),
     ])
 });
 
@@ -96,6 +158,12 @@ pub struct ChunkFileRowBuilder {
     original_file_modified_date: TimestampMillisecondBuilder,
     original_file_size: UInt64Builder,
     original_file_tags: StringBuilder,
+    language: StringBuilder,
+    user_tags: ListBuilder<StringBuilder>,
+    bigram_text: StringBuilder,
+    full_text: LargeStringBuilder,
+    original_file_id: StringBuilder,
+    original_file_fingerprint: StringBuilder,
 }
 
 impl Default for ChunkFileRowBuilder {
@@ -117,6 +185,12 @@ impl ChunkFileRowBuilder {
             original_file_modified_date: TimestampMillisecondBuilder::new().with_timezone("UTC"),
             original_file_size: UInt64Builder::new(),
             original_file_tags: StringBuilder::new(),
+            language: StringBuilder::new(),
+            user_tags: ListBuilder::new(StringBuilder::new()),
+            bigram_text: StringBuilder::new(),
+            full_text: LargeStringBuilder::new(),
+            original_file_id: StringBuilder::new(),
+            original_file_fingerprint: StringBuilder::new(),
         }
     }
 }
@@ -133,9 +207,30 @@ impl RowBuilder<ChunkFile> for ChunkFileRowBuilder {
         self.original_file_modified_date.append_value(row.original_file_modified_date.timestamp_millis());
         self.original_file_size.append_value(row.original_file_size);
 
+        // The full text is stored in its own column, see ChunkFile::FULL_TEXT_ATTR
+        let mut tags = row.original_file_tags;
+        let full_text = match tags.remove(ChunkFile::FULL_TEXT_TAG) {
+            Some(Value::String(text)) => Some(text),
+            Some(other) => {
+                tags.insert(ChunkFile::FULL_TEXT_TAG.to_owned(), other);
+                None
+            },
+            None => None,
+        };
+
         // Serialize tags as JSON string
-        let tags_json = serde_json::to_string(&row.original_file_tags).unwrap_or_else(|_| "{}".to_string());
+        let tags_json = serde_json::to_string(&tags).unwrap_or_else(|_| "{}".to_string());
         self.original_file_tags.append_value(&tags_json);
+        let bigram_text = match &full_text {
+            Some(text) if language::is_unspaced(&row.language) => text.as_str(),
+            _ => "",
+        };
+        self.bigram_text.append_value(bigram_text);
+        self.full_text.append_option(full_text);
+        self.language.append_value(&row.language);
+        self.user_tags.append_value(row.user_tags.into_iter().map(Some));
+        self.original_file_id.append_option(row.original_file_id);
+        self.original_file_fingerprint.append_option(row.original_file_fingerprint);
     }
 
     fn finish(mut self) -> Vec<(Arc<Field>, ArrayRef)> {
@@ -150,6 +245,12 @@ impl RowBuilder<ChunkFile> for ChunkFileRowBuilder {
             (FILE_MODIFIED_DATE_FIELD.clone(), Arc::new(self.original_file_modified_date.finish())),
             (FILE_SIZE_FIELD.clone(), Arc::new(self.original_file_size.finish())),
             (FILE_TAGS_FIELD.clone(), Arc::new(self.original_file_tags.finish())),
+            (LANGUAGE_FIELD.clone(), Arc::new(self.language.finish())),
+            (USER_TAGS_FIELD.clone(), Arc::new(self.user_tags.finish())),
+            (BIGRAM_TEXT_FIELD.clone(), Arc::new(self.bigram_text.finish())),
+            (FULL_TEXT_FIELD.clone(), Arc::new(self.full_text.finish())),
+            (ORIGINAL_FILE_ID_FIELD.clone(), Arc::new(self.original_file_id.finish())),
+            (FILE_FINGERPRINT_FIELD.clone(), Arc::new(self.original_file_fingerprint.finish())),
         ]
     }
 }
@@ -161,6 +262,22 @@ impl ArrowData for ChunkFile {
         CHUNKFILE_SCHEMA.clone()
     }
 
+    /// The full text column is compressed as set in the settings when the table is opened, which LanceDB reads from
+    /// the field metadata when the column is created
+    fn table_schema() -> Schema {
+        if !app_config::is_full_text_compressed() {
+            return CHUNKFILE_SCHEMA.clone();
+        }
+        Schema::new(CHUNKFILE_SCHEMA.fields().iter().map(|field| {
+            if field.name() == ChunkFile::FULL_TEXT_COLUMN_NAME {
+                Arc::new(field.as_ref().clone()
+                    .with_metadata([(COMPRESSION_METADATA_KEY.to_owned(), "zstd".to_owned())].into()))
+            } else {
+                field.clone()
+            }
+        }).collect::<Vec<_>>())
+    }
+
     fn row_builder() -> Self::RowBuilder {
         ChunkFileRowBuilder::new()
     }
@@ -218,13 +335,55 @@ impl ArrowData for ChunkFile {
                 .as_string::<i32>()
                 .value(i);
 
-            let tags: Map<String, Value> = serde_json::from_str(tags_json_str)
+            let mut tags: Map<String, Value> = serde_json::from_str(tags_json_str)
                 .unwrap_or_else(|_| Map::new());
+            // Rows stored before the full text had its own column have a null full text, and keep it in the tags. The
+            // column is missing from projections that do not select it, and from tables not yet migrated to it.
+            if let Some(full_text_column) = record_batch.column_by_name(ChunkFile::FULL_TEXT_COLUMN_NAME) {
+                let full_text_column = full_text_column.as_string::<i64>();
+                if !full_text_column.is_null(i) {
+                    tags.insert(ChunkFile::FULL_TEXT_TAG.to_owned(), full_text_column.value(i).into());
+                }
+            }
+            let original_file_id = record_batch.column_by_name(ChunkFile::ORIGINAL_FILE_ID_COLUMN_NAME)
+                .expect("original_file_id column not found")
+                .as_string::<i32>();
+            let original_file_id = (!original_file_id.is_null(i)).then(|| original_file_id.value(i).to_string());
+            let original_file_fingerprint = record_batch.column_by_name(ChunkFile::FILE_FINGERPRINT_COLUMN_NAME)
+                .expect("original_file_fingerprint column not found")
+                .as_string::<i32>();
+            let original_file_fingerprint = (!original_file_fingerprint.is_null(i))
+                .then(|| original_file_fingerprint.value(i).to_string());
+            // Rows indexed before languages were detected have a null language
+            let language = record_batch.column_by_name(ChunkFile::LANGUAGE_COLUMN_NAME)
+                .expect("language column not found")
+                .as_string::<i32>()
+                .value(i);
+            let language = if language.is_empty() { UNDETERMINED_LANGUAGE } else { language };
+            // Rows indexed before user tags were added have null tags
+            let user_tags_list = record_batch.column_by_name(ChunkFile::USER_TAGS_COLUMN_NAME)
+                .expect("user_tags column not found")
+                .as_list::<i32>();
+            let user_tags = if user_tags_list.is_null(i) {
+                vec![]
+            } else {
+                user_tags_list.value(i)
+                    .as_string::<i32>()
+                    .iter()
+                    .flatten()
+                    .map(str::to_owned)
+                    .collect()
+            };
+            // The sequence number is stored by the store itself, so it is missing from batches not read from one
+            let sequence_number = record_batch.column_by_name(SEQUENCE_NUMBER_COLUMN)
+                .map(|column| column.as_primitive::<UInt64Type>().value(i))
+                .unwrap_or(original_file_modified_date as u64);
 
             ChunkFile {
                 original_file: Utf8PathBuf::from(original_file),
                 chunk_channel,
                 chunk_sequence_id,
+                original_file_id,
                 chunkfile: Utf8PathBuf::from(chunkfile),
                 chunk_type: string_to_chunk_type(chunk_type),
                 chunk_length,
@@ -233,7 +392,11 @@ impl ArrowData for ChunkFile {
                 original_file_modified_date: Utc.timestamp_millis_opt(
                     original_file_modified_date).unwrap(),
                 original_file_size,
+                original_file_fingerprint,
                 original_file_tags: tags,
+                language: language.to_owned(),
+                user_tags,
+                sequence_number,
             }
         })
     }
@@ -250,6 +413,12 @@ impl ArrowData for ChunkFile {
             ChunkFile::FILE_MODIFIED_DATE_ATTR => ChunkFile::FILE_MODIFIED_DATE_COLUMN_NAME,
             ChunkFile::FILE_SIZE_ATTR => ChunkFile::FILE_SIZE_COLUMN_NAME,
             ChunkFile::FILE_TAGS_ATTR => ChunkFile::FILE_TAGS_COLUMN_NAME,
+            ChunkFile::LANGUAGE_ATTR => ChunkFile::LANGUAGE_COLUMN_NAME,
+            ChunkFile::USER_TAGS_ATTR => ChunkFile::USER_TAGS_COLUMN_NAME,
+            ChunkFile::BIGRAM_TEXT_ATTR => ChunkFile::BIGRAM_TEXT_COLUMN_NAME,
+            ChunkFile::FULL_TEXT_ATTR => ChunkFile::FULL_TEXT_COLUMN_NAME,
+            ChunkFile::ORIGINAL_FILE_ID_ATTR => ChunkFile::ORIGINAL_FILE_ID_COLUMN_NAME,
+            ChunkFile::FILE_FINGERPRINT_ATTR => ChunkFile::FILE_FINGERPRINT_COLUMN_NAME,
             _ => panic!("Unknown ChunkFile attribute: {}", attr),
         }
     }
@@ -259,11 +428,20 @@ impl Filterable for ChunkFile {
     fn filterable_attributes() -> Vec<&'static str> {
         [
             ChunkFile::ORIGINAL_FILE_ATTR,
+            ChunkFile::ORIGINAL_FILE_ID_ATTR,
+            ChunkFile::CHUNK_CHANNEL_ATTR,
             ChunkFile::FILE_CREATION_DATE_ATTR,
             ChunkFile::FILE_MODIFIED_DATE_ATTR,
             ChunkFile::FILE_SIZE_ATTR,
+            ChunkFile::FILE_FINGERPRINT_ATTR,
+            ChunkFile::LANGUAGE_ATTR,
+            ChunkFile::USER_TAGS_ATTR,
         ].to_vec()
     }
+
+    fn keyed_attributes() -> Vec<&'static str> {
+        vec![ChunkFile::FILE_TAGS_ATTR]
+    }
 }
 
 impl FTSData for ChunkFile {
@@ -271,12 +449,25 @@ impl FTSData for ChunkFile {
         [
             ChunkFile::ORIGINAL_FILE_ATTR,
             ChunkFile::FILE_TAGS_ATTR,
+            ChunkFile::USER_TAGS_ATTR,
+            ChunkFile::BIGRAM_TEXT_ATTR,
+            ChunkFile::FULL_TEXT_ATTR,
         ].to_vec()
     }
+
+    fn fts_analyzer(attr: &str) -> FtsAnalyzer {
+        match attr {
+            ChunkFile::BIGRAM_TEXT_ATTR => FtsAnalyzer::Bigrams,
+            _ => FtsAnalyzer::Words,
+        }
+    }
 }
 
 // private methods
 
+/// Field metadata key LanceDB reads the compression of a column from
+const COMPRESSION_METADATA_KEY: &str = "lance-encoding:compression";
+
 fn chunk_type_to_string(ty: ChunkType) -> String {
     match ty {
         ChunkType::Text => "text",
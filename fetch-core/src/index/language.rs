@@ -0,0 +1,96 @@
+use whatlang::Lang;
+
+/// ISO 639-3 code given to chunks whose language could not be determined, including chunks with no text
+pub const UNDETERMINED_LANGUAGE: &str = "und";
+
+/// Detects the language of a piece of text, returning its ISO 639-3 code. Returns UNDETERMINED_LANGUAGE if the
+/// text is too short or too mixed to be detected reliably.
+pub fn detect_language(text: &str) -> &'static str {
+    match whatlang::detect(text) {
+        Some(info) if info.is_reliable() => info.lang().code(),
+        _ => UNDETERMINED_LANGUAGE,
+    }
+}
+
+/// Resolves a language written by a user into the ISO 639-3 code that chunks are tagged with. Accepts ISO 639-1
+/// codes ("ja"), ISO 639-3 codes ("jpn") and English language names ("japanese").
+pub fn resolve_language_code(language: &str) -> Option<&'static str> {
+    let language = language.to_lowercase();
+    if let Some(lang) = Lang::from_code(&language) {
+        return Some(lang.code());
+    }
+    if let Some((_, code)) = ISO_639_1_CODES.iter().find(|(short, _)| *short == language) {
+        return Some(code);
+    }
+    Lang::all().iter()
+        .find(|lang| lang.eng_name().eq_ignore_ascii_case(&language))
+        .map(|lang| lang.code())
+}
+
+/// Resolves a programming language written by a user into the name that code chunks are tagged with, eg.
+/// "rust" for "rs". Returns None for languages whose code is not chunked by symbol.
+pub fn resolve_code_language(language: &str) -> Option<&'static str> {
+    let language = language.to_lowercase();
+    CODE_LANGUAGES.iter()
+        .find(|(name, aliases)| *name == language || aliases.contains(&language.as_str()))
+        .map(|(name, _)| *name)
+}
+
+/// Whether the language, as an ISO 639-3 code, is written without spaces between its words, so that its text has
+/// to be searched by pairs of characters rather than by words. Korean is spaced, but its words are long compounds
+/// that are searched by their parts too.
+pub fn is_unspaced(language: &str) -> bool {
+    UNSPACED_LANGUAGES.contains(&language)
+}
+
+/// Estimates the number of model tokens in a piece of text. Each whitespace separated word is counted as one
+/// token, except that every Han or Kana character is counted as a token of its own, since Chinese and Japanese
+/// do not separate words with whitespace.
+pub fn estimate_tokens(text: &str) -> u32 {
+    text.split_whitespace()
+        .map(|word| {
+            let cjk_chars = word.chars().filter(|c| is_cjk(*c)).count() as u32;
+            let has_other_chars = word.chars().any(|c| !is_cjk(c));
+            cjk_chars + has_other_chars as u32
+        })
+        .sum()
+}
+
+// Private variables and functions
+
+/// Chinese, Japanese, Korean, Thai, Khmer and Burmese
+const UNSPACED_LANGUAGES: &[&str] = &["cmn", "jpn", "kor", "tha", "khm", "mya"];
+
+/// Programming languages code is chunked by symbol for, along with other names users may write them as
+const CODE_LANGUAGES: &[(&str, &[&str])] = &[
+    ("rust", &["rs"]),
+    ("python", &["py"]),
+    ("javascript", &["js"]),
+    ("typescript", &["ts", "tsx"]),
+    ("go", &["golang"]),
+    ("java", &[]),
+];
+
+/// ISO 639-1 codes for the languages that can be detected, mapped to their ISO 639-3 codes
+const ISO_639_1_CODES: &[(&str, &str)] = &[
+    ("af", "afr"), ("ak", "aka"), ("am", "amh"), ("ar", "ara"), ("az", "aze"), ("be", "bel"), ("bg", "bul"),
+    ("bn", "ben"), ("ca", "cat"), ("cs", "ces"), ("da", "dan"), ("de", "deu"), ("el", "ell"), ("en", "eng"),
+    ("eo", "epo"), ("es", "spa"), ("et", "est"), ("fa", "pes"), ("fi", "fin"), ("fr", "fra"), ("gu", "guj"),
+    ("he", "heb"), ("hi", "hin"), ("hr", "hrv"), ("hu", "hun"), ("hy", "hye"), ("id", "ind"), ("it", "ita"),
+    ("ja", "jpn"), ("jv", "jav"), ("ka", "kat"), ("km", "khm"), ("kn", "kan"), ("ko", "kor"), ("la", "lat"),
+    ("lt", "lit"), ("lv", "lav"), ("mk", "mkd"), ("ml", "mal"), ("mr", "mar"), ("my", "mya"), ("nb", "nob"),
+    ("ne", "nep"), ("nl", "nld"), ("or", "ori"), ("pa", "pan"), ("pl", "pol"), ("pt", "por"), ("ro", "ron"),
+    ("ru", "rus"), ("si", "sin"), ("sk", "slk"), ("sl", "slv"), ("sn", "sna"), ("sr", "srp"), ("sv", "swe"),
+    ("ta", "tam"), ("te", "tel"), ("th", "tha"), ("tk", "tuk"), ("tl", "tgl"), ("tr", "tur"), ("uk", "ukr"),
+    ("ur", "urd"), ("uz", "uzb"), ("vi", "vie"), ("yi", "yid"), ("zh", "cmn"), ("zu", "zul"),
+];
+
+fn is_cjk(c: char) -> bool {
+    matches!(c,
+        '\u{3040}'..='\u{30FF}' | // Hiragana and Katakana
+        '\u{3400}'..='\u{4DBF}' | // CJK Unified Ideographs Extension A
+        '\u{4E00}'..='\u{9FFF}' | // CJK Unified Ideographs
+        '\u{F900}'..='\u{FAFF}' | // CJK Compatibility Ideographs
+        '\u{20000}'..='\u{2FA1F}' // CJK Unified Ideographs Extensions B and beyond
+    )
+}
@@ -1,29 +1,168 @@
-use std::hash::{DefaultHasher, Hash, Hasher};
+use std::{future::Future, hash::{DefaultHasher, Hash, Hasher}};
 
 use async_trait::async_trait;
 use camino::{Utf8Path, Utf8PathBuf};
 use chrono::{DateTime, Utc};
-use log::debug;
 use tokio::{fs, io};
+use tracing::debug;
 
-use crate::{app_config::get_default_chunk_directory, index::ChunkFile};
+use crate::{app_config::get_default_chunk_directory, files::volumes, index::{ChunkFile, provider::registry::ProviderCapabilities}, sequence, store::{ChunkStore, FTSData, Filter, FilterRelation, FilterValue, Filterable, KeyedSequencedData, VectorData}};
 
 #[async_trait]
 pub trait ChunkingIndexProvider: Send + Sync {
+    /// Name used to identify this provider in results and errors
+    fn provider_name(&self) -> &'static str;
     fn provides_indexing_for_extension(&self, ext: &str) -> bool;
+    /// MIME types and cost of the provider, used by the ProviderRegistry to route files and pick providers
+    fn capabilities(&self) -> ProviderCapabilities;
     // I see no point to providing opt_modified on the index API, as we can always get it from 
     // the source of truth, the file itself.
     async fn index(&self, path: &Utf8Path, opt_modified: Option<DateTime<Utc>>) -> Result<(), IndexProviderError>;
     async fn clear(&self, path: &Utf8Path, opt_modified: Option<DateTime<Utc>>) -> Result<(), IndexProviderError>;
-    async fn query_n(&self, str: &str, num_results: u32, offset: u32) -> Result<Vec<ChunkQueryResult>, IndexProviderError>;
+    /// Replaces the user tags stored on every chunk indexed for the file. Files that have not been indexed by this
+    /// provider are left untouched.
+    async fn tag(&self, path: &Utf8Path, tags: &[String]) -> Result<(), IndexProviderError>;
+    /// Restores the cleared chunks of the file at the path, or of every file under the path if it is a directory.
+    /// Returns the number of chunks restored.
+    async fn restore(&self, path: &Utf8Path) -> Result<u64, IndexProviderError>;
+    /// Moves the chunks indexed for the file at from to the path the file was moved or renamed to, keeping their
+    /// embeddings rather than indexing the file again. Chunks indexed for another file at the new path are cleared.
+    /// Returns the number of chunks moved, 0 if the file has not been indexed by this provider.
+    async fn rename(&self, from: &Utf8Path, to: &Utf8Path) -> Result<u64, IndexProviderError>;
+    /// The file with the id (see files::volumes::file_id) as it was indexed by this provider, if it was indexed with
+    /// its id
+    async fn indexed_file(&self, file_id: &str) -> Result<Option<IndexedFile>, IndexProviderError>;
+    /// Paths files with the content fingerprint (see files::dupes::content_fingerprint) were indexed under by this
+    /// provider, without duplicates. Only the first MAX_CONTENT_MATCH_CHUNKS chunks with the fingerprint are read.
+    async fn indexed_paths_by_content(&self, fingerprint: &str) -> Result<Vec<Utf8PathBuf>, IndexProviderError>;
+    /// The chunks after offset in the provider's ranking of its chunks for the query, at most num_results, best
+    /// first. Providers with more than one store rank the chunks of all of them together, see
+    /// score::ScoreNormalizer::normalize_page.
+    async fn query_n(&self, str: &str, filters: &ChunkQueryFilters, num_results: u32, offset: u32)
+        -> Result<Vec<ChunkQueryResult>, IndexProviderError>;
+    /// Chunks of other files whose embeddings are nearest to the chunks indexed for the file, scored by cosine
+    /// similarity (0-100). Returns no results if the file has not been indexed by this provider.
+    async fn query_related_n(&self, path: &Utf8Path, num_results: u32)
+        -> Result<Vec<ChunkQueryResult>, IndexProviderError>;
+    /// Words taken from the names and tags of every file indexed by this provider. Used to build the dictionary
+    /// for query spelling correction.
+    async fn vocabulary(&self) -> Result<Vec<String>, IndexProviderError>;
+}
+
+/// A file as it was when it was indexed, read from one of its stored chunks
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexedFile {
+    pub path: Utf8PathBuf,
+    pub size: u64,
+    pub modified: DateTime<Utc>,
+    /// See ChunkFile::original_file_fingerprint
+    pub fingerprint: Option<String>,
+}
+
+impl From<ChunkFile> for IndexedFile {
+    fn from(chunkfile: ChunkFile) -> Self {
+        IndexedFile {
+            path: chunkfile.original_file,
+            size: chunkfile.original_file_size,
+            modified: chunkfile.original_file_modified_date,
+            fingerprint: chunkfile.original_file_fingerprint,
+        }
+    }
+}
+
+/// Constraints on the chunks returned by a provider query, in addition to the query string
+#[derive(Debug, Clone, Default)]
+pub struct ChunkQueryFilters {
+    /// ISO 639-3 code of the language chunks must be written in. Chunks without text are undetermined and
+    /// will not match.
+    pub language: Option<String>,
+    /// Range the original file's creation date must fall within
+    pub created: Option<DateRange>,
+    /// Range the original file's modified date must fall within
+    pub modified: Option<DateRange>,
+    /// User tags the original file must have, all of which must match
+    pub tags: Vec<String>,
+    /// Programming language code chunks must be written in, see language::resolve_code_language. Only code
+    /// chunks, whose channel is their programming language, will match.
+    pub code_language: Option<String>,
+    /// Keys and values of the original file's tags (see ChunkFile::original_file_tags) the chunks must have, all of
+    /// which must match
+    pub file_tags: Vec<(String, String)>,
+}
+
+/// A range of dates, either end of which may be open. The start is inclusive and the end is exclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct DateRange {
+    pub start: Option<DateTime<Utc>>,
+    pub end: Option<DateTime<Utc>>,
+}
+
+impl ChunkQueryFilters {
+    /// Converts the filters into store filters on ChunkFile attributes
+    pub fn store_filters(&self) -> Vec<Filter<'_>> {
+        let mut filters = vec![];
+        if let Some(language) = &self.language {
+            filters.push(Filter {
+                attribute: ChunkFile::LANGUAGE_ATTR,
+                filter: FilterValue::String(language),
+                relation: FilterRelation::Eq,
+            });
+        }
+        if let Some(code_language) = &self.code_language {
+            filters.push(Filter {
+                attribute: ChunkFile::CHUNK_CHANNEL_ATTR,
+                filter: FilterValue::String(code_language),
+                relation: FilterRelation::Eq,
+            });
+        }
+        for tag in &self.tags {
+            filters.push(Filter {
+                attribute: ChunkFile::USER_TAGS_ATTR,
+                filter: FilterValue::String(tag),
+                relation: FilterRelation::Contains,
+            });
+        }
+        for (key, value) in &self.file_tags {
+            filters.push(Filter {
+                attribute: ChunkFile::FILE_TAGS_ATTR,
+                filter: FilterValue::Entry { key, value },
+                relation: FilterRelation::Eq,
+            });
+        }
+        for (attribute, range) in [
+            (ChunkFile::FILE_CREATION_DATE_ATTR, &self.created),
+            (ChunkFile::FILE_MODIFIED_DATE_ATTR, &self.modified),
+        ] {
+            // Stores only support strict comparisons, so a file dated exactly at the start of the range is excluded
+            if let Some(start) = range.as_ref().and_then(|r| r.start.as_ref()) {
+                filters.push(Filter {
+                    attribute,
+                    filter: FilterValue::DateTime(start),
+                    relation: FilterRelation::Gt,
+                });
+            }
+            if let Some(end) = range.as_ref().and_then(|r| r.end.as_ref()) {
+                filters.push(Filter {
+                    attribute,
+                    filter: FilterValue::DateTime(end),
+                    relation: FilterRelation::Lt,
+                });
+            }
+        }
+        filters
+    }
 }
 
 pub struct ChunkQueryResult {
     chunkfile: ChunkFile,
     /// Normalized score value, ascending order. Higher = more relevant
-    /// Implementers of ChunkingIndexProvider should target values between 0-100, but <100 is not guaranteed.
+    /// Implementers of ChunkingIndexProvider should target values between 0-100, but <100 is not guaranteed, by
+    /// normalizing the scores of their stores with score::ScoreNormalizer, so scores of different providers compare.
     /// There will be no negative values.
     score: f32,
+    /// Score of the chunk in the store it was found in, before normalizing. None if the score was not normalized
+    /// from a store score, eg. the fixed score of notes linked to the notes found.
+    raw_score: Option<f32>,
 }
 
 impl ChunkQueryResult {
@@ -32,7 +171,11 @@ impl ChunkQueryResult {
             panic!("Attempted creating a chunkfile with score < 0!");
         }
 
-        ChunkQueryResult { chunkfile, score }
+        ChunkQueryResult { chunkfile, score, raw_score: None }
+    }
+
+    pub fn with_raw_score(chunkfile: ChunkFile, score: f32, raw_score: f32) -> Self {
+        ChunkQueryResult { raw_score: Some(raw_score), ..ChunkQueryResult::new(chunkfile, score) }
     }
 
     pub fn chunkfile(&self) -> &ChunkFile {
@@ -42,18 +185,74 @@ impl ChunkQueryResult {
     pub fn score(&self) -> f32 {
         self.score
     }
+
+    pub fn raw_score(&self) -> Option<f32> {
+        self.raw_score
+    }
 }
 
 pub use error::*;
 
 pub mod image;
+pub mod font;
+#[cfg(feature = "code")]
+pub mod code;
+pub mod markdown;
+#[cfg(feature = "models")]
+pub mod model;
+pub mod notebook;
 pub mod error;
+pub mod registry;
+pub mod score;
+pub mod sandbox;
+pub mod subprocess;
+pub mod subtitle;
 
 #[cfg(feature = "pdf")]
 pub mod pdf;
 
+/// Runs the future with the chunkfiles of the files it indexes or clears kept under the chunk directory given, see
+/// FileIndexerBuilder::chunk_directory. Without a chunk directory, chunkfiles are kept in the default chunk directory.
+pub(crate) async fn with_chunk_directory<F: Future>(chunk_directory: Option<Utf8PathBuf>, future: F) -> F::Output {
+    match chunk_directory {
+        Some(chunk_directory) => CHUNK_DIRECTORY.scope(chunk_directory, future).await,
+        None => future.await,
+    }
+}
+
+/// Moves the chunkfiles of a file to the chunkfile directory of the path it was moved or renamed to, replacing the
+/// chunkfiles of any file that was at the new path. Files without chunkfiles are left alone. Called once the chunks
+/// of the file have been moved (see rename_chunks), so a failed rename leaves chunks to clear rather than chunks
+/// whose chunkfiles have moved away from under them.
+pub(crate) async fn move_chunkfiles(from: &Utf8Path, to: &Utf8Path) -> Result<(), io::Error> {
+    let from_dir = generate_chunkfile_dir_name(from);
+    if !fs::try_exists(&from_dir).await? {
+        return Ok(());
+    }
+
+    let to_dir = generate_chunkfile_dir_name(to);
+    debug!("Moving directory with all chunkfiles from {from_dir} to {to_dir}");
+    match fs::remove_dir_all(&to_dir).await {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+        _ => (),
+    }
+    fs::rename(&from_dir, &to_dir).await
+}
+
+/// Whether the file at the path has a chunkfile directory, which every provider creates when it indexes a file and
+/// removes when it clears it. Tells files indexed at the path apart from ones not seen there yet without querying
+/// the stores.
+pub(crate) async fn has_chunkfiles(path: &Utf8Path) -> bool {
+    fs::try_exists(generate_chunkfile_dir_name(path)).await.unwrap_or(false)
+}
+
 // Private functions
 
+tokio::task_local! {
+    /// Chunk directory overriding the default one for the task, see with_chunk_directory
+    static CHUNK_DIRECTORY: Utf8PathBuf;
+}
+
 /// Common function for generating the chunkfile dir from the original file, and making sure it exists
 /// in the file system.
 /// 
@@ -69,6 +268,16 @@ async fn create_chunkfile_dir(original_file_path: &Utf8Path) -> Result<Utf8PathB
     Ok(chunk_out_dir)
 }
 
+/// Prefix of the paths of every file under a directory path, used to restore a whole directory at once
+fn directory_prefix(path: &Utf8Path) -> String {
+    let path = path.as_str();
+    if path.ends_with(std::path::MAIN_SEPARATOR) {
+        path.to_owned()
+    } else {
+        format!("{}{}", path, std::path::MAIN_SEPARATOR)
+    }
+}
+
 async fn clear_chunkfiles(original_file_path: &Utf8Path) -> Result<(), io::Error> {
     let chunk_out_dir = generate_chunkfile_dir_name(original_file_path);
 
@@ -76,12 +285,389 @@ async fn clear_chunkfiles(original_file_path: &Utf8Path) -> Result<(), io::Error
     fs::remove_dir_all(&chunk_out_dir).await
 }
 
+/// Removes a chunkfile written when the file was indexed before, so the new chunkfile is written as a new file rather
+/// than over the old one, which index snapshots may share by hard link (see files::snapshot)
+fn unlink_chunkfile(chunkfile: impl AsRef<std::path::Path>) -> Result<(), io::Error> {
+    match std::fs::remove_file(chunkfile) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// Maximum number of a file's chunks used as query vectors when finding related files
+const MAX_RELATED_QUERY_CHUNKS: usize = 8;
+
+/// Queries the store for the chunks nearest to the chunks of a file, excluding the file's own chunks. Files with
+/// many chunks are represented by an evenly spaced selection of them.
+async fn query_related_chunks<D>(
+    store: &dyn ChunkStore<D>,
+    path: &Utf8Path,
+    num_results: u32,
+    provider_name: &str,
+    into_parts: fn(D) -> (ChunkFile, Vec<f32>),
+) -> Result<Vec<ChunkQueryResult>, IndexProviderError>
+where
+    D: KeyedSequencedData<String> + VectorData + Filterable + FTSData + Send,
+{
+    let own_chunks = store.query_filter(&[Filter {
+        attribute: ChunkFile::ORIGINAL_FILE_ATTR,
+        filter: FilterValue::String(path.as_str()),
+        relation: FilterRelation::Eq,
+    }]).await.map_err(|e| IndexProviderError {
+        provider_name: provider_name.to_string(),
+        r#type: IndexProviderErrorType::Store {
+            operation: "query by filter",
+            source: e.into(),
+        }
+    })?;
+    let num_own_chunks = own_chunks.len() as u32;
+    let step = own_chunks.len().div_ceil(MAX_RELATED_QUERY_CHUNKS).max(1);
+
+    let mut results = vec![];
+    for (_, embedding) in own_chunks.into_iter().step_by(step).map(into_parts) {
+        // The file's own chunks are the nearest to themselves, so fetch enough to still fill the results
+        let chunks = store.query_full_select_n(Some(embedding), None, &[], ChunkFile::QUERY_RESULT_ATTRS,
+            num_results + num_own_chunks, 0).await
+            .map_err(|e| IndexProviderError {
+                provider_name: provider_name.to_string(),
+                r#type: IndexProviderErrorType::Store {
+                    operation: "query full",
+                    source: e,
+                }
+            })?;
+        results.extend(chunks.into_iter()
+            .map(|c| (into_parts(c.result).0, c.score))
+            .filter(|(chunkfile, _)| chunkfile.original_file != path)
+            .map(|(chunkfile, similarity)| ChunkQueryResult::new(chunkfile, similarity.max(0.0) * 100.0)));
+    }
+    Ok(results)
+}
+
+/// Moves the chunks of a file in the store to the path it was moved or renamed to, see ChunkingIndexProvider::rename.
+/// The chunks point to where their chunkfiles are moved to afterwards, see move_chunkfiles. Chunks keyed by file id
+/// (see ChunkKeyStrategy) replace the stored chunks, while chunks keyed by path are put under their new keys and the
+/// chunks under the old keys cleared.
+async fn rename_chunks<D>(
+    store: &dyn ChunkStore<D>,
+    from: &Utf8Path,
+    to: &Utf8Path,
+    provider_name: &str,
+    chunkfile_of: fn(&mut D) -> &mut ChunkFile,
+) -> Result<u64, IndexProviderError>
+where
+    D: KeyedSequencedData<String> + VectorData + Filterable + FTSData + Send,
+{
+    let mut chunks = store.query_filter(&[Filter {
+        attribute: ChunkFile::ORIGINAL_FILE_ATTR,
+        filter: FilterValue::String(from.as_str()),
+        relation: FilterRelation::Eq,
+    }]).await.map_err(|e| IndexProviderError {
+        provider_name: provider_name.to_string(),
+        r#type: IndexProviderErrorType::Store {
+            operation: "query by filter",
+            source: e.into(),
+        }
+    })?;
+    if chunks.is_empty() {
+        return Ok(0);
+    }
+
+    // The file may have replaced another file at the new path
+    store.clear_filter(&[Filter {
+        attribute: ChunkFile::ORIGINAL_FILE_ATTR,
+        filter: FilterValue::String(to.as_str()),
+        relation: FilterRelation::Eq,
+    }]).await.map_err(|e| IndexProviderError {
+        provider_name: provider_name.to_string(),
+        r#type: IndexProviderErrorType::Store {
+            operation: "clear by filter",
+            source: e.into(),
+        }
+    })?;
+
+    let from_dir = generate_chunkfile_dir_name(from);
+    let to_dir = generate_chunkfile_dir_name(to);
+    // Files that cannot be keyed by id at the new path, eg. ones hard linked since, are keyed by path instead
+    let original_file_id = volumes::file_id(to);
+    let num_chunks = chunks.len() as u64;
+    for chunk in &mut chunks {
+        let chunkfile = chunkfile_of(chunk);
+        // Clearing the chunks at the new path gave them newer sequence numbers, which would keep chunks keyed by path
+        // from replacing them
+        chunkfile.sequence_number = sequence::next();
+        chunkfile.original_file = to.to_owned();
+        chunkfile.original_file_id = original_file_id.clone();
+        if let Ok(moved) = chunkfile.chunkfile.strip_prefix(&from_dir).map(|relative| to_dir.join(relative)) {
+            chunkfile.chunkfile = moved;
+        }
+    }
+    store.put(chunks).await.map_err(|e| IndexProviderError {
+        provider_name: provider_name.to_string(),
+        r#type: IndexProviderErrorType::Store {
+            operation: "put",
+            source: e.into(),
+        }
+    })?;
+
+    // Only chunks whose key changed are left under the old path
+    store.clear_filter(&[Filter {
+        attribute: ChunkFile::ORIGINAL_FILE_ATTR,
+        filter: FilterValue::String(from.as_str()),
+        relation: FilterRelation::Eq,
+    }]).await.map_err(|e| IndexProviderError {
+        provider_name: provider_name.to_string(),
+        r#type: IndexProviderErrorType::Store {
+            operation: "clear by filter",
+            source: e.into(),
+        }
+    })?;
+    Ok(num_chunks)
+}
+
+/// The file with the id as its chunks are stored in the store, see ChunkingIndexProvider::indexed_file
+async fn indexed_file_of<D>(
+    store: &dyn ChunkStore<D>,
+    file_id: &str,
+    provider_name: &str,
+    into_chunkfile: fn(D) -> ChunkFile,
+) -> Result<Option<IndexedFile>, IndexProviderError>
+where
+    D: KeyedSequencedData<String> + VectorData + Filterable + FTSData + Send,
+{
+    let chunks = store.query_filter_n(&[Filter {
+        attribute: ChunkFile::ORIGINAL_FILE_ID_ATTR,
+        filter: FilterValue::String(file_id),
+        relation: FilterRelation::Eq,
+    }], 1, 0).await.map_err(|e| IndexProviderError {
+        provider_name: provider_name.to_string(),
+        r#type: IndexProviderErrorType::Store {
+            operation: "query by filter",
+            source: e.into(),
+        }
+    })?;
+    Ok(chunks.into_iter().next().map(|chunk| into_chunkfile(chunk).into()))
+}
+
+/// Maximum number of chunks read when finding the paths of files by their content fingerprint
+const MAX_CONTENT_MATCH_CHUNKS: u32 = 64;
+
+/// Paths the chunks of files with the content fingerprint are stored under in the store, see
+/// ChunkingIndexProvider::indexed_paths_by_content
+async fn indexed_paths_by_content_of<D>(
+    store: &dyn ChunkStore<D>,
+    fingerprint: &str,
+    provider_name: &str,
+    into_chunkfile: fn(D) -> ChunkFile,
+) -> Result<Vec<Utf8PathBuf>, IndexProviderError>
+where
+    D: KeyedSequencedData<String> + VectorData + Filterable + FTSData + Send,
+{
+    let chunks = store.query_filter_n(&[Filter {
+        attribute: ChunkFile::FILE_FINGERPRINT_ATTR,
+        filter: FilterValue::String(fingerprint),
+        relation: FilterRelation::Eq,
+    }], MAX_CONTENT_MATCH_CHUNKS, 0).await.map_err(|e| IndexProviderError {
+        provider_name: provider_name.to_string(),
+        r#type: IndexProviderErrorType::Store {
+            operation: "query by filter",
+            source: e.into(),
+        }
+    })?;
+    let mut paths: Vec<Utf8PathBuf> = vec![];
+    for chunk in chunks {
+        let original_file = into_chunkfile(chunk).original_file;
+        if !paths.contains(&original_file) {
+            paths.push(original_file);
+        }
+    }
+    Ok(paths)
+}
+
+/// Splits the name, string tags and user tags of a chunk's original file into lowercased words, skipping numbers
+/// and words too short to be worth spell checking against.
+pub(crate) fn chunkfile_vocabulary(chunkfile: &ChunkFile) -> impl Iterator<Item = String> + '_ {
+    let name_words = chunkfile.original_file.file_stem().into_iter();
+    let tag_words = chunkfile.original_file_tags.values().filter_map(|v| v.as_str());
+    let user_tag_words = chunkfile.user_tags.iter().map(String::as_str);
+
+    name_words.chain(tag_words).chain(user_tag_words)
+        .flat_map(|s| s.split(|c: char| !c.is_alphanumeric()))
+        .filter(|w| w.chars().count() >= 3 && !w.chars().all(|c| c.is_numeric()))
+        .map(str::to_lowercase)
+}
+
 fn generate_chunkfile_dir_name(original_file_path: &Utf8Path) -> Utf8PathBuf {
-    let chunk_data_dir = get_default_chunk_directory();
+    let chunk_data_dir = CHUNK_DIRECTORY.try_with(Clone::clone).unwrap_or_else(|_| get_default_chunk_directory());
     let mut hasher = DefaultHasher::new();
     original_file_path.as_str().hash(&mut hasher);
     let filename_hash = hasher.finish().to_string();
     let chunk_out_dir = chunk_data_dir.join(filename_hash);
 
     chunk_out_dir
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, sync::Mutex};
+
+    use serde_json::Map;
+
+    use crate::{index::{ChunkKeyStrategy, ChunkType, embedding::siglip2::Siglip2EmbeddedChunkFile, language::UNDETERMINED_LANGUAGE}, store::{FilterStoreError, FullQueryResult, KeyedSequencedStoreError}};
+
+    use super::*;
+
+    /// Keeps chunks in memory the way LanceDBStore keeps them in a table: rows are only replaced by rows with an
+    /// equal or greater sequence number, and clearing a row gives it a new sequence number.
+    struct MemoryChunkStore {
+        strategy: ChunkKeyStrategy,
+        /// Rows by key, along with their sequence number and whether they are cleared
+        rows: Mutex<HashMap<String, (Siglip2EmbeddedChunkFile, u64, bool)>>,
+    }
+
+    impl MemoryChunkStore {
+        fn new(strategy: ChunkKeyStrategy) -> MemoryChunkStore {
+            MemoryChunkStore { strategy, rows: Mutex::new(HashMap::new()) }
+        }
+    }
+
+    #[async_trait]
+    impl ChunkStore<Siglip2EmbeddedChunkFile> for MemoryChunkStore {
+        async fn put(&self, data: Vec<Siglip2EmbeddedChunkFile>) -> Result<(), KeyedSequencedStoreError> {
+            let mut rows = self.rows.lock().unwrap();
+            for chunk in data {
+                let key = chunk.chunkfile.key_with(self.strategy);
+                let sequence_number = chunk.chunkfile.sequence_number;
+                if rows.get(&key).is_none_or(|(_, stored, _)| *stored <= sequence_number) {
+                    rows.insert(key, (chunk, sequence_number, false));
+                }
+            }
+            Ok(())
+        }
+
+        async fn clear(&self, _key: String, _optional_sequence_number: Option<u64>) -> Result<(), KeyedSequencedStoreError> {
+            unimplemented!()
+        }
+
+        async fn get(&self, _key: String) -> Result<Option<Siglip2EmbeddedChunkFile>, KeyedSequencedStoreError> {
+            unimplemented!()
+        }
+
+        async fn query_full_n<'a>(&self, _vector: Option<Vec<f32>>, _fts_terms: Option<&str>, _filters: &[Filter<'a>],
+            _num_results: u32, _offset: u32) -> Result<Vec<FullQueryResult<Siglip2EmbeddedChunkFile>>, anyhow::Error>
+        {
+            unimplemented!()
+        }
+
+        async fn query_full_select_n<'a>(&self, _vector: Option<Vec<f32>>, _fts_terms: Option<&str>,
+            _filters: &[Filter<'a>], _attributes: &[&str], _num_results: u32, _offset: u32)
+            -> Result<Vec<FullQueryResult<Siglip2EmbeddedChunkFile>>, anyhow::Error>
+        {
+            unimplemented!()
+        }
+
+        async fn query_filter<'a>(&self, filters: &[Filter<'a>]) -> Result<Vec<Siglip2EmbeddedChunkFile>, FilterStoreError> {
+            self.query_filter_n(filters, u32::MAX, 0).await
+        }
+
+        async fn query_filter_n<'a>(&self, filters: &[Filter<'a>], num_results: u32, offset: u32)
+            -> Result<Vec<Siglip2EmbeddedChunkFile>, FilterStoreError>
+        {
+            let rows = self.rows.lock().unwrap();
+            Ok(rows.values()
+                .filter(|(chunk, _, cleared)| !cleared && matches(&chunk.chunkfile, filters))
+                .map(|(chunk, _, _)| chunk.clone())
+                .skip(offset as usize)
+                .take(num_results as usize)
+                .collect())
+        }
+
+        async fn clear_filter<'a>(&self, filters: &[Filter<'a>]) -> Result<(), FilterStoreError> {
+            let mut rows = self.rows.lock().unwrap();
+            for (chunk, sequence_number, cleared) in rows.values_mut() {
+                if !*cleared && matches(&chunk.chunkfile, filters) {
+                    *sequence_number = sequence::next();
+                    *cleared = true;
+                }
+            }
+            Ok(())
+        }
+
+        async fn restore_filter<'a>(&self, _filters: &[Filter<'a>]) -> Result<u64, FilterStoreError> {
+            unimplemented!()
+        }
+    }
+
+    fn matches(chunkfile: &ChunkFile, filters: &[Filter]) -> bool {
+        filters.iter().all(|filter| {
+            let value = match filter.attribute {
+                ChunkFile::ORIGINAL_FILE_ATTR => Some(chunkfile.original_file.as_str()),
+                ChunkFile::ORIGINAL_FILE_ID_ATTR => chunkfile.original_file_id.as_deref(),
+                ChunkFile::FILE_FINGERPRINT_ATTR => chunkfile.original_file_fingerprint.as_deref(),
+                attribute => unimplemented!("Filtering on {}", attribute),
+            };
+            match (&filter.filter, &filter.relation) {
+                (FilterValue::String(expected), FilterRelation::Eq) => value == Some(*expected),
+                _ => unimplemented!("Filtering other than by string equality"),
+            }
+        })
+    }
+
+    fn chunk(original_file: &Utf8Path, original_file_id: Option<String>, chunk_sequence_id: f32) -> Siglip2EmbeddedChunkFile {
+        let now = Utc::now();
+        Siglip2EmbeddedChunkFile {
+            chunkfile: ChunkFile {
+                original_file: original_file.to_owned(),
+                chunk_channel: "image".to_owned(),
+                chunk_sequence_id,
+                original_file_id,
+                chunkfile: Utf8PathBuf::from(format!("{}-{}.png", original_file.file_stem().unwrap(), chunk_sequence_id)),
+                chunk_type: ChunkType::Image,
+                chunk_length: 1.0,
+                original_file_creation_date: now,
+                original_file_modified_date: now,
+                original_file_size: 1,
+                original_file_fingerprint: None,
+                original_file_tags: Map::new(),
+                language: UNDETERMINED_LANGUAGE.to_owned(),
+                user_tags: vec![],
+                sequence_number: sequence::next(),
+            },
+            embedding: vec![0.0; 4],
+        }
+    }
+
+    async fn chunkfiles_of(store: &MemoryChunkStore, path: &Utf8Path) -> Vec<Utf8PathBuf> {
+        let mut chunkfiles: Vec<Utf8PathBuf> = store.query_filter(&[Filter {
+            attribute: ChunkFile::ORIGINAL_FILE_ATTR,
+            filter: FilterValue::String(path.as_str()),
+            relation: FilterRelation::Eq,
+        }]).await.unwrap().into_iter().map(|chunk| chunk.chunkfile.chunkfile).collect();
+        chunkfiles.sort();
+        chunkfiles
+    }
+
+    #[tokio::test]
+    async fn rename_onto_indexed_path_keeps_moved_chunks() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let dir = Utf8Path::from_path(temp_dir.path()).unwrap();
+        let (from, to) = (dir.join("a.png"), dir.join("b.png"));
+        // a.png was moved onto b.png, replacing the file indexed there
+        std::fs::write(&to, b"a").unwrap();
+        let moved_file_id = volumes::file_id(&to);
+
+        for strategy in [ChunkKeyStrategy::FileId, ChunkKeyStrategy::Path] {
+            let store = MemoryChunkStore::new(strategy);
+            store.put(vec![chunk(&from, moved_file_id.clone(), 0.0), chunk(&from, moved_file_id.clone(), 1.0)]).await
+                .unwrap();
+            store.put(vec![chunk(&to, Some("replaced".to_owned()), 0.0)]).await.unwrap();
+
+            let renamed = with_chunk_directory(Some(dir.join("chunks")), rename_chunks(&store, &from, &to, "test",
+                |c: &mut Siglip2EmbeddedChunkFile| &mut c.chunkfile)).await.unwrap();
+
+            assert_eq!(renamed, 2, "{:?}", strategy);
+            assert_eq!(chunkfiles_of(&store, &to).await,
+                vec![Utf8PathBuf::from("a-0.png"), Utf8PathBuf::from("a-1.png")], "{:?}", strategy);
+            assert!(chunkfiles_of(&store, &from).await.is_empty(), "{:?}", strategy);
+        }
+    }
+}
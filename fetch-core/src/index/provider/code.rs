@@ -0,0 +1,641 @@
+use std::{collections::HashSet, fs::Metadata, sync::Arc};
+
+use async_trait::async_trait;
+use camino::{Utf8Path, Utf8PathBuf};
+use chrono::{DateTime, Utc};
+use serde_json::Map;
+use tokio::task;
+use tracing::{debug, info, instrument};
+use tree_sitter::{Language, Node, Parser};
+
+use crate::{files::{dupes, os_path::to_os_path, volumes}, index::{ChunkFile, ChunkType, embedding::embeddinggemma::{self, EmbeddingGemmaEmbeddedChunkFile}, language::{self, UNDETERMINED_LANGUAGE}, provider::{ChunkQueryFilters, ChunkQueryResult, ChunkingIndexProvider, IndexProviderError, IndexProviderErrorType, IndexedFile, chunkfile_vocabulary, clear_chunkfiles, create_chunkfile_dir, directory_prefix, indexed_file_of, indexed_paths_by_content_of, query_related_chunks, rename_chunks, unlink_chunkfile, registry::{CostClass, ProviderCapabilities}, score::ScoreNormalizer}}, sequence, store::{ChunkStore, Filter, FilterRelation, FilterValue, KeyedSequencedData}};
+
+/// Index provider for source code. Files are parsed with tree-sitter and chunked at the boundaries of their
+/// functions, classes and other definitions, so queries describing what some code does return the region of the
+/// file that does it. The names of the symbols in each chunk and the programming language are stored in the chunk
+/// tags, so they are searched with FTS, and the programming language is the channel of each chunk, so queries can
+/// be filtered on it (see ChunkQueryFilters::code_language).
+pub struct CodeIndexProvider {
+    text_store: Arc<dyn ChunkStore<EmbeddingGemmaEmbeddedChunkFile>>,
+    hybrid_search: bool,
+}
+
+impl CodeIndexProvider {
+    pub fn using(text_store: Arc<dyn ChunkStore<EmbeddingGemmaEmbeddedChunkFile>>) -> Self {
+        CodeIndexProvider { text_store, hybrid_search: false }
+    }
+
+    /// Matches the words of queries with full text search along with their meaning, and adds up the scores of both,
+    /// see store::FusionWeights. Queries only match by meaning by default.
+    pub fn with_hybrid_search(mut self, enabled: bool) -> Self {
+        self.hybrid_search = enabled;
+        self
+    }
+}
+
+#[async_trait]
+impl ChunkingIndexProvider for CodeIndexProvider {
+    fn provider_name(&self) -> &'static str {
+        PROVIDER_NAME
+    }
+
+    fn provides_indexing_for_extension(&self, ext: &str) -> bool {
+        language_for_extension(ext).is_some()
+    }
+
+    fn capabilities(&self) -> ProviderCapabilities {
+        // Source code is plain text, so it cannot be sniffed and is routed by extension
+        ProviderCapabilities { mime_types: vec![], cost: CostClass::Moderate }
+    }
+
+    async fn index(&self, path: &Utf8Path, opt_modified: Option<DateTime<Utc>>) -> Result<(), IndexProviderError> {
+        debug!("Code Index Provider: Indexing file at path: {}", path);
+        let spec = language_for_extension(path.extension().unwrap_or("")).ok_or_else(|| IndexProviderError {
+            provider_name: PROVIDER_NAME.to_string(),
+            r#type: IndexProviderErrorType::InvalidExtension { path: path.to_owned() },
+        })?;
+        let contents = tokio::fs::read_to_string(to_os_path(path)).await
+            .map_err(|e| IndexProviderError {
+                provider_name: PROVIDER_NAME.to_string(),
+                r#type: IndexProviderErrorType::IO {
+                    path: path.to_string(),
+                    source: e.into(),
+                },
+            })?;
+        let metadata = tokio::fs::metadata(to_os_path(path)).await
+            .map_err(|e| IndexProviderError {
+                provider_name: PROVIDER_NAME.to_string(),
+                r#type: IndexProviderErrorType::IO {
+                    path: path.to_string(),
+                    source: e.into(),
+                },
+            })?;
+
+        // If the store has indexed chunks for this file, then check the stored original_file_modified_date to
+        // make sure it comes before the current file's modified date. If so, then make sure to clear the previously
+        // stored chunks from the store before proceeding.
+        let discover_filter = &[Filter {
+            attribute: ChunkFile::ORIGINAL_FILE_ATTR,
+            filter: FilterValue::String(path.as_str()),
+            relation: FilterRelation::Eq,
+        }];
+        let discovered_chunk = self.text_store.query_filter_n(discover_filter, 1, 0).await
+            .map_err(|e| IndexProviderError {
+                provider_name: PROVIDER_NAME.to_string(),
+                r#type: IndexProviderErrorType::Store {
+                    operation: "query filter",
+                    source: e.into(),
+                }
+            })?
+            .into_iter().map(|ec| ec.chunkfile).next();
+
+        if let Some(discovered_chunk) = discovered_chunk {
+            sequence::observe(discovered_chunk.sequence_number);
+            let last_modified: DateTime<Utc> = opt_modified.unwrap_or(DateTime::from(metadata.modified()
+                .expect("File modified datetime not available on this platform")));
+            let stored_modified = discovered_chunk.original_file_modified_date;
+            if last_modified.timestamp_millis() <= stored_modified.timestamp_millis() {
+                info!("Attempted indexing on file: {} but the stored modified_date ({}) was equal to or later than the \
+                    file's modified_date ({}). Ignoring.", path, stored_modified, last_modified);
+                return Ok(());
+            }
+
+            self.clear(path, Some(last_modified)).await?;
+        }
+
+        let sequence_number = sequence::next();
+
+        let chunk_out_dir = create_chunkfile_dir(path).await
+            .map_err(|e| IndexProviderError {
+                provider_name: PROVIDER_NAME.to_string(),
+                r#type: IndexProviderErrorType::IO {
+                    path: path.to_string(),
+                    source: e.into(),
+                }
+            })?;
+
+        debug!("Code Index Provider: Chunking file at path: {} to out_dir: {}", path, chunk_out_dir);
+        let chunkfiles = chunk_code(path, spec, contents, metadata, &chunk_out_dir, sequence_number).await
+            .map_err(|e| IndexProviderError {
+                provider_name: PROVIDER_NAME.to_owned(),
+                r#type: IndexProviderErrorType::Chunking {
+                    path: path.to_string(),
+                    source: e,
+                }
+            })?;
+
+        debug!("Code Index Provider: Embedding chunks at dir: {}", chunk_out_dir);
+        let mut embedded_chunkfiles = vec![];
+        for chunkfile in chunkfiles {
+            debug!("Code Index Provider: Embedding chunk with id: {}", chunkfile.get_key());
+            embedded_chunkfiles.push(embeddinggemma::embed_chunk(chunkfile).await
+                .map_err(|e| IndexProviderError {
+                    provider_name: PROVIDER_NAME.to_string(),
+                    r#type: IndexProviderErrorType::Embedding { source: e },
+                })?);
+        }
+
+        debug!("Code Index Provider: Storing chunks and embeddings for path: {}", path);
+        self.text_store.put(embedded_chunkfiles).await
+            .map_err(|e| IndexProviderError {
+                provider_name: PROVIDER_NAME.to_string(),
+                r#type: IndexProviderErrorType::Store {
+                    operation: "put",
+                    source: e.into(),
+                }
+            })?;
+
+        Ok(())
+    }
+
+    async fn clear(&self, path: &Utf8Path, opt_modified: Option<DateTime<Utc>>) -> Result<(), IndexProviderError> {
+        debug!("Code Index Provider: Clearing index of path: {}", path);
+        clear_chunkfiles(path).await.map_err(|e| IndexProviderError {
+            provider_name: PROVIDER_NAME.to_string(),
+            r#type: IndexProviderErrorType::IO { path: path.to_string(), source: e.into() }
+        })?;
+
+        let mut filters = vec![Filter {
+            attribute: ChunkFile::ORIGINAL_FILE_ATTR,
+            filter: FilterValue::String(path.as_str()),
+            relation: FilterRelation::Eq,
+        }];
+        if let Some(modified_dt) = &opt_modified {
+            filters.push(Filter {
+                attribute: ChunkFile::FILE_MODIFIED_DATE_ATTR,
+                filter: FilterValue::DateTime(modified_dt),
+                relation: FilterRelation::Eq,
+            });
+        }
+        self.text_store.clear_filter(&filters).await.map_err(|e| IndexProviderError {
+            provider_name: PROVIDER_NAME.to_string(),
+            r#type: IndexProviderErrorType::Store {
+                operation: "clear filter",
+                source: e.into(),
+            }
+        })?;
+
+        Ok(())
+    }
+
+    async fn restore(&self, path: &Utf8Path) -> Result<u64, IndexProviderError> {
+        debug!("Code Index Provider: Restoring cleared chunks under path: {}", path);
+        let prefix = directory_prefix(path);
+        let mut restored = 0;
+        for filters in [
+            [Filter { attribute: ChunkFile::ORIGINAL_FILE_ATTR, filter: FilterValue::String(path.as_str()), relation: FilterRelation::Eq }],
+            [Filter { attribute: ChunkFile::ORIGINAL_FILE_ATTR, filter: FilterValue::String(&prefix), relation: FilterRelation::StartsWith }],
+        ] {
+            restored += self.text_store.restore_filter(&filters).await.map_err(|e| IndexProviderError {
+                provider_name: PROVIDER_NAME.to_string(),
+                r#type: IndexProviderErrorType::Store {
+                    operation: "restore filter",
+                    source: e.into(),
+                }
+            })?;
+        }
+        Ok(restored)
+    }
+
+    async fn tag(&self, path: &Utf8Path, tags: &[String]) -> Result<(), IndexProviderError> {
+        debug!("Code Index Provider: Tagging chunks of path: {} with tags: {:?}", path, tags);
+        let filters = &[Filter {
+            attribute: ChunkFile::ORIGINAL_FILE_ATTR,
+            filter: FilterValue::String(path.as_str()),
+            relation: FilterRelation::Eq,
+        }];
+        let mut chunks = self.text_store.query_filter(filters).await.map_err(|e| IndexProviderError {
+            provider_name: PROVIDER_NAME.to_string(),
+            r#type: IndexProviderErrorType::Store {
+                operation: "query filter",
+                source: e.into(),
+            }
+        })?;
+        if chunks.is_empty() {
+            return Ok(());
+        }
+
+        for chunk in &mut chunks {
+            chunk.chunkfile.user_tags = tags.to_vec();
+        }
+        self.text_store.put(chunks).await.map_err(|e| IndexProviderError {
+            provider_name: PROVIDER_NAME.to_string(),
+            r#type: IndexProviderErrorType::Store {
+                operation: "put",
+                source: e.into(),
+            }
+        })?;
+
+        Ok(())
+    }
+
+    async fn rename(&self, from: &Utf8Path, to: &Utf8Path) -> Result<u64, IndexProviderError> {
+        debug!("Code Index Provider: Renaming chunks of path: {} to: {}", from, to);
+        rename_chunks(self.text_store.as_ref(), from, to, PROVIDER_NAME, |c: &mut EmbeddingGemmaEmbeddedChunkFile| &mut c.chunkfile).await
+    }
+
+    async fn indexed_file(&self, file_id: &str) -> Result<Option<IndexedFile>, IndexProviderError> {
+        indexed_file_of(self.text_store.as_ref(), file_id, PROVIDER_NAME, |c: EmbeddingGemmaEmbeddedChunkFile| c.chunkfile).await
+    }
+
+    async fn indexed_paths_by_content(&self, fingerprint: &str) -> Result<Vec<Utf8PathBuf>, IndexProviderError> {
+        indexed_paths_by_content_of(self.text_store.as_ref(), fingerprint, PROVIDER_NAME, |c: EmbeddingGemmaEmbeddedChunkFile| c.chunkfile).await
+    }
+
+    async fn query_n(&self, str: &str, filters: &ChunkQueryFilters, num_results: u32, offset: u32)
+        -> Result<Vec<ChunkQueryResult>, IndexProviderError>
+    {
+        debug!("Code Index Provider: Querying index of with params: {}, filters: {:?}, \
+            num_results: {}, offset: {}", str, filters, num_results, offset);
+        let text_vec = embeddinggemma::embed_query(str).await.map_err(|e| IndexProviderError {
+            provider_name: PROVIDER_NAME.to_string(),
+            r#type: IndexProviderErrorType::Embedding { source: e },
+        })?;
+
+        let chunks = self.text_store.query_full_select_n(
+            Some(text_vec),
+            self.hybrid_search.then_some(str),
+            &filters.store_filters(),
+            ChunkFile::QUERY_RESULT_ATTRS,
+            num_results,
+            offset
+        ).await.map_err(|e| IndexProviderError {
+            provider_name: PROVIDER_NAME.to_string(),
+            r#type: IndexProviderErrorType::Store {
+                operation: "query full",
+                source: e,
+            }
+        })?;
+
+        let results = ScoreNormalizer::for_provider(PROVIDER_NAME).normalize_all(chunks.into_iter().map(|c| (c.score, c.result.chunkfile)));
+        Ok(results)
+    }
+
+    async fn query_related_n(&self, path: &Utf8Path, num_results: u32)
+        -> Result<Vec<ChunkQueryResult>, IndexProviderError>
+    {
+        debug!("Code Index Provider: Querying files related to path: {}, num_results: {}", path, num_results);
+        query_related_chunks(self.text_store.as_ref(), path, num_results, PROVIDER_NAME,
+            |c: EmbeddingGemmaEmbeddedChunkFile| (c.chunkfile, c.embedding)).await
+    }
+
+    async fn vocabulary(&self) -> Result<Vec<String>, IndexProviderError> {
+        debug!("Code Index Provider: Collecting vocabulary of indexed files");
+        let chunks = self.text_store.query_filter(&[]).await.map_err(|e| IndexProviderError {
+            provider_name: PROVIDER_NAME.to_string(),
+            r#type: IndexProviderErrorType::Store {
+                operation: "query by filter",
+                source: e.into(),
+            }
+        })?;
+
+        // Symbol names are left out, as they are rarely words a query would be corrected to
+        let mut seen_files = HashSet::new();
+        Ok(chunks.iter()
+            .map(|c| &c.chunkfile)
+            .filter(|c| seen_files.insert(c.original_file.clone()))
+            .flat_map(chunkfile_vocabulary)
+            .collect())
+    }
+}
+
+// private constants and functions
+
+const PROVIDER_NAME: &str = "CodeIndexProvider";
+
+const CODE_CHUNK_MAX_TOKENS: u32 = 1000;
+
+
+/// How a programming language is parsed and chunked
+struct LanguageSpec {
+    /// Name of the language, see language::resolve_code_language
+    name: &'static str,
+    extensions: &'static [&'static str],
+    grammar: fn() -> Language,
+    /// Node kinds of the definitions that code is chunked at
+    definitions: &'static [&'static str],
+    /// Definitions whose members are chunked separately when the whole definition is too large for one chunk, eg.
+    /// classes
+    containers: &'static [&'static str],
+    /// Node kinds wrapping a definition, eg. decorators or exports, that belong in the chunk of the definition
+    wrappers: &'static [&'static str],
+    /// Separates the name of a member from the name of its container in symbol names, eg. "Client::get"
+    separator: &'static str,
+}
+
+const LANGUAGES: &[LanguageSpec] = &[
+    LanguageSpec {
+        name: "rust",
+        extensions: &["rs"],
+        grammar: || tree_sitter_rust::LANGUAGE.into(),
+        definitions: &["function_item", "struct_item", "enum_item", "union_item", "trait_item", "impl_item",
+            "mod_item", "macro_definition", "type_item"],
+        containers: &["impl_item", "trait_item", "mod_item"],
+        wrappers: &[],
+        separator: "::",
+    },
+    LanguageSpec {
+        name: "python",
+        extensions: &["py", "pyi"],
+        grammar: || tree_sitter_python::LANGUAGE.into(),
+        definitions: &["function_definition", "class_definition"],
+        containers: &["class_definition"],
+        wrappers: &["decorated_definition"],
+        separator: ".",
+    },
+    LanguageSpec {
+        name: "javascript",
+        extensions: &["js", "mjs", "cjs", "jsx"],
+        grammar: || tree_sitter_javascript::LANGUAGE.into(),
+        definitions: &["function_declaration", "generator_function_declaration", "class_declaration",
+            "method_definition"],
+        containers: &["class_declaration"],
+        wrappers: &["export_statement"],
+        separator: ".",
+    },
+    LanguageSpec {
+        name: "typescript",
+        extensions: &["ts", "mts", "cts"],
+        grammar: || tree_sitter_typescript::LANGUAGE_TYPESCRIPT.into(),
+        definitions: &["function_declaration", "generator_function_declaration", "class_declaration",
+            "abstract_class_declaration", "method_definition", "interface_declaration", "type_alias_declaration",
+            "enum_declaration"],
+        containers: &["class_declaration", "abstract_class_declaration"],
+        wrappers: &["export_statement"],
+        separator: ".",
+    },
+    LanguageSpec {
+        name: "typescript",
+        extensions: &["tsx"],
+        grammar: || tree_sitter_typescript::LANGUAGE_TSX.into(),
+        definitions: &["function_declaration", "generator_function_declaration", "class_declaration",
+            "abstract_class_declaration", "method_definition", "interface_declaration", "type_alias_declaration",
+            "enum_declaration"],
+        containers: &["class_declaration", "abstract_class_declaration"],
+        wrappers: &["export_statement"],
+        separator: ".",
+    },
+    LanguageSpec {
+        name: "go",
+        extensions: &["go"],
+        grammar: || tree_sitter_go::LANGUAGE.into(),
+        definitions: &["function_declaration", "method_declaration", "type_declaration"],
+        containers: &[],
+        wrappers: &[],
+        separator: ".",
+    },
+    LanguageSpec {
+        name: "java",
+        extensions: &["java"],
+        grammar: || tree_sitter_java::LANGUAGE.into(),
+        definitions: &["class_declaration", "interface_declaration", "enum_declaration", "record_declaration",
+            "method_declaration", "constructor_declaration"],
+        containers: &["class_declaration", "interface_declaration", "enum_declaration", "record_declaration"],
+        wrappers: &[],
+        separator: ".",
+    },
+];
+
+fn language_for_extension(ext: &str) -> Option<&'static LanguageSpec> {
+    let ext = ext.to_lowercase();
+    LANGUAGES.iter().find(|spec| spec.extensions.contains(&ext.as_str()))
+}
+
+/// A region of a source file that becomes a chunk
+struct CodeRegion {
+    start_byte: usize,
+    end_byte: usize,
+    start_line: usize,
+    end_line: usize,
+    /// Names of the symbols defined in the region, none for code between definitions
+    symbols: Vec<String>,
+    /// Node kind of the definition in the region
+    kind: Option<&'static str>,
+}
+
+#[instrument(name = "chunk", level = "debug", skip_all, fields(path = %path))]
+async fn chunk_code(
+    path: &Utf8Path,
+    spec: &'static LanguageSpec,
+    contents: String,
+    metadata: Metadata,
+    out_dir: &Utf8Path,
+    sequence_number: u64,
+) -> Result<Vec<ChunkFile>, anyhow::Error> {
+    let file_creation: DateTime<Utc> = DateTime::from(metadata.created()
+        .expect("File creation datetime not available on this platform"));
+    let file_modified: DateTime<Utc> = DateTime::from(metadata.modified()
+        .expect("File modified datetime not available on this platform"));
+    let file_length = metadata.len();
+    let original_file_id = volumes::file_id(path);
+    let original_file_fingerprint = dupes::content_fingerprint(path);
+
+    let path = path.to_owned();
+    let out_dir = out_dir.to_owned();
+    task::spawn_blocking(move || {
+        let mut parser = Parser::new();
+        parser.set_language(&(spec.grammar)())?;
+        let tree = parser.parse(&contents, None)
+            .ok_or_else(|| anyhow::anyhow!("Parser did not return a syntax tree"))?;
+
+        let mut regions = vec![];
+        collect_definitions(spec, tree.root_node(), contents.as_bytes(), None, &mut regions);
+        let regions = fill_gaps(&contents, regions);
+
+        let mut chunkfiles = vec![];
+        // Chunks are numbered in the order they appear in the file, their lines are kept in the tags
+        for (i, region) in regions.into_iter().flat_map(|r| split_region(&contents, r)).enumerate() {
+            let text = &contents[region.start_byte..region.end_byte];
+            let chunk_sequence = i as f32;
+            let chunkfile = out_dir.join(format!("{}-{}.txt", spec.name, chunk_sequence));
+            unlink_chunkfile(&chunkfile)?;
+            std::fs::write(&chunkfile, text)?;
+
+            let mut tags_map = Map::new();
+            tags_map.insert("full_text".to_string(), text.into());
+            tags_map.insert("code_language".to_string(), spec.name.into());
+            tags_map.insert("symbols".to_string(), region.symbols.clone().into());
+            if let Some(kind) = region.kind {
+                tags_map.insert("symbol_kind".to_string(), kind.into());
+            }
+            tags_map.insert("start_line".to_string(), (region.start_line + 1).into());
+            tags_map.insert("end_line".to_string(), (region.end_line + 1).into());
+
+            chunkfiles.push(ChunkFile {
+                original_file: path.clone(),
+                chunk_channel: spec.name.to_owned(),
+                chunk_sequence_id: chunk_sequence,
+                original_file_id: original_file_id.clone(),
+                chunkfile,
+                chunk_type: ChunkType::Text,
+                // Chunks of code span lines rather than pages
+                chunk_length: (region.end_line - region.start_line + 1) as f32,
+                original_file_creation_date: file_creation,
+                original_file_modified_date: file_modified,
+                original_file_size: file_length,
+                original_file_fingerprint: original_file_fingerprint.clone(),
+                original_file_tags: tags_map,
+                language: UNDETERMINED_LANGUAGE.to_owned(),
+                user_tags: vec![],
+                sequence_number,
+            });
+        }
+
+        Ok::<Vec<ChunkFile>, anyhow::Error>(chunkfiles)
+    }).await? // this is Result<Result<vec, closure_error>, tokio::task_error>
+}
+
+/// Collects the definitions among the children of the node as regions, in order. Containers too large for one
+/// chunk are split into their members, which are named after the container.
+fn collect_definitions(
+    spec: &LanguageSpec,
+    node: Node,
+    source: &[u8],
+    container: Option<&str>,
+    regions: &mut Vec<CodeRegion>,
+) {
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        let definition = if spec.wrappers.contains(&child.kind()) {
+            let mut wrapper_cursor = child.walk();
+            let inner = child.named_children(&mut wrapper_cursor).find(|c| spec.definitions.contains(&c.kind()));
+            match inner {
+                Some(inner) => inner,
+                None => continue,
+            }
+        } else if spec.definitions.contains(&child.kind()) {
+            child
+        } else {
+            continue;
+        };
+
+        let name = symbol_name(definition, source);
+        let qualified_name = match container {
+            Some(container) => format!("{}{}{}", container, spec.separator, name),
+            None => name,
+        };
+
+        // Comments and attributes right above the definition document it, so they go in its chunk
+        let mut start = child;
+        while let Some(previous) = start.prev_named_sibling() {
+            let is_adjacent = previous.end_position().row + 1 >= start.start_position().row;
+            if is_adjacent && (previous.kind().contains("comment") || previous.kind().contains("attribute")) {
+                start = previous;
+            } else {
+                break;
+            }
+        }
+
+        let text = String::from_utf8_lossy(&source[start.start_byte()..child.end_byte()]);
+        if spec.containers.contains(&definition.kind()) && language::estimate_tokens(&text) > CODE_CHUNK_MAX_TOKENS {
+            if let Some(body) = definition.child_by_field_name("body") {
+                let members_start = regions.len();
+                collect_definitions(spec, body, source, Some(&qualified_name), regions);
+                if regions.len() > members_start {
+                    continue;
+                }
+            }
+        }
+
+        regions.push(CodeRegion {
+            start_byte: start.start_byte(),
+            end_byte: child.end_byte(),
+            start_line: start.start_position().row,
+            end_line: child.end_position().row,
+            symbols: vec![qualified_name],
+            kind: Some(definition.kind()),
+        });
+    }
+}
+
+/// Name of a definition, or its node kind if it has none. Rust impls are named after the type they implement.
+fn symbol_name(definition: Node, source: &[u8]) -> String {
+    let name_node = definition.child_by_field_name("name")
+        .or_else(|| definition.child_by_field_name("type"))
+        .or_else(|| {
+            // Go type declarations name their types in type specs
+            let mut cursor = definition.walk();
+            let spec = definition.named_children(&mut cursor).find(|c| c.kind() == "type_spec");
+            spec.and_then(|s| s.child_by_field_name("name"))
+        });
+    name_node.and_then(|n| n.utf8_text(source).ok())
+        .map(str::to_owned)
+        .unwrap_or_else(|| definition.kind().to_owned())
+}
+
+/// Adds regions for the code between definitions, eg. imports and top level statements, leaving out whitespace
+fn fill_gaps(source: &str, regions: Vec<CodeRegion>) -> Vec<CodeRegion> {
+    let mut filled = vec![];
+    let mut position = 0;
+    for region in regions.into_iter().chain([gap_end_marker(source)]) {
+        if region.start_byte > position && !source[position..region.start_byte].trim().is_empty() {
+            let gap = &source[position..region.start_byte];
+            let start_byte = position + (gap.len() - gap.trim_start().len());
+            let end_byte = position + gap.trim_end().len();
+            filled.push(CodeRegion {
+                start_byte,
+                end_byte,
+                start_line: line_of(source, start_byte),
+                end_line: line_of(source, end_byte),
+                symbols: vec![],
+                kind: None,
+            });
+        }
+        position = position.max(region.end_byte);
+        if region.end_byte > region.start_byte {
+            filled.push(region);
+        }
+    }
+    filled
+}
+
+/// Empty region at the end of the source, so the code after the last definition is filled in like other gaps
+fn gap_end_marker(source: &str) -> CodeRegion {
+    CodeRegion {
+        start_byte: source.len(),
+        end_byte: source.len(),
+        start_line: 0,
+        end_line: 0,
+        symbols: vec![],
+        kind: None,
+    }
+}
+
+fn line_of(source: &str, byte: usize) -> usize {
+    source[..byte].matches('\n').count()
+}
+
+/// Splits a region too large for one chunk at line boundaries. Every part keeps the symbols of the region.
+fn split_region(source: &str, region: CodeRegion) -> Vec<CodeRegion> {
+    let text = &source[region.start_byte..region.end_byte];
+    if language::estimate_tokens(text) <= CODE_CHUNK_MAX_TOKENS {
+        return vec![region];
+    }
+
+    let mut parts = vec![];
+    let mut part_start = region.start_byte;
+    let mut part_tokens = 0;
+    let mut offset = region.start_byte;
+    for line in text.split_inclusive('\n') {
+        let line_tokens = language::estimate_tokens(line);
+        if part_tokens > 0 && part_tokens + line_tokens > CODE_CHUNK_MAX_TOKENS {
+            parts.push((part_start, offset));
+            part_start = offset;
+            part_tokens = 0;
+        }
+        part_tokens += line_tokens;
+        offset += line.len();
+    }
+    parts.push((part_start, region.end_byte));
+
+    parts.into_iter()
+        .map(|(start_byte, end_byte)| CodeRegion {
+            start_byte,
+            end_byte,
+            start_line: line_of(source, start_byte),
+            end_line: line_of(source, end_byte),
+            symbols: region.symbols.clone(),
+            kind: region.kind,
+        })
+        .collect()
+}
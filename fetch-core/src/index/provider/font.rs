@@ -0,0 +1,515 @@
+use std::{collections::HashSet, fs::Metadata, sync::Arc};
+
+use async_trait::async_trait;
+use camino::{Utf8Path, Utf8PathBuf};
+use chrono::{DateTime, Utc};
+use futures::TryFutureExt;
+use image::ImageFormat;
+use serde_json::{Map, Value};
+use tokio::{join, task};
+use tracing::{debug, info, instrument};
+use ttf_parser::{Face, name_id};
+
+use crate::{files::{dupes, os_path::to_os_path, volumes}, index::{ChunkFile, ChunkType, embedding::{embeddinggemma::{self, EmbeddingGemmaEmbeddedChunkFile}, siglip2::{self, Siglip2EmbeddedChunkFile}}, language::{self, UNDETERMINED_LANGUAGE}, provider::{ChunkQueryFilters, ChunkQueryResult, ChunkingIndexProvider, IndexProviderError, IndexProviderErrorType, IndexedFile, chunkfile_vocabulary, clear_chunkfiles, create_chunkfile_dir, directory_prefix, indexed_file_of, indexed_paths_by_content_of, query_related_chunks, rename_chunks, unlink_chunkfile, registry::{CostClass, ProviderCapabilities}, score::ScoreNormalizer}}, previewable::font_specimen, sequence, store::{ChunkStore, Filter, FilterRelation, FilterValue, KeyedSequencedData}};
+
+/// Index provider for font files (.ttf, .otf). A specimen of the font, its name and characters set in the font
+/// itself, is indexed as an image chunk so fonts can be found by how they look, eg. "rounded geometric sans". The
+/// font's name table (family, style, designer, description and so on) is indexed as a text chunk, along with its
+/// weight and whether it is italic or monospaced.
+pub struct FontIndexProvider {
+    text_store: Arc<dyn ChunkStore<EmbeddingGemmaEmbeddedChunkFile>>,
+    image_store: Arc<dyn ChunkStore<Siglip2EmbeddedChunkFile>>,
+    hybrid_search: bool,
+}
+
+impl FontIndexProvider {
+    pub fn using(text_store: Arc<dyn ChunkStore<EmbeddingGemmaEmbeddedChunkFile>>, image_store: Arc<dyn ChunkStore<Siglip2EmbeddedChunkFile>>) -> Self {
+        FontIndexProvider { text_store, image_store, hybrid_search: false }
+    }
+
+    /// Matches the words of queries with full text search along with their meaning, and adds up the scores of both,
+    /// see store::FusionWeights. Queries only match by meaning by default.
+    pub fn with_hybrid_search(mut self, enabled: bool) -> Self {
+        self.hybrid_search = enabled;
+        self
+    }
+}
+
+#[async_trait]
+impl ChunkingIndexProvider for FontIndexProvider {
+    fn provider_name(&self) -> &'static str {
+        PROVIDER_NAME
+    }
+
+    fn provides_indexing_for_extension(&self, ext: &str) -> bool {
+        EXTENSIONS.contains(&ext)
+    }
+
+    fn capabilities(&self) -> ProviderCapabilities {
+        ProviderCapabilities { mime_types: vec!["font/ttf", "font/otf"], cost: CostClass::Moderate }
+    }
+
+    async fn index(&self, path: &Utf8Path, opt_modified: Option<DateTime<Utc>>) -> Result<(), IndexProviderError> {
+        debug!("Font Index Provider: Indexing file at path: {}", path);
+        let contents = tokio::fs::read(to_os_path(path)).await
+            .map_err(|e| IndexProviderError {
+                provider_name: PROVIDER_NAME.to_string(),
+                r#type: IndexProviderErrorType::IO {
+                    path: path.to_string(),
+                    source: e.into(),
+                },
+            })?;
+        let metadata = tokio::fs::metadata(to_os_path(path)).await
+            .map_err(|e| IndexProviderError {
+                provider_name: PROVIDER_NAME.to_string(),
+                r#type: IndexProviderErrorType::IO {
+                    path: path.to_string(),
+                    source: e.into(),
+                },
+            })?;
+
+        // If the store has indexed chunks for this file, then check the stored original_file_modified_date to
+        // make sure it comes before the current file's modified date. If so, then make sure to clear the previously
+        // stored chunks from the store before proceeding.
+        let discover_filter = &[Filter {
+            attribute: ChunkFile::ORIGINAL_FILE_ATTR,
+            filter: FilterValue::String(path.as_str()),
+            relation: FilterRelation::Eq,
+        }];
+        let discovered_chunks: (Option<ChunkFile>, Option<ChunkFile>) = futures::try_join!(
+            self.text_store.query_filter_n(discover_filter, 1, 0)
+                .map_ok(|vec| vec.into_iter().map(|ec| ec.chunkfile).next()),
+            self.image_store.query_filter_n(discover_filter, 1, 0)
+                .map_ok(|vec| vec.into_iter().map(|ec| ec.chunkfile).next()),
+        ).map_err(|e| IndexProviderError {
+            provider_name: PROVIDER_NAME.to_string(),
+            r#type: IndexProviderErrorType::Store {
+                operation: "query filter",
+                source: e.into(),
+            }
+        })?;
+
+        if let Some(discovered_chunk) = discovered_chunks.0.or(discovered_chunks.1) {
+            sequence::observe(discovered_chunk.sequence_number);
+            let last_modified: DateTime<Utc> = opt_modified.unwrap_or(DateTime::from(metadata.modified()
+                .expect("File modified datetime not available on this platform")));
+            let stored_modified = discovered_chunk.original_file_modified_date;
+            if last_modified.timestamp_millis() <= stored_modified.timestamp_millis() {
+                info!("Attempted indexing on file: {} but the stored modified_date ({}) was equal to or later than the \
+                    file's modified_date ({}). Ignoring.", path, stored_modified, last_modified);
+                return Ok(());
+            }
+
+            self.clear(path, Some(last_modified)).await?;
+        }
+
+        let sequence_number = sequence::next();
+
+        let chunk_out_dir = create_chunkfile_dir(path).await
+            .map_err(|e| IndexProviderError {
+                provider_name: PROVIDER_NAME.to_string(),
+                r#type: IndexProviderErrorType::IO {
+                    path: path.to_string(),
+                    source: e.into(),
+                }
+            })?;
+
+        debug!("Font Index Provider: Chunking file at path: {} to out_dir: {}", path, chunk_out_dir);
+        let chunkfiles = chunk_font(path, contents, metadata, &chunk_out_dir, sequence_number).await
+            .map_err(|e| IndexProviderError {
+                provider_name: PROVIDER_NAME.to_owned(),
+                r#type: IndexProviderErrorType::Chunking {
+                    path: path.to_string(),
+                    source: e,
+                }
+            })?;
+
+        debug!("Font Index Provider: Embedding chunks at dir: {}", chunk_out_dir);
+        let mut embedded_text_chunkfiles = vec![];
+        let mut embedded_image_chunkfiles = vec![];
+        for chunkfile in chunkfiles {
+            debug!("Font Index Provider: Embedding chunk with id: {}", chunkfile.get_key());
+            match chunkfile.chunk_type {
+                ChunkType::Text => {
+                    embedded_text_chunkfiles.push(embeddinggemma::embed_chunk(chunkfile).await
+                        .map_err(|e| IndexProviderError {
+                            provider_name: PROVIDER_NAME.to_string(),
+                            r#type: IndexProviderErrorType::Embedding { source: e },
+                        })?);
+                },
+                ChunkType::Image => {
+                    embedded_image_chunkfiles.push(siglip2::embed_chunk(chunkfile).await
+                        .map_err(|e| IndexProviderError {
+                            provider_name: PROVIDER_NAME.to_string(),
+                            r#type: IndexProviderErrorType::Embedding { source: e },
+                        })?);
+                }
+                _ => unreachable!("Font chunker should only produce text and image chunks"),
+            }
+        }
+
+        debug!("Font Index Provider: Storing chunks and embeddings for path: {}", path);
+        futures::try_join!(
+            self.text_store.put(embedded_text_chunkfiles),
+            self.image_store.put(embedded_image_chunkfiles),
+        ).map_err(|e| IndexProviderError {
+            provider_name: PROVIDER_NAME.to_string(),
+            r#type: IndexProviderErrorType::Store {
+                operation: "put",
+                source: e.into(),
+            }
+        })?;
+
+        Ok(())
+    }
+
+    async fn clear(&self, path: &Utf8Path, opt_modified: Option<DateTime<Utc>>) -> Result<(), IndexProviderError> {
+        debug!("Font Index Provider: Clearing index of path: {}", path);
+        clear_chunkfiles(path).await.map_err(|e| IndexProviderError {
+            provider_name: PROVIDER_NAME.to_string(),
+            r#type: IndexProviderErrorType::IO { path: path.to_string(), source: e.into() }
+        })?;
+
+        let mut filters = vec![Filter {
+            attribute: ChunkFile::ORIGINAL_FILE_ATTR,
+            filter: FilterValue::String(path.as_str()),
+            relation: FilterRelation::Eq,
+        }];
+        if let Some(modified_dt) = &opt_modified {
+            filters.push(Filter {
+                attribute: ChunkFile::FILE_MODIFIED_DATE_ATTR,
+                filter: FilterValue::DateTime(modified_dt),
+                relation: FilterRelation::Eq,
+            });
+        }
+        futures::try_join!(
+            self.text_store.clear_filter(&filters),
+            self.image_store.clear_filter(&filters)
+        ).map_err(|e| IndexProviderError {
+            provider_name: PROVIDER_NAME.to_string(),
+            r#type: IndexProviderErrorType::Store {
+                operation: "clear filter",
+                source: e.into(),
+            }
+        })?;
+
+        Ok(())
+    }
+
+    async fn restore(&self, path: &Utf8Path) -> Result<u64, IndexProviderError> {
+        debug!("Font Index Provider: Restoring cleared chunks under path: {}", path);
+        let prefix = directory_prefix(path);
+        let mut restored = 0;
+        for filters in [
+            [Filter { attribute: ChunkFile::ORIGINAL_FILE_ATTR, filter: FilterValue::String(path.as_str()), relation: FilterRelation::Eq }],
+            [Filter { attribute: ChunkFile::ORIGINAL_FILE_ATTR, filter: FilterValue::String(&prefix), relation: FilterRelation::StartsWith }],
+        ] {
+            let (text_restored, image_restored) = futures::try_join!(
+                self.text_store.restore_filter(&filters),
+                self.image_store.restore_filter(&filters)
+            ).map_err(|e| IndexProviderError {
+                provider_name: PROVIDER_NAME.to_string(),
+                r#type: IndexProviderErrorType::Store {
+                    operation: "restore filter",
+                    source: e.into(),
+                }
+            })?;
+            restored += text_restored + image_restored;
+        }
+        Ok(restored)
+    }
+
+    async fn tag(&self, path: &Utf8Path, tags: &[String]) -> Result<(), IndexProviderError> {
+        debug!("Font Index Provider: Tagging chunks of path: {} with tags: {:?}", path, tags);
+        let filters = &[Filter {
+            attribute: ChunkFile::ORIGINAL_FILE_ATTR,
+            filter: FilterValue::String(path.as_str()),
+            relation: FilterRelation::Eq,
+        }];
+        let (mut text_chunks, mut image_chunks) = futures::try_join!(
+            self.text_store.query_filter(filters),
+            self.image_store.query_filter(filters),
+        ).map_err(|e| IndexProviderError {
+            provider_name: PROVIDER_NAME.to_string(),
+            r#type: IndexProviderErrorType::Store {
+                operation: "query filter",
+                source: e.into(),
+            }
+        })?;
+        if text_chunks.is_empty() && image_chunks.is_empty() {
+            return Ok(());
+        }
+
+        for chunk in &mut text_chunks {
+            chunk.chunkfile.user_tags = tags.to_vec();
+        }
+        for chunk in &mut image_chunks {
+            chunk.chunkfile.user_tags = tags.to_vec();
+        }
+        futures::try_join!(
+            self.text_store.put(text_chunks),
+            self.image_store.put(image_chunks),
+        ).map_err(|e| IndexProviderError {
+            provider_name: PROVIDER_NAME.to_string(),
+            r#type: IndexProviderErrorType::Store {
+                operation: "put",
+                source: e.into(),
+            }
+        })?;
+
+        Ok(())
+    }
+
+    async fn rename(&self, from: &Utf8Path, to: &Utf8Path) -> Result<u64, IndexProviderError> {
+        debug!("Font Index Provider: Renaming chunks of path: {} to: {}", from, to);
+        let (text_chunks, image_chunks) = futures::try_join!(
+            rename_chunks(self.text_store.as_ref(), from, to, PROVIDER_NAME,
+                |c: &mut EmbeddingGemmaEmbeddedChunkFile| &mut c.chunkfile),
+            rename_chunks(self.image_store.as_ref(), from, to, PROVIDER_NAME,
+                |c: &mut Siglip2EmbeddedChunkFile| &mut c.chunkfile),
+        )?;
+        Ok(text_chunks + image_chunks)
+    }
+
+    async fn indexed_file(&self, file_id: &str) -> Result<Option<IndexedFile>, IndexProviderError> {
+        let (text_file, image_file) = futures::try_join!(
+            indexed_file_of(self.text_store.as_ref(), file_id, PROVIDER_NAME, |c: EmbeddingGemmaEmbeddedChunkFile| c.chunkfile),
+            indexed_file_of(self.image_store.as_ref(), file_id, PROVIDER_NAME, |c: Siglip2EmbeddedChunkFile| c.chunkfile),
+        )?;
+        Ok(text_file.or(image_file))
+    }
+
+    async fn indexed_paths_by_content(&self, fingerprint: &str) -> Result<Vec<Utf8PathBuf>, IndexProviderError> {
+        let (text_paths, image_paths) = futures::try_join!(
+            indexed_paths_by_content_of(self.text_store.as_ref(), fingerprint, PROVIDER_NAME, |c: EmbeddingGemmaEmbeddedChunkFile| c.chunkfile),
+            indexed_paths_by_content_of(self.image_store.as_ref(), fingerprint, PROVIDER_NAME, |c: Siglip2EmbeddedChunkFile| c.chunkfile),
+        )?;
+        Ok(text_paths.into_iter().chain(image_paths).collect())
+    }
+
+    async fn query_n(&self, str: &str, filters: &ChunkQueryFilters, num_results: u32, offset: u32)
+        -> Result<Vec<ChunkQueryResult>, IndexProviderError>
+    {
+        debug!("Font Index Provider: Querying index of with params: {}, filters: {:?}, \
+            num_results: {}, offset: {}", str, filters, num_results, offset);
+        let store_filters = &filters.store_filters();
+        // Each store is queried from the start, and the chunks of both are paged together by their scores
+        let store_results = offset.saturating_add(num_results);
+
+        let text_chunk_future = async move {
+            let text_vec = embeddinggemma::embed_query(str).await.map_err(|e| IndexProviderError {
+                provider_name: PROVIDER_NAME.to_string(),
+                r#type: IndexProviderErrorType::Embedding { source: e },
+            })?;
+
+            self.text_store.query_full_select_n(Some(text_vec), self.hybrid_search.then_some(str), store_filters, ChunkFile::QUERY_RESULT_ATTRS, store_results, 0).await
+                .map_err(|e| IndexProviderError {
+                    provider_name: PROVIDER_NAME.to_string(),
+                    r#type: IndexProviderErrorType::Store {
+                        operation: "query full",
+                        source: e,
+                    }
+                })
+        };
+        let image_chunk_future = async move {
+            let image_vec = siglip2::embed_query(str).await.map_err(|e| IndexProviderError {
+                provider_name: PROVIDER_NAME.to_string(),
+                r#type: IndexProviderErrorType::Embedding { source: e },
+            })?;
+
+            self.image_store.query_full_select_n(Some(image_vec), self.hybrid_search.then_some(str), store_filters, ChunkFile::QUERY_RESULT_ATTRS, store_results, 0).await
+                .map_err(|e| IndexProviderError {
+                    provider_name: PROVIDER_NAME.to_string(),
+                    r#type: IndexProviderErrorType::Store {
+                        operation: "query full",
+                        source: e,
+                    }
+                })
+        };
+
+        let (text_result, image_result) = join!(
+            text_chunk_future,
+            image_chunk_future
+        );
+        let chunks = text_result?.into_iter()
+            .map(|c| (c.score, c.result.chunkfile))
+            .chain(image_result?.into_iter().map(|c| (c.score, c.result.chunkfile)));
+
+        let results = ScoreNormalizer::for_provider(PROVIDER_NAME).normalize_page(chunks, num_results, offset);
+        Ok(results)
+    }
+
+    async fn query_related_n(&self, path: &Utf8Path, num_results: u32)
+        -> Result<Vec<ChunkQueryResult>, IndexProviderError>
+    {
+        debug!("Font Index Provider: Querying files related to path: {}, num_results: {}", path, num_results);
+        let (text_chunks, image_chunks) = futures::try_join!(
+            query_related_chunks(self.text_store.as_ref(), path, num_results, PROVIDER_NAME,
+                |c: EmbeddingGemmaEmbeddedChunkFile| (c.chunkfile, c.embedding)),
+            query_related_chunks(self.image_store.as_ref(), path, num_results, PROVIDER_NAME,
+                |c: Siglip2EmbeddedChunkFile| (c.chunkfile, c.embedding)),
+        )?;
+
+        Ok(text_chunks.into_iter().chain(image_chunks).collect())
+    }
+
+    async fn vocabulary(&self) -> Result<Vec<String>, IndexProviderError> {
+        debug!("Font Index Provider: Collecting vocabulary of indexed files");
+        // Every font has a text chunk of its names, so the text store covers every file
+        let chunks = self.text_store.query_filter(&[]).await.map_err(|e| IndexProviderError {
+            provider_name: PROVIDER_NAME.to_string(),
+            r#type: IndexProviderErrorType::Store {
+                operation: "query by filter",
+                source: e.into(),
+            }
+        })?;
+
+        let mut seen_files = HashSet::new();
+        Ok(chunks.iter()
+            .map(|c| &c.chunkfile)
+            .filter(|c| seen_files.insert(c.original_file.clone()))
+            .flat_map(chunkfile_vocabulary)
+            .collect())
+    }
+}
+
+// private constants and functions
+
+const PROVIDER_NAME: &str = "FontIndexProvider";
+const EXTENSIONS: [&str; 2] = ["ttf", "otf"];
+
+const TEXT_CHUNK_CHANNEL: &str = "names";
+const IMAGE_CHUNK_CHANNEL: &str = "specimen";
+const IMAGE_CHUNK_MAX_SIDE: u32 = 512;
+const CHUNK_SEQUENCE_ID: f32 = 0.0;
+const CHUNK_LENGTH: f32 = 1.0;
+
+/// Entries of the name table that describe the font. Copyright, license and version entries are left out, as
+/// they are mostly boilerplate shared by every font.
+const DESCRIPTIVE_NAME_IDS: [(u16, &str); 8] = [
+    (name_id::FULL_NAME, "Name"),
+    (name_id::FAMILY, "Family"),
+    (name_id::SUBFAMILY, "Style"),
+    (name_id::TYPOGRAPHIC_FAMILY, "Family"),
+    (name_id::TYPOGRAPHIC_SUBFAMILY, "Style"),
+    (name_id::MANUFACTURER, "Foundry"),
+    (name_id::DESIGNER, "Designer"),
+    (name_id::DESCRIPTION, "Description"),
+];
+
+
+#[instrument(name = "chunk", level = "debug", skip_all, fields(path = %path))]
+async fn chunk_font(path: &Utf8Path, contents: Vec<u8>, metadata: Metadata, out_dir: &Utf8Path, sequence_number: u64)
+    -> Result<Vec<ChunkFile>, anyhow::Error>
+{
+    let file_creation: DateTime<Utc> = DateTime::from(metadata.created()
+        .expect("File creation datetime not available on this platform"));
+    let file_modified: DateTime<Utc> = DateTime::from(metadata.modified()
+        .expect("File modified datetime not available on this platform"));
+    let file_length = metadata.len();
+    let original_file_id = volumes::file_id(path);
+    let original_file_fingerprint = dupes::content_fingerprint(path);
+
+    let path = path.to_owned();
+    let out_dir = out_dir.to_owned();
+    task::spawn_blocking(move || {
+        let face = Face::parse(&contents, 0)?;
+        let (description, tags) = describe_font(&face);
+
+        let new_chunk = |
+            chunk_channel: &str,
+            chunkfile: Utf8PathBuf,
+            chunk_type: ChunkType,
+            tags: Map<String, Value>,
+            language: &str,
+        | ChunkFile {
+            original_file: path.clone(),
+            chunk_channel: chunk_channel.to_owned(),
+            chunk_sequence_id: CHUNK_SEQUENCE_ID,
+            original_file_id: original_file_id.clone(),
+            chunkfile,
+            chunk_type,
+            chunk_length: CHUNK_LENGTH,
+            original_file_creation_date: file_creation,
+            original_file_modified_date: file_modified,
+            original_file_size: file_length,
+            original_file_fingerprint: original_file_fingerprint.clone(),
+            original_file_tags: tags,
+            language: language.to_owned(),
+            user_tags: vec![],
+            sequence_number,
+        };
+
+        let text_chunkfile = out_dir.join(format!("{}-{}.txt", TEXT_CHUNK_CHANNEL, CHUNK_SEQUENCE_ID));
+        unlink_chunkfile(&text_chunkfile)?;
+        std::fs::write(&text_chunkfile, &description)?;
+        let mut text_tags = tags.clone();
+        text_tags.insert("full_text".to_string(), description.as_str().into());
+        let text_chunk = new_chunk(TEXT_CHUNK_CHANNEL, text_chunkfile, ChunkType::Text, text_tags,
+            language::detect_language(&description));
+
+        let image_chunkfile = out_dir.join(format!("{}-{}.webp", IMAGE_CHUNK_CHANNEL, CHUNK_SEQUENCE_ID));
+        unlink_chunkfile(&image_chunkfile)?;
+        font_specimen(&contents, IMAGE_CHUNK_MAX_SIDE)?.save_with_format(&image_chunkfile, ImageFormat::WebP)?;
+        let image_chunk = new_chunk(IMAGE_CHUNK_CHANNEL, image_chunkfile, ChunkType::Image, tags,
+            UNDETERMINED_LANGUAGE);
+
+        Ok::<Vec<ChunkFile>, anyhow::Error>(vec![text_chunk, image_chunk])
+    }).await? // this is Result<Result<vec, closure_error>, tokio::task_error>
+}
+
+/// Describes the font in text from its name table and style, returning the description and the chunk tags
+/// recording the font's family, style and weight
+fn describe_font(face: &Face) -> (String, Map<String, Value>) {
+    let mut lines = vec![];
+    let mut seen = HashSet::new();
+    for (id, label) in DESCRIPTIVE_NAME_IDS {
+        // Names are often repeated for several platforms and languages, only distinct ones are kept
+        for name in face.names().into_iter().filter(|n| n.name_id == id).filter_map(|n| n.to_string()) {
+            let name = name.trim().to_owned();
+            if !name.is_empty() && seen.insert(name.clone()) {
+                lines.push(format!("{}: {}", label, name));
+            }
+        }
+    }
+
+    let weight = face.weight().to_number();
+    let mut style = vec![weight_name(weight).to_owned()];
+    if face.is_italic() {
+        style.push("italic".to_owned());
+    }
+    if face.is_monospaced() {
+        style.push("monospaced".to_owned());
+    }
+    if face.is_variable() {
+        style.push("variable".to_owned());
+    }
+    lines.push(format!("Font: {} font", style.join(" ")));
+
+    let name_of = |id: u16| face.names().into_iter().filter(|n| n.name_id == id).find_map(|n| n.to_string());
+    let mut tags = Map::new();
+    if let Some(family) = name_of(name_id::TYPOGRAPHIC_FAMILY).or_else(|| name_of(name_id::FAMILY)) {
+        tags.insert("font_family".to_string(), family.into());
+    }
+    if let Some(subfamily) = name_of(name_id::TYPOGRAPHIC_SUBFAMILY).or_else(|| name_of(name_id::SUBFAMILY)) {
+        tags.insert("font_style".to_string(), subfamily.into());
+    }
+    tags.insert("font_weight".to_string(), weight.into());
+
+    (lines.join("\n"), tags)
+}
+
+/// Common name of a usWeightClass value
+fn weight_name(weight: u16) -> &'static str {
+    match weight {
+        0..=149 => "thin",
+        150..=249 => "extra light",
+        250..=349 => "light",
+        350..=449 => "regular",
+        450..=549 => "medium",
+        550..=649 => "semibold",
+        650..=749 => "bold",
+        750..=849 => "extra bold",
+        _ => "black",
+    }
+}
@@ -1,56 +1,63 @@
-use std::{collections::HashSet, fs::Metadata, io::Cursor, sync::{Arc, LazyLock}};
+use std::{collections::HashSet, fs::Metadata, io::BufReader, path::Path, sync::{Arc, LazyLock}};
 
 use async_trait::async_trait;
-use camino::Utf8Path;
+use camino::{Utf8Path, Utf8PathBuf};
 use chrono::{DateTime, Utc};
-use image::{DynamicImage, ImageFormat, ImageReader, RgbaImage, imageops::FilterType};
-use log::debug;
+use image::{DynamicImage, GrayImage, ImageError, ImageFormat, ImageReader, Limits, RgbImage, RgbaImage, error::{LimitError, LimitErrorKind}, imageops::FilterType};
+use jpeg_decoder::PixelFormat;
+use memmap2::Mmap;
 use psd::{Psd, PsdLayer};
 use serde_json::Map;
-use tokio::{fs::File, io::AsyncReadExt, task};
+use tokio::{fs::File, task};
+use tracing::{debug, instrument};
 
-use crate::{index::{ChunkFile, ChunkType, embedding::siglip2::{Siglip2EmbeddedChunkFile, embed_chunk, embed_query}, provider::{ChunkQueryResult, ChunkingIndexProvider, IndexProviderError, IndexProviderErrorType, create_chunkfile_dir, clear_chunkfiles}}, store::{ClearByFilter, Filter, FilterRelation, FilterValue, KeyedSequencedStore, QueryByFilter, QueryFull}};
+use crate::{files::{dupes, os_path::to_os_path, volumes}, index::{ChunkFile, ChunkType, embedding::siglip2::{Siglip2EmbeddedChunkFile, embed_chunk, embed_query}, language::UNDETERMINED_LANGUAGE, provider::{ChunkQueryFilters, ChunkQueryResult, ChunkingIndexProvider, IndexProviderError, IndexProviderErrorType, IndexedFile, chunkfile_vocabulary, create_chunkfile_dir, clear_chunkfiles, directory_prefix, indexed_file_of, indexed_paths_by_content_of, query_related_chunks, rename_chunks, unlink_chunkfile, registry::{CostClass, ProviderCapabilities, sniff_mime_type}, sandbox::{self, SandboxedFormat}, score::ScoreNormalizer}}, previewable::{has_os_generator_for_type, os_thumbnail}, sequence, store::{ChunkStore, Filter, FilterRelation, FilterValue}};
 
-pub struct ImageIndexProvider<S>
-where
-    S: KeyedSequencedStore<String, Siglip2EmbeddedChunkFile> +
-        QueryFull<Siglip2EmbeddedChunkFile> +
-        QueryByFilter<Siglip2EmbeddedChunkFile> +
-        ClearByFilter<Siglip2EmbeddedChunkFile> +
-        Send + Sync
-{
-    vector_store: Arc<S>,
+pub struct ImageIndexProvider {
+    vector_store: Arc<dyn ChunkStore<Siglip2EmbeddedChunkFile>>,
+    max_file_size: Option<u64>,
+    hybrid_search: bool,
 }
 
-impl<S> ImageIndexProvider<S>
-where
-    S: KeyedSequencedStore<String, Siglip2EmbeddedChunkFile> +
-        QueryFull<Siglip2EmbeddedChunkFile> +
-        QueryByFilter<Siglip2EmbeddedChunkFile> +
-        ClearByFilter<Siglip2EmbeddedChunkFile> +
-        Send + Sync
-{
-    pub fn using(vector_store: Arc<S>) -> Self {
-        ImageIndexProvider { vector_store }
+impl ImageIndexProvider {
+    pub fn using(vector_store: Arc<dyn ChunkStore<Siglip2EmbeddedChunkFile>>) -> Self {
+        ImageIndexProvider { vector_store, max_file_size: None, hybrid_search: false }
+    }
+
+    /// Matches the words of queries with full text search along with their meaning, and adds up the scores of both,
+    /// see store::FusionWeights. Queries only match by meaning by default.
+    pub fn with_hybrid_search(mut self, enabled: bool) -> Self {
+        self.hybrid_search = enabled;
+        self
+    }
+
+    /// Sets the size in bytes of the largest file decoded by Fetch itself. Larger files are indexed through the
+    /// operating system's thumbnail generator if there is one for their type, and fail otherwise. By default
+    /// files of any size are decoded, as long as their decoded image fits in memory.
+    pub fn with_max_file_size(mut self, max_file_size: u64) -> Self {
+        self.max_file_size = Some(max_file_size);
+        self
     }
 }
 
 #[async_trait]
-impl<S> ChunkingIndexProvider for ImageIndexProvider<S>
-where
-    S: KeyedSequencedStore<String, Siglip2EmbeddedChunkFile> +
-        QueryFull<Siglip2EmbeddedChunkFile> +
-        QueryByFilter<Siglip2EmbeddedChunkFile> +
-        ClearByFilter<Siglip2EmbeddedChunkFile> +
-        Send + Sync
-{
+impl ChunkingIndexProvider for ImageIndexProvider {
+    fn provider_name(&self) -> &'static str {
+        PROVIDER_NAME
+    }
+
     fn provides_indexing_for_extension(&self, ext: &str) -> bool {
-        EXTENSIONS.contains(ext)
+        // File types Fetch cannot decode itself can still be indexed through thumbnails from the OS
+        EXTENSIONS.contains(ext) || (!OTHER_PROVIDER_EXTENSIONS.contains(&ext) && has_os_generator_for_type(ext))
+    }
+
+    fn capabilities(&self) -> ProviderCapabilities {
+        ProviderCapabilities { mime_types: MIME_TYPES.to_vec(), cost: CostClass::Moderate }
     }
 
     async fn index(&self, path: &Utf8Path, opt_modified: Option<DateTime<Utc>>) -> Result<(), IndexProviderError> {
         debug!("Image Index Provider: Indexing file at path: {}", path);
-        let mut file = File::open(path).await
+        let file = File::open(to_os_path(path)).await
             .map_err(|e| IndexProviderError {
                 provider_name: PROVIDER_NAME.to_string(),
                 r#type: IndexProviderErrorType::IO {
@@ -85,10 +92,11 @@ where
                 source: e.into()
             }
         })?;
-        if !prev_indexed.is_empty() {
+        if let Some(prev_chunk) = prev_indexed.first() {
+            sequence::observe(prev_chunk.chunkfile.sequence_number);
             let last_modified: DateTime<Utc> = opt_modified.unwrap_or(DateTime::from(metadata.modified()
                 .expect("Modified date not available on platform")));
-            let stored_modified = prev_indexed.first().unwrap().chunkfile.original_file_modified_date;
+            let stored_modified = prev_chunk.chunkfile.original_file_modified_date;
             if last_modified.timestamp_millis() <= stored_modified.timestamp_millis() {
                 return Err(IndexProviderError {
                     provider_name: PROVIDER_NAME.to_string(),
@@ -102,6 +110,10 @@ where
             self.clear(path, Some(last_modified)).await?;
         }
 
+        // Taken once any stored chunks have been observed and before the file is chunked, so the new chunks are
+        // ordered after both the stored chunks and chunks written from older reads of the file
+        let sequence_number = sequence::next();
+
         // generate folder to store file chunks
         let chunk_out_dir = create_chunkfile_dir(path).await
             .map_err(|e| IndexProviderError {
@@ -113,10 +125,51 @@ where
             })?;
 
         debug!("Image Index Provider: Chunking file at path: {} to out_dir: {}", path, chunk_out_dir);
-        let chunkfiles = if path.extension() == Some("psd") {
-            chunk_psd(path, &mut file, &metadata, &chunk_out_dir).await?
-        } else {
-            chunk_image(path, &mut file, &metadata, &chunk_out_dir).await?
+        let extension = path.extension().unwrap_or("");
+        // Files are decoded by their content when it is recognized, as their extension may be missing or wrong
+        let mut chunk_method = match sniff_mime_type(path) {
+            Some(PSD_MIME_TYPE) => ChunkMethod::Psd,
+            Some(mime_type) if MIME_TYPES.contains(&mime_type) => ChunkMethod::Image,
+            _ if extension == "psd" => ChunkMethod::Psd,
+            _ if EXTENSIONS.contains(extension) => ChunkMethod::Image,
+            _ => ChunkMethod::OsThumbnail,
+        };
+        let has_os_generator = has_os_generator_for_type(extension);
+        let too_large = self.max_file_size.filter(|max| metadata.len() > *max);
+        if let Some(max_file_size) = too_large.filter(|_| !matches!(chunk_method, ChunkMethod::OsThumbnail)) {
+            if !has_os_generator {
+                return Err(IndexProviderError {
+                    provider_name: PROVIDER_NAME.to_string(),
+                    r#type: IndexProviderErrorType::Chunking {
+                        path: path.to_string(),
+                        source: anyhow::anyhow!("File is larger than the maximum size decoded ({} bytes)",
+                            max_file_size),
+                    },
+                });
+            }
+            debug!("Image Index Provider: File at path: {} is too large to decode, using OS thumbnail", path);
+            chunk_method = ChunkMethod::OsThumbnail;
+        }
+        let chunked = match chunk_method {
+            ChunkMethod::Psd => chunk_psd(path, &metadata, &chunk_out_dir, sequence_number).await,
+            ChunkMethod::Image if sandbox::is_untrusted(path) => {
+                sandbox::chunk_in_sandbox(SandboxedFormat::Image, path, &chunk_out_dir, sequence_number).await
+                    .map_err(|e| IndexProviderError {
+                        provider_name: PROVIDER_NAME.to_string(),
+                        r#type: IndexProviderErrorType::Chunking { path: path.to_string(), source: e },
+                    })
+            },
+            ChunkMethod::Image => chunk_image(path, &metadata, &chunk_out_dir, sequence_number).await,
+            ChunkMethod::OsThumbnail => chunk_os_thumbnail(path, &metadata, &chunk_out_dir, sequence_number).await,
+        };
+        // Images too large to decode in memory are left to the operating system, whose thumbnail generators can
+        // decode them at a reduced size
+        let chunkfiles = match chunked {
+            Err(e) if is_too_large_to_decode(&e) && has_os_generator => {
+                debug!("Image Index Provider: File at path: {} is too large to decode, using OS thumbnail", path);
+                chunk_os_thumbnail(path, &metadata, &chunk_out_dir, sequence_number).await?
+            },
+            chunked => chunked?,
         };
 
         debug!("Image Index Provider: Embedding chunks at dir: {}", chunk_out_dir);
@@ -170,19 +223,85 @@ where
         })
     }
 
-    async fn query_n(&self, str: &str, num_results: u32, offset: u32) -> Result<Vec<ChunkQueryResult>, IndexProviderError> {
-        debug!("Image Index Provider: Querying index of with params: {}, \
-            num_results: {}, offset: {}", str, num_results, offset);
+    async fn restore(&self, path: &Utf8Path) -> Result<u64, IndexProviderError> {
+        debug!("Image Index Provider: Restoring cleared chunks under path: {}", path);
+        let prefix = directory_prefix(path);
+        let mut restored = 0;
+        for filter in [
+            Filter { attribute: ChunkFile::ORIGINAL_FILE_ATTR, filter: FilterValue::String(path.as_str()), relation: FilterRelation::Eq },
+            Filter { attribute: ChunkFile::ORIGINAL_FILE_ATTR, filter: FilterValue::String(&prefix), relation: FilterRelation::StartsWith },
+        ] {
+            restored += self.vector_store.restore_filter(&[filter]).await.map_err(|e| IndexProviderError {
+                provider_name: PROVIDER_NAME.to_string(),
+                r#type: IndexProviderErrorType::Store {
+                    operation: "restore by filter",
+                    source: e.into(),
+                }
+            })?;
+        }
+        Ok(restored)
+    }
+
+    async fn tag(&self, path: &Utf8Path, tags: &[String]) -> Result<(), IndexProviderError> {
+        debug!("Image Index Provider: Tagging chunks of path: {} with tags: {:?}", path, tags);
+        let mut chunks = self.vector_store.query_filter(&[Filter {
+            attribute: ChunkFile::ORIGINAL_FILE_ATTR,
+            filter: FilterValue::String(path.as_str()),
+            relation: FilterRelation::Eq,
+        }]).await.map_err(|e| IndexProviderError {
+            provider_name: PROVIDER_NAME.to_string(),
+            r#type: IndexProviderErrorType::Store {
+                operation: "query by filter",
+                source: e.into(),
+            }
+        })?;
+        if chunks.is_empty() {
+            return Ok(());
+        }
+
+        // Chunks keep their sequence number, so they replace the stored chunks without overwriting chunks written by a
+        // newer index of the file
+        for chunk in &mut chunks {
+            chunk.chunkfile.user_tags = tags.to_vec();
+        }
+        self.vector_store.put(chunks).await.map_err(|e| IndexProviderError {
+            provider_name: PROVIDER_NAME.to_string(),
+            r#type: IndexProviderErrorType::Store {
+                operation: "put",
+                source: e.into(),
+            }
+        })
+    }
+
+    async fn rename(&self, from: &Utf8Path, to: &Utf8Path) -> Result<u64, IndexProviderError> {
+        debug!("Image Index Provider: Renaming chunks of path: {} to: {}", from, to);
+        rename_chunks(self.vector_store.as_ref(), from, to, PROVIDER_NAME, |c: &mut Siglip2EmbeddedChunkFile| &mut c.chunkfile).await
+    }
+
+    async fn indexed_file(&self, file_id: &str) -> Result<Option<IndexedFile>, IndexProviderError> {
+        indexed_file_of(self.vector_store.as_ref(), file_id, PROVIDER_NAME, |c: Siglip2EmbeddedChunkFile| c.chunkfile).await
+    }
+
+    async fn indexed_paths_by_content(&self, fingerprint: &str) -> Result<Vec<Utf8PathBuf>, IndexProviderError> {
+        indexed_paths_by_content_of(self.vector_store.as_ref(), fingerprint, PROVIDER_NAME, |c: Siglip2EmbeddedChunkFile| c.chunkfile).await
+    }
+
+    async fn query_n(&self, str: &str, filters: &ChunkQueryFilters, num_results: u32, offset: u32)
+        -> Result<Vec<ChunkQueryResult>, IndexProviderError>
+    {
+        debug!("Image Index Provider: Querying index of with params: {}, filters: {:?}, \
+            num_results: {}, offset: {}", str, filters, num_results, offset);
         debug!("Image Index Provider: Embedding query");
         let vec = embed_query(str).await.map_err(|e| IndexProviderError {
             provider_name: PROVIDER_NAME.to_string(),
             r#type: IndexProviderErrorType::Embedding { source: e },
         })?;
 
-        let chunks = self.vector_store.query_full_n(
+        let chunks = self.vector_store.query_full_select_n(
             Some(vec),
-            None, // Some(str) // temporarily disabled for tuning
-            &[],
+            self.hybrid_search.then_some(str),
+            &filters.store_filters(),
+            ChunkFile::QUERY_RESULT_ATTRS,
             num_results,
             offset
         ).await.map_err(|e| IndexProviderError {
@@ -193,23 +312,35 @@ where
             }
         })?;
 
-        let mut results = vec![];
-        for chunk in chunks {
-            if chunk.score >= MIN_SCORE {
-                // normalize to 0-100
-                let norm_score = ((chunk.score - MIN_SCORE) / (EXPECTED_MAX_SCORE - MIN_SCORE)) * 100.0;
-                debug!("Image Index Provider: Normalized result score: orig: {}, chunkfile: {}, orig_score: {}, \
-                    norm_score: {}", chunk.result.chunkfile.original_file, chunk.result.chunkfile.chunkfile,
-                    chunk.score, norm_score);
-                results.push(ChunkQueryResult::new(chunk.result.chunkfile, norm_score));
-            } else {
-                debug!("Image Index Provider: Result score is under minimum threshold: orig: {}, chunkfile: {}, \
-                    orig_score: {}", chunk.result.chunkfile.original_file, chunk.result.chunkfile.chunkfile,
-                    chunk.score);
-            }
-        }
+        let results = ScoreNormalizer::for_provider(PROVIDER_NAME).normalize_all(chunks.into_iter().map(|c| (c.score, c.result.chunkfile)));
         Ok(results)
     }
+
+    async fn query_related_n(&self, path: &Utf8Path, num_results: u32)
+        -> Result<Vec<ChunkQueryResult>, IndexProviderError>
+    {
+        debug!("Image Index Provider: Querying files related to path: {}, num_results: {}", path, num_results);
+        query_related_chunks(self.vector_store.as_ref(), path, num_results, PROVIDER_NAME,
+            |c: Siglip2EmbeddedChunkFile| (c.chunkfile, c.embedding)).await
+    }
+
+    async fn vocabulary(&self) -> Result<Vec<String>, IndexProviderError> {
+        debug!("Image Index Provider: Collecting vocabulary of indexed files");
+        let chunks = self.vector_store.query_filter(&[]).await.map_err(|e| IndexProviderError {
+            provider_name: PROVIDER_NAME.to_string(),
+            r#type: IndexProviderErrorType::Store {
+                operation: "query by filter",
+                source: e.into(),
+            }
+        })?;
+
+        let mut seen_files = HashSet::new();
+        Ok(chunks.iter()
+            .map(|c| &c.chunkfile)
+            .filter(|c| seen_files.insert(c.original_file.clone()))
+            .flat_map(chunkfile_vocabulary)
+            .collect())
+    }
 }
 
 // private functions and variables
@@ -244,18 +375,38 @@ static EXTENSIONS: LazyLock<HashSet<&'static str>> = LazyLock::new(|| {
     set
 });
 
+/// How a file is turned into chunks
+enum ChunkMethod {
+    /// Layers are composited with the psd crate
+    Psd,
+    /// Decoded with the image crate
+    Image,
+    /// Thumbnail generated by the operating system, for file types Fetch cannot decode itself
+    OsThumbnail,
+}
+
+/// MIME types of the files decoded by the image crate, see sniff_mime_type
+const MIME_TYPES: [&str; 9] = ["image/avif", "image/bmp", "image/x-icon", "image/jpeg", "image/png", "image/qoi",
+    "image/tiff", "image/webp", PSD_MIME_TYPE];
+const PSD_MIME_TYPE: &str = "image/vnd.adobe.photoshop";
+
+// Extensions indexed by other providers, which should not also be indexed through OS thumbnails
+const OTHER_PROVIDER_EXTENSIONS: [&str; 24] = ["pdf", "md", "markdown", "rs", "py", "pyi", "js", "mjs", "cjs", "jsx",
+    "ts", "mts", "cts", "tsx", "go", "java", "srt", "vtt", "ttf", "otf", "obj", "stl", "gltf", "glb"];
+
 const CHUNK_MAX_SIDE: u32 = 512;
 const IMAGE_CHUNK_EXTENSION: &str = "webp";
 const IMAGE_CHUNK_CHANNEL: &str = "base";
 const IMAGE_CHUNK_SEQUENCE_ID: f32 = 0.0;
 const IMAGE_CHUNK_LENGTH: f32 = 1.0;
 
-// These constants must be tuned to the hybrid query results of lance FTS and siglip2 vector cosine similarity reranking
-// TODO: tune
-const EXPECTED_MAX_SCORE: f32 = 0.3;
-const MIN_SCORE: f32 = 0.05;
+/// Most memory a decoded image may take up. Larger images are decoded by the operating system's thumbnail
+/// generators instead, if there are any for their type.
+const MAX_DECODE_BYTES: u64 = 512 * 1024 * 1024;
 
-async fn chunk_image(path: &Utf8Path, file: &mut File, metadata: &Metadata, out_dir: &Utf8Path)
+
+#[instrument(name = "chunk", level = "debug", skip_all, fields(path = %path))]
+async fn chunk_image(path: &Utf8Path, metadata: &Metadata, out_dir: &Utf8Path, sequence_number: u64)
     -> Result<Vec<ChunkFile>, IndexProviderError>
 {
     let file_creation: DateTime<Utc> = DateTime::from(metadata.created()
@@ -263,24 +414,128 @@ async fn chunk_image(path: &Utf8Path, file: &mut File, metadata: &Metadata, out_
     let file_modification: DateTime<Utc> = DateTime::from(metadata.modified()
         .expect("Modified date not available on platform"));
     let file_length = metadata.len();
-    let mut file_bytes: Vec<u8> = Vec::with_capacity(file_length as usize);
-    file.read_to_end(&mut file_bytes).await.map_err(|e| IndexProviderError {
+
+    let path_clone = path.to_owned();
+    let out_dir_clone = out_dir.to_owned();
+    let chunk_files = task::spawn_blocking(move || {
+        decode_image_chunk(&path_clone, file_creation, file_modification, file_length, sequence_number,
+            &out_dir_clone)
+    }).await // this is Result<Result<vec, closure_error>, tokio::task_error>
+    .map_err(|e| IndexProviderError {
         provider_name: PROVIDER_NAME.to_string(),
-        r#type: IndexProviderErrorType::IO {
-            path: path.to_string(),
+        r#type: IndexProviderErrorType::Unknown {
+            msg: "Failed to join image chunking task",
             source: e.into(),
-        }
+        },
+    })?
+    .map_err(|e| IndexProviderError {
+        provider_name: PROVIDER_NAME.to_string(),
+        r#type: IndexProviderErrorType::Chunking {
+            path: path.to_string(),
+            source: e,
+        },
     })?;
 
+    Ok(chunk_files)
+}
+
+/// Chunks the image at the path on the calling thread, for sandbox workers, see sandbox::run_sandbox_worker_if_requested
+pub(crate) fn chunk_image_blocking(path: &Utf8Path, out_dir: &Utf8Path, sequence_number: u64)
+    -> Result<Vec<ChunkFile>, anyhow::Error>
+{
+    let metadata = std::fs::metadata(to_os_path(path))?;
+    let file_creation: DateTime<Utc> = DateTime::from(metadata.created()
+        .expect("Created date not available on platform"));
+    let file_modification: DateTime<Utc> = DateTime::from(metadata.modified()
+        .expect("Modified date not available on platform"));
+    decode_image_chunk(path, file_creation, file_modification, metadata.len(), sequence_number, out_dir)
+}
+
+fn decode_image_chunk(
+    path: &Utf8Path,
+    file_creation: DateTime<Utc>,
+    file_modification: DateTime<Utc>,
+    file_length: u64,
+    sequence_number: u64,
+    out_dir: &Utf8Path,
+) -> Result<Vec<ChunkFile>, anyhow::Error> {
+    let image = decode_image(&to_os_path(path))?;
+
+    // TODO: chunk large images into multiple chunks? with separate focus window to total window?
+    // or really long aspect ratios?
+
+    let image = image.resize(
+        CHUNK_MAX_SIDE,
+        CHUNK_MAX_SIDE,
+        FilterType::Triangle,
+    );
+
+    let chunk_filename = format!("{}-{}.{}", IMAGE_CHUNK_CHANNEL, IMAGE_CHUNK_SEQUENCE_ID,
+        IMAGE_CHUNK_EXTENSION);
+    let chunkfile_path = out_dir.join(chunk_filename);
+    unlink_chunkfile(&chunkfile_path)?;
+    image.save_with_format(&chunkfile_path, ImageFormat::WebP)?;
+
+    Ok(vec![ChunkFile {
+        original_file: path.to_owned(),
+        chunk_channel: IMAGE_CHUNK_CHANNEL.to_owned(),
+        chunk_sequence_id: IMAGE_CHUNK_SEQUENCE_ID,
+        original_file_id: volumes::file_id(path),
+        chunkfile: chunkfile_path,
+        chunk_type: ChunkType::Image,
+        chunk_length: IMAGE_CHUNK_LENGTH,
+        original_file_creation_date: file_creation,
+        original_file_modified_date: file_modification,
+        original_file_size: file_length,
+        original_file_fingerprint: dupes::content_fingerprint(path),
+        original_file_tags: Map::new(),
+        language: UNDETERMINED_LANGUAGE.to_owned(),
+        user_tags: vec![],
+        sequence_number,
+    }])
+}
+
+#[instrument(name = "chunk", level = "debug", skip_all, fields(path = %path))]
+async fn chunk_psd(path: &Utf8Path, metadata: &Metadata, out_dir: &Utf8Path, sequence_number: u64)
+    -> Result<Vec<ChunkFile>, IndexProviderError>
+{
+    let file_creation: DateTime<Utc> = DateTime::from(metadata.created()
+        .expect("Created date not available on platform"));
+    let file_modification: DateTime<Utc> = DateTime::from(metadata.modified()
+        .expect("Modified date not available on platform"));
+    let file_length = metadata.len();
+
+    let os_path = to_os_path(path);
+    let original_file_id = volumes::file_id(path);
+    let original_file_fingerprint = dupes::content_fingerprint(path);
     let path_clone = path.to_owned();
     let out_dir_clone = out_dir.to_owned();
     let chunk_files = task::spawn_blocking(move || {
-        let image = ImageReader::new(Cursor::new(file_bytes))
-            .with_guessed_format()?
-            .decode()?;
+        // The file is mapped rather than read into memory, so only the parts of it the psd crate reads are paged in
+        let file = std::fs::File::open(&os_path)?;
+        // SAFETY: the file could be modified by another program while it is mapped, in which case the psd crate
+        // reads garbage and fails to parse it. Files being modified are indexed again once they are saved.
+        let mapped = unsafe { Mmap::map(&file)? };
+
+        // The width and height are read from the header first, as flattening needs 4 bytes for every pixel
+        let (width, height) = match mapped.get(14..22) {
+            Some(header) => (u32::from_be_bytes([header[4], header[5], header[6], header[7]]),
+                u32::from_be_bytes([header[0], header[1], header[2], header[3]])),
+            None => anyhow::bail!("File is too short to be a psd file"),
+        };
+        if width as u64 * height as u64 * 4 > MAX_DECODE_BYTES {
+            return Err(too_large_to_decode().into());
+        }
+
+        let psd = Psd::from_bytes(&mapped)?;
+
+        let width = psd.width();
+        let height = psd.height();
+        // return true for all layers for now, include all layers in the flattened image
+        let filter = &|(_i, _l): (usize, &PsdLayer)| true;
+        let flattened_bytes = psd.flatten_layers_rgba(filter)?;
 
-        // TODO: chunk large images into multiple chunks? with separate focus window to total window?
-        // or really long aspect ratios?
+        let image = DynamicImage::from(RgbaImage::from_raw(width, height, flattened_bytes).unwrap());
 
         let image = image.resize(
             CHUNK_MAX_SIDE,
@@ -291,19 +546,25 @@ async fn chunk_image(path: &Utf8Path, file: &mut File, metadata: &Metadata, out_
         let chunk_filename = format!("{}-{}.{}", IMAGE_CHUNK_CHANNEL, IMAGE_CHUNK_SEQUENCE_ID,
             IMAGE_CHUNK_EXTENSION);
         let chunkfile_path = out_dir_clone.join(chunk_filename);
+        unlink_chunkfile(&chunkfile_path)?;
         image.save_with_format(&chunkfile_path, ImageFormat::WebP)?;
         
         Ok::<Vec<ChunkFile>, anyhow::Error>(vec![ChunkFile {
             original_file: path_clone,
             chunk_channel: IMAGE_CHUNK_CHANNEL.to_owned(),
             chunk_sequence_id: IMAGE_CHUNK_SEQUENCE_ID,
+            original_file_id,
             chunkfile: chunkfile_path,
             chunk_type: ChunkType::Image,
             chunk_length: IMAGE_CHUNK_LENGTH,
             original_file_creation_date: file_creation,
             original_file_modified_date: file_modification,
             original_file_size: file_length,
+            original_file_fingerprint,
             original_file_tags: Map::new(),
+            language: UNDETERMINED_LANGUAGE.to_owned(),
+            user_tags: vec![],
+            sequence_number,
         }])
     }).await // this is Result<Result<vec, closure_error>, tokio::task_error>
     .map_err(|e| IndexProviderError {
@@ -324,7 +585,8 @@ async fn chunk_image(path: &Utf8Path, file: &mut File, metadata: &Metadata, out_
     Ok(chunk_files)
 }
 
-async fn chunk_psd(path: &Utf8Path, file: &mut File, metadata: &Metadata, out_dir: &Utf8Path)
+#[instrument(name = "chunk", level = "debug", skip_all, fields(path = %path))]
+async fn chunk_os_thumbnail(path: &Utf8Path, metadata: &Metadata, out_dir: &Utf8Path, sequence_number: u64)
     -> Result<Vec<ChunkFile>, IndexProviderError>
 {
     let file_creation: DateTime<Utc> = DateTime::from(metadata.created()
@@ -332,50 +594,50 @@ async fn chunk_psd(path: &Utf8Path, file: &mut File, metadata: &Metadata, out_di
     let file_modification: DateTime<Utc> = DateTime::from(metadata.modified()
         .expect("Modified date not available on platform"));
     let file_length = metadata.len();
-    let mut file_bytes: Vec<u8> = Vec::with_capacity(file_length as usize);
-    file.read_to_end(&mut file_bytes).await.map_err(|e| IndexProviderError {
-        provider_name: PROVIDER_NAME.to_string(),
-        r#type: IndexProviderErrorType::IO {
-            path: path.to_string(),
-            source: e.into(),
-        }
-    })?;
 
+    let image = os_thumbnail(path, CHUNK_MAX_SIDE).await
+        .map_err(|e| IndexProviderError {
+            provider_name: PROVIDER_NAME.to_string(),
+            r#type: IndexProviderErrorType::Chunking {
+                path: path.to_string(),
+                source: e.into(),
+            },
+        })?
+        .ok_or_else(|| IndexProviderError {
+            provider_name: PROVIDER_NAME.to_string(),
+            r#type: IndexProviderErrorType::Chunking {
+                path: path.to_string(),
+                source: anyhow::Error::msg("OS could not generate a thumbnail for the file"),
+            },
+        })?;
+
+    let original_file_id = volumes::file_id(path);
+    let original_file_fingerprint = dupes::content_fingerprint(path);
     let path_clone = path.to_owned();
     let out_dir_clone = out_dir.to_owned();
     let chunk_files = task::spawn_blocking(move || {
-        let psd = Psd::from_bytes(&file_bytes)?;
-
-        let width = psd.width();
-        let height = psd.height();
-        // return true for all layers for now, include all layers in the flattened image
-        let filter = &|(_i, _l): (usize, &PsdLayer)| true;
-        let flattened_bytes = psd.flatten_layers_rgba(filter)?;
-
-        let image = DynamicImage::from(RgbaImage::from_raw(width, height, flattened_bytes).unwrap());
-
-        let image = image.resize(
-            CHUNK_MAX_SIDE,
-            CHUNK_MAX_SIDE,
-            FilterType::Triangle,
-        );
-
         let chunk_filename = format!("{}-{}.{}", IMAGE_CHUNK_CHANNEL, IMAGE_CHUNK_SEQUENCE_ID,
             IMAGE_CHUNK_EXTENSION);
         let chunkfile_path = out_dir_clone.join(chunk_filename);
+        unlink_chunkfile(&chunkfile_path)?;
         image.save_with_format(&chunkfile_path, ImageFormat::WebP)?;
-        
+
         Ok::<Vec<ChunkFile>, anyhow::Error>(vec![ChunkFile {
             original_file: path_clone,
             chunk_channel: IMAGE_CHUNK_CHANNEL.to_owned(),
             chunk_sequence_id: IMAGE_CHUNK_SEQUENCE_ID,
+            original_file_id,
             chunkfile: chunkfile_path,
             chunk_type: ChunkType::Image,
             chunk_length: IMAGE_CHUNK_LENGTH,
             original_file_creation_date: file_creation,
             original_file_modified_date: file_modification,
             original_file_size: file_length,
+            original_file_fingerprint,
             original_file_tags: Map::new(),
+            language: UNDETERMINED_LANGUAGE.to_owned(),
+            user_tags: vec![],
+            sequence_number,
         }])
     }).await // this is Result<Result<vec, closure_error>, tokio::task_error>
     .map_err(|e| IndexProviderError {
@@ -394,4 +656,52 @@ async fn chunk_psd(path: &Utf8Path, file: &mut File, metadata: &Metadata, out_di
     })?;
 
     Ok(chunk_files)
-}
\ No newline at end of file
+}
+
+/// Decodes the image at the path, streaming it from disk. JPEGs larger than the chunk are decoded at a reduced
+/// size directly, other images at their full size as long as they fit in MAX_DECODE_BYTES.
+fn decode_image(os_path: &Path) -> Result<DynamicImage, anyhow::Error> {
+    let mut reader = ImageReader::open(os_path)?.with_guessed_format()?;
+    if reader.format() == Some(ImageFormat::Jpeg) {
+        // JPEGs can be decoded at 1/2, 1/4 or 1/8 of their size, the smallest that is still larger than the chunk
+        // is picked
+        let mut decoder = jpeg_decoder::Decoder::new(BufReader::new(std::fs::File::open(os_path)?));
+        decoder.scale(CHUNK_MAX_SIDE as u16, CHUNK_MAX_SIDE as u16)?;
+        let pixels = decoder.decode()?;
+        let info = decoder.info().expect("JPEG info should be read once it is decoded");
+        let (width, height) = (info.width as u32, info.height as u32);
+        let image = match info.pixel_format {
+            PixelFormat::RGB24 => RgbImage::from_raw(width, height, pixels).map(DynamicImage::from),
+            PixelFormat::L8 => GrayImage::from_raw(width, height, pixels).map(DynamicImage::from),
+            // CMYK and 16 bit JPEGs are left to the image crate, at their full size
+            _ => None,
+        };
+        if let Some(image) = image {
+            return Ok(image);
+        }
+    }
+
+    let mut limits = Limits::default();
+    limits.max_alloc = Some(MAX_DECODE_BYTES);
+    reader.limits(limits);
+    Ok(reader.decode()?)
+}
+
+/// The error of an image that would not fit in MAX_DECODE_BYTES once decoded. Also reported by the sandbox when
+/// its worker found the image too large, so the image is still indexed through the OS thumbnail.
+pub(crate) fn too_large_to_decode() -> ImageError {
+    ImageError::Limits(LimitError::from_kind(LimitErrorKind::InsufficientMemory))
+}
+
+/// Whether chunking failed because the image would not fit in MAX_DECODE_BYTES once decoded
+fn is_too_large_to_decode(e: &IndexProviderError) -> bool {
+    match &e.r#type {
+        IndexProviderErrorType::Chunking { source, .. } => is_too_large_to_decode_error(source),
+        _ => false,
+    }
+}
+
+/// Whether the chunking error is from an image that would not fit in MAX_DECODE_BYTES once decoded
+pub(crate) fn is_too_large_to_decode_error(e: &anyhow::Error) -> bool {
+    e.downcast_ref::<ImageError>().is_some_and(|e| matches!(e, ImageError::Limits(_)))
+}
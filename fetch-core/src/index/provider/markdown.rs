@@ -0,0 +1,681 @@
+use std::{collections::{BTreeSet, HashMap, HashSet}, fs::Metadata, sync::{Arc, Mutex}};
+
+use async_trait::async_trait;
+use camino::{Utf8Path, Utf8PathBuf};
+use chrono::{DateTime, Utc};
+use serde_json::{Map, Value};
+use tokio::task;
+use tracing::{debug, info, instrument, warn};
+
+use crate::{files::{dupes, notes::NoteLinks, os_path::{from_os_path, to_os_path}, tags::normalize_tag, volumes}, index::{ChunkFile, ChunkType, embedding::embeddinggemma::{self, EmbeddingGemmaEmbeddedChunkFile}, language, provider::{ChunkQueryFilters, ChunkQueryResult, ChunkingIndexProvider, IndexProviderError, IndexProviderErrorType, IndexedFile, chunkfile_vocabulary, clear_chunkfiles, create_chunkfile_dir, directory_prefix, indexed_file_of, indexed_paths_by_content_of, query_related_chunks, rename_chunks, unlink_chunkfile, registry::{CostClass, ProviderCapabilities}, score::ScoreNormalizer}}, sequence, store::{ChunkStore, Filter, FilterRelation, FilterValue, KeyedSequencedData}};
+
+/// Index provider for markdown notes, aware of note vaults such as Obsidian's. The frontmatter tags of each note
+/// are stored as tags of its chunks, so they can be filtered on like user tags, and the [[wikilinks]] of each note
+/// are resolved within its vault and recorded (see NoteLinks). Queries return the notes closely linked to the notes
+/// they match, in either direction, with a lower score.
+pub struct MarkdownIndexProvider {
+    text_store: Arc<dyn ChunkStore<EmbeddingGemmaEmbeddedChunkFile>>,
+    hybrid_search: bool,
+}
+
+impl MarkdownIndexProvider {
+    pub fn using(text_store: Arc<dyn ChunkStore<EmbeddingGemmaEmbeddedChunkFile>>) -> Self {
+        MarkdownIndexProvider { text_store, hybrid_search: false }
+    }
+
+    /// Matches the words of queries with full text search along with their meaning, and adds up the scores of both,
+    /// see store::FusionWeights. Queries only match by meaning by default.
+    pub fn with_hybrid_search(mut self, enabled: bool) -> Self {
+        self.hybrid_search = enabled;
+        self
+    }
+
+    /// Notes linked with the best scoring of the given notes, scored by the score of the note they are linked
+    /// with. The given notes themselves, and notes that do not match the filters, are left out.
+    async fn linked_notes(&self, note_scores: &HashMap<&Utf8Path, f32>, filters: &ChunkQueryFilters)
+        -> Result<Vec<ChunkQueryResult>, IndexProviderError>
+    {
+        let note_links = match NoteLinks::load() {
+            Ok(note_links) => note_links,
+            Err(e) => {
+                warn!("Markdown Index Provider: Could not load note links, returning no linked notes: {:?}", e);
+                return Ok(vec![]);
+            },
+        };
+
+        let mut notes: Vec<_> = note_scores.iter().collect();
+        notes.sort_by(|a, b| b.1.total_cmp(a.1));
+
+        let mut neighbor_scores: HashMap<Utf8PathBuf, f32> = HashMap::new();
+        for (note, score) in notes.into_iter().take(LINKED_NOTE_SOURCES) {
+            for neighbor in note_links.neighbors(note) {
+                if note_scores.contains_key(neighbor.as_path()) {
+                    continue;
+                }
+                let neighbor_score = neighbor_scores.entry(neighbor).or_insert(0.0);
+                *neighbor_score = neighbor_score.max(score * LINKED_NOTE_SCORE_FACTOR);
+            }
+        }
+
+        let mut linked = vec![];
+        for (neighbor, score) in neighbor_scores {
+            let mut neighbor_filters = filters.store_filters();
+            neighbor_filters.push(Filter {
+                attribute: ChunkFile::ORIGINAL_FILE_ATTR,
+                filter: FilterValue::String(neighbor.as_str()),
+                relation: FilterRelation::Eq,
+            });
+            let chunk = self.text_store.query_filter_n(&neighbor_filters, 1, 0).await
+                .map_err(|e| IndexProviderError {
+                    provider_name: PROVIDER_NAME.to_string(),
+                    r#type: IndexProviderErrorType::Store {
+                        operation: "query filter",
+                        source: e.into(),
+                    }
+                })?;
+            if let Some(chunk) = chunk.into_iter().next() {
+                debug!("Markdown Index Provider: Adding linked note: {}, score: {}", neighbor, score);
+                linked.push(ChunkQueryResult::new(chunk.chunkfile, score));
+            }
+        }
+        Ok(linked)
+    }
+}
+
+#[async_trait]
+impl ChunkingIndexProvider for MarkdownIndexProvider {
+    fn provider_name(&self) -> &'static str {
+        PROVIDER_NAME
+    }
+
+    fn provides_indexing_for_extension(&self, ext: &str) -> bool {
+        EXTENSIONS.contains(&ext)
+    }
+
+    fn capabilities(&self) -> ProviderCapabilities {
+        // Markdown is plain text, so it cannot be sniffed and is routed by extension
+        ProviderCapabilities { mime_types: vec!["text/markdown"], cost: CostClass::Moderate }
+    }
+
+    async fn index(&self, path: &Utf8Path, opt_modified: Option<DateTime<Utc>>) -> Result<(), IndexProviderError> {
+        debug!("Markdown Index Provider: Indexing file at path: {}", path);
+        let contents = tokio::fs::read_to_string(to_os_path(path)).await
+            .map_err(|e| IndexProviderError {
+                provider_name: PROVIDER_NAME.to_string(),
+                r#type: IndexProviderErrorType::IO {
+                    path: path.to_string(),
+                    source: e.into(),
+                },
+            })?;
+        let metadata = tokio::fs::metadata(to_os_path(path)).await
+            .map_err(|e| IndexProviderError {
+                provider_name: PROVIDER_NAME.to_string(),
+                r#type: IndexProviderErrorType::IO {
+                    path: path.to_string(),
+                    source: e.into(),
+                },
+            })?;
+
+        // If the store has indexed chunks for this file, then check the stored original_file_modified_date to
+        // make sure it comes before the current file's modified date. If so, then make sure to clear the previously
+        // stored chunks from the store before proceeding.
+        let discover_filter = &[Filter {
+            attribute: ChunkFile::ORIGINAL_FILE_ATTR,
+            filter: FilterValue::String(path.as_str()),
+            relation: FilterRelation::Eq,
+        }];
+        let discovered_chunk = self.text_store.query_filter_n(discover_filter, 1, 0).await
+            .map_err(|e| IndexProviderError {
+                provider_name: PROVIDER_NAME.to_string(),
+                r#type: IndexProviderErrorType::Store {
+                    operation: "query filter",
+                    source: e.into(),
+                }
+            })?
+            .into_iter().map(|ec| ec.chunkfile).next();
+
+        if let Some(discovered_chunk) = discovered_chunk {
+            sequence::observe(discovered_chunk.sequence_number);
+            let last_modified: DateTime<Utc> = opt_modified.unwrap_or(DateTime::from(metadata.modified()
+                .expect("File modified datetime not available on this platform")));
+            let stored_modified = discovered_chunk.original_file_modified_date;
+            if last_modified.timestamp_millis() <= stored_modified.timestamp_millis() {
+                info!("Attempted indexing on file: {} but the stored modified_date ({}) was equal to or later than the \
+                    file's modified_date ({}). Ignoring.", path, stored_modified, last_modified);
+                return Ok(());
+            }
+
+            self.clear(path, Some(last_modified)).await?;
+        }
+
+        let sequence_number = sequence::next();
+
+        let chunk_out_dir = create_chunkfile_dir(path).await
+            .map_err(|e| IndexProviderError {
+                provider_name: PROVIDER_NAME.to_string(),
+                r#type: IndexProviderErrorType::IO {
+                    path: path.to_string(),
+                    source: e.into(),
+                }
+            })?;
+
+        debug!("Markdown Index Provider: Chunking file at path: {} to out_dir: {}", path, chunk_out_dir);
+        let (chunkfiles, links) = chunk_note(path, contents, metadata, &chunk_out_dir, sequence_number).await
+            .map_err(|e| IndexProviderError {
+                provider_name: PROVIDER_NAME.to_owned(),
+                r#type: IndexProviderErrorType::Chunking {
+                    path: path.to_string(),
+                    source: e,
+                }
+            })?;
+
+        debug!("Markdown Index Provider: Embedding chunks at dir: {}", chunk_out_dir);
+        let mut embedded_chunkfiles = vec![];
+        for chunkfile in chunkfiles {
+            debug!("Markdown Index Provider: Embedding chunk with id: {}", chunkfile.get_key());
+            embedded_chunkfiles.push(embeddinggemma::embed_chunk(chunkfile).await
+                .map_err(|e| IndexProviderError {
+                    provider_name: PROVIDER_NAME.to_string(),
+                    r#type: IndexProviderErrorType::Embedding { source: e },
+                })?);
+        }
+
+        debug!("Markdown Index Provider: Storing chunks and embeddings for path: {}", path);
+        self.text_store.put(embedded_chunkfiles).await
+            .map_err(|e| IndexProviderError {
+                provider_name: PROVIDER_NAME.to_string(),
+                r#type: IndexProviderErrorType::Store {
+                    operation: "put",
+                    source: e.into(),
+                }
+            })?;
+
+        record_links(path, links);
+        Ok(())
+    }
+
+    async fn clear(&self, path: &Utf8Path, opt_modified: Option<DateTime<Utc>>) -> Result<(), IndexProviderError> {
+        debug!("Markdown Index Provider: Clearing index of path: {}", path);
+        clear_chunkfiles(path).await.map_err(|e| IndexProviderError {
+            provider_name: PROVIDER_NAME.to_string(),
+            r#type: IndexProviderErrorType::IO { path: path.to_string(), source: e.into() }
+        })?;
+
+        let mut filters = vec![Filter {
+            attribute: ChunkFile::ORIGINAL_FILE_ATTR,
+            filter: FilterValue::String(path.as_str()),
+            relation: FilterRelation::Eq,
+        }];
+        if let Some(modified_dt) = &opt_modified {
+            filters.push(Filter {
+                attribute: ChunkFile::FILE_MODIFIED_DATE_ATTR,
+                filter: FilterValue::DateTime(modified_dt),
+                relation: FilterRelation::Eq,
+            });
+        }
+        self.text_store.clear_filter(&filters).await.map_err(|e| IndexProviderError {
+            provider_name: PROVIDER_NAME.to_string(),
+            r#type: IndexProviderErrorType::Store {
+                operation: "clear filter",
+                source: e.into(),
+            }
+        })?;
+
+        record_links(path, BTreeSet::new());
+        Ok(())
+    }
+
+    async fn restore(&self, path: &Utf8Path) -> Result<u64, IndexProviderError> {
+        debug!("Markdown Index Provider: Restoring cleared chunks under path: {}", path);
+        let prefix = directory_prefix(path);
+        let mut restored = 0;
+        for filters in [
+            [Filter { attribute: ChunkFile::ORIGINAL_FILE_ATTR, filter: FilterValue::String(path.as_str()), relation: FilterRelation::Eq }],
+            [Filter { attribute: ChunkFile::ORIGINAL_FILE_ATTR, filter: FilterValue::String(&prefix), relation: FilterRelation::StartsWith }],
+        ] {
+            let filter_restored = self.text_store.restore_filter(&filters).await.map_err(|e| IndexProviderError {
+                provider_name: PROVIDER_NAME.to_string(),
+                r#type: IndexProviderErrorType::Store {
+                    operation: "restore filter",
+                    source: e.into(),
+                }
+            })?;
+            if filter_restored == 0 {
+                continue;
+            }
+            restored += filter_restored;
+
+            // Links were forgotten when the notes were cleared, record them again from the restored chunks
+            let chunks = self.text_store.query_filter(&filters).await.map_err(|e| IndexProviderError {
+                provider_name: PROVIDER_NAME.to_string(),
+                r#type: IndexProviderErrorType::Store {
+                    operation: "query filter",
+                    source: e.into(),
+                }
+            })?;
+            let mut seen_files = HashSet::new();
+            for chunk in chunks.iter().filter(|c| seen_files.insert(c.chunkfile.original_file.clone())) {
+                record_links(&chunk.chunkfile.original_file, stored_links(&chunk.chunkfile));
+            }
+        }
+        Ok(restored)
+    }
+
+    async fn tag(&self, path: &Utf8Path, tags: &[String]) -> Result<(), IndexProviderError> {
+        debug!("Markdown Index Provider: Tagging chunks of path: {} with tags: {:?}", path, tags);
+        let filters = &[Filter {
+            attribute: ChunkFile::ORIGINAL_FILE_ATTR,
+            filter: FilterValue::String(path.as_str()),
+            relation: FilterRelation::Eq,
+        }];
+        let mut chunks = self.text_store.query_filter(filters).await.map_err(|e| IndexProviderError {
+            provider_name: PROVIDER_NAME.to_string(),
+            r#type: IndexProviderErrorType::Store {
+                operation: "query filter",
+                source: e.into(),
+            }
+        })?;
+        if chunks.is_empty() {
+            return Ok(());
+        }
+
+        // The frontmatter tags of the note are kept alongside the tags the user has added
+        for chunk in &mut chunks {
+            let mut user_tags: BTreeSet<String> = stored_string_list(&chunk.chunkfile, FRONTMATTER_TAGS_KEY)
+                .into_iter()
+                .collect();
+            user_tags.extend(tags.iter().cloned());
+            chunk.chunkfile.user_tags = user_tags.into_iter().collect();
+        }
+        self.text_store.put(chunks).await.map_err(|e| IndexProviderError {
+            provider_name: PROVIDER_NAME.to_string(),
+            r#type: IndexProviderErrorType::Store {
+                operation: "put",
+                source: e.into(),
+            }
+        })?;
+
+        Ok(())
+    }
+
+    async fn rename(&self, from: &Utf8Path, to: &Utf8Path) -> Result<u64, IndexProviderError> {
+        debug!("Markdown Index Provider: Renaming chunks of path: {} to: {}", from, to);
+        rename_chunks(self.text_store.as_ref(), from, to, PROVIDER_NAME, |c: &mut EmbeddingGemmaEmbeddedChunkFile| &mut c.chunkfile).await
+    }
+
+    async fn indexed_file(&self, file_id: &str) -> Result<Option<IndexedFile>, IndexProviderError> {
+        indexed_file_of(self.text_store.as_ref(), file_id, PROVIDER_NAME, |c: EmbeddingGemmaEmbeddedChunkFile| c.chunkfile).await
+    }
+
+    async fn indexed_paths_by_content(&self, fingerprint: &str) -> Result<Vec<Utf8PathBuf>, IndexProviderError> {
+        indexed_paths_by_content_of(self.text_store.as_ref(), fingerprint, PROVIDER_NAME, |c: EmbeddingGemmaEmbeddedChunkFile| c.chunkfile).await
+    }
+
+    async fn query_n(&self, str: &str, filters: &ChunkQueryFilters, num_results: u32, offset: u32)
+        -> Result<Vec<ChunkQueryResult>, IndexProviderError>
+    {
+        debug!("Markdown Index Provider: Querying index of with params: {}, filters: {:?}, \
+            num_results: {}, offset: {}", str, filters, num_results, offset);
+        let text_vec = embeddinggemma::embed_query(str).await.map_err(|e| IndexProviderError {
+            provider_name: PROVIDER_NAME.to_string(),
+            r#type: IndexProviderErrorType::Embedding { source: e },
+        })?;
+
+        let chunks = self.text_store.query_full_n(
+            Some(text_vec),
+            self.hybrid_search.then_some(str),
+            &filters.store_filters(),
+            num_results,
+            offset
+        ).await.map_err(|e| IndexProviderError {
+            provider_name: PROVIDER_NAME.to_string(),
+            r#type: IndexProviderErrorType::Store {
+                operation: "query full",
+                source: e,
+            }
+        })?;
+
+        let mut results = ScoreNormalizer::for_provider(PROVIDER_NAME).normalize_all(chunks.into_iter().map(|c| (c.score, c.result.chunkfile)));
+
+        let mut note_scores: HashMap<&Utf8Path, f32> = HashMap::new();
+        for result in &results {
+            let score = note_scores.entry(&result.chunkfile().original_file).or_insert(0.0);
+            *score = score.max(result.score());
+        }
+        let linked = self.linked_notes(&note_scores, filters).await?;
+        results.extend(linked);
+        Ok(results)
+    }
+
+    async fn query_related_n(&self, path: &Utf8Path, num_results: u32)
+        -> Result<Vec<ChunkQueryResult>, IndexProviderError>
+    {
+        debug!("Markdown Index Provider: Querying files related to path: {}, num_results: {}", path, num_results);
+        let mut results = query_related_chunks(self.text_store.as_ref(), path, num_results, PROVIDER_NAME,
+            |c: EmbeddingGemmaEmbeddedChunkFile| (c.chunkfile, c.embedding)).await?;
+
+        // Notes linked with the note are related to it, however different their contents are
+        let note_scores = HashMap::from([(path, LINKED_RELATED_SCORE)]);
+        results.extend(self.linked_notes(&note_scores, &ChunkQueryFilters::default()).await?);
+        Ok(results)
+    }
+
+    async fn vocabulary(&self) -> Result<Vec<String>, IndexProviderError> {
+        debug!("Markdown Index Provider: Collecting vocabulary of indexed files");
+        let chunks = self.text_store.query_filter(&[]).await.map_err(|e| IndexProviderError {
+            provider_name: PROVIDER_NAME.to_string(),
+            r#type: IndexProviderErrorType::Store {
+                operation: "query by filter",
+                source: e.into(),
+            }
+        })?;
+
+        let mut seen_files = HashSet::new();
+        Ok(chunks.iter()
+            .map(|c| &c.chunkfile)
+            .filter(|c| seen_files.insert(c.original_file.clone()))
+            .flat_map(chunkfile_vocabulary)
+            .collect())
+    }
+}
+
+// private constants and functions
+
+const PROVIDER_NAME: &str = "MarkdownIndexProvider";
+const EXTENSIONS: [&str; 2] = ["md", "markdown"];
+
+const TEXT_CHUNK_CHANNEL: &str = "text";
+const TEXT_CHUNK_MAX_TOKENS: u32 = 1000;
+
+/// Keys of the original file tags of each chunk that hold the frontmatter tags and the resolved links of the note
+const FRONTMATTER_TAGS_KEY: &str = "frontmatter_tags";
+const LINKS_KEY: &str = "links";
+/// Directory marking the root of an Obsidian vault
+const VAULT_MARKER_DIR: &str = ".obsidian";
+
+/// Number of best scoring notes of a query whose linked notes are returned along with them
+const LINKED_NOTE_SOURCES: usize = 5;
+/// Linked notes are scored this much of the score of the note they are linked with
+const LINKED_NOTE_SCORE_FACTOR: f32 = 0.5;
+/// Score of the notes linked with a note, before LINKED_NOTE_SCORE_FACTOR, when querying related files
+const LINKED_RELATED_SCORE: f32 = 100.0;
+
+
+/// Serializes updates of the note links from concurrent indexing jobs, which would otherwise overwrite each other
+static NOTE_LINKS_LOCK: Mutex<()> = Mutex::new(());
+
+/// Records the notes a note links to. Failures are logged, as the chunks of the note are indexed either way.
+fn record_links(note: &Utf8Path, links: BTreeSet<Utf8PathBuf>) {
+    let _guard = NOTE_LINKS_LOCK.lock().expect("Note links lock should not be poisoned");
+    let res = NoteLinks::load().and_then(|mut note_links| {
+        if note_links.set(note, links) {
+            note_links.save()?;
+        }
+        Ok(())
+    });
+    if let Err(e) = res {
+        warn!("Markdown Index Provider: Could not record links of note {}: {:?}", note, e);
+    }
+}
+
+fn stored_string_list(chunkfile: &ChunkFile, key: &str) -> Vec<String> {
+    chunkfile.original_file_tags.get(key)
+        .and_then(Value::as_array)
+        .map(|values| values.iter().filter_map(|v| v.as_str().map(str::to_owned)).collect())
+        .unwrap_or_default()
+}
+
+fn stored_links(chunkfile: &ChunkFile) -> BTreeSet<Utf8PathBuf> {
+    stored_string_list(chunkfile, LINKS_KEY).into_iter().map(Utf8PathBuf::from).collect()
+}
+
+/// Chunks the note, returning its chunks and the notes it links to
+#[instrument(name = "chunk", level = "debug", skip_all, fields(path = %path))]
+async fn chunk_note(path: &Utf8Path, contents: String, metadata: Metadata, out_dir: &Utf8Path, sequence_number: u64)
+    -> Result<(Vec<ChunkFile>, BTreeSet<Utf8PathBuf>), anyhow::Error>
+{
+    let file_creation: DateTime<Utc> = DateTime::from(metadata.created()
+        .expect("File creation datetime not available on this platform"));
+    let file_modified: DateTime<Utc> = DateTime::from(metadata.modified()
+        .expect("File modified datetime not available on this platform"));
+    let file_length = metadata.len();
+    let original_file_id = volumes::file_id(path);
+    let original_file_fingerprint = dupes::content_fingerprint(path);
+
+    let path = path.to_owned();
+    let out_dir = out_dir.to_owned();
+    // Resolving links looks through the vault, so this runs on a blocking thread
+    task::spawn_blocking(move || {
+        let (frontmatter, body) = split_frontmatter(&contents);
+        let (mut file_tags, frontmatter_tags) = parse_frontmatter(frontmatter);
+
+        let vault = find_vault_root(&path);
+        let notes_by_name = vault_notes_by_name(&vault);
+        let links: BTreeSet<Utf8PathBuf> = wikilink_targets(body).iter()
+            .filter_map(|target| resolve_wikilink(&vault, &notes_by_name, target))
+            .filter(|target| *target != path)
+            .collect();
+
+        file_tags.insert(FRONTMATTER_TAGS_KEY.to_owned(), frontmatter_tags.clone().into());
+        file_tags.insert(LINKS_KEY.to_owned(), links.iter().map(|l| l.to_string()).collect::<Vec<_>>().into());
+
+        let sections = chunk_sections(&render_wikilinks(body));
+        let num_sections = sections.len();
+        let mut chunkfiles = vec![];
+        for (i, section) in sections.into_iter().enumerate() {
+            let chunk_sequence = i as f32;
+            let chunkfile = out_dir.join(format!("{}-{}.txt", TEXT_CHUNK_CHANNEL, chunk_sequence));
+            unlink_chunkfile(&chunkfile)?;
+            std::fs::write(&chunkfile, &section)?;
+
+            let mut tags_map = file_tags.clone();
+            tags_map.insert("full_text".to_string(), section.clone().into());
+
+            chunkfiles.push(ChunkFile {
+                original_file: path.clone(),
+                chunk_channel: TEXT_CHUNK_CHANNEL.to_owned(),
+                chunk_sequence_id: chunk_sequence,
+                original_file_id: original_file_id.clone(),
+                chunkfile,
+                chunk_type: ChunkType::Text,
+                chunk_length: 1.0 / num_sections as f32,
+                original_file_creation_date: file_creation,
+                original_file_modified_date: file_modified,
+                original_file_size: file_length,
+                original_file_fingerprint: original_file_fingerprint.clone(),
+                original_file_tags: tags_map,
+                language: language::detect_language(&section).to_owned(),
+                user_tags: frontmatter_tags.clone(),
+                sequence_number,
+            });
+        }
+
+        Ok::<_, anyhow::Error>((chunkfiles, links))
+    }).await? // this is Result<Result<_, closure_error>, tokio::task_error>
+}
+
+/// Splits a note into its YAML frontmatter, between --- lines at the start of the note, and its body
+fn split_frontmatter(contents: &str) -> (&str, &str) {
+    let contents = contents.strip_prefix('\u{feff}').unwrap_or(contents);
+    let Some(rest) = contents.strip_prefix("---\n").or_else(|| contents.strip_prefix("---\r\n")) else {
+        return ("", contents);
+    };
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        if line.trim_end() == "---" {
+            return (&rest[..offset], &rest[offset + line.len()..]);
+        }
+        offset += line.len();
+    }
+    // The frontmatter is never closed, so this is not frontmatter
+    ("", contents)
+}
+
+/// Reads the frontmatter of a note, returning its scalar values by key and its normalized tags. Only the parts of
+/// YAML used by note apps are understood: `key: value` pairs, and tags given inline (`tags: [a, b]`, `tags: a, b`)
+/// or as a list of `- tag` lines.
+fn parse_frontmatter(frontmatter: &str) -> (Map<String, Value>, Vec<String>) {
+    let mut values = Map::new();
+    let mut tags = BTreeSet::new();
+    let mut in_tag_list = false;
+    for line in frontmatter.lines() {
+        if in_tag_list {
+            if let Some(tag) = line.trim_start().strip_prefix("- ") {
+                tags.extend(normalize_frontmatter_tag(tag));
+                continue;
+            }
+            in_tag_list = false;
+        }
+
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        if line.starts_with([' ', '\t']) {
+            continue;
+        }
+        let (key, value) = (key.trim(), value.trim());
+        if key.eq_ignore_ascii_case("tags") || key.eq_ignore_ascii_case("tag") {
+            if value.is_empty() {
+                in_tag_list = true;
+            } else {
+                let value = value.trim_start_matches('[').trim_end_matches(']');
+                tags.extend(value.split(',').filter_map(normalize_frontmatter_tag));
+            }
+        } else if !value.is_empty() {
+            values.insert(key.to_owned(), value.trim_matches(['"', '\'']).to_owned().into());
+        }
+    }
+    (values, tags.into_iter().collect())
+}
+
+/// Normalizes a frontmatter tag like a user tag. Nested tags (eg. "project/fetch") are joined with hyphens.
+fn normalize_frontmatter_tag(tag: &str) -> Option<String> {
+    let tag = tag.trim().trim_matches(['"', '\'']).trim_start_matches('#').replace(['/', ' '], "-");
+    normalize_tag(&tag)
+}
+
+/// Targets of the [[wikilinks]] of a note body, without their headings, block references and display text, eg.
+/// "Kubernetes" for [[Kubernetes#Pods|pods]]. Embeds (![[...]]) count as links.
+fn wikilink_targets(body: &str) -> Vec<String> {
+    let mut targets = vec![];
+    let mut rest = body;
+    while let Some(start) = rest.find("[[") {
+        rest = &rest[start + 2..];
+        let Some(end) = rest.find("]]") else {
+            break;
+        };
+        let link = &rest[..end];
+        rest = &rest[end + 2..];
+        let target = link.split(['|', '#', '^']).next().unwrap_or("").trim();
+        if !target.is_empty() && !target.contains('\n') {
+            targets.push(target.to_owned());
+        }
+    }
+    targets
+}
+
+/// Replaces the [[wikilinks]] of a note body with the text they are displayed as, so that chunks read naturally
+fn render_wikilinks(body: &str) -> String {
+    let mut rendered = String::with_capacity(body.len());
+    let mut rest = body;
+    while let Some(start) = rest.find("[[") {
+        let Some(end) = rest[start..].find("]]").map(|end| start + end) else {
+            break;
+        };
+        rendered.push_str(rest[..start].trim_end_matches('!'));
+        let link = &rest[start + 2..end];
+        let display = match link.split_once('|') {
+            Some((_, display)) => display,
+            None => link.split(['#', '^']).next().unwrap_or(link),
+        };
+        rendered.push_str(display);
+        rest = &rest[end + 2..];
+    }
+    rendered.push_str(rest);
+    rendered
+}
+
+/// Root of the vault a note belongs to: the nearest directory above it marked as an Obsidian vault, or the
+/// directory of the note if there is none
+fn find_vault_root(note: &Utf8Path) -> Utf8PathBuf {
+    let note_dir = note.parent().unwrap_or(note);
+    note_dir.ancestors()
+        .find(|dir| to_os_path(&dir.join(VAULT_MARKER_DIR)).is_dir())
+        .unwrap_or(note_dir)
+        .to_owned()
+}
+
+/// Every note in the vault, keyed by its lowercased file name without extension. Where names are shared, the note
+/// with the shortest path wins, as Obsidian does. Hidden directories are not looked through.
+fn vault_notes_by_name(vault: &Utf8Path) -> HashMap<String, Utf8PathBuf> {
+    let mut notes_by_name: HashMap<String, Utf8PathBuf> = HashMap::new();
+    let mut dirs = vec![vault.to_owned()];
+    while let Some(dir) = dirs.pop() {
+        let Ok(entries) = std::fs::read_dir(to_os_path(&dir)) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = from_os_path(&entry.path());
+            let Some(name) = path.file_name() else {
+                continue;
+            };
+            if name.starts_with('.') {
+                continue;
+            }
+            match entry.file_type() {
+                Ok(file_type) if file_type.is_dir() => dirs.push(path),
+                Ok(file_type) if file_type.is_file() && path.extension().is_some_and(|e| EXTENSIONS.contains(&e)) => {
+                    let key = path.file_stem().unwrap_or(name).to_lowercase();
+                    match notes_by_name.get(&key) {
+                        Some(existing) if existing.as_str().len() <= path.as_str().len() => (),
+                        _ => {
+                            notes_by_name.insert(key, path);
+                        },
+                    }
+                },
+                _ => (),
+            }
+        }
+    }
+    notes_by_name
+}
+
+/// Resolves the target of a wikilink to a note. Targets with a path (eg. "projects/fetch") are relative to the
+/// vault root, and others are looked up by name anywhere in the vault. Links to notes that do not exist resolve to
+/// nothing.
+fn resolve_wikilink(vault: &Utf8Path, notes_by_name: &HashMap<String, Utf8PathBuf>, target: &str)
+    -> Option<Utf8PathBuf>
+{
+    if target.contains('/') {
+        let target = vault.join(target);
+        return std::iter::once(target.clone())
+            .chain(EXTENSIONS.iter().map(|ext| target.with_extension(ext)))
+            .find(|candidate| to_os_path(candidate).is_file());
+    }
+    let name = target.strip_suffix(".md").unwrap_or(target).to_lowercase();
+    notes_by_name.get(&name).cloned()
+}
+
+/// Splits a note body into chunks at its headings, splitting sections that are too long at blank lines. Sections
+/// without any text are left out.
+fn chunk_sections(body: &str) -> Vec<String> {
+    let mut sections = vec![];
+    let mut current = String::new();
+    let mut in_code_block = false;
+    for line in body.split_inclusive('\n') {
+        if line.trim_start().starts_with("```") {
+            in_code_block = !in_code_block;
+        }
+        let is_heading = !in_code_block && line.starts_with('#') && line.trim_start_matches('#').starts_with(' ');
+        let is_paragraph_break = line.trim().is_empty()
+            && language::estimate_tokens(&current) >= TEXT_CHUNK_MAX_TOKENS;
+        if (is_heading || is_paragraph_break) && !current.trim().is_empty() {
+            sections.push(std::mem::take(&mut current));
+        }
+        current.push_str(line);
+    }
+    if !current.trim().is_empty() {
+        sections.push(current);
+    }
+    sections.into_iter().map(|s| s.trim().to_owned()).collect()
+}
@@ -0,0 +1,529 @@
+use std::{collections::HashSet, fs::Metadata, sync::Arc};
+
+use async_trait::async_trait;
+use base64::{Engine, engine::general_purpose::STANDARD};
+use camino::{Utf8Path, Utf8PathBuf};
+use chrono::{DateTime, Utc};
+use futures::TryFutureExt;
+use image::{ImageFormat, imageops::FilterType};
+use serde_json::{Map, Value};
+use tokio::{join, task};
+use tracing::{debug, info, instrument};
+
+use crate::{files::{dupes, os_path::to_os_path, volumes}, index::{ChunkFile, ChunkType, embedding::{embeddinggemma::{self, EmbeddingGemmaEmbeddedChunkFile}, siglip2::{self, Siglip2EmbeddedChunkFile}}, language::{self, UNDETERMINED_LANGUAGE}, provider::{ChunkQueryFilters, ChunkQueryResult, ChunkingIndexProvider, IndexProviderError, IndexProviderErrorType, IndexedFile, chunkfile_vocabulary, clear_chunkfiles, create_chunkfile_dir, directory_prefix, indexed_file_of, indexed_paths_by_content_of, query_related_chunks, rename_chunks, unlink_chunkfile, registry::{CostClass, ProviderCapabilities}, score::ScoreNormalizer}}, sequence, store::{ChunkStore, Filter, FilterRelation, FilterValue, KeyedSequencedData}};
+
+/// Index provider for Jupyter notebooks. Markdown and code cells are indexed as text chunks, and the images
+/// output by code cells (eg. plots) as image chunks. The chunk sequence of each chunk is the index of its cell,
+/// which is also stored in the chunk tags, so a result can be traced back to the cell it came from.
+pub struct NotebookIndexProvider {
+    text_store: Arc<dyn ChunkStore<EmbeddingGemmaEmbeddedChunkFile>>,
+    image_store: Arc<dyn ChunkStore<Siglip2EmbeddedChunkFile>>,
+    hybrid_search: bool,
+}
+
+impl NotebookIndexProvider {
+    pub fn using(text_store: Arc<dyn ChunkStore<EmbeddingGemmaEmbeddedChunkFile>>, image_store: Arc<dyn ChunkStore<Siglip2EmbeddedChunkFile>>) -> Self {
+        NotebookIndexProvider { text_store, image_store, hybrid_search: false }
+    }
+
+    /// Matches the words of queries with full text search along with their meaning, and adds up the scores of both,
+    /// see store::FusionWeights. Queries only match by meaning by default.
+    pub fn with_hybrid_search(mut self, enabled: bool) -> Self {
+        self.hybrid_search = enabled;
+        self
+    }
+}
+
+#[async_trait]
+impl ChunkingIndexProvider for NotebookIndexProvider {
+    fn provider_name(&self) -> &'static str {
+        PROVIDER_NAME
+    }
+
+    fn provides_indexing_for_extension(&self, ext: &str) -> bool {
+        ext.eq("ipynb")
+    }
+
+    fn capabilities(&self) -> ProviderCapabilities {
+        // Notebooks are JSON, so they cannot be told apart from other JSON by their content
+        ProviderCapabilities { mime_types: vec!["application/x-ipynb+json"], cost: CostClass::Moderate }
+    }
+
+    async fn index(&self, path: &Utf8Path, opt_modified: Option<DateTime<Utc>>) -> Result<(), IndexProviderError> {
+        debug!("Notebook Index Provider: Indexing file at path: {}", path);
+        let contents = tokio::fs::read_to_string(to_os_path(path)).await
+            .map_err(|e| IndexProviderError {
+                provider_name: PROVIDER_NAME.to_string(),
+                r#type: IndexProviderErrorType::IO {
+                    path: path.to_string(),
+                    source: e.into(),
+                },
+            })?;
+        let metadata = tokio::fs::metadata(to_os_path(path)).await
+            .map_err(|e| IndexProviderError {
+                provider_name: PROVIDER_NAME.to_string(),
+                r#type: IndexProviderErrorType::IO {
+                    path: path.to_string(),
+                    source: e.into(),
+                },
+            })?;
+
+        // If the store has indexed chunks for this file, then check the stored original_file_modified_date to
+        // make sure it comes before the current file's modified date. If so, then make sure to clear the previously
+        // stored chunks from the store before proceeding.
+        let discover_filter = &[Filter {
+            attribute: ChunkFile::ORIGINAL_FILE_ATTR,
+            filter: FilterValue::String(path.as_str()),
+            relation: FilterRelation::Eq,
+        }];
+        let discovered_chunks: (Option<ChunkFile>, Option<ChunkFile>) = futures::try_join!(
+            self.text_store.query_filter_n(discover_filter, 1, 0)
+                .map_ok(|vec| vec.into_iter().map(|ec| ec.chunkfile).next()),
+            self.image_store.query_filter_n(discover_filter, 1, 0)
+                .map_ok(|vec| vec.into_iter().map(|ec| ec.chunkfile).next()),
+        ).map_err(|e| IndexProviderError {
+            provider_name: PROVIDER_NAME.to_string(),
+            r#type: IndexProviderErrorType::Store {
+                operation: "query filter",
+                source: e.into(),
+            }
+        })?;
+
+        if let Some(discovered_chunk) = discovered_chunks.0.or(discovered_chunks.1) {
+            sequence::observe(discovered_chunk.sequence_number);
+            let last_modified: DateTime<Utc> = opt_modified.unwrap_or(DateTime::from(metadata.modified()
+                .expect("File modified datetime not available on this platform")));
+            let stored_modified = discovered_chunk.original_file_modified_date;
+            if last_modified.timestamp_millis() <= stored_modified.timestamp_millis() {
+                info!("Attempted indexing on file: {} but the stored modified_date ({}) was equal to or later than the \
+                    file's modified_date ({}). Ignoring.", path, stored_modified, last_modified);
+                return Ok(());
+            }
+
+            self.clear(path, Some(last_modified)).await?;
+        }
+
+        let sequence_number = sequence::next();
+
+        let chunk_out_dir = create_chunkfile_dir(path).await
+            .map_err(|e| IndexProviderError {
+                provider_name: PROVIDER_NAME.to_string(),
+                r#type: IndexProviderErrorType::IO {
+                    path: path.to_string(),
+                    source: e.into(),
+                }
+            })?;
+
+        debug!("Notebook Index Provider: Chunking file at path: {} to out_dir: {}", path, chunk_out_dir);
+        let chunkfiles = chunk_notebook(path, contents, metadata, &chunk_out_dir, sequence_number).await
+            .map_err(|e| IndexProviderError {
+                provider_name: PROVIDER_NAME.to_owned(),
+                r#type: IndexProviderErrorType::Chunking {
+                    path: path.to_string(),
+                    source: e,
+                }
+            })?;
+
+        debug!("Notebook Index Provider: Embedding chunks at dir: {}", chunk_out_dir);
+        let mut embedded_text_chunkfiles = vec![];
+        let mut embedded_image_chunkfiles = vec![];
+        for chunkfile in chunkfiles {
+            debug!("Notebook Index Provider: Embedding chunk with id: {}", chunkfile.get_key());
+            match chunkfile.chunk_type {
+                ChunkType::Text => {
+                    embedded_text_chunkfiles.push(embeddinggemma::embed_chunk(chunkfile).await
+                        .map_err(|e| IndexProviderError {
+                            provider_name: PROVIDER_NAME.to_string(),
+                            r#type: IndexProviderErrorType::Embedding { source: e },
+                        })?);
+                },
+                ChunkType::Image => {
+                    embedded_image_chunkfiles.push(siglip2::embed_chunk(chunkfile).await
+                        .map_err(|e| IndexProviderError {
+                            provider_name: PROVIDER_NAME.to_string(),
+                            r#type: IndexProviderErrorType::Embedding { source: e },
+                        })?);
+                }
+                _ => unreachable!("Notebook chunker should only produce text and image chunks"),
+            }
+        }
+
+        debug!("Notebook Index Provider: Storing chunks and embeddings for path: {}", path);
+        futures::try_join!(
+            self.text_store.put(embedded_text_chunkfiles),
+            self.image_store.put(embedded_image_chunkfiles),
+        ).map_err(|e| IndexProviderError {
+            provider_name: PROVIDER_NAME.to_string(),
+            r#type: IndexProviderErrorType::Store {
+                operation: "put",
+                source: e.into(),
+            }
+        })?;
+
+        Ok(())
+    }
+
+    async fn clear(&self, path: &Utf8Path, opt_modified: Option<DateTime<Utc>>) -> Result<(), IndexProviderError> {
+        debug!("Notebook Index Provider: Clearing index of path: {}", path);
+        clear_chunkfiles(path).await.map_err(|e| IndexProviderError {
+            provider_name: PROVIDER_NAME.to_string(),
+            r#type: IndexProviderErrorType::IO { path: path.to_string(), source: e.into() }
+        })?;
+
+        let mut filters = vec![Filter {
+            attribute: ChunkFile::ORIGINAL_FILE_ATTR,
+            filter: FilterValue::String(path.as_str()),
+            relation: FilterRelation::Eq,
+        }];
+        if let Some(modified_dt) = &opt_modified {
+            filters.push(Filter {
+                attribute: ChunkFile::FILE_MODIFIED_DATE_ATTR,
+                filter: FilterValue::DateTime(modified_dt),
+                relation: FilterRelation::Eq,
+            });
+        }
+        futures::try_join!(
+            self.text_store.clear_filter(&filters),
+            self.image_store.clear_filter(&filters)
+        ).map_err(|e| IndexProviderError {
+            provider_name: PROVIDER_NAME.to_string(),
+            r#type: IndexProviderErrorType::Store {
+                operation: "clear filter",
+                source: e.into(),
+            }
+        })?;
+
+        Ok(())
+    }
+
+    async fn restore(&self, path: &Utf8Path) -> Result<u64, IndexProviderError> {
+        debug!("Notebook Index Provider: Restoring cleared chunks under path: {}", path);
+        let prefix = directory_prefix(path);
+        let mut restored = 0;
+        for filters in [
+            [Filter { attribute: ChunkFile::ORIGINAL_FILE_ATTR, filter: FilterValue::String(path.as_str()), relation: FilterRelation::Eq }],
+            [Filter { attribute: ChunkFile::ORIGINAL_FILE_ATTR, filter: FilterValue::String(&prefix), relation: FilterRelation::StartsWith }],
+        ] {
+            let (text_restored, image_restored) = futures::try_join!(
+                self.text_store.restore_filter(&filters),
+                self.image_store.restore_filter(&filters)
+            ).map_err(|e| IndexProviderError {
+                provider_name: PROVIDER_NAME.to_string(),
+                r#type: IndexProviderErrorType::Store {
+                    operation: "restore filter",
+                    source: e.into(),
+                }
+            })?;
+            restored += text_restored + image_restored;
+        }
+        Ok(restored)
+    }
+
+    async fn tag(&self, path: &Utf8Path, tags: &[String]) -> Result<(), IndexProviderError> {
+        debug!("Notebook Index Provider: Tagging chunks of path: {} with tags: {:?}", path, tags);
+        let filters = &[Filter {
+            attribute: ChunkFile::ORIGINAL_FILE_ATTR,
+            filter: FilterValue::String(path.as_str()),
+            relation: FilterRelation::Eq,
+        }];
+        let (mut text_chunks, mut image_chunks) = futures::try_join!(
+            self.text_store.query_filter(filters),
+            self.image_store.query_filter(filters),
+        ).map_err(|e| IndexProviderError {
+            provider_name: PROVIDER_NAME.to_string(),
+            r#type: IndexProviderErrorType::Store {
+                operation: "query filter",
+                source: e.into(),
+            }
+        })?;
+        if text_chunks.is_empty() && image_chunks.is_empty() {
+            return Ok(());
+        }
+
+        for chunk in &mut text_chunks {
+            chunk.chunkfile.user_tags = tags.to_vec();
+        }
+        for chunk in &mut image_chunks {
+            chunk.chunkfile.user_tags = tags.to_vec();
+        }
+        futures::try_join!(
+            self.text_store.put(text_chunks),
+            self.image_store.put(image_chunks),
+        ).map_err(|e| IndexProviderError {
+            provider_name: PROVIDER_NAME.to_string(),
+            r#type: IndexProviderErrorType::Store {
+                operation: "put",
+                source: e.into(),
+            }
+        })?;
+
+        Ok(())
+    }
+
+    async fn rename(&self, from: &Utf8Path, to: &Utf8Path) -> Result<u64, IndexProviderError> {
+        debug!("Notebook Index Provider: Renaming chunks of path: {} to: {}", from, to);
+        let (text_chunks, image_chunks) = futures::try_join!(
+            rename_chunks(self.text_store.as_ref(), from, to, PROVIDER_NAME,
+                |c: &mut EmbeddingGemmaEmbeddedChunkFile| &mut c.chunkfile),
+            rename_chunks(self.image_store.as_ref(), from, to, PROVIDER_NAME,
+                |c: &mut Siglip2EmbeddedChunkFile| &mut c.chunkfile),
+        )?;
+        Ok(text_chunks + image_chunks)
+    }
+
+    async fn indexed_file(&self, file_id: &str) -> Result<Option<IndexedFile>, IndexProviderError> {
+        let (text_file, image_file) = futures::try_join!(
+            indexed_file_of(self.text_store.as_ref(), file_id, PROVIDER_NAME, |c: EmbeddingGemmaEmbeddedChunkFile| c.chunkfile),
+            indexed_file_of(self.image_store.as_ref(), file_id, PROVIDER_NAME, |c: Siglip2EmbeddedChunkFile| c.chunkfile),
+        )?;
+        Ok(text_file.or(image_file))
+    }
+
+    async fn indexed_paths_by_content(&self, fingerprint: &str) -> Result<Vec<Utf8PathBuf>, IndexProviderError> {
+        let (text_paths, image_paths) = futures::try_join!(
+            indexed_paths_by_content_of(self.text_store.as_ref(), fingerprint, PROVIDER_NAME, |c: EmbeddingGemmaEmbeddedChunkFile| c.chunkfile),
+            indexed_paths_by_content_of(self.image_store.as_ref(), fingerprint, PROVIDER_NAME, |c: Siglip2EmbeddedChunkFile| c.chunkfile),
+        )?;
+        Ok(text_paths.into_iter().chain(image_paths).collect())
+    }
+
+    async fn query_n(&self, str: &str, filters: &ChunkQueryFilters, num_results: u32, offset: u32)
+        -> Result<Vec<ChunkQueryResult>, IndexProviderError>
+    {
+        debug!("Notebook Index Provider: Querying index of with params: {}, filters: {:?}, \
+            num_results: {}, offset: {}", str, filters, num_results, offset);
+        let store_filters = &filters.store_filters();
+        // Each store is queried from the start, and the chunks of both are paged together by their scores
+        let store_results = offset.saturating_add(num_results);
+
+        let text_chunk_future = async move {
+            let text_vec = embeddinggemma::embed_query(str).await.map_err(|e| IndexProviderError {
+                provider_name: PROVIDER_NAME.to_string(),
+                r#type: IndexProviderErrorType::Embedding { source: e },
+            })?;
+
+            self.text_store.query_full_select_n(Some(text_vec), self.hybrid_search.then_some(str), store_filters, ChunkFile::QUERY_RESULT_ATTRS, store_results, 0).await
+                .map_err(|e| IndexProviderError {
+                    provider_name: PROVIDER_NAME.to_string(),
+                    r#type: IndexProviderErrorType::Store {
+                        operation: "query full",
+                        source: e,
+                    }
+                })
+        };
+        let image_chunk_future = async move {
+            let image_vec = siglip2::embed_query(str).await.map_err(|e| IndexProviderError {
+                provider_name: PROVIDER_NAME.to_string(),
+                r#type: IndexProviderErrorType::Embedding { source: e },
+            })?;
+
+            self.image_store.query_full_select_n(Some(image_vec), self.hybrid_search.then_some(str), store_filters, ChunkFile::QUERY_RESULT_ATTRS, store_results, 0).await
+                .map_err(|e| IndexProviderError {
+                    provider_name: PROVIDER_NAME.to_string(),
+                    r#type: IndexProviderErrorType::Store {
+                        operation: "query full",
+                        source: e,
+                    }
+                })
+        };
+
+        let (text_result, image_result) = join!(
+            text_chunk_future,
+            image_chunk_future
+        );
+        let chunks = text_result?.into_iter()
+            .map(|c| (c.score, c.result.chunkfile))
+            .chain(image_result?.into_iter().map(|c| (c.score, c.result.chunkfile)));
+
+        let results = ScoreNormalizer::for_provider(PROVIDER_NAME).normalize_page(chunks, num_results, offset);
+        Ok(results)
+    }
+
+    async fn query_related_n(&self, path: &Utf8Path, num_results: u32)
+        -> Result<Vec<ChunkQueryResult>, IndexProviderError>
+    {
+        debug!("Notebook Index Provider: Querying files related to path: {}, num_results: {}", path, num_results);
+        let (text_chunks, image_chunks) = futures::try_join!(
+            query_related_chunks(self.text_store.as_ref(), path, num_results, PROVIDER_NAME,
+                |c: EmbeddingGemmaEmbeddedChunkFile| (c.chunkfile, c.embedding)),
+            query_related_chunks(self.image_store.as_ref(), path, num_results, PROVIDER_NAME,
+                |c: Siglip2EmbeddedChunkFile| (c.chunkfile, c.embedding)),
+        )?;
+
+        Ok(text_chunks.into_iter().chain(image_chunks).collect())
+    }
+
+    async fn vocabulary(&self) -> Result<Vec<String>, IndexProviderError> {
+        debug!("Notebook Index Provider: Collecting vocabulary of indexed files");
+        // Every notebook has at least one text chunk unless it has no cells, so the text store covers every file
+        let chunks = self.text_store.query_filter(&[]).await.map_err(|e| IndexProviderError {
+            provider_name: PROVIDER_NAME.to_string(),
+            r#type: IndexProviderErrorType::Store {
+                operation: "query by filter",
+                source: e.into(),
+            }
+        })?;
+
+        let mut seen_files = HashSet::new();
+        Ok(chunks.iter()
+            .map(|c| &c.chunkfile)
+            .filter(|c| seen_files.insert(c.original_file.clone()))
+            .flat_map(chunkfile_vocabulary)
+            .collect())
+    }
+}
+
+// private constants and functions
+
+const PROVIDER_NAME: &str = "NotebookIndexProvider";
+
+const TEXT_CHUNK_CHANNEL: &str = "text";
+const TEXT_CHUNK_MAX_TOKENS: u32 = 1000;
+const IMAGE_CHUNK_CHANNEL: &str = "image";
+const IMAGE_CHUNK_MAX_SIDE: u32 = 512;
+/// Output formats of code cells that are indexed as images, base64 encoded in the notebook
+const IMAGE_OUTPUT_TYPES: [&str; 2] = ["image/png", "image/jpeg"];
+
+
+#[instrument(name = "chunk", level = "debug", skip_all, fields(path = %path))]
+async fn chunk_notebook(path: &Utf8Path, contents: String, metadata: Metadata, out_dir: &Utf8Path, sequence_number: u64)
+    -> Result<Vec<ChunkFile>, anyhow::Error>
+{
+    let file_creation: DateTime<Utc> = DateTime::from(metadata.created()
+        .expect("File creation datetime not available on this platform"));
+    let file_modified: DateTime<Utc> = DateTime::from(metadata.modified()
+        .expect("File modified datetime not available on this platform"));
+    let file_length = metadata.len();
+    let original_file_id = volumes::file_id(path);
+    let original_file_fingerprint = dupes::content_fingerprint(path);
+
+    let path = path.to_owned();
+    let out_dir = out_dir.to_owned();
+    task::spawn_blocking(move || {
+        let notebook: Value = serde_json::from_str(&contents)?;
+        let cells = notebook.get("cells").and_then(Value::as_array)
+            .ok_or_else(|| anyhow::anyhow!("Notebook has no cells, it may be in the unsupported nbformat 3"))?;
+        let kernel_language = notebook.pointer("/metadata/language_info/name")
+            .or_else(|| notebook.pointer("/metadata/kernelspec/language"))
+            .and_then(Value::as_str)
+            .map(str::to_owned);
+
+        let new_chunk = |
+            chunk_channel: &str,
+            chunk_sequence: f32,
+            chunkfile: Utf8PathBuf,
+            chunk_type: ChunkType,
+            chunk_length: f32,
+            tags: Map<String, Value>,
+            language: String,
+        | ChunkFile {
+            original_file: path.clone(),
+            chunk_channel: chunk_channel.to_owned(),
+            chunk_sequence_id: chunk_sequence,
+            original_file_id: original_file_id.clone(),
+            chunkfile,
+            chunk_type,
+            chunk_length,
+            original_file_creation_date: file_creation,
+            original_file_modified_date: file_modified,
+            original_file_size: file_length,
+            original_file_fingerprint: original_file_fingerprint.clone(),
+            original_file_tags: tags,
+            language,
+            user_tags: vec![],
+            sequence_number,
+        };
+
+        let mut chunks = vec![];
+        for (cell_index, cell) in cells.iter().enumerate() {
+            let cell_type = cell.get("cell_type").and_then(Value::as_str).unwrap_or("raw");
+            if cell_type != "markdown" && cell_type != "code" {
+                continue;
+            }
+
+            let mut cell_tags = Map::new();
+            cell_tags.insert("cell_index".to_string(), cell_index.into());
+            cell_tags.insert("cell_type".to_string(), cell_type.into());
+            if let Some(kernel_language) = kernel_language.as_ref().filter(|_| cell_type == "code") {
+                cell_tags.insert("code_language".to_string(), kernel_language.as_str().into());
+            }
+
+            // Cells are indexed as "1.0" chunk length each, split into parts if they are too long to embed
+            let source = multiline_string(cell.get("source"));
+            let parts = split_text(&source);
+            let num_parts = parts.len();
+            for (part_index, part) in parts.into_iter().enumerate() {
+                let chunk_sequence = cell_index as f32 + (part_index as f32 / num_parts as f32);
+                let chunkfile = out_dir.join(format!("{}-{}.txt", TEXT_CHUNK_CHANNEL, chunk_sequence));
+                unlink_chunkfile(&chunkfile)?;
+                std::fs::write(&chunkfile, part)?;
+
+                let mut tags = cell_tags.clone();
+                tags.insert("full_text".to_string(), part.into());
+                // Code is not written in a natural language, so only markdown cells are detected
+                let language = match cell_type {
+                    "markdown" => language::detect_language(part),
+                    _ => UNDETERMINED_LANGUAGE,
+                };
+                chunks.push(new_chunk(TEXT_CHUNK_CHANNEL, chunk_sequence, chunkfile, ChunkType::Text,
+                    1.0 / num_parts as f32, tags, language.to_owned()));
+            }
+
+            let images = cell.get("outputs").and_then(Value::as_array).into_iter().flatten()
+                .filter_map(|output| output.get("data"))
+                .filter_map(|data| IMAGE_OUTPUT_TYPES.iter().find_map(|t| data.get(*t)))
+                .map(|image| multiline_string(Some(image)))
+                .collect::<Vec<_>>();
+            let num_images = images.len();
+            for (image_index, encoded) in images.into_iter().enumerate() {
+                let encoded: String = encoded.chars().filter(|c| !c.is_whitespace()).collect();
+                let image = image::load_from_memory(&STANDARD.decode(encoded)?)?
+                    .resize(IMAGE_CHUNK_MAX_SIDE, IMAGE_CHUNK_MAX_SIDE, FilterType::Triangle);
+
+                let chunk_sequence = cell_index as f32 + (image_index as f32 / num_images as f32);
+                let chunkfile = out_dir.join(format!("{}-{}.webp", IMAGE_CHUNK_CHANNEL, chunk_sequence));
+                unlink_chunkfile(&chunkfile)?;
+                image.save_with_format(&chunkfile, ImageFormat::WebP)?;
+
+                chunks.push(new_chunk(IMAGE_CHUNK_CHANNEL, chunk_sequence, chunkfile, ChunkType::Image,
+                    1.0 / num_images as f32, cell_tags.clone(), UNDETERMINED_LANGUAGE.to_owned()));
+            }
+        }
+
+        Ok::<Vec<ChunkFile>, anyhow::Error>(chunks)
+    }).await? // this is Result<Result<vec, closure_error>, tokio::task_error>
+}
+
+/// Reads a notebook string, which may be stored as a single string or as a list of lines
+fn multiline_string(value: Option<&Value>) -> String {
+    match value {
+        Some(Value::String(s)) => s.clone(),
+        Some(Value::Array(lines)) => lines.iter().filter_map(Value::as_str).collect(),
+        _ => String::new(),
+    }
+}
+
+/// Splits the text of a cell into parts of at most TEXT_CHUNK_MAX_TOKENS tokens at line boundaries, leaving out
+/// empty text
+fn split_text(text: &str) -> Vec<&str> {
+    if text.trim().is_empty() {
+        return vec![];
+    }
+
+    let mut parts = vec![];
+    let mut start = 0;
+    let mut offset = 0;
+    let mut tokens = 0;
+    for line in text.split_inclusive('\n') {
+        let line_tokens = language::estimate_tokens(line);
+        if tokens > 0 && tokens + line_tokens > TEXT_CHUNK_MAX_TOKENS {
+            parts.push(&text[start..offset]);
+            start = offset;
+            tokens = 0;
+        }
+        tokens += line_tokens;
+        offset += line.len();
+    }
+    parts.push(&text[start..]);
+    parts
+}
@@ -1,74 +1,152 @@
-use std::{fs::Metadata, sync::Arc};
+use std::{collections::HashSet, fs::Metadata, hash::{DefaultHasher, Hash, Hasher}, io::{Read, Seek}, num::NonZero, sync::{Arc, LazyLock, Mutex, atomic::{AtomicBool, Ordering}, mpsc}, thread};
 
 use async_trait::async_trait;
-use camino::Utf8Path;
+use camino::{Utf8Path, Utf8PathBuf};
 use chrono::{DateTime, Utc};
 use futures::TryFutureExt;
 use image::{DynamicImage, ImageFormat, imageops::FilterType};
-use log::{debug, info};
 use pdfium_render::prelude::{PdfPage, PdfPageObjectsCommon};
+use serde::Deserialize;
 use serde_json::Map;
 use tokio::{fs::File, join, task};
 use tokio_util::io::SyncIoBridge;
+use tracing::{debug, info, instrument, warn};
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::{app_config, environment::get_pdfium, files::{dupes, os_path::to_os_path, volumes}, index::{ChunkFile, ChunkType, embedding::{embeddinggemma::{self, EmbeddingGemmaEmbeddedChunkFile}, siglip2::{self, Siglip2EmbeddedChunkFile}}, language::{self, UNDETERMINED_LANGUAGE}, provider::{ChunkQueryFilters, ChunkQueryResult, ChunkingIndexProvider, IndexProviderError, IndexProviderErrorType, IndexedFile, chunkfile_vocabulary, clear_chunkfiles, create_chunkfile_dir, directory_prefix, indexed_file_of, indexed_paths_by_content_of, query_related_chunks, rename_chunks, unlink_chunkfile, registry::{CostClass, ProviderCapabilities}, sandbox::{self, SandboxedFormat}, score::ScoreNormalizer}}, sequence, store::{ChunkStore, Filter, FilterRelation, FilterValue, KeyedSequencedData, Reranking}};
+
+/// Settings in the [pdf_chunking] table of the providers.toml settings file, which size the chunks of a PDF
+#[derive(Debug, Clone, Deserialize)]
+pub struct PdfChunkSettings {
+    /// Most tokens in a text chunk, including the tokens it shares with the chunk before it, as counted by the
+    /// tokenizer of EmbeddingGemma. Capped at the tokens the model can read along with its prompt.
+    #[serde(default = "default_text_max_tokens")]
+    pub text_max_tokens: u32,
+    /// Percentage of text_max_tokens a text chunk repeats from the end of the chunk before it in the same page, so
+    /// that a passage split between chunks is still found whole in one of them. Only whole sentences are repeated,
+    /// and at most 50%.
+    #[serde(default = "default_text_overlap_percent")]
+    pub text_overlap_percent: u32,
+    /// Length of the longest side of image chunks, in pixels
+    #[serde(default = "default_image_max_side")]
+    pub image_max_side: u32,
+}
 
-use crate::{environment::get_pdfium, index::{ChunkFile, ChunkType, embedding::{embeddinggemma::{self, EmbeddingGemmaEmbeddedChunkFile}, siglip2::{self, Siglip2EmbeddedChunkFile}}, provider::{ChunkQueryResult, ChunkingIndexProvider, IndexProviderError, IndexProviderErrorType, clear_chunkfiles, create_chunkfile_dir}}, store::{ClearByFilter, Filter, FilterRelation, FilterValue, KeyedSequencedData, KeyedSequencedStore, QueryByFilter, QueryFull}};
-
-pub struct PdfIndexProvider<TS, IS>
-where
-    TS: KeyedSequencedStore<String, EmbeddingGemmaEmbeddedChunkFile> +
-        QueryFull<EmbeddingGemmaEmbeddedChunkFile> +
-        QueryByFilter<EmbeddingGemmaEmbeddedChunkFile> +
-        ClearByFilter<EmbeddingGemmaEmbeddedChunkFile> +
-        Send + Sync,
-    IS: KeyedSequencedStore<String, Siglip2EmbeddedChunkFile> +
-        QueryFull<Siglip2EmbeddedChunkFile> +
-        QueryByFilter<Siglip2EmbeddedChunkFile> +
-        ClearByFilter<Siglip2EmbeddedChunkFile> +
-        Send + Sync
-{
-    text_store: Arc<TS>,
-    image_store: Arc<IS>,
-}
-
-impl<TS, IS> PdfIndexProvider<TS, IS>
-where
-    TS: KeyedSequencedStore<String, EmbeddingGemmaEmbeddedChunkFile> +
-        QueryFull<EmbeddingGemmaEmbeddedChunkFile> +
-        QueryByFilter<EmbeddingGemmaEmbeddedChunkFile> +
-        ClearByFilter<EmbeddingGemmaEmbeddedChunkFile> +
-        Send + Sync,
-    IS: KeyedSequencedStore<String, Siglip2EmbeddedChunkFile> +
-        QueryFull<Siglip2EmbeddedChunkFile> +
-        QueryByFilter<Siglip2EmbeddedChunkFile> +
-        ClearByFilter<Siglip2EmbeddedChunkFile> +
-        Send + Sync
-{
-    pub fn using(text_store: Arc<TS>, image_store: Arc<IS>) -> Self {
-        PdfIndexProvider { text_store, image_store }
+impl Default for PdfChunkSettings {
+    fn default() -> Self {
+        PdfChunkSettings {
+            text_max_tokens: default_text_max_tokens(),
+            text_overlap_percent: default_text_overlap_percent(),
+            image_max_side: default_image_max_side(),
+        }
+    }
+}
+
+impl PdfChunkSettings {
+    /// Loads the PDF chunk settings from the providers.toml settings file, with defaults for anything not set
+    pub fn from_settings() -> Result<PdfChunkSettings, config::ConfigError> {
+        match app_config::get_providers_config()?.get::<PdfChunkSettings>("pdf_chunking") {
+            Ok(settings) => Ok(settings),
+            Err(config::ConfigError::NotFound(_)) => Ok(PdfChunkSettings::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn text_max_tokens(&self) -> u32 {
+        self.text_max_tokens.min(embeddinggemma::max_chunk_tokens())
+    }
+
+    /// Tokens a text chunk repeats from the chunk before it
+    fn text_overlap_tokens(&self) -> u32 {
+        self.text_max_tokens() * self.text_overlap_percent.min(50) / 100
+    }
+}
+
+/// Settings in the [pdf_images] table of the providers.toml settings file, which keep decorative images out of the
+/// image chunks of a PDF. Slide decks repeat the same logo and backgrounds on every page, which would otherwise each
+/// become a chunk.
+#[derive(Debug, Clone, Deserialize)]
+pub struct PdfImageSettings {
+    /// Images whose shorter side is smaller than this, in pixels, are skipped as icons, bullets or rules
+    #[serde(default = "default_min_side_px")]
+    pub min_side_px: u32,
+    /// Whether images with the same pixels as an image earlier in the document are skipped
+    #[serde(default = "default_skip_repeated")]
+    pub skip_repeated: bool,
+    /// Most images chunked per page, the first images of the page are kept
+    #[serde(default = "default_max_per_page")]
+    pub max_per_page: usize,
+    /// Most images chunked per document, the images of the first pages are kept
+    #[serde(default = "default_max_per_document")]
+    pub max_per_document: usize,
+}
+
+impl Default for PdfImageSettings {
+    fn default() -> Self {
+        PdfImageSettings {
+            min_side_px: default_min_side_px(),
+            skip_repeated: default_skip_repeated(),
+            max_per_page: default_max_per_page(),
+            max_per_document: default_max_per_document(),
+        }
+    }
+}
+
+impl PdfImageSettings {
+    /// Loads the PDF image settings from the providers.toml settings file, with defaults for anything not set
+    pub fn from_settings() -> Result<PdfImageSettings, config::ConfigError> {
+        match app_config::get_providers_config()?.get::<PdfImageSettings>("pdf_images") {
+            Ok(settings) => Ok(settings),
+            Err(config::ConfigError::NotFound(_)) => Ok(PdfImageSettings::default()),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+pub struct PdfIndexProvider {
+    text_store: Arc<dyn ChunkStore<EmbeddingGemmaEmbeddedChunkFile>>,
+    image_store: Arc<dyn ChunkStore<Siglip2EmbeddedChunkFile>>,
+    text_reranker: Option<Arc<dyn Reranking<EmbeddingGemmaEmbeddedChunkFile>>>,
+    hybrid_search: bool,
+}
+
+impl PdfIndexProvider {
+    pub fn using(text_store: Arc<dyn ChunkStore<EmbeddingGemmaEmbeddedChunkFile>>, image_store: Arc<dyn ChunkStore<Siglip2EmbeddedChunkFile>>) -> Self {
+        PdfIndexProvider { text_store, image_store, text_reranker: None, hybrid_search: false }
+    }
+
+    /// Matches the words of queries with full text search along with their meaning, and adds up the scores of both,
+    /// see store::FusionWeights. Queries only match by meaning by default.
+    pub fn with_hybrid_search(mut self, enabled: bool) -> Self {
+        self.hybrid_search = enabled;
+        self
+    }
+
+    /// Rescores the text chunks returned by each query with the given reranker before they are returned
+    pub fn with_text_reranker(mut self, reranker: Arc<dyn Reranking<EmbeddingGemmaEmbeddedChunkFile>>) -> Self {
+        self.text_reranker = Some(reranker);
+        self
     }
 }
 
 #[async_trait]
-impl<TS, IS> ChunkingIndexProvider for PdfIndexProvider<TS, IS>
-where
-    TS: KeyedSequencedStore<String, EmbeddingGemmaEmbeddedChunkFile> +
-        QueryFull<EmbeddingGemmaEmbeddedChunkFile> +
-        QueryByFilter<EmbeddingGemmaEmbeddedChunkFile> +
-        ClearByFilter<EmbeddingGemmaEmbeddedChunkFile> +
-        Send + Sync,
-    IS: KeyedSequencedStore<String, Siglip2EmbeddedChunkFile> +
-        QueryFull<Siglip2EmbeddedChunkFile> +
-        QueryByFilter<Siglip2EmbeddedChunkFile> +
-        ClearByFilter<Siglip2EmbeddedChunkFile> +
-        Send + Sync
-{
+impl ChunkingIndexProvider for PdfIndexProvider {
+    fn provider_name(&self) -> &'static str {
+        PROVIDER_NAME
+    }
+
     fn provides_indexing_for_extension(&self, ext: &str) -> bool {
         ext.eq("pdf")
     }
 
+    fn capabilities(&self) -> ProviderCapabilities {
+        // Every page is rendered and its text embedded on top of the page image
+        ProviderCapabilities { mime_types: vec!["application/pdf"], cost: CostClass::Expensive }
+    }
+
     async fn index(&self, path: &Utf8Path, opt_modified: Option<DateTime<Utc>>) -> Result<(), IndexProviderError> {
         debug!("PDF Index Provider: Indexing file at path: {}", path);
-        let file = File::open(path).await
+        let file = File::open(to_os_path(path)).await
             .map_err(|e| IndexProviderError {
                 provider_name: PROVIDER_NAME.to_string(),
                 r#type: IndexProviderErrorType::IO {
@@ -107,6 +185,7 @@ where
         })?;
 
         if let Some(discovered_chunk) = discovered_chunks.0.or(discovered_chunks.1) {
+            sequence::observe(discovered_chunk.sequence_number);
             let last_modified: DateTime<Utc> = opt_modified.unwrap_or(DateTime::from(metadata.modified()
                 .expect("File modified datetime not available on this platform")));
             let stored_modified = discovered_chunk.original_file_modified_date;
@@ -119,6 +198,10 @@ where
             self.clear(path, Some(last_modified)).await?;
         }
 
+        // Taken once any stored chunks have been observed and before the file is chunked, so the new chunks are
+        // ordered after both the stored chunks and chunks written from older reads of the file
+        let sequence_number = sequence::next();
+
         // generate folder to store file chunks
         let chunk_out_dir = create_chunkfile_dir(path).await
             .map_err(|e| IndexProviderError {
@@ -130,7 +213,12 @@ where
             })?;
 
         debug!("PDF Index Provider: Chunking file at path: {} to out_dir: {}", path, chunk_out_dir);
-        let chunkfiles = chunk_pdf(path, file, metadata, &chunk_out_dir).await
+        let chunked = if sandbox::is_untrusted(path) {
+            sandbox::chunk_in_sandbox(SandboxedFormat::Pdf, path, &chunk_out_dir, sequence_number).await
+        } else {
+            chunk_pdf(path, file, metadata, &chunk_out_dir, sequence_number).await
+        };
+        let chunkfiles = chunked
             .map_err(|e| IndexProviderError {
                 provider_name: PROVIDER_NAME.to_owned(),
                 r#type: IndexProviderErrorType::Chunking {
@@ -217,9 +305,108 @@ where
         Ok(())
     }
 
-    async fn query_n(&self, str: &str, num_results: u32, offset: u32) -> Result<Vec<ChunkQueryResult>, IndexProviderError> {
-        debug!("PDF Index Provider: Querying index of with params: {}, \
-            num_results: {}, offset: {}", str, num_results, offset);
+    async fn restore(&self, path: &Utf8Path) -> Result<u64, IndexProviderError> {
+        debug!("PDF Index Provider: Restoring cleared chunks under path: {}", path);
+        let prefix = directory_prefix(path);
+        let mut restored = 0;
+        for filters in [
+            [Filter { attribute: ChunkFile::ORIGINAL_FILE_ATTR, filter: FilterValue::String(path.as_str()), relation: FilterRelation::Eq }],
+            [Filter { attribute: ChunkFile::ORIGINAL_FILE_ATTR, filter: FilterValue::String(&prefix), relation: FilterRelation::StartsWith }],
+        ] {
+            let (text_restored, image_restored) = futures::try_join!(
+                self.text_store.restore_filter(&filters),
+                self.image_store.restore_filter(&filters)
+            ).map_err(|e| IndexProviderError {
+                provider_name: PROVIDER_NAME.to_string(),
+                r#type: IndexProviderErrorType::Store {
+                    operation: "restore filter",
+                    source: e.into(),
+                }
+            })?;
+            restored += text_restored + image_restored;
+        }
+        Ok(restored)
+    }
+
+    async fn tag(&self, path: &Utf8Path, tags: &[String]) -> Result<(), IndexProviderError> {
+        debug!("PDF Index Provider: Tagging chunks of path: {} with tags: {:?}", path, tags);
+        let filters = &[Filter {
+            attribute: ChunkFile::ORIGINAL_FILE_ATTR,
+            filter: FilterValue::String(path.as_str()),
+            relation: FilterRelation::Eq,
+        }];
+        let (mut text_chunks, mut image_chunks) = futures::try_join!(
+            self.text_store.query_filter(filters),
+            self.image_store.query_filter(filters),
+        ).map_err(|e| IndexProviderError {
+            provider_name: PROVIDER_NAME.to_string(),
+            r#type: IndexProviderErrorType::Store {
+                operation: "query filter",
+                source: e.into(),
+            }
+        })?;
+        if text_chunks.is_empty() && image_chunks.is_empty() {
+            return Ok(());
+        }
+
+        // Chunks keep their sequence number, so they replace the stored chunks without overwriting chunks written by a
+        // newer index of the file
+        for chunk in &mut text_chunks {
+            chunk.chunkfile.user_tags = tags.to_vec();
+        }
+        for chunk in &mut image_chunks {
+            chunk.chunkfile.user_tags = tags.to_vec();
+        }
+        futures::try_join!(
+            self.text_store.put(text_chunks),
+            self.image_store.put(image_chunks),
+        ).map_err(|e| IndexProviderError {
+            provider_name: PROVIDER_NAME.to_string(),
+            r#type: IndexProviderErrorType::Store {
+                operation: "put",
+                source: e.into(),
+            }
+        })?;
+
+        Ok(())
+    }
+
+    async fn rename(&self, from: &Utf8Path, to: &Utf8Path) -> Result<u64, IndexProviderError> {
+        debug!("PDF Index Provider: Renaming chunks of path: {} to: {}", from, to);
+        let (text_chunks, image_chunks) = futures::try_join!(
+            rename_chunks(self.text_store.as_ref(), from, to, PROVIDER_NAME,
+                |c: &mut EmbeddingGemmaEmbeddedChunkFile| &mut c.chunkfile),
+            rename_chunks(self.image_store.as_ref(), from, to, PROVIDER_NAME,
+                |c: &mut Siglip2EmbeddedChunkFile| &mut c.chunkfile),
+        )?;
+        Ok(text_chunks + image_chunks)
+    }
+
+    async fn indexed_file(&self, file_id: &str) -> Result<Option<IndexedFile>, IndexProviderError> {
+        let (text_file, image_file) = futures::try_join!(
+            indexed_file_of(self.text_store.as_ref(), file_id, PROVIDER_NAME, |c: EmbeddingGemmaEmbeddedChunkFile| c.chunkfile),
+            indexed_file_of(self.image_store.as_ref(), file_id, PROVIDER_NAME, |c: Siglip2EmbeddedChunkFile| c.chunkfile),
+        )?;
+        Ok(text_file.or(image_file))
+    }
+
+    async fn indexed_paths_by_content(&self, fingerprint: &str) -> Result<Vec<Utf8PathBuf>, IndexProviderError> {
+        let (text_paths, image_paths) = futures::try_join!(
+            indexed_paths_by_content_of(self.text_store.as_ref(), fingerprint, PROVIDER_NAME, |c: EmbeddingGemmaEmbeddedChunkFile| c.chunkfile),
+            indexed_paths_by_content_of(self.image_store.as_ref(), fingerprint, PROVIDER_NAME, |c: Siglip2EmbeddedChunkFile| c.chunkfile),
+        )?;
+        Ok(text_paths.into_iter().chain(image_paths).collect())
+    }
+
+    async fn query_n(&self, str: &str, filters: &ChunkQueryFilters, num_results: u32, offset: u32)
+        -> Result<Vec<ChunkQueryResult>, IndexProviderError>
+    {
+        debug!("PDF Index Provider: Querying index of with params: {}, filters: {:?}, \
+            num_results: {}, offset: {}", str, filters, num_results, offset);
+        // Borrowed so that both of the query futures below can share the filters
+        let store_filters = &filters.store_filters();
+        // Each store is queried from the start, and the chunks of both are paged together by their scores
+        let store_results = offset.saturating_add(num_results);
         debug!("PDF Index Provider: Embedding query");
 
         let text_chunk_future = async move {
@@ -228,19 +415,31 @@ where
                 r#type: IndexProviderErrorType::Embedding { source: e },
             })?;
 
-            self.text_store.query_full_n(
+            let text_chunks = self.text_store.query_full_select_n(
                 Some(text_vec),
-                None, // Some(str), // temporarily disabled for tuning
-                &[],
-                num_results,
-                offset
+                self.hybrid_search.then_some(str),
+                store_filters,
+                ChunkFile::QUERY_RESULT_ATTRS,
+                store_results,
+                0
             ).await.map_err(|e| IndexProviderError {
                 provider_name: PROVIDER_NAME.to_string(),
                 r#type: IndexProviderErrorType::Store {
                     operation: "query full",
                     source: e,
                 }
-            })
+            })?;
+
+            match &self.text_reranker {
+                Some(reranker) => reranker.rerank(str, text_chunks).await.map_err(|e| IndexProviderError {
+                    provider_name: PROVIDER_NAME.to_string(),
+                    r#type: IndexProviderErrorType::Store {
+                        operation: "rerank",
+                        source: e,
+                    }
+                }),
+                None => Ok(text_chunks),
+            }
         };
         let image_chunk_future = async move {
             let image_vec = siglip2::embed_query(str).await.map_err(|e| IndexProviderError {
@@ -248,12 +447,13 @@ where
                 r#type: IndexProviderErrorType::Embedding { source: e },
             })?;
 
-            self.image_store.query_full_n(
+            self.image_store.query_full_select_n(
                 Some(image_vec),
-                None, // Some(str), // temporarily disabled for tuning
-                &[],
-                num_results,
-                offset
+                self.hybrid_search.then_some(str),
+                store_filters,
+                ChunkFile::QUERY_RESULT_ATTRS,
+                store_results,
+                0
             ).await.map_err(|e| IndexProviderError {
                 provider_name: PROVIDER_NAME.to_string(),
                 r#type: IndexProviderErrorType::Store {
@@ -275,21 +475,41 @@ where
             .chain(image_chunks.into_iter().map(|c| (c.score, c.result.chunkfile)))
             .collect::<Vec<(f32, _)>>();
 
+        let results = ScoreNormalizer::for_provider(PROVIDER_NAME).normalize_page(chunks, num_results, offset);
+        Ok(results)
+    }
+
+    async fn query_related_n(&self, path: &Utf8Path, num_results: u32)
+        -> Result<Vec<ChunkQueryResult>, IndexProviderError>
+    {
+        debug!("PDF Index Provider: Querying files related to path: {}, num_results: {}", path, num_results);
+        let (text_chunks, image_chunks) = futures::try_join!(
+            query_related_chunks(self.text_store.as_ref(), path, num_results, PROVIDER_NAME,
+                |c: EmbeddingGemmaEmbeddedChunkFile| (c.chunkfile, c.embedding)),
+            query_related_chunks(self.image_store.as_ref(), path, num_results, PROVIDER_NAME,
+                |c: Siglip2EmbeddedChunkFile| (c.chunkfile, c.embedding)),
+        )?;
+
+        Ok(text_chunks.into_iter().chain(image_chunks).collect())
+    }
 
-        let mut results = vec![];
-        for (score, chunkfile) in chunks {
-            if score >= MIN_SCORE {
-                // normalize to 0-100
-                let norm_score = ((score - MIN_SCORE) / (EXPECTED_MAX_SCORE - MIN_SCORE)) * 100.0;
-                debug!("PDF Index Provider: Normalized result score: orig: {}, chunkfile: {}, orig_score: {}, \
-                    norm_score: {}", chunkfile.original_file, chunkfile.chunkfile, score, norm_score);
-                results.push(ChunkQueryResult::new(chunkfile, norm_score));
-            } else {
-                debug!("PDF Index Provider: Result score is under minimum threshold: orig: {}, chunkfile: {}, \
-                    orig_score: {}", chunkfile.original_file, chunkfile.chunkfile, score)
+    async fn vocabulary(&self) -> Result<Vec<String>, IndexProviderError> {
+        debug!("PDF Index Provider: Collecting vocabulary of indexed files");
+        // Both stores index the same files, so the text store is enough to cover every file
+        let chunks = self.text_store.query_filter(&[]).await.map_err(|e| IndexProviderError {
+            provider_name: PROVIDER_NAME.to_string(),
+            r#type: IndexProviderErrorType::Store {
+                operation: "query by filter",
+                source: e.into(),
             }
-        }
-        Ok(results)
+        })?;
+
+        let mut seen_files = HashSet::new();
+        Ok(chunks.iter()
+            .map(|c| &c.chunkfile)
+            .filter(|c| seen_files.insert(c.original_file.clone()))
+            .flat_map(chunkfile_vocabulary)
+            .collect())
     }
 }
 
@@ -297,24 +517,57 @@ where
 
 const PROVIDER_NAME: &str = "PdfIndexProvider";
 
-// These constants define chunking behavior
-// EmbeddingGemma can do up to 2048 tokens context length, so this could be tuned up.
-// The tokenizing in this chunker is not as robust. I am just splitting by whitespace. For example,
-// I do not tokenize punctuation separately, I do not separate special characters, I will not
-// slice up words/with/slashes/and/hyphens, etc, so I expect the actual token count will be somewhat
-// higher when inputted into EmbeddingGemma
 const TEXT_CHUNK_CHANNEL: &str = "text";
-const TEXT_CHUNK_MAX_TOKENS: u32 = 1000;
-// Length/width of the longest side in the chunked image
 const IMAGE_CHUNK_CHANNEL: &str = "image";
-const IMAGE_CHUNK_MAX_SIDE: u32 = 512;
+// Most of chunking a page is splitting its text, detecting its language and encoding its images, which runs on up to
+// this many threads per PDF. Several files are usually indexed at once, so this is kept low.
+const MAX_PAGE_WORKERS: usize = 4;
+
+// Loaded once rather than by the provider, as sandbox workers chunk PDFs without one
+static CHUNK_SETTINGS: LazyLock<PdfChunkSettings> = LazyLock::new(|| PdfChunkSettings::from_settings()
+    .unwrap_or_else(|e| {
+        warn!("Could not load PDF chunk settings, using the defaults: {:?}", e);
+        PdfChunkSettings::default()
+    }));
+static IMAGE_SETTINGS: LazyLock<PdfImageSettings> = LazyLock::new(|| PdfImageSettings::from_settings()
+    .unwrap_or_else(|e| {
+        warn!("Could not load PDF image settings, using the defaults: {:?}", e);
+        PdfImageSettings::default()
+    }));
+
+// EmbeddingGemma reads up to 2048 tokens, including its prompt. Sentences are tokenized one by one, which can count
+// a token or so differently at their edges than tokenizing the whole chunk, so this leaves some room.
+fn default_text_max_tokens() -> u32 {
+    1900
+}
+
+fn default_text_overlap_percent() -> u32 {
+    10
+}
+
+fn default_image_max_side() -> u32 {
+    512
+}
+
+fn default_min_side_px() -> u32 {
+    32
+}
 
-// These constants must be tuned to the hybrid query results of lance FTS and siglip2 vector cosine similarity reranking
-// TODO: tune
-const EXPECTED_MAX_SCORE: f32 = 1.0;
-const MIN_SCORE: f32 = 0.1;
+fn default_skip_repeated() -> bool {
+    true
+}
+
+fn default_max_per_page() -> usize {
+    16
+}
 
-async fn chunk_pdf(path: &Utf8Path, file: File, metadata: Metadata, out_dir: &Utf8Path)
+fn default_max_per_document() -> usize {
+    200
+}
+
+
+#[instrument(name = "chunk", level = "debug", skip_all, fields(path = %path))]
+async fn chunk_pdf(path: &Utf8Path, file: File, metadata: Metadata, out_dir: &Utf8Path, sequence_number: u64)
     -> Result<Vec<ChunkFile>, anyhow::Error>
 {
     let file = SyncIoBridge::new(file);
@@ -327,55 +580,217 @@ async fn chunk_pdf(path: &Utf8Path, file: File, metadata: Metadata, out_dir: &Ut
     let path = path.to_owned();
     let out_dir = out_dir.to_owned();
     let chunk_files = task::spawn_blocking(move || {
-        let pdfium = get_pdfium();
-        let document = pdfium.load_pdf_from_reader(file, None)?;
-        let pages = document.pages();
-
-        let mut chunks = vec![];
-        for (page_index, page) in pages.iter().enumerate() {
-            chunks.extend(create_text_chunks(
-                &page,
-                page_index,
-                &path,
-                file_creation,
-                file_modified,
-                file_length,
-                &out_dir
-            )?);
-            chunks.extend(create_image_chunks(
-                &page,
-                page_index,
-                &path,
-                file_creation,
-                file_modified,
-                file_length,
-                &out_dir
-            )?);
-        }
-
-        Ok::<Vec<ChunkFile>, anyhow::Error>(chunks)
+        chunk_pdf_reader(file, &path, file_creation, file_modified, file_length, sequence_number, &out_dir)
     }).await??; // this is Result<Result<vec, closure_error>, tokio::task_error>
 
     Ok(chunk_files)
 }
 
+/// Chunks the PDF at the path on the calling thread, for sandbox workers, see sandbox::run_sandbox_worker_if_requested
+pub(crate) fn chunk_pdf_blocking(path: &Utf8Path, out_dir: &Utf8Path, sequence_number: u64)
+    -> Result<Vec<ChunkFile>, anyhow::Error>
+{
+    let file = std::fs::File::open(to_os_path(path))?;
+    let metadata = file.metadata()?;
+    let file_creation: DateTime<Utc> = DateTime::from(metadata.created()
+        .expect("File creation datetime not available on this platform"));
+    let file_modified: DateTime<Utc> = DateTime::from(metadata.modified()
+        .expect("File modified datetime not available on this platform"));
+    chunk_pdf_reader(file, path, file_creation, file_modified, metadata.len(), sequence_number, out_dir)
+}
+
+fn chunk_pdf_reader(
+    reader: impl Read + Seek + 'static,
+    path: &Utf8Path,
+    file_creation: DateTime<Utc>,
+    file_modified: DateTime<Utc>,
+    file_length: u64,
+    sequence_number: u64,
+    out_dir: &Utf8Path
+) -> Result<Vec<ChunkFile>, anyhow::Error> {
+    let pdfium = get_pdfium();
+    let document = pdfium.load_pdf_from_reader(reader, None)?;
+    let pages = document.pages();
+    let num_workers = thread::available_parallelism().map_or(1, NonZero::get)
+        .min(MAX_PAGE_WORKERS)
+        .min((pages.len() as usize).max(1));
+
+    // The channel is bounded so that extracted pages do not pile up in memory while the workers are busy
+    let (page_sender, page_receiver) = mpsc::sync_channel::<ExtractedPage>(num_workers);
+    let page_receiver = Mutex::new(page_receiver);
+    let failed = AtomicBool::new(false);
+    let mut image_filter = ImageFilter::using(&IMAGE_SETTINGS);
+
+    let mut page_chunks = thread::scope(|scope| {
+        let workers: Vec<_> = (0..num_workers).map(|_| scope.spawn(|| {
+            let mut worker_chunks = vec![];
+            let mut result = Ok(());
+            // Pages are received until the channel closes, even after a failure, so extraction never blocks on a
+            // full channel
+            while let Ok(page) = page_receiver.lock().expect("PDF page channel lock poisoned").recv() {
+                if result.is_err() {
+                    continue;
+                }
+                match chunk_page(page, path, file_creation, file_modified, file_length, sequence_number, out_dir) {
+                    Ok(chunks) => worker_chunks.push(chunks),
+                    Err(e) => {
+                        failed.store(true, Ordering::Relaxed);
+                        result = Err(e);
+                    },
+                }
+            }
+            result.map(|_| worker_chunks)
+        })).collect();
+
+        // PDFium is not thread safe, and pdfium-render serializes every call into it, so pages are extracted on
+        // this thread and only chunked by the workers
+        let extracted = (|| {
+            for (page_index, page) in pages.iter().enumerate() {
+                if failed.load(Ordering::Relaxed) {
+                    break;
+                }
+                let (text, text_extraction) = extract_text_from_page(&page)?;
+                let extracted_page = ExtractedPage {
+                    page_index,
+                    text,
+                    text_extraction,
+                    images: extract_images_from_page(&page, &mut image_filter)?,
+                };
+                page_sender.send(extracted_page).expect("PDF page workers should receive until the channel closes");
+            }
+            Ok::<_, anyhow::Error>(())
+        })();
+        drop(page_sender);
+
+        let mut page_chunks = vec![];
+        for worker in workers {
+            page_chunks.extend(worker.join().expect("PDF page worker panicked")?);
+        }
+        extracted.map(|_| page_chunks)
+    })?;
+
+    // Workers finish pages out of order, so the chunks are put back in page order
+    page_chunks.sort_by_key(|(page_index, _)| *page_index);
+    Ok(page_chunks.into_iter().flat_map(|(_, chunks)| chunks).collect())
+}
+
+/// The text and images of a page, extracted from the document so they can be chunked on another thread
+struct ExtractedPage {
+    page_index: usize,
+    text: String,
+    text_extraction: TextExtraction,
+    images: Vec<DynamicImage>,
+}
+
+/// How the text of a page was extracted, recorded in the text_extraction tag of its text chunks so that pages
+/// missing from results can be traced back to their extraction. The values are single words so that they do not
+/// add common words to the full text search of the tags.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TextExtraction {
+    /// The text page PDFium builds from the page, with the characters in reading order
+    TextPage,
+    /// The text objects of the page one by one, each mapped to unicode through its font. Used when the text page is
+    /// empty, which happens with fonts PDFium cannot build a text page from.
+    TextObjects,
+}
+
+impl TextExtraction {
+    fn as_str(&self) -> &'static str {
+        match self {
+            TextExtraction::TextPage => "textpage",
+            TextExtraction::TextObjects => "textobjects",
+        }
+    }
+}
+
+/// Decides which images of a document are chunked, see PdfImageSettings. Images are passed in document order.
+struct ImageFilter<'a> {
+    settings: &'a PdfImageSettings,
+    seen_hashes: HashSet<u64>,
+    num_kept: usize,
+}
+
+impl<'a> ImageFilter<'a> {
+    fn using(settings: &'a PdfImageSettings) -> Self {
+        ImageFilter { settings, seen_hashes: HashSet::new(), num_kept: 0 }
+    }
+
+    /// Whether the image should be chunked, given the number of images already kept from its page
+    fn keep(&mut self, image: &DynamicImage, num_kept_in_page: usize) -> bool {
+        if self.num_kept >= self.settings.max_per_document || num_kept_in_page >= self.settings.max_per_page {
+            return false;
+        }
+        if image.width().min(image.height()) < self.settings.min_side_px {
+            return false;
+        }
+        if self.settings.skip_repeated && !self.seen_hashes.insert(hash_image(image)) {
+            return false;
+        }
+        self.num_kept += 1;
+        true
+    }
+}
+
+fn hash_image(image: &DynamicImage) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    (image.width(), image.height()).hash(&mut hasher);
+    image.as_bytes().hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Chunks an extracted page, returning its index along with its text chunks followed by its image chunks
+fn chunk_page(
+    page: ExtractedPage,
+    path: &Utf8Path,
+    file_creation: DateTime<Utc>,
+    file_modified: DateTime<Utc>,
+    file_length: u64,
+    sequence_number: u64,
+    out_dir: &Utf8Path
+) -> Result<(usize, Vec<ChunkFile>), anyhow::Error> {
+    let mut chunks = create_text_chunks(
+        &page.text,
+        page.text_extraction,
+        page.page_index,
+        path,
+        file_creation,
+        file_modified,
+        file_length,
+        sequence_number,
+        out_dir
+    )?;
+    chunks.extend(create_image_chunks(
+        page.images,
+        page.page_index,
+        path,
+        file_creation,
+        file_modified,
+        file_length,
+        sequence_number,
+        out_dir
+    )?);
+    Ok((page.page_index, chunks))
+}
+
 fn create_text_chunks(
-    page: &PdfPage,
+    text: &str,
+    text_extraction: TextExtraction,
     page_index: usize,
     path: &Utf8Path,
     file_creation: DateTime<Utc>,
     file_modified: DateTime<Utc>,
     file_length: u64,
+    sequence_number: u64,
     out_dir: &Utf8Path
 ) -> Result<Vec<ChunkFile>, anyhow::Error> {
-    let text = page.text()?.all();
-
     // Separate page text into chunks if necessary (larger than max tokens)
-    let chunks = chunk_text(&text);
+    let chunks = chunk_text(text, &CHUNK_SETTINGS);
     let num_chunks_in_page = chunks.len();
 
     // Assuming each page is "1.0" chunk length
     let chunk_length = 1.0 / num_chunks_in_page as f32;
+    let original_file_id = volumes::file_id(path);
+    let original_file_fingerprint = dupes::content_fingerprint(path);
     let mut text_chunks = vec![];
     for (i, chunk) in chunks.into_iter().enumerate() {
         // The chunk sequence is the page index plus a fractional part marking the start of
@@ -388,112 +803,185 @@ fn create_text_chunks(
 
         // Write out the text chunk
         let chunk_owned = chunk.to_owned();
+        unlink_chunkfile(&chunkfile)?;
         std::fs::write(&chunkfile, &chunk_owned)?;
 
         // Add the full text blob to the metadata in the chunkfile struct, so it can be
         // searched with FTS
         let mut tags_map = Map::new();
         tags_map.insert("full_text".to_string(), chunk_owned.into());
+        tags_map.insert("text_extraction".to_string(), text_extraction.as_str().into());
 
         text_chunks.push(ChunkFile {
             original_file: path.to_owned(),
             chunk_channel: TEXT_CHUNK_CHANNEL.to_owned(),
             chunk_sequence_id: chunk_sequence,
+            original_file_id: original_file_id.clone(),
             chunkfile,
             chunk_type: ChunkType::Text,
             chunk_length,
             original_file_creation_date: file_creation,
             original_file_modified_date: file_modified,
             original_file_size: file_length,
+            original_file_fingerprint: original_file_fingerprint.clone(),
             original_file_tags: tags_map,
+            language: language::detect_language(chunk).to_owned(),
+            user_tags: vec![],
+            sequence_number,
         });
     }
 
     Ok(text_chunks)
 }
 
-fn chunk_text(text: &str) -> Vec<&str> {
-    // roughly, by whitespace
-    let tokens = text.split_whitespace().collect::<Vec<&str>>();
-    let divisor = (tokens.len() as u32 / TEXT_CHUNK_MAX_TOKENS) + 1;
-    let token_target = (tokens.len() as f32 / divisor as f32).ceil() as u32;
-    partition_by_whitespaces(text, token_target)
+fn chunk_text<'a>(text: &'a str, settings: &PdfChunkSettings) -> Vec<&'a str> {
+    // Break the page into units that each fit in a chunk: sentences, or the words of sentences that are too long
+    // to fit. Unicode sentence and word boundaries are used so that text without whitespace (Chinese, Japanese)
+    // is still split.
+    let max_tokens = settings.text_max_tokens();
+    let mut units = vec![];
+    for (start, sentence) in text.split_sentence_bound_indices() {
+        let tokens = embeddinggemma::count_tokens(sentence);
+        if tokens <= max_tokens {
+            units.push((start, tokens));
+        } else {
+            units.extend(sentence.split_word_bound_indices()
+                .map(|(i, word)| (start + i, embeddinggemma::count_tokens(word))));
+        }
+    }
+
+    // Chunks are sized so that they still fit in the max tokens with the overlap added to their start
+    let overlap_tokens = settings.text_overlap_tokens();
+    let max_new_tokens = (max_tokens - overlap_tokens).max(1);
+    let total_tokens: u32 = units.iter().map(|(_, tokens)| tokens).sum();
+    let divisor = (total_tokens / max_new_tokens) + 1;
+    let token_target = (total_tokens as f32 / divisor as f32).ceil() as u32;
+    partition_by_units(text, &units, token_target, overlap_tokens)
 }
 
-fn partition_by_whitespaces(text: &str, whitespace_count: u32) -> Vec<&str> {
+/// Partitions text into chunks of roughly token_target tokens, only splitting at the start of a unit. Units are
+/// (start byte index, token count) pairs in ascending order of start index. Each chunk after the first also starts
+/// with the units at the end of the chunk before it that fit in overlap_tokens, but never all of them.
+fn partition_by_units<'a>(text: &'a str, units: &[(usize, u32)], token_target: u32, overlap_tokens: u32)
+    -> Vec<&'a str>
+{
     let mut partitions = Vec::new();
     let mut start = 0;
-    let mut ws_seen = 0;
-    
-    for (idx, ch) in text.char_indices() {
-        if ch.is_whitespace() {
-            ws_seen += 1;
-            
-            if ws_seen == whitespace_count {
-                // Partition from start up to and including this whitespace
-                let end = idx + ch.len_utf8();
-                partitions.push(&text[start..end]);
-                start = end;
-                ws_seen = 0;
-            }
+    // Index of the first unit of the current partition that is not repeated from the one before it
+    let mut first_unit = 0;
+    let mut tokens_seen = 0;
+
+    for (index, &(unit_start, unit_tokens)) in units.iter().enumerate() {
+        if tokens_seen > 0 && tokens_seen + unit_tokens > token_target {
+            partitions.push(&text[start..unit_start]);
+            start = overlap_start(units, first_unit, index, overlap_tokens);
+            first_unit = index;
+            tokens_seen = 0;
         }
+        tokens_seen += unit_tokens;
     }
-    
+
     // Don't forget the last partition if there's remaining text
     if start < text.len() {
         partitions.push(&text[start..]);
     }
-    
+
     partitions
 }
 
+/// Start of the units before units[index], back to but excluding units[first_unit], that fit in overlap_tokens
+fn overlap_start(units: &[(usize, u32)], first_unit: usize, index: usize, overlap_tokens: u32) -> usize {
+    let mut start = units[index].0;
+    let mut tokens = 0;
+    for &(unit_start, unit_tokens) in units[first_unit + 1..index].iter().rev() {
+        if tokens + unit_tokens > overlap_tokens {
+            break;
+        }
+        tokens += unit_tokens;
+        start = unit_start;
+    }
+    start
+}
+
 fn create_image_chunks(
-    page: &PdfPage,
+    images: Vec<DynamicImage>,
     page_index: usize,
     path: &Utf8Path,
     file_creation: DateTime<Utc>,
     file_modified: DateTime<Utc>,
     file_length: u64,
+    sequence_number: u64,
     out_dir: &Utf8Path
 ) -> Result<Vec<ChunkFile>, anyhow::Error> {
-    let images = extract_images_from_page(page)?;
     let images_len = images.len();
 
     let chunk_len = 1.0 / images_len as f32;
+    let original_file_id = volumes::file_id(path);
+    let original_file_fingerprint = dupes::content_fingerprint(path);
     let mut image_chunks = vec![];
     for (index, image) in images.into_iter().enumerate() {
         let image = image.resize(
-            IMAGE_CHUNK_MAX_SIDE,
-            IMAGE_CHUNK_MAX_SIDE,
+            CHUNK_SETTINGS.image_max_side,
+            CHUNK_SETTINGS.image_max_side,
             FilterType::Triangle,
         );
 
         let chunk_sequence = page_index as f32 + (index as f32 / images_len as f32);
         let chunk_filename = format!("{}-{}.webp", IMAGE_CHUNK_CHANNEL, chunk_sequence);
         let chunkfile = out_dir.join(chunk_filename);
+        unlink_chunkfile(&chunkfile)?;
         image.save_with_format(&chunkfile, ImageFormat::WebP)?;
         
         image_chunks.push(ChunkFile {
             original_file: path.to_owned(),
             chunk_channel: IMAGE_CHUNK_CHANNEL.to_owned(),
             chunk_sequence_id: chunk_sequence,
+            original_file_id: original_file_id.clone(),
             chunkfile,
             chunk_type: ChunkType::Image,
             chunk_length: chunk_len,
             original_file_creation_date: file_creation,
             original_file_modified_date: file_modified,
             original_file_size: file_length,
+            original_file_fingerprint: original_file_fingerprint.clone(),
             original_file_tags: Map::new(),
+            language: UNDETERMINED_LANGUAGE.to_owned(),
+            user_tags: vec![],
+            sequence_number,
         });
     }
 
     Ok(image_chunks)
 }
 
+/// Extracts the text of the page, falling back to the text objects of the page if PDFium's text page is empty
+fn extract_text_from_page(page: &PdfPage) -> Result<(String, TextExtraction), anyhow::Error> {
+    let page_text = page.text()?.all();
+    if !page_text.trim().is_empty() {
+        return Ok((page_text, TextExtraction::TextPage));
+    }
+
+    let object_text = page.objects().iter()
+        .filter_map(|object| object.as_text_object().map(|text_object| text_object.text()))
+        .filter(|text| !text.trim().is_empty())
+        .collect::<Vec<_>>()
+        .join(" ");
+    if !object_text.is_empty() {
+        debug!("Text page is empty, extracted text from the text objects of the page instead");
+        return Ok((object_text, TextExtraction::TextObjects));
+    }
+
+    // eg. a scanned page, which is only indexed through its images
+    debug!("No text could be extracted from page");
+    Ok((page_text, TextExtraction::TextPage))
+}
+
 fn extract_images_from_page(
     page: &PdfPage,
+    filter: &mut ImageFilter,
 ) -> Result<Vec<DynamicImage>, anyhow::Error> {
     let mut images = vec![];
+    let mut num_skipped = 0;
 
     // Iterate through all objects on the page
     for object in page.objects().iter() {
@@ -502,9 +990,17 @@ fn extract_images_from_page(
             // Potentially at some point it would be possible to determine exactly where the image
             // is positioned on the page, and base the sequence id of the image on that. This is
             // worth some thought.
-            images.push(image_object.get_raw_image()?);
+            let image = image_object.get_raw_image()?;
+            if filter.keep(&image, images.len()) {
+                images.push(image);
+            } else {
+                num_skipped += 1;
+            }
         }
     }
 
+    if num_skipped > 0 {
+        debug!("Skipped {} decorative, repeated or excess images in page", num_skipped);
+    }
     Ok(images)
 }
\ No newline at end of file
@@ -0,0 +1,360 @@
+use std::{collections::HashMap, fs::File, io::Read, sync::Arc};
+
+use camino::Utf8Path;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+
+use crate::{app_config, files::os_path::to_os_path, index::{embedding::{embeddinggemma::EmbeddingGemmaEmbeddedChunkFile, siglip2::Siglip2EmbeddedChunkFile}, provider::{ChunkingIndexProvider, font::FontIndexProvider, image::ImageIndexProvider, markdown::MarkdownIndexProvider, notebook::NotebookIndexProvider, subprocess::{PluginSettings, SubprocessIndexProvider}, subtitle::SubtitleIndexProvider}}, store::{FTSData, Filterable, FusionWeights, VectorData, lancedb::{ArrowData, LanceDBError, LanceDBStore}}};
+#[cfg(feature = "code")]
+use crate::index::provider::code::CodeIndexProvider;
+#[cfg(feature = "models")]
+use crate::index::provider::model::ModelIndexProvider;
+#[cfg(feature = "pdf")]
+use crate::index::{provider::pdf::PdfIndexProvider, rerank::CrossEncoderReranker};
+
+/// How expensive a provider is to run on a file, eg. a provider running a large model on every page of a document
+/// is more expensive than one embedding a single thumbnail
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CostClass {
+    Cheap,
+    Moderate,
+    Expensive,
+}
+
+/// What a provider can index, in addition to the extensions it handles (see
+/// ChunkingIndexProvider::provides_indexing_for_extension)
+#[derive(Debug, Clone)]
+pub struct ProviderCapabilities {
+    /// MIME types of the files the provider indexes, used to route files by their content (see route)
+    pub mime_types: Vec<&'static str>,
+    pub cost: CostClass,
+}
+
+/// Settings of a single provider in the providers.toml settings file
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProviderSettings {
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    #[serde(default)]
+    pub priority: i32,
+    /// Most files the provider indexes at once. Unlimited if not set.
+    #[serde(default)]
+    pub max_concurrent: Option<usize>,
+    /// Most memory in megabytes the files the provider indexes at once may take up, estimated from their sizes.
+    /// Unlimited if not set.
+    #[serde(default)]
+    pub max_memory_mb: Option<u32>,
+    /// Size in megabytes of the largest file the provider decodes itself, for providers that support it
+    /// (ImageIndexProvider). Unlimited if not set.
+    #[serde(default)]
+    pub max_file_mb: Option<u64>,
+    /// Whether queries also match the words of chunks with full text search, along with their meaning, see
+    /// FusionWeights. Off if not set.
+    #[serde(default)]
+    pub hybrid_search: bool,
+}
+
+/// Limits on the files a provider indexes at once, enforced by FileIndexer, so that eg. a 2GB psd being flattened
+/// holds up the other psds rather than every provider
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ProviderBudget {
+    pub max_concurrent: Option<usize>,
+    pub max_memory_mb: Option<u32>,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ProviderRegistryError {
+    #[error("Could not open store {table} for the index providers")]
+    Store { table: String, #[source] source: LanceDBError },
+}
+
+/// The index providers files are indexed and queried with, in order of priority, highest first. Several providers
+/// can index the same type of file, in which case every one of them indexes the file (see route).
+#[derive(Clone, Default)]
+pub struct ProviderRegistry {
+    entries: Vec<RegisteredProvider>,
+    budgets: HashMap<&'static str, ProviderBudget>,
+}
+
+impl ProviderRegistry {
+    pub fn new() -> ProviderRegistry {
+        ProviderRegistry::default()
+    }
+
+    /// Opens the stores of the providers built into Fetch and of the plugins in the data directory, and registers
+    /// the providers turned on by the providers.toml settings file and the enabled features. Settings that cannot
+    /// be loaded are logged, and every built in provider is registered with its default priority instead. The stores
+    /// are opened read-only if the index is read-only on this machine, see app_config::is_index_read_only.
+    pub async fn open(data_dir: &Utf8Path) -> Result<ProviderRegistry, ProviderRegistryError> {
+        Self::open_providers(data_dir, None, app_config::is_index_read_only()).await
+    }
+
+    /// Opens the providers like open, with their stores opened read-only (see LanceDBStore::read_only), so files
+    /// can be queried but not indexed
+    pub async fn open_read_only(data_dir: &Utf8Path) -> Result<ProviderRegistry, ProviderRegistryError> {
+        Self::open_providers(data_dir, None, true).await
+    }
+
+    /// Opens the providers like open, with hybrid search turned on or off for every provider regardless of their
+    /// settings, eg. to compare the relevance of both
+    pub async fn open_with_hybrid_search(data_dir: &Utf8Path, hybrid_search: bool)
+        -> Result<ProviderRegistry, ProviderRegistryError>
+    {
+        Self::open_providers(data_dir, Some(hybrid_search), app_config::is_index_read_only()).await
+    }
+
+    /// Registers a provider. Providers registered with the same priority keep the order they were registered in.
+    pub fn register(&mut self, provider: Arc<dyn ChunkingIndexProvider>, priority: i32) -> &mut ProviderRegistry {
+        let position = self.entries.iter().position(|e| e.priority < priority).unwrap_or(self.entries.len());
+        self.entries.insert(position, RegisteredProvider { provider, priority });
+        self
+    }
+
+    /// Every registered provider, in order of priority
+    pub fn providers(&self) -> Vec<Arc<dyn ChunkingIndexProvider>> {
+        self.entries.iter().map(|e| e.provider.clone()).collect()
+    }
+
+    /// Limits the files the provider indexes at once. Providers are not limited by default.
+    pub fn set_budget(&mut self, provider_name: &'static str, budget: ProviderBudget) -> &mut ProviderRegistry {
+        self.budgets.insert(provider_name, budget);
+        self
+    }
+
+    /// Budgets of the providers that are limited, by provider name
+    pub fn budgets(&self) -> HashMap<&'static str, ProviderBudget> {
+        self.budgets.clone()
+    }
+
+    async fn open_providers(data_dir: &Utf8Path, hybrid_search_override: Option<bool>, read_only: bool)
+        -> Result<ProviderRegistry, ProviderRegistryError>
+    {
+        let (provider_settings, plugin_settings, max_cost) = load_settings().unwrap_or_else(|e| {
+            warn!("Could not load provider settings, registering every provider: {:?}", e);
+            (HashMap::new(), HashMap::new(), CostClass::Expensive)
+        });
+        let fusion_weights = FusionWeights::from_settings().unwrap_or_else(|e| {
+            warn!("Could not load hybrid search weights from settings, using the defaults: {:?}", e);
+            FusionWeights::default()
+        });
+        let hybrid_search = |name: &str| hybrid_search_override
+            .unwrap_or_else(|| provider_settings.get(name).is_some_and(|s| s.hybrid_search));
+
+        let siglip_store = Arc::new(open_chunk_store::<Siglip2EmbeddedChunkFile>(data_dir, "siglip2_chunkfile".to_owned(), read_only).await
+            .map_err(|e| ProviderRegistryError::Store { table: "siglip2_chunkfile".to_owned(), source: e })?.with_fusion_weights(fusion_weights));
+        let mut image = ImageIndexProvider::using(siglip_store.clone())
+            .with_hybrid_search(hybrid_search("ImageIndexProvider"));
+        if let Some(max_file_mb) = provider_settings.get(image.provider_name()).and_then(|s| s.max_file_mb) {
+            image = image.with_max_file_size(max_file_mb * BYTES_PER_MB);
+        }
+        let mut candidates: Vec<(Arc<dyn ChunkingIndexProvider>, i32)> = vec![
+            (Arc::new(image), IMAGE_DEFAULT_PRIORITY),
+        ];
+        let markdown_store = Arc::new(open_chunk_store::<EmbeddingGemmaEmbeddedChunkFile>(data_dir, "markdown_chunkfile".to_owned(), read_only).await
+            .map_err(|e| ProviderRegistryError::Store { table: "markdown_chunkfile".to_owned(), source: e })?.with_fusion_weights(fusion_weights));
+        candidates.push((Arc::new(MarkdownIndexProvider::using(markdown_store)
+            .with_hybrid_search(hybrid_search("MarkdownIndexProvider"))), MARKDOWN_DEFAULT_PRIORITY));
+        let notebook_text_store = Arc::new(open_chunk_store::<EmbeddingGemmaEmbeddedChunkFile>(data_dir, "notebook_gemma_chunkfile".to_owned(), read_only).await
+            .map_err(|e| ProviderRegistryError::Store { table: "notebook_gemma_chunkfile".to_owned(), source: e })?.with_fusion_weights(fusion_weights));
+        let notebook_image_store = Arc::new(open_chunk_store::<Siglip2EmbeddedChunkFile>(data_dir, "notebook_siglip2_chunkfile".to_owned(), read_only).await
+            .map_err(|e| ProviderRegistryError::Store { table: "notebook_siglip2_chunkfile".to_owned(), source: e })?.with_fusion_weights(fusion_weights));
+        candidates.push((Arc::new(NotebookIndexProvider::using(notebook_text_store, notebook_image_store)
+            .with_hybrid_search(hybrid_search("NotebookIndexProvider"))),
+            NOTEBOOK_DEFAULT_PRIORITY));
+        let subtitle_store = Arc::new(open_chunk_store::<EmbeddingGemmaEmbeddedChunkFile>(data_dir, "subtitle_chunkfile".to_owned(), read_only).await
+            .map_err(|e| ProviderRegistryError::Store { table: "subtitle_chunkfile".to_owned(), source: e })?.with_fusion_weights(fusion_weights));
+        candidates.push((Arc::new(SubtitleIndexProvider::using(subtitle_store)
+            .with_hybrid_search(hybrid_search("SubtitleIndexProvider"))), SUBTITLE_DEFAULT_PRIORITY));
+        let font_text_store = Arc::new(open_chunk_store::<EmbeddingGemmaEmbeddedChunkFile>(data_dir, "font_gemma_chunkfile".to_owned(), read_only).await
+            .map_err(|e| ProviderRegistryError::Store { table: "font_gemma_chunkfile".to_owned(), source: e })?.with_fusion_weights(fusion_weights));
+        let font_image_store = Arc::new(open_chunk_store::<Siglip2EmbeddedChunkFile>(data_dir, "font_siglip2_chunkfile".to_owned(), read_only).await
+            .map_err(|e| ProviderRegistryError::Store { table: "font_siglip2_chunkfile".to_owned(), source: e })?.with_fusion_weights(fusion_weights));
+        candidates.push((Arc::new(FontIndexProvider::using(font_text_store, font_image_store)
+            .with_hybrid_search(hybrid_search("FontIndexProvider"))), FONT_DEFAULT_PRIORITY));
+        #[cfg(feature = "code")]
+        {
+            let code_store = Arc::new(open_chunk_store::<EmbeddingGemmaEmbeddedChunkFile>(data_dir, "code_chunkfile".to_owned(), read_only).await
+                .map_err(|e| ProviderRegistryError::Store { table: "code_chunkfile".to_owned(), source: e })?.with_fusion_weights(fusion_weights));
+            candidates.push((Arc::new(CodeIndexProvider::using(code_store)
+                .with_hybrid_search(hybrid_search("CodeIndexProvider"))), CODE_DEFAULT_PRIORITY));
+        }
+        #[cfg(feature = "models")]
+        {
+            let model_text_store = Arc::new(open_chunk_store::<EmbeddingGemmaEmbeddedChunkFile>(data_dir, "model_gemma_chunkfile".to_owned(), read_only).await
+                .map_err(|e| ProviderRegistryError::Store { table: "model_gemma_chunkfile".to_owned(), source: e })?.with_fusion_weights(fusion_weights));
+            let model_image_store = Arc::new(open_chunk_store::<Siglip2EmbeddedChunkFile>(data_dir, "model_siglip2_chunkfile".to_owned(), read_only).await
+                .map_err(|e| ProviderRegistryError::Store { table: "model_siglip2_chunkfile".to_owned(), source: e })?.with_fusion_weights(fusion_weights));
+            candidates.push((Arc::new(ModelIndexProvider::using(model_text_store, model_image_store)
+                .with_hybrid_search(hybrid_search("ModelIndexProvider"))),
+                MODEL_DEFAULT_PRIORITY));
+        }
+        #[cfg(feature = "pdf")]
+        {
+            let gemma_store = Arc::new(open_chunk_store::<EmbeddingGemmaEmbeddedChunkFile>(data_dir, "gemma_chunkfile".to_owned(), read_only).await
+                .map_err(|e| ProviderRegistryError::Store { table: "gemma_chunkfile".to_owned(), source: e })?.with_fusion_weights(fusion_weights));
+            let mut pdf = PdfIndexProvider::using(gemma_store, siglip_store)
+                .with_hybrid_search(hybrid_search("PdfIndexProvider"));
+            // The cross-encoder model is optional, only rerank if it has been installed
+            match CrossEncoderReranker::new(RERANK_TOP_N) {
+                Ok(reranker) => pdf = pdf.with_text_reranker(Arc::new(reranker)),
+                Err(e) => debug!("Cross-encoder reranking disabled: {}", e),
+            }
+            candidates.push((Arc::new(pdf), PDF_DEFAULT_PRIORITY));
+        }
+        for (name, settings) in plugin_settings {
+            // Each plugin has tables of its own, so its chunks are not returned by the queries of other providers
+            let table_prefix = plugin_table_prefix(&name);
+            let text_table = format!("{}_gemma_chunkfile", table_prefix);
+            let image_table = format!("{}_siglip2_chunkfile", table_prefix);
+            let text_store = Arc::new(open_chunk_store::<EmbeddingGemmaEmbeddedChunkFile>(data_dir, text_table.clone(), read_only).await
+                .map_err(|e| ProviderRegistryError::Store { table: text_table, source: e })?.with_fusion_weights(fusion_weights));
+            let image_store = Arc::new(open_chunk_store::<Siglip2EmbeddedChunkFile>(data_dir, image_table.clone(), read_only).await
+                .map_err(|e| ProviderRegistryError::Store { table: image_table, source: e })?.with_fusion_weights(fusion_weights));
+            let priority = settings.priority;
+            candidates.push((Arc::new(SubprocessIndexProvider::using(&name, settings, text_store, image_store)), priority));
+        }
+
+        let mut registry = ProviderRegistry::new();
+        for (provider, default_priority) in candidates {
+            let settings = provider_settings.get(provider.provider_name());
+            if settings.is_some_and(|s| !s.enabled) {
+                debug!("ProviderRegistry: {} is turned off in settings", provider.provider_name());
+                continue;
+            }
+            if provider.capabilities().cost > max_cost {
+                debug!("ProviderRegistry: {} costs more than the maximum cost {:?}", provider.provider_name(), max_cost);
+                continue;
+            }
+            if let Some(settings) = settings.filter(|s| s.max_concurrent.is_some() || s.max_memory_mb.is_some()) {
+                registry.set_budget(provider.provider_name(), ProviderBudget {
+                    max_concurrent: settings.max_concurrent,
+                    max_memory_mb: settings.max_memory_mb,
+                });
+            }
+            registry.register(provider, settings.map(|s| s.priority).unwrap_or(default_priority));
+        }
+        Ok(registry)
+    }
+}
+
+/// Names of the providers that index the file at the path. Files are routed by their content first, to every
+/// provider handling their MIME type (see sniff_mime_type), so that extensionless files, misnamed files and eg.
+/// .bak copies of pdfs reach the right provider. Files whose content is not recognized, or not handled by any
+/// provider, are routed to every provider handling their extension instead. So are files that cannot be read,
+/// eg. deleted files being cleared from the index.
+pub fn route(providers: &[Arc<dyn ChunkingIndexProvider>], path: &Utf8Path) -> Vec<&'static str> {
+    if let Some(mime_type) = sniff_mime_type(path) {
+        let by_content: Vec<_> = providers.iter()
+            .filter(|p| p.capabilities().mime_types.contains(&mime_type))
+            .map(|p| p.provider_name())
+            .collect();
+        if !by_content.is_empty() {
+            return by_content;
+        }
+    }
+
+    let ext = path.extension().unwrap_or("");
+    providers.iter()
+        .filter(|p| p.provides_indexing_for_extension(ext))
+        .map(|p| p.provider_name())
+        .collect()
+}
+
+/// Guesses the MIME type of a file from the first bytes of its content. Returns None if the file cannot be read or
+/// is of an unknown type.
+pub fn sniff_mime_type(path: &Utf8Path) -> Option<&'static str> {
+    let mut header = [0u8; 16];
+    let mut file = File::open(to_os_path(path)).ok()?;
+    let len = file.read(&mut header).ok()?;
+    let header = &header[..len];
+
+    let mime_type = match header {
+        [b'%', b'P', b'D', b'F', ..] => "application/pdf",
+        [0x89, b'P', b'N', b'G', ..] => "image/png",
+        [0xFF, 0xD8, 0xFF, ..] => "image/jpeg",
+        [b'G', b'I', b'F', b'8', ..] => "image/gif",
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => "image/webp",
+        [_, _, _, _, b'f', b't', b'y', b'p', b'a', b'v', b'i', b'f', ..] => "image/avif",
+        [b'B', b'M', ..] => "image/bmp",
+        [b'I', b'I', 0x2A, 0x00, ..] | [b'M', b'M', 0x00, 0x2A, ..] => "image/tiff",
+        [b'8', b'B', b'P', b'S', ..] => "image/vnd.adobe.photoshop",
+        [b'q', b'o', b'i', b'f', ..] => "image/qoi",
+        [0x00, 0x00, 0x01, 0x00, ..] => "image/x-icon",
+        [0x00, 0x01, 0x00, 0x00, ..] | [b't', b'r', b'u', b'e', ..] => "font/ttf",
+        [b'O', b'T', b'T', b'O', ..] => "font/otf",
+        [b'g', b'l', b'T', b'F', ..] => "model/gltf-binary",
+        _ => return None,
+    };
+    Some(mime_type)
+}
+
+// Private functions and variables
+
+#[derive(Clone)]
+struct RegisteredProvider {
+    provider: Arc<dyn ChunkingIndexProvider>,
+    priority: i32,
+}
+
+const IMAGE_DEFAULT_PRIORITY: i32 = 10;
+const MARKDOWN_DEFAULT_PRIORITY: i32 = 15;
+const NOTEBOOK_DEFAULT_PRIORITY: i32 = 15;
+const SUBTITLE_DEFAULT_PRIORITY: i32 = 15;
+const FONT_DEFAULT_PRIORITY: i32 = 15;
+#[cfg(feature = "code")]
+const CODE_DEFAULT_PRIORITY: i32 = 15;
+#[cfg(feature = "models")]
+const MODEL_DEFAULT_PRIORITY: i32 = 15;
+#[cfg(feature = "pdf")]
+const PDF_DEFAULT_PRIORITY: i32 = 20;
+const BYTES_PER_MB: u64 = 1024 * 1024;
+/// Number of text chunks reranked by the cross-encoder per query, if it is installed
+#[cfg(feature = "pdf")]
+const RERANK_TOP_N: usize = 20;
+
+/// Opens the chunk store of a provider, read-only if the providers are opened read-only
+async fn open_chunk_store<D: ArrowData + VectorData + Filterable + FTSData>(data_dir: &Utf8Path, table_name: String,
+    read_only: bool) -> Result<LanceDBStore<D>, LanceDBError>
+{
+    match read_only {
+        true => LanceDBStore::read_only(data_dir.as_str(), table_name).await,
+        false => LanceDBStore::local_full(data_dir.as_str(), table_name).await,
+    }
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+/// Loads the settings of each provider and plugin by name, and the maximum cost class, from the providers.toml
+/// settings file
+#[allow(clippy::type_complexity)]
+fn load_settings() -> Result<(HashMap<String, ProviderSettings>, HashMap<String, PluginSettings>, CostClass), anyhow::Error> {
+    let settings = app_config::get_providers_config()?;
+    let provider_settings = match settings.get::<HashMap<String, ProviderSettings>>("providers") {
+        Ok(provider_settings) => provider_settings,
+        Err(config::ConfigError::NotFound(_)) => HashMap::new(),
+        Err(e) => return Err(e.into()),
+    };
+    let plugin_settings = match settings.get::<HashMap<String, PluginSettings>>("plugins") {
+        Ok(plugin_settings) => plugin_settings,
+        Err(config::ConfigError::NotFound(_)) => HashMap::new(),
+        Err(e) => return Err(e.into()),
+    };
+    let max_cost = match settings.get::<CostClass>("max_cost") {
+        Ok(max_cost) => max_cost,
+        Err(config::ConfigError::NotFound(_)) => CostClass::Expensive,
+        Err(e) => return Err(e.into()),
+    };
+    Ok((provider_settings, plugin_settings, max_cost))
+}
+
+/// Prefix of the names of the tables a plugin stores its chunks in, made of the characters of its name that are
+/// safe in table names
+fn plugin_table_prefix(name: &str) -> String {
+    let name: String = name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_lowercase() } else { '_' })
+        .collect();
+    format!("plugin_{}", name)
+}
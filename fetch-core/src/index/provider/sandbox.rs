@@ -0,0 +1,522 @@
+use std::{io::{self, Read, Write}, process::Stdio, sync::{LazyLock, atomic::{AtomicBool, Ordering}}, time::Duration};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use chrono::DateTime;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use tokio::{io::AsyncWriteExt, process::Command};
+use tracing::{instrument, warn};
+
+use crate::{app_config, index::{ChunkFile, ChunkType, embedding::sessions::{get_base_resource_dir, init_model_resource_directory}, provider::image}};
+
+/// Settings in the [sandbox] table of the providers.toml settings file
+#[derive(Debug, Clone, Deserialize)]
+pub struct SandboxSettings {
+    /// Directories whose files are untrusted, eg. because they were downloaded. PDFs and images in them are parsed
+    /// in a sandbox, see chunk_in_sandbox. Defaults to the Downloads directory of the user.
+    #[serde(default = "default_untrusted_directories")]
+    pub untrusted_directories: Vec<Utf8PathBuf>,
+    /// Memory the sandbox may allocate before it is stopped, in megabytes
+    #[serde(default = "default_memory_limit_mb")]
+    pub memory_limit_mb: u64,
+    /// Seconds the sandbox is given to parse a file before it is killed
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+impl Default for SandboxSettings {
+    fn default() -> Self {
+        SandboxSettings {
+            untrusted_directories: default_untrusted_directories(),
+            memory_limit_mb: default_memory_limit_mb(),
+            timeout_secs: default_timeout_secs(),
+        }
+    }
+}
+
+impl SandboxSettings {
+    /// Loads the sandbox settings from the providers.toml settings file, with defaults for anything not set
+    pub fn from_settings() -> Result<SandboxSettings, config::ConfigError> {
+        match app_config::get_providers_config()?.get::<SandboxSettings>("sandbox") {
+            Ok(settings) => Ok(settings),
+            Err(config::ConfigError::NotFound(_)) => Ok(SandboxSettings::default()),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// Formats whose parsers can be run in the sandbox
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) enum SandboxedFormat {
+    Pdf,
+    Image,
+}
+
+/// Runs this process as a sandbox worker, and exits once it has parsed its file, if it was started as one by
+/// chunk_in_sandbox. Otherwise, returns and allows this process to start sandbox workers of itself.
+///
+/// Binaries that index files must call this first thing in main, before reading their own arguments or setting up
+/// logging, as the worker prints its response to stdout. Until they do, files are parsed in-process even if they are untrusted, as the process cannot start
+/// workers of itself, eg. when the engine is embedded in another application.
+pub fn run_sandbox_worker_if_requested() {
+    if std::env::args_os().nth(1).is_some_and(|arg| arg == WORKER_ARG) {
+        std::process::exit(run_worker());
+    }
+    WORKERS_AVAILABLE.store(true, Ordering::Relaxed);
+}
+
+/// Whether the file is in an untrusted directory and should be parsed in the sandbox. Both are compared once
+/// canonicalized, so a file reached through a symlink or `..` into an untrusted directory is still untrusted. Files
+/// are never sandboxed if this process cannot start sandbox workers, see run_sandbox_worker_if_requested.
+pub(crate) fn is_untrusted(path: &Utf8Path) -> bool {
+    let path = canonicalize(path);
+    let untrusted = SETTINGS.untrusted_directories.iter().any(|directory| is_within(&path, &canonicalize(directory)));
+    if untrusted && !WORKERS_AVAILABLE.load(Ordering::Relaxed) {
+        warn!("File {} is untrusted, but this process cannot start sandbox workers, parsing it in-process", path);
+        return false;
+    }
+    untrusted
+}
+
+/// Parses the file in a separate process of this binary and returns its chunks, written to out_dir as they would
+/// be in-process. The process is limited to the memory and time in the sandbox settings and runs at a lower
+/// priority. A file that crashes the parser, or makes it exceed its limits, only fails to index rather than taking
+/// down the process indexing it.
+///
+/// On Linux the process also cannot gain privileges, and is confined with Landlock (see confine_worker) so that a
+/// file that takes over the parser can only read the file, the libraries and settings it is parsed with, and
+/// write to out_dir. On kernels without Landlock, and on macOS and Windows, the process has the filesystem and
+/// network access of the user. The memory and CPU time limits are only enforced on Unix. On Windows the process is
+/// only killed once it runs out of time.
+#[instrument(name = "sandbox", level = "debug", skip_all, fields(format = ?format, path = %path))]
+pub(crate) async fn chunk_in_sandbox(format: SandboxedFormat, path: &Utf8Path, out_dir: &Utf8Path,
+    sequence_number: u64) -> Result<Vec<ChunkFile>, anyhow::Error>
+{
+    let settings = &*SETTINGS;
+    let mut command = Command::new(std::env::current_exe()?);
+    command.arg(WORKER_ARG)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+    limit_worker(&mut command, settings);
+    let mut child = command.spawn()?;
+
+    let request = serde_json::to_string(&WorkerRequest {
+        format,
+        path: path.to_owned(),
+        out_dir: out_dir.to_owned(),
+        sequence_number,
+        #[cfg(feature = "pdf")]
+        pdfium_lib_path: crate::environment::PDFIUM_LIB_PATH.get().cloned(),
+        #[cfg(not(feature = "pdf"))]
+        pdfium_lib_path: None,
+        model_resource_dir: get_base_resource_dir(),
+    })?;
+    let mut stdin = child.stdin.take().expect("Sandbox worker stdin should be piped");
+    stdin.write_all(request.as_bytes()).await?;
+    // Closing stdin tells the worker the request is complete
+    drop(stdin);
+
+    let timeout = Duration::from_secs(settings.timeout_secs);
+    let output = tokio::time::timeout(timeout, child.wait_with_output()).await
+        .map_err(|_| anyhow::anyhow!("Sandbox did not finish parsing {} within {:?}", path, timeout))??;
+    // Workers that crash, or are stopped for exceeding their limits, do not print a response
+    let response: WorkerResponse = match serde_json::from_slice(&output.stdout) {
+        Ok(response) => response,
+        Err(_) => anyhow::bail!("Sandbox exited with {} while parsing {}: {}", output.status, path,
+            String::from_utf8_lossy(&output.stderr).trim()),
+    };
+    match response {
+        WorkerResponse::Chunks(chunks) => {
+            check_worker_chunks(&chunks, path, out_dir, sequence_number)?;
+            chunks.into_iter().map(ChunkFile::try_from).collect()
+        },
+        WorkerResponse::TooLargeToDecode => Err(image::too_large_to_decode().into()),
+        WorkerResponse::Error(error) => anyhow::bail!("Sandbox could not parse {}: {}", path, error),
+    }
+}
+
+// Private functions and structs
+
+/// First argument of the processes started as sandbox workers
+const WORKER_ARG: &str = "--fetch-sandbox-worker";
+
+static WORKERS_AVAILABLE: AtomicBool = AtomicBool::new(false);
+
+static SETTINGS: LazyLock<SandboxSettings> = LazyLock::new(|| SandboxSettings::from_settings().unwrap_or_else(|e| {
+    warn!("Could not load sandbox settings, using the defaults: {:?}", e);
+    SandboxSettings::default()
+}));
+
+/// The path with symlinks and `..` resolved, or the path as it is if it cannot be resolved, eg. as it does not exist
+fn canonicalize(path: &Utf8Path) -> Utf8PathBuf {
+    path.canonicalize_utf8().unwrap_or_else(|_| path.to_owned())
+}
+
+/// Whether the path is the directory or in it. Both should be canonicalized. Paths differing only in letter case
+/// name the same file on the default filesystems of macOS and Windows, so letter case is ignored there.
+fn is_within(path: &Utf8Path, directory: &Utf8Path) -> bool {
+    if cfg!(any(target_os = "macos", target_os = "windows")) {
+        Utf8PathBuf::from(path.as_str().to_lowercase()).starts_with(directory.as_str().to_lowercase())
+    } else {
+        path.starts_with(directory)
+    }
+}
+
+/// Checks that every chunk the worker returned is a chunk of the file it was sent, at the sequence number it was
+/// sent, written to out_dir. A worker taken over by the file it parsed could otherwise get other files, eg. files it
+/// was not allowed to read, indexed in place of its chunks. Any chunk failing the checks fails the whole response.
+fn check_worker_chunks(chunks: &[WorkerChunk], path: &Utf8Path, out_dir: &Utf8Path, sequence_number: u64)
+    -> Result<(), anyhow::Error>
+{
+    let out_dir = out_dir.canonicalize_utf8()?;
+    for chunk in chunks {
+        if chunk.original_file != path {
+            anyhow::bail!("Sandbox returned a chunk of {} while parsing {}", chunk.original_file, path);
+        }
+        if chunk.sequence_number != sequence_number {
+            anyhow::bail!("Sandbox returned a chunk at sequence number {} while parsing {} at {}",
+                chunk.sequence_number, path, sequence_number);
+        }
+        let chunkfile = chunk.chunkfile.canonicalize_utf8()
+            .map_err(|e| anyhow::anyhow!("Sandbox returned a chunk file {} that cannot be read: {}", chunk.chunkfile, e))?;
+        if !chunkfile.starts_with(&out_dir) {
+            anyhow::bail!("Sandbox returned a chunk file {} outside of {}", chunk.chunkfile, out_dir);
+        }
+    }
+    Ok(())
+}
+
+fn default_untrusted_directories() -> Vec<Utf8PathBuf> {
+    dirs::download_dir()
+        .and_then(|directory| Utf8PathBuf::from_path_buf(directory).ok())
+        .into_iter()
+        .collect()
+}
+
+fn default_memory_limit_mb() -> u64 {
+    2048
+}
+
+fn default_timeout_secs() -> u64 {
+    120
+}
+
+#[derive(Serialize, Deserialize)]
+struct WorkerRequest {
+    format: SandboxedFormat,
+    path: Utf8PathBuf,
+    out_dir: Utf8PathBuf,
+    sequence_number: u64,
+    /// See environment::init_resources, as the worker is not initialized by the binary
+    pdfium_lib_path: Option<Utf8PathBuf>,
+    /// Directory the tokenizers are loaded from, as chunks are sized by their tokens
+    model_resource_dir: Utf8PathBuf,
+}
+
+#[derive(Serialize, Deserialize)]
+enum WorkerResponse {
+    Chunks(Vec<WorkerChunk>),
+    /// The image would not fit in memory once decoded, so it can be indexed through the OS thumbnail instead
+    TooLargeToDecode,
+    Error(String),
+}
+
+/// ChunkFile as it is sent from the worker, as it is not serializable itself
+#[derive(Serialize, Deserialize)]
+struct WorkerChunk {
+    original_file: Utf8PathBuf,
+    chunk_channel: String,
+    chunk_sequence_id: f32,
+    original_file_id: Option<String>,
+    chunkfile: Utf8PathBuf,
+    chunk_type: String,
+    chunk_length: f32,
+    original_file_creation_millis: i64,
+    original_file_modified_millis: i64,
+    original_file_size: u64,
+    original_file_fingerprint: Option<String>,
+    original_file_tags: Map<String, Value>,
+    language: String,
+    sequence_number: u64,
+}
+
+impl From<ChunkFile> for WorkerChunk {
+    fn from(chunk: ChunkFile) -> Self {
+        WorkerChunk {
+            original_file: chunk.original_file,
+            chunk_channel: chunk.chunk_channel,
+            chunk_sequence_id: chunk.chunk_sequence_id,
+            original_file_id: chunk.original_file_id,
+            chunkfile: chunk.chunkfile,
+            chunk_type: match chunk.chunk_type {
+                ChunkType::Text => "text",
+                ChunkType::Image => "image",
+                ChunkType::Video => "video",
+                ChunkType::Audio => "audio",
+            }.to_owned(),
+            chunk_length: chunk.chunk_length,
+            original_file_creation_millis: chunk.original_file_creation_date.timestamp_millis(),
+            original_file_modified_millis: chunk.original_file_modified_date.timestamp_millis(),
+            original_file_size: chunk.original_file_size,
+            original_file_fingerprint: chunk.original_file_fingerprint,
+            original_file_tags: chunk.original_file_tags,
+            language: chunk.language,
+            sequence_number: chunk.sequence_number,
+        }
+    }
+}
+
+impl TryFrom<WorkerChunk> for ChunkFile {
+    type Error = anyhow::Error;
+
+    fn try_from(chunk: WorkerChunk) -> Result<Self, Self::Error> {
+        let chunk_type = match chunk.chunk_type.as_str() {
+            "text" => ChunkType::Text,
+            "image" => ChunkType::Image,
+            "video" => ChunkType::Video,
+            "audio" => ChunkType::Audio,
+            other => anyhow::bail!("Sandbox returned a chunk of unknown type {}", other),
+        };
+        let date = |millis| DateTime::from_timestamp_millis(millis)
+            .ok_or_else(|| anyhow::anyhow!("Sandbox returned a chunk with an invalid date {}", millis));
+        Ok(ChunkFile {
+            original_file: chunk.original_file,
+            chunk_channel: chunk.chunk_channel,
+            chunk_sequence_id: chunk.chunk_sequence_id,
+            original_file_id: chunk.original_file_id,
+            chunkfile: chunk.chunkfile,
+            chunk_type,
+            chunk_length: chunk.chunk_length,
+            original_file_creation_date: date(chunk.original_file_creation_millis)?,
+            original_file_modified_date: date(chunk.original_file_modified_millis)?,
+            original_file_size: chunk.original_file_size,
+            original_file_fingerprint: chunk.original_file_fingerprint,
+            original_file_tags: chunk.original_file_tags,
+            language: chunk.language,
+            user_tags: vec![],
+            sequence_number: chunk.sequence_number,
+        })
+    }
+}
+
+/// Reads the request from stdin, parses the file and prints the response to stdout, returning the exit code
+fn run_worker() -> i32 {
+    let mut request = String::new();
+    if let Err(e) = io::stdin().read_to_string(&mut request) {
+        eprintln!("Could not read sandbox request: {:?}", e);
+        return 1;
+    }
+    let request: WorkerRequest = match serde_json::from_str(&request) {
+        Ok(request) => request,
+        Err(e) => {
+            eprintln!("Could not parse sandbox request: {:?}", e);
+            return 1;
+        },
+    };
+
+    #[cfg(feature = "pdf")]
+    if let Some(pdfium_lib_path) = request.pdfium_lib_path {
+        // Nothing else has set it this early in the worker
+        let _ = crate::environment::PDFIUM_LIB_PATH.set(pdfium_lib_path);
+    }
+    init_model_resource_directory(&request.model_resource_dir);
+
+    #[cfg(target_os = "linux")]
+    if let Err(e) = std::fs::create_dir_all(&request.out_dir).and_then(|_| confine_worker(&request)) {
+        eprintln!("Could not confine sandbox worker: {:?}", e);
+        return 1;
+    }
+
+    let (path, out_dir, sequence_number) = (&request.path, &request.out_dir, request.sequence_number);
+    let chunked = match request.format {
+        #[cfg(feature = "pdf")]
+        SandboxedFormat::Pdf => super::pdf::chunk_pdf_blocking(path, out_dir, sequence_number),
+        #[cfg(not(feature = "pdf"))]
+        SandboxedFormat::Pdf => Err(anyhow::anyhow!("PDF support is not built in")),
+        SandboxedFormat::Image => image::chunk_image_blocking(path, out_dir, sequence_number),
+    };
+    let response = match chunked {
+        Ok(chunks) => WorkerResponse::Chunks(chunks.into_iter().map(WorkerChunk::from).collect()),
+        Err(e) if image::is_too_large_to_decode_error(&e) => WorkerResponse::TooLargeToDecode,
+        Err(e) => WorkerResponse::Error(format!("{:?}", e)),
+    };
+
+    let response = serde_json::to_vec(&response).expect("Sandbox response should always be serializable");
+    match io::stdout().write_all(&response).and_then(|_| io::stdout().flush()) {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("Could not write sandbox response: {:?}", e);
+            1
+        },
+    }
+}
+
+/// Limits the memory and CPU time of the worker, and lowers its priority
+#[cfg(unix)]
+fn limit_worker(command: &mut Command, settings: &SandboxSettings) {
+    let memory_limit = settings.memory_limit_mb.saturating_mul(1024 * 1024) as libc::rlim_t;
+    let cpu_limit = settings.timeout_secs as libc::rlim_t;
+    // Safety: only calls functions that are safe to call between fork and exec
+    unsafe {
+        command.pre_exec(move || {
+            let limits = [
+                (libc::RLIMIT_AS, memory_limit),
+                (libc::RLIMIT_CPU, cpu_limit),
+                // Crashes of the parser are expected, and should not leave core dumps of the file behind
+                (libc::RLIMIT_CORE, 0),
+            ];
+            for (resource, limit) in limits {
+                if libc::setrlimit(resource, &libc::rlimit { rlim_cur: limit, rlim_max: limit }) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+            #[cfg(target_os = "linux")]
+            if libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            libc::nice(10);
+            Ok(())
+        });
+    }
+}
+
+/// Confines the worker with Landlock to reading the file it parses, the libraries, models and settings it parses it
+/// with and the system libraries they load, and to writing its chunks to the output directory, for the rest of its
+/// life. On kernels that support it (Linux 6.7) the worker also cannot open TCP connections. Kernels without
+/// Landlock (before Linux 5.13, or with it disabled) leave the worker unconfined.
+#[cfg(target_os = "linux")]
+fn confine_worker(request: &WorkerRequest) -> io::Result<()> {
+    use landlock::*;
+
+    // Safety: passes no attributes, only asks for the version of Landlock the kernel supports
+    let abi = unsafe {
+        libc::syscall(libc::SYS_landlock_create_ruleset, std::ptr::null::<RulesetAttr>(), 0, CREATE_RULESET_VERSION)
+    };
+    if abi < 1 {
+        return match io::Error::last_os_error().raw_os_error() {
+            Some(libc::ENOSYS | libc::EOPNOTSUPP) => Ok(()),
+            _ => Err(io::Error::last_os_error()),
+        };
+    }
+
+    let mut handled_access_fs = ACCESS_FS_V1;
+    if abi >= 2 {
+        handled_access_fs |= ACCESS_FS_REFER;
+    }
+    if abi >= 3 {
+        handled_access_fs |= ACCESS_FS_TRUNCATE;
+    }
+    let attr = RulesetAttr {
+        handled_access_fs,
+        handled_access_net: if abi >= 4 { ACCESS_NET_BIND_TCP | ACCESS_NET_CONNECT_TCP } else { 0 },
+    };
+    // Kernels before network rules only know of the first field
+    let attr_size = if abi >= 4 { size_of::<RulesetAttr>() } else { size_of::<u64>() };
+    // Safety: attr outlives the call, and attr_size does not exceed it
+    let ruleset = unsafe { libc::syscall(libc::SYS_landlock_create_ruleset, &attr, attr_size, 0) };
+    if ruleset < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // Safety: the kernel returned a new file descriptor, owned from here on
+    let ruleset = unsafe { OwnedFd::from_raw_fd(ruleset as RawFd) };
+
+    let read_only = ACCESS_FS_READ_FILE | ACCESS_FS_READ_DIR | ACCESS_FS_EXECUTE;
+    let read_write = read_only | ACCESS_FS_WRITE_FILE | ACCESS_FS_TRUNCATE | ACCESS_FS_MAKE_REG | ACCESS_FS_MAKE_DIR
+        | ACCESS_FS_REMOVE_FILE | ACCESS_FS_REMOVE_DIR;
+    let mut rules: Vec<(Utf8PathBuf, u64)> = vec![
+        (request.path.clone(), ACCESS_FS_READ_FILE),
+        (request.out_dir.clone(), read_write),
+        (request.model_resource_dir.clone(), read_only),
+        (app_config::get_app_folder().to_owned(), read_only),
+    ];
+    if let Some(pdfium_lib_path) = &request.pdfium_lib_path {
+        let pdfium_lib_path = if pdfium_lib_path.as_str().is_empty() { Utf8Path::new(".") } else { pdfium_lib_path };
+        rules.push((pdfium_lib_path.to_owned(), read_only));
+    }
+    rules.extend(SYSTEM_READ_ONLY_DIRECTORIES.iter().map(|directory| (Utf8PathBuf::from(directory), read_only)));
+
+    for (path, allowed_access) in rules {
+        let file = match std::fs::OpenOptions::new().read(true).custom_flags(libc::O_PATH | libc::O_CLOEXEC)
+            .open(&path)
+        {
+            Ok(file) => file,
+            // Eg. /lib64 on distributions without it
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+        let allowed_access = if file.metadata()?.is_dir() { allowed_access } else { allowed_access & ACCESS_FS_FILE };
+        let rule = PathBeneathAttr { allowed_access: allowed_access & handled_access_fs, parent_fd: file.as_raw_fd() };
+        // Safety: rule and the file it refers to outlive the call
+        let added = unsafe {
+            libc::syscall(libc::SYS_landlock_add_rule, ruleset.as_raw_fd(), RULE_PATH_BENEATH, &rule, 0)
+        };
+        if added < 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+
+    // Safety: prctl and landlock_restrict_self only change the restrictions of this process
+    unsafe {
+        if libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != 0
+            || libc::syscall(libc::SYS_landlock_restrict_self, ruleset.as_raw_fd(), 0) != 0
+        {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+/// Directories the worker may read from, as the libraries it parses files with load the system libraries and
+/// configuration they depend on, eg. the C++ runtime and fonts of PDFium
+#[cfg(target_os = "linux")]
+const SYSTEM_READ_ONLY_DIRECTORIES: [&str; 6] = ["/usr", "/lib", "/lib64", "/etc", "/proc/self", "/sys/devices/system/cpu"];
+
+/// Landlock ABI, see the landlock(7) man page
+#[cfg(target_os = "linux")]
+mod landlock {
+    pub(super) use std::os::{fd::{AsRawFd, FromRawFd, OwnedFd, RawFd}, unix::fs::OpenOptionsExt};
+
+    pub(super) const CREATE_RULESET_VERSION: u32 = 1 << 0;
+    pub(super) const RULE_PATH_BENEATH: u32 = 1;
+
+    pub(super) const ACCESS_FS_EXECUTE: u64 = 1 << 0;
+    pub(super) const ACCESS_FS_WRITE_FILE: u64 = 1 << 1;
+    pub(super) const ACCESS_FS_READ_FILE: u64 = 1 << 2;
+    pub(super) const ACCESS_FS_READ_DIR: u64 = 1 << 3;
+    pub(super) const ACCESS_FS_REMOVE_DIR: u64 = 1 << 4;
+    pub(super) const ACCESS_FS_REMOVE_FILE: u64 = 1 << 5;
+    pub(super) const ACCESS_FS_MAKE_DIR: u64 = 1 << 7;
+    pub(super) const ACCESS_FS_MAKE_REG: u64 = 1 << 8;
+    /// Every access right of the first version of Landlock
+    pub(super) const ACCESS_FS_V1: u64 = (1 << 13) - 1;
+    pub(super) const ACCESS_FS_REFER: u64 = 1 << 13;
+    pub(super) const ACCESS_FS_TRUNCATE: u64 = 1 << 14;
+    /// Access rights that apply to files rather than directories
+    pub(super) const ACCESS_FS_FILE: u64 = ACCESS_FS_EXECUTE | ACCESS_FS_WRITE_FILE | ACCESS_FS_READ_FILE
+        | ACCESS_FS_TRUNCATE;
+
+    pub(super) const ACCESS_NET_BIND_TCP: u64 = 1 << 0;
+    pub(super) const ACCESS_NET_CONNECT_TCP: u64 = 1 << 1;
+
+    #[repr(C)]
+    pub(super) struct RulesetAttr {
+        pub(super) handled_access_fs: u64,
+        pub(super) handled_access_net: u64,
+    }
+
+    #[repr(C, packed)]
+    pub(super) struct PathBeneathAttr {
+        pub(super) allowed_access: u64,
+        pub(super) parent_fd: i32,
+    }
+}
+
+/// Lowers the priority of the worker and keeps it from opening a console window
+#[cfg(windows)]
+fn limit_worker(command: &mut Command, _settings: &SandboxSettings) {
+    const CREATE_NO_WINDOW: u32 = 0x08000000;
+    const BELOW_NORMAL_PRIORITY_CLASS: u32 = 0x00004000;
+    command.creation_flags(CREATE_NO_WINDOW | BELOW_NORMAL_PRIORITY_CLASS);
+}
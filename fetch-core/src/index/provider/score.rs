@@ -0,0 +1,175 @@
+use std::{collections::HashMap, fs, sync::{LazyLock, atomic::{AtomicBool, Ordering}}};
+
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+
+use crate::{app_config, index::{ChunkFile, ChunkType, provider::ChunkQueryResult}};
+
+/// Range of the raw similarity scores of the chunks an embedding model finds, from the score of a chunk that has
+/// nothing to do with the query (min) to that of a chunk that matches it as well as chunks get (max). Models score
+/// on very different scales, eg. SigLIP 2 rarely scores an image above 0.3, while EmbeddingGemma scores matching
+/// text well above 0.5, so raw scores are mapped onto 0-100 by the range of the model before they are compared.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ScoreRange {
+    pub min: f32,
+    pub max: f32,
+}
+
+impl ScoreRange {
+    /// Range of EmbeddingGemma, which text chunks are embedded with
+    pub const TEXT: ScoreRange = ScoreRange { min: 0.1, max: 1.0 };
+    /// Range of SigLIP 2, which image chunks are embedded with
+    pub const IMAGE: ScoreRange = ScoreRange { min: 0.05, max: 0.3 };
+
+    /// The default range of chunks of the type, by the model chunks of the type are embedded with
+    pub fn default_for(chunk_type: ChunkType) -> ScoreRange {
+        match chunk_type {
+            ChunkType::Image | ChunkType::Video => ScoreRange::IMAGE,
+            ChunkType::Text | ChunkType::Audio => ScoreRange::TEXT,
+        }
+    }
+
+    /// Maps the raw score onto 0-100, or None if it is under the range
+    pub fn normalize(&self, raw_score: f32) -> Option<f32> {
+        (raw_score >= self.min).then(|| (raw_score - self.min) / (self.max - self.min) * 100.0)
+    }
+
+    fn is_valid(&self) -> bool {
+        self.min.is_finite() && self.max.is_finite() && self.min < self.max
+    }
+}
+
+/// Score ranges measured on the user's own files and labeled queries by `fetch eval --calibrate`, by provider name
+/// and chunk type, eg. the range of the image chunks of PdfIndexProvider. Kept in the score calibration file, see
+/// app_config::get_score_calibration_file_path.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ScoreCalibration {
+    ranges: HashMap<String, HashMap<String, ScoreRange>>,
+}
+
+impl ScoreCalibration {
+    /// Loads the calibrated ranges. Returns no ranges if nothing has been calibrated yet.
+    pub fn load() -> Result<ScoreCalibration, anyhow::Error> {
+        let calibration_file = app_config::get_score_calibration_file_path();
+        if !fs::exists(&calibration_file)? {
+            return Ok(ScoreCalibration::default());
+        }
+
+        let contents = fs::read_to_string(&calibration_file)?;
+        Ok(serde_json::from_str(&contents)?)
+    }
+
+    /// Saves the calibrated ranges, which are used by processes started after saving them
+    pub fn save(&self) -> Result<(), anyhow::Error> {
+        let calibration_file = app_config::get_score_calibration_file_path();
+        if let Some(parent) = calibration_file.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        fs::write(&calibration_file, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// The calibrated range of the chunks of the type found by the provider, if it has been calibrated
+    pub fn range(&self, provider_name: &str, chunk_type: ChunkType) -> Option<ScoreRange> {
+        self.ranges.get(provider_name)?.get(chunk_type_name(chunk_type)).copied()
+    }
+
+    /// Sets the range of the chunks of the type found by the provider. Invalid ranges, eg. with a min not under
+    /// the max, are ignored.
+    pub fn set_range(&mut self, provider_name: &str, chunk_type: ChunkType, range: ScoreRange) -> &mut Self {
+        if range.is_valid() {
+            self.ranges.entry(provider_name.to_owned()).or_default()
+                .insert(chunk_type_name(chunk_type).to_owned(), range);
+        } else {
+            warn!("Ignoring invalid score range of {} {} chunks: {:?}", provider_name, chunk_type_name(chunk_type),
+                range);
+        }
+        self
+    }
+}
+
+/// Maps the raw scores of the chunks a provider found onto 0-100, by the calibrated range of the provider and the
+/// type of the chunk if there is one, and the default range of the chunk type otherwise (see ScoreRange), so a
+/// score of 72 means the same relevance whichever provider and model found the chunk. Chunks scoring under the
+/// range are dropped.
+pub struct ScoreNormalizer {
+    provider_name: String,
+}
+
+impl ScoreNormalizer {
+    pub fn for_provider(provider_name: &str) -> ScoreNormalizer {
+        ScoreNormalizer { provider_name: provider_name.to_owned() }
+    }
+
+    /// The range the raw scores of chunks of the type are normalized by
+    pub fn range(&self, chunk_type: ChunkType) -> ScoreRange {
+        CALIBRATION.range(&self.provider_name, chunk_type).unwrap_or_else(|| ScoreRange::default_for(chunk_type))
+    }
+
+    /// The chunk along with its normalized score, or None if its raw score is under the range of its type
+    pub fn normalize(&self, chunkfile: ChunkFile, raw_score: f32) -> Option<ChunkQueryResult> {
+        let range = self.range(chunkfile.chunk_type);
+        match range.normalize(raw_score) {
+            Some(score) => {
+                debug!("{}: Normalized result score: orig: {}, chunkfile: {}, orig_score: {}, norm_score: {}",
+                    self.provider_name, chunkfile.original_file, chunkfile.chunkfile, raw_score, score);
+                Some(ChunkQueryResult::with_raw_score(chunkfile, score, raw_score))
+            },
+            None if KEEP_UNDER_RANGE.load(Ordering::Relaxed) => {
+                Some(ChunkQueryResult::with_raw_score(chunkfile, 0.0, raw_score))
+            },
+            None => {
+                debug!("{}: Result score is under minimum threshold: orig: {}, chunkfile: {}, orig_score: {}",
+                    self.provider_name, chunkfile.original_file, chunkfile.chunkfile, raw_score);
+                None
+            },
+        }
+    }
+
+    /// Normalizes the chunks, dropping those under the range of their type
+    pub fn normalize_all(&self, chunks: impl IntoIterator<Item = (f32, ChunkFile)>) -> Vec<ChunkQueryResult> {
+        chunks.into_iter()
+            .filter_map(|(raw_score, chunkfile)| self.normalize(chunkfile, raw_score))
+            .collect()
+    }
+
+    /// The page of chunks after offset, num_results long, of the chunks found in all the stores of the provider,
+    /// ranked by their normalized scores. Providers that keep chunks in more than one store query each of them for
+    /// the first offset + num_results chunks and page the chunks here, so the page is ranked across the stores and
+    /// the same offset skips the same chunks however they are spread over the stores.
+    pub fn normalize_page(&self, chunks: impl IntoIterator<Item = (f32, ChunkFile)>, num_results: u32, offset: u32)
+        -> Vec<ChunkQueryResult>
+    {
+        let mut results = self.normalize_all(chunks);
+        results.sort_by(|a, b| b.score().total_cmp(&a.score()));
+        results.into_iter().skip(offset as usize).take(num_results as usize).collect()
+    }
+}
+
+/// Makes normalizers keep chunks under their range with a score of 0 instead of dropping them, for the whole
+/// process. Used while calibrating, which needs the raw scores of the chunks that do not match too.
+pub fn keep_chunks_under_range(keep: bool) {
+    KEEP_UNDER_RANGE.store(keep, Ordering::Relaxed);
+}
+
+// Private variables and functions
+
+static KEEP_UNDER_RANGE: AtomicBool = AtomicBool::new(false);
+
+static CALIBRATION: LazyLock<ScoreCalibration> = LazyLock::new(|| {
+    ScoreCalibration::load().unwrap_or_else(|e| {
+        warn!("Could not load score calibration, using the default score ranges: {:?}", e);
+        ScoreCalibration::default()
+    })
+});
+
+/// Name of the chunk type in the score calibration file
+fn chunk_type_name(chunk_type: ChunkType) -> &'static str {
+    match chunk_type {
+        ChunkType::Text => "text",
+        ChunkType::Image => "image",
+        ChunkType::Video => "video",
+        ChunkType::Audio => "audio",
+    }
+}
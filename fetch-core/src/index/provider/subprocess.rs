@@ -0,0 +1,463 @@
+use std::{collections::HashSet, fs::Metadata, process::Stdio, sync::{Arc, LazyLock, Mutex}, time::Duration};
+
+use async_trait::async_trait;
+use camino::{Utf8Path, Utf8PathBuf};
+use chrono::{DateTime, Utc};
+use futures::TryFutureExt;
+use serde::{Deserialize, Serialize};
+use serde_json::Map;
+use tokio::{io::AsyncWriteExt, join, process::Command};
+use tracing::{debug, info, instrument};
+
+use crate::{files::{dupes, os_path::to_os_path, volumes}, index::{ChunkFile, ChunkType, embedding::{embeddinggemma::{self, EmbeddingGemmaEmbeddedChunkFile}, siglip2::{self, Siglip2EmbeddedChunkFile}}, language::{self, UNDETERMINED_LANGUAGE}, provider::{ChunkQueryFilters, ChunkQueryResult, ChunkingIndexProvider, IndexProviderError, IndexProviderErrorType, IndexedFile, chunkfile_vocabulary, clear_chunkfiles, create_chunkfile_dir, directory_prefix, indexed_file_of, indexed_paths_by_content_of, query_related_chunks, rename_chunks, unlink_chunkfile, registry::{CostClass, ProviderCapabilities}, score::ScoreNormalizer}}, sequence, store::{ChunkStore, Filter, FilterRelation, FilterValue, KeyedSequencedData}};
+
+/// Version of the plugin protocol spoken by SubprocessIndexProvider, sent with every request
+pub const PLUGIN_PROTOCOL_VERSION: u32 = 1;
+
+/// Settings of a plugin in the [plugins] tables of the providers.toml settings file, keyed by the name of the
+/// plugin.
+#[derive(Debug, Clone, Deserialize)]
+pub struct PluginSettings {
+    /// Program run to chunk each file, and the arguments it is run with
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Extensions of the files the plugin indexes, without the leading dot
+    #[serde(default)]
+    pub extensions: Vec<String>,
+    /// MIME types of the files the plugin indexes, see registry::route
+    #[serde(default)]
+    pub mime_types: Vec<String>,
+    #[serde(default = "default_cost")]
+    pub cost: CostClass,
+    #[serde(default)]
+    pub priority: i32,
+    /// Seconds the plugin is given to chunk a file before it is killed
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+/// Index provider that chunks files with an external program, so formats Fetch does not understand (eg. CAD
+/// drawings, DICOM scans, exports of note taking apps) can be indexed without rebuilding Fetch.
+///
+/// The program is run once for every file indexed. It is sent a single line of JSON on stdin:
+///
+/// `{"version": 1, "path": "/path/to/file.dcm", "out_dir": "/path/to/chunk/dir"}`
+///
+/// and must print a single JSON object on stdout, then exit with status 0:
+///
+/// `{"chunks": [{"type": "text", "text": "..."}, {"type": "image", "file": "slice-0.png"}]}`
+///
+/// Text chunks are embedded with EmbeddingGemma, and image chunks, which the program writes to out_dir (relative
+/// paths are relative to out_dir), are embedded with SigLIP 2. A program that cannot chunk the file prints
+/// `{"error": "..."}` instead, or exits with a non-zero status, in which case stderr is reported. Chunks are stored
+/// in tables of the plugin's own, so they are only returned by queries to this provider.
+pub struct SubprocessIndexProvider {
+    name: &'static str,
+    settings: PluginSettings,
+    mime_types: Vec<&'static str>,
+    text_store: Arc<dyn ChunkStore<EmbeddingGemmaEmbeddedChunkFile>>,
+    image_store: Arc<dyn ChunkStore<Siglip2EmbeddedChunkFile>>,
+}
+
+impl SubprocessIndexProvider {
+    /// Creates the provider for the plugin with the given name, which is also the name of the provider
+    pub fn using(name: &str, settings: PluginSettings, text_store: Arc<dyn ChunkStore<EmbeddingGemmaEmbeddedChunkFile>>, image_store: Arc<dyn ChunkStore<Siglip2EmbeddedChunkFile>>) -> Self {
+        let mime_types = settings.mime_types.iter().map(|m| intern(m)).collect();
+        SubprocessIndexProvider { name: intern(name), settings, mime_types, text_store, image_store }
+    }
+
+    fn error(&self, r#type: IndexProviderErrorType) -> IndexProviderError {
+        IndexProviderError { provider_name: self.name.to_string(), r#type }
+    }
+
+    /// Runs the plugin on the file, returning the chunks it produced
+    #[instrument(name = "plugin", level = "debug", skip_all, fields(plugin = %self.name, path = %path))]
+    async fn run_plugin(&self, path: &Utf8Path, out_dir: &Utf8Path) -> Result<Vec<PluginChunk>, anyhow::Error> {
+        let mut child = Command::new(&self.settings.command)
+            .args(&self.settings.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()?;
+
+        let request = serde_json::to_string(&PluginRequest { version: PLUGIN_PROTOCOL_VERSION, path, out_dir })?;
+        let mut stdin = child.stdin.take().expect("Plugin stdin should be piped");
+        stdin.write_all(request.as_bytes()).await?;
+        stdin.write_all(b"\n").await?;
+        // Closing stdin tells the plugin the request is complete
+        drop(stdin);
+
+        let timeout = Duration::from_secs(self.settings.timeout_secs);
+        let output = tokio::time::timeout(timeout, child.wait_with_output()).await
+            .map_err(|_| anyhow::anyhow!("Plugin {} did not finish within {:?}", self.name, timeout))??;
+        if !output.status.success() {
+            anyhow::bail!("Plugin {} exited with {}: {}", self.name, output.status,
+                String::from_utf8_lossy(&output.stderr).trim());
+        }
+
+        let response: PluginResponse = serde_json::from_slice(&output.stdout)?;
+        match response.error {
+            Some(error) => anyhow::bail!("Plugin {} could not chunk the file: {}", self.name, error),
+            None => Ok(response.chunks),
+        }
+    }
+}
+
+#[async_trait]
+impl ChunkingIndexProvider for SubprocessIndexProvider {
+    fn provider_name(&self) -> &'static str {
+        self.name
+    }
+
+    fn provides_indexing_for_extension(&self, ext: &str) -> bool {
+        self.settings.extensions.iter().any(|e| e.eq_ignore_ascii_case(ext))
+    }
+
+    fn capabilities(&self) -> ProviderCapabilities {
+        ProviderCapabilities { mime_types: self.mime_types.clone(), cost: self.settings.cost }
+    }
+
+    async fn index(&self, path: &Utf8Path, opt_modified: Option<DateTime<Utc>>) -> Result<(), IndexProviderError> {
+        debug!("Subprocess Index Provider {}: Indexing file at path: {}", self.name, path);
+        let metadata = tokio::fs::metadata(to_os_path(path)).await
+            .map_err(|e| self.error(IndexProviderErrorType::IO { path: path.to_string(), source: e.into() }))?;
+
+        // Same as the other providers, stored chunks of the file are only replaced by chunks of a newer version
+        let discover_filter = &[Filter {
+            attribute: ChunkFile::ORIGINAL_FILE_ATTR,
+            filter: FilterValue::String(path.as_str()),
+            relation: FilterRelation::Eq,
+        }];
+        let discovered_chunks: (Option<ChunkFile>, Option<ChunkFile>) = futures::try_join!(
+            self.text_store.query_filter_n(discover_filter, 1, 0)
+                .map_ok(|vec| vec.into_iter().map(|ec| ec.chunkfile).next()),
+            self.image_store.query_filter_n(discover_filter, 1, 0)
+                .map_ok(|vec| vec.into_iter().map(|ec| ec.chunkfile).next()),
+        ).map_err(|e| self.error(IndexProviderErrorType::Store { operation: "query filter", source: e.into() }))?;
+
+        if let Some(discovered_chunk) = discovered_chunks.0.or(discovered_chunks.1) {
+            sequence::observe(discovered_chunk.sequence_number);
+            let last_modified: DateTime<Utc> = opt_modified.unwrap_or(DateTime::from(metadata.modified()
+                .expect("File modified datetime not available on this platform")));
+            let stored_modified = discovered_chunk.original_file_modified_date;
+            if last_modified.timestamp_millis() <= stored_modified.timestamp_millis() {
+                info!("Attempted indexing on file: {} but the stored modified_date ({}) was equal to or later than the \
+                    file's modified_date ({}). Ignoring.", path, stored_modified, last_modified);
+                return Ok(());
+            }
+
+            self.clear(path, Some(last_modified)).await?;
+        }
+
+        let sequence_number = sequence::next();
+
+        let chunk_out_dir = create_chunkfile_dir(path).await
+            .map_err(|e| self.error(IndexProviderErrorType::IO { path: path.to_string(), source: e.into() }))?;
+
+        debug!("Subprocess Index Provider {}: Running plugin on path: {} with out_dir: {}", self.name, path,
+            chunk_out_dir);
+        let chunkfiles = self.run_plugin(path, &chunk_out_dir).await
+            .and_then(|chunks| create_chunkfiles(chunks, path, &metadata, &chunk_out_dir, sequence_number))
+            .map_err(|e| self.error(IndexProviderErrorType::Chunking { path: path.to_string(), source: e }))?;
+
+        debug!("Subprocess Index Provider {}: Embedding chunks at dir: {}", self.name, chunk_out_dir);
+        let mut embedded_text_chunkfiles = vec![];
+        let mut embedded_image_chunkfiles = vec![];
+        for chunkfile in chunkfiles {
+            debug!("Subprocess Index Provider {}: Embedding chunk with id: {}", self.name, chunkfile.get_key());
+            match chunkfile.chunk_type {
+                ChunkType::Text => {
+                    embedded_text_chunkfiles.push(embeddinggemma::embed_chunk(chunkfile).await
+                        .map_err(|e| self.error(IndexProviderErrorType::Embedding { source: e }))?);
+                },
+                ChunkType::Image => {
+                    embedded_image_chunkfiles.push(siglip2::embed_chunk(chunkfile).await
+                        .map_err(|e| self.error(IndexProviderErrorType::Embedding { source: e }))?);
+                },
+                _ => unreachable!("Plugins should only produce text and image chunks"),
+            }
+        }
+
+        debug!("Subprocess Index Provider {}: Storing chunks and embeddings for path: {}", self.name, path);
+        futures::try_join!(
+            self.text_store.put(embedded_text_chunkfiles),
+            self.image_store.put(embedded_image_chunkfiles),
+        ).map_err(|e| self.error(IndexProviderErrorType::Store { operation: "put", source: e.into() }))?;
+
+        Ok(())
+    }
+
+    async fn clear(&self, path: &Utf8Path, opt_modified: Option<DateTime<Utc>>) -> Result<(), IndexProviderError> {
+        debug!("Subprocess Index Provider {}: Clearing index of path: {}", self.name, path);
+        clear_chunkfiles(path).await
+            .map_err(|e| self.error(IndexProviderErrorType::IO { path: path.to_string(), source: e.into() }))?;
+
+        let mut filters = vec![Filter {
+            attribute: ChunkFile::ORIGINAL_FILE_ATTR,
+            filter: FilterValue::String(path.as_str()),
+            relation: FilterRelation::Eq,
+        }];
+        if let Some(modified_dt) = &opt_modified {
+            filters.push(Filter {
+                attribute: ChunkFile::FILE_MODIFIED_DATE_ATTR,
+                filter: FilterValue::DateTime(modified_dt),
+                relation: FilterRelation::Eq,
+            });
+        }
+        futures::try_join!(
+            self.text_store.clear_filter(&filters),
+            self.image_store.clear_filter(&filters)
+        ).map_err(|e| self.error(IndexProviderErrorType::Store { operation: "clear filter", source: e.into() }))?;
+
+        Ok(())
+    }
+
+    async fn restore(&self, path: &Utf8Path) -> Result<u64, IndexProviderError> {
+        debug!("Subprocess Index Provider {}: Restoring cleared chunks under path: {}", self.name, path);
+        let prefix = directory_prefix(path);
+        let mut restored = 0;
+        for filters in [
+            [Filter { attribute: ChunkFile::ORIGINAL_FILE_ATTR, filter: FilterValue::String(path.as_str()), relation: FilterRelation::Eq }],
+            [Filter { attribute: ChunkFile::ORIGINAL_FILE_ATTR, filter: FilterValue::String(&prefix), relation: FilterRelation::StartsWith }],
+        ] {
+            let (text_restored, image_restored) = futures::try_join!(
+                self.text_store.restore_filter(&filters),
+                self.image_store.restore_filter(&filters)
+            ).map_err(|e| self.error(IndexProviderErrorType::Store { operation: "restore filter", source: e.into() }))?;
+            restored += text_restored + image_restored;
+        }
+        Ok(restored)
+    }
+
+    async fn tag(&self, path: &Utf8Path, tags: &[String]) -> Result<(), IndexProviderError> {
+        debug!("Subprocess Index Provider {}: Tagging chunks of path: {} with tags: {:?}", self.name, path, tags);
+        let filters = &[Filter {
+            attribute: ChunkFile::ORIGINAL_FILE_ATTR,
+            filter: FilterValue::String(path.as_str()),
+            relation: FilterRelation::Eq,
+        }];
+        let (mut text_chunks, mut image_chunks) = futures::try_join!(
+            self.text_store.query_filter(filters),
+            self.image_store.query_filter(filters),
+        ).map_err(|e| self.error(IndexProviderErrorType::Store { operation: "query filter", source: e.into() }))?;
+        if text_chunks.is_empty() && image_chunks.is_empty() {
+            return Ok(());
+        }
+
+        for chunk in &mut text_chunks {
+            chunk.chunkfile.user_tags = tags.to_vec();
+        }
+        for chunk in &mut image_chunks {
+            chunk.chunkfile.user_tags = tags.to_vec();
+        }
+        futures::try_join!(
+            self.text_store.put(text_chunks),
+            self.image_store.put(image_chunks),
+        ).map_err(|e| self.error(IndexProviderErrorType::Store { operation: "put", source: e.into() }))?;
+
+        Ok(())
+    }
+
+    async fn rename(&self, from: &Utf8Path, to: &Utf8Path) -> Result<u64, IndexProviderError> {
+        debug!("Subprocess Index Provider {}: Renaming chunks of path: {} to: {}", self.name, from, to);
+        let (text_chunks, image_chunks) = futures::try_join!(
+            rename_chunks(self.text_store.as_ref(), from, to, self.name,
+                |c: &mut EmbeddingGemmaEmbeddedChunkFile| &mut c.chunkfile),
+            rename_chunks(self.image_store.as_ref(), from, to, self.name,
+                |c: &mut Siglip2EmbeddedChunkFile| &mut c.chunkfile),
+        )?;
+        Ok(text_chunks + image_chunks)
+    }
+
+    async fn indexed_file(&self, file_id: &str) -> Result<Option<IndexedFile>, IndexProviderError> {
+        let (text_file, image_file) = futures::try_join!(
+            indexed_file_of(self.text_store.as_ref(), file_id, self.name, |c: EmbeddingGemmaEmbeddedChunkFile| c.chunkfile),
+            indexed_file_of(self.image_store.as_ref(), file_id, self.name, |c: Siglip2EmbeddedChunkFile| c.chunkfile),
+        )?;
+        Ok(text_file.or(image_file))
+    }
+
+    async fn indexed_paths_by_content(&self, fingerprint: &str) -> Result<Vec<Utf8PathBuf>, IndexProviderError> {
+        let (text_paths, image_paths) = futures::try_join!(
+            indexed_paths_by_content_of(self.text_store.as_ref(), fingerprint, self.name, |c: EmbeddingGemmaEmbeddedChunkFile| c.chunkfile),
+            indexed_paths_by_content_of(self.image_store.as_ref(), fingerprint, self.name, |c: Siglip2EmbeddedChunkFile| c.chunkfile),
+        )?;
+        Ok(text_paths.into_iter().chain(image_paths).collect())
+    }
+
+    async fn query_n(&self, str: &str, filters: &ChunkQueryFilters, num_results: u32, offset: u32)
+        -> Result<Vec<ChunkQueryResult>, IndexProviderError>
+    {
+        debug!("Subprocess Index Provider {}: Querying index with params: {}, filters: {:?}, num_results: {}, \
+            offset: {}", self.name, str, filters, num_results, offset);
+        let store_filters = &filters.store_filters();
+        // Each store is queried from the start, and the chunks of both are paged together by their scores
+        let store_results = offset.saturating_add(num_results);
+
+        let text_chunk_future = async move {
+            let text_vec = embeddinggemma::embed_query(str).await
+                .map_err(|e| self.error(IndexProviderErrorType::Embedding { source: e }))?;
+            self.text_store.query_full_select_n(Some(text_vec), None, store_filters, ChunkFile::QUERY_RESULT_ATTRS, store_results, 0).await
+                .map_err(|e| self.error(IndexProviderErrorType::Store { operation: "query full", source: e }))
+        };
+        let image_chunk_future = async move {
+            let image_vec = siglip2::embed_query(str).await
+                .map_err(|e| self.error(IndexProviderErrorType::Embedding { source: e }))?;
+            self.image_store.query_full_select_n(Some(image_vec), None, store_filters, ChunkFile::QUERY_RESULT_ATTRS, store_results, 0).await
+                .map_err(|e| self.error(IndexProviderErrorType::Store { operation: "query full", source: e }))
+        };
+
+        let (text_result, image_result) = join!(text_chunk_future, image_chunk_future);
+        let chunks = text_result?.into_iter()
+            .map(|c| (c.score, c.result.chunkfile))
+            .chain(image_result?.into_iter().map(|c| (c.score, c.result.chunkfile)));
+
+        let results = ScoreNormalizer::for_provider(self.name).normalize_page(chunks, num_results, offset);
+        Ok(results)
+    }
+
+    async fn query_related_n(&self, path: &Utf8Path, num_results: u32)
+        -> Result<Vec<ChunkQueryResult>, IndexProviderError>
+    {
+        debug!("Subprocess Index Provider {}: Querying files related to path: {}, num_results: {}", self.name,
+            path, num_results);
+        let (text_chunks, image_chunks) = futures::try_join!(
+            query_related_chunks(self.text_store.as_ref(), path, num_results, self.name,
+                |c: EmbeddingGemmaEmbeddedChunkFile| (c.chunkfile, c.embedding)),
+            query_related_chunks(self.image_store.as_ref(), path, num_results, self.name,
+                |c: Siglip2EmbeddedChunkFile| (c.chunkfile, c.embedding)),
+        )?;
+
+        Ok(text_chunks.into_iter().chain(image_chunks).collect())
+    }
+
+    async fn vocabulary(&self) -> Result<Vec<String>, IndexProviderError> {
+        debug!("Subprocess Index Provider {}: Collecting vocabulary of indexed files", self.name);
+        let (text_chunks, image_chunks) = futures::try_join!(
+            self.text_store.query_filter(&[]),
+            self.image_store.query_filter(&[]),
+        ).map_err(|e| self.error(IndexProviderErrorType::Store { operation: "query by filter", source: e.into() }))?;
+
+        // Plugins may produce only text or only images for a file, so both stores are needed to cover every file
+        let mut seen_files = HashSet::new();
+        Ok(text_chunks.iter().map(|c| &c.chunkfile)
+            .chain(image_chunks.iter().map(|c| &c.chunkfile))
+            .filter(|c| seen_files.insert(c.original_file.clone()))
+            .flat_map(chunkfile_vocabulary)
+            .collect())
+    }
+}
+
+// private constants and functions
+
+const TEXT_CHUNK_CHANNEL: &str = "text";
+const IMAGE_CHUNK_CHANNEL: &str = "image";
+
+
+/// Plugin names and MIME types, which providers hand out as static strings. Interned so that opening the registry
+/// more than once does not leak a copy of each every time.
+static INTERNED: LazyLock<Mutex<HashSet<&'static str>>> = LazyLock::new(|| Mutex::new(HashSet::new()));
+
+fn intern(s: &str) -> &'static str {
+    let mut interned = INTERNED.lock().expect("Interned strings lock should not be poisoned");
+    match interned.get(s) {
+        Some(s) => s,
+        None => {
+            let leaked: &'static str = Box::leak(s.to_owned().into_boxed_str());
+            interned.insert(leaked);
+            leaked
+        },
+    }
+}
+
+fn default_cost() -> CostClass {
+    CostClass::Moderate
+}
+
+fn default_timeout_secs() -> u64 {
+    300
+}
+
+#[derive(Serialize)]
+struct PluginRequest<'a> {
+    version: u32,
+    path: &'a Utf8Path,
+    out_dir: &'a Utf8Path,
+}
+
+#[derive(Deserialize)]
+struct PluginResponse {
+    #[serde(default)]
+    chunks: Vec<PluginChunk>,
+    #[serde(default)]
+    error: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+enum PluginChunk {
+    Text { text: String },
+    Image { file: Utf8PathBuf },
+}
+
+/// Turns the chunks returned by a plugin into chunkfiles, writing text chunks out to the chunk directory. Each chunk
+/// is given a length of 1.0, in the order the plugin returned them.
+fn create_chunkfiles(
+    chunks: Vec<PluginChunk>,
+    path: &Utf8Path,
+    metadata: &Metadata,
+    out_dir: &Utf8Path,
+    sequence_number: u64,
+) -> Result<Vec<ChunkFile>, anyhow::Error> {
+    let file_creation: DateTime<Utc> = DateTime::from(metadata.created()
+        .expect("File creation datetime not available on this platform"));
+    let file_modified: DateTime<Utc> = DateTime::from(metadata.modified()
+        .expect("File modified datetime not available on this platform"));
+    let original_file_id = volumes::file_id(path);
+    let original_file_fingerprint = dupes::content_fingerprint(path);
+
+    let mut chunkfiles = vec![];
+    for (index, chunk) in chunks.into_iter().enumerate() {
+        let chunk_sequence = index as f32;
+        let (chunk_channel, chunkfile, chunk_type, tags_map, language) = match chunk {
+            PluginChunk::Text { text } => {
+                let chunkfile = out_dir.join(format!("{}-{}.txt", TEXT_CHUNK_CHANNEL, chunk_sequence));
+                unlink_chunkfile(&chunkfile)?;
+                std::fs::write(&chunkfile, &text)?;
+                let language = language::detect_language(&text).to_owned();
+                let mut tags_map = Map::new();
+                tags_map.insert("full_text".to_string(), text.into());
+                (TEXT_CHUNK_CHANNEL, chunkfile, ChunkType::Text, tags_map, language)
+            },
+            PluginChunk::Image { file } => {
+                let chunkfile = out_dir.join(file);
+                if !chunkfile.is_file() {
+                    anyhow::bail!("Plugin returned image chunk {} that does not exist", chunkfile);
+                }
+                (IMAGE_CHUNK_CHANNEL, chunkfile, ChunkType::Image, Map::new(), UNDETERMINED_LANGUAGE.to_owned())
+            },
+        };
+
+        chunkfiles.push(ChunkFile {
+            original_file: path.to_owned(),
+            chunk_channel: chunk_channel.to_owned(),
+            chunk_sequence_id: chunk_sequence,
+            original_file_id: original_file_id.clone(),
+            chunkfile,
+            chunk_type,
+            chunk_length: 1.0,
+            original_file_creation_date: file_creation,
+            original_file_modified_date: file_modified,
+            original_file_size: metadata.len(),
+            original_file_fingerprint: original_file_fingerprint.clone(),
+            original_file_tags: tags_map,
+            language,
+            user_tags: vec![],
+            sequence_number,
+        });
+    }
+
+    Ok(chunkfiles)
+}
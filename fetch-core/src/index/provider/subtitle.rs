@@ -0,0 +1,508 @@
+use std::{collections::HashSet, fs::Metadata, sync::Arc};
+
+use async_trait::async_trait;
+use camino::{Utf8Path, Utf8PathBuf};
+use chrono::{DateTime, Utc};
+use serde_json::Map;
+use tokio::task;
+use tracing::{debug, info, instrument};
+
+use crate::{files::{dupes, os_path::to_os_path, volumes}, index::{ChunkFile, ChunkType, embedding::embeddinggemma::{self, EmbeddingGemmaEmbeddedChunkFile}, language, provider::{ChunkQueryFilters, ChunkQueryResult, ChunkingIndexProvider, IndexProviderError, IndexProviderErrorType, IndexedFile, chunkfile_vocabulary, clear_chunkfiles, create_chunkfile_dir, directory_prefix, indexed_file_of, indexed_paths_by_content_of, query_related_chunks, rename_chunks, unlink_chunkfile, registry::{CostClass, ProviderCapabilities}, score::ScoreNormalizer}}, sequence, store::{ChunkStore, Filter, FilterRelation, FilterValue, KeyedSequencedData}};
+
+/// Index provider for subtitle and transcript files (.srt, .vtt). Cues are grouped into timestamped text chunks,
+/// whose chunk sequence is the time they start at in seconds, and whose start and end times are stored in the
+/// chunk tags. When a video with the same name sits next to the subtitles (eg. talk.mp4 for talk.srt or
+/// talk.en.srt), the chunks are indexed under the video instead, so searching for something said in the video
+/// finds the video, along with the time it is said at.
+pub struct SubtitleIndexProvider {
+    text_store: Arc<dyn ChunkStore<EmbeddingGemmaEmbeddedChunkFile>>,
+    hybrid_search: bool,
+}
+
+impl SubtitleIndexProvider {
+    pub fn using(text_store: Arc<dyn ChunkStore<EmbeddingGemmaEmbeddedChunkFile>>) -> Self {
+        SubtitleIndexProvider { text_store, hybrid_search: false }
+    }
+
+    /// Matches the words of queries with full text search along with their meaning, and adds up the scores of both,
+    /// see store::FusionWeights. Queries only match by meaning by default.
+    pub fn with_hybrid_search(mut self, enabled: bool) -> Self {
+        self.hybrid_search = enabled;
+        self
+    }
+}
+
+#[async_trait]
+impl ChunkingIndexProvider for SubtitleIndexProvider {
+    fn provider_name(&self) -> &'static str {
+        PROVIDER_NAME
+    }
+
+    fn provides_indexing_for_extension(&self, ext: &str) -> bool {
+        EXTENSIONS.contains(&ext)
+    }
+
+    fn capabilities(&self) -> ProviderCapabilities {
+        // Subtitles are plain text, so they cannot be sniffed and are routed by extension
+        ProviderCapabilities { mime_types: vec!["application/x-subrip", "text/vtt"], cost: CostClass::Cheap }
+    }
+
+    async fn index(&self, path: &Utf8Path, opt_modified: Option<DateTime<Utc>>) -> Result<(), IndexProviderError> {
+        debug!("Subtitle Index Provider: Indexing file at path: {}", path);
+        let contents = tokio::fs::read(to_os_path(path)).await
+            .map_err(|e| IndexProviderError {
+                provider_name: PROVIDER_NAME.to_string(),
+                r#type: IndexProviderErrorType::IO {
+                    path: path.to_string(),
+                    source: e.into(),
+                },
+            })?;
+        let metadata = tokio::fs::metadata(to_os_path(path)).await
+            .map_err(|e| IndexProviderError {
+                provider_name: PROVIDER_NAME.to_string(),
+                r#type: IndexProviderErrorType::IO {
+                    path: path.to_string(),
+                    source: e.into(),
+                },
+            })?;
+        let attributed_file = attributed_file(path);
+        let chunk_channel = chunk_channel(path);
+
+        // If the store has indexed chunks for this file, then check the stored original_file_modified_date to
+        // make sure it comes before the current file's modified date. If so, then make sure to clear the previously
+        // stored chunks from the store before proceeding.
+        let discover_filter = &[
+            Filter {
+                attribute: ChunkFile::ORIGINAL_FILE_ATTR,
+                filter: FilterValue::String(attributed_file.as_str()),
+                relation: FilterRelation::Eq,
+            },
+            Filter {
+                attribute: ChunkFile::CHUNK_CHANNEL_ATTR,
+                filter: FilterValue::String(&chunk_channel),
+                relation: FilterRelation::Eq,
+            },
+        ];
+        let discovered_chunk = self.text_store.query_filter_n(discover_filter, 1, 0).await
+            .map_err(|e| IndexProviderError {
+                provider_name: PROVIDER_NAME.to_string(),
+                r#type: IndexProviderErrorType::Store {
+                    operation: "query filter",
+                    source: e.into(),
+                }
+            })?
+            .into_iter().map(|ec| ec.chunkfile).next();
+
+        if let Some(discovered_chunk) = discovered_chunk {
+            sequence::observe(discovered_chunk.sequence_number);
+            let last_modified: DateTime<Utc> = opt_modified.unwrap_or(DateTime::from(metadata.modified()
+                .expect("File modified datetime not available on this platform")));
+            let stored_modified = discovered_chunk.original_file_modified_date;
+            if last_modified.timestamp_millis() <= stored_modified.timestamp_millis() {
+                info!("Attempted indexing on file: {} but the stored modified_date ({}) was equal to or later than the \
+                    file's modified_date ({}). Ignoring.", path, stored_modified, last_modified);
+                return Ok(());
+            }
+
+            self.clear(path, Some(last_modified)).await?;
+        }
+
+        let sequence_number = sequence::next();
+
+        let chunk_out_dir = create_chunkfile_dir(path).await
+            .map_err(|e| IndexProviderError {
+                provider_name: PROVIDER_NAME.to_string(),
+                r#type: IndexProviderErrorType::IO {
+                    path: path.to_string(),
+                    source: e.into(),
+                }
+            })?;
+
+        debug!("Subtitle Index Provider: Chunking file at path: {} to out_dir: {}, attributed to: {}", path,
+            chunk_out_dir, attributed_file);
+        let contents = String::from_utf8_lossy(&contents).into_owned();
+        let chunkfiles = chunk_subtitles(path, &attributed_file, &chunk_channel, contents, metadata,
+            &chunk_out_dir, sequence_number).await
+            .map_err(|e| IndexProviderError {
+                provider_name: PROVIDER_NAME.to_owned(),
+                r#type: IndexProviderErrorType::Chunking {
+                    path: path.to_string(),
+                    source: e,
+                }
+            })?;
+
+        debug!("Subtitle Index Provider: Embedding chunks at dir: {}", chunk_out_dir);
+        let mut embedded_chunkfiles = vec![];
+        for chunkfile in chunkfiles {
+            debug!("Subtitle Index Provider: Embedding chunk with id: {}", chunkfile.get_key());
+            embedded_chunkfiles.push(embeddinggemma::embed_chunk(chunkfile).await
+                .map_err(|e| IndexProviderError {
+                    provider_name: PROVIDER_NAME.to_string(),
+                    r#type: IndexProviderErrorType::Embedding { source: e },
+                })?);
+        }
+
+        debug!("Subtitle Index Provider: Storing chunks and embeddings for path: {}", path);
+        self.text_store.put(embedded_chunkfiles).await
+            .map_err(|e| IndexProviderError {
+                provider_name: PROVIDER_NAME.to_string(),
+                r#type: IndexProviderErrorType::Store {
+                    operation: "put",
+                    source: e.into(),
+                }
+            })?;
+
+        Ok(())
+    }
+
+    async fn clear(&self, path: &Utf8Path, opt_modified: Option<DateTime<Utc>>) -> Result<(), IndexProviderError> {
+        debug!("Subtitle Index Provider: Clearing index of path: {}", path);
+        clear_chunkfiles(path).await.map_err(|e| IndexProviderError {
+            provider_name: PROVIDER_NAME.to_string(),
+            r#type: IndexProviderErrorType::IO { path: path.to_string(), source: e.into() }
+        })?;
+
+        // The video the chunks were attributed to may have been removed since, so chunks indexed under either the
+        // subtitles or the video are cleared
+        let chunk_channel = chunk_channel(path);
+        for original_file in [path.to_owned(), attributed_file(path)] {
+            let mut filters = vec![
+                Filter {
+                    attribute: ChunkFile::ORIGINAL_FILE_ATTR,
+                    filter: FilterValue::String(original_file.as_str()),
+                    relation: FilterRelation::Eq,
+                },
+                Filter {
+                    attribute: ChunkFile::CHUNK_CHANNEL_ATTR,
+                    filter: FilterValue::String(&chunk_channel),
+                    relation: FilterRelation::Eq,
+                },
+            ];
+            if let Some(modified_dt) = &opt_modified {
+                filters.push(Filter {
+                    attribute: ChunkFile::FILE_MODIFIED_DATE_ATTR,
+                    filter: FilterValue::DateTime(modified_dt),
+                    relation: FilterRelation::Eq,
+                });
+            }
+            self.text_store.clear_filter(&filters).await.map_err(|e| IndexProviderError {
+                provider_name: PROVIDER_NAME.to_string(),
+                r#type: IndexProviderErrorType::Store {
+                    operation: "clear filter",
+                    source: e.into(),
+                }
+            })?;
+        }
+
+        Ok(())
+    }
+
+    async fn restore(&self, path: &Utf8Path) -> Result<u64, IndexProviderError> {
+        debug!("Subtitle Index Provider: Restoring cleared chunks under path: {}", path);
+        let prefix = directory_prefix(path);
+        let attributed_file = attributed_file(path);
+        let chunk_channel = chunk_channel(path);
+        let filter_sets = [
+            vec![Filter { attribute: ChunkFile::ORIGINAL_FILE_ATTR, filter: FilterValue::String(path.as_str()), relation: FilterRelation::Eq }],
+            vec![Filter { attribute: ChunkFile::ORIGINAL_FILE_ATTR, filter: FilterValue::String(&prefix), relation: FilterRelation::StartsWith }],
+            vec![
+                Filter { attribute: ChunkFile::ORIGINAL_FILE_ATTR, filter: FilterValue::String(attributed_file.as_str()), relation: FilterRelation::Eq },
+                Filter { attribute: ChunkFile::CHUNK_CHANNEL_ATTR, filter: FilterValue::String(&chunk_channel), relation: FilterRelation::Eq },
+            ],
+        ];
+        let mut restored = 0;
+        for filters in filter_sets.iter().take(if attributed_file == path { 2 } else { 3 }) {
+            restored += self.text_store.restore_filter(filters).await.map_err(|e| IndexProviderError {
+                provider_name: PROVIDER_NAME.to_string(),
+                r#type: IndexProviderErrorType::Store {
+                    operation: "restore filter",
+                    source: e.into(),
+                }
+            })?;
+        }
+        Ok(restored)
+    }
+
+    async fn tag(&self, path: &Utf8Path, tags: &[String]) -> Result<(), IndexProviderError> {
+        debug!("Subtitle Index Provider: Tagging chunks of path: {} with tags: {:?}", path, tags);
+        // Tags are looked up by the file the chunks are indexed under, so tagging a video tags its subtitles too
+        let filters = &[Filter {
+            attribute: ChunkFile::ORIGINAL_FILE_ATTR,
+            filter: FilterValue::String(path.as_str()),
+            relation: FilterRelation::Eq,
+        }];
+        let mut chunks = self.text_store.query_filter(filters).await.map_err(|e| IndexProviderError {
+            provider_name: PROVIDER_NAME.to_string(),
+            r#type: IndexProviderErrorType::Store {
+                operation: "query filter",
+                source: e.into(),
+            }
+        })?;
+        if chunks.is_empty() {
+            return Ok(());
+        }
+
+        for chunk in &mut chunks {
+            chunk.chunkfile.user_tags = tags.to_vec();
+        }
+        self.text_store.put(chunks).await.map_err(|e| IndexProviderError {
+            provider_name: PROVIDER_NAME.to_string(),
+            r#type: IndexProviderErrorType::Store {
+                operation: "put",
+                source: e.into(),
+            }
+        })?;
+
+        Ok(())
+    }
+
+    async fn rename(&self, from: &Utf8Path, to: &Utf8Path) -> Result<u64, IndexProviderError> {
+        debug!("Subtitle Index Provider: Renaming chunks of path: {} to: {}", from, to);
+        rename_chunks(self.text_store.as_ref(), from, to, PROVIDER_NAME, |c: &mut EmbeddingGemmaEmbeddedChunkFile| &mut c.chunkfile).await
+    }
+
+    async fn indexed_file(&self, file_id: &str) -> Result<Option<IndexedFile>, IndexProviderError> {
+        indexed_file_of(self.text_store.as_ref(), file_id, PROVIDER_NAME, |c: EmbeddingGemmaEmbeddedChunkFile| c.chunkfile).await
+    }
+
+    async fn indexed_paths_by_content(&self, fingerprint: &str) -> Result<Vec<Utf8PathBuf>, IndexProviderError> {
+        indexed_paths_by_content_of(self.text_store.as_ref(), fingerprint, PROVIDER_NAME, |c: EmbeddingGemmaEmbeddedChunkFile| c.chunkfile).await
+    }
+
+    async fn query_n(&self, str: &str, filters: &ChunkQueryFilters, num_results: u32, offset: u32)
+        -> Result<Vec<ChunkQueryResult>, IndexProviderError>
+    {
+        debug!("Subtitle Index Provider: Querying index of with params: {}, filters: {:?}, \
+            num_results: {}, offset: {}", str, filters, num_results, offset);
+        let text_vec = embeddinggemma::embed_query(str).await.map_err(|e| IndexProviderError {
+            provider_name: PROVIDER_NAME.to_string(),
+            r#type: IndexProviderErrorType::Embedding { source: e },
+        })?;
+
+        let chunks = self.text_store.query_full_select_n(
+            Some(text_vec),
+            self.hybrid_search.then_some(str),
+            &filters.store_filters(),
+            ChunkFile::QUERY_RESULT_ATTRS,
+            num_results,
+            offset
+        ).await.map_err(|e| IndexProviderError {
+            provider_name: PROVIDER_NAME.to_string(),
+            r#type: IndexProviderErrorType::Store {
+                operation: "query full",
+                source: e,
+            }
+        })?;
+
+        let results = ScoreNormalizer::for_provider(PROVIDER_NAME).normalize_all(chunks.into_iter().map(|c| (c.score, c.result.chunkfile)));
+        Ok(results)
+    }
+
+    async fn query_related_n(&self, path: &Utf8Path, num_results: u32)
+        -> Result<Vec<ChunkQueryResult>, IndexProviderError>
+    {
+        debug!("Subtitle Index Provider: Querying files related to path: {}, num_results: {}", path, num_results);
+        query_related_chunks(self.text_store.as_ref(), path, num_results, PROVIDER_NAME,
+            |c: EmbeddingGemmaEmbeddedChunkFile| (c.chunkfile, c.embedding)).await
+    }
+
+    async fn vocabulary(&self) -> Result<Vec<String>, IndexProviderError> {
+        debug!("Subtitle Index Provider: Collecting vocabulary of indexed files");
+        let chunks = self.text_store.query_filter(&[]).await.map_err(|e| IndexProviderError {
+            provider_name: PROVIDER_NAME.to_string(),
+            r#type: IndexProviderErrorType::Store {
+                operation: "query by filter",
+                source: e.into(),
+            }
+        })?;
+
+        let mut seen_files = HashSet::new();
+        Ok(chunks.iter()
+            .map(|c| &c.chunkfile)
+            .filter(|c| seen_files.insert(c.original_file.clone()))
+            .flat_map(chunkfile_vocabulary)
+            .collect())
+    }
+}
+
+// private constants and functions
+
+const PROVIDER_NAME: &str = "SubtitleIndexProvider";
+const EXTENSIONS: [&str; 2] = ["srt", "vtt"];
+/// Extensions of the videos subtitles are attributed to, in order of preference
+const VIDEO_EXTENSIONS: [&str; 8] = ["mp4", "mkv", "webm", "mov", "m4v", "avi", "wmv", "mpg"];
+
+/// Cues are grouped into chunks of up to this many tokens, or this many milliseconds, whichever comes first
+const CUE_CHUNK_MAX_TOKENS: u32 = 200;
+const CUE_CHUNK_MAX_MILLIS: u64 = 60_000;
+
+
+/// The video with the same name as the subtitles, or the subtitles themselves if there is none. Names with a
+/// language suffix, eg. talk.en.srt, also match the video without it.
+fn attributed_file(path: &Utf8Path) -> Utf8PathBuf {
+    let Some(stem) = path.file_stem() else {
+        return path.to_owned();
+    };
+    let stems = [Some(stem), Utf8Path::new(stem).file_stem().filter(|s| *s != stem)];
+    stems.into_iter().flatten()
+        .flat_map(|stem| VIDEO_EXTENSIONS.iter().map(move |ext| path.with_file_name(format!("{}.{}", stem, ext))))
+        .find(|video| to_os_path(video).is_file())
+        .unwrap_or_else(|| path.to_owned())
+}
+
+/// Channel of the chunks of a subtitle file. Every subtitle file attributed to a video has its own channel, so the
+/// chunks of eg. talk.en.srt and talk.fr.srt can both be indexed under talk.mp4.
+fn chunk_channel(path: &Utf8Path) -> String {
+    format!("subtitles:{}", path.file_name().unwrap_or(path.as_str()))
+}
+
+/// A subtitle cue, with times in milliseconds
+struct Cue {
+    start: u64,
+    end: u64,
+    text: String,
+}
+
+#[instrument(name = "chunk", level = "debug", skip_all, fields(path = %path))]
+async fn chunk_subtitles(
+    path: &Utf8Path,
+    attributed_file: &Utf8Path,
+    chunk_channel: &str,
+    contents: String,
+    metadata: Metadata,
+    out_dir: &Utf8Path,
+    sequence_number: u64,
+) -> Result<Vec<ChunkFile>, anyhow::Error> {
+    let (path, attributed_file, chunk_channel, out_dir) =
+        (path.to_owned(), attributed_file.to_owned(), chunk_channel.to_owned(), out_dir.to_owned());
+    task::spawn_blocking(move || {
+        let file_creation: DateTime<Utc> = DateTime::from(metadata.created()
+            .expect("File creation datetime not available on this platform"));
+        let file_modified: DateTime<Utc> = DateTime::from(metadata.modified()
+            .expect("File modified datetime not available on this platform"));
+        // Chunks are indexed under the attributed file, so they follow it when it is moved or renamed
+        let original_file_id = volumes::file_id(&attributed_file);
+        let original_file_fingerprint = dupes::content_fingerprint(&attributed_file);
+
+        let cues = parse_cues(&contents);
+        if cues.is_empty() {
+            anyhow::bail!("No subtitle cues found");
+        }
+
+        // Group consecutive cues into chunks
+        let mut groups: Vec<Vec<Cue>> = vec![];
+        let mut tokens = 0;
+        for cue in cues {
+            let cue_tokens = language::estimate_tokens(&cue.text);
+            let starts_group = match groups.last().and_then(|g| g.first()) {
+                Some(first) => tokens + cue_tokens > CUE_CHUNK_MAX_TOKENS || cue.end.saturating_sub(first.start) > CUE_CHUNK_MAX_MILLIS,
+                None => true,
+            };
+            if starts_group {
+                groups.push(vec![]);
+                tokens = 0;
+            }
+            tokens += cue_tokens;
+            groups.last_mut().expect("A group was just pushed").push(cue);
+        }
+
+        let mut chunkfiles = vec![];
+        for group in groups {
+            let start = group.first().map(|c| c.start).unwrap_or_default();
+            let end = group.iter().map(|c| c.end).max().unwrap_or(start);
+            let text = group.iter().map(|c| c.text.as_str()).collect::<Vec<_>>().join("\n");
+
+            // The chunk sequence is the time the chunk starts at, in seconds
+            let chunk_sequence = start as f32 / 1000.0;
+            let chunkfile = out_dir.join(format!("text-{}.txt", chunk_sequence));
+            unlink_chunkfile(&chunkfile)?;
+            std::fs::write(&chunkfile, &text)?;
+
+            let mut tags_map = Map::new();
+            tags_map.insert("full_text".to_string(), text.clone().into());
+            tags_map.insert("subtitle_file".to_string(), path.as_str().into());
+            tags_map.insert("start_ms".to_string(), start.into());
+            tags_map.insert("end_ms".to_string(), end.into());
+            tags_map.insert("timestamp".to_string(), format_timestamp(start).into());
+
+            chunkfiles.push(ChunkFile {
+                original_file: attributed_file.clone(),
+                chunk_channel: chunk_channel.clone(),
+                chunk_sequence_id: chunk_sequence,
+                original_file_id: original_file_id.clone(),
+                chunkfile,
+                chunk_type: ChunkType::Text,
+                chunk_length: end.saturating_sub(start) as f32 / 1000.0,
+                original_file_creation_date: file_creation,
+                original_file_modified_date: file_modified,
+                original_file_size: metadata.len(),
+                original_file_fingerprint: original_file_fingerprint.clone(),
+                original_file_tags: tags_map,
+                language: language::detect_language(&text).to_owned(),
+                user_tags: vec![],
+                sequence_number,
+            });
+        }
+
+        Ok(chunkfiles)
+    }).await?
+}
+
+/// Parses the cues of SubRip (.srt) and WebVTT (.vtt) subtitles. Both are blocks separated by blank lines, with a
+/// `start --> end` timing line followed by the text of the cue. Blocks without a timing line (eg. the WEBVTT
+/// header, NOTE and STYLE blocks) are skipped, and formatting tags are removed from the text.
+fn parse_cues(contents: &str) -> Vec<Cue> {
+    let contents = contents.replace("\r\n", "\n");
+    let mut cues = vec![];
+    for block in contents.split("\n\n") {
+        let mut lines = block.lines().skip_while(|line| !line.contains("-->"));
+        let Some(timing) = lines.next() else {
+            continue;
+        };
+        let Some((start, end)) = timing.split_once("-->") else {
+            continue;
+        };
+        // WebVTT cue settings follow the end time
+        let end = end.split_whitespace().next().unwrap_or("");
+        let (Some(start), Some(end)) = (parse_timestamp(start.trim()), parse_timestamp(end)) else {
+            continue;
+        };
+
+        let text = lines.map(strip_formatting).filter(|line| !line.trim().is_empty()).collect::<Vec<_>>().join(" ");
+        if !text.is_empty() {
+            cues.push(Cue { start, end, text });
+        }
+    }
+    cues
+}
+
+/// Parses a `[hh:]mm:ss[.,]mmm` timestamp into milliseconds
+fn parse_timestamp(timestamp: &str) -> Option<u64> {
+    let (clock, millis) = timestamp.split_once([',', '.']).unwrap_or((timestamp, "0"));
+    let mut seconds = 0;
+    for part in clock.split(':') {
+        seconds = seconds * 60 + part.trim().parse::<u64>().ok()?;
+    }
+    Some(seconds * 1000 + millis.trim().parse::<u64>().ok()?)
+}
+
+fn format_timestamp(millis: u64) -> String {
+    let seconds = millis / 1000;
+    format!("{:02}:{:02}:{:02}", seconds / 3600, (seconds / 60) % 60, seconds % 60)
+}
+
+/// Removes <i>-style tags and {\an8}-style override codes from a line of subtitle text
+fn strip_formatting(line: &str) -> String {
+    let mut stripped = String::with_capacity(line.len());
+    let mut closing = None;
+    for c in line.chars() {
+        match (closing, c) {
+            (None, '<') => closing = Some('>'),
+            (None, '{') => closing = Some('}'),
+            (None, c) => stripped.push(c),
+            (Some(end), c) if c == end => closing = None,
+            (Some(_), _) => (),
+        }
+    }
+    stripped
+}
@@ -0,0 +1,144 @@
+use std::sync::LazyLock;
+
+use async_trait::async_trait;
+use ndarray::{Array, Axis};
+use ort::{inputs, value::TensorRef};
+use tokenizers::{Tokenizer, TruncationParams, TruncationStrategy};
+use tokio::{fs, task};
+use tracing::{debug, instrument, warn};
+
+use crate::{index::embedding::{EmbeddingError, embeddinggemma::EmbeddingGemmaEmbeddedChunkFile, sessions::{Priority, SessionPool, SessionPoolExt, create_session_pool, create_tokenizer, get_base_resource_dir}}, store::{FullQueryResult, Reranking}};
+
+/// Reranker that rescores text chunks with an ONNX cross-encoder model, which reads the query and the chunk
+/// text together rather than comparing independently computed embeddings.
+///
+/// Only the top `top_n` results (by their existing score) are rescored, the rest are kept after them in their
+/// original order.
+pub struct CrossEncoderReranker {
+    top_n: usize,
+}
+
+impl CrossEncoderReranker {
+    /// Creates a reranker using the cross-encoder model in the model resource directory. Returns an error if
+    /// the model files are not present, since the model is not bundled with Fetch by default. The model itself
+    /// is loaded lazily on first use.
+    pub fn new(top_n: usize) -> Result<CrossEncoderReranker, EmbeddingError> {
+        let base_dir = get_base_resource_dir();
+        for path in [MODEL_PATH, TOKENIZER_PATH] {
+            if !base_dir.join(path).exists() {
+                return Err(EmbeddingError::Initialization(anyhow::anyhow!(
+                    "Cross-encoder resource not found at {}", base_dir.join(path))));
+            }
+        }
+
+        Ok(CrossEncoderReranker { top_n })
+    }
+
+    async fn score(query: &str, text: String) -> Result<f32, EmbeddingError> {
+        let query = query.to_owned();
+
+        task::spawn_blocking(move || -> Result<f32, EmbeddingError> {
+            let mut model = SESSION_POOL.get_session(Priority::Interactive);
+            let tokenizer = &TOKENIZER;
+
+            let encoding = tokenizer.encode((query.as_str(), text.as_str()), true)
+                .map_err(|e| EmbeddingError::Preprocessing {
+                    element: format!("Query: {}", query),
+                    step: "tokenizing",
+                    source: anyhow::anyhow!(e) })?;
+            let to_array = |ids: &[u32]| Array::from_vec(ids.iter().map(|n| *n as i64).collect::<Vec<i64>>())
+                .insert_axis(Axis(0));
+            let input = to_array(encoding.get_ids());
+            let att_mask = to_array(encoding.get_attention_mask());
+            let type_ids = to_array(encoding.get_type_ids());
+
+            let to_tensor_error = |e: ort::Error| EmbeddingError::Preprocessing {
+                element: format!("Query: {}", query),
+                step: "Converting to tensor",
+                source: e.into(),
+            };
+            let logit = model.run(inputs![
+                    "input_ids" => TensorRef::from_array_view(&input).map_err(to_tensor_error)?,
+                    "attention_mask" => TensorRef::from_array_view(&att_mask).map_err(to_tensor_error)?,
+                    "token_type_ids" => TensorRef::from_array_view(&type_ids).map_err(to_tensor_error)?,
+                ])
+                .map_err(|e| EmbeddingError::Calculation {
+                    element: format!("Query: {}", query),
+                    step: "Performing cross-encoder scoring", source: e.into(),
+                })?
+                .get("logits")
+                .expect("model should place output in 'logits' key")
+                .try_extract_array::<f32>()
+                .map_err(|e| EmbeddingError::Unknown {
+                    msg: "Error while extracting array from output as f32",
+                    source: e.into(),
+                })?
+                .iter()
+                .next()
+                .copied()
+                .expect("Model should return a (1, 1) shaped array of logits");
+
+            // Squash the logit into 0.0 - 1.0 so it is comparable to the cosine similarity scores it replaces
+            Ok(1.0 / (1.0 + (-logit).exp()))
+        })
+        .await
+        .map_err(|e| EmbeddingError::Unknown { msg: "Error while joining reranking blocking task",
+            source: e.into() })?
+    }
+}
+
+#[async_trait]
+impl Reranking<EmbeddingGemmaEmbeddedChunkFile> for CrossEncoderReranker {
+    #[instrument(name = "rerank", level = "debug", skip_all, fields(num_results = results.len()))]
+    async fn rerank(&self, query: &str, mut results: Vec<FullQueryResult<EmbeddingGemmaEmbeddedChunkFile>>)
+        -> Result<Vec<FullQueryResult<EmbeddingGemmaEmbeddedChunkFile>>, anyhow::Error>
+    {
+        results.sort_by(|a, b| b.score.total_cmp(&a.score));
+        let tail = results.split_off(self.top_n.min(results.len()));
+
+        let mut reranked = Vec::with_capacity(results.len() + tail.len());
+        for mut result in results {
+            let chunkfile = &result.result.chunkfile.chunkfile;
+            match fs::read_to_string(chunkfile).await {
+                Ok(text) => result.score = Self::score(query, text).await?,
+                // The chunk may have been cleared since the query ran, keep its original score
+                Err(e) => warn!("Could not read chunk {} for reranking, keeping original score: {}", chunkfile, e),
+            }
+            reranked.push(result);
+        }
+        reranked.sort_by(|a, b| b.score.total_cmp(&a.score));
+
+        // Results outside of the top N were not rescored, so keep them below every rescored result
+        let floor = reranked.last().map(|r| r.score).unwrap_or(f32::MAX);
+        reranked.extend(tail.into_iter().map(|mut r| {
+            r.score = r.score.min(floor);
+            r
+        }));
+
+        Ok(reranked)
+    }
+}
+
+// Private variables and functions
+
+const MODEL_INPUT_LENGTH: usize = 512;
+
+const MODEL_PATH: &str = "ms-marco-minilm-l6-v2/model.onnx";
+const TOKENIZER_PATH: &str = "ms-marco-minilm-l6-v2/tokenizer.json";
+
+static SESSION_POOL: LazyLock<SessionPool> = LazyLock::new(|| {
+    debug!("Initializing cross-encoder reranking resources");
+    create_session_pool(1, MODEL_PATH.into())
+});
+
+static TOKENIZER: LazyLock<Tokenizer> = LazyLock::new(|| {
+    debug!("Initializing cross-encoder tokenizer resources");
+    let mut tokenizer = create_tokenizer(TOKENIZER_PATH.into());
+    tokenizer.with_truncation(Some(TruncationParams {
+            max_length: MODEL_INPUT_LENGTH,
+            strategy: TruncationStrategy::OnlySecond,
+            ..Default::default()
+        }))
+        .expect("Cross-encoder truncation parameters should be valid");
+    tokenizer
+});
@@ -0,0 +1,158 @@
+use std::sync::LazyLock;
+
+use ndarray::{Array, Axis};
+use ort::{inputs, value::TensorRef};
+use tokenizers::{Tokenizer, TruncationParams};
+use tokio::task;
+use tracing::{debug, instrument};
+
+use crate::index::embedding::{EmbeddingError, sessions::{Priority, SessionPool, SessionPoolExt, create_session_pool, create_tokenizer, get_base_resource_dir}};
+
+/// Abstractive summarizer backed by an ONNX encoder-decoder model (DistilBART fine-tuned on CNN/DailyMail), which
+/// writes a few sentences summarizing the text it is given. Summaries are generated with greedy decoding.
+pub struct Summarizer {
+    max_summary_tokens: usize,
+}
+
+impl Summarizer {
+    /// Creates a summarizer using the summarization model in the model resource directory. Returns an error if
+    /// the model files are not present, since the model is not bundled with Fetch by default. The model itself
+    /// is loaded lazily on first use.
+    pub fn new() -> Result<Summarizer, EmbeddingError> {
+        let base_dir = get_base_resource_dir();
+        for path in [ENCODER_PATH, DECODER_PATH, TOKENIZER_PATH] {
+            if !base_dir.join(path).exists() {
+                return Err(EmbeddingError::Initialization(anyhow::anyhow!(
+                    "Summarization resource not found at {}", base_dir.join(path))));
+            }
+        }
+
+        Ok(Summarizer { max_summary_tokens: MAX_SUMMARY_TOKENS })
+    }
+
+    /// Summarizes the text. Text longer than the model's input length is truncated, so the text should be
+    /// ordered with the most important parts first.
+    #[instrument(name = "summarize", level = "debug", skip_all, fields(text_len = text.len()))]
+    pub async fn summarize(&self, text: String) -> Result<String, EmbeddingError> {
+        let max_summary_tokens = self.max_summary_tokens;
+        task::spawn_blocking(move || -> Result<String, EmbeddingError> {
+            let tokenizer = &TOKENIZER;
+            let element = || format!("Text: {}...", text.chars().take(50).collect::<String>());
+
+            let encoding = tokenizer.encode(text.as_str(), true)
+                .map_err(|e| EmbeddingError::Preprocessing {
+                    element: element(),
+                    step: "tokenizing",
+                    source: anyhow::anyhow!(e) })?;
+            let to_array = |ids: &[u32]| Array::from_vec(ids.iter().map(|n| *n as i64).collect::<Vec<i64>>())
+                .insert_axis(Axis(0));
+            let input = to_array(encoding.get_ids());
+            let att_mask = to_array(encoding.get_attention_mask());
+
+            let to_tensor_error = |e: ort::Error| EmbeddingError::Preprocessing {
+                element: element(),
+                step: "Converting to tensor",
+                source: e.into(),
+            };
+            let to_extract_error = |e: ort::Error| EmbeddingError::Unknown {
+                msg: "Error while extracting array from output as f32",
+                source: e.into(),
+            };
+
+            let mut encoder = ENCODER_POOL.get_session(Priority::Background);
+            let hidden_states = encoder.run(inputs![
+                    "input_ids" => TensorRef::from_array_view(&input).map_err(to_tensor_error)?,
+                    "attention_mask" => TensorRef::from_array_view(&att_mask).map_err(to_tensor_error)?,
+                ])
+                .map_err(|e| EmbeddingError::Calculation {
+                    element: element(),
+                    step: "Encoding text for summarization", source: e.into(),
+                })?
+                .get("last_hidden_state")
+                .expect("encoder should place output in 'last_hidden_state' key")
+                .try_extract_array::<f32>()
+                .map_err(to_extract_error)?
+                .into_owned();
+            drop(encoder);
+
+            // Greedily pick the most likely next token until the model ends the summary. The whole sequence is
+            // fed back into the decoder at every step, since the decoder is exported without a key/value cache.
+            let mut decoder = DECODER_POOL.get_session(Priority::Background);
+            let mut generated = vec![DECODER_START_TOKEN_ID];
+            while generated.len() <= max_summary_tokens {
+                let decoder_input = Array::from_vec(generated.clone()).insert_axis(Axis(0));
+                let outputs = decoder.run(inputs![
+                        "input_ids" => TensorRef::from_array_view(&decoder_input).map_err(to_tensor_error)?,
+                        "encoder_attention_mask" => TensorRef::from_array_view(&att_mask).map_err(to_tensor_error)?,
+                        "encoder_hidden_states" => TensorRef::from_array_view(&hidden_states).map_err(to_tensor_error)?,
+                    ])
+                    .map_err(|e| EmbeddingError::Calculation {
+                        element: element(),
+                        step: "Decoding summary", source: e.into(),
+                    })?;
+                let logits = outputs.get("logits")
+                    .expect("decoder should place output in 'logits' key")
+                    .try_extract_array::<f32>()
+                    .map_err(to_extract_error)?;
+
+                // Logits are shaped (1, sequence length, vocabulary size), only the last position is new
+                let next_token = logits.index_axis(Axis(1), generated.len() - 1)
+                    .iter()
+                    .enumerate()
+                    .max_by(|(_, l), (_, r)| l.total_cmp(r))
+                    .map(|(token, _)| token as i64)
+                    .expect("Decoder should return logits for at least one token");
+                if next_token == EOS_TOKEN_ID {
+                    break;
+                }
+                generated.push(next_token);
+            }
+
+            let ids = generated.iter().map(|id| *id as u32).collect::<Vec<u32>>();
+            let summary = tokenizer.decode(&ids, true)
+                .map_err(|e| EmbeddingError::Unknown {
+                    msg: "Error while decoding summary tokens",
+                    source: anyhow::anyhow!(e),
+                })?;
+
+            Ok(summary.trim().to_owned())
+        })
+        .await
+        .map_err(|e| EmbeddingError::Unknown { msg: "Error while joining summarization blocking task",
+            source: e.into() })?
+    }
+}
+
+// Private variables and functions
+
+const MODEL_INPUT_LENGTH: usize = 1024;
+const MAX_SUMMARY_TOKENS: usize = 142;
+
+// BART starts decoding from the end of sequence token
+const DECODER_START_TOKEN_ID: i64 = 2;
+const EOS_TOKEN_ID: i64 = 2;
+
+const ENCODER_PATH: &str = "distilbart-cnn-6-6/encoder_model.onnx";
+const DECODER_PATH: &str = "distilbart-cnn-6-6/decoder_model.onnx";
+const TOKENIZER_PATH: &str = "distilbart-cnn-6-6/tokenizer.json";
+
+static ENCODER_POOL: LazyLock<SessionPool> = LazyLock::new(|| {
+    debug!("Initializing summarization encoder resources");
+    create_session_pool(1, ENCODER_PATH.into())
+});
+
+static DECODER_POOL: LazyLock<SessionPool> = LazyLock::new(|| {
+    debug!("Initializing summarization decoder resources");
+    create_session_pool(1, DECODER_PATH.into())
+});
+
+static TOKENIZER: LazyLock<Tokenizer> = LazyLock::new(|| {
+    debug!("Initializing summarization tokenizer resources");
+    let mut tokenizer = create_tokenizer(TOKENIZER_PATH.into());
+    tokenizer.with_truncation(Some(TruncationParams {
+            max_length: MODEL_INPUT_LENGTH,
+            ..Default::default()
+        }))
+        .expect("Summarization truncation parameters should be valid");
+    tokenizer
+});
@@ -1,9 +1,16 @@
 pub mod app_config;
+pub mod engine;
 pub mod environment;
 pub mod files;
 pub mod index;
 pub mod previewable;
+pub mod sequence;
 pub mod store;
+pub mod telemetry;
 
 // Re-export key initialization functions
-pub use environment::{init_resources, init_indexing, init_querying};
\ No newline at end of file
+pub use environment::{init_resources, warm_models, set_model_keepalive, ModelId, WarmupProgress};
+// Must be called first thing by binaries that index files, see index::provider::sandbox
+pub use index::provider::sandbox::run_sandbox_worker_if_requested;
+// High level entry point for embedding the engine
+pub use engine::{Fetch, FetchConfig, FetchError, FetchWatcher, IndexReport};
\ No newline at end of file
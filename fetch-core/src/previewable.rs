@@ -1,20 +1,81 @@
 use std::{future::Future, time::SystemTime};
 
 use camino::{Utf8Path, Utf8PathBuf};
+use image::DynamicImage;
+use tokio::task::JoinHandle;
 
 /// A file that has been successfully processed into a preview representation.
 /// 
-/// This struct contains metadata about both the original file and its generated preview,
-/// including timing information and the type of preview that was created.
-pub struct PreviewedFile {
+/// This struct contains the decoded preview image, so callers can process it without reading it back from disk,
+/// along with a handle to the copy of the preview persisted in the preview cache.
+pub struct Preview {
     /// The path to the original file
     pub path: Utf8PathBuf,
-    /// The path to the generated preview file
-    pub preview_path: Utf8PathBuf,
+    /// The preview image
+    pub image: DynamicImage,
+    /// The preview persisted in the preview cache, which may still be being written
+    pub persisted: PersistedPreview,
     /// When this preview was generated
     pub timestamp: SystemTime,
 }
 
+/// The size class of a preview. Previews of each size are generated and cached separately.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum PreviewSize {
+    /// Small preview shown in grids of results
+    #[default]
+    Thumbnail,
+    /// Larger preview shown when looking at a single file
+    Detail,
+}
+
+impl PreviewSize {
+    /// Maximum height/width of previews of this size, in pixels
+    pub fn max_side(&self) -> u32 {
+        match self {
+            PreviewSize::Thumbnail => 300,
+            PreviewSize::Detail => 1024,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            PreviewSize::Thumbnail => "thumb",
+            PreviewSize::Detail => "detail",
+        }
+    }
+}
+
+/// Handle to a preview written to the preview cache. Newly generated previews are written in the background, the
+/// write continues even if the handle is dropped.
+pub struct PersistedPreview {
+    path: Utf8PathBuf,
+    write: Option<JoinHandle<Result<(), PreviewError>>>,
+}
+
+impl PersistedPreview {
+    fn persisted(path: Utf8PathBuf) -> PersistedPreview {
+        PersistedPreview { path, write: None }
+    }
+
+    fn pending(path: Utf8PathBuf, write: JoinHandle<Result<(), PreviewError>>) -> PersistedPreview {
+        PersistedPreview { path, write: Some(write) }
+    }
+
+    /// The path the preview is persisted at. The file may not exist yet if the preview is still being written.
+    pub fn path(&self) -> &Utf8Path {
+        &self.path
+    }
+
+    /// Waits for the preview to be written, returning the path it was persisted at
+    pub async fn wait(self) -> Result<Utf8PathBuf, PreviewError> {
+        if let Some(write) = self.write {
+            write.await.map_err(|e| PreviewError::Generation { path: self.path.to_string(), source: e.into() })??;
+        }
+        Ok(self.path)
+    }
+}
+
 /// Describes an object that can potentially be simplified or condensed into a smaller, 
 /// limited size preview representation.
 /// 
@@ -32,14 +93,14 @@ pub struct PreviewedFile {
 /// in order to generate semantic representations of the previews, which will be indexed and then
 /// utilized to find semantically related files to a given input query.
 pub trait PossiblyPreviewable {
-    /// Attempt to generate a preview representation of this object.
+    /// Attempt to generate a preview representation of this object, no larger than the size class.
     /// 
     /// # Returns
     /// 
-    /// * `Ok(Some(PreviewedFile))` - A preview was successfully generated
+    /// * `Ok(Some(Preview))` - A preview was successfully generated
     /// * `Ok(None)` - No preview could be generated (unsupported file type)
     /// * `Err(PreviewError)` - An error occurred during preview generation
-    fn preview(&self) -> impl Future<Output = Result<Option<PreviewedFile>, PreviewError>> + Send;
+    fn preview(&self, size: PreviewSize) -> impl Future<Output = Result<Option<Preview>, PreviewError>> + Send;
 }
 
 /// Errors that can occur during preview generation.
@@ -63,25 +124,55 @@ pub enum PreviewError {
 }
 
 impl PossiblyPreviewable for Utf8Path {
-    async fn preview(&self) -> Result<Option<PreviewedFile>, PreviewError> {
+    async fn preview(&self, size: PreviewSize) -> Result<Option<Preview>, PreviewError> {
         // check if preview is already available
 
-        let extension = self.extension().unwrap_or("");
-
-        let preview_path = if cache::os::has_generator_for_type(extension) {
-            cache::os::generate_preview(self).await?
-        } else {
-            cache::default::generate_preview(self).await?
-        };
+        let preview = cache::default::generate_preview(self, size).await?;
 
-        Ok(preview_path.map(|pp| PreviewedFile {
+        Ok(preview.map(|(image, persisted)| Preview {
             path: self.to_path_buf(),
-            preview_path: pp.to_path_buf(),
+            image,
+            persisted,
             timestamp: SystemTime::now(),
         }))
     }
 }
 
+/// The preview of the current version of the file at the size in the preview cache, or None if it has not been
+/// generated yet. Unlike PossiblyPreviewable::preview, never generates the preview, so it is cheap enough to call for
+/// every result of a query, eg. for the icons of launcher integrations.
+pub fn cached_preview_path(path: &Utf8Path, size: PreviewSize) -> Option<Utf8PathBuf> {
+    cache::default::cached_preview_path(path, size)
+}
+
+/// Whether the operating system has a thumbnail generator installed for the file type, eg. through a QuickLook
+/// plugin on OS X or a thumbnail handler on Windows
+pub fn has_os_generator_for_type(extension: &str) -> bool {
+    cache::os::has_generator_for_type(extension)
+}
+
+/// Renders a thumbnail of the file no larger than max_side on either side using the operating system's thumbnail
+/// generators, without going through the preview cache. Returns None if the operating system could not generate
+/// a thumbnail for the file.
+pub async fn os_thumbnail(path: &Utf8Path, max_side: u32) -> Result<Option<DynamicImage>, PreviewError> {
+    cache::os::generate_preview(path, max_side).await
+}
+
+/// Renders a specimen of the font no larger than max_side on either side, showing the font's name, a pangram and
+/// its characters set in the font itself. Used to index fonts by how they look.
+pub fn font_specimen(font_data: &[u8], max_side: u32) -> Result<DynamicImage, anyhow::Error> {
+    cache::default::font_specimen(font_data, max_side)
+}
+
+/// Renders a snapshot of the 3D model (obj, stl, gltf or glb) no larger than max_side on either side, looking at
+/// it from above and to the side. Used to index models by how they look.
+#[cfg(feature = "models")]
+pub fn model_snapshot(path: &Utf8Path, model_data: &[u8], max_side: u32) -> Result<DynamicImage, anyhow::Error> {
+    cache::default::model_snapshot(path, model_data, max_side)
+}
+
+pub mod preview_cache;
+
 // Private helper methods/modules?
 
 mod cache;
\ No newline at end of file
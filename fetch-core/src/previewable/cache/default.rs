@@ -1,24 +1,31 @@
-use std::{collections::HashMap, future::Future, hash::{DefaultHasher, Hash, Hasher}, io, pin::Pin, sync::LazyLock};
+use std::{collections::HashMap, future::Future, hash::{DefaultHasher, Hash, Hasher}, io::{self, Cursor}, pin::Pin, sync::LazyLock};
 
 use camino::{Utf8Path, Utf8PathBuf};
-use ::image::ImageFormat;
-use log::info;
-use tokio::fs::{self, File};
+use ::image::{DynamicImage, ImageFormat};
+use tokio::{fs::{self, File}, task};
+use tracing::{info, warn};
 
-use crate::{app_config, previewable::PreviewError};
+use crate::{app_config, files::os_path::to_os_path, previewable::{PersistedPreview, PreviewError, PreviewSize}};
+
+use self::text::TextStyle;
+use super::os;
 
 pub fn has_generator_for_type(extension: &str) -> bool {
-    EXTENSION_TO_FUNCTION.contains_key(extension)
+    EXTENSION_TO_FUNCTION.contains_key(extension) || os::has_generator_for_type(extension)
 }
 
-pub async fn generate_preview(path: &Utf8Path) -> Result<Option<Utf8PathBuf>, PreviewError> {
+/// Returns the preview image of the file, and a handle to the preview persisted in the preview directory. Newly
+/// generated previews are written to the preview directory in the background.
+pub async fn generate_preview(path: &Utf8Path, size: PreviewSize)
+    -> Result<Option<(DynamicImage, PersistedPreview)>, PreviewError>
+{
     // verify that file_name of the path is valid
     if path.file_name().is_none() {
         return Err(PreviewError::NotFound { path: path.to_string() });
     }
 
     // Verify the file exists and open it
-    let file = File::open(path).await.map_err(|e| -> PreviewError {
+    let file = File::open(to_os_path(path)).await.map_err(|e| -> PreviewError {
         match e.kind() {
             std::io::ErrorKind::NotFound => PreviewError::NotFound { path: path.to_string() },
             _ => PreviewError::IO { path: path.to_string(), source: e },
@@ -31,53 +38,102 @@ pub async fn generate_preview(path: &Utf8Path) -> Result<Option<Utf8PathBuf>, Pr
         return Ok(None);
     }
 
-    // At this point we know that 1) the file exists and 2) we should be able to generate a preview for it,
-    // because the module has registered the fact that we have a generator for this file type.
-    let preview_fn = EXTENSION_TO_FUNCTION.get(extension)
-        .expect("Already checked that extension is something this module can operate on");
-
     // TODO: Locking when the try_lock() API is available to stable rust std?
 
-    // First check if the preview is already available in the cache
-    let preview_filename = hash_file_path(path);
-    let preview_path = retrieve_preview_directory().join(preview_filename);
+    // First check if the preview is already available in the cache. Previews are keyed by the file's path, the
+    // preview size and the file's modified time, so a preview of an outdated version of the file is never found.
+    let modified = file_modified_millis(&file).await
+        .map_err(|e| PreviewError::IO { path: path.to_string(), source: e })?;
+    let preview_path = retrieve_preview_directory().join(preview_file_name(path, size, modified));
     if preview_path.is_file() {
-        let preview_file = File::open(&preview_path).await
-            .unwrap_or_else(|_| panic!("Could not open preview file even though .is_file() succeeded: {}", preview_path));
-        if preview_creation_after_file_modification(&file, &preview_file).await
-            .map_err(|e| PreviewError::IO { path: path.to_string(), source: e })? {
-            return Ok(Some(preview_path));
-        }
+        let image = load_preview(&preview_path).await
+            .map_err(|e| PreviewError::Generation { path: path.to_string(), source: e })?;
+        return Ok(Some((image, PersistedPreview::persisted(preview_path))));
     }
 
     // preview is not available or outdated so it needs to be re-generated
 
-    let bytes = preview_fn(file).await
-        .map_err(|e| PreviewError::Generation { path: path.to_string(), source: e })?;
-    fs::write(&preview_path, &bytes).await
-        .map_err(|e| PreviewError::IO { path: path.to_string(), source: e })?;
+    // The OS provided generators are preferred, falling back to Fetch's own generator for the file type if the
+    // OS could not generate a preview
+    let os_image = if os::has_generator_for_type(extension) {
+        os::generate_preview(path, size.max_side()).await
+            .unwrap_or_else(|e| {
+                warn!("OS preview generator failed for file {}: {:?}", path, e);
+                None
+            })
+    } else {
+        None
+    };
+    let image = match (os_image, EXTENSION_TO_FUNCTION.get(extension)) {
+        (Some(image), _) => image,
+        (None, Some(preview_fn)) => preview_fn(path.to_path_buf(), file, size.max_side()).await
+            .map_err(|e| PreviewError::Generation { path: path.to_string(), source: e })?,
+        (None, None) => return Ok(None),
+    };
+
+    // Callers can use the image straight away, so encoding and writing it to the cache happens in the background
+    let persist_image = image.clone();
+    let persist_path = preview_path.clone();
+    let original_path = path.to_path_buf();
+    let write = tokio::spawn(async move {
+        persist_preview(persist_image, &persist_path).await
+            .map_err(|e| PreviewError::IO { path: original_path.to_string(), source: e })?;
+
+        info!("Generated preview for file: {} at {}", original_path, persist_path);
+
+        // Previews of previous versions of the file will never be used again
+        remove_stale_previews(&original_path, size, &persist_path).await
+            .unwrap_or_else(|e| warn!("Could not remove outdated previews of file {}: {:?}", original_path, e));
+
+        Ok(())
+    });
+
+    Ok(Some((image, PersistedPreview::pending(preview_path, write))))
+}
 
-    info!("Generated preview for file: {} at {}", path, preview_path);
+/// Returns the path of the preview of the current version of the file at the size in the preview directory, or None
+/// if it has not been generated yet. Does not generate the preview.
+pub fn cached_preview_path(path: &Utf8Path, size: PreviewSize) -> Option<Utf8PathBuf> {
+    path.file_name()?;
+    let modified = std::fs::metadata(to_os_path(path)).and_then(|metadata| metadata.modified()).ok()?
+        .duration_since(std::time::UNIX_EPOCH).ok()?
+        .as_millis();
+    let preview_path = retrieve_preview_directory().join(preview_file_name(path, size, modified));
+    preview_path.is_file().then_some(preview_path)
+}
 
-    Ok(Some(preview_path))
+/// Decodes a preview previously written to disk
+pub async fn load_preview(preview_path: &Utf8Path) -> Result<DynamicImage, anyhow::Error> {
+    let preview_path = preview_path.to_path_buf();
+    let image = task::spawn_blocking(move || ::image::open(preview_path)).await??;
+    Ok(image)
 }
 
-// private functions/modules/constant
+/// Renders a specimen of the font's name and characters, set in the font itself
+pub fn font_specimen(font_data: &[u8], max_side: u32) -> Result<DynamicImage, anyhow::Error> {
+    font::render_specimen(font_data, max_side)
+}
+
+/// Renders a snapshot of the 3D model from the default angle
+#[cfg(feature = "models")]
+pub fn model_snapshot(path: &Utf8Path, model_data: &[u8], max_side: u32) -> Result<DynamicImage, anyhow::Error> {
+    model::render_model(path, model_data, max_side)
+}
 
-// max height/width for generated previews. to be imported in submodules
-const PREVIEW_MAX_SIDE: u32 = 300;
+// private functions/modules/constant
 
 const PREVIEW_FORMAT: ImageFormat = ImageFormat::WebP;
 const PREVIEW_FILE_EXTENSION: &str = "webp";
 
-// Function interface, takes in a file, returns the bytes of the generated preview and its file extension
-// in eg. "txt" format
-type CalcFnPointer = fn(File) -> Pin<Box<dyn Future<Output = Result<Vec<u8>, anyhow::Error>> + Send>>;
+// Function interface, takes in the path of a file, the opened file and the max height/width of the preview, returns
+// the generated preview image. Most formats only read the opened file, the path is for formats that refer to other
+// files next to them.
+type CalcFnPointer = fn(Utf8PathBuf, File, u32) -> Pin<Box<dyn Future<Output = Result<DynamicImage, anyhow::Error>> + Send>>;
 
 static EXTENSION_TO_FUNCTION: LazyLock<HashMap<&'static str, CalcFnPointer>> = LazyLock::new(|| {
     let mut map = HashMap::new();
     // file types supported by image crate
-    let standard_image_fn = (|f| Box::pin(image::calculate_preview(f))) as CalcFnPointer;
+    let standard_image_fn = (|_, f, s| Box::pin(image::calculate_preview(f, s))) as CalcFnPointer;
     map.insert("avif", standard_image_fn);
     map.insert("bmp", standard_image_fn);
     map.insert("dds", standard_image_fn);
@@ -97,43 +153,99 @@ static EXTENSION_TO_FUNCTION: LazyLock<HashMap<&'static str, CalcFnPointer>> = L
     // psd files, using psd crate
     #[cfg(feature = "psd")]
     {
-        let psd_image_fn = (|f| Box::pin(psd::calculate_preview(f))) as CalcFnPointer;
+        let psd_image_fn = (|_, f, s| Box::pin(psd::calculate_preview(f, s))) as CalcFnPointer;
         map.insert("psd", psd_image_fn);
     }
     #[cfg(feature = "pdf")]
     {
-        let pdf_image_fn = (|f| Box::pin(pdf::calculate_preview(f))) as CalcFnPointer;
+        let pdf_image_fn = (|_, f, s| Box::pin(pdf::calculate_preview(f, s))) as CalcFnPointer;
         map.insert("pdf", pdf_image_fn);
     }
+    // text files, rendered to an image of their first page
+    let plain_text_fn = (|_, f, s| Box::pin(text::calculate_preview(f, s, TextStyle::Plain))) as CalcFnPointer;
+    map.insert("txt", plain_text_fn);
+    map.insert("log", plain_text_fn);
+    map.insert("csv", plain_text_fn);
+    map.insert("tsv", plain_text_fn);
+    let markdown_fn = (|_, f, s| Box::pin(text::calculate_preview(f, s, TextStyle::Markdown))) as CalcFnPointer;
+    map.insert("md", markdown_fn);
+    map.insert("markdown", markdown_fn);
+    let code_fn = (|_, f, s| Box::pin(text::calculate_preview(f, s, TextStyle::Code))) as CalcFnPointer;
+    for extension in ["c", "cc", "cpp", "cs", "css", "go", "h", "hpp", "html", "java", "js", "json", "jsx", "kt",
+        "lua", "php", "py", "rb", "rs", "sh", "sql", "svelte", "swift", "toml", "ts", "tsx", "xml", "yaml", "yml"] {
+        map.insert(extension, code_fn);
+    }
+    // font files, rendered to a specimen of the font
+    let font_fn = (|_, f, s| Box::pin(font::calculate_preview(f, s))) as CalcFnPointer;
+    map.insert("ttf", font_fn);
+    map.insert("otf", font_fn);
+    // 3D models, rendered to a snapshot from the default angle
+    #[cfg(feature = "models")]
+    {
+        let model_fn = (|p, f, s| Box::pin(model::calculate_preview(p, f, s))) as CalcFnPointer;
+        for extension in ["obj", "stl", "gltf", "glb"] {
+            map.insert(extension, model_fn);
+        }
+    }
     // Add more extensions and their corresponding preview calculation functions here
     map
 });
 
-// checks if the preview file was created after the original file was modified
-async fn preview_creation_after_file_modification(file: &File, preview_file: &File) -> Result<bool, io::Error> {
-    let file_modified = file.metadata().await?.modified()?.duration_since(std::time::UNIX_EPOCH)
-        .expect("File modified time should be after UNIX_EPOCH");
-    let preview_created = preview_file.metadata().await?.created()?.duration_since(std::time::UNIX_EPOCH)
-        .expect("Preview created time should be after UNIX_EPOCH");
+async fn persist_preview(image: DynamicImage, preview_path: &Utf8Path) -> Result<(), io::Error> {
+    let bytes = task::spawn_blocking(move || {
+        let mut bytes: Vec<u8> = Vec::new();
+        image.write_to(&mut Cursor::new(&mut bytes), PREVIEW_FORMAT).map_err(io::Error::other)?;
+        Ok::<Vec<u8>, io::Error>(bytes)
+    }).await??;
+    fs::write(preview_path, &bytes).await
+}
+
+async fn file_modified_millis(file: &File) -> Result<u128, io::Error> {
+    Ok(file.metadata().await?.modified()?.duration_since(std::time::UNIX_EPOCH)
+        .expect("File modified time should be after UNIX_EPOCH")
+        .as_millis())
+}
 
-    Ok(preview_created > file_modified)
+/// Removes every preview of the file at the size other than the current one
+async fn remove_stale_previews(path: &Utf8Path, size: PreviewSize, current_preview: &Utf8Path)
+    -> Result<(), io::Error>
+{
+    let prefix = format!("{}-{}-", hash_path(path), size.name());
+    let mut entries = fs::read_dir(retrieve_preview_directory()).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let is_stale = entry.file_name().to_str().is_some_and(|name| name.starts_with(&prefix))
+            && entry.path() != current_preview.as_std_path();
+        if is_stale {
+            fs::remove_file(entry.path()).await?;
+        }
+    }
+    Ok(())
 }
 
 fn retrieve_preview_directory() -> Utf8PathBuf {
     app_config::get_default_preview_directory()
 }
 
-// Hash file path. Expects that path.file_name() results in a valid UTF-8 string. Will panic otherwise.
-fn hash_file_path(path: &Utf8Path) -> String {
+// Name of the preview of a version of the file at the size. Expects that path.file_name() results in a valid UTF-8
+// string. Will panic otherwise.
+fn preview_file_name(path: &Utf8Path, size: PreviewSize, modified_millis: u128) -> String {
+    format!("{}-{}-{}-{}.{}", hash_path(path), size.name(), modified_millis,
+        path.file_stem().expect("file_stem() should be previously checked, cannot be None"),
+        PREVIEW_FILE_EXTENSION)
+}
+
+fn hash_path(path: &Utf8Path) -> String {
     let mut hasher = DefaultHasher::new();
     path.as_str().hash(&mut hasher);
-    format!("{:x}-{}.{}", hasher.finish(), 
-        path.file_stem().expect("file_stem() should be previously checked, cannot be None"), 
-        PREVIEW_FILE_EXTENSION)
+    format!("{:x}", hasher.finish())
 }
 
+mod font;
 mod image;
+mod text;
 #[cfg(feature = "psd")]
 mod psd;
 #[cfg(feature = "pdf")]
-mod pdf;
\ No newline at end of file
+mod pdf;
+#[cfg(feature = "models")]
+mod model;
\ No newline at end of file
@@ -0,0 +1,140 @@
+use ab_glyph::{Font, FontRef, GlyphId, PxScale, ScaleFont, point};
+use image::{DynamicImage, Rgb, RgbImage};
+use tokio::{fs::File, io::AsyncReadExt, task};
+use ttf_parser::{Face, name_id};
+
+/// Returns a specimen of the font, max_side pixels on each side
+pub async fn calculate_preview(mut file: File, max_side: u32) -> Result<DynamicImage, anyhow::Error> {
+    let length = file.metadata().await?.len();
+    let mut file_bytes: Vec<u8> = Vec::with_capacity(length as usize);
+    file.read_to_end(&mut file_bytes).await?;
+
+    let preview_image = task::spawn_blocking(move || {
+        render_specimen(&file_bytes, max_side)
+    }).await??; // this is Result<Result<image, closure_error>, tokio::task_error>
+
+    Ok(preview_image)
+}
+
+/// Renders the name of the font, a pangram, the alphabet and the digits, each set in the font itself, onto a
+/// square image max_side pixels on each side. Lines that do not fit on the image are left out.
+pub fn render_specimen(font_data: &[u8], max_side: u32) -> Result<DynamicImage, anyhow::Error> {
+    let font = FontRef::try_from_slice(font_data)?;
+    let name = full_name(font_data).unwrap_or_else(|| "Aa Bb Cc".to_owned());
+
+    let side = max_side as f32;
+    let margin = side * MARGIN_RATIO;
+    let width = side - 2.0 * margin;
+    let mut lines: Vec<(String, f32, Rgb<u8>)> = vec![];
+    lines.extend(wrap(&font, side * NAME_SCALE_RATIO, &name, width).into_iter()
+        .map(|line| (line, side * NAME_SCALE_RATIO, NAME_COLOR)));
+    lines.extend(wrap(&font, side * PANGRAM_SCALE_RATIO, PANGRAM, width).into_iter()
+        .map(|line| (line, side * PANGRAM_SCALE_RATIO, TEXT_COLOR)));
+    lines.extend(CHARACTER_SET.iter()
+        .flat_map(|characters| wrap(&font, side * CHARACTER_SET_SCALE_RATIO, characters, width))
+        .map(|line| (line, side * CHARACTER_SET_SCALE_RATIO, TEXT_COLOR)));
+
+    let mut image = RgbImage::from_pixel(max_side, max_side, BACKGROUND_COLOR);
+    let mut top = margin;
+    for (line, scale, color) in lines {
+        let scaled = font.as_scaled(PxScale::from(scale));
+        let baseline = top + scaled.ascent();
+        if baseline - scaled.descent() > side - margin {
+            break;
+        }
+        draw_line(&mut image, &font, PxScale::from(scale), &line, margin, baseline, color);
+        top += scaled.height() + scaled.line_gap() + scale * LINE_SPACING_RATIO;
+    }
+
+    Ok(DynamicImage::from(image))
+}
+
+// Private functions and variables
+
+const PANGRAM: &str = "The quick brown fox jumps over the lazy dog";
+const CHARACTER_SET: [&str; 3] = ["ABCDEFGHIJKLMNOPQRSTUVWXYZ", "abcdefghijklmnopqrstuvwxyz", "0123456789 &@?!"];
+
+/// Sizes of the parts of the specimen, and the space around them, relative to the side of the image
+const MARGIN_RATIO: f32 = 0.06;
+const NAME_SCALE_RATIO: f32 = 0.1;
+const PANGRAM_SCALE_RATIO: f32 = 0.085;
+const CHARACTER_SET_SCALE_RATIO: f32 = 0.06;
+const LINE_SPACING_RATIO: f32 = 0.15;
+
+const BACKGROUND_COLOR: Rgb<u8> = Rgb([250, 250, 250]);
+const NAME_COLOR: Rgb<u8> = Rgb([5, 80, 174]);
+const TEXT_COLOR: Rgb<u8> = Rgb([36, 41, 47]);
+
+/// Full name of the font from its name table, eg. "Inter Bold Italic"
+fn full_name(font_data: &[u8]) -> Option<String> {
+    let face = Face::parse(font_data, 0).ok()?;
+    [name_id::FULL_NAME, name_id::FAMILY].into_iter()
+        .find_map(|id| face.names().into_iter().filter(|n| n.name_id == id).find_map(|n| n.to_string()))
+        .filter(|name| !name.trim().is_empty())
+}
+
+/// Breaks the text into lines no wider than width at the scale, at spaces. Words wider than a line are left on a
+/// line of their own, to be cut off at the edge of the image.
+fn wrap(font: &FontRef, scale: f32, text: &str, width: f32) -> Vec<String> {
+    let mut lines: Vec<String> = vec![];
+    for word in text.split_whitespace() {
+        match lines.last_mut() {
+            Some(line) if measure(font, scale, &format!("{} {}", line, word)) <= width => {
+                line.push(' ');
+                line.push_str(word);
+            },
+            _ => lines.push(word.to_owned()),
+        }
+    }
+    lines
+}
+
+/// Width of the text set at the scale, in pixels
+fn measure(font: &FontRef, scale: f32, text: &str) -> f32 {
+    let scaled = font.as_scaled(PxScale::from(scale));
+    let mut previous: Option<GlyphId> = None;
+    let mut width = 0.0;
+    for c in text.chars() {
+        let id = scaled.glyph_id(c);
+        if let Some(previous) = previous {
+            width += scaled.kern(previous, id);
+        }
+        width += scaled.h_advance(id);
+        previous = Some(id);
+    }
+    width
+}
+
+/// Draws the text starting at x, sitting on the baseline. Glyphs are blended into the image by their coverage of
+/// each pixel, and anything outside the image is cut off.
+fn draw_line(image: &mut RgbImage, font: &FontRef, scale: PxScale, text: &str, x: f32, baseline: f32, color: Rgb<u8>) {
+    let scaled = font.as_scaled(scale);
+    let (image_width, image_height) = image.dimensions();
+    let mut caret = x;
+    let mut previous: Option<GlyphId> = None;
+    for c in text.chars() {
+        let id = scaled.glyph_id(c);
+        if let Some(previous) = previous {
+            caret += scaled.kern(previous, id);
+        }
+        let glyph = id.with_scale_and_position(scale, point(caret, baseline));
+        caret += scaled.h_advance(id);
+        previous = Some(id);
+
+        let Some(outlined) = font.outline_glyph(glyph) else {
+            continue;
+        };
+        let bounds = outlined.px_bounds();
+        outlined.draw(|gx, gy, coverage| {
+            let (px, py) = (bounds.min.x as i64 + gx as i64, bounds.min.y as i64 + gy as i64);
+            if px < 0 || py < 0 || px >= image_width as i64 || py >= image_height as i64 {
+                return;
+            }
+            let pixel = image.get_pixel_mut(px as u32, py as u32);
+            let coverage = coverage.clamp(0.0, 1.0);
+            for (channel, target) in pixel.0.iter_mut().zip(color.0) {
+                *channel = (*channel as f32 * (1.0 - coverage) + target as f32 * coverage).round() as u8;
+            }
+        });
+    }
+}
@@ -0,0 +1,278 @@
+use std::f32::consts::PI;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use gltf::{Gltf, mesh::Mode};
+use image::{DynamicImage, Rgb, RgbImage, imageops::FilterType};
+use tokio::{fs::File, io::AsyncReadExt, task};
+
+/// Returns a snapshot of the 3D model from the default angle
+pub async fn calculate_preview(path: Utf8PathBuf, mut file: File, max_side: u32) -> Result<DynamicImage, anyhow::Error> {
+    let length = file.metadata().await?.len();
+    let mut file_bytes: Vec<u8> = Vec::with_capacity(length as usize);
+    file.read_to_end(&mut file_bytes).await?;
+
+    let preview_image = task::spawn_blocking(move || {
+        render_model(&path, &file_bytes, max_side)
+    }).await??; // this is Result<Result<image, closure_error>, tokio::task_error>
+
+    Ok(preview_image)
+}
+
+/// Renders the model (obj, stl, gltf or glb) from above and to the side, fit to a square image max_side pixels on
+/// each side. The path is used to tell the format apart, and to find the buffers of gltf files stored next to
+/// them. Materials and textures are not loaded, every surface is shaded in the same color.
+pub fn render_model(path: &Utf8Path, data: &[u8], max_side: u32) -> Result<DynamicImage, anyhow::Error> {
+    let triangles = match path.extension().map(str::to_lowercase).as_deref() {
+        Some("obj") => load_obj(data),
+        Some("stl") => load_stl(data)?,
+        Some("gltf") | Some("glb") => load_gltf(path, data)?,
+        _ => anyhow::bail!("Unsupported 3D model format"),
+    };
+    if triangles.is_empty() {
+        anyhow::bail!("3D model has no triangles");
+    }
+
+    Ok(rasterize(&triangles, max_side))
+}
+
+// Private functions and variables
+
+type Vec3 = [f32; 3];
+type Triangle = [Vec3; 3];
+
+/// Angles the model is looked at from, in radians. Models are turned around their vertical axis and tilted
+/// towards the camera, so three sides of a box-shaped model are visible.
+const YAW: f32 = -35.0 * PI / 180.0;
+const PITCH: f32 = 25.0 * PI / 180.0;
+/// Models are rendered this many times larger than the preview and scaled down, to smooth their edges
+const SUPERSAMPLING: u32 = 2;
+const MARGIN_RATIO: f32 = 0.08;
+
+const BACKGROUND_COLOR: Rgb<u8> = Rgb([250, 250, 250]);
+const MODEL_COLOR: [f32; 3] = [120.0, 150.0, 190.0];
+/// Direction light comes from, towards the upper left of the camera
+const LIGHT_DIRECTION: Vec3 = [-0.4, 0.6, 0.7];
+const AMBIENT_LIGHT: f32 = 0.3;
+
+/// Reads the vertices and faces of a Wavefront obj file. Faces with more than three vertices are split into a fan
+/// of triangles.
+fn load_obj(data: &[u8]) -> Vec<Triangle> {
+    let text = String::from_utf8_lossy(data);
+    let mut vertices: Vec<Vec3> = vec![];
+    let mut triangles = vec![];
+    for line in text.lines() {
+        let mut parts = line.split_whitespace();
+        match parts.next() {
+            Some("v") => {
+                let coords: Vec<f32> = parts.take(3).filter_map(|p| p.parse().ok()).collect();
+                if let [x, y, z] = coords[..] {
+                    vertices.push([x, y, z]);
+                }
+            },
+            Some("f") => {
+                // Vertices are 1-indexed, negative indices count back from the last vertex read
+                let face: Vec<Vec3> = parts
+                    .filter_map(|p| p.split('/').next()?.parse::<i64>().ok())
+                    .filter_map(|i| match i {
+                        i if i > 0 => vertices.get(i as usize - 1),
+                        i if i < 0 => vertices.len().checked_sub(i.unsigned_abs() as usize).and_then(|i| vertices.get(i)),
+                        _ => None,
+                    })
+                    .copied()
+                    .collect();
+                for i in 1..face.len().saturating_sub(1) {
+                    triangles.push([face[0], face[i], face[i + 1]]);
+                }
+            },
+            _ => (),
+        }
+    }
+    triangles
+}
+
+/// Reads the triangles of a binary or ASCII stl file. stl models are usually Z-up, so they are turned to be Y-up
+/// like the other formats.
+fn load_stl(data: &[u8]) -> Result<Vec<Triangle>, anyhow::Error> {
+    let z_up = |[x, y, z]: Vec3| [x, z, -y];
+
+    // Binary files are an 80 byte header, a triangle count and 50 bytes per triangle. ASCII files start with
+    // "solid", but so do the headers of some binary files, so the size of the file is checked first.
+    let binary_count = data.get(80..84).map(|c| u32::from_le_bytes([c[0], c[1], c[2], c[3]]) as usize);
+    if let Some(count) = binary_count.filter(|count| 84 + count * 50 == data.len()) {
+        let read_vec3 = |offset: usize| -> Vec3 {
+            let f = |i: usize| {
+                let b = &data[offset + i * 4..offset + i * 4 + 4];
+                f32::from_le_bytes([b[0], b[1], b[2], b[3]])
+            };
+            z_up([f(0), f(1), f(2)])
+        };
+        return Ok((0..count)
+            .map(|i| 84 + i * 50)
+            // Each triangle starts with its normal, which is skipped
+            .map(|offset| [read_vec3(offset + 12), read_vec3(offset + 24), read_vec3(offset + 36)])
+            .collect());
+    }
+
+    let text = std::str::from_utf8(data)?;
+    if !text.trim_start().starts_with("solid") {
+        anyhow::bail!("File is not a valid stl file");
+    }
+    let vertices: Vec<Vec3> = text.lines()
+        .filter_map(|line| line.trim().strip_prefix("vertex"))
+        .filter_map(|coords| {
+            let coords: Vec<f32> = coords.split_whitespace().filter_map(|p| p.parse().ok()).collect();
+            match coords[..] {
+                [x, y, z] => Some(z_up([x, y, z])),
+                _ => None,
+            }
+        })
+        .collect();
+    Ok(vertices.chunks_exact(3).map(|t| [t[0], t[1], t[2]]).collect())
+}
+
+/// Reads the triangles of the default scene of a gltf or glb file, placed by the transforms of their nodes.
+/// Files without scenes have their meshes read as they are.
+fn load_gltf(path: &Utf8Path, data: &[u8]) -> Result<Vec<Triangle>, anyhow::Error> {
+    let Gltf { document, blob } = Gltf::from_slice(data)?;
+    let buffers = gltf::import_buffers(&document, path.parent().map(Utf8Path::as_std_path), blob)?;
+
+    let mut meshes: Vec<(gltf::Mesh, [[f32; 4]; 4])> = vec![];
+    match document.default_scene().or_else(|| document.scenes().next()) {
+        Some(scene) => {
+            let mut nodes: Vec<(gltf::Node, [[f32; 4]; 4])> = scene.nodes().map(|n| (n, IDENTITY)).collect();
+            while let Some((node, parent_transform)) = nodes.pop() {
+                let transform = multiply(&parent_transform, &node.transform().matrix());
+                if let Some(mesh) = node.mesh() {
+                    meshes.push((mesh, transform));
+                }
+                nodes.extend(node.children().map(|child| (child, transform)));
+            }
+        },
+        None => meshes.extend(document.meshes().map(|mesh| (mesh, IDENTITY))),
+    }
+
+    let mut triangles = vec![];
+    for (mesh, transform) in meshes {
+        for primitive in mesh.primitives().filter(|p| p.mode() == Mode::Triangles) {
+            let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(|data| &data.0[..]));
+            let Some(positions) = reader.read_positions() else {
+                continue;
+            };
+            let positions: Vec<Vec3> = positions.map(|p| transform_point(&transform, p)).collect();
+            let indices: Vec<usize> = match reader.read_indices() {
+                Some(indices) => indices.into_u32().map(|i| i as usize).collect(),
+                None => (0..positions.len()).collect(),
+            };
+            triangles.extend(indices.chunks_exact(3)
+                .filter_map(|t| Some([*positions.get(t[0])?, *positions.get(t[1])?, *positions.get(t[2])?])));
+        }
+    }
+    Ok(triangles)
+}
+
+const IDENTITY: [[f32; 4]; 4] = [[1.0, 0.0, 0.0, 0.0], [0.0, 1.0, 0.0, 0.0], [0.0, 0.0, 1.0, 0.0], [0.0, 0.0, 0.0, 1.0]];
+
+/// Multiplies two column-major 4x4 matrices
+fn multiply(a: &[[f32; 4]; 4], b: &[[f32; 4]; 4]) -> [[f32; 4]; 4] {
+    let mut result = [[0.0; 4]; 4];
+    for (column, result_column) in result.iter_mut().enumerate() {
+        for (row, value) in result_column.iter_mut().enumerate() {
+            *value = (0..4).map(|k| a[k][row] * b[column][k]).sum();
+        }
+    }
+    result
+}
+
+fn transform_point(m: &[[f32; 4]; 4], [x, y, z]: Vec3) -> Vec3 {
+    [
+        m[0][0] * x + m[1][0] * y + m[2][0] * z + m[3][0],
+        m[0][1] * x + m[1][1] * y + m[2][1] * z + m[3][1],
+        m[0][2] * x + m[1][2] * y + m[2][2] * z + m[3][2],
+    ]
+}
+
+fn sub(a: Vec3, b: Vec3) -> Vec3 {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn cross(a: Vec3, b: Vec3) -> Vec3 {
+    [a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]]
+}
+
+fn dot(a: Vec3, b: Vec3) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn normalize(v: Vec3) -> Vec3 {
+    let length = dot(v, v).sqrt();
+    if length > 0.0 { [v[0] / length, v[1] / length, v[2] / length] } else { v }
+}
+
+/// Rotates a point to the camera's view. The camera looks down the -z axis, so points with a larger z are closer.
+fn view(p: Vec3) -> Vec3 {
+    let (sin_yaw, cos_yaw) = YAW.sin_cos();
+    let (sin_pitch, cos_pitch) = PITCH.sin_cos();
+    let [x, y, z] = p;
+    let (x, z) = (x * cos_yaw + z * sin_yaw, -x * sin_yaw + z * cos_yaw);
+    let (y, z) = (y * cos_pitch - z * sin_pitch, y * sin_pitch + z * cos_pitch);
+    [x, y, z]
+}
+
+/// Draws the triangles with an orthographic camera, flat shading and a depth buffer, fit to the image
+fn rasterize(triangles: &[Triangle], max_side: u32) -> DynamicImage {
+    let triangles: Vec<Triangle> = triangles.iter().map(|t| [view(t[0]), view(t[1]), view(t[2])]).collect();
+
+    // Fit the model's bounds on screen to the image
+    let (mut min, mut max) = ([f32::MAX; 2], [f32::MIN; 2]);
+    for p in triangles.iter().flatten().filter(|p| p.iter().all(|c| c.is_finite())) {
+        min = [min[0].min(p[0]), min[1].min(p[1])];
+        max = [max[0].max(p[0]), max[1].max(p[1])];
+    }
+    let side = (max_side * SUPERSAMPLING) as f32;
+    let extent = (max[0] - min[0]).max(max[1] - min[1]).max(f32::EPSILON);
+    let scale = side * (1.0 - 2.0 * MARGIN_RATIO) / extent;
+    let center = [(min[0] + max[0]) / 2.0, (min[1] + max[1]) / 2.0];
+    let to_screen = |p: Vec3| [side / 2.0 + (p[0] - center[0]) * scale, side / 2.0 - (p[1] - center[1]) * scale, p[2]];
+
+    let size = side as u32;
+    let mut image = RgbImage::from_pixel(size, size, BACKGROUND_COLOR);
+    let mut depth = vec![f32::MIN; (size * size) as usize];
+    let light = normalize(LIGHT_DIRECTION);
+    for triangle in &triangles {
+        // Models do not reliably wind their faces the same way, so faces are lit from whichever side is visible
+        let mut normal = normalize(cross(sub(triangle[1], triangle[0]), sub(triangle[2], triangle[0])));
+        if normal[2] < 0.0 {
+            normal = [-normal[0], -normal[1], -normal[2]];
+        }
+        let brightness = AMBIENT_LIGHT + (1.0 - AMBIENT_LIGHT) * dot(normal, light).max(0.0);
+        let color = Rgb(MODEL_COLOR.map(|c| (c * brightness).min(255.0) as u8));
+
+        let [a, b, c] = triangle.map(to_screen);
+        let area = (b[0] - a[0]) * (c[1] - a[1]) - (b[1] - a[1]) * (c[0] - a[0]);
+        if area.abs() < f32::EPSILON || !area.is_finite() {
+            continue;
+        }
+        let x_range = (a[0].min(b[0]).min(c[0]).floor().max(0.0) as u32)..(a[0].max(b[0]).max(c[0]).ceil().min(side) as u32);
+        let y_range = (a[1].min(b[1]).min(c[1]).floor().max(0.0) as u32)..(a[1].max(b[1]).max(c[1]).ceil().min(side) as u32);
+        for y in y_range {
+            for x in x_range.clone() {
+                let (px, py) = (x as f32 + 0.5, y as f32 + 0.5);
+                // Barycentric weights of the pixel's center, all of the same sign when it is inside the triangle
+                let wa = ((b[0] - px) * (c[1] - py) - (b[1] - py) * (c[0] - px)) / area;
+                let wb = ((c[0] - px) * (a[1] - py) - (c[1] - py) * (a[0] - px)) / area;
+                let wc = 1.0 - wa - wb;
+                if wa < 0.0 || wb < 0.0 || wc < 0.0 {
+                    continue;
+                }
+                let z = wa * a[2] + wb * b[2] + wc * c[2];
+                let index = (y * size + x) as usize;
+                if z > depth[index] {
+                    depth[index] = z;
+                    image.put_pixel(x, y, color);
+                }
+            }
+        }
+    }
+
+    DynamicImage::from(image).resize(max_side, max_side, FilterType::Triangle)
+}
@@ -1,22 +1,21 @@
-use std::io::Cursor;
-
+use image::DynamicImage;
 use pdfium_render::prelude::{PdfPageRenderRotation, PdfRenderConfig};
 use tokio::{fs::File, io::AsyncReadExt, task};
 
-use crate::{environment::get_pdfium, previewable::cache::default::{PREVIEW_FORMAT, PREVIEW_MAX_SIDE}};
+use crate::environment::get_pdfium;
 
-/// Returns a vector of bytes representing the preview image
-pub async fn calculate_preview(mut file: File) -> Result<Vec<u8>, anyhow::Error> {
+/// Returns the preview image rendered from the first page
+pub async fn calculate_preview(mut file: File, max_side: u32) -> Result<DynamicImage, anyhow::Error> {
     let length = file.metadata().await?.len();
     let mut file_bytes: Vec<u8> = Vec::with_capacity(length as usize);
     file.read_to_end(&mut file_bytes).await?;
 
-    let preview_bytes = task::spawn_blocking(move || {
+    let preview_image = task::spawn_blocking(move || {
         let pdfium = get_pdfium();
         let document = pdfium.load_pdf_from_byte_vec(file_bytes, None)?;
 
         let render_config = PdfRenderConfig::new()
-            .scale_page_to_display_size(PREVIEW_MAX_SIDE as i32, PREVIEW_MAX_SIDE as i32)
+            .scale_page_to_display_size(max_side as i32, max_side as i32)
             .rotate(PdfPageRenderRotation::None, false)
             .use_print_quality(false)
             .set_image_smoothing(false)
@@ -30,10 +29,8 @@ pub async fn calculate_preview(mut file: File) -> Result<Vec<u8>, anyhow::Error>
             return Err(anyhow::Error::msg("PDF did not have any pages"));
         };
 
-        let mut preview_bytes: Vec<u8> = Vec::new();
-        image.write_to(&mut Cursor::new(&mut preview_bytes), PREVIEW_FORMAT)?;
-        Ok::<Vec<u8>, anyhow::Error>(preview_bytes)
-    }).await??; // this is Result<Result<vec, closure_error>, tokio::task_error>
+        Ok::<DynamicImage, anyhow::Error>(image)
+    }).await??; // this is Result<Result<image, closure_error>, tokio::task_error>
 
-    Ok(preview_bytes)
+    Ok(preview_image)
 }
\ No newline at end of file
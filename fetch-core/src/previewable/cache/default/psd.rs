@@ -1,18 +1,14 @@
-use std::io::Cursor;
-
 use image::{imageops::FilterType, DynamicImage, RgbaImage};
 use psd::{Psd, PsdLayer};
 use tokio::{fs::File, io::AsyncReadExt, task};
 
-use crate::previewable::cache::default::{PREVIEW_FORMAT, PREVIEW_MAX_SIDE};
-
-/// Returns a vector of bytes representing a resized preview of the rendered psd file
-pub async fn calculate_preview(mut file: File) -> Result<Vec<u8>, anyhow::Error> {
+/// Returns a resized preview of the rendered psd file
+pub async fn calculate_preview(mut file: File, max_side: u32) -> Result<DynamicImage, anyhow::Error> {
     let length = file.metadata().await?.len();
     let mut file_bytes: Vec<u8> = Vec::with_capacity(length as usize);
     file.read_to_end(&mut file_bytes).await?;
 
-    let preview_bytes = task::spawn_blocking(move || {
+    let preview_image = task::spawn_blocking(move || {
         let psd = Psd::from_bytes(&file_bytes)?;
 
         let width = psd.width();
@@ -24,15 +20,13 @@ pub async fn calculate_preview(mut file: File) -> Result<Vec<u8>, anyhow::Error>
         let image = DynamicImage::from(RgbaImage::from_raw(width, height, flattened_bytes).unwrap());
 
         let image = image.resize(
-            PREVIEW_MAX_SIDE,
-            PREVIEW_MAX_SIDE,
+            max_side,
+            max_side,
             FilterType::Triangle,
         );
 
-        let mut preview_bytes: Vec<u8> = Vec::new();
-        image.write_to(&mut Cursor::new(&mut preview_bytes), PREVIEW_FORMAT)?;
-        Ok::<Vec<u8>, anyhow::Error>(preview_bytes)
-    }).await??; // this is Result<Result<vec, closure_error>, tokio::task_error>
+        Ok::<DynamicImage, anyhow::Error>(image)
+    }).await??; // this is Result<Result<image, closure_error>, tokio::task_error>
 
-    Ok(preview_bytes)
+    Ok(preview_image)
 }
\ No newline at end of file
@@ -0,0 +1,202 @@
+use std::{collections::HashSet, sync::LazyLock};
+
+use font8x8::{UnicodeFonts, BASIC_FONTS, LATIN_FONTS};
+use image::{DynamicImage, Rgb, RgbImage};
+use tokio::{fs::File, io::AsyncReadExt, task};
+
+/// How the text of a file is colored when rendered
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TextStyle {
+    Plain,
+    Markdown,
+    Code,
+}
+
+/// Returns an image of the first page of the text file, max_side pixels tall
+pub async fn calculate_preview(file: File, max_side: u32, style: TextStyle) -> Result<DynamicImage, anyhow::Error> {
+    // Only the start of the file can fit on the page
+    let mut file_bytes: Vec<u8> = Vec::with_capacity(MAX_TEXT_BYTES as usize);
+    file.take(MAX_TEXT_BYTES).read_to_end(&mut file_bytes).await?;
+
+    let preview_image = task::spawn_blocking(move || {
+        let text = String::from_utf8_lossy(&file_bytes);
+        let page = Page::new(max_side);
+        let lines = layout_lines(&text, style, &page);
+
+        let mut image = RgbImage::from_pixel(page.width, page.height, BACKGROUND_COLOR);
+        for (row, line) in lines.iter().enumerate() {
+            let y = page.margin + row as u32 * LINE_HEIGHT * page.scale;
+            for (column, (c, color)) in line.iter().enumerate() {
+                let x = page.margin + column as u32 * GLYPH_SIDE * page.scale;
+                draw_glyph(&mut image, *c, x, y, page.scale, *color);
+            }
+        }
+
+        Ok::<DynamicImage, anyhow::Error>(DynamicImage::from(image))
+    }).await??; // this is Result<Result<image, closure_error>, tokio::task_error>
+
+    Ok(preview_image)
+}
+
+// Private functions and variables
+
+const MAX_TEXT_BYTES: u64 = 16 * 1024;
+/// Page height at which glyphs are drawn at their native size. Larger pages scale glyphs up, so every page size
+/// fits roughly the same text.
+const BASE_PAGE_HEIGHT: u32 = 300;
+const PAGE_MARGIN: u32 = 8;
+const GLYPH_SIDE: u32 = 8;
+const LINE_HEIGHT: u32 = GLYPH_SIDE + 2;
+const TAB_WIDTH: usize = 4;
+
+const BACKGROUND_COLOR: Rgb<u8> = Rgb([250, 250, 250]);
+const TEXT_COLOR: Rgb<u8> = Rgb([36, 41, 47]);
+const COMMENT_COLOR: Rgb<u8> = Rgb([110, 119, 129]);
+const STRING_COLOR: Rgb<u8> = Rgb([10, 48, 105]);
+const KEYWORD_COLOR: Rgb<u8> = Rgb([207, 34, 46]);
+const NUMBER_COLOR: Rgb<u8> = Rgb([5, 80, 174]);
+const HEADING_COLOR: Rgb<u8> = Rgb([5, 80, 174]);
+
+/// Keywords of commonly used programming languages. Files are not parsed, so the keywords of every language are
+/// highlighted in every code file.
+static KEYWORDS: LazyLock<HashSet<&'static str>> = LazyLock::new(|| {
+    HashSet::from([
+        "as", "async", "await", "break", "case", "catch", "class", "const", "continue", "def", "default", "elif",
+        "else", "enum", "export", "extends", "false", "fn", "for", "from", "func", "function", "if", "impl",
+        "import", "in", "interface", "let", "match", "mod", "new", "nil", "None", "null", "package", "private",
+        "pub", "public", "return", "self", "static", "struct", "switch", "this", "throw", "trait", "True",
+        "true", "try", "type", "use", "var", "void", "while", "with", "yield",
+    ])
+});
+
+type ColoredLine = Vec<(char, Rgb<u8>)>;
+
+/// Dimensions of a rendered page. Pages are portrait, like a sheet of paper.
+struct Page {
+    width: u32,
+    height: u32,
+    margin: u32,
+    scale: u32,
+    columns: usize,
+    rows: usize,
+}
+
+impl Page {
+    fn new(max_side: u32) -> Page {
+        let scale = (max_side / BASE_PAGE_HEIGHT).max(1);
+        let (width, height, margin) = (max_side * 3 / 4, max_side, PAGE_MARGIN * scale);
+        Page {
+            width,
+            height,
+            margin,
+            scale,
+            columns: (width.saturating_sub(2 * margin) / (GLYPH_SIDE * scale)) as usize,
+            rows: (height.saturating_sub(2 * margin) / (LINE_HEIGHT * scale)) as usize,
+        }
+    }
+}
+
+/// Colors the text and lays it out into the lines that fit on the page. Code is truncated at the edge of the
+/// page to keep its indentation readable, other text is wrapped.
+fn layout_lines(text: &str, style: TextStyle, page: &Page) -> Vec<ColoredLine> {
+    let mut lines = vec![];
+    let mut in_code_block = false;
+    for line in text.lines() {
+        let line = line.replace('\t', &" ".repeat(TAB_WIDTH));
+        let colored = match style {
+            TextStyle::Plain => line.chars().map(|c| (c, TEXT_COLOR)).collect(),
+            TextStyle::Markdown => color_markdown_line(&line, &mut in_code_block),
+            TextStyle::Code => color_code_line(&line),
+        };
+
+        if style == TextStyle::Code || colored.len() <= page.columns {
+            lines.push(colored.into_iter().take(page.columns).collect());
+        } else {
+            lines.extend(colored.chunks(page.columns.max(1)).map(|chunk| chunk.to_vec()));
+        }
+        if lines.len() >= page.rows {
+            break;
+        }
+    }
+    lines.truncate(page.rows);
+    lines
+}
+
+fn color_markdown_line(line: &str, in_code_block: &mut bool) -> ColoredLine {
+    let trimmed = line.trim_start();
+    let color = if trimmed.starts_with("```") {
+        *in_code_block = !*in_code_block;
+        COMMENT_COLOR
+    } else if *in_code_block || trimmed.starts_with('>') {
+        COMMENT_COLOR
+    } else if trimmed.starts_with('#') {
+        HEADING_COLOR
+    } else {
+        TEXT_COLOR
+    };
+    line.chars().map(|c| (c, color)).collect()
+}
+
+fn color_code_line(line: &str) -> ColoredLine {
+    let chars: Vec<char> = line.chars().collect();
+    let mut colored = Vec::with_capacity(chars.len());
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c == '#' || (c == '/' && chars.get(i + 1) == Some(&'/')) {
+            // Line comment, runs to the end of the line
+            colored.extend(chars[i..].iter().map(|c| (*c, COMMENT_COLOR)));
+            break;
+        } else if c == '"' || c == '\'' {
+            let mut end = i + 1;
+            while end < chars.len() && chars[end] != c {
+                // Skip escaped characters
+                end += if chars[end] == '\\' { 2 } else { 1 };
+            }
+            let end = (end + 1).min(chars.len());
+            colored.extend(chars[i..end].iter().map(|c| (*c, STRING_COLOR)));
+            i = end;
+        } else if c.is_alphanumeric() || c == '_' {
+            let mut end = i;
+            while end < chars.len() && (chars[end].is_alphanumeric() || chars[end] == '_') {
+                end += 1;
+            }
+            let word: String = chars[i..end].iter().collect();
+            let color = if c.is_ascii_digit() {
+                NUMBER_COLOR
+            } else if KEYWORDS.contains(word.as_str()) {
+                KEYWORD_COLOR
+            } else {
+                TEXT_COLOR
+            };
+            colored.extend(chars[i..end].iter().map(|c| (*c, color)));
+            i = end;
+        } else {
+            colored.push((c, TEXT_COLOR));
+            i += 1;
+        }
+    }
+    colored
+}
+
+/// Draws the glyph with its top left corner at x, y, scaling each pixel of the glyph into a square of scale
+/// pixels. Characters the font does not cover are left blank.
+fn draw_glyph(image: &mut RgbImage, c: char, x: u32, y: u32, scale: u32, color: Rgb<u8>) {
+    let glyph = match BASIC_FONTS.get(c).or_else(|| LATIN_FONTS.get(c)) {
+        Some(glyph) => glyph,
+        None => return,
+    };
+    // Each byte is a row of the glyph, with the lowest bit as the leftmost pixel
+    for (row, bits) in glyph.iter().enumerate() {
+        for column in 0..GLYPH_SIDE {
+            if bits & (1 << column) == 0 {
+                continue;
+            }
+            for dy in 0..scale {
+                for dx in 0..scale {
+                    image.put_pixel(x + column * scale + dx, y + row as u32 * scale + dy, color);
+                }
+            }
+        }
+    }
+}
@@ -1,37 +1,264 @@
-use std::fs::File;
-
-use camino::{Utf8Path, Utf8PathBuf};
+use camino::Utf8Path;
+use image::DynamicImage;
+use tokio::task;
 
 use crate::previewable::PreviewError;
 
+/// Whether the operating system has a thumbnail generator installed for the file type, eg. a QuickLook generator
+/// on OS X or a thumbnail handler on Windows
 pub fn has_generator_for_type(extension: &str) -> bool {
-    let os_generator: Option<Box<dyn ImpliesGeneratingPreview>> = None;
+    if extension.is_empty() {
+        return false;
+    }
 
+    #[cfg(target_os = "macos")]
+    let has_generator = quicklook::has_generator_for_type(extension);
     #[cfg(target_os = "windows")]
-    let os_generator = Some(Box::new(WindowsPreviewGenerator));
-    
-    os_generator.map(|gen| gen.has_generator_for_type(extension)).unwrap_or(false)
-}
+    let has_generator = windows_thumbnail::has_generator_for_type(extension);
+    #[cfg(not(any(target_os = "macos", target_os = "windows")))]
+    let has_generator = false;
 
-pub async fn generate_preview(_path: &Utf8Path) -> Result<Option<Utf8PathBuf>, PreviewError> {
-    Ok(Some(Utf8PathBuf::new()))
+    has_generator
 }
 
-trait ImpliesGeneratingPreview {
-    fn has_generator_for_type(&self, extension: &str) -> bool;
-}
-trait GeneratesPreview {
-    async fn generate_preview(&self, file: File) -> Result<Utf8PathBuf, anyhow::Error>;
+/// Renders a thumbnail of the file no larger than max_side on either side using the operating system's thumbnail
+/// generators. Returns None if the operating system could not generate a thumbnail for the file.
+pub async fn generate_preview(path: &Utf8Path, max_side: u32) -> Result<Option<DynamicImage>, PreviewError> {
+    let path_clone = path.to_owned();
+    task::spawn_blocking(move || {
+        #[cfg(target_os = "macos")]
+        let thumbnail = quicklook::thumbnail(&path_clone, max_side);
+        #[cfg(target_os = "windows")]
+        let thumbnail = windows_thumbnail::thumbnail(&path_clone, max_side);
+        #[cfg(not(any(target_os = "macos", target_os = "windows")))]
+        let thumbnail = {
+            let _ = (&path_clone, max_side);
+            Ok(None)
+        };
+
+        thumbnail
+    }).await
+    .map_err(|e| PreviewError::Generation { path: path.to_string(), source: e.into() })?
+    .map_err(|e| PreviewError::Generation { path: path.to_string(), source: e })
 }
 
-struct WindowsPreviewGenerator;
-impl ImpliesGeneratingPreview for WindowsPreviewGenerator {
-    fn has_generator_for_type(&self, _extension: &str) -> bool {
-        false
+// Private modules
+
+#[cfg(target_os = "macos")]
+mod quicklook {
+    use std::{collections::HashSet, ffi::c_void, process::Command, sync::LazyLock};
+
+    use camino::Utf8Path;
+    use image::DynamicImage;
+    use tracing::{debug, warn};
+
+    pub fn has_generator_for_type(extension: &str) -> bool {
+        let uti = match uti_for_extension(extension) {
+            Some(uti) => uti,
+            None => return false,
+        };
+        GENERATOR_TYPES.iter().any(|generator_type| conforms_to(&uti, generator_type))
+    }
+
+    pub fn thumbnail(path: &Utf8Path, max_side: u32) -> Result<Option<DynamicImage>, anyhow::Error> {
+        let out_dir = std::env::temp_dir().join(format!("fetch-quicklook-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&out_dir)?;
+
+        let result = (|| -> Result<Option<DynamicImage>, anyhow::Error> {
+            let output = Command::new("qlmanage")
+                .arg("-t")
+                .arg("-s").arg(max_side.to_string())
+                .arg("-o").arg(&out_dir)
+                .arg(crate::files::os_path::to_os_path(path))
+                .output()?;
+            if !output.status.success() {
+                return Err(anyhow::anyhow!("qlmanage exited with {}", output.status));
+            }
+
+            // qlmanage names thumbnails after the original file, eg. drawing.dwg.png
+            let thumbnail_path = out_dir.join(format!("{}.png", path.file_name().unwrap_or_default()));
+            if !thumbnail_path.is_file() {
+                debug!("QuickLook did not generate a thumbnail for {}", path);
+                return Ok(None);
+            }
+            Ok(Some(image::open(thumbnail_path)?))
+        })();
+
+        std::fs::remove_dir_all(&out_dir)
+            .unwrap_or_else(|e| warn!("Could not remove QuickLook output directory {:?}: {:?}", out_dir, e));
+        result
+    }
+
+    // Private functions and variables
+
+    type CFStringRef = *const c_void;
+
+    const K_CF_STRING_ENCODING_UTF8: u32 = 0x08000100;
+
+    #[link(name = "CoreFoundation", kind = "framework")]
+    extern "C" {
+        fn CFStringCreateWithBytes(alloc: *const c_void, bytes: *const u8, num_bytes: isize, encoding: u32,
+            is_external_representation: u8) -> CFStringRef;
+        fn CFStringGetCString(string: CFStringRef, buffer: *mut u8, buffer_size: isize, encoding: u32) -> u8;
+        fn CFRelease(cf: *const c_void);
+    }
+
+    #[link(name = "CoreServices", kind = "framework")]
+    extern "C" {
+        static kUTTagClassFilenameExtension: CFStringRef;
+        fn UTTypeCreatePreferredIdentifierForTag(tag_class: CFStringRef, tag: CFStringRef,
+            conforming_to_uti: CFStringRef) -> CFStringRef;
+        fn UTTypeConformsTo(uti: CFStringRef, conforms_to_uti: CFStringRef) -> u8;
+    }
+
+    /// Content types with a QuickLook generator installed, as listed by qlmanage
+    static GENERATOR_TYPES: LazyLock<HashSet<String>> = LazyLock::new(|| {
+        let output = match Command::new("qlmanage").args(["-m", "plugins"]).output() {
+            Ok(output) => output,
+            Err(e) => {
+                warn!("Could not list QuickLook generators: {:?}", e);
+                return HashSet::new();
+            },
+        };
+        // Generators are listed one per line, as "  public.jpeg -> /path/to/Generator.qlgenerator (1.0)"
+        String::from_utf8_lossy(&output.stdout).lines()
+            .filter_map(|line| line.split_once("->"))
+            .map(|(content_type, _)| content_type.trim().to_owned())
+            .filter(|content_type| !content_type.is_empty())
+            .collect()
+    });
+
+    /// Owned CFString, released when dropped
+    struct CFString(CFStringRef);
+
+    impl CFString {
+        fn new(string: &str) -> Option<CFString> {
+            let cf_string = unsafe {
+                CFStringCreateWithBytes(std::ptr::null(), string.as_ptr(), string.len() as isize,
+                    K_CF_STRING_ENCODING_UTF8, 0)
+            };
+            (!cf_string.is_null()).then_some(CFString(cf_string))
+        }
+
+        fn to_rust_string(&self) -> Option<String> {
+            let mut buffer = [0u8; 256];
+            let copied = unsafe {
+                CFStringGetCString(self.0, buffer.as_mut_ptr(), buffer.len() as isize, K_CF_STRING_ENCODING_UTF8)
+            };
+            if copied == 0 {
+                return None;
+            }
+            let end = buffer.iter().position(|b| *b == 0).unwrap_or(buffer.len());
+            String::from_utf8(buffer[..end].to_vec()).ok()
+        }
+    }
+
+    impl Drop for CFString {
+        fn drop(&mut self) {
+            unsafe { CFRelease(self.0) };
+        }
+    }
+
+    fn uti_for_extension(extension: &str) -> Option<String> {
+        let extension = CFString::new(extension)?;
+        let uti = unsafe {
+            UTTypeCreatePreferredIdentifierForTag(kUTTagClassFilenameExtension, extension.0, std::ptr::null())
+        };
+        if uti.is_null() {
+            return None;
+        }
+        CFString(uti).to_rust_string()
+    }
+
+    fn conforms_to(uti: &str, conforms_to_uti: &str) -> bool {
+        match (CFString::new(uti), CFString::new(conforms_to_uti)) {
+            (Some(uti), Some(conforms_to_uti)) => unsafe { UTTypeConformsTo(uti.0, conforms_to_uti.0) != 0 },
+            _ => false,
+        }
     }
 }
-impl GeneratesPreview for WindowsPreviewGenerator {
-    async fn generate_preview(&self, _file: File) -> Result<Utf8PathBuf, anyhow::Error> {
-        Ok(Utf8PathBuf::new())
+
+#[cfg(target_os = "windows")]
+mod windows_thumbnail {
+    use std::ffi::c_void;
+
+    use camino::Utf8Path;
+    use image::{DynamicImage, RgbaImage};
+    use windows::{
+        core::{w, HSTRING},
+        Win32::{
+            Foundation::SIZE,
+            Graphics::Gdi::{DeleteObject, GetDC, GetDIBits, GetObjectW, ReleaseDC, BITMAP, BITMAPINFO,
+                BITMAPINFOHEADER, BI_RGB, DIB_RGB_COLORS},
+            System::Com::{CoInitializeEx, CoUninitialize, COINIT_MULTITHREADED},
+            UI::Shell::{AssocQueryStringW, IShellItemImageFactory, SHCreateItemFromParsingName, ASSOCF_NONE,
+                ASSOCSTR_SHELLEXTENSION, SIIGBF_THUMBNAILONLY},
+        },
+    };
+
+    pub fn has_generator_for_type(extension: &str) -> bool {
+        let extension = HSTRING::from(format!(".{}", extension));
+        let mut length = 0u32;
+        // Succeeds if an IThumbnailProvider shell extension is registered for the file type
+        unsafe {
+            AssocQueryStringW(ASSOCF_NONE, ASSOCSTR_SHELLEXTENSION, &extension,
+                w!("{e357fccd-a995-4576-b01f-234630154e96}"), None, &mut length).is_ok()
+        }
+    }
+
+    pub fn thumbnail(path: &Utf8Path, max_side: u32) -> Result<Option<DynamicImage>, anyhow::Error> {
+        let com_initialized = unsafe { CoInitializeEx(None, COINIT_MULTITHREADED) }.is_ok();
+        let result = unsafe { render_thumbnail(path, max_side) };
+        if com_initialized {
+            unsafe { CoUninitialize() };
+        }
+        result
+    }
+
+    // Private functions
+
+    unsafe fn render_thumbnail(path: &Utf8Path, max_side: u32) -> Result<Option<DynamicImage>, anyhow::Error> {
+        let factory: IShellItemImageFactory = SHCreateItemFromParsingName(&HSTRING::from(crate::files::os_path::to_os_path(path).as_os_str()), None)?;
+        let size = SIZE { cx: max_side as i32, cy: max_side as i32 };
+        let bitmap = match factory.GetImage(size, SIIGBF_THUMBNAILONLY) {
+            Ok(bitmap) => bitmap,
+            // No thumbnail handler could render the file
+            Err(_) => return Ok(None),
+        };
+
+        let mut info = BITMAP::default();
+        GetObjectW(bitmap.into(), std::mem::size_of::<BITMAP>() as i32, Some(&mut info as *mut _ as *mut c_void));
+        let (width, height) = (info.bmWidth, info.bmHeight);
+
+        let mut header = BITMAPINFO {
+            bmiHeader: BITMAPINFOHEADER {
+                biSize: std::mem::size_of::<BITMAPINFOHEADER>() as u32,
+                biWidth: width,
+                // Negative height requests rows from top to bottom
+                biHeight: -height,
+                biPlanes: 1,
+                biBitCount: 32,
+                biCompression: BI_RGB.0,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut pixels = vec![0u8; (width * height * 4) as usize];
+        let dc = GetDC(None);
+        let copied_rows = GetDIBits(dc, bitmap, 0, height as u32, Some(pixels.as_mut_ptr() as *mut c_void),
+            &mut header, DIB_RGB_COLORS);
+        ReleaseDC(None, dc);
+        let _ = DeleteObject(bitmap.into());
+        if copied_rows == 0 {
+            return Err(anyhow::anyhow!("Could not read thumbnail bitmap"));
+        }
+
+        // Bitmaps are stored as BGRA
+        for pixel in pixels.chunks_exact_mut(4) {
+            pixel.swap(0, 2);
+        }
+        let image = RgbaImage::from_raw(width as u32, height as u32, pixels)
+            .ok_or_else(|| anyhow::anyhow!("Thumbnail bitmap had unexpected dimensions"))?;
+        Ok(Some(DynamicImage::from(image)))
     }
-}
\ No newline at end of file
+}
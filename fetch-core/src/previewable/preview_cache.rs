@@ -0,0 +1,217 @@
+use std::{collections::HashMap, io, sync::{Arc, Mutex}, time::SystemTime};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use image::DynamicImage;
+use tokio::fs;
+use tracing::{debug, instrument, warn};
+
+use crate::{app_config, files::os_path::to_os_path, previewable::{PossiblyPreviewable, PreviewError, PreviewSize}};
+
+/// A preview of a file that has been persisted to the preview directory
+#[derive(Debug, Clone)]
+pub struct CachedPreview {
+    /// The path to the original file
+    pub path: Utf8PathBuf,
+    pub size: PreviewSize,
+    /// The path to the generated preview file
+    pub preview_path: Utf8PathBuf,
+    /// The preview image
+    pub image: Arc<DynamicImage>,
+}
+
+/// Caches previews in memory and on disk, so previews are only generated once for each version of a file.
+///
+/// Previews are keyed by the path and modified time of the original file and the preview size, so changing a file invalidates its
+/// previews. Recently used previews are kept in memory up to the memory budget, and the preview directory is
+/// trimmed of its least recently generated previews whenever it grows past the disk budget.
+pub struct PreviewCache {
+    memory: Mutex<MemoryCache>,
+    memory_budget: u64,
+    disk_budget: u64,
+}
+
+impl Default for PreviewCache {
+    fn default() -> Self {
+        PreviewCache::new()
+    }
+}
+
+impl PreviewCache {
+    pub fn new() -> PreviewCache {
+        PreviewCache {
+            memory: Mutex::new(MemoryCache::default()),
+            memory_budget: DEFAULT_MEMORY_BUDGET,
+            disk_budget: DEFAULT_DISK_BUDGET,
+        }
+    }
+
+    /// Sets the maximum number of bytes of previews kept in memory. Defaults to 64 MB.
+    pub fn with_memory_budget(mut self, bytes: u64) -> PreviewCache {
+        self.memory_budget = bytes;
+        self
+    }
+
+    /// Sets the maximum number of bytes of previews kept on disk. Defaults to 1 GB.
+    pub fn with_disk_budget(mut self, bytes: u64) -> PreviewCache {
+        self.disk_budget = bytes;
+        self
+    }
+
+    /// Returns the preview of the file at the size, generating it if there is no preview of the current version of
+    /// the file.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Some(CachedPreview))` - The preview of the file
+    /// * `Ok(None)` - No preview could be generated (unsupported file type)
+    /// * `Err(PreviewError)` - An error occurred during preview generation
+    #[instrument(skip(self))]
+    pub async fn get(&self, path: &Utf8Path, size: PreviewSize) -> Result<Option<CachedPreview>, PreviewError> {
+        let modified = fs::metadata(to_os_path(path)).await
+            .and_then(|m| m.modified())
+            .map_err(|e| match e.kind() {
+                io::ErrorKind::NotFound => PreviewError::NotFound { path: path.to_string() },
+                _ => PreviewError::IO { path: path.to_string(), source: e },
+            })?;
+
+        if let Some(preview) = self.memory.lock().expect("Preview cache lock should not be poisoned")
+            .get(path, size, modified) {
+            return Ok(Some(preview));
+        }
+
+        let preview = match path.preview(size).await? {
+            Some(preview) => preview,
+            None => return Ok(None),
+        };
+        // Callers of the cache expect the preview path to be usable straight away
+        let preview_path = preview.persisted.wait().await?;
+        let preview = CachedPreview { path: preview.path, size, preview_path, image: Arc::new(preview.image) };
+
+        let misses = {
+            let mut memory = self.memory.lock().expect("Preview cache lock should not be poisoned");
+            memory.insert(modified, preview.clone(), self.memory_budget);
+            memory.misses += 1;
+            memory.misses
+        };
+        // Walking the preview directory is expensive, so the disk budget is only checked every so often
+        if misses % DISK_CHECK_INTERVAL == 1 {
+            self.enforce_disk_budget().await
+                .unwrap_or_else(|e| warn!("Could not trim preview directory to disk budget: {:?}", e));
+        }
+
+        Ok(Some(preview))
+    }
+
+    /// Removes the previews of the file from memory. Previews on disk are keyed by the modified time of the file,
+    /// so they do not need to be invalidated.
+    pub fn invalidate(&self, path: &Utf8Path) {
+        self.memory.lock().expect("Preview cache lock should not be poisoned").remove(path);
+    }
+
+    /// Deletes the least recently generated previews until the preview directory fits within the disk budget.
+    /// Returns the number of bytes deleted.
+    #[instrument(skip(self))]
+    pub async fn enforce_disk_budget(&self) -> Result<u64, io::Error> {
+        let mut previews = vec![];
+        let mut entries = fs::read_dir(app_config::get_default_preview_directory()).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let metadata = entry.metadata().await?;
+            if metadata.is_file() {
+                previews.push((entry.path(), metadata.len(), metadata.modified()?));
+            }
+        }
+
+        let mut total: u64 = previews.iter().map(|(_, size, _)| size).sum();
+        if total <= self.disk_budget {
+            return Ok(0);
+        }
+
+        previews.sort_by_key(|(_, _, modified)| *modified);
+        let mut removed = 0;
+        for (path, size, _) in previews {
+            if total <= self.disk_budget {
+                break;
+            }
+            fs::remove_file(&path).await?;
+            total -= size;
+            removed += size;
+        }
+        debug!("Removed {} bytes of previews to fit the disk budget", removed);
+
+        Ok(removed)
+    }
+}
+
+// Private functions and variables
+
+const DEFAULT_MEMORY_BUDGET: u64 = 64 * 1024 * 1024;
+const DEFAULT_DISK_BUDGET: u64 = 1024 * 1024 * 1024;
+/// Number of newly cached previews between checks of the disk budget
+const DISK_CHECK_INTERVAL: u64 = 64;
+
+struct MemoryEntry {
+    modified: SystemTime,
+    preview: CachedPreview,
+    last_used: u64,
+}
+
+/// Least recently used cache of previews, keyed by the path of the original file and the preview size
+#[derive(Default)]
+struct MemoryCache {
+    entries: HashMap<(Utf8PathBuf, PreviewSize), MemoryEntry>,
+    size: u64,
+    clock: u64,
+    misses: u64,
+}
+
+impl MemoryCache {
+    fn get(&mut self, path: &Utf8Path, size: PreviewSize, modified: SystemTime) -> Option<CachedPreview> {
+        let key = (path.to_path_buf(), size);
+        let is_current = self.entries.get(&key).is_some_and(|entry| entry.modified == modified);
+        if !is_current {
+            // Drop any preview of a previous version of the file
+            self.remove_entry(&key);
+            return None;
+        }
+
+        self.clock += 1;
+        let entry = self.entries.get_mut(&key).expect("Entry should exist, it was just checked");
+        entry.last_used = self.clock;
+        Some(entry.preview.clone())
+    }
+
+    fn insert(&mut self, modified: SystemTime, preview: CachedPreview, budget: u64) {
+        let key = (preview.path.clone(), preview.size);
+        self.remove_entry(&key);
+        self.clock += 1;
+        self.size += image_size(&preview.image);
+        self.entries.insert(key, MemoryEntry { modified, preview, last_used: self.clock });
+
+        while self.size > budget {
+            let least_recent = self.entries.iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone());
+            match least_recent {
+                Some(key) => self.remove_entry(&key),
+                None => break,
+            }
+        }
+    }
+
+    /// Removes the previews of the file at every size
+    fn remove(&mut self, path: &Utf8Path) {
+        for size in [PreviewSize::Thumbnail, PreviewSize::Detail] {
+            self.remove_entry(&(path.to_path_buf(), size));
+        }
+    }
+
+    fn remove_entry(&mut self, key: &(Utf8PathBuf, PreviewSize)) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.size -= image_size(&entry.preview.image);
+        }
+    }
+}
+
+fn image_size(image: &DynamicImage) -> u64 {
+    image.as_bytes().len() as u64
+}
@@ -0,0 +1,81 @@
+use std::sync::{LazyLock, Mutex};
+
+use chrono::Utc;
+
+/// Returns a new sequence number, greater than every sequence number issued or observed by this process so far.
+///
+/// Sequence numbers order writes to keyed stores (see store::KeyedSequencedData): a stored row is only replaced by
+/// a row with an equal or greater sequence number. They come from a hybrid logical clock, so they follow the wall
+/// clock closely enough to be compared across processes (eg. the GUI, the CLI and the file daemon indexing the same
+/// file), but never go backwards when the wall clock does, or when two writes happen in the same millisecond.
+///
+/// The upper 48 bits are milliseconds since the unix epoch, and the lower 16 bits a counter that orders sequence
+/// numbers issued in the same millisecond. Sequence numbers from before the clock existed were plain milliseconds,
+/// so they are always smaller than the ones it issues.
+pub fn next() -> u64 {
+    CLOCK.lock().expect("Sequence clock lock should not be poisoned").tick(wall_clock_millis())
+}
+
+/// Records a sequence number read from elsewhere, eg. from a row written by another process, so that sequence
+/// numbers issued afterwards are greater than it.
+pub fn observe(sequence_number: u64) {
+    CLOCK.lock().expect("Sequence clock lock should not be poisoned").observe(sequence_number);
+}
+
+// Private functions and variables
+
+const LOGICAL_BITS: u32 = 16;
+const LOGICAL_MASK: u64 = (1 << LOGICAL_BITS) - 1;
+
+static CLOCK: LazyLock<Mutex<HybridLogicalClock>> = LazyLock::new(|| Mutex::new(HybridLogicalClock::default()));
+
+#[derive(Debug, Default)]
+struct HybridLogicalClock {
+    /// Physical part of the latest sequence number issued or observed, in milliseconds
+    physical: u64,
+    /// Logical part of the latest sequence number issued or observed
+    logical: u64,
+}
+
+impl HybridLogicalClock {
+    fn tick(&mut self, now: u64) -> u64 {
+        if now > self.physical {
+            self.physical = now;
+            self.logical = 0;
+        } else {
+            self.advance_logical();
+        }
+        encode(self.physical, self.logical)
+    }
+
+    /// Merges in a sequence number, so the next tick moves past it unless the wall clock already has
+    fn observe(&mut self, sequence_number: u64) {
+        let (physical, logical) = decode(sequence_number);
+        if physical > self.physical || (physical == self.physical && logical > self.logical) {
+            self.physical = physical;
+            self.logical = logical;
+        }
+    }
+
+    /// Moves the logical counter forward, borrowing a millisecond from the future if it runs out
+    fn advance_logical(&mut self) {
+        if self.logical == LOGICAL_MASK {
+            self.physical += 1;
+            self.logical = 0;
+        } else {
+            self.logical += 1;
+        }
+    }
+}
+
+fn encode(physical: u64, logical: u64) -> u64 {
+    (physical << LOGICAL_BITS) | (logical & LOGICAL_MASK)
+}
+
+fn decode(sequence_number: u64) -> (u64, u64) {
+    (sequence_number >> LOGICAL_BITS, sequence_number & LOGICAL_MASK)
+}
+
+fn wall_clock_millis() -> u64 {
+    Utc::now().timestamp_millis().max(0) as u64
+}
@@ -1,7 +1,10 @@
 use std::future::Future;
 
+use async_trait::async_trait;
 use chrono::{DateTime, Utc};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+
+use crate::app_config;
 
 /// Errors that can occur during keyed store operations.
 #[derive(thiserror::Error, Debug)]
@@ -27,6 +30,8 @@ pub trait KeyedSequencedStore<K: Serialize + Send, D: KeyedSequencedData<K>> {
 
 pub trait KeyedSequencedData<K: Serialize + Send> {
     fn get_key(&self) -> K;
+    /// Stored data is only replaced by data with the same key and an equal or greater sequence number. Sequence
+    /// numbers should come from crate::sequence, so they are ordered across processes.
     fn get_sequence_num(&self) -> u64;
 }
 
@@ -36,26 +41,47 @@ pub trait KeyedSequencedData<K: Serialize + Send> {
 pub enum FilterStoreError {
     #[error("Filter provided for attribute that is not marked as filterable")]
     UnavailableFilter { attribute: String },
+    /// The filter value cannot be compared against, eg. a float that is NaN or infinite
+    #[error("Filter value for attribute {attribute} is invalid: {issue}")]
+    InvalidFilterValue { attribute: String, issue: String },
     /// An error occurred during a CRUD operation on a single record.
     /// 
     /// This error wraps underlying storage errors that occur during index, update,
     /// or delete operations on individual records.
     #[error("Error performing clear with filters operation on table")]
     Clear { #[source] source: anyhow::Error },
+    #[error("Error performing restore with filters operation on table")]
+    Restore { #[source] source: anyhow::Error },
     #[error("Error performing query with filters on table")]
     Query { #[source] source: anyhow::Error },
+    /// A query result could not be decoded into data.
+    ///
+    /// This error occurs when the result is missing a column of the data schema, which usually means the table
+    /// is damaged.
+    #[error("Could not decode filter query result: {issue}")]
+    Decode { issue: String },
     #[error("Unknown Error")]
     Other { #[source] source: anyhow::Error },
 }
 
 pub trait Filterable {
     fn filterable_attributes() -> Vec<&'static str>;
+    /// Attributes holding JSON objects, whose entries can be filtered on with FilterValue::Entry. Entries are found
+    /// by searching the JSON, unless their key has been promoted to a column of its own (see
+    /// lancedb::LanceDBSchema).
+    fn keyed_attributes() -> Vec<&'static str> {
+        vec![]
+    }
 }
 
 pub enum FilterRelation {
     Lt,
     Eq,
     Gt,
+    /// The attribute is a list containing the value
+    Contains,
+    /// The attribute is a string starting with the value
+    StartsWith,
 }
 
 pub struct Filter<'a> {
@@ -69,12 +95,21 @@ pub enum FilterValue<'a> {
     Int(i32),
     Float(f32),
     DateTime(&'a DateTime<Utc>),
+    /// The attribute is a JSON object with the key set to the value. Only the Eq relation is supported. Numbers and
+    /// booleans are matched by their JSON text, eg. "100" or "true".
+    Entry { key: &'a str, value: &'a str },
 }
 
 pub trait ClearByFilter<D: Filterable> {
     fn clear_filter<'a>(&self, filters: &[Filter<'a>]) -> impl Future<Output = Result<(), FilterStoreError>> + Send;
 }
 
+/// Stores that keep cleared data for a while before removing it for good, so that it can be restored.
+pub trait RestoreByFilter<D: Filterable> {
+    /// Restores the cleared data matching the filters. Returns the number of records restored.
+    fn restore_filter<'a>(&self, filters: &[Filter<'a>]) -> impl Future<Output = Result<u64, FilterStoreError>> + Send;
+}
+
 pub trait QueryByFilter<D: Filterable> {
     fn query_filter<'a>(&self, filters: &[Filter<'a>]) -> impl Future<Output = Result<Vec<D>, FilterStoreError>> + Send;
     fn query_filter_n<'a>(&self, filters: &[Filter<'a>], num_results: u32, offset: u32) -> impl Future<Output = Result<Vec<D>, FilterStoreError>> + Send;
@@ -95,7 +130,13 @@ pub enum VectorStoreError {
     /// 
     /// This error wraps underlying errors that occur during similarity search operations.
     #[error("Error performing vector query: {source}")]
-    Query { #[source] source: anyhow::Error }
+    Query { #[source] source: anyhow::Error },
+    /// A query result could not be decoded into data and scores.
+    /// 
+    /// This error occurs when the result is missing a column, eg. the _distance column of a vector query, or
+    /// its columns have different lengths, which usually means the table is damaged.
+    #[error("Could not decode vector query result: {issue}")]
+    Decode { issue: String },
 }
 
 pub trait VectorData {
@@ -116,8 +157,54 @@ pub struct VectorQueryResult<D: VectorData> {
     pub distance: f32,
 }
 
+/// How the text of an FTS attribute is split into the terms it is searched by. Queries are split the same way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FtsAnalyzer {
+    /// Lowercased words, separated by whitespace and punctuation
+    Words,
+    /// Every pair of adjacent characters, for languages written without spaces between words, eg. Chinese
+    Bigrams,
+}
+
 pub trait FTSData {
     fn fts_attributes() -> Vec<&'static str>;
+    fn fts_analyzer(_attr: &str) -> FtsAnalyzer {
+        FtsAnalyzer::Words
+    }
+}
+
+/// Weights the scores of the vector and full text searches of a hybrid query are added up with, set under [hybrid]
+/// in the ranking.toml settings file. Vector scores are the cosine similarity of a chunk to the query, and full text
+/// scores are relative to the best full text match of the query, so the best chunk matching both the meaning and the
+/// words of a query scores up to vector + fts. With the default vector weight of 1.0, chunks only found by their
+/// meaning score the same as in queries without full text search.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub struct FusionWeights {
+    #[serde(default = "default_vector_weight")]
+    pub vector: f32,
+    #[serde(default = "default_fts_weight")]
+    pub fts: f32,
+}
+
+impl Default for FusionWeights {
+    fn default() -> Self {
+        FusionWeights { vector: default_vector_weight(), fts: default_fts_weight() }
+    }
+}
+
+impl FusionWeights {
+    /// Loads the fusion weights from the ranking.toml settings file, with defaults for anything not set
+    pub fn from_settings() -> Result<FusionWeights, anyhow::Error> {
+        let weights = match app_config::get_ranking_config()?.get::<FusionWeights>("hybrid") {
+            Ok(weights) => weights,
+            Err(config::ConfigError::NotFound(_)) => FusionWeights::default(),
+            Err(e) => return Err(e.into()),
+        };
+        if weights.vector < 0.0 || weights.fts < 0.0 {
+            return Err(anyhow::anyhow!("Hybrid search weights must not be negative, got {:?}", weights));
+        }
+        Ok(weights)
+    }
 }
 
 pub trait QueryFull<D: VectorData + Filterable + FTSData> {
@@ -131,6 +218,18 @@ pub trait QueryFull<D: VectorData + Filterable + FTSData> {
         num_results: u32,
         offset: u32,
     ) -> impl Future<Output = Result<Vec<FullQueryResult<D>>, anyhow::Error>> + Send;
+    /// Like query_full_n, but only reads the given attributes of the results, eg. to avoid decoding large attributes
+    /// that the caller does not use. The other attributes of the results are left empty or zero, so only the
+    /// attributes asked for can be relied on.
+    fn query_full_select_n<'a>(
+        &self,
+        vector: Option<Vec<f32>>,
+        fts_terms: Option<&str>,
+        filters: &[Filter<'a>],
+        attributes: &[&str],
+        num_results: u32,
+        offset: u32,
+    ) -> impl Future<Output = Result<Vec<FullQueryResult<D>>, anyhow::Error>> + Send;
 }
 
 pub struct FullQueryResult<D: VectorData + Filterable + FTSData> {
@@ -141,4 +240,127 @@ pub struct FullQueryResult<D: VectorData + Filterable + FTSData> {
     pub score: f32,
 }
 
+// Reranking traits
+
+/// Describes an object that rescores the results of a full query against the original query text.
+///
+/// Rerankers are usually too expensive to run over an entire table, so they are meant to be applied to the
+/// top results that were already retrieved (and fused) by a QueryFull implementation.
+#[async_trait]
+pub trait Reranking<D: VectorData + Filterable + FTSData + Send>: Send + Sync {
+    /// Rescores the given results against the query. Returned results are sorted by descending score.
+    async fn rerank(&self, query: &str, results: Vec<FullQueryResult<D>>) -> Result<Vec<FullQueryResult<D>>, anyhow::Error>;
+}
+
+// Chunk store traits
+
+/// Object safe combination of the store traits index providers keep their chunks in, so providers can hold any store
+/// backend as an Arc<dyn ChunkStore<D>> instead of being generic over it. Implemented for every store that implements
+/// all of them, eg. LanceDBStore.
+#[async_trait]
+pub trait ChunkStore<D>: Send + Sync
+where
+    D: KeyedSequencedData<String> + VectorData + Filterable + FTSData + Send
+{
+    async fn put(&self, data: Vec<D>) -> Result<(), KeyedSequencedStoreError>;
+    async fn clear(&self, key: String, optional_sequence_number: Option<u64>) -> Result<(), KeyedSequencedStoreError>;
+    async fn get(&self, key: String) -> Result<Option<D>, KeyedSequencedStoreError>;
+    async fn query_full_n<'a>(
+        &self,
+        vector: Option<Vec<f32>>,
+        fts_terms: Option<&str>,
+        filters: &[Filter<'a>],
+        num_results: u32,
+        offset: u32,
+    ) -> Result<Vec<FullQueryResult<D>>, anyhow::Error>;
+    /// See QueryFull::query_full_select_n
+    async fn query_full_select_n<'a>(
+        &self,
+        vector: Option<Vec<f32>>,
+        fts_terms: Option<&str>,
+        filters: &[Filter<'a>],
+        attributes: &[&str],
+        num_results: u32,
+        offset: u32,
+    ) -> Result<Vec<FullQueryResult<D>>, anyhow::Error>;
+    async fn query_filter<'a>(&self, filters: &[Filter<'a>]) -> Result<Vec<D>, FilterStoreError>;
+    async fn query_filter_n<'a>(&self, filters: &[Filter<'a>], num_results: u32, offset: u32) -> Result<Vec<D>, FilterStoreError>;
+    async fn clear_filter<'a>(&self, filters: &[Filter<'a>]) -> Result<(), FilterStoreError>;
+    /// See RestoreByFilter::restore_filter
+    async fn restore_filter<'a>(&self, filters: &[Filter<'a>]) -> Result<u64, FilterStoreError>;
+}
+
+#[async_trait]
+impl<D, S> ChunkStore<D> for S
+where
+    D: KeyedSequencedData<String> + VectorData + Filterable + FTSData + Send,
+    S: KeyedSequencedStore<String, D> +
+        QueryFull<D> +
+        QueryByFilter<D> +
+        ClearByFilter<D> +
+        RestoreByFilter<D> +
+        Send + Sync
+{
+    async fn put(&self, data: Vec<D>) -> Result<(), KeyedSequencedStoreError> {
+        <S as KeyedSequencedStore<String, D>>::put(self, data).await
+    }
+
+    async fn clear(&self, key: String, optional_sequence_number: Option<u64>) -> Result<(), KeyedSequencedStoreError> {
+        <S as KeyedSequencedStore<String, D>>::clear(self, key, optional_sequence_number).await
+    }
+
+    async fn get(&self, key: String) -> Result<Option<D>, KeyedSequencedStoreError> {
+        <S as KeyedSequencedStore<String, D>>::get(self, key).await
+    }
+
+    async fn query_full_n<'a>(
+        &self,
+        vector: Option<Vec<f32>>,
+        fts_terms: Option<&str>,
+        filters: &[Filter<'a>],
+        num_results: u32,
+        offset: u32,
+    ) -> Result<Vec<FullQueryResult<D>>, anyhow::Error> {
+        <S as QueryFull<D>>::query_full_n(self, vector, fts_terms, filters, num_results, offset).await
+    }
+
+    async fn query_full_select_n<'a>(
+        &self,
+        vector: Option<Vec<f32>>,
+        fts_terms: Option<&str>,
+        filters: &[Filter<'a>],
+        attributes: &[&str],
+        num_results: u32,
+        offset: u32,
+    ) -> Result<Vec<FullQueryResult<D>>, anyhow::Error> {
+        <S as QueryFull<D>>::query_full_select_n(self, vector, fts_terms, filters, attributes, num_results, offset).await
+    }
+
+    async fn query_filter<'a>(&self, filters: &[Filter<'a>]) -> Result<Vec<D>, FilterStoreError> {
+        <S as QueryByFilter<D>>::query_filter(self, filters).await
+    }
+
+    async fn query_filter_n<'a>(&self, filters: &[Filter<'a>], num_results: u32, offset: u32) -> Result<Vec<D>, FilterStoreError> {
+        <S as QueryByFilter<D>>::query_filter_n(self, filters, num_results, offset).await
+    }
+
+    async fn clear_filter<'a>(&self, filters: &[Filter<'a>]) -> Result<(), FilterStoreError> {
+        <S as ClearByFilter<D>>::clear_filter(self, filters).await
+    }
+
+    async fn restore_filter<'a>(&self, filters: &[Filter<'a>]) -> Result<u64, FilterStoreError> {
+        <S as RestoreByFilter<D>>::restore_filter(self, filters).await
+    }
+}
+
+// Private functions
+
+fn default_vector_weight() -> f32 {
+    1.0
+}
+
+fn default_fts_weight() -> f32 {
+    0.3
+}
+
 pub mod lancedb;
\ No newline at end of file
@@ -1,17 +1,29 @@
-use std::{future::Future, marker::PhantomData, sync::{Arc, LazyLock, atomic::{AtomicI32, Ordering}}};
+use std::{cmp::Reverse, collections::{HashMap, hash_map::Entry}, future::Future, marker::PhantomData, sync::{Arc, LazyLock, Mutex, atomic::{AtomicI32, Ordering}}, time::Duration};
 
-use arrow::array::{StringBuilder, UInt64Builder};
-use arrow_array::{Array, ArrayRef, Float32Array, RecordBatch, RecordBatchIterator, RecordBatchReader, StructArray};
+use arrow::array::{Int64Array, StringBuilder, UInt64Builder};
+use arrow_array::{Array, ArrayRef, Float32Array, RecordBatch, RecordBatchIterator, RecordBatchReader, StringArray, StructArray, new_null_array};
 use arrow_schema::{DataType, Field, Schema};
-use futures::stream::StreamExt;
-use lancedb::{Connection, DistanceType, Table, connect, database::CreateTableMode, index::{Index, scalar::{FtsQuery, FullTextSearchQuery, MultiMatchQuery, Operator}, vector::IvfPqIndexBuilder}, query::{ExecutableQuery, Query, QueryBase, QueryExecutionOptions, VectorQuery}, rerankers::{Reranker, rrf::RRFReranker}, table::OptimizeAction};
-use log::info;
+use futures::{FutureExt, future::{BoxFuture, Shared}, stream::StreamExt};
+use lancedb::{Connection, DistanceType, Table, arrow::SendableRecordBatchStream, connect, database::CreateTableMode, index::{Index, IndexType, scalar::{FtsIndexBuilder, FtsQuery, FullTextSearchQuery, MultiMatchQuery, Operator}, vector::IvfPqIndexBuilder}, query::{ExecutableQuery, Query, QueryBase, Select, VectorQuery}, table::{ColumnAlteration, CompactionOptions, NewColumnTransform, OptimizeAction, OptimizeOptions, Tags}};
+use chrono::{TimeDelta, Utc};
 use serde::Serialize;
+use serde_json::{Map, Value};
+use tracing::{debug, info, instrument, warn};
 
-use crate::store::{ClearByFilter, FTSData, Filter, FilterRelation, FilterStoreError, FilterValue, Filterable, FullQueryResult, KeyedSequencedData, KeyedSequencedStore, KeyedSequencedStoreError, QueryByFilter, QueryByVector, QueryFull, VectorData, VectorQueryResult, VectorStoreError};
+use crate::{sequence, store::{ClearByFilter, FTSData, Filter, FtsAnalyzer, FilterRelation, FilterStoreError, FilterValue, Filterable, FullQueryResult, FusionWeights, KeyedSequencedData, KeyedSequencedStore, KeyedSequencedStoreError, QueryByFilter, QueryByVector, QueryFull, RestoreByFilter, VectorData, VectorQueryResult, VectorStoreError}};
 
 // Number of operations to run before running optimize.
 const OPERATIONS_PER_OPTIMIZE: i32 = 20;
+/// How long cleared rows are kept before they are purged, during which they can be restored (see RestoreByFilter)
+const CLEARED_ROW_RETENTION: TimeDelta = TimeDelta::days(7);
+/// How often stores opened with LanceDBStore::read_only check for versions written by other processes
+const READ_ONLY_CONSISTENCY_INTERVAL: Duration = Duration::from_secs(10);
+/// Data directory of the empty tables opened by LanceDBStore::read_only for tables that do not exist yet
+const IN_MEMORY_DATA_DIR: &str = "memory:///";
+
+/// Column the sequence number of every row is stored in, see KeyedSequencedData::get_sequence_num. It is part of
+/// every batch passed to ArrowData::batch_to_iter, so data types can read their sequence numbers back.
+pub const SEQUENCE_NUMBER_COLUMN: &str = "sequence_number";
 
 #[derive(thiserror::Error, Debug)]
 pub enum LanceDBError {
@@ -27,12 +39,43 @@ pub enum LanceDBError {
     Connection (#[source] lancedb::error::Error),
     #[error("Error performing holistic table operations")]
     TableOperation { operation: &'static str, #[source] source: lancedb::error::Error },
+    #[error("Table is damaged and could not be read in full")]
+    Corrupted { table: String, #[source] source: anyhow::Error },
+    #[error("Table {table} was opened read-only and cannot be written to")]
+    ReadOnly { table: String },
+}
+
+/// Outcome of recovering a damaged table, see LanceDBStore::recover
+#[derive(Debug)]
+pub enum TableRecovery {
+    /// The table was restored to an earlier version that could still be read in full
+    Restored { version: u64, salvaged_keys: Vec<String> },
+    /// No earlier version could be read in full, so the table was dropped. It is created again, empty, the next
+    /// time the store is opened.
+    Dropped { salvaged_keys: Vec<String> },
+}
+
+impl TableRecovery {
+    /// Keys of the rows that could still be read from the damaged version of the table. Rows are stored under
+    /// their serialized (JSON) keys.
+    pub fn salvaged_keys(&self) -> &[String] {
+        match self {
+            TableRecovery::Restored { salvaged_keys, .. } => salvaged_keys,
+            TableRecovery::Dropped { salvaged_keys } => salvaged_keys,
+        }
+    }
 }
 
 pub trait ArrowData: Send + Sync where Self: Sized {
     type RowBuilder: RowBuilder<Self> + Send;
 
     fn schema() -> Schema;
+    /// The schema tables of the data are created with, and columns added to them with. Unlike schema, which is read
+    /// for every batch written or read, this is read once when the table is opened, so it can add field metadata
+    /// taken from the settings, eg. the compression of a column.
+    fn table_schema() -> Schema {
+        Self::schema()
+    }
     fn row_builder() -> Self::RowBuilder;
     fn attribute_to_column_name(attr: &str) -> &'static str;
     fn batch_to_iter(record_batch: RecordBatch) -> impl IntoIterator<Item = Self>;
@@ -61,7 +104,9 @@ pub struct LanceDBStore<D: ArrowData> {
     table: Table,
     table_name: String,
     schema: Arc<Schema>,
+    fusion_weights: FusionWeights,
     ops_to_optimize: Arc<AtomicI32>,
+    read_only: bool,
     _phantom_data: PhantomData<D>,
 }
 
@@ -74,18 +119,26 @@ impl<D: ArrowData> LanceDBStore<D> {
             .map_err(|e| LanceDBError::InvalidParameter {
                 parameter: "data schema",
                 issue: "Data schema and base schema could not be merged. \
-                    Could there be a key conflict? Data schema must not use 'key' or 'sequence_number' keys.",
+                    Could there be a key conflict? Data schema must not use 'key', 'sequence_number' or 'cleared_at' keys.",
+                source: Some(e.into()),
+            })?);
+        // Same fields as the schema, see ArrowData::table_schema
+        let table_schema = Arc::new(Schema::try_merge([build_base_schema(), D::table_schema()])
+            .map_err(|e| LanceDBError::InvalidParameter {
+                parameter: "data table schema",
+                issue: "Data table schema and base schema could not be merged.",
                 source: Some(e.into()),
             })?);
 
         let db = connect(data_dir)
             .execute().await
             .map_err(LanceDBError::Connection)?;
-        let table = db.create_empty_table(table_name.clone(), schema.clone())
+        let table = db.create_empty_table(table_name.clone(), table_schema.clone())
             .mode(CreateTableMode::ExistOk(Box::new(|r| r)))
             .execute().await
             .map_err(|e| LanceDBError::TableOperation { operation: "Creating or opening table", source: e })?;
 
+        Self::add_missing_columns(&table, &table_schema).await?;
         Self::create_key_index(&table).await?;
 
         Ok(LanceDBStore {
@@ -93,12 +146,92 @@ impl<D: ArrowData> LanceDBStore<D> {
             table,
             table_name,
             schema,
+            fusion_weights: FusionWeights::default(),
+            ops_to_optimize: Arc::new(AtomicI32::new(OPERATIONS_PER_OPTIMIZE)),
+            read_only: false,
+            _phantom_data: Default::default(),
+        })
+    }
+
+    /// Opens the table without writing anything to the data directory, eg. to query an index on a network share that
+    /// another machine indexes into. The table is not created, migrated or indexed, and every write to it, including
+    /// optimizing, fails with LanceDBError::ReadOnly. The table is read as it was indexed, so it should be opened
+    /// read-write by the indexing machine first for its indexes to exist. Tables that do not exist yet are opened as
+    /// empty tables in memory. Versions written by the indexing machine show up within READ_ONLY_CONSISTENCY_INTERVAL.
+    pub async fn read_only(data_dir: &str, table_name: String) -> Result<LanceDBStore<D>, LanceDBError> {
+        let schema = Arc::new(Schema::try_merge([build_base_schema(), D::schema()])
+            .map_err(|e| LanceDBError::InvalidParameter {
+                parameter: "data schema",
+                issue: "Data schema and base schema could not be merged. \
+                    Could there be a key conflict? Data schema must not use 'key', 'sequence_number' or 'cleared_at' keys.",
+                source: Some(e.into()),
+            })?);
+
+        let db = connect(data_dir)
+            .read_consistency_interval(READ_ONLY_CONSISTENCY_INTERVAL)
+            .execute().await
+            .map_err(LanceDBError::Connection)?;
+        let table_names = db.table_names().execute().await
+            .map_err(|e| LanceDBError::TableOperation { operation: "Listing tables", source: e })?;
+        let (db, table) = if table_names.contains(&table_name) {
+            let table = db.open_table(&table_name)
+                .execute().await
+                .map_err(|e| LanceDBError::TableOperation { operation: "Opening table", source: e })?;
+            (db, table)
+        } else {
+            debug!("Table {} does not exist in {}, opening an empty table in memory", table_name, data_dir);
+            let db = connect(IN_MEMORY_DATA_DIR)
+                .execute().await
+                .map_err(LanceDBError::Connection)?;
+            let table = db.create_empty_table(table_name.clone(), schema.clone())
+                .execute().await
+                .map_err(|e| LanceDBError::TableOperation { operation: "Creating empty table", source: e })?;
+            (db, table)
+        };
+
+        Ok(LanceDBStore {
+            db,
+            table,
+            table_name,
+            schema,
+            fusion_weights: FusionWeights::default(),
             ops_to_optimize: Arc::new(AtomicI32::new(OPERATIONS_PER_OPTIMIZE)),
+            read_only: true,
             _phantom_data: Default::default(),
         })
     }
 
+    /// Whether the store was opened with read_only
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    fn check_writable(&self) -> Result<(), LanceDBError> {
+        if self.read_only {
+            return Err(LanceDBError::ReadOnly { table: self.table_name.clone() });
+        }
+        Ok(())
+    }
+
+    /// Names of the columns of the table, read only when the filters need them to tell whether the keys of their
+    /// FilterValue::Entry filters have been promoted to columns of their own, see LanceDBSchema
+    async fn filter_columns(&self, filters: &[Filter<'_>]) -> Result<Vec<String>, lancedb::error::Error> {
+        if !filters.iter().any(|f| matches!(f.filter, FilterValue::Entry { .. })) {
+            return Ok(vec![]);
+        }
+        let table_schema = self.table.schema().await?;
+        Ok(table_schema.fields().iter().map(|f| f.name().clone()).collect())
+    }
+
+    /// Sets the weights the scores of vector and full text search results are added up with in hybrid queries
+    pub fn with_fusion_weights(mut self, fusion_weights: FusionWeights) -> Self {
+        self.fusion_weights = fusion_weights;
+        self
+    }
+
+    #[instrument(name = "store", level = "debug", skip_all, fields(table = %self.table_name, operation = "merge_insert"))]
     pub async fn merge_insert(&self, reader: impl RecordBatchReader + Send + 'static) -> Result<(), LanceDBError> {
+        self.check_writable()?;
         let mut merge = self.table.merge_insert(&[KEY_COLUMN]);
         merge.when_matched_update_all(Some(format!("target.{SEQUENCE_NUMBER_COLUMN} <= \
             source.{SEQUENCE_NUMBER_COLUMN}"))).when_not_matched_insert_all();
@@ -109,18 +242,128 @@ impl<D: ArrowData> LanceDBStore<D> {
         self.maybe_optimize().await
     }
 
+    #[instrument(name = "store", level = "debug", skip_all, fields(table = %self.table_name, operation = "delete_one"))]
     pub async fn delete_one(&self, key: String, optional_sequence_number: Option<u64>) -> Result<(), LanceDBError> {
-        let mut delete_condition = format!("{KEY_COLUMN} = '{key}'");
+        let mut delete_condition = format!("{KEY_COLUMN} = {}", string_literal(&key));
         if let Some(sn) = optional_sequence_number {
             delete_condition.push_str(&format!(" AND {SEQUENCE_NUMBER_COLUMN} < {sn}"));
         }
 
-        self.table.delete(&delete_condition).await
-            .map_err(|e| LanceDBError::Delete { source: e })?;
+        self.soft_delete(&delete_condition).await?;
 
         self.maybe_optimize().await
     }
 
+    /// Marks the rows matching the condition as cleared rather than deleting them, so that they can be restored
+    /// until they are purged (see purge_cleared). Cleared rows are left out of every read. They are also given a new
+    /// sequence number, so that writes started before they were cleared cannot bring them back.
+    async fn soft_delete(&self, condition: &str) -> Result<(), LanceDBError> {
+        self.check_writable()?;
+        self.table.update()
+            .only_if(format!("({condition}) AND {NOT_CLEARED_CONDITION}"))
+            .column(CLEARED_AT_COLUMN, Utc::now().timestamp_millis().to_string())
+            .column(SEQUENCE_NUMBER_COLUMN, sequence::next().to_string())
+            .execute().await
+            .map_err(|e| LanceDBError::Delete { source: e })?;
+        Ok(())
+    }
+
+    /// Deletes the rows that were cleared longer ago than the retention window, after which they can no longer be
+    /// restored
+    async fn purge_cleared(&self) -> Result<(), LanceDBError> {
+        let purge_before = (Utc::now() - CLEARED_ROW_RETENTION).timestamp_millis();
+        self.table.delete(&format!("{CLEARED_AT_COLUMN} IS NOT NULL AND {CLEARED_AT_COLUMN} < {purge_before}")).await
+            .map_err(|e| LanceDBError::Delete { source: e })?;
+        Ok(())
+    }
+
+    /// Reads the whole table, returning a LanceDBError::Corrupted error if any part of it cannot be read or
+    /// decoded.
+    #[instrument(name = "store", level = "debug", skip_all, fields(table = %self.table_name, operation = "verify"))]
+    pub async fn verify(&self) -> Result<(), LanceDBError> {
+        let expected_rows = self.table.count_rows(None).await
+            .map_err(|e| self.corrupted(e.into()))?;
+        let read_rows = scan_table::<D>(&self.table).await
+            .map_err(|e| self.corrupted(e))?;
+        if read_rows != expected_rows {
+            return Err(self.corrupted(anyhow::anyhow!("{} rows could be read out of {}", read_rows, expected_rows)));
+        }
+        Ok(())
+    }
+
+    /// Recovers a damaged table. The newest earlier version of the table that can still be read in full is
+    /// restored if there is one, otherwise the table is dropped. Either way, the keys of the rows that could still
+    /// be read from the damaged version are returned, so that whatever the rows were built from (eg. the original
+    /// files of chunkfiles) can be indexed again. The store must be opened again after recovering.
+    #[instrument(name = "store", level = "debug", skip_all, fields(table = %self.table_name, operation = "recover"))]
+    pub async fn recover(&self) -> Result<TableRecovery, LanceDBError> {
+        self.check_writable()?;
+        let salvaged_keys = self.salvage_keys().await;
+        info!("Table {}: Salvaged {} keys from damaged table", self.table_name, salvaged_keys.len());
+
+        let current_version = self.table.version().await
+            .map_err(|e| LanceDBError::TableOperation { operation: "Reading table version", source: e })?;
+        let mut versions = self.table.list_versions().await
+            .map_err(|e| LanceDBError::TableOperation { operation: "Listing table versions", source: e })?;
+        versions.sort_by_key(|v| Reverse(v.version));
+
+        for version in versions.into_iter().filter(|v| v.version < current_version) {
+            if let Err(e) = self.table.checkout(version.version).await {
+                debug!("Table {}: Could not check out version {}: {:?}", self.table_name, version.version, e);
+                continue;
+            }
+            match scan_table::<D>(&self.table).await {
+                Ok(_) => {
+                    self.table.restore().await
+                        .map_err(|e| LanceDBError::TableOperation { operation: "Restoring table version", source: e })?;
+                    info!("Table {}: Restored version {}", self.table_name, version.version);
+                    return Ok(TableRecovery::Restored { version: version.version, salvaged_keys });
+                },
+                Err(e) => debug!("Table {}: Version {} is also damaged: {:?}", self.table_name, version.version, e),
+            }
+        }
+
+        warn!("Table {}: No readable version found, dropping table", self.table_name);
+        self.db.drop_table(&self.table_name, &[]).await
+            .map_err(|e| LanceDBError::TableOperation { operation: "Dropping table", source: e })?;
+        Ok(TableRecovery::Dropped { salvaged_keys })
+    }
+
+    /// Reads the keys of every row that can still be read from the current version of the table, skipping the
+    /// parts of the table that cannot be read
+    async fn salvage_keys(&self) -> Vec<String> {
+        let mut result_stream = match self.table.query()
+            .only_if(NOT_CLEARED_CONDITION)
+            .select(Select::columns(&[KEY_COLUMN]))
+            .execute().await {
+            Ok(result_stream) => result_stream,
+            Err(e) => {
+                warn!("Table {}: Could not read keys: {:?}", self.table_name, e);
+                return vec![];
+            },
+        };
+
+        let mut keys = vec![];
+        while let Some(rb) = result_stream.next().await {
+            let batch = match rb {
+                Ok(batch) => batch,
+                Err(e) => {
+                    warn!("Table {}: Skipping unreadable part of table: {:?}", self.table_name, e);
+                    continue;
+                },
+            };
+            match batch.column_by_name(KEY_COLUMN).and_then(|c| c.as_any().downcast_ref::<StringArray>()) {
+                Some(key_column) => keys.extend(key_column.iter().flatten().map(str::to_owned)),
+                None => warn!("Table {}: Skipping part of table without a key column", self.table_name),
+            }
+        }
+        keys
+    }
+
+    fn corrupted(&self, source: anyhow::Error) -> LanceDBError {
+        LanceDBError::Corrupted { table: self.table_name.clone(), source }
+    }
+
     /// TODO: documentation
     /// It is recommended to call this function after every table record operation that is performed.
     async fn maybe_optimize(&self) -> Result<(), LanceDBError> {
@@ -134,14 +377,66 @@ impl<D: ArrowData> LanceDBStore<D> {
             // both triggering optimization
             self.ops_to_optimize.store(OPERATIONS_PER_OPTIMIZE, Ordering::Relaxed);
 
-            info!("Optimizing table: {}", self.table_name);
             // Run optimization (this may take a while, but counter is already reset)
-            self.table.optimize(OptimizeAction::All).await
-                .map_err(|e| LanceDBError::Optimize { original_operation: "merge_insert", source: e })?;
+            self.optimize_table("merge_insert").await?;
+        }
+        Ok(())
+    }
+
+    /// Purges the rows cleared longer ago than the retention window and compacts the table, eg. from a scheduled
+    /// job, rather than waiting for enough writes to the table to optimize it
+    #[instrument(name = "store", level = "debug", skip_all, fields(table = %self.table_name, operation = "optimize"))]
+    pub async fn optimize(&self) -> Result<(), LanceDBError> {
+        self.ops_to_optimize.store(OPERATIONS_PER_OPTIMIZE, Ordering::Relaxed);
+        self.optimize_table("optimize").await
+    }
+
+    async fn optimize_table(&self, original_operation: &'static str) -> Result<(), LanceDBError> {
+        self.check_writable()?;
+        info!("Optimizing table: {}", self.table_name);
+        self.purge_cleared().await?;
+        // Same as OptimizeAction::All, except that pruning keeps the versions tagged by snapshots (see LanceDBTags)
+        // rather than failing on them
+        let actions = [
+            OptimizeAction::Compact { options: CompactionOptions::default(), remap_options: None },
+            OptimizeAction::Prune { older_than: None, delete_unverified: None, error_if_tagged_old_versions: Some(false) },
+            OptimizeAction::Index(OptimizeOptions::default()),
+        ];
+        for action in actions {
+            self.table.optimize(action).await
+                .map_err(|e| LanceDBError::Optimize { original_operation, source: e })?;
         }
         Ok(())
     }
 
+    /// Adds columns that are in the schema but missing from the existing table, so that tables created before
+    /// an attribute was added to the data schema can still be opened. Existing rows get null values in the new
+    /// columns, so the new fields are added as nullable.
+    async fn add_missing_columns(table: &Table, schema: &Schema) -> Result<(), LanceDBError> {
+        let table_schema = table.schema().await
+            .map_err(|e| LanceDBError::TableOperation {
+                operation: "Reading table schema",
+                source: e,
+            })?;
+        let missing_fields = schema.fields().iter()
+            .filter(|f| table_schema.field_with_name(f.name()).is_err())
+            .map(|f| f.as_ref().clone().with_nullable(true))
+            .collect::<Vec<Field>>();
+        if missing_fields.is_empty() {
+            return Ok(());
+        }
+
+        info!("Table {}: Adding missing columns: {:?}", table.name(),
+            missing_fields.iter().map(|f| f.name()).collect::<Vec<_>>());
+        table.add_columns(NewColumnTransform::AllNulls(Arc::new(Schema::new(missing_fields))), None).await
+            .map_err(|e| LanceDBError::TableOperation {
+                operation: "Adding missing columns",
+                source: e,
+            })?;
+
+        Ok(())
+    }
+
     /// Creates index on key column, allowing for key based retrievals
     async fn create_key_index(table: &Table) -> Result<(), LanceDBError> {
         info!("Table {}: Ensuring key index", table.name());
@@ -154,6 +449,8 @@ impl<D: ArrowData> LanceDBStore<D> {
         ).await
     }
 
+    /// Creates the index unless an index of the same name and type already exists on the column. Other indexes of the
+    /// same type on the column are dropped, eg. an FTS index built with another analyzer (see fts_index_name).
     async fn ensure_index(
         table: &Table,
         column_name: &str,
@@ -165,8 +462,9 @@ impl<D: ArrowData> LanceDBStore<D> {
                 operation: "Listing indices",
                 source: e,
             })?;
-        
-        for config in indices {
+        let index_type = index_type(&index);
+
+        for config in &indices {
             if config.name == index_name {
                 if config.columns.len() > 1 {
                     return Err(LanceDBError::InvalidParameter {
@@ -179,15 +477,36 @@ impl<D: ArrowData> LanceDBStore<D> {
                         ))),
                     });
                 }
-                let existing_column_name = config.columns.first()
-                    .expect("LanceDB indexes should have at least one column");
-                if existing_column_name == column_name { // AND index type does not match, but that is a TODO
+                let existing_column_name = match config.columns.first() {
+                    Some(column_name) => column_name,
+                    None => return Err(LanceDBError::InvalidParameter {
+                        parameter: "Current index",
+                        issue: "No columns",
+                        source: Some(anyhow::Error::msg(format!(
+                            "Currently existing lancedb index {} is not indexing any columns", config.name,
+                        ))),
+                    }),
+                };
+                if existing_column_name == column_name && index_type.as_ref().is_none_or(|t| *t == config.index_type) {
                     // index already exists
                     return Ok(());
                 }
             }
         }
 
+        for config in indices {
+            if config.name != index_name && config.columns.iter().any(|column| column == column_name)
+                && index_type.as_ref().is_some_and(|t| *t == config.index_type)
+            {
+                info!("Dropping index {} on column {}, replaced by index {}", config.name, column_name, index_name);
+                table.drop_index(&config.name).await
+                    .map_err(|e| LanceDBError::TableOperation {
+                        operation: "Dropping replaced index",
+                        source: e,
+                    })?;
+            }
+        }
+
         table.create_index(&[column_name], index)
             .replace(true)
             .name(index_name)
@@ -216,15 +535,32 @@ impl<K: Serialize + Send, D: ArrowData + KeyedSequencedData<K>> KeyedSequencedSt
             row_builder.append(arrow_data);
         }
 
+        let num_rows = key_array.len();
         // These fields must be ordered in the same way as the schema
         let mut data_columns = vec![
             (KEY_FIELD.clone(), Arc::new(key_array.finish()) as ArrayRef),
             (SEQUENCE_NUMBER_FIELD.clone(), Arc::new(sequence_array.finish()) as ArrayRef),
+            // Putting a row that was cleared restores it
+            (CLEARED_AT_FIELD.clone(), Arc::new(Int64Array::new_null(num_rows)) as ArrayRef),
         ];
         for field_and_array in row_builder.finish() {
             data_columns.push(field_and_array)
         }
 
+        // Keys promoted to columns of their own are filled in from the JSON they were promoted from, see
+        // LanceDBSchema
+        let table_schema = self.table.schema().await
+            .map_err(|e| KeyedSequencedStoreError::Put { issue: "read table schema", source: e.into() })?;
+        let promoted_columns = promoted_column_values(&table_schema, &data_columns);
+        let schema = if promoted_columns.is_empty() {
+            self.schema.clone()
+        } else {
+            Arc::new(Schema::new(self.schema.fields().iter().cloned()
+                .chain(promoted_columns.iter().map(|(field, _)| field.clone()))
+                .collect::<Vec<_>>()))
+        };
+        data_columns.extend(promoted_columns);
+
         let struct_array = StructArray::from(data_columns);
 
         // push the data
@@ -232,7 +568,7 @@ impl<K: Serialize + Send, D: ArrowData + KeyedSequencedData<K>> KeyedSequencedSt
             vec![RecordBatch::from(struct_array)]
                 .into_iter()
                 .map(Ok),
-            self.schema.clone(),
+            schema,
         );
 
         self.merge_insert(reader).await
@@ -247,6 +583,7 @@ impl<K: Serialize + Send, D: ArrowData + KeyedSequencedData<K>> KeyedSequencedSt
             .map_err(|e| KeyedSequencedStoreError::Clear { issue: "delete_one", source: e.into() })
     }
 
+    #[instrument(name = "store", level = "debug", skip_all, fields(table = %self.table_name, operation = "get"))]
     async fn get(&self, key: K) -> Result<Option<D>, KeyedSequencedStoreError> {
         let key_string = serde_json::to_string(&key).map_err(|e|
                 KeyedSequencedStoreError::Serialization { element: "key".to_owned(), source: e.into() })?;
@@ -260,6 +597,8 @@ impl<K: Serialize + Send, D: ArrowData + KeyedSequencedData<K>> KeyedSequencedSt
         let mut result_list: Vec<D> = Vec::new();
         while let Some(rb) = result_stream.next().await {
             let batch = rb.map_err(|e| KeyedSequencedStoreError::Get { issue: "read RecordBatch", source: e.into() })?;
+            check_data_columns::<D>(&batch)
+                .map_err(|issue| KeyedSequencedStoreError::Get { issue: "decode RecordBatch", source: anyhow::Error::msg(issue) })?;
 
             for item in D::batch_to_iter(batch) {
                 result_list.push(item);
@@ -331,11 +670,16 @@ impl<D: ArrowData + Filterable> LanceDBStore<D> {
             info!("Table {}: Ensuring filter indexes on columns: {:?}", self.table_name, column_names);
 
             for column_name in column_names {
+                // List columns are filtered by the labels they contain, which btree indexes do not support
+                let index = match self.schema.field_with_name(column_name).map(|f| f.data_type()) {
+                    Ok(DataType::List(_)) => Index::LabelList(Default::default()),
+                    _ => Index::BTree(Default::default()),
+                };
                 Self::ensure_index(
                     &self.table,
                     column_name,
                     default_filter_index_name(column_name),
-                    Index::BTree(Default::default()),
+                    index,
                 ).await?;
             }
         }
@@ -356,19 +700,19 @@ impl<D: ArrowData + FTSData> LanceDBStore<D> {
     /// Creates FTS indexes on all FTS attributes.
     async fn create_fts_indexes(&self) -> Result<(), LanceDBError> {
         let attribute_names = D::fts_attributes();
-        let column_names: Vec<&str> = attribute_names.iter()
-            .map(|attr| D::attribute_to_column_name(attr))
+        let columns: Vec<(&str, FtsAnalyzer)> = attribute_names.iter()
+            .map(|attr| (D::attribute_to_column_name(attr), D::fts_analyzer(attr)))
             .collect();
 
-        if !column_names.is_empty() {
-            info!("Table {}: Ensuring FTS indexes on columns: {:?}", self.table_name, column_names);
+        if !columns.is_empty() {
+            info!("Table {}: Ensuring FTS indexes on columns: {:?}", self.table_name, columns);
 
-            for column_name in column_names {
+            for (column_name, analyzer) in columns {
                 Self::ensure_index(
                     &self.table,
                     column_name,
-                    default_fts_index_name(column_name),
-                    Index::FTS(Default::default()),
+                    fts_index_name(column_name, analyzer),
+                    Index::FTS(fts_index_builder(analyzer)),
                 ).await?;
             }
         }
@@ -390,14 +734,17 @@ impl<D: ArrowData + VectorData + Filterable + FTSData> LanceDBStore<D> {
 
 // ClearByFilter implementation - only available when D: Filterable
 impl<D: ArrowData + Filterable> ClearByFilter<D> for LanceDBStore<D> {
+    #[instrument(name = "store", level = "debug", skip_all, fields(table = %self.table_name, operation = "clear_filter"))]
     async fn clear_filter<'a>(&self, filters: &[Filter<'a>]) -> Result<(), FilterStoreError> {
         if filters.is_empty() {
             return Ok(());
         }
 
-        let condition = build_filter_condition::<D>(filters)?;
+        let table_columns = self.filter_columns(filters).await
+            .map_err(|e| FilterStoreError::Clear { source: e.into() })?;
+        let condition = build_filter_condition::<D>(filters, &table_columns)?;
 
-        self.table.delete(&condition).await
+        self.soft_delete(&condition).await
             .map_err(|e| FilterStoreError::Clear { source: e.into() })?;
 
         self.maybe_optimize().await
@@ -407,15 +754,43 @@ impl<D: ArrowData + Filterable> ClearByFilter<D> for LanceDBStore<D> {
     }
 }
 
+// RestoreByFilter implementation - only available when D: Filterable
+impl<D: ArrowData + Filterable> RestoreByFilter<D> for LanceDBStore<D> {
+    #[instrument(name = "store", level = "debug", skip_all, fields(table = %self.table_name, operation = "restore_filter"))]
+    async fn restore_filter<'a>(&self, filters: &[Filter<'a>]) -> Result<u64, FilterStoreError> {
+        if filters.is_empty() {
+            return Ok(0);
+        }
+
+        let table_columns = self.filter_columns(filters).await
+            .map_err(|e| FilterStoreError::Restore { source: e.into() })?;
+        let condition = build_filter_condition::<D>(filters, &table_columns)?;
+        self.check_writable()
+            .map_err(|e| FilterStoreError::Restore { source: e.into() })?;
+
+        let result = self.table.update()
+            .only_if(format!("({condition}) AND {CLEARED_AT_COLUMN} IS NOT NULL"))
+            .column(CLEARED_AT_COLUMN, "NULL")
+            .column(SEQUENCE_NUMBER_COLUMN, sequence::next().to_string())
+            .execute().await
+            .map_err(|e| FilterStoreError::Restore { source: e.into() })?;
+
+        Ok(result.rows_updated)
+    }
+}
+
 // QueryByFilter implementation - only available when D: Filterable
 impl<D: ArrowData + Filterable> QueryByFilter<D> for LanceDBStore<D> {
     fn query_filter<'a>(&self, filters: &[Filter<'a>]) -> impl Future<Output = Result<Vec<D>, FilterStoreError>> {
         self.query_filter_n(filters, 0, 0)
     }
 
+    #[instrument(name = "store", level = "debug", skip_all, fields(table = %self.table_name, operation = "query_filter"))]
     async fn query_filter_n<'a>(&self, filters: &[Filter<'a>], num_results: u32, offset: u32) -> Result<Vec<D>, FilterStoreError> {
+        let table_columns = self.filter_columns(filters).await
+            .map_err(|e| FilterStoreError::Query { source: e.into() })?;
         let mut query = self.table.query();
-        query = apply_filters::<D, _>(query, filters)?;
+        query = apply_filters::<D, _>(query, filters, &table_columns)?;
         query = apply_pagination(query, num_results, offset);
 
         let mut result_stream = query.execute().await
@@ -424,6 +799,7 @@ impl<D: ArrowData + Filterable> QueryByFilter<D> for LanceDBStore<D> {
         let mut result_list: Vec<D> = Vec::new();
         while let Some(rb) = result_stream.next().await {
             let batch = rb.map_err(|e| FilterStoreError::Query { source: e.into() })?;
+            check_data_columns::<D>(&batch).map_err(|issue| FilterStoreError::Decode { issue })?;
 
             for item in D::batch_to_iter(batch) {
                 result_list.push(item);
@@ -440,8 +816,9 @@ impl<D: ArrowData + VectorData> QueryByVector<D> for LanceDBStore<D> {
         self.query_vector_n(vector, 0, 0)
     }
 
+    #[instrument(name = "store", level = "debug", skip_all, fields(table = %self.table_name, operation = "query_vector"))]
     async fn query_vector_n(&self, vector: Vec<f32>, num_results: u32, offset: u32) -> Result<Vec<VectorQueryResult<D>>, VectorStoreError> {
-        let mut query = self.table.query();
+        let mut query = self.table.query().only_if(NOT_CLEARED_CONDITION);
         query = apply_pagination(query, num_results, offset);
         let query = apply_vector_search::<D>(query, vector)?;
 
@@ -452,32 +829,15 @@ impl<D: ArrowData + VectorData> QueryByVector<D> for LanceDBStore<D> {
         while let Some(rb) = result_stream.next().await {
             match rb {
                 Ok(batch) => {
-                    let distance_column = batch.column_by_name("_distance") // Pick out the distance column
-                        .expect("_distance column should exist in vector query")
-                        // cast to a float32 array
-                        .as_any().downcast_ref::<Float32Array>()
-                        // Expect: the column is definitively returned as a f32, this conversion from arrow array
-                        // should not fail.
-                        .expect("Returned query result of distances could not be converted to a f32")
-                        // unwrap the optionals
-                        // Expect: the column is defined as non-nullable in the schema, there should be no reason for
-                        // the optional to be empty
-                        .iter().map(|s| s.expect("Missing f32 in optional for non-nullable distance column"))
-                        .collect::<Vec<f32>>();
-
-                    let mut data_iter = D::batch_to_iter(batch).into_iter();
-                    let mut distance_iter = distance_column.into_iter();
-
-                    while let (Some(data), Some(distance)) = (data_iter.next(), distance_iter.next()) {
-                        result_list.push(VectorQueryResult {
-                            result: data,
-                            distance,
-                        })
-                    }
-                    if data_iter.next().is_some() || distance_iter.next().is_some() {
-                        // TODO: probably better to return error here
-                        panic!("columns in query result should not have different lengths!");
-                    }
+                    let distance_column = read_score_column(&batch, "_distance")
+                        .map_err(|issue| VectorStoreError::Decode { issue })?;
+
+                    let rows = zip_scores::<D>(batch, distance_column)
+                        .map_err(|issue| VectorStoreError::Decode { issue })?;
+                    result_list.extend(rows.into_iter().map(|(data, distance)| VectorQueryResult {
+                        result: data,
+                        distance,
+                    }));
                 }
                 Err(e) => return Err(VectorStoreError::Query { source: e.into() })
             }
@@ -493,6 +853,7 @@ impl<D: ArrowData + VectorData + Filterable + FTSData> QueryFull<D> for LanceDBS
         self.query_full_n(vector, fts_terms, filters, 0, 0)
     }
 
+    #[instrument(name = "store", level = "debug", skip_all, fields(table = %self.table_name, operation = "query_full"))]
     async fn query_full_n<'a>(
         &self,
         vector: Option<Vec<f32>>,
@@ -501,126 +862,578 @@ impl<D: ArrowData + VectorData + Filterable + FTSData> QueryFull<D> for LanceDBS
         num_results: u32,
         offset: u32,
     ) -> Result<Vec<FullQueryResult<D>>, anyhow::Error> {
+        self.query_projected(vector, fts_terms, filters, None, num_results, offset).await
+    }
+
+    #[instrument(name = "store", level = "debug", skip_all, fields(table = %self.table_name, operation = "query_full_select"))]
+    async fn query_full_select_n<'a>(
+        &self,
+        vector: Option<Vec<f32>>,
+        fts_terms: Option<&str>,
+        filters: &[Filter<'a>],
+        attributes: &[&str],
+        num_results: u32,
+        offset: u32,
+    ) -> Result<Vec<FullQueryResult<D>>, anyhow::Error> {
+        self.query_projected(vector, fts_terms, filters, Some(attributes), num_results, offset).await
+    }
+}
+
+impl<D: ArrowData + VectorData + Filterable + FTSData> LanceDBStore<D> {
+    /// Runs a full query, reading only the columns of the attributes given if there are any, see
+    /// QueryFull::query_full_select_n
+    async fn query_projected<'a>(
+        &self,
+        vector: Option<Vec<f32>>,
+        fts_terms: Option<&str>,
+        filters: &[Filter<'a>],
+        attributes: Option<&[&str]>,
+        num_results: u32,
+        offset: u32,
+    ) -> Result<Vec<FullQueryResult<D>>, anyhow::Error> {
+        match (vector, fts_terms) {
+            (Some(vector), Some(fts_terms)) =>
+                self.query_hybrid(vector, fts_terms, filters, attributes, num_results, offset).await,
+            (vector, fts_terms) => Ok(self.query_scored(vector, fts_terms, filters, attributes, num_results, offset)
+                .await?
+                .into_iter()
+                .map(|(_, result)| result)
+                .collect()),
+        }
+    }
+
+    /// Runs the vector and full text searches of a hybrid query separately and adds up their scores with the fusion
+    /// weights of the store. Each search returns the results up to the end of the requested page, which are then
+    /// ranked together and paged.
+    async fn query_hybrid<'a>(
+        &self,
+        vector: Vec<f32>,
+        fts_terms: &str,
+        filters: &[Filter<'a>],
+        attributes: Option<&[&str]>,
+        num_results: u32,
+        offset: u32,
+    ) -> Result<Vec<FullQueryResult<D>>, anyhow::Error> {
+        let limit = if num_results > 0 { num_results + offset } else { 0 };
+        let (vector_results, fts_results) = futures::try_join!(
+            self.query_scored(Some(vector), None, filters, attributes, limit, 0),
+            self.query_scored(None, Some(fts_terms), filters, attributes, limit, 0),
+        )?;
+
+        let max_fts_score = fts_results.iter().map(|(_, r)| r.score).fold(0.0, f32::max);
+        let mut fused: HashMap<String, FullQueryResult<D>> = HashMap::new();
+        for (key, mut result) in vector_results {
+            result.score *= self.fusion_weights.vector;
+            fused.insert(key, result);
+        }
+        for (key, result) in fts_results {
+            let fts_score = if max_fts_score > 0.0 { result.score / max_fts_score } else { 0.0 };
+            let fts_score = self.fusion_weights.fts * fts_score;
+            match fused.entry(key) {
+                Entry::Occupied(mut entry) => entry.get_mut().score += fts_score,
+                Entry::Vacant(entry) => {
+                    entry.insert(FullQueryResult { result: result.result, score: fts_score });
+                },
+            }
+        }
+
+        let mut fused: Vec<FullQueryResult<D>> = fused.into_values().collect();
+        fused.sort_by(|a, b| b.score.total_cmp(&a.score));
+        let page = fused.into_iter().skip(offset as usize);
+        Ok(if num_results > 0 { page.take(num_results as usize).collect() } else { page.collect() })
+    }
+
+    /// Runs a vector, full text or filter query and returns its results along with their keys. If attributes are
+    /// given, only their columns are read, and the other attributes of the results are left empty or zero.
+    async fn query_scored<'a>(
+        &self,
+        vector: Option<Vec<f32>>,
+        fts_terms: Option<&str>,
+        filters: &[Filter<'a>],
+        attributes: Option<&[&str]>,
+        num_results: u32,
+        offset: u32,
+    ) -> Result<Vec<(String, FullQueryResult<D>)>, anyhow::Error> {
         let is_fts = fts_terms.is_some();
         let is_vector = vector.is_some();
-        let is_hybrid = is_fts && is_vector;
 
         let mut query = self.table.query();
 
         // Apply FTS
-        if is_fts {
-            query = apply_fts::<D, _>(query, fts_terms.unwrap())?;
+        if let Some(fts_terms) = fts_terms {
+            query = apply_fts::<D, _>(query, fts_terms)?;
         }
 
         // Apply filters
-        query = apply_filters::<D, _>(query, filters)
+        let table_columns = self.filter_columns(filters).await
+            .map_err(|e| VectorStoreError::Query { source: e.into() })?;
+        query = apply_filters::<D, _>(query, filters, &table_columns)
             .map_err(|e| VectorStoreError::Query { source: e.into() })?;
 
         // Apply pagination
         query = apply_pagination(query, num_results, offset);
 
-        // Execute hybrid search
-        let mut result_stream = if is_vector {
-            // Apply vector search
-            let query = apply_vector_search::<D>(query, vector.unwrap())?;
-            if is_hybrid {
-                query.execute_hybrid(QueryExecutionOptions::default()).await
-                    .map_err(|e| VectorStoreError::Query { source: e.into() })?
+        // Apply projection. Keys are always read, see read_key_column.
+        let selected_columns = attributes.map(|attributes| selected_columns::<D>(attributes));
+        if let Some(selected_columns) = &selected_columns {
+            query = query.select(Select::columns(selected_columns.as_slice()));
+        }
+
+        // Identical queries of the table in the same batch share their results, see LanceDBQueryBatch
+        let query_batch = QUERY_BATCH.try_with(Arc::clone).ok();
+        let batch_key = match &query_batch {
+            Some(_) => Some(batch_key::<D>(self.table.dataset_uri(), vector.as_deref(), fts_terms, filters,
+                &table_columns, selected_columns.as_deref(), num_results, offset)?),
+            None => None,
+        };
+        let execution = match vector {
+            Some(vector) => {
+                let query = apply_vector_search::<D>(query, vector)?;
+                async move { collect_batches(query.execute().await).await }.boxed()
+            },
+            None => async move { collect_batches(query.execute().await).await }.boxed(),
+        };
+        let batches = match (query_batch, batch_key) {
+            (Some(query_batch), Some(batch_key)) => query_batch.run(batch_key, execution).await,
+            _ => execution.await,
+        }.map_err(|e| VectorStoreError::Query { source: e })?;
+
+        let mut result_list: Vec<(String, FullQueryResult<D>)> = Vec::new();
+        for batch in batches.iter().cloned() {
+            if batch.num_rows() == 0 {
+                // LanceDB will return a batch with num_rows = 0, but still containing the _score and
+                // _relevance_score columns (albeit empty) on a query with no results. However, none
+                // of the other columns will exist in the batch, so if we dont catch this empty result
+                // here, implementors of batch_to_iter may error out if they dont check for an empty
+                // result before they try pulling their non-existent columns out of the batch.
+                break;
+            }
+
+            let score_column = if is_fts {
+                // If this is an fts query, our scores are calculated for us and built-in to the query in
+                // the _score column. They are unbounded, see query_hybrid for how they are scaled.
+                read_score_column(&batch, "_score")
+            } else if is_vector {
+                // if this is not a hybrid query, we only have the _distance column so we must calculate
+                // the score ourselves. cosine distances will range from 0.0 -> 2.0, the lower the better
+                read_score_column(&batch, "_distance")
+                    .map(|distances| distances.into_iter().map(|dist| 1.0 - dist).collect())
             } else {
-                query.execute().await.map_err(|e| VectorStoreError::Query { source: e.into() })?
+                // This is a normal query or filter query, and therefore will not have scores
+                Ok(vec![0.0; batch.num_rows()])
+            }.map_err(|issue| VectorStoreError::Decode { issue })?;
+
+            let keys = read_key_column(&batch).map_err(|issue| VectorStoreError::Decode { issue })?;
+            let batch = match selected_columns {
+                Some(_) => fill_unselected_columns::<D>(batch).map_err(|issue| VectorStoreError::Decode { issue })?,
+                None => batch,
+            };
+            let rows = zip_scores::<D>(batch, score_column)
+                .map_err(|issue| VectorStoreError::Decode { issue })?;
+            result_list.extend(keys.into_iter().zip(rows).map(|(key, (data, score))| (key, FullQueryResult {
+                result: data,
+                score,
+            })));
+        }
+        Ok(result_list)
+    }
+}
+
+/// Tagged versions of every table in a data directory, so the tables can be rolled back together to how they were
+/// when they were tagged, eg. after a bad bulk operation. Tagged versions are kept when old versions are pruned by
+/// optimizing, until their tag is deleted.
+pub struct LanceDBTags {
+    db: Connection,
+}
+
+impl LanceDBTags {
+    pub async fn local(data_dir: &str) -> Result<LanceDBTags, LanceDBError> {
+        let db = connect(data_dir)
+            .execute().await
+            .map_err(LanceDBError::Connection)?;
+        Ok(LanceDBTags { db })
+    }
+
+    /// Tags the current version of every table, returning the tagged version of each table by its name. Tags must
+    /// be made of letters, digits, '.', '-' and '_', and not start with '.'.
+    pub async fn tag_all(&self, tag: &str) -> Result<HashMap<String, u64>, LanceDBError> {
+        let mut versions = HashMap::new();
+        for table in self.tables().await? {
+            let version = table.version().await
+                .map_err(|e| LanceDBError::TableOperation { operation: "Reading table version", source: e })?;
+            table.tags().await
+                .map_err(|e| LanceDBError::TableOperation { operation: "Reading table tags", source: e })?
+                .create(tag, version).await
+                .map_err(|e| LanceDBError::TableOperation { operation: "Tagging table version", source: e })?;
+            debug!("Table {}: Tagged version {} as {}", table.name(), version, tag);
+            versions.insert(table.name().to_owned(), version);
+        }
+        Ok(versions)
+    }
+
+    /// Restores every table that has the tag to its tagged version, returning the names of the restored tables.
+    /// Restoring adds the tagged version as the newest version of the table, so the versions written since it was
+    /// tagged are kept until they are pruned, and the restore can itself be rolled back. Tables without the tag, eg.
+    /// those created after tagging, are left as they are.
+    pub async fn restore_all(&self, tag: &str) -> Result<Vec<String>, LanceDBError> {
+        let mut restored = vec![];
+        for table in self.tables().await? {
+            let tags = table.tags().await
+                .map_err(|e| LanceDBError::TableOperation { operation: "Reading table tags", source: e })?
+                .list().await
+                .map_err(|e| LanceDBError::TableOperation { operation: "Listing table tags", source: e })?;
+            if !tags.contains_key(tag) {
+                debug!("Table {}: No version tagged {}, leaving table as it is", table.name(), tag);
+                continue;
             }
-        } else {
-            query.execute().await.map_err(|e| VectorStoreError::Query { source: e.into() })?
+
+            table.checkout_tag(tag).await
+                .map_err(|e| LanceDBError::TableOperation { operation: "Checking out tagged version", source: e })?;
+            table.restore().await
+                .map_err(|e| LanceDBError::TableOperation { operation: "Restoring table version", source: e })?;
+            info!("Table {}: Restored version tagged {}", table.name(), tag);
+            restored.push(table.name().to_owned());
+        }
+        Ok(restored)
+    }
+
+    /// Deletes the tag from every table that has it, so the tagged versions can be pruned
+    pub async fn delete_all(&self, tag: &str) -> Result<(), LanceDBError> {
+        for table in self.tables().await? {
+            let mut tags = table.tags().await
+                .map_err(|e| LanceDBError::TableOperation { operation: "Reading table tags", source: e })?;
+            let has_tag = tags.list().await
+                .map_err(|e| LanceDBError::TableOperation { operation: "Listing table tags", source: e })?
+                .contains_key(tag);
+            if has_tag {
+                tags.delete(tag).await
+                    .map_err(|e| LanceDBError::TableOperation { operation: "Deleting table tag", source: e })?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn tables(&self) -> Result<Vec<Table>, LanceDBError> {
+        let table_names = self.db.table_names().execute().await
+            .map_err(|e| LanceDBError::TableOperation { operation: "Listing tables", source: e })?;
+        let mut tables = Vec::with_capacity(table_names.len());
+        for table_name in table_names {
+            tables.push(self.db.open_table(&table_name).execute().await
+                .map_err(|e| LanceDBError::TableOperation { operation: "Opening table", source: e })?);
+        }
+        Ok(tables)
+    }
+}
+
+/// Keys of JSON object columns, eg. the original_file_tags of chunks, promoted to columns of their own in every
+/// table that has the JSON column, so filters on them (see FilterValue::Entry) compare a column rather than
+/// searching the JSON of every row. The column of a promoted key is named `<json column>__<key>`.
+///
+/// Promoting a key adds its column as pending, which puts fill in from then on. The rows that were already in the
+/// table are filled in by backfill_batch while the table is in use, after which complete_backfill gives the column its
+/// final name and indexes it. Filters search the JSON until then, so they find the same rows throughout.
+pub struct LanceDBSchema {
+    db: Connection,
+}
+
+/// A key promoted to a column of its own in a table, see LanceDBSchema
+#[derive(Debug, Clone, PartialEq)]
+pub struct PromotedKey {
+    pub table: String,
+    pub json_column: String,
+    pub key: String,
+    /// Whether the rows that were in the table when the key was promoted have been filled in, so filters on the key
+    /// use its column
+    pub backfilled: bool,
+}
+
+/// Rows of a table to fill in the pending column of a promoted key for with the same value, see
+/// LanceDBSchema::backfill_batch
+#[derive(Debug, Clone)]
+pub struct BackfillBatch {
+    table: String,
+    json_column: String,
+    key: String,
+    value: String,
+    keys: Vec<String>,
+}
+
+impl LanceDBSchema {
+    pub async fn local(data_dir: &str) -> Result<LanceDBSchema, LanceDBError> {
+        let db = connect(data_dir)
+            .execute().await
+            .map_err(LanceDBError::Connection)?;
+        Ok(LanceDBSchema { db })
+    }
+
+    /// Promotes the key of the JSON column in every table that has the column and has not promoted the key yet,
+    /// returning the names of those tables. Keys must be made of lowercase letters and digits, optionally separated
+    /// by single '_'.
+    pub async fn promote_key(&self, json_column: &str, key: &str) -> Result<Vec<String>, LanceDBError> {
+        if !is_promotable_key(key) {
+            return Err(LanceDBError::InvalidParameter {
+                parameter: "promoted key",
+                issue: "Keys must be made of lowercase letters and digits, optionally separated by single '_'",
+                source: None,
+            });
+        }
+        let column_name = promoted_column_name(json_column, key);
+        let pending_column_name = pending_column_name(json_column, key);
+
+        let mut promoted = vec![];
+        for table in self.tables().await? {
+            let table_schema = table.schema().await
+                .map_err(|e| LanceDBError::TableOperation { operation: "Reading table schema", source: e })?;
+            let has_json_column = table_schema.field_with_name(json_column)
+                .is_ok_and(|f| f.data_type() == &DataType::Utf8);
+            if !has_json_column || table_schema.field_with_name(&column_name).is_ok()
+                || table_schema.field_with_name(&pending_column_name).is_ok()
+            {
+                continue;
+            }
+
+            let pending_field = Field::new(pending_column_name.clone(), DataType::Utf8, true);
+            table.add_columns(NewColumnTransform::AllNulls(Arc::new(Schema::new(vec![pending_field]))), None).await
+                .map_err(|e| LanceDBError::TableOperation { operation: "Adding promoted column", source: e })?;
+            info!("Table {}: Promoted key {} of column {}, pending backfill", table.name(), key, json_column);
+            promoted.push(table.name().to_owned());
+        }
+        Ok(promoted)
+    }
+
+    /// Drops the column of the key from every table that promoted it, returning the names of those tables. Filters
+    /// on the key go back to searching the JSON. Puts must not run at the same time, as they write to the column.
+    pub async fn demote_key(&self, json_column: &str, key: &str) -> Result<Vec<String>, LanceDBError> {
+        let column_name = promoted_column_name(json_column, key);
+        let pending_column_name = pending_column_name(json_column, key);
+
+        let mut demoted = vec![];
+        for table in self.tables().await? {
+            let table_schema = table.schema().await
+                .map_err(|e| LanceDBError::TableOperation { operation: "Reading table schema", source: e })?;
+            if table_schema.field_with_name(&column_name).is_ok() {
+                let index_name = default_filter_index_name(&column_name);
+                let indices = table.list_indices().await
+                    .map_err(|e| LanceDBError::TableOperation { operation: "Listing indices", source: e })?;
+                if indices.iter().any(|config| config.name == index_name) {
+                    table.drop_index(&index_name).await
+                        .map_err(|e| LanceDBError::TableOperation { operation: "Dropping promoted column index", source: e })?;
+                }
+                table.drop_columns(&[column_name.as_str()]).await
+                    .map_err(|e| LanceDBError::TableOperation { operation: "Dropping promoted column", source: e })?;
+            } else if table_schema.field_with_name(&pending_column_name).is_ok() {
+                table.drop_columns(&[pending_column_name.as_str()]).await
+                    .map_err(|e| LanceDBError::TableOperation { operation: "Dropping promoted column", source: e })?;
+            } else {
+                continue;
+            }
+            info!("Table {}: Demoted key {} of column {}", table.name(), key, json_column);
+            demoted.push(table.name().to_owned());
+        }
+        Ok(demoted)
+    }
+
+    /// Every key promoted in every table, ordered by table
+    pub async fn promoted_keys(&self) -> Result<Vec<PromotedKey>, LanceDBError> {
+        let mut promoted_keys = vec![];
+        for table in self.tables().await? {
+            let table_schema = table.schema().await
+                .map_err(|e| LanceDBError::TableOperation { operation: "Reading table schema", source: e })?;
+            for field in table_schema.fields() {
+                let Some((json_column, key, pending)) = parse_promoted_column(field.name()) else {
+                    continue;
+                };
+                if table_schema.field_with_name(json_column).is_err() {
+                    continue;
+                }
+                promoted_keys.push(PromotedKey {
+                    table: table.name().to_owned(),
+                    json_column: json_column.to_owned(),
+                    key: key.to_owned(),
+                    backfilled: !pending,
+                });
+            }
+        }
+        Ok(promoted_keys)
+    }
+
+    /// The rows whose pending column of the promoted key is not filled in yet, in batches of up to
+    /// BACKFILL_BATCH_SIZE rows, so the table can be used while they are filled in one batch at a time with
+    /// backfill_batch. Running it again after an interruption carries on with the rows that were not filled in.
+    pub async fn backfill_batches(&self, promoted: &PromotedKey) -> Result<Vec<BackfillBatch>, LanceDBError> {
+        if promoted.backfilled {
+            return Ok(vec![]);
+        }
+        let table = self.db.open_table(&promoted.table).execute().await
+            .map_err(|e| LanceDBError::TableOperation { operation: "Opening table", source: e })?;
+        let column_name = pending_column_name(&promoted.json_column, &promoted.key);
+        let corrupted = |issue: String| LanceDBError::Corrupted {
+            table: promoted.table.clone(),
+            source: anyhow::Error::msg(issue),
         };
 
-        let mut result_list: Vec<FullQueryResult<D>> = Vec::new();
+        // Only rows whose JSON has the key somewhere are read
+        let key_text = format!("{}:", Value::from(promoted.key.as_str()));
+        let mut result_stream = table.query()
+            .only_if(format!("{} IS NULL AND strpos({}, {}) > 0", column_name, promoted.json_column,
+                string_literal(&key_text)))
+            .select(Select::columns(&[KEY_COLUMN, promoted.json_column.as_str()]))
+            .execute().await
+            .map_err(|e| LanceDBError::TableOperation { operation: "Reading rows to backfill", source: e })?;
+        let mut keys_by_value: HashMap<String, Vec<String>> = HashMap::new();
         while let Some(rb) = result_stream.next().await {
-            match rb {
-                Ok(batch) => {
-                    if batch.num_rows() == 0 {
-                        // LanceDB will return a batch with num_rows = 0, but still containing the _score and
-                        // _relevance_score columns (albeit empty) on a query with no results. However, none
-                        // of the other columns will exist in the batch, so if we dont catch this empty result
-                        // here, implementors of batch_to_iter may error out if they dont check for an empty
-                        // result before they try pulling their non-existent columns out of the batch.
-                        break;
-                    }
-
-                    let mut score_iter: Box<dyn Iterator<Item = f32>> = if is_hybrid {
-                        // If this is a hybrid query, our scoring metric is already precalculated for us
-                        // by the built-in reranker so we can just return the _relevance_score column directly
-                        let relevance_column = batch.column_by_name("_relevance_score")
-                            .expect("_relevance_score column should exist in hybrid query")
-                            .as_any().downcast_ref::<Float32Array>()
-                            .expect("Returned query result of relevance scores could not be converted to a f32")
-                            .iter().map(|s| s.expect("Missing f32 in optional for non-nullable relevance score column"))
-                            .collect::<Vec<f32>>();
-
-                        // TODO: Scale these to 0.0 - 1.0
-                        Box::new(relevance_column.into_iter())
-                    } else if is_fts {
-                        // If this is an fts query, our scores are also calculated for us and built-in
-                        // to the query in the _score column.
-                        let score_column = batch.column_by_name("_score")
-                            .expect("_score column should exist in fts query")
-                            .as_any().downcast_ref::<Float32Array>()
-                            .expect("Returned query result of scores could not be converted to a f32")
-                            .iter().map(|s| s.expect("Missing f32 in optional for non-nullable score column"))
-                            .collect::<Vec<f32>>();
-
-                        // TODO: Scale these to 0.0 - 1.0
-                        Box::new(score_column.into_iter())
-                    } else if is_vector {
-                        // if this is not a hybrid query, we only have the _distance column so we must calculate
-                        // the score ourselves. cosine distances will range from 0.0 -> 2.0, the lower the better
-                        let distance_column = batch.column_by_name("_distance")
-                            .expect("_distance column should exist in vector query")
-                            .as_any().downcast_ref::<Float32Array>()
-                            .expect("Returned query result of distances could not be converted to a f32")
-                            .iter().map(|s| s.expect("Missing f32 in optional for non-nullable distance column"))
-                            .collect::<Vec<f32>>();
-
-                        Box::new(distance_column.into_iter().map(|dist| {
-                            1.0 - dist
-                        }))
-                    } else {
-                        // This is a normal query or filter query, and therefore will not have scores
-                        Box::new(vec![0.0; batch.num_rows()].into_iter())
-                    };
-
-                    let mut data_iter = D::batch_to_iter(batch).into_iter();
-
-                    while let (Some(data), Some(score)) = (data_iter.next(), score_iter.next()) {
-                        result_list.push(FullQueryResult {
-                            result: data,
-                            score,
-                        })
-                    }
-                    if data_iter.next().is_some() || score_iter.next().is_some() {
-                        // TODO: probably better to return error here
-                        panic!("columns in query result should not have different lengths!");
-                    }
+            let batch = rb
+                .map_err(|e| LanceDBError::TableOperation { operation: "Reading rows to backfill", source: e })?;
+            let keys = read_key_column(&batch).map_err(corrupted)?;
+            let json_values = batch.column_by_name(&promoted.json_column)
+                .and_then(|c| c.as_any().downcast_ref::<StringArray>())
+                .ok_or_else(|| corrupted(format!("{} column is missing", promoted.json_column)))?;
+            for (key, json) in keys.into_iter().zip(json_values.iter()) {
+                if let Some(value) = json.and_then(|json| promoted_value(json, &promoted.key)) {
+                    keys_by_value.entry(value).or_default().push(key);
                 }
-                Err(e) => return Err(VectorStoreError::Query { source: e.into() }.into())
             }
         }
-        Ok(result_list)
+
+        let batches = keys_by_value.into_iter()
+            .flat_map(|(value, keys)| keys.chunks(BACKFILL_BATCH_SIZE).map(|keys| BackfillBatch {
+                table: promoted.table.clone(),
+                json_column: promoted.json_column.clone(),
+                key: promoted.key.clone(),
+                value: value.clone(),
+                keys: keys.to_vec(),
+            }).collect::<Vec<_>>())
+            .collect();
+        Ok(batches)
+    }
+
+    /// Fills in the pending column of the promoted key for the rows of the batch, returning the number of rows filled
+    /// in. This writes to the table, so the caller must hold the index lock while it runs, see files::lock::IndexLock.
+    pub async fn backfill_batch(&self, batch: &BackfillBatch) -> Result<u64, LanceDBError> {
+        let table = self.db.open_table(&batch.table).execute().await
+            .map_err(|e| LanceDBError::TableOperation { operation: "Opening table", source: e })?;
+        let column_name = pending_column_name(&batch.json_column, &batch.key);
+        let key_list = batch.keys.iter().map(|key| string_literal(key)).collect::<Vec<_>>().join(", ");
+        // Rows put since they were read have the column filled in by the put, unless their JSON no longer has the key
+        // set to the value
+        let result = table.update()
+            .only_if(format!("{} IS NULL AND {} IN ({}) AND {}", column_name, KEY_COLUMN, key_list,
+                json_entry_condition(&batch.json_column, &batch.key, &batch.value)))
+            .column(column_name.as_str(), string_literal(&batch.value))
+            .execute().await
+            .map_err(|e| LanceDBError::TableOperation { operation: "Backfilling promoted column", source: e })?;
+        debug!("Table {}: Backfilled {} rows of key {}", batch.table, result.rows_updated, batch.key);
+        Ok(result.rows_updated)
+    }
+
+    /// Gives the backfilled column of the promoted key its final name and indexes it, so filters on the key use it.
+    /// Puts must not run at the same time, as they write to the column by its pending name.
+    pub async fn complete_backfill(&self, promoted: &PromotedKey) -> Result<(), LanceDBError> {
+        if promoted.backfilled {
+            return Ok(());
+        }
+        let table = self.db.open_table(&promoted.table).execute().await
+            .map_err(|e| LanceDBError::TableOperation { operation: "Opening table", source: e })?;
+        let column_name = promoted_column_name(&promoted.json_column, &promoted.key);
+
+        table.alter_columns(&[ColumnAlteration::new(pending_column_name(&promoted.json_column, &promoted.key))
+            .rename(column_name.clone())]).await
+            .map_err(|e| LanceDBError::TableOperation { operation: "Renaming backfilled column", source: e })?;
+        table.create_index(&[column_name.as_str()], Index::BTree(Default::default()))
+            .replace(true)
+            .name(default_filter_index_name(&column_name))
+            .execute().await
+            .map_err(|e| LanceDBError::TableOperation { operation: "Indexing promoted column", source: e })?;
+        info!("Table {}: Key {} of column {} is backfilled", promoted.table, promoted.key, promoted.json_column);
+        Ok(())
+    }
+
+    async fn tables(&self) -> Result<Vec<Table>, LanceDBError> {
+        let table_names = self.db.table_names().execute().await
+            .map_err(|e| LanceDBError::TableOperation { operation: "Listing tables", source: e })?;
+        let mut tables = Vec::with_capacity(table_names.len());
+        for table_name in table_names {
+            tables.push(self.db.open_table(&table_name).execute().await
+                .map_err(|e| LanceDBError::TableOperation { operation: "Opening table", source: e })?);
+        }
+        Ok(tables)
+    }
+}
+
+/// Shares the results of identical queries of the same table, eg. the image provider and the page images of the PDF
+/// provider both searching the siglip2 table for the same query vector, while queries are run in the batch (see
+/// LanceDBQueryBatch::scope). Queries started while an identical query is still running wait for its results instead
+/// of running again. Results are only kept for as long as the batch, so a batch should cover a single page of a
+/// query, see files::query::QueryPlanner.
+#[derive(Default)]
+pub struct LanceDBQueryBatch {
+    queries: Mutex<HashMap<String, SharedQuery>>,
+}
+
+impl LanceDBQueryBatch {
+    pub fn new() -> Arc<LanceDBQueryBatch> {
+        Arc::new(LanceDBQueryBatch::default())
+    }
+
+    /// Runs the future with the batch, so the queries of every LanceDBStore it runs are shared with the other
+    /// futures run with the batch. Tasks spawned by the future do not run with the batch.
+    pub async fn scope<F: Future>(self: Arc<Self>, future: F) -> F::Output {
+        QUERY_BATCH.scope(self, future).await
+    }
+
+    /// Number of distinct queries run in the batch so far
+    pub fn num_queries(&self) -> usize {
+        self.queries.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).len()
+    }
+
+    async fn run(&self, key: String, execution: BoxFuture<'static, Result<Arc<Vec<RecordBatch>>, anyhow::Error>>)
+        -> Result<Arc<Vec<RecordBatch>>, anyhow::Error>
+    {
+        let shared = {
+            let mut queries = self.queries.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            match queries.entry(key) {
+                Entry::Occupied(entry) => {
+                    debug!("Sharing the results of an identical query in the query batch");
+                    entry.get().clone()
+                },
+                Entry::Vacant(entry) => entry.insert(execution.map(|res| res.map_err(Arc::new)).boxed().shared())
+                    .clone(),
+            }
+        };
+        shared.await.map_err(|e| anyhow::anyhow!("{:#}", e))
     }
 }
 
 // Private variables and methods
 
+/// Results of a query run in a LanceDBQueryBatch, shared by every identical query
+type SharedQuery = Shared<BoxFuture<'static, Result<Arc<Vec<RecordBatch>>, Arc<anyhow::Error>>>>;
+
+tokio::task_local! {
+    /// The batch the queries of the current task are run in, see LanceDBQueryBatch::scope
+    static QUERY_BATCH: Arc<LanceDBQueryBatch>;
+}
+
 const KEY_COLUMN: &str = "key";
-const SEQUENCE_NUMBER_COLUMN: &str = "sequence_number";
+/// Separates the JSON column from the key in the names of promoted columns, see LanceDBSchema
+const PROMOTED_COLUMN_SEPARATOR: &str = "__";
+/// Prefix of the names of promoted columns that have not been backfilled yet
+const PENDING_COLUMN_PREFIX: &str = "pending__";
+/// Number of rows LanceDBSchema::backfill updates at once
+const BACKFILL_BATCH_SIZE: usize = 500;
 
 static KEY_FIELD: LazyLock<Arc<Field>> = LazyLock::new(|| {
     Arc::new(Field::new(KEY_COLUMN, DataType::Utf8, false))
 });
+/// When the row was cleared, in milliseconds since the unix epoch, or null if it has not been
+const CLEARED_AT_COLUMN: &str = "cleared_at";
+const NOT_CLEARED_CONDITION: &str = "cleared_at IS NULL";
+
 static SEQUENCE_NUMBER_FIELD: LazyLock<Arc<Field>> = LazyLock::new(|| {
     Arc::new(Field::new(SEQUENCE_NUMBER_COLUMN, DataType::UInt64, false))
 });
-static DEFAULT_RERANKER: LazyLock<Arc<dyn Reranker>> = LazyLock::new(|| {
-    Arc::new(RRFReranker::default())
+static CLEARED_AT_FIELD: LazyLock<Arc<Field>> = LazyLock::new(|| {
+    Arc::new(Field::new(CLEARED_AT_COLUMN, DataType::Int64, true))
 });
 
 /// Builds a base schema object given a number of floats that the embedded vector will occupy
@@ -629,6 +1442,7 @@ fn build_base_schema() -> Schema {
     Schema::new(vec![
         KEY_FIELD.clone(),
         SEQUENCE_NUMBER_FIELD.clone(),
+        CLEARED_AT_FIELD.clone(),
     ])
 }
 
@@ -643,38 +1457,147 @@ fn check_vector_length(given: u32, expected: u32) -> Result<(), VectorStoreError
     Ok(())
 }
 
+/// Checks that a query result batch contains every column of the data schema, with the expected types, so that
+/// ArrowData::batch_to_iter implementations can rely on them. LanceDB leaves the data columns out of empty
+/// results, so those are not checked.
+fn check_data_columns<D: ArrowData>(batch: &RecordBatch) -> Result<(), String> {
+    if batch.num_rows() == 0 {
+        return Ok(());
+    }
+    for field in D::schema().fields() {
+        match batch.column_by_name(field.name()) {
+            Some(column) if column.data_type().equals_datatype(field.data_type()) => (),
+            Some(column) => return Err(format!("{} column has type {} instead of {}", field.name(),
+                column.data_type(), field.data_type())),
+            None => return Err(format!("{} column is missing", field.name())),
+        }
+    }
+    Ok(())
+}
+
+/// Reads one of the score columns LanceDB adds to query results, eg. _distance. These are non-nullable f32 columns.
+fn read_score_column(batch: &RecordBatch, column_name: &str) -> Result<Vec<f32>, String> {
+    if batch.num_rows() == 0 {
+        return Ok(vec![]);
+    }
+    let column = batch.column_by_name(column_name)
+        .ok_or_else(|| format!("{} column is missing", column_name))?
+        .as_any().downcast_ref::<Float32Array>()
+        .ok_or_else(|| format!("{} column could not be converted to a f32", column_name))?;
+
+    column.iter()
+        .map(|s| s.ok_or_else(|| format!("{} column has a missing value", column_name)))
+        .collect()
+}
+
+fn read_key_column(batch: &RecordBatch) -> Result<Vec<String>, String> {
+    let column = batch.column_by_name(KEY_COLUMN)
+        .ok_or_else(|| format!("{} column is missing", KEY_COLUMN))?
+        .as_any().downcast_ref::<StringArray>()
+        .ok_or_else(|| format!("{} column could not be converted to a string", KEY_COLUMN))?;
+
+    column.iter()
+        .map(|key| key.map(str::to_owned).ok_or_else(|| format!("{} column has a missing value", KEY_COLUMN)))
+        .collect()
+}
+
+/// Decodes the rows of a query result batch and pairs each with its score
+fn zip_scores<D: ArrowData>(batch: RecordBatch, scores: Vec<f32>) -> Result<Vec<(D, f32)>, String> {
+    check_data_columns::<D>(&batch)?;
+    let rows = D::batch_to_iter(batch).into_iter().collect::<Vec<D>>();
+    if rows.len() != scores.len() {
+        return Err(format!("query result has {} rows but {} scores", rows.len(), scores.len()));
+    }
+    Ok(rows.into_iter().zip(scores).collect())
+}
+
+/// Reads and decodes every row of a table, returning the number of rows read
+async fn scan_table<D: ArrowData>(table: &Table) -> Result<usize, anyhow::Error> {
+    let mut result_stream = table.query().execute().await?;
+
+    let mut num_rows = 0;
+    while let Some(rb) = result_stream.next().await {
+        let batch = rb?;
+        check_data_columns::<D>(&batch).map_err(anyhow::Error::msg)?;
+        num_rows += D::batch_to_iter(batch).into_iter().count();
+    }
+    Ok(num_rows)
+}
+
 // Helper function to apply exact match filter specifically for a key in the key column
 // Keys should be guaranteed unique
 fn apply_key_filter<Q: QueryBase>(query: Q, key: &str) -> Q {
-    query.only_if(format!("{} = '{}'", KEY_COLUMN, key))
+    query.only_if(format!("{} = {} AND {}", KEY_COLUMN, string_literal(key), NOT_CLEARED_CONDITION))
+}
+
+/// Quotes a string as a SQL string literal, so strings such as file names like "Bob's notes.pdf" or
+/// "x' OR '1'='1.txt" are compared against as they are rather than breaking or changing the condition. Quotes are
+/// escaped by doubling them, the only escape in the SQL dialect of LanceDB, which does not treat backslashes
+/// specially, so Windows paths are placed as they are. Every string placed in a condition must go through this.
+fn string_literal(s: &str) -> String {
+    let mut literal = String::with_capacity(s.len() + 2);
+    literal.push('\'');
+    for c in s.chars() {
+        if c == '\'' {
+            literal.push('\'');
+        }
+        literal.push(c);
+    }
+    literal.push('\'');
+    literal
 }
 
 /// Builds a SQL WHERE condition from a list of filters.
-/// Filters are combined with AND logic.
-fn build_filter_condition<D: ArrowData + Filterable>(filters: &[Filter]) -> Result<String, FilterStoreError> {
+/// Filters are combined with AND logic. FilterValue::Entry filters compare the column their key was promoted to if
+/// it is one of the table columns, and search the JSON otherwise.
+fn build_filter_condition<D: ArrowData + Filterable>(filters: &[Filter], table_columns: &[String])
+    -> Result<String, FilterStoreError>
+{
     let filterable_attributes = D::filterable_attributes();
 
     let mut conditions = vec![];
     for filter in filters {
+        if let FilterValue::Entry { key, value } = filter.filter {
+            if !D::keyed_attributes().contains(&filter.attribute) {
+                return Err(FilterStoreError::UnavailableFilter { attribute: filter.attribute.to_owned() })
+            }
+            if !matches!(filter.relation, FilterRelation::Eq) {
+                return Err(FilterStoreError::InvalidFilterValue {
+                    attribute: filter.attribute.to_owned(),
+                    issue: "entries can only be filtered on by equality".to_owned(),
+                });
+            }
+            let column_name = D::attribute_to_column_name(filter.attribute);
+            let promoted_column = promoted_column_name(column_name, key);
+            conditions.push(if table_columns.contains(&promoted_column) {
+                format!("{} = {}", promoted_column, string_literal(value))
+            } else {
+                json_entry_condition(column_name, key, value)
+            });
+            continue;
+        }
         if !filterable_attributes.contains(&filter.attribute) {
             return Err(FilterStoreError::UnavailableFilter { attribute: filter.attribute.to_owned() })
         }
         let column_name = D::attribute_to_column_name(filter.attribute);
-        let operator = match filter.relation {
-            FilterRelation::Lt => "<",
-            FilterRelation::Eq => "=",
-            FilterRelation::Gt => ">",
+        let value = match filter.filter {
+            FilterValue::String(s) => string_literal(s),
+            FilterValue::Int(i) => i.to_string(),
+            // NaN and infinity would be written as identifiers rather than numbers
+            FilterValue::Float(f) if !f.is_finite() => return Err(FilterStoreError::InvalidFilterValue {
+                attribute: filter.attribute.to_owned(),
+                issue: format!("{} is not a finite number", f),
+            }),
+            FilterValue::Float(f) => f.to_string(),
+            FilterValue::DateTime(date_time) => format!("timestamp '{}'", date_time.format("%Y-%m-%d %H:%M:%S")),
+            FilterValue::Entry { .. } => unreachable!("Entry filters are handled above"),
         };
-        let condition_str = match filter.filter {
-            FilterValue::String(s) => format!("{} {} '{}'", column_name, operator, s),
-            FilterValue::Int(i) => format!("{} {} {}", column_name, operator, i),
-            FilterValue::Float(f) => format!("{} {} {}", column_name, operator, f),
-            FilterValue::DateTime(date_time) => format!(
-                "{} {} timestamp '{}'",
-                column_name,
-                operator,
-                date_time.format("%Y-%m-%d %H:%M:%S"),
-            ),
+        let condition_str = match filter.relation {
+            FilterRelation::Lt => format!("{} < {}", column_name, value),
+            FilterRelation::Eq => format!("{} = {}", column_name, value),
+            FilterRelation::Gt => format!("{} > {}", column_name, value),
+            FilterRelation::Contains => format!("array_has_any({}, [{}])", column_name, value),
+            FilterRelation::StartsWith => format!("starts_with({}, {})", column_name, value),
         };
         conditions.push(condition_str);
     }
@@ -682,13 +1605,149 @@ fn build_filter_condition<D: ArrowData + Filterable>(filters: &[Filter]) -> Resu
     Ok(conditions.join(" AND "))
 }
 
-/// Helper function to apply filters to a query if filters are not empty.
-fn apply_filters<D: ArrowData + Filterable, Q: QueryBase>(mut query: Q, filters: &[Filter]) -> Result<Q, FilterStoreError> {
-    if !filters.is_empty() {
-        let condition = build_filter_condition::<D>(filters)?;
-        query = query.only_if(&condition);
+/// Name of the column the key of the JSON column is promoted to, see LanceDBSchema
+fn promoted_column_name(json_column: &str, key: &str) -> String {
+    format!("{json_column}{PROMOTED_COLUMN_SEPARATOR}{key}")
+}
+
+/// Name of the column the key of the JSON column is promoted to until it is backfilled
+fn pending_column_name(json_column: &str, key: &str) -> String {
+    format!("{PENDING_COLUMN_PREFIX}{}", promoted_column_name(json_column, key))
+}
+
+/// Splits the name of a promoted column into the JSON column and key it was promoted from, and whether it is still
+/// pending backfill. Returns None for other columns.
+fn parse_promoted_column(column_name: &str) -> Option<(&str, &str, bool)> {
+    let (column_name, pending) = match column_name.strip_prefix(PENDING_COLUMN_PREFIX) {
+        Some(column_name) => (column_name, true),
+        None => (column_name, false),
+    };
+    let (json_column, key) = column_name.rsplit_once(PROMOTED_COLUMN_SEPARATOR)?;
+    (!json_column.is_empty() && is_promotable_key(key)).then_some((json_column, key, pending))
+}
+
+/// Whether the key can be promoted to a column, see LanceDBSchema::promote_key. Separators cannot be doubled, so
+/// the key can be told apart from the JSON column in the column name.
+fn is_promotable_key(key: &str) -> bool {
+    !key.is_empty() && key.len() <= 64
+        && key.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+        && !key.starts_with('_') && !key.ends_with('_') && !key.contains("__")
+}
+
+/// The value of the key in a JSON object, as it is stored in the column the key is promoted to. Numbers and
+/// booleans are stored as their JSON text. Other values, and objects without the key, have no value.
+fn promoted_value(json: &str, key: &str) -> Option<String> {
+    let object: Map<String, Value> = serde_json::from_str(json).ok()?;
+    match object.get(key)? {
+        Value::String(s) => Some(s.clone()),
+        value @ (Value::Number(_) | Value::Bool(_)) => Some(value.to_string()),
+        _ => None,
     }
-    Ok(query)
+}
+
+/// Fills in the promoted columns of the table, pending or not, from the JSON columns of the data being put.
+/// Returns each promoted column of the table with its values, in the order of the table schema.
+fn promoted_column_values(table_schema: &Schema, data_columns: &[(Arc<Field>, ArrayRef)]) -> Vec<(Arc<Field>, ArrayRef)> {
+    let mut promoted_columns = vec![];
+    for field in table_schema.fields() {
+        let Some((json_column, key, _)) = parse_promoted_column(field.name()) else {
+            continue;
+        };
+        let json_values = data_columns.iter()
+            .find(|(data_field, _)| data_field.name() == json_column)
+            .and_then(|(_, array)| array.as_any().downcast_ref::<StringArray>());
+        let Some(json_values) = json_values else {
+            continue;
+        };
+        let values: StringArray = json_values.iter()
+            .map(|json| json.and_then(|json| promoted_value(json, key)))
+            .collect();
+        promoted_columns.push((field.clone(), Arc::new(values) as ArrayRef));
+    }
+    promoted_columns
+}
+
+/// Condition matching rows whose JSON column has the key set to the value, for keys that have not been promoted
+/// to a column. The JSON text is searched as serde_json writes it, so no index can be used, and the key can also be
+/// matched in nested objects.
+fn json_entry_condition(column_name: &str, key: &str, value: &str) -> String {
+    let key = Value::from(key).to_string();
+    let mut value_texts = vec![Value::from(value).to_string()];
+    // Numbers and booleans are written without quotes
+    if let Ok(literal @ (Value::Number(_) | Value::Bool(_))) = serde_json::from_str::<Value>(value) {
+        if literal.to_string() == value {
+            value_texts.push(value.to_owned());
+        }
+    }
+    // The entry ends where the value does, so "100" does not match 1000
+    let conditions: Vec<String> = value_texts.iter()
+        .flat_map(|value_text| [',', '}'].map(|end| format!("{key}:{value_text}{end}")))
+        .map(|entry_text| format!("strpos({}, {}) > 0", column_name, string_literal(&entry_text)))
+        .collect();
+    format!("({})", conditions.join(" OR "))
+}
+
+/// Helper function to apply filters to a query. Cleared rows are always filtered out.
+fn apply_filters<D: ArrowData + Filterable, Q: QueryBase>(query: Q, filters: &[Filter], table_columns: &[String])
+    -> Result<Q, FilterStoreError>
+{
+    if filters.is_empty() {
+        return Ok(query.only_if(NOT_CLEARED_CONDITION));
+    }
+    let condition = build_filter_condition::<D>(filters, table_columns)?;
+    Ok(query.only_if(format!("{} AND {}", condition, NOT_CLEARED_CONDITION)))
+}
+
+/// Key identifying a query of the table in a LanceDBQueryBatch. Query vectors are keyed by the bits of their values,
+/// as identical vectors come from the same cached query embedding.
+fn batch_key<D: ArrowData + Filterable>(table_uri: &str, vector: Option<&[f32]>, fts_terms: Option<&str>,
+    filters: &[Filter], table_columns: &[String], selected_columns: Option<&[String]>, num_results: u32, offset: u32)
+    -> Result<String, FilterStoreError>
+{
+    let condition = if filters.is_empty() { String::new() } else { build_filter_condition::<D>(filters, table_columns)? };
+    let vector_bits: Option<Vec<u32>> = vector.map(|vector| vector.iter().map(|v| v.to_bits()).collect());
+    Ok(format!("{}|{:?}|{:?}|{}|{:?}|{}|{}", table_uri, vector_bits, fts_terms, condition, selected_columns,
+        num_results, offset))
+}
+
+/// Columns a query only reading the attributes selects: the key column and the columns of the attributes
+fn selected_columns<D: ArrowData>(attributes: &[&str]) -> Vec<String> {
+    let mut columns = vec![KEY_COLUMN.to_owned()];
+    for attribute in attributes {
+        let column_name = D::attribute_to_column_name(attribute);
+        if !columns.iter().any(|c| c == column_name) {
+            columns.push(column_name.to_owned());
+        }
+    }
+    columns
+}
+
+/// Adds null columns for the columns of the data schema a query did not select, so ArrowData::batch_to_iter
+/// implementations can decode its rows. The attributes of the null columns decode as empty or zero values.
+fn fill_unselected_columns<D: ArrowData>(batch: RecordBatch) -> Result<RecordBatch, String> {
+    let num_rows = batch.num_rows();
+    let mut fields: Vec<Arc<Field>> = batch.schema().fields().iter().cloned().collect();
+    let mut columns = batch.columns().to_vec();
+    for field in D::schema().fields() {
+        if batch.column_by_name(field.name()).is_none() {
+            fields.push(Arc::new(field.as_ref().clone().with_nullable(true)));
+            columns.push(new_null_array(field.data_type(), num_rows));
+        }
+    }
+    RecordBatch::try_new(Arc::new(Schema::new(fields)), columns)
+        .map_err(|e| format!("could not fill in unselected columns: {}", e))
+}
+
+/// Collects every batch of a query's results
+async fn collect_batches(result_stream: lancedb::error::Result<SendableRecordBatchStream>)
+    -> Result<Arc<Vec<RecordBatch>>, anyhow::Error>
+{
+    let mut result_stream = result_stream?;
+    let mut batches = vec![];
+    while let Some(batch) = result_stream.next().await {
+        batches.push(batch?);
+    }
+    Ok(Arc::new(batches))
 }
 
 /// Helper function to apply pagination (limit and offset) to a query.
@@ -710,14 +1769,15 @@ fn apply_vector_search<D: ArrowData + VectorData>(query: Query, vector: Vec<f32>
 
     let query = query
         .nearest_to(vector)
-        .expect("Unexpected issue converting Vec<f32> to QueryVector")
+        .map_err(|e| VectorStoreError::Query { source: e.into() })?
         .distance_type(DistanceType::Cosine)
         .column(vector_column);
     
     Ok(query)
 }
 
-fn apply_fts<D: ArrowData + FTSData, Q: QueryBase>(mut query: Q, fts_terms: &str) -> Result<Q, anyhow::Error> {
+fn apply_fts<D: ArrowData + FTSData, Q: QueryBase>(mut query: Q, fts_terms: &str) -> Result<Q, anyhow::Error>
+{
     let fts_columns: Vec<String> = D::fts_attributes()
         .into_iter()
         .map(|a| D::attribute_to_column_name(a).to_owned())
@@ -733,20 +1793,169 @@ fn apply_fts<D: ArrowData + FTSData, Q: QueryBase>(mut query: Q, fts_terms: &str
         );
 
         query = query.full_text_search(fts_query);
-        query = query.rerank(DEFAULT_RERANKER.clone());
     }
 
     Ok(query)
 }
 
+fn fts_index_builder(analyzer: FtsAnalyzer) -> FtsIndexBuilder {
+    match analyzer {
+        FtsAnalyzer::Words => FtsIndexBuilder::default(),
+        // Stemming and stop words are for words of a language, not pairs of characters
+        FtsAnalyzer::Bigrams => FtsIndexBuilder::default()
+            .base_tokenizer("ngram".to_owned())
+            .ngram_min_length(2)
+            .ngram_max_length(2)
+            .stem(false)
+            .remove_stop_words(false),
+    }
+}
+
 fn default_filter_index_name(column_name: &str) -> String {
     default_index_name(column_name) + "_filter"
 }
 
+/// The name of the FTS index on the column. LanceDB does not list the analyzer an index was built with, so indexes
+/// built with an analyzer other than the default are named after it, and an index built with another analyzer
+/// before the column's analyzer changed is found by its name and replaced, see ensure_index.
+fn fts_index_name(column_name: &str, analyzer: FtsAnalyzer) -> String {
+    match analyzer {
+        FtsAnalyzer::Words => default_fts_index_name(column_name),
+        FtsAnalyzer::Bigrams => default_fts_index_name(column_name) + "_bigrams",
+    }
+}
+
+/// The type LanceDB lists the index as once created, if known before it is created
+fn index_type(index: &Index) -> Option<IndexType> {
+    match index {
+        Index::Auto => None,
+        Index::BTree(_) => Some(IndexType::BTree),
+        Index::Bitmap(_) => Some(IndexType::Bitmap),
+        Index::LabelList(_) => Some(IndexType::LabelList),
+        Index::FTS(_) => Some(IndexType::FTS),
+        Index::IvfFlat(_) => Some(IndexType::IvfFlat),
+        Index::IvfPq(_) => Some(IndexType::IvfPq),
+        Index::IvfRq(_) => Some(IndexType::IvfRq),
+        Index::IvfHnswPq(_) => Some(IndexType::IvfHnswPq),
+        Index::IvfHnswSq(_) => Some(IndexType::IvfHnswSq),
+    }
+}
+
 fn default_fts_index_name(column_name: &str) -> String {
     default_index_name(column_name) + "_fts"
 }
 
 fn default_index_name(column_name: &str) -> String {
     column_name.to_owned() + "_idx"
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use crate::index::{ChunkFile, embedding::siglip2::Siglip2EmbeddedChunkFile};
+
+    use super::*;
+
+    /// Splits the condition into its string literals, unescaped, and the condition with each literal replaced by ?,
+    /// the way LanceDB's SQL parser reads them. Fails on an unterminated literal.
+    fn split_literals(condition: &str) -> (String, Vec<String>) {
+        let mut skeleton = String::new();
+        let mut literals = vec![];
+        let mut chars = condition.chars().peekable();
+        while let Some(c) = chars.next() {
+            if c != '\'' {
+                skeleton.push(c);
+                continue;
+            }
+            let mut literal = String::new();
+            loop {
+                match chars.next() {
+                    Some('\'') if chars.peek() == Some(&'\'') => {
+                        chars.next();
+                        literal.push('\'');
+                    },
+                    Some('\'') => break,
+                    Some(c) => literal.push(c),
+                    None => panic!("Unterminated string literal in condition: {}", condition),
+                }
+            }
+            skeleton.push('?');
+            literals.push(literal);
+        }
+        (skeleton, literals)
+    }
+
+    fn condition(attribute: &str, filter: FilterValue, relation: FilterRelation) -> Result<String, FilterStoreError> {
+        build_filter_condition::<Siglip2EmbeddedChunkFile>(&[Filter { attribute, filter, relation }], &[])
+    }
+
+    /// Strings made of the pieces that break out of or change a naively quoted condition, along with any other text
+    fn hostile_string() -> impl Strategy<Value = String> {
+        let piece = prop_oneof![
+            Just("'".to_owned()),
+            Just("''".to_owned()),
+            Just("\\".to_owned()),
+            Just("--".to_owned()),
+            Just(";".to_owned()),
+            Just(" OR 1=1".to_owned()),
+            Just("' OR '1'='1".to_owned()),
+            ".{0,4}",
+        ];
+        prop::collection::vec(piece, 0..8).prop_map(|pieces| pieces.concat())
+    }
+
+    proptest! {
+        #[test]
+        fn string_literal_round_trips(s in hostile_string()) {
+            let (skeleton, literals) = split_literals(&string_literal(&s));
+            prop_assert_eq!(skeleton, "?");
+            prop_assert_eq!(literals, vec![s]);
+        }
+
+        #[test]
+        fn string_filters_compare_one_literal(s in hostile_string()) {
+            let column_name = Siglip2EmbeddedChunkFile::attribute_to_column_name(ChunkFile::ORIGINAL_FILE_ATTR);
+            for (relation, expected) in [
+                (FilterRelation::Eq, format!("{} = ?", column_name)),
+                (FilterRelation::Lt, format!("{} < ?", column_name)),
+                (FilterRelation::StartsWith, format!("starts_with({}, ?)", column_name)),
+            ] {
+                let condition = condition(ChunkFile::ORIGINAL_FILE_ATTR, FilterValue::String(&s), relation).unwrap();
+                let (skeleton, literals) = split_literals(&condition);
+                prop_assert_eq!(skeleton, expected);
+                prop_assert_eq!(literals, vec![s.clone()]);
+            }
+        }
+
+        #[test]
+        fn entry_filters_search_for_the_entry(key in hostile_string(), value in hostile_string()) {
+            // Prefixed so the value is never a JSON number or boolean, which is also searched for without quotes
+            let value = format!("v{}", value);
+            let condition = condition(ChunkFile::FILE_TAGS_ATTR, FilterValue::Entry { key: &key, value: &value },
+                FilterRelation::Eq).unwrap();
+            let (skeleton, literals) = split_literals(&condition);
+            let column_name = Siglip2EmbeddedChunkFile::attribute_to_column_name(ChunkFile::FILE_TAGS_ATTR);
+            prop_assert_eq!(skeleton, format!("(strpos({0}, ?) > 0 OR strpos({0}, ?) > 0)", column_name));
+            let entry = format!("{}:{}", Value::from(key.as_str()), Value::from(value.as_str()));
+            prop_assert_eq!(literals, vec![format!("{},", entry), format!("{}}}", entry)]);
+        }
+
+        #[test]
+        fn finite_floats_are_compared_as_numbers(f in any::<f32>().prop_filter("finite", |f| f.is_finite())) {
+            let condition = condition(ChunkFile::FILE_SIZE_ATTR, FilterValue::Float(f), FilterRelation::Gt).unwrap();
+            let column_name = Siglip2EmbeddedChunkFile::attribute_to_column_name(ChunkFile::FILE_SIZE_ATTR);
+            let number = condition.strip_prefix(&format!("{} > ", column_name)).unwrap();
+            prop_assert_eq!(number.parse::<f32>().unwrap(), f);
+        }
+    }
+
+    #[test]
+    fn non_finite_floats_are_rejected() {
+        for f in [f32::NAN, f32::INFINITY, f32::NEG_INFINITY] {
+            for relation in [FilterRelation::Lt, FilterRelation::Eq, FilterRelation::Gt] {
+                let result = condition(ChunkFile::FILE_SIZE_ATTR, FilterValue::Float(f), relation);
+                assert!(matches!(result, Err(FilterStoreError::InvalidFilterValue { .. })), "{} was not rejected", f);
+            }
+        }
+    }
+}
@@ -0,0 +1,90 @@
+use std::env;
+
+use tracing_subscriber::{EnvFilter, fmt::format::FmtSpan};
+
+use crate::app_config;
+
+/// Environment variable that overrides the configured log filter when present.
+pub const LOG_FILTER_ENV_VAR: &str = "FETCH_LOG";
+
+/// Output format for logs and tracing events.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Human readable, single line output
+    Text,
+    /// One JSON object per line, including the fields of all enclosing spans
+    Json,
+}
+
+/// Settings controlling how Fetch emits logs and tracing spans.
+#[derive(Debug, Clone)]
+pub struct TelemetryConfig {
+    pub format: LogFormat,
+    /// Per-module filter directives, eg. "warn,fetch_core::index=debug"
+    pub filter: String,
+    /// Whether to emit an event with timing information every time a span closes
+    pub span_durations: bool,
+}
+
+impl Default for TelemetryConfig {
+    fn default() -> Self {
+        TelemetryConfig {
+            format: LogFormat::Text,
+            filter: "warn".to_owned(),
+            span_durations: false,
+        }
+    }
+}
+
+impl TelemetryConfig {
+    /// Loads the telemetry configuration from the telemetry.toml settings file, falling back to defaults
+    /// for any setting that is missing. The FETCH_LOG environment variable takes precedence over the
+    /// configured filter.
+    pub fn from_settings() -> Result<TelemetryConfig, anyhow::Error> {
+        let settings = app_config::get_telemetry_config()?;
+        let mut config = TelemetryConfig::default();
+
+        if let Ok(format) = settings.get_string("format") {
+            config.format = match format.to_lowercase().as_str() {
+                "text" => LogFormat::Text,
+                "json" => LogFormat::Json,
+                other => return Err(anyhow::anyhow!("Unknown log format in telemetry settings: {}", other)),
+            };
+        }
+        if let Ok(filter) = settings.get_string("filter") {
+            config.filter = filter;
+        }
+        if let Ok(span_durations) = settings.get_bool("span_durations") {
+            config.span_durations = span_durations;
+        }
+        if let Ok(filter) = env::var(LOG_FILTER_ENV_VAR) {
+            config.filter = filter;
+        }
+
+        Ok(config)
+    }
+}
+
+/// Initializes the global tracing subscriber using the telemetry settings file.
+///
+/// Records emitted through the `log` crate by dependencies are forwarded into the subscriber as well.
+/// Should be called once, as early as possible, by binaries using fetch-core.
+pub fn init() -> Result<(), anyhow::Error> {
+    init_with(TelemetryConfig::from_settings()?)
+}
+
+/// Initializes the global tracing subscriber with the given configuration.
+pub fn init_with(config: TelemetryConfig) -> Result<(), anyhow::Error> {
+    let filter = EnvFilter::try_new(&config.filter)
+        .map_err(|e| anyhow::anyhow!("Invalid log filter {:?}: {}", config.filter, e))?;
+    let span_events = if config.span_durations { FmtSpan::CLOSE } else { FmtSpan::NONE };
+
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_span_events(span_events);
+
+    match config.format {
+        LogFormat::Text => builder.try_init(),
+        LogFormat::Json => builder.json().with_current_span(true).with_span_list(true).try_init(),
+    }.map_err(|e| anyhow::anyhow!("Failed to initialize tracing subscriber: {}", e))
+}
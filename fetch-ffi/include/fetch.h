@@ -0,0 +1,77 @@
+/*
+ * C interface to the Fetch engine, see fetch-ffi/src/lib.rs.
+ *
+ * Functions either return a pointer, which is NULL on failure, or a FetchStatus. On failure fetch_last_error
+ * describes what went wrong. Strings are UTF-8 and nul terminated.
+ */
+
+#ifndef FETCH_H
+#define FETCH_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef enum FetchStatus {
+    FETCH_STATUS_OK = 0,
+    /* A pointer was NULL, or a string was not valid UTF-8 */
+    FETCH_STATUS_INVALID_ARGUMENT = 1,
+    /* The engine failed, see fetch_last_error */
+    FETCH_STATUS_ERROR = 2,
+} FetchStatus;
+
+/* Number of files indexed, skipped and failed by fetch_index */
+typedef struct FetchIndexReport {
+    uint64_t indexed;
+    uint64_t skipped;
+    uint64_t failed;
+} FetchIndexReport;
+
+/* An open engine */
+typedef struct FetchHandle FetchHandle;
+
+/*
+ * Initializes the models and libraries the engine uses from the resource directory. Must be called once, before
+ * fetch_open. A NULL resource directory uses the working directory.
+ */
+FetchStatus fetch_init_resources(const char *resource_dir);
+
+/*
+ * Opens the index in the data directory, creating it if it does not exist yet. A NULL data directory opens the
+ * index of the Fetch app. Returns NULL on failure. The handle must be freed with fetch_free.
+ */
+FetchHandle *fetch_open(const char *data_dir);
+
+/*
+ * Indexes the files given and every file under the directories given, waiting for any other process writing to
+ * the index to finish first. Files that fail to index are counted in the report, which may be NULL.
+ */
+FetchStatus fetch_index(const FetchHandle *handle, const char *const *paths, size_t num_paths,
+                        FetchIndexReport *report);
+
+/*
+ * Queries the index, returning the best matching files as a JSON array of { "path", "score", "rank" } objects,
+ * best first. Returns NULL on failure. The string must be freed with fetch_string_free.
+ */
+char *fetch_query(const FetchHandle *handle, const char *query);
+
+/* Frees a string returned by the engine. Does nothing if the string is NULL. */
+void fetch_string_free(char *s);
+
+/* Closes the index and frees the handle. Does nothing if the handle is NULL. */
+void fetch_free(FetchHandle *handle);
+
+/*
+ * Describes the last failure on the calling thread, or NULL if nothing has failed on it yet. The string is owned
+ * by the engine, and is valid until the next call that fails on the same thread.
+ */
+const char *fetch_last_error(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* FETCH_H */
@@ -0,0 +1,240 @@
+//! C interface to the Fetch engine, for integrations that cannot link Rust directly, eg. a Swift companion app for
+//! the Spotlight importer on macOS or a C# shell extension on Windows. See include/fetch.h for the declarations.
+//!
+//! Functions either return a pointer, which is null on failure, or a FetchStatus. On failure fetch_last_error
+//! describes what went wrong.
+
+use std::{cell::RefCell, ffi::{c_char, CStr, CString}, ptr, slice};
+
+use camino::Utf8PathBuf;
+use fetch_core::{Fetch, FetchConfig};
+use serde::Serialize;
+use tokio::runtime::Runtime;
+use tracing::debug;
+
+/// Result of the functions that do not return a pointer
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FetchStatus {
+    Ok = 0,
+    /// A pointer was null, or a string was not valid UTF-8
+    InvalidArgument = 1,
+    /// The engine failed, see fetch_last_error
+    Error = 2,
+}
+
+/// Number of files indexed, skipped and failed by fetch_index
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FetchIndexReport {
+    pub indexed: u64,
+    pub skipped: u64,
+    pub failed: u64,
+}
+
+/// An open engine, with the runtime its work runs on. Opaque to C.
+pub struct FetchHandle {
+    runtime: Runtime,
+    fetch: Fetch,
+}
+
+/// Initializes the models and libraries the engine uses from the resource directory. Must be called once, before
+/// fetch_open. A null resource directory uses the working directory.
+///
+/// # Safety
+/// resource_dir must be null or a valid nul terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn fetch_init_resources(resource_dir: *const c_char) -> FetchStatus {
+    let resource_dir = match optional_str(resource_dir) {
+        Ok(resource_dir) => resource_dir.map(Utf8PathBuf::from),
+        Err(status) => return status,
+    };
+    match fetch_core::init_resources(resource_dir.as_deref()) {
+        Ok(()) => FetchStatus::Ok,
+        Err(e) => fail(FetchStatus::Error, format!("Could not initialize resources: {:?}", e)),
+    }
+}
+
+/// Opens the index in the data directory, creating it if it does not exist yet. A null data directory opens the
+/// index of the Fetch app. Returns null on failure. The handle must be freed with fetch_free.
+///
+/// # Safety
+/// data_dir must be null or a valid nul terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn fetch_open(data_dir: *const c_char) -> *mut FetchHandle {
+    let mut config = FetchConfig { holder: "Fetch C interface".to_owned(), ..FetchConfig::default() };
+    match optional_str(data_dir) {
+        Ok(Some(data_dir)) => config.data_dir = Utf8PathBuf::from(data_dir),
+        Ok(None) => {},
+        Err(_) => return ptr::null_mut(),
+    }
+
+    let runtime = match tokio::runtime::Builder::new_multi_thread().enable_all().build() {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            fail(FetchStatus::Error, format!("Could not start runtime: {:?}", e));
+            return ptr::null_mut();
+        },
+    };
+    debug!("Opening index at {}", config.data_dir);
+    match runtime.block_on(Fetch::open(config)) {
+        Ok(fetch) => Box::into_raw(Box::new(FetchHandle { runtime, fetch })),
+        Err(e) => {
+            fail(FetchStatus::Error, format!("Could not open index: {:?}", e));
+            ptr::null_mut()
+        },
+    }
+}
+
+/// Indexes the files given and every file under the directories given, waiting for any other process writing to
+/// the index to finish first. Files that fail to index do not stop the others from being indexed, and are counted
+/// in the report instead, which may be null.
+///
+/// # Safety
+/// handle must be a handle returned by fetch_open that has not been freed, paths must point to num_paths valid nul
+/// terminated strings, and report must be null or point to a FetchIndexReport.
+#[no_mangle]
+pub unsafe extern "C" fn fetch_index(handle: *const FetchHandle, paths: *const *const c_char, num_paths: usize,
+    report: *mut FetchIndexReport) -> FetchStatus {
+    let Some(handle) = handle.as_ref() else {
+        return fail(FetchStatus::InvalidArgument, "Handle is null");
+    };
+    if paths.is_null() && num_paths > 0 {
+        return fail(FetchStatus::InvalidArgument, "Paths are null");
+    }
+    let mut index_paths = Vec::with_capacity(num_paths);
+    if num_paths > 0 {
+        for &path in slice::from_raw_parts(paths, num_paths) {
+            match required_str(path) {
+                Ok(path) => index_paths.push(Utf8PathBuf::from(path)),
+                Err(status) => return status,
+            }
+        }
+    }
+
+    match handle.runtime.block_on(handle.fetch.index(&index_paths)) {
+        Ok(index_report) => {
+            if let Some(report) = report.as_mut() {
+                *report = FetchIndexReport {
+                    indexed: index_report.indexed.len() as u64,
+                    skipped: index_report.skipped.len() as u64,
+                    failed: index_report.failed.len() as u64,
+                };
+            }
+            FetchStatus::Ok
+        },
+        Err(e) => fail(FetchStatus::Error, format!("Could not index files: {:?}", e)),
+    }
+}
+
+/// Queries the index, returning the best matching files as a JSON array of `{ "path", "score", "rank" }` objects,
+/// best first. Returns null on failure. The string must be freed with fetch_string_free.
+///
+/// # Safety
+/// handle must be a handle returned by fetch_open that has not been freed, and query a valid nul terminated
+/// string.
+#[no_mangle]
+pub unsafe extern "C" fn fetch_query(handle: *const FetchHandle, query: *const c_char) -> *mut c_char {
+    let Some(handle) = handle.as_ref() else {
+        fail(FetchStatus::InvalidArgument, "Handle is null");
+        return ptr::null_mut();
+    };
+    let Ok(query) = required_str(query) else {
+        return ptr::null_mut();
+    };
+
+    let results = match handle.runtime.block_on(handle.fetch.query(query)) {
+        Ok(results) => results,
+        Err(e) => {
+            fail(FetchStatus::Error, format!("Could not query index: {:?}", e));
+            return ptr::null_mut();
+        },
+    };
+    let results: Vec<FfiQueryResult> = results.iter()
+        .map(|result| FfiQueryResult { path: result.path.as_str(), score: result.score, rank: result.rank })
+        .collect();
+    match serde_json::to_string(&results).map(CString::new) {
+        Ok(Ok(json)) => json.into_raw(),
+        Ok(Err(e)) => {
+            fail(FetchStatus::Error, format!("Query results contain a nul character: {:?}", e));
+            ptr::null_mut()
+        },
+        Err(e) => {
+            fail(FetchStatus::Error, format!("Could not serialize query results: {:?}", e));
+            ptr::null_mut()
+        },
+    }
+}
+
+/// Frees a string returned by the engine. Does nothing if the string is null.
+///
+/// # Safety
+/// s must be null or a string returned by the engine that has not been freed.
+#[no_mangle]
+pub unsafe extern "C" fn fetch_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+/// Closes the index and frees the handle. Does nothing if the handle is null.
+///
+/// # Safety
+/// handle must be null or a handle returned by fetch_open that has not been freed.
+#[no_mangle]
+pub unsafe extern "C" fn fetch_free(handle: *mut FetchHandle) {
+    if !handle.is_null() {
+        drop(Box::from_raw(handle));
+    }
+}
+
+/// Describes the last failure on the calling thread, or null if nothing has failed on it yet. The string is owned
+/// by the engine, and is valid until the next call that fails on the same thread.
+#[no_mangle]
+pub extern "C" fn fetch_last_error() -> *const c_char {
+    LAST_ERROR.with(|last_error| last_error.borrow().as_ref().map_or(ptr::null(), |e| e.as_ptr()))
+}
+
+// Private functions and variables
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Query result as serialized by fetch_query, kept separate from the engine's so the JSON stays stable
+#[derive(Serialize)]
+struct FfiQueryResult<'a> {
+    path: &'a str,
+    score: f32,
+    rank: u32,
+}
+
+/// Records the failure for fetch_last_error, returning the status to return
+fn fail(status: FetchStatus, message: impl Into<String>) -> FetchStatus {
+    let message = message.into();
+    debug!("C interface call failed: {}", message);
+    // Messages with nul characters are cut at the first one
+    let message = CString::new(message).unwrap_or_else(|e| {
+        let nul_position = e.nul_position();
+        let mut bytes = e.into_vec();
+        bytes.truncate(nul_position);
+        CString::new(bytes).expect("bytes before the first nul character should not contain a nul character")
+    });
+    LAST_ERROR.with(|last_error| *last_error.borrow_mut() = Some(message));
+    status
+}
+
+/// The string pointed to, failing if it is null or not UTF-8
+unsafe fn required_str<'a>(s: *const c_char) -> Result<&'a str, FetchStatus> {
+    optional_str(s)?.ok_or_else(|| fail(FetchStatus::InvalidArgument, "String is null"))
+}
+
+/// The string pointed to, or None if it is null, failing if it is not UTF-8
+unsafe fn optional_str<'a>(s: *const c_char) -> Result<Option<&'a str>, FetchStatus> {
+    if s.is_null() {
+        return Ok(None);
+    }
+    CStr::from_ptr(s).to_str()
+        .map(Some)
+        .map_err(|e| fail(FetchStatus::InvalidArgument, format!("String is not valid UTF-8: {:?}", e)))
+}
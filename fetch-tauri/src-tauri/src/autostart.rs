@@ -0,0 +1,157 @@
+use std::{env, error::Error, fs, path::PathBuf};
+
+use tauri::AppHandle;
+
+/// Argument passed when started at login, so the app starts in the tray without opening a window
+pub const BACKGROUND_ARG: &str = "--background";
+
+/// Registers or unregisters the app to start when the user logs in. Uses a launch agent on macOS, the Run
+/// registry key on Windows and an XDG autostart entry on Linux.
+pub fn set_start_at_login(app: &AppHandle, enabled: bool) -> Result<(), Box<dyn Error>> {
+    let exe = env::current_exe()?;
+
+    #[cfg(target_os = "macos")]
+    {
+        let path = launch_agent_path(app)?;
+        if enabled {
+            let plist = format!(
+                r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>Label</key>
+    <string>{}</string>
+    <key>ProgramArguments</key>
+    <array>
+        <string>{}</string>
+        <string>{}</string>
+    </array>
+    <key>RunAtLoad</key>
+    <true/>
+</dict>
+</plist>
+"#,
+                app.config().identifier,
+                escape_xml(&exe.to_string_lossy()),
+                BACKGROUND_ARG,
+            );
+            write_file(&path, &plist)?;
+        } else {
+            remove_file(&path)?;
+        }
+    }
+
+    #[cfg(target_os = "windows")]
+    {
+        use std::process::Command;
+
+        let _ = app;
+        let output = if enabled {
+            Command::new("reg")
+                .args([
+                    "add",
+                    RUN_KEY,
+                    "/v",
+                    REGISTRY_VALUE_NAME,
+                    "/t",
+                    "REG_SZ",
+                    "/f",
+                    "/d",
+                ])
+                .arg(format!("\"{}\" {}", exe.display(), BACKGROUND_ARG))
+                .output()?
+        } else if is_registered_in_run_key()? {
+            Command::new("reg")
+                .args(["delete", RUN_KEY, "/v", REGISTRY_VALUE_NAME, "/f"])
+                .output()?
+        } else {
+            return Ok(());
+        };
+        if !output.status.success() {
+            return Err(format!(
+                "Could not update the Run registry key: {}",
+                String::from_utf8_lossy(&output.stderr)
+            )
+            .into());
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        let _ = app;
+        let path = autostart_entry_path()?;
+        if enabled {
+            let entry = format!(
+                "[Desktop Entry]\nType=Application\nName=Fetch\nExec=\"{}\" {}\nX-GNOME-Autostart-enabled=true\n",
+                exe.display(),
+                BACKGROUND_ARG,
+            );
+            write_file(&path, &entry)?;
+        } else {
+            remove_file(&path)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether the app was started at login rather than by the user
+pub fn started_in_background() -> bool {
+    env::args().any(|arg| arg == BACKGROUND_ARG)
+}
+
+// Private functions
+
+#[cfg(any(target_os = "macos", target_os = "linux"))]
+fn write_file(path: &PathBuf, contents: &str) -> Result<(), Box<dyn Error>> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, contents)?;
+    Ok(())
+}
+
+#[cfg(any(target_os = "macos", target_os = "linux"))]
+fn remove_file(path: &PathBuf) -> Result<(), Box<dyn Error>> {
+    if path.exists() {
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "macos")]
+fn launch_agent_path(app: &AppHandle) -> Result<PathBuf, Box<dyn Error>> {
+    let home = env::var("HOME")?;
+    Ok(PathBuf::from(home)
+        .join("Library/LaunchAgents")
+        .join(format!("{}.plist", app.config().identifier)))
+}
+
+#[cfg(target_os = "macos")]
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(target_os = "windows")]
+const RUN_KEY: &str = r"HKCU\Software\Microsoft\Windows\CurrentVersion\Run";
+#[cfg(target_os = "windows")]
+const REGISTRY_VALUE_NAME: &str = "Fetch";
+
+#[cfg(target_os = "windows")]
+fn is_registered_in_run_key() -> Result<bool, Box<dyn Error>> {
+    let output = std::process::Command::new("reg")
+        .args(["query", RUN_KEY, "/v", REGISTRY_VALUE_NAME])
+        .output()?;
+    Ok(output.status.success())
+}
+
+#[cfg(target_os = "linux")]
+fn autostart_entry_path() -> Result<PathBuf, Box<dyn Error>> {
+    let config_dir = match env::var("XDG_CONFIG_HOME") {
+        Ok(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(env::var("HOME")?).join(".config"),
+    };
+    Ok(config_dir.join("autostart/fetch.desktop"))
+}
@@ -1,13 +1,14 @@
 use std::{collections::HashMap, error::Error, path::PathBuf};
 
-use fetch_cli::{index::IndexArgs, query::QueryArgs, query_by_file::QueryByFileArgs};
+use fetch_cli::{collection::CollectionArgs, dupes::DupesArgs, eval::EvalArgs, index::IndexArgs, log::LogArgs, migrate::MigrateDataArgs, query::{CollapseMode, QueryArgs, QueryFormat}, query_by_file::QueryByFileArgs, repair::RepairArgs, restore::RestoreArgs, schema::SchemaArgs, snapshot::SnapshotArgs, summarize::SummarizeArgs, tag::TagArgs, topics::TopicsArgs};
 use tauri::AppHandle;
 use tauri_plugin_cli::{ArgData, CliExt};
+use tracing::{debug, error};
 
 /// Checks to see if we are running a CLI program, then executes it if so. Returns
 /// true if CLI command was detected.
 pub fn intercept_cli_command(app_handle: &AppHandle) -> bool {
-    println!("Intercepting CLI command...");
+    debug!("Intercepting CLI command...");
     if let Ok(matches) = app_handle.cli().matches() {
         check_help_and_maybe_exit(app_handle, &matches.args);
         if let Some(subcommand) = matches.subcommand {
@@ -23,7 +24,7 @@ pub fn intercept_cli_command(app_handle: &AppHandle) -> bool {
                             .and_then(|s| s.parse().ok())
                             .unwrap_or(4);
 
-                        println!("index sc_args {:?}", sc_args);
+                        debug!("index sc_args {:?}", sc_args);
                         let recursive = sc_args
                             .get("recursive")
                             .and_then(|arg| arg.value.as_bool())
@@ -51,14 +52,54 @@ pub fn intercept_cli_command(app_handle: &AppHandle) -> bool {
 
                         fetch_cli::index::index(args).await?;
                     },
-                    "query" => {
-                        let query = sc_args
-                            .get("query")
-                            .expect("subcommand was 'query' but query arg does not exist")
+                    "log" => {
+                        let path = PathBuf::from(sc_args
+                            .get("path")
+                            .expect("subcommand was 'log' but path arg does not exist")
                             .value
                             .as_str()
-                            .expect("Could not get query arg as string")
-                            .to_owned();
+                            .expect("Could not get path arg as string"));
+
+                        let num_entries: usize = sc_args
+                            .get("num_entries")
+                            .and_then(|arg| arg.value.as_str())
+                            .and_then(|s| s.parse().ok())
+                            .unwrap_or(20);
+
+                        let args = LogArgs { path, num_entries };
+
+                        #[cfg(windows)]
+                        alloc_attach_console();
+
+                        fetch_cli::log::log(args).await?;
+                    },
+                    "migrate-data" => {
+                        let to = PathBuf::from(sc_args
+                            .get("to")
+                            .expect("subcommand was 'migrate-data' but to arg does not exist")
+                            .value
+                            .as_str()
+                            .expect("Could not get to arg as string"));
+
+                        let args = MigrateDataArgs { to };
+
+                        #[cfg(windows)]
+                        alloc_attach_console();
+
+                        fetch_cli::migrate::migrate_data(args).await?;
+                    },
+                    "query" => {
+                        let interactive = sc_args
+                            .get("interactive")
+                            .and_then(|arg| arg.value.as_bool())
+                            .unwrap_or(false);
+
+                        // The query is only optional in interactive mode, which prompts for queries
+                        let query = match sc_args.get("query").and_then(|arg| arg.value.as_str()) {
+                            Some(query) => query.to_owned(),
+                            None if interactive => String::new(),
+                            None => return Err("A query is required unless querying interactively (-i)".into()),
+                        };
 
                         let num_results: u32 = sc_args
                             .get("num_results")
@@ -72,10 +113,25 @@ pub fn intercept_cli_command(app_handle: &AppHandle) -> bool {
                             .and_then(|s| s.parse().ok())
                             .unwrap_or(100);
 
+                        let format: QueryFormat = sc_args
+                            .get("format")
+                            .and_then(|arg| arg.value.as_str())
+                            .unwrap_or("text")
+                            .parse()?;
+
+                        let collapse: CollapseMode = sc_args
+                            .get("collapse")
+                            .and_then(|arg| arg.value.as_str())
+                            .unwrap_or("none")
+                            .parse()?;
+
                         let args = QueryArgs {
                             query,
                             num_results,
                             chunks_per_query,
+                            format,
+                            interactive,
+                            collapse,
                         };
 
                         #[cfg(windows)]
@@ -107,6 +163,243 @@ pub fn intercept_cli_command(app_handle: &AppHandle) -> bool {
 
                         fetch_cli::query_by_file::query_by_file(args).await?;
                     },
+                    "collection" => {
+                        let action = sc_args
+                            .get("action")
+                            .expect("subcommand was 'collection' but action arg does not exist")
+                            .value
+                            .as_str()
+                            .expect("Could not get action arg as string");
+                        let name = sc_args
+                            .get("name")
+                            .and_then(|arg| arg.value.as_str())
+                            .map(str::to_owned);
+                        let path = sc_args
+                            .get("path")
+                            .and_then(|arg| arg.value.as_str())
+                            .map(PathBuf::from);
+
+                        let args = match (action, name, path) {
+                            ("list", name, _) => CollectionArgs::List { name },
+                            ("add", Some(name), Some(path)) => CollectionArgs::Add { name, path },
+                            ("remove", Some(name), Some(path)) => CollectionArgs::Remove { name, path },
+                            ("export", Some(name), Some(destination)) => CollectionArgs::Export { name, destination },
+                            _ => return Err("collection add, remove and export require a name and a path".into()),
+                        };
+
+                        #[cfg(windows)]
+                        alloc_attach_console();
+
+                        fetch_cli::collection::collection(args).await?;
+                    },
+                    "dupes" => {
+                        let threshold: f32 = sc_args
+                            .get("threshold")
+                            .and_then(|arg| arg.value.as_str())
+                            .and_then(|s| s.parse().ok())
+                            .unwrap_or(0.95);
+
+                        let args = DupesArgs { threshold };
+
+                        #[cfg(windows)]
+                        alloc_attach_console();
+
+                        fetch_cli::dupes::dupes(args).await?;
+                    },
+                    "eval" => {
+                        let labels = PathBuf::from(sc_args
+                            .get("labels")
+                            .expect("subcommand was 'eval' but labels arg does not exist")
+                            .value
+                            .as_str()
+                            .expect("Could not get labels arg as string"));
+
+                        let k: u32 = sc_args
+                            .get("k")
+                            .and_then(|arg| arg.value.as_str())
+                            .and_then(|s| s.parse().ok())
+                            .unwrap_or(10);
+
+                        let chunks_per_query: u32 = sc_args
+                            .get("chunks_per_query")
+                            .and_then(|arg| arg.value.as_str())
+                            .and_then(|s| s.parse().ok())
+                            .unwrap_or(100);
+
+                        let compare_hybrid = sc_args
+                            .get("compare_hybrid")
+                            .and_then(|arg| arg.value.as_bool())
+                            .unwrap_or(false);
+
+                        let calibrate = sc_args
+                            .get("calibrate")
+                            .and_then(|arg| arg.value.as_bool())
+                            .unwrap_or(false);
+
+                        let args = EvalArgs {
+                            labels,
+                            k,
+                            chunks_per_query,
+                            compare_hybrid,
+                            calibrate,
+                        };
+
+                        #[cfg(windows)]
+                        alloc_attach_console();
+
+                        fetch_cli::eval::eval(args).await?;
+                    },
+                    "repair" => {
+                        let jobs: usize = sc_args
+                            .get("jobs")
+                            .and_then(|arg| arg.value.as_str())
+                            .and_then(|s| s.parse().ok())
+                            .unwrap_or(4);
+
+                        let args = RepairArgs { jobs };
+
+                        #[cfg(windows)]
+                        alloc_attach_console();
+
+                        fetch_cli::repair::repair(args).await?;
+                    },
+                    "restore" => {
+                        let path = PathBuf::from(sc_args
+                            .get("path")
+                            .expect("subcommand was 'restore' but path arg does not exist")
+                            .value
+                            .as_str()
+                            .expect("Could not get path arg as string"));
+
+                        let args = RestoreArgs { path };
+
+                        #[cfg(windows)]
+                        alloc_attach_console();
+
+                        fetch_cli::restore::restore(args).await?;
+                    },
+                    "schema" => {
+                        let action = sc_args
+                            .get("action")
+                            .expect("subcommand was 'schema' but action arg does not exist")
+                            .value
+                            .as_str()
+                            .expect("Could not get action arg as string");
+                        let key = sc_args
+                            .get("key")
+                            .and_then(|arg| arg.value.as_str())
+                            .map(str::to_owned);
+
+                        let args = match (action, key) {
+                            ("promote-tag", Some(key)) => SchemaArgs::PromoteTag { key },
+                            ("demote-tag", Some(key)) => SchemaArgs::DemoteTag { key },
+                            ("list", _) => SchemaArgs::List,
+                            ("backfill", _) => SchemaArgs::Backfill,
+                            _ => return Err("schema promote-tag and demote-tag require a tag key".into()),
+                        };
+
+                        #[cfg(windows)]
+                        alloc_attach_console();
+
+                        fetch_cli::schema::schema(args).await?;
+                    },
+                    "snapshot" => {
+                        let action = sc_args
+                            .get("action")
+                            .expect("subcommand was 'snapshot' but action arg does not exist")
+                            .value
+                            .as_str()
+                            .expect("Could not get action arg as string");
+                        let name = sc_args
+                            .get("name")
+                            .and_then(|arg| arg.value.as_str())
+                            .map(str::to_owned);
+
+                        let args = match (action, name) {
+                            ("create", name) => SnapshotArgs::Create { name },
+                            ("list", _) => SnapshotArgs::List,
+                            ("restore", Some(name)) => SnapshotArgs::Restore { name },
+                            ("delete", Some(name)) => SnapshotArgs::Delete { name },
+                            _ => return Err("snapshot restore and delete require a snapshot name".into()),
+                        };
+
+                        #[cfg(windows)]
+                        alloc_attach_console();
+
+                        fetch_cli::snapshot::snapshot(args).await?;
+                    },
+                    "stats" => {
+                        #[cfg(windows)]
+                        alloc_attach_console();
+
+                        fetch_cli::stats::stats().await?;
+                    },
+                    "summarize" => {
+                        let path = PathBuf::from(sc_args
+                            .get("path")
+                            .expect("subcommand was 'summarize' but path arg does not exist")
+                            .value
+                            .as_str()
+                            .expect("Could not get path arg as string"));
+
+                        let args = SummarizeArgs { path };
+
+                        #[cfg(windows)]
+                        alloc_attach_console();
+
+                        fetch_cli::summarize::summarize(args).await?;
+                    },
+                    "tag" => {
+                        let action = sc_args
+                            .get("action")
+                            .expect("subcommand was 'tag' but action arg does not exist")
+                            .value
+                            .as_str()
+                            .expect("Could not get action arg as string");
+                        let path = sc_args
+                            .get("path")
+                            .and_then(|arg| arg.value.as_str())
+                            .map(PathBuf::from);
+                        let tag = sc_args
+                            .get("tag")
+                            .and_then(|arg| arg.value.as_str())
+                            .map(str::to_owned);
+
+                        let args = match (action, path, tag) {
+                            ("add", Some(path), Some(tag)) => TagArgs::Add { path, tag },
+                            ("remove", Some(path), Some(tag)) => TagArgs::Remove { path, tag },
+                            ("list", path, _) => TagArgs::List { path },
+                            _ => return Err("tag add and tag remove require a path and a tag".into()),
+                        };
+
+                        #[cfg(windows)]
+                        alloc_attach_console();
+
+                        fetch_cli::tag::tag(args).await?;
+                    },
+                    "topics" => {
+                        let num_topics: usize = sc_args
+                            .get("num_topics")
+                            .and_then(|arg| arg.value.as_str())
+                            .and_then(|s| s.parse().ok())
+                            .unwrap_or(10);
+
+                        let files_per_topic: usize = sc_args
+                            .get("files_per_topic")
+                            .and_then(|arg| arg.value.as_str())
+                            .and_then(|s| s.parse().ok())
+                            .unwrap_or(5);
+
+                        let args = TopicsArgs {
+                            num_topics,
+                            files_per_topic,
+                        };
+
+                        #[cfg(windows)]
+                        alloc_attach_console();
+
+                        fetch_cli::topics::topics(args).await?;
+                    },
                     _ => panic!("Invalid cli subcommand name"),
                 }
                 
@@ -116,7 +409,7 @@ pub fn intercept_cli_command(app_handle: &AppHandle) -> bool {
             match result {
                 Ok(_) => app_handle.exit(0),
                 Err(e) => {
-                    eprintln!("{:?}", e);
+                    error!("{:?}", e);
                     app_handle.exit(1);
                 },
             }
@@ -0,0 +1,50 @@
+use std::{
+    path::PathBuf,
+    sync::{LazyLock, Mutex},
+};
+
+use arboard::{Clipboard, ImageData};
+
+/// Places text on the system clipboard
+pub fn copy_text(text: String) -> Result<(), String> {
+    with_clipboard(|clipboard| clipboard.set_text(text))
+}
+
+/// Places references to the files on the system clipboard, so they can be pasted into a file manager or another app
+/// as the files themselves
+pub fn copy_files(paths: Vec<PathBuf>) -> Result<(), String> {
+    with_clipboard(|clipboard| clipboard.set().file_list(&paths))
+}
+
+/// Places an image on the system clipboard
+///
+/// * `rgba` - Pixels of the image as 8-bit RGBA, row by row
+pub fn copy_image(width: u32, height: u32, rgba: Vec<u8>) -> Result<(), String> {
+    let image = ImageData {
+        width: width as usize,
+        height: height as usize,
+        bytes: rgba.into(),
+    };
+    with_clipboard(|clipboard| clipboard.set_image(image))
+}
+
+// Private functions and variables
+
+/// Kept open for the life of the app, since on Linux clipboard contents are only served while a clipboard is open
+static CLIPBOARD: LazyLock<Mutex<Option<Clipboard>>> = LazyLock::new(|| Mutex::new(None));
+
+fn with_clipboard(
+    f: impl FnOnce(&mut Clipboard) -> Result<(), arboard::Error>,
+) -> Result<(), String> {
+    let mut clipboard = CLIPBOARD
+        .lock()
+        .expect("Clipboard lock should not be poisoned");
+    if clipboard.is_none() {
+        *clipboard =
+            Some(Clipboard::new().map_err(|e| format!("Could not open clipboard, source: {}", e))?);
+    }
+    let clipboard = clipboard
+        .as_mut()
+        .expect("Clipboard should be open, it was just checked");
+    f(clipboard).map_err(|e| format!("Could not write to clipboard, source: {}", e))
+}
@@ -1,6 +1,104 @@
 // Learn more about Tauri commands at https://tauri.app/develop/calling-rust/
+pub mod batch;
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+pub mod clipboard;
+pub mod collection;
+pub mod dashboard;
+pub mod details;
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+pub mod drag;
 pub mod index;
+pub mod models;
 pub mod open;
 pub mod open_location;
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+pub mod open_with;
 pub mod preview;
 pub mod query;
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+pub mod quick_window;
+pub mod settings;
+pub mod summarize;
+pub mod tag;
+pub mod timeline;
+pub mod topics;
+pub mod tray;
+
+/// Collects every command for the invoke handler and the TypeScript bindings. Desktop only commands are passed in,
+/// since collect_commands! does not take cfg attributes.
+macro_rules! collect_app_commands {
+    ($($desktop:tt)*) => {
+        tauri_specta::collect_commands![
+            batch::batch,
+            collection::add_to_collection,
+            collection::collection_items,
+            collection::delete_collection,
+            collection::list_collections,
+            collection::remove_from_collection,
+            dashboard::clear_index_queue,
+            dashboard::index_dashboard,
+            dashboard::pause_indexing,
+            dashboard::resume_indexing,
+            details::get_file_details,
+            index::exclude_from_index,
+            index::include_in_index,
+            index::index,
+            index::list_index_exclusions,
+            models::models_ready,
+            open::open,
+            open_location::open_location,
+            preview::preview,
+            query::query,
+            query::query_incremental,
+            query::query_refine,
+            query::query_related,
+            settings::get_settings,
+            settings::update_settings,
+            summarize::summarize,
+            tag::add_tag,
+            tag::get_tags,
+            tag::remove_tag,
+            timeline::timeline,
+            topics::topics,
+            tray::take_pending_search,
+            $($desktop)*
+        ]
+    };
+}
+
+/// Builder of the invoke handler, which also exports the TypeScript bindings of every command to
+/// src/lib/bindings.ts, see export_bindings
+pub fn builder() -> tauri_specta::Builder<tauri::Wry> {
+    #[cfg(not(any(target_os = "android", target_os = "ios")))]
+    let commands = collect_app_commands![
+        clipboard::copy_file_reference,
+        clipboard::copy_path,
+        clipboard::copy_preview_image,
+        drag::start_file_drag,
+        open_with::list_open_with_handlers,
+        open_with::open_with,
+        quick_window::dismiss_quick_window,
+        quick_window::get_quick_window_pinned,
+        quick_window::set_quick_window_pinned,
+    ];
+    #[cfg(any(target_os = "android", target_os = "ios"))]
+    let commands = collect_app_commands![];
+
+    tauri_specta::Builder::<tauri::Wry>::new().commands(commands)
+}
+
+/// Writes the TypeScript bindings of every command, so the frontend is checked against the commands' arguments,
+/// results and errors. Run on every debug build, the bindings are committed so release builds do not need to.
+#[cfg(debug_assertions)]
+pub fn export_bindings(builder: &tauri_specta::Builder<tauri::Wry>) {
+    use specta_typescript::{BigIntExportBehavior, Typescript};
+
+    builder
+        .export(
+            Typescript::default()
+                .bigint(BigIntExportBehavior::Number)
+                .header("// @ts-nocheck\n// Generated by tauri-specta, do not edit"),
+            "../src/lib/bindings.ts",
+        )
+        .unwrap_or_else(|e| tracing::error!("Could not export TypeScript bindings: {}", e));
+}
@@ -0,0 +1,158 @@
+use std::error::Error;
+
+use camino::Utf8PathBuf;
+use fetch_core::files::batch::{BatchAction, BatchProgress};
+use serde::Serialize;
+use tauri::{AppHandle, Emitter};
+use tracing::error;
+
+use crate::{commands::open::open, error::CommandError, utility::get_file_batch};
+
+const PROGRESS_EVENT_IDENTIFIER: &str = "batch_progress";
+#[derive(Debug, Clone, Serialize)]
+pub struct Progress {
+    pub completed: usize,
+    pub total: usize,
+    pub path: String,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize, specta::Type)]
+pub struct BatchFailure {
+    pub path: String,
+    pub error: String,
+}
+
+#[derive(Debug, Serialize, specta::Type)]
+pub struct BatchSummary {
+    /// Paths of the files the action succeeded for, moved files are listed at their new path
+    pub succeeded: Vec<String>,
+    pub failed: Vec<BatchFailure>,
+}
+
+/// Applies an action to every file given, eg. the results the user has multi-selected, emitting progress events
+/// as each file is processed. The action continues past files it fails for.
+///
+/// * `action` - One of "open", "tag", "add_to_collection", "move" or "reindex"
+/// * `tag` - The tag to add, for "tag"
+/// * `collection` - The collection to save the files into, for "add_to_collection"
+/// * `directory` - The directory to move the files into, for "move"
+/// * `query` - The query the files were found with, recorded when opening files or saving them into a collection
+#[tauri::command]
+#[specta::specta]
+pub async fn batch(
+    app: AppHandle,
+    paths: Vec<String>,
+    action: &str,
+    tag: Option<&str>,
+    collection: Option<&str>,
+    directory: Option<&str>,
+    query: Option<&str>,
+) -> Result<BatchSummary, CommandError> {
+    let action = match action {
+        "open" => return open_all(&app, paths, query).await,
+        "tag" => BatchAction::Tag {
+            tag: tag
+                .ok_or_else(|| CommandError::invalid_argument("A tag is required to tag files"))?
+                .to_owned(),
+        },
+        "add_to_collection" => BatchAction::AddToCollection {
+            collection: collection
+                .ok_or_else(|| {
+                    CommandError::invalid_argument(
+                        "A collection is required to add files to a collection",
+                    )
+                })?
+                .to_owned(),
+            query: query.map(str::to_owned),
+        },
+        "move" => BatchAction::MoveTo {
+            directory: Utf8PathBuf::from(directory.ok_or_else(|| {
+                CommandError::invalid_argument("A directory is required to move files")
+            })?),
+        },
+        "reindex" => BatchAction::Reindex,
+        other => {
+            return Err(CommandError::invalid_argument(format!(
+                "Unknown batch action: {}",
+                other
+            )))
+        }
+    };
+
+    let file_batch = get_file_batch().await?;
+    let paths: Vec<Utf8PathBuf> = paths.into_iter().map(Utf8PathBuf::from).collect();
+    let result = file_batch
+        .run(&paths, &action, |progress: BatchProgress| {
+            emit_progress(
+                &app,
+                Progress {
+                    completed: progress.completed,
+                    total: progress.total,
+                    path: progress.path.to_string(),
+                    error: progress.error.map(format_error),
+                },
+            )
+        })
+        .await;
+
+    Ok(BatchSummary {
+        succeeded: result.succeeded.into_iter().map(String::from).collect(),
+        failed: result
+            .failed
+            .into_iter()
+            .map(|(path, e)| BatchFailure {
+                path: path.to_string(),
+                error: format_error(&e),
+            })
+            .collect(),
+    })
+}
+
+// Private functions
+
+/// Opening files is done by the desktop rather than fetch-core, so it is handled separately from the other actions
+async fn open_all(
+    app: &AppHandle,
+    paths: Vec<String>,
+    query: Option<&str>,
+) -> Result<BatchSummary, CommandError> {
+    let total = paths.len();
+    let mut summary = BatchSummary {
+        succeeded: vec![],
+        failed: vec![],
+    };
+    for (i, path) in paths.into_iter().enumerate() {
+        let result = open(&path, query).await;
+        emit_progress(
+            app,
+            Progress {
+                completed: i + 1,
+                total,
+                path: path.clone(),
+                error: result.as_ref().err().map(|e| e.message.clone()),
+            },
+        );
+        match result {
+            Ok(()) => summary.succeeded.push(path),
+            Err(e) => summary.failed.push(BatchFailure {
+                path,
+                error: e.message,
+            }),
+        }
+    }
+    Ok(summary)
+}
+
+fn emit_progress(app: &AppHandle, progress: Progress) {
+    app.emit_to("full", PROGRESS_EVENT_IDENTIFIER, progress)
+        .unwrap_or_else(|e: tauri::Error| error!("Could not emit batch progress event: {}", e));
+}
+
+fn format_error(e: &(impl Error + ?Sized)) -> String {
+    format!(
+        "{}, source: {}",
+        e,
+        e.source().map(<dyn Error>::to_string).unwrap_or_default()
+    )
+}
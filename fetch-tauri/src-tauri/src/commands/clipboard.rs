@@ -0,0 +1,51 @@
+use std::path::PathBuf;
+
+use camino::Utf8Path;
+use fetch_core::previewable::PreviewSize;
+
+use crate::{
+    clipboard::{copy_files, copy_image, copy_text},
+    commands::preview::PREVIEW_CACHE,
+    error::{CommandError, CommandErrorCode},
+};
+
+/// Copies the path of the file to the clipboard as text
+#[tauri::command]
+#[specta::specta]
+pub async fn copy_path(path: &str) -> Result<(), CommandError> {
+    copy_text(path.to_owned()).map_err(CommandError::platform)
+}
+
+/// Copies the file itself to the clipboard, so it can be pasted into a file manager or another app
+#[tauri::command]
+#[specta::specta]
+pub async fn copy_file_reference(path: &str) -> Result<(), CommandError> {
+    copy_files(vec![PathBuf::from(path)]).map_err(CommandError::platform)
+}
+
+/// Copies the detail preview of the file to the clipboard as an image
+#[tauri::command]
+#[specta::specta]
+pub async fn copy_preview_image(path: &str) -> Result<(), CommandError> {
+    let preview = match PREVIEW_CACHE
+        .get(Utf8Path::new(path), PreviewSize::Detail)
+        .await
+    {
+        Ok(Some(preview)) => preview,
+        Ok(None) => {
+            return Err(CommandError::invalid_argument(format!(
+                "No preview can be generated for {}",
+                path
+            )))
+        }
+        Err(e) => {
+            return Err(CommandError::from_error(
+                CommandErrorCode::Index,
+                "Error while getting preview",
+                &e,
+            ))
+        }
+    };
+    let rgba = preview.image.to_rgba8();
+    copy_image(rgba.width(), rgba.height(), rgba.into_raw()).map_err(CommandError::platform)
+}
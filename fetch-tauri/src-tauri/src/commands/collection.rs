@@ -0,0 +1,88 @@
+use camino::Utf8Path;
+use fetch_core::files::collection::CollectionError;
+use serde::Serialize;
+
+use crate::{error::CommandError, utility::get_file_collections};
+
+#[derive(Debug, Serialize, specta::Type)]
+pub struct Collection {
+    pub name: String,
+    pub num_items: usize,
+}
+
+#[derive(Debug, Serialize, specta::Type)]
+pub struct CollectionItem {
+    pub name: String,
+    pub path: String,
+    pub query: Option<String>,
+}
+
+#[tauri::command]
+#[specta::specta]
+pub async fn list_collections() -> Result<Vec<Collection>, CommandError> {
+    let collections = get_file_collections().await?;
+    collections
+        .list()
+        .await
+        .map(|summaries| {
+            summaries
+                .into_iter()
+                .map(|summary| Collection {
+                    name: summary.name,
+                    num_items: summary.num_items,
+                })
+                .collect()
+        })
+        .map_err(format_error)
+}
+
+#[tauri::command]
+#[specta::specta]
+pub async fn collection_items(collection: &str) -> Result<Vec<CollectionItem>, CommandError> {
+    let collections = get_file_collections().await?;
+    collections
+        .items(collection)
+        .await
+        .map(|items| {
+            items
+                .into_iter()
+                .map(|item| CollectionItem {
+                    name: item.file.file_name().unwrap_or(item.file.as_str()).to_string(),
+                    path: item.file.to_string(),
+                    query: item.query,
+                })
+                .collect()
+        })
+        .map_err(format_error)
+}
+
+#[tauri::command]
+#[specta::specta]
+pub async fn add_to_collection(collection: &str, path: &str, query: Option<&str>) -> Result<(), CommandError> {
+    let collections = get_file_collections().await?;
+    collections
+        .add(collection, Utf8Path::new(path), query)
+        .await
+        .map_err(format_error)
+}
+
+#[tauri::command]
+#[specta::specta]
+pub async fn remove_from_collection(collection: &str, path: &str) -> Result<(), CommandError> {
+    let collections = get_file_collections().await?;
+    collections
+        .remove(collection, Utf8Path::new(path))
+        .await
+        .map_err(format_error)
+}
+
+#[tauri::command]
+#[specta::specta]
+pub async fn delete_collection(collection: &str) -> Result<(), CommandError> {
+    let collections = get_file_collections().await?;
+    collections.delete(collection).await.map_err(format_error)
+}
+
+fn format_error(e: CollectionError) -> CommandError {
+    CommandError::index(&e)
+}
@@ -0,0 +1,102 @@
+use chrono::{DateTime, Local, Utc};
+use fetch_core::{
+    app_config::{get_default_chunk_directory, get_default_index_directory},
+    files::{
+        disk_space::{directory_size, free_space},
+        schedule::JobScheduler,
+    },
+};
+use serde::Serialize;
+use tracing::warn;
+
+use crate::{
+    error::{CommandError, CommandErrorCode},
+    index_status::{self, IndexStatus},
+};
+
+#[derive(Debug, Clone, Serialize, specta::Type)]
+pub struct IndexDashboard {
+    pub status: IndexStatus,
+    /// Disk space used by the index directory, in bytes
+    pub index_directory_bytes: u64,
+    /// Disk space used by the chunk directory, in bytes
+    pub chunk_directory_bytes: u64,
+    /// Free space on the disk of the index directory, in bytes, if it could be read
+    pub free_bytes: Option<u64>,
+    /// Jobs the file daemon runs on a schedule, see scheduled_jobs in daemon.toml
+    pub scheduled_jobs: Vec<ScheduledJobStatus>,
+}
+
+#[derive(Debug, Clone, Serialize, specta::Type)]
+pub struct ScheduledJobStatus {
+    pub job: String,
+    pub schedule: String,
+    /// When the job runs next, or None if its schedule never comes up
+    pub next_run: Option<DateTime<Utc>>,
+}
+
+/// Gets the indexing queue, provider throughput, recent errors, disk usage and scheduled jobs shown on the indexing
+/// dashboard
+#[tauri::command]
+#[specta::specta]
+pub async fn index_dashboard() -> Result<IndexDashboard, CommandError> {
+    let (index_directory_bytes, chunk_directory_bytes, free_bytes) = tokio::task::spawn_blocking(|| {
+        let index_directory = get_default_index_directory();
+        (
+            directory_size(&index_directory),
+            directory_size(&get_default_chunk_directory()),
+            free_space(&index_directory),
+        )
+    })
+    .await
+    .map_err(|e| {
+        CommandError::from_error(
+            CommandErrorCode::Platform,
+            "Could not measure disk usage",
+            &e,
+        )
+    })?;
+
+    Ok(IndexDashboard {
+        status: index_status::status(),
+        index_directory_bytes,
+        chunk_directory_bytes,
+        free_bytes,
+        scheduled_jobs: scheduled_jobs(),
+    })
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn pause_indexing() {
+    index_status::set_paused(true);
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn resume_indexing() {
+    index_status::set_paused(false);
+}
+
+/// Drops every file waiting to be indexed. The file currently being indexed is finished.
+#[tauri::command]
+#[specta::specta]
+pub fn clear_index_queue() {
+    index_status::clear_queue();
+}
+
+fn scheduled_jobs() -> Vec<ScheduledJobStatus> {
+    let scheduler = JobScheduler::from_settings().unwrap_or_else(|e| {
+        warn!("Could not load scheduled jobs: {:?}", e);
+        JobScheduler::default()
+    });
+    scheduler
+        .next_runs(Local::now())
+        .into_iter()
+        .map(|(job, next_run)| ScheduledJobStatus {
+            job: job.kind.to_string(),
+            schedule: job.schedule.expression().to_owned(),
+            next_run: next_run.map(|next_run| next_run.with_timezone(&Utc)),
+        })
+        .collect()
+}
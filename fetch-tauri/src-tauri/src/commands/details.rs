@@ -0,0 +1,74 @@
+use camino::Utf8Path;
+use fetch_core::index::ChunkType;
+use serde::Serialize;
+
+use crate::{error::CommandError, utility::get_file_inspector};
+
+#[derive(Debug, Serialize, specta::Type)]
+pub struct FileDetails {
+    pub name: String,
+    pub path: String,
+    pub size: u64,
+    /// RFC 3339 creation date of the file
+    pub created: String,
+    /// RFC 3339 modified date of the file
+    pub modified: String,
+    pub tags: Vec<String>,
+    pub matched_chunks: Vec<MatchedChunk>,
+}
+
+#[derive(Debug, Serialize, specta::Type)]
+pub struct MatchedChunk {
+    pub channel: String,
+    pub sequence_id: f32,
+    /// "text", "image", "video" or "audio"
+    pub chunk_type: &'static str,
+    pub chunkfile: String,
+    pub similarity: Option<f32>,
+    pub snippet: Option<String>,
+}
+
+/// Returns the indexed metadata of a file for the inspector, along with the chunks of the file that best match
+/// the query if one is given.
+#[tauri::command]
+#[specta::specta]
+pub async fn get_file_details(
+    path: &str,
+    query: Option<&str>,
+) -> Result<FileDetails, CommandError> {
+    let inspector = get_file_inspector().await?;
+
+    inspector
+        .details(Utf8Path::new(path), query)
+        .await
+        .map(|details| FileDetails {
+            name: details
+                .path
+                .file_name()
+                .expect("Indexed path should have a name")
+                .to_string(),
+            path: details.path.to_string(),
+            size: details.size,
+            created: details.created.to_rfc3339(),
+            modified: details.modified.to_rfc3339(),
+            tags: details.user_tags,
+            matched_chunks: details
+                .matched_chunks
+                .into_iter()
+                .map(|chunk| MatchedChunk {
+                    channel: chunk.channel,
+                    sequence_id: chunk.sequence_id,
+                    chunk_type: match chunk.chunk_type {
+                        ChunkType::Text => "text",
+                        ChunkType::Image => "image",
+                        ChunkType::Video => "video",
+                        ChunkType::Audio => "audio",
+                    },
+                    chunkfile: chunk.chunkfile.to_string(),
+                    similarity: chunk.similarity,
+                    snippet: chunk.snippet,
+                })
+                .collect(),
+        })
+        .map_err(|e| CommandError::index(&e))
+}
@@ -0,0 +1,21 @@
+use std::path::PathBuf;
+
+use tauri::WebviewWindow;
+
+use crate::{drag::drag_file, error::CommandError};
+
+/// Drags the file out of the window as a real file, eg. into Finder or Explorer. Resolves once the drag ends, with
+/// whether the file was dropped somewhere.
+///
+/// * `icon_path` - Optional image shown under the cursor, eg. the file's preview
+#[tauri::command]
+#[specta::specta]
+pub async fn start_file_drag(
+    window: WebviewWindow,
+    path: &str,
+    icon_path: Option<&str>,
+) -> Result<bool, CommandError> {
+    drag_file(&window, PathBuf::from(path), icon_path.map(PathBuf::from))
+        .await
+        .map_err(CommandError::platform)
+}
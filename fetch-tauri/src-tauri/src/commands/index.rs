@@ -1,12 +1,35 @@
-use std::{collections::HashSet, error::Error};
+use std::{
+    collections::HashSet,
+    error::Error,
+    time::{Duration, Instant},
+};
 
-use camino::Utf8PathBuf;
+use camino::{Utf8Path, Utf8PathBuf};
 use chrono::Utc;
-use fetch_core::files::index::{FileIndexingResultType, IndexFiles};
+use fetch_core::{
+    app_config,
+    files::{
+        disk_space::{DiskSpace, DiskSpaceGuard},
+        exclusions::IndexExclusions,
+        index::{FileIndexingResultType, IndexFiles},
+        links::{LinkResolver, ResolvedPath, SymlinkPolicy},
+        lock::{IndexLock, IndexLockError},
+        os_path::{from_os_path, to_os_path},
+        queue::IndexQueue,
+    },
+};
 use serde::Serialize;
 use tauri::{AppHandle, Emitter};
+use tracing::{error, warn};
 
-use crate::utility::get_file_indexer;
+use crate::{
+    error::{CommandError, CommandErrorCode},
+    index_status::{self, LowDiskSpace},
+    utility::get_file_indexer,
+};
+
+/// How long to wait for another process to finish writing to the index before queuing files for the daemon instead
+const INDEX_LOCK_TIMEOUT: Duration = Duration::from_secs(10);
 
 const PROGRESS_EVENT_IDENTIFIER: &str = "index_progress";
 #[derive(Debug, Clone, Serialize)]
@@ -22,7 +45,8 @@ pub struct Log {
 }
 
 #[tauri::command]
-pub async fn index(app: AppHandle, paths: Vec<String>) -> Result<(), String> {
+#[specta::specta]
+pub async fn index(app: AppHandle, paths: Vec<String>) -> Result<(), CommandError> {
     let file_indexer = get_file_indexer().await?;
 
     let utf8_paths: Vec<Utf8PathBuf> = paths.into_iter().map(Utf8PathBuf::from).collect();
@@ -33,10 +57,47 @@ pub async fn index(app: AppHandle, paths: Vec<String>) -> Result<(), String> {
             message: format!("Exploring paths to find files to index..."),
         },
     )
-    .unwrap_or_else(|e: tauri::Error| eprintln!("Could not emit log event: {}", e));
+    .unwrap_or_else(|e: tauri::Error| error!("Could not emit log event: {}", e));
     let unique_files = explore_paths(utf8_paths);
 
+    // Only one process writes to the index at a time, files are handed to the daemon if another one is
+    let data_dir = app_config::get_default_index_directory();
+    let _index_lock =
+        match IndexLock::acquire(&data_dir, "Fetch app", Some(INDEX_LOCK_TIMEOUT)).await {
+            Ok(index_lock) => index_lock,
+            Err(IndexLockError::Busy { holder }) => {
+                let queued = IndexQueue::enqueue(unique_files)
+                    .map_err(|e| {
+                        CommandError::new(
+                            CommandErrorCode::Index,
+                            format!("Could not queue files for the file daemon: {}", e),
+                        )
+                    })?;
+                app.emit_to(
+                    "full",
+                    LOG_EVENT_IDENTIFIER,
+                    Log {
+                        message: format!(
+                            "Index is busy, it is being written to by {}. Queued {} file(s) to be \
+                            indexed by the file daemon once it is free.",
+                            holder, queued
+                        ),
+                    },
+                )
+                .unwrap_or_else(|e: tauri::Error| error!("Could not emit log event: {}", e));
+                return Ok(());
+            },
+            Err(e) => {
+                return Err(CommandError::from_error(
+                    CommandErrorCode::Index,
+                    "Could not lock index",
+                    &e,
+                ))
+            }
+        };
+
     let num_files = unique_files.len();
+    index_status::enqueue(&unique_files);
     app.emit_to(
         "full",
         PROGRESS_EVENT_IDENTIFIER,
@@ -45,9 +106,53 @@ pub async fn index(app: AppHandle, paths: Vec<String>) -> Result<(), String> {
             total: num_files as f32,
         },
     )
-    .unwrap_or_else(|e: tauri::Error| eprintln!("Could not emit progress event: {}", e));
+    .unwrap_or_else(|e: tauri::Error| error!("Could not emit progress event: {}", e));
 
+    let disk_space = DiskSpaceGuard::from_settings();
     for (i, path) in unique_files.iter().map(Utf8PathBuf::as_path).enumerate() {
+        // Checked before each file is chunked and embedded, so the index does not fill the disk up
+        if let DiskSpace::Low { directory, free_bytes, min_free_bytes } = disk_space.check() {
+            app.emit_to(
+                "full",
+                LOG_EVENT_IDENTIFIER,
+                Log {
+                    message: format!(
+                        "Low disk space, only {} MB free for {}. Indexing paused, free up space and resume \
+                        it from the indexing dashboard.",
+                        free_bytes / (1024 * 1024),
+                        directory
+                    ),
+                },
+            )
+            .unwrap_or_else(|e: tauri::Error| error!("Could not emit log event: {}", e));
+            index_status::pause_for_low_disk_space(LowDiskSpace { directory, free_bytes, min_free_bytes });
+        }
+
+        if index_status::is_suspended() {
+            app.emit_to(
+                "full",
+                LOG_EVENT_IDENTIFIER,
+                Log {
+                    message: "Indexing paused or privacy mode on, waiting to resume...".to_string(),
+                },
+            )
+            .unwrap_or_else(|e: tauri::Error| error!("Could not emit log event: {}", e));
+            index_status::wait_while_suspended().await;
+        }
+
+        // Clearing the queue drops every file that has not started indexing yet
+        if !index_status::start(path) {
+            app.emit_to(
+                "full",
+                LOG_EVENT_IDENTIFIER,
+                Log {
+                    message: "Indexing queue was cleared, stopping.".to_string(),
+                },
+            )
+            .unwrap_or_else(|e: tauri::Error| error!("Could not emit log event: {}", e));
+            break;
+        }
+
         app.emit_to(
             "full",
             LOG_EVENT_IDENTIFIER,
@@ -55,12 +160,15 @@ pub async fn index(app: AppHandle, paths: Vec<String>) -> Result<(), String> {
                 message: format!("Indexing file: {}", path),
             },
         )
-        .unwrap_or_else(|e: tauri::Error| eprintln!("Could not emit log event: {}", e));
+        .unwrap_or_else(|e: tauri::Error| error!("Could not emit log event: {}", e));
 
+        let started = Instant::now();
+        let providers = file_indexer.providers_for(path);
         match file_indexer.index(path, Some(Utc::now())).await {
             Ok(res) => {
                 match res.r#type {
                     FileIndexingResultType::Skipped { reason } => {
+                        index_status::finish(path, &[], &[], started.elapsed(), None);
                         app.emit_to(
                             "full",
                             LOG_EVENT_IDENTIFIER,
@@ -72,12 +180,54 @@ pub async fn index(app: AppHandle, paths: Vec<String>) -> Result<(), String> {
                                 )
                             },
                         )
-                        .unwrap_or_else(|e: tauri::Error| eprintln!("Could not emit log event: {}", e));
+                        .unwrap_or_else(|e: tauri::Error| error!("Could not emit log event: {}", e));
                     },
-                    _ => {},
+                    FileIndexingResultType::PartiallyIndexed { indexed_providers, provider_errors } => {
+                        index_status::finish(
+                            path,
+                            &indexed_providers.iter().map(String::as_str).collect::<Vec<_>>(),
+                            &provider_errors.keys().map(String::as_str).collect::<Vec<_>>(),
+                            started.elapsed(),
+                            Some(
+                                provider_errors
+                                    .iter()
+                                    .map(|(name, e)| format!("{}: {}", name, e))
+                                    .collect::<Vec<_>>()
+                                    .join(", "),
+                            ),
+                        );
+                        app.emit_to(
+                            "full",
+                            LOG_EVENT_IDENTIFIER,
+                            Log {
+                                message: format!(
+                                    "File partially indexed {}, succeeded: {:?}, failed: {:?}\nContinuing...",
+                                    path,
+                                    indexed_providers,
+                                    provider_errors
+                                        .iter()
+                                        .map(|(name, e)| format!("{}: {}", name, e))
+                                        .collect::<Vec<_>>(),
+                                )
+                            },
+                        )
+                        .unwrap_or_else(|e: tauri::Error| error!("Could not emit log event: {}", e));
+                    },
+                    _ => index_status::finish(path, &providers, &[], started.elapsed(), None),
                 }
             },
             Err(e) => {
+                index_status::finish(
+                    path,
+                    &[],
+                    &providers,
+                    started.elapsed(),
+                    Some(format!(
+                        "{}, source: {}",
+                        e,
+                        e.source().map(<dyn Error>::to_string).unwrap_or_default()
+                    )),
+                );
                 app.emit_to(
                     "full",
                     LOG_EVENT_IDENTIFIER,
@@ -91,7 +241,7 @@ pub async fn index(app: AppHandle, paths: Vec<String>) -> Result<(), String> {
                         )
                     },
                 )
-                .unwrap_or_else(|e: tauri::Error| eprintln!("Could not emit log event: {}", e));
+                .unwrap_or_else(|e: tauri::Error| error!("Could not emit log event: {}", e));
             },
         }
 
@@ -103,7 +253,7 @@ pub async fn index(app: AppHandle, paths: Vec<String>) -> Result<(), String> {
                 total: num_files as f32,
             },
         )
-        .unwrap_or_else(|e: tauri::Error| eprintln!("Could not emit progress event: {}", e));
+        .unwrap_or_else(|e: tauri::Error| error!("Could not emit progress event: {}", e));
     }
 
     app.emit_to(
@@ -113,7 +263,64 @@ pub async fn index(app: AppHandle, paths: Vec<String>) -> Result<(), String> {
             message: "All done! Goodbye.".to_string(),
         },
     )
-    .unwrap_or_else(|e: tauri::Error| eprintln!("Could not emit log event: {}", e));
+    .unwrap_or_else(|e: tauri::Error| error!("Could not emit log event: {}", e));
+
+    Ok(())
+}
+
+/// Excludes a file from the index, clearing it from the index and skipping it when indexing in the future
+#[tauri::command]
+#[specta::specta]
+pub async fn exclude_from_index(path: &str) -> Result<(), CommandError> {
+    let path = Utf8Path::new(path);
+    let mut exclusions = IndexExclusions::load()
+        .map_err(|e| CommandError::settings("Could not load index exclusions", e))?;
+    if exclusions.add(path) {
+        exclusions
+            .save()
+            .map_err(|e| CommandError::settings("Could not save index exclusions", e))?;
+    }
+
+    let file_indexer = get_file_indexer().await?;
+    file_indexer
+        .clear(path, None)
+        .await
+        .map_err(|e| CommandError::index(&e))?;
+
+    Ok(())
+}
+
+/// Lists the files and directories excluded from the index, in alphabetical order
+#[tauri::command]
+#[specta::specta]
+pub async fn list_index_exclusions() -> Result<Vec<String>, CommandError> {
+    let exclusions = IndexExclusions::load()
+        .map_err(|e| CommandError::settings("Could not load index exclusions", e))?;
+    Ok(exclusions.paths().into_iter().map(Utf8PathBuf::into_string).collect())
+}
+
+/// Includes a previously excluded file or directory in the index again. Excluded files are indexed again right
+/// away, while files under excluded directories are indexed the next time their directory is.
+#[tauri::command]
+#[specta::specta]
+pub async fn include_in_index(path: &str) -> Result<(), CommandError> {
+    let path = Utf8Path::new(path);
+    let mut exclusions = IndexExclusions::load()
+        .map_err(|e| CommandError::settings("Could not load index exclusions", e))?;
+    if !exclusions.remove(path) {
+        return Ok(());
+    }
+    exclusions
+        .save()
+        .map_err(|e| CommandError::settings("Could not save index exclusions", e))?;
+
+    if path.is_file() {
+        let file_indexer = get_file_indexer().await?;
+        file_indexer
+            .index(path, Some(Utc::now()))
+            .await
+            .map_err(|e| CommandError::index(&e))?;
+    }
 
     Ok(())
 }
@@ -121,49 +328,49 @@ pub async fn index(app: AppHandle, paths: Vec<String>) -> Result<(), String> {
 // Private functions
 
 /// Expands the paths given, returning all files and files found while exploring directories.
-/// Ignores non-existant paths
+/// Ignores non-existant paths. Symlinks are handled according to the symlink policy, and files and
+/// directories reached through more than one path are only returned or explored once.
 fn explore_paths(paths: Vec<Utf8PathBuf>) -> Vec<Utf8PathBuf> {
-    let mut seen: HashSet<Utf8PathBuf> = HashSet::new();
+    let symlink_policy = SymlinkPolicy::from_settings().unwrap_or_else(|e| {
+        warn!("Could not load symlink policy, following symlinks: {:?}", e);
+        SymlinkPolicy::default()
+    });
+    let mut link_resolver = LinkResolver::using(symlink_policy);
     let mut files: HashSet<Utf8PathBuf> = HashSet::new();
     let mut queue = paths;
     while let Some(path) = queue.pop() {
-        if seen.contains(&path) {
-            eprintln!("Warning: Circled back to folder that was already seen before. Maybe there is a symlink creating a circular
-                directory structure somewhere? Folder: {}", path);
+        let os_path = to_os_path(&path);
+        if !os_path.exists() && !os_path.is_symlink() {
+            warn!("Path does not exist, ignoring: {}", path);
             continue;
         }
 
-        if path.is_file() {
-            files.insert(path.clone());
-        } else if path.is_dir() {
-            for entry_result in path
-                .read_dir()
-                .unwrap_or_else(|_| panic!("failed reading directory: {}", path))
-            {
-                match entry_result {
-                    Ok(entry) => {
-                        let convert_result = Utf8PathBuf::try_from(entry.path());
-                        match convert_result {
-                            Err(e) => {
-                                eprintln!("Warning: could not convert pathbuf to utf8pathbuf, ignoring path: {}, error: {e:?}",
-                                    entry.path().to_string_lossy());
-                                continue;
-                            }
-                            Ok(entry_path) => {
-                                queue.push(entry_path);
-                            }
-                        }
+        match link_resolver.resolve(&path) {
+            ResolvedPath::File(file) => {
+                files.insert(file);
+            }
+            ResolvedPath::Directory(directory) => {
+                for entry_result in to_os_path(&directory)
+                    .read_dir()
+                    .unwrap_or_else(|_| panic!("failed reading directory: {}", directory))
+                {
+                    match entry_result {
+                        // Paths that are not valid UTF-8 are escaped, see os_path::from_os_path
+                        Ok(entry) => queue.push(from_os_path(&entry.path())),
+                        Err(e) => panic!("Issue reading directory entry: {e:?}"),
                     }
-                    Err(e) => panic!("Issue reading directory entry: {e:?}"),
                 }
             }
-        } else {
-            println!(
-                "Warning: path is neither a file nor a directory, ignoring: {}",
-                path
-            );
+            ResolvedPath::Skip { reason } => {
+                warn!("{}, ignoring: {}", reason, path);
+            }
         }
-        seen.insert(path);
+    }
+    if let Err(e) = link_resolver.record_aliases() {
+        warn!(
+            "Could not record the names of symlinks as tags of their targets: {:?}",
+            e
+        );
     }
     files.into_iter().collect()
 }
@@ -0,0 +1,15 @@
+use tauri::AppHandle;
+
+use crate::warmup;
+
+/// Whether the models used for querying are loaded. If they are not, they are warmed up and a models_ready event
+/// is sent once they are.
+#[tauri::command]
+#[specta::specta]
+pub fn models_ready(app: AppHandle) -> bool {
+    let ready = warmup::models_ready();
+    if !ready {
+        warmup::warm_in_background(&app);
+    }
+    ready
+}
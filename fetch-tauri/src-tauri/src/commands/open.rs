@@ -1,46 +1,50 @@
-use std::error::Error;
-use std::process::{Command, Stdio};
-
 use camino::Utf8Path;
+use fetch_core::files::{affinity::DirectoryAffinity, desktop::open_with_default_app, feedback::ClickFeedback, privacy::PrivacyMode, query::RankingBoosts};
+use tracing::warn;
+
+use crate::{
+    error::{CommandError, CommandErrorCode},
+    tray_status,
+};
 
 #[tauri::command]
-pub async fn open(path: &str) -> Result<(), String> {
+#[specta::specta]
+pub async fn open(path: &str, query: Option<&str>) -> Result<(), CommandError> {
     let path = Utf8Path::new(path);
-    open_file_with_default_app(path).map_err(|e| {
-        format!(
-            "{}, source: {}",
-            e,
-            e.source().map(<dyn Error>::to_string).unwrap_or_default()
-        )
-    })
-}
+    open_with_default_app(path)
+        .map_err(|e| CommandError::from_error(CommandErrorCode::Platform, "", &e))?;
 
-// Private functions
-
-fn open_file_with_default_app(path: &Utf8Path) -> Result<(), Box<dyn Error>> {
-    #[cfg(target_os = "windows")]
-    Command::new("cmd")
-        .args(["/c", "start", "", &path.to_string()])
-        .stdin(Stdio::null())
-        .stdout(Stdio::null())
-        .stderr(Stdio::null())
-        .spawn()?;
-
-    #[cfg(target_os = "macos")]
-    Command::new("open")
-        .arg(path)
-        .stdin(Stdio::null())
-        .stdout(Stdio::null())
-        .stderr(Stdio::null())
-        .spawn()?;
-
-    #[cfg(target_os = "linux")]
-    Command::new("xdg-open")
-        .arg(path)
-        .stdin(Stdio::null())
-        .stdout(Stdio::null())
-        .stderr(Stdio::null())
-        .spawn()?;
+    record_open(path, query);
 
     Ok(())
 }
+
+/// Records that the file was opened from a search, for ranking future searches. Failing to record the open does
+/// not fail opening the file, since the file was already opened.
+pub(crate) fn record_open(path: &Utf8Path, query: Option<&str>) {
+    // Nothing is recorded while privacy mode is on
+    if PrivacyMode::enabled() {
+        return;
+    }
+
+    // Track which directories files are opened from, for directory affinity boosting
+    DirectoryAffinity::load()
+        .and_then(|mut affinity| affinity.record_open(path).save())
+        .unwrap_or_else(|e| warn!("Could not record file open in open history: {:?}", e));
+
+    // Searches that found something are listed in the tray
+    if let Some(query) = query {
+        tray_status::record_search(query);
+    }
+
+    // Click feedback is opt-in, so only record which result was chosen for the query if it is enabled
+    if let Some(query) = query {
+        match RankingBoosts::feedback_recording_enabled() {
+            Ok(true) => ClickFeedback::load()
+                .and_then(|mut feedback| feedback.record_click(query, path).save())
+                .unwrap_or_else(|e| warn!("Could not record click feedback: {:?}", e)),
+            Ok(false) => {},
+            Err(e) => warn!("Could not determine if click feedback is enabled: {:?}", e),
+        }
+    }
+}
@@ -1,51 +1,13 @@
-use std::error::Error;
-#[cfg(target_os = "windows")]
-use std::os::windows::process::CommandExt;
-use std::process::{Command, Stdio};
-
 use camino::Utf8Path;
+use fetch_core::files::desktop::reveal_in_file_manager;
+
+use crate::error::{CommandError, CommandErrorCode};
 
+/// Shows the folder the file is in, with the file selected, see desktop::reveal_in_file_manager
 #[tauri::command]
-pub async fn open_location(path: &str) -> Result<(), String> {
+#[specta::specta]
+pub async fn open_location(path: &str) -> Result<(), CommandError> {
     let path = Utf8Path::new(path);
-    show_file_location(path).map_err(|e| {
-        format!(
-            "{}, source: {}",
-            e,
-            e.source().map(<dyn Error>::to_string).unwrap_or_default()
-        )
-    })
-}
-
-// Private functions
-
-fn show_file_location(path: &Utf8Path) -> Result<(), Box<dyn Error>> {
-    #[cfg(target_os = "windows")]
-    Command::new("explorer.exe")
-        .raw_arg(format!("/select,{}", path.to_string()))
-        .stdin(Stdio::null())
-        .stdout(Stdio::null())
-        .stderr(Stdio::null())
-        .spawn()?;
-
-    #[cfg(target_os = "macos")]
-    Command::new("open")
-        .arg("-R")
-        .arg(path)
-        .stdin(Stdio::null())
-        .stdout(Stdio::null())
-        .stderr(Stdio::null())
-        .spawn()?;
-
-    #[cfg(target_os = "linux")]
-    // TODO: use dbus-send?
-    Command::new("nautilus")
-        .arg("--select")
-        .arg(path)
-        .stdin(Stdio::null())
-        .stdout(Stdio::null())
-        .stderr(Stdio::null())
-        .spawn()?;
-
-    Ok(())
+    reveal_in_file_manager(path)
+        .map_err(|e| CommandError::from_error(CommandErrorCode::Platform, "", &e))
 }
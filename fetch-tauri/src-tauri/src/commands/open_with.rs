@@ -0,0 +1,46 @@
+use camino::Utf8Path;
+use fetch_core::files::os_path::to_os_path;
+use tauri::async_runtime::spawn_blocking;
+
+use crate::{
+    commands::open::record_open,
+    error::{CommandError, CommandErrorCode},
+    open_with::{handlers_for, open_with as open_file_with, OpenWithHandler},
+};
+
+/// Lists the applications registered to open the file, for an "Open with..." menu
+#[tauri::command]
+#[specta::specta]
+pub async fn list_open_with_handlers(path: &str) -> Result<Vec<OpenWithHandler>, CommandError> {
+    let path = Utf8Path::new(path).to_owned();
+    // Enumerating handlers reads the OS's app registrations, which can block for a while
+    spawn_blocking(move || {
+        handlers_for(&to_os_path(&path))
+            .map_err(|e| CommandError::from_error(CommandErrorCode::Platform, "", &*e))
+    })
+    .await
+    .map_err(|e| {
+        CommandError::from_error(
+            CommandErrorCode::Platform,
+            "Could not list applications",
+            &e,
+        )
+    })?
+}
+
+/// Opens the file with one of the applications from list_open_with_handlers
+#[tauri::command]
+#[specta::specta]
+pub async fn open_with(
+    path: &str,
+    handler_id: &str,
+    query: Option<&str>,
+) -> Result<(), CommandError> {
+    let path = Utf8Path::new(path);
+    open_file_with(&to_os_path(path), handler_id)
+        .map_err(|e| CommandError::from_error(CommandErrorCode::Platform, "", &*e))?;
+
+    record_open(path, query);
+
+    Ok(())
+}
@@ -1,12 +1,28 @@
+use std::sync::LazyLock;
+
 use camino::Utf8Path;
-use fetch_core::previewable::PossiblyPreviewable;
+use fetch_core::previewable::{PreviewSize, preview_cache::PreviewCache};
+
+use crate::error::{CommandError, CommandErrorCode};
+
+// Shared between calls so recently shown previews are served from memory
+pub(crate) static PREVIEW_CACHE: LazyLock<PreviewCache> = LazyLock::new(PreviewCache::new);
 
+/// Returns the path of the preview of the file, or None if no preview can be generated for it.
+///
+/// * `size` - "thumbnail" for result grids or "detail" for showing a single file, defaults to "thumbnail"
 #[tauri::command]
-pub async fn preview(path: &str) -> Result<Option<String>, String> {
+#[specta::specta]
+pub async fn preview(path: &str, size: Option<&str>) -> Result<Option<String>, CommandError> {
+    let size = match size {
+        None | Some("thumbnail") => PreviewSize::Thumbnail,
+        Some("detail") => PreviewSize::Detail,
+        Some(other) => return Err(CommandError::invalid_argument(format!("Unknown preview size: {}", other))),
+    };
     let path = Utf8Path::new(path);
-    match path.preview().await {
-        Ok(Some(previewed_file)) => Ok(Some(previewed_file.preview_path.to_string())),
+    match PREVIEW_CACHE.get(path, size).await {
+        Ok(Some(preview)) => Ok(Some(preview.preview_path.to_string())),
         Ok(None) => Ok(None),
-        Err(e) => Err(format!("Error while getting preview: {}", e)),
+        Err(e) => Err(CommandError::from_error(CommandErrorCode::Index, "Error while getting preview", &e)),
     }
 }
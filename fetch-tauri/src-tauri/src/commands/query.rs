@@ -1,51 +1,180 @@
-use std::error::Error;
-
-use fetch_core::files::query::QueryFiles;
+use camino::Utf8Path;
+use fetch_core::files::{
+    query::{self, QueryFiles},
+    volumes::{self, FileLocation, IndexedVolumes, Volume},
+};
 use serde::Serialize;
+use tracing::warn;
 
-use crate::utility::get_file_queryer;
+use crate::{error::CommandError, utility::get_file_queryer};
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, specta::Type)]
 pub struct FileQueryingResult {
     pub results_len: u32,
     pub changed_results: Vec<QueryResult>,
     pub cursor_id: Option<String>,
+    pub corrected_query: Option<String>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, specta::Type)]
 pub struct QueryResult {
     pub name: String,
     pub path: String,
     pub old_rank: Option<u32>,
     pub rank: u32,
     pub score: f32,
+    /// Label of the drive or network share the file is on, if it is not connected. The file cannot be opened until
+    /// it is connected again.
+    pub offline_volume: Option<String>,
+    /// The providers and channels that found the file, highest scoring first, eg. to show which parts of a PDF
+    /// matched
+    pub sources: Vec<MatchSource>,
+}
+
+#[derive(Debug, Serialize, specta::Type)]
+pub struct MatchSource {
+    /// Short name of the provider and channel, eg. pdf-text or pdf-image
+    pub label: String,
+    pub provider: String,
+    pub channel: String,
+    pub score: f32,
+}
+
+#[tauri::command]
+#[specta::specta]
+pub async fn query(
+    query: &str,
+    cursor_id: Option<&str>,
+) -> Result<FileQueryingResult, CommandError> {
+    query_incremental(query, cursor_id, 100).await
+}
+
+/// Continues a query by aggregating the given number of chunks into the cursor. Small numbers of chunks return
+/// results sooner, for windows that stream results in while the user types.
+#[tauri::command]
+#[specta::specta]
+pub async fn query_incremental(
+    query: &str,
+    cursor_id: Option<&str>,
+    num_chunks: u32,
+) -> Result<FileQueryingResult, CommandError> {
+    let file_queryer = get_file_queryer().await?;
+    let (indexed_volumes, mounted) = load_volumes();
+
+    file_queryer
+        .query_n(query, num_chunks, cursor_id)
+        .await
+        .map(|result| to_file_querying_result(result, &indexed_volumes, &mounted))
+        .map_err(|e| CommandError::index(&e))
 }
 
+/// Refines a query the user is still typing, reusing the previous query's cursor and results if the query extends
+/// the previous query. If the returned cursor id differs from the one given, the results start over.
 #[tauri::command]
-pub async fn query(query: &str, cursor_id: Option<&str>) -> Result<FileQueryingResult, String> {
+#[specta::specta]
+pub async fn query_refine(
+    query: &str,
+    previous_query: &str,
+    cursor_id: &str,
+    num_chunks: u32,
+) -> Result<FileQueryingResult, CommandError> {
     let file_queryer = get_file_queryer().await?;
+    let (indexed_volumes, mounted) = load_volumes();
 
     file_queryer
-        .query_n(query, 100, cursor_id)
+        .refine_n(query, previous_query, num_chunks, cursor_id)
         .await
-        .map(|result| FileQueryingResult {
-            results_len: result.results_len,
-            changed_results: result
-                .changed_results
+        .map(|result| to_file_querying_result(result, &indexed_volumes, &mounted))
+        .map_err(|e| CommandError::index(&e))
+}
+
+/// Finds the files most similar to an indexed file, eg. for the related files of a selected result
+#[tauri::command]
+#[specta::specta]
+pub async fn query_related(path: &str, num_results: u32) -> Result<Vec<QueryResult>, CommandError> {
+    let file_queryer = get_file_queryer().await?;
+    let (indexed_volumes, mounted) = load_volumes();
+
+    file_queryer
+        .query_related(Utf8Path::new(path), num_results)
+        .await
+        .map(|results| {
+            results
                 .into_iter()
-                .map(|query_result| QueryResult {
-                    name: query_result
-                        .path
-                        .file_name()
-                        .expect("Result path should have a name")
-                        .to_string(),
-                    path: query_result.path.to_string(),
-                    old_rank: query_result.old_rank,
-                    rank: query_result.rank,
-                    score: query_result.score,
-                })
-                .collect(),
-            cursor_id: result.cursor_id,
+                .map(|query_result| to_query_result(query_result, &indexed_volumes, &mounted))
+                .collect()
         })
-        .map_err(|e| format!("{}, source: {:?}", e, e.source()))
+        .map_err(|e| CommandError::index(&e))
+}
+
+// Private functions and variables
+
+/// Loads the volumes indexed files are on, following any that have been mounted somewhere else since
+fn load_volumes() -> (IndexedVolumes, Vec<Volume>) {
+    let mounted = volumes::mounted_volumes();
+    let mut indexed_volumes = IndexedVolumes::load().unwrap_or_else(|e| {
+        warn!(
+            "Could not load indexed volumes, not locating results: {:?}",
+            e
+        );
+        IndexedVolumes::default()
+    });
+    if !indexed_volumes.relink(&mounted).is_empty() {
+        indexed_volumes
+            .save()
+            .unwrap_or_else(|e| warn!("Could not save relinked volumes: {:?}", e));
+    }
+    (indexed_volumes, mounted)
+}
+
+fn to_file_querying_result(
+    result: query::FileQueryingResult,
+    indexed_volumes: &IndexedVolumes,
+    mounted: &[Volume],
+) -> FileQueryingResult {
+    FileQueryingResult {
+        results_len: result.results_len,
+        changed_results: result
+            .changed_results
+            .into_iter()
+            .map(|query_result| to_query_result(query_result, indexed_volumes, mounted))
+            .collect(),
+        cursor_id: result.cursor_id,
+        corrected_query: result.corrected_query,
+    }
+}
+
+/// Points the result at where the file can currently be found, or marks it as offline if its volume is not
+/// connected
+fn to_query_result(
+    query_result: query::QueryResult,
+    indexed_volumes: &IndexedVolumes,
+    mounted: &[Volume],
+) -> QueryResult {
+    let (path, offline_volume) = match indexed_volumes.locate(&query_result.path, mounted) {
+        FileLocation::Relinked { path } => (path, None),
+        FileLocation::Offline { volume_label } => (query_result.path, Some(volume_label)),
+        FileLocation::Available | FileLocation::Missing => (query_result.path, None),
+    };
+    QueryResult {
+        name: path
+            .file_name()
+            .expect("Result path should have a name")
+            .to_string(),
+        path: path.to_string(),
+        old_rank: query_result.old_rank,
+        rank: query_result.rank,
+        score: query_result.score,
+        offline_volume,
+        sources: query_result
+            .sources
+            .into_iter()
+            .map(|source| MatchSource {
+                label: source.label(),
+                provider: source.provider,
+                channel: source.channel,
+                score: source.score,
+            })
+            .collect(),
+    }
 }
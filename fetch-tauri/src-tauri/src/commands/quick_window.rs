@@ -0,0 +1,27 @@
+use tauri::{AppHandle, WebviewWindow};
+
+use crate::{
+    error::CommandError,
+    quick_window::{dismiss, is_pinned, set_pinned},
+};
+
+/// Hides the quick window and returns focus to the app that was focused before it was summoned
+#[tauri::command]
+#[specta::specta]
+pub fn dismiss_quick_window(window: WebviewWindow) -> Result<(), CommandError> {
+    dismiss(&window)
+        .map_err(|e| CommandError::platform(format!("Could not hide quick window: {}", e)))
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn get_quick_window_pinned(app: AppHandle) -> bool {
+    is_pinned(&app)
+}
+
+/// Pins the quick window so it stays open when it loses focus
+#[tauri::command]
+#[specta::specta]
+pub fn set_quick_window_pinned(app: AppHandle, pinned: bool) {
+    set_pinned(&app, pinned);
+}
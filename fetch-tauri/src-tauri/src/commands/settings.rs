@@ -0,0 +1,32 @@
+use tauri::AppHandle;
+
+use crate::{error::CommandError, settings::AppSettings, warmup};
+
+#[tauri::command]
+#[specta::specta]
+pub fn get_settings(app: AppHandle) -> Result<AppSettings, CommandError> {
+    AppSettings::load(&app).map_err(|e| CommandError::settings("Could not load settings", e))
+}
+
+/// Saves the settings, registering or unregistering the app to start at login if that changed, and applying the
+/// model keepalive
+#[tauri::command]
+#[specta::specta]
+pub fn update_settings(app: AppHandle, settings: AppSettings) -> Result<(), CommandError> {
+    let previous = AppSettings::load(&app).unwrap_or_default();
+
+    #[cfg(not(any(target_os = "android", target_os = "ios")))]
+    if previous.start_at_login != settings.start_at_login {
+        crate::autostart::set_start_at_login(&app, settings.start_at_login).map_err(|e| {
+            CommandError::platform(format!("Could not update start at login: {}", e))
+        })?;
+    }
+    #[cfg(any(target_os = "android", target_os = "ios"))]
+    let _ = previous;
+
+    warmup::apply_keepalive(&settings);
+
+    settings
+        .save(&app)
+        .map_err(|e| CommandError::settings("Could not save settings", e))
+}
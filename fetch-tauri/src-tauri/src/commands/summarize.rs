@@ -0,0 +1,13 @@
+use camino::Utf8Path;
+
+use crate::{error::CommandError, utility::get_file_summarizer};
+
+#[tauri::command]
+#[specta::specta]
+pub async fn summarize(path: &str) -> Result<String, CommandError> {
+    let summarizer = get_file_summarizer().await?;
+    summarizer
+        .summarize(Utf8Path::new(path))
+        .await
+        .map_err(|e| CommandError::index(&e))
+}
@@ -0,0 +1,51 @@
+use camino::Utf8Path;
+use fetch_core::files::{index::IndexFiles, tags::{UserTags, normalize_tag}};
+
+use crate::{error::CommandError, utility::get_file_indexer};
+
+#[tauri::command]
+#[specta::specta]
+pub async fn get_tags(path: &str) -> Result<Vec<String>, CommandError> {
+    let user_tags = UserTags::load().map_err(|e| CommandError::settings("Could not load tags", e))?;
+    Ok(user_tags.tags(Utf8Path::new(path)))
+}
+
+/// Adds a tag to a file, returning the file's tags
+#[tauri::command]
+#[specta::specta]
+pub async fn add_tag(path: &str, tag: &str) -> Result<Vec<String>, CommandError> {
+    let path = Utf8Path::new(path);
+    let tag = normalize_tag(tag).ok_or_else(|| {
+        CommandError::invalid_argument(format!("Invalid tag {:?}: tags may only contain letters, numbers, '-', '_' and '.'", tag))
+    })?;
+    let mut user_tags = UserTags::load().map_err(|e| CommandError::settings("Could not load tags", e))?;
+    if user_tags.add(path, &tag) {
+        update_tags(path, user_tags).await
+    } else {
+        Ok(user_tags.tags(path))
+    }
+}
+
+/// Removes a tag from a file, returning the file's remaining tags
+#[tauri::command]
+#[specta::specta]
+pub async fn remove_tag(path: &str, tag: &str) -> Result<Vec<String>, CommandError> {
+    let path = Utf8Path::new(path);
+    let mut user_tags = UserTags::load().map_err(|e| CommandError::settings("Could not load tags", e))?;
+    if user_tags.remove(path, tag) {
+        update_tags(path, user_tags).await
+    } else {
+        Ok(user_tags.tags(path))
+    }
+}
+
+/// Saves the changed tags and copies the file's tags onto its indexed chunks
+async fn update_tags(path: &Utf8Path, user_tags: UserTags) -> Result<Vec<String>, CommandError> {
+    user_tags.save().map_err(|e| CommandError::settings("Could not save tags", e))?;
+
+    let tags = user_tags.tags(path);
+    let file_indexer = get_file_indexer().await?;
+    file_indexer.tag(path, &tags).await.map_err(|e| CommandError::index(&e))?;
+
+    Ok(tags)
+}
@@ -0,0 +1,84 @@
+use chrono::{DateTime, Utc};
+use fetch_core::{files::timeline::{TimelineDate, TimelineGranularity}, index::provider::DateRange};
+use serde::Serialize;
+
+use crate::{error::CommandError, utility::get_file_timeline};
+
+#[derive(Debug, Serialize, specta::Type)]
+pub struct TimelineBucket {
+    /// RFC 3339 start of the bucket, inclusive
+    pub start: String,
+    /// RFC 3339 end of the bucket, exclusive
+    pub end: String,
+    pub num_files: usize,
+    pub top_files: Vec<TimelineFile>,
+}
+
+#[derive(Debug, Serialize, specta::Type)]
+pub struct TimelineFile {
+    pub name: String,
+    pub path: String,
+    pub date: String,
+}
+
+/// Buckets indexed files by date for the timeline scrubber.
+///
+/// * `date` - "created" or "modified"
+/// * `granularity` - "day", "week", "month" or "year"
+/// * `start`, `end` - Optional RFC 3339 dates to limit the timeline to
+#[tauri::command]
+#[specta::specta]
+pub async fn timeline(
+    date: &str,
+    granularity: &str,
+    start: Option<&str>,
+    end: Option<&str>,
+    top_files: usize,
+) -> Result<Vec<TimelineBucket>, CommandError> {
+    let date = match date {
+        "created" => TimelineDate::Created,
+        "modified" => TimelineDate::Modified,
+        _ => return Err(CommandError::invalid_argument(format!("Unknown timeline date: {}", date))),
+    };
+    let granularity = match granularity {
+        "day" => TimelineGranularity::Day,
+        "week" => TimelineGranularity::Week,
+        "month" => TimelineGranularity::Month,
+        "year" => TimelineGranularity::Year,
+        _ => return Err(CommandError::invalid_argument(format!("Unknown timeline granularity: {}", granularity))),
+    };
+    let range = DateRange {
+        start: start.map(parse_date).transpose()?,
+        end: end.map(parse_date).transpose()?,
+    };
+
+    let file_timeline = get_file_timeline().await?.with_top_files(top_files);
+    let buckets = file_timeline
+        .timeline(date, granularity, range)
+        .await
+        .map_err(|e| CommandError::index(&e))?;
+
+    Ok(buckets
+        .into_iter()
+        .map(|bucket| TimelineBucket {
+            start: bucket.start.to_rfc3339(),
+            end: bucket.end.to_rfc3339(),
+            num_files: bucket.num_files,
+            top_files: bucket
+                .top_files
+                .into_iter()
+                .map(|file| TimelineFile {
+                    name: file.path.file_name().unwrap_or(file.path.as_str()).to_string(),
+                    path: file.path.to_string(),
+                    date: file.date.to_rfc3339(),
+                })
+                .collect(),
+        })
+        .collect())
+}
+
+fn parse_date(date: &str) -> Result<DateTime<Utc>, CommandError> {
+    DateTime::parse_from_rfc3339(date)
+        .map(|d| d.with_timezone(&Utc))
+        .map_err(|e| CommandError::invalid_argument(format!("Invalid date {}: {}", date, e)))
+}
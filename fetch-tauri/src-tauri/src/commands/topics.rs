@@ -0,0 +1,44 @@
+use serde::Serialize;
+
+use crate::{error::CommandError, utility::get_topic_clusterer};
+
+#[derive(Debug, Serialize, specta::Type)]
+pub struct Topic {
+    pub terms: Vec<String>,
+    pub num_files: usize,
+    pub files: Vec<TopicFile>,
+}
+
+#[derive(Debug, Serialize, specta::Type)]
+pub struct TopicFile {
+    pub name: String,
+    pub path: String,
+}
+
+/// Groups the indexed files into topics for browsing, returning the most representative files of each topic
+#[tauri::command]
+#[specta::specta]
+pub async fn topics(num_topics: usize, files_per_topic: usize) -> Result<Vec<Topic>, CommandError> {
+    let clusterer = get_topic_clusterer().await?;
+    let topics = clusterer
+        .cluster_index(num_topics)
+        .await
+        .map_err(|e| CommandError::index(&e))?;
+
+    Ok(topics
+        .into_iter()
+        .map(|topic| Topic {
+            terms: topic.terms,
+            num_files: topic.files.len(),
+            files: topic
+                .files
+                .into_iter()
+                .take(files_per_topic)
+                .map(|path| TopicFile {
+                    name: path.file_name().unwrap_or(path.as_str()).to_string(),
+                    path: path.to_string(),
+                })
+                .collect(),
+        })
+        .collect())
+}
@@ -0,0 +1,8 @@
+use crate::tray_status;
+
+/// Takes the recent search picked from the tray, if the full window has not run it yet
+#[tauri::command]
+#[specta::specta]
+pub fn take_pending_search() -> Option<String> {
+    tray_status::take_pending_search()
+}
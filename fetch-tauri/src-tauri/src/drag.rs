@@ -0,0 +1,65 @@
+use std::{path::PathBuf, sync::Mutex};
+
+use drag::{DragItem, DragResult, Image, Options};
+use tauri::WebviewWindow;
+use tokio::sync::oneshot;
+use tracing::warn;
+
+/// Icon shown under the cursor when a file has no preview to show instead
+const DEFAULT_DRAG_ICON: &[u8] = include_bytes!("../icons/32x32.png");
+
+/// Starts a native drag of the file from the window, so it can be dropped into file managers and other apps as a
+/// real file. Resolves once the drag ends, with whether the file was dropped somewhere.
+pub async fn drag_file(window: &WebviewWindow, path: PathBuf, icon_path: Option<PathBuf>) -> Result<bool, String> {
+    let icon = match icon_path {
+        Some(icon_path) => Image::File(icon_path),
+        None => Image::Raw(DEFAULT_DRAG_ICON.to_vec()),
+    };
+
+    // The drop callback may be called from any thread once the drag ends
+    let (sender, receiver) = oneshot::channel();
+    let sender = Mutex::new(Some(sender));
+    let on_drop = move |result: DragResult, _| {
+        if let Some(sender) = sender.lock().expect("Drag sender lock should not be poisoned").take() {
+            let _ = sender.send(matches!(result, DragResult::Dropped));
+        }
+    };
+
+    // Drag sessions must be started from the UI thread on every platform
+    let drag_window = window.clone();
+    window
+        .run_on_main_thread(move || {
+            start_drag(&drag_window, DragItem::Files(vec![path]), icon, on_drop)
+                .unwrap_or_else(|e| warn!("Could not start native file drag: {}", e));
+        })
+        .map_err(|e| format!("Could not schedule native file drag, source: {}", e))?;
+
+    // The sender is dropped without sending if the drag could not be started
+    receiver
+        .await
+        .map_err(|_| "Native file drag could not be started".to_string())
+}
+
+// Private functions
+
+#[cfg(any(target_os = "macos", target_os = "windows"))]
+fn start_drag(
+    window: &WebviewWindow,
+    item: DragItem,
+    icon: Image,
+    on_drop: impl Fn(DragResult, drag::CursorPosition) + Send + 'static,
+) -> Result<(), String> {
+    drag::start_drag(window, item, icon, on_drop, Options::default()).map_err(|e| e.to_string())
+}
+
+#[cfg(target_os = "linux")]
+fn start_drag(
+    window: &WebviewWindow,
+    item: DragItem,
+    icon: Image,
+    on_drop: impl Fn(DragResult, drag::CursorPosition) + Send + 'static,
+) -> Result<(), String> {
+    // GTK drags start from the GTK window rather than the raw window handle
+    let gtk_window = window.gtk_window().map_err(|e| e.to_string())?;
+    drag::start_drag(&gtk_window, item, icon, on_drop, Options::default()).map_err(|e| e.to_string())
+}
@@ -0,0 +1,145 @@
+use std::{error::Error, fmt, io};
+
+use fetch_core::{
+    files::{
+        lock::IndexLockError,
+        query::{FileQueryingError, FileQueryingErrorType},
+    },
+    index::embedding::EmbeddingError,
+    previewable::PreviewError,
+};
+use serde::Serialize;
+
+/// What went wrong in a command, so the frontend can tell the user what to do about it rather than parse messages
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, specta::Type)]
+#[serde(rename_all = "snake_case")]
+pub enum CommandErrorCode {
+    /// An argument was missing or could not be understood, eg. an unknown preview size
+    InvalidArgument,
+    /// Settings or other data saved in the application data directory could not be loaded or saved
+    Settings,
+    /// A model the command needs is not installed, eg. the optional summarization model
+    ModelMissing,
+    /// The file or directory does not exist, or is on a volume that is not connected
+    PathNotFound,
+    /// The operating system does not permit the app to read or write the file or directory
+    PathNotPermitted,
+    /// Another process is writing to the index
+    IndexBusy,
+    /// The cursor of the query has expired, the query should be run again from the start
+    CursorExpired,
+    /// The index, or one of the stores it depends on, could not be opened
+    Initialization,
+    /// Indexing, querying or another operation on the index failed
+    Index,
+    /// The operating system failed to do something for the app, eg. open a file or copy to the clipboard
+    Platform,
+}
+
+/// Error returned by every command, serialized as `{ code, message, details }`
+#[derive(Debug, Clone, Serialize, specta::Type)]
+pub struct CommandError {
+    pub code: CommandErrorCode,
+    /// What went wrong, to show to the user
+    pub message: String,
+    /// The errors that caused it, for logs and bug reports
+    pub details: Option<String>,
+}
+
+impl CommandError {
+    pub fn new(code: CommandErrorCode, message: impl Into<String>) -> CommandError {
+        CommandError {
+            code,
+            message: message.into(),
+            details: None,
+        }
+    }
+
+    /// Describes the error, eg. "Could not open index providers: <error>", with the errors that caused it as the
+    /// details. The code is taken from the error, or what caused it, if it is one the frontend can act on, eg. a
+    /// missing model or a busy index, and is the given code otherwise.
+    pub fn from_error(
+        code: CommandErrorCode,
+        context: &str,
+        e: &(dyn Error + 'static),
+    ) -> CommandError {
+        let message = if context.is_empty() {
+            e.to_string()
+        } else {
+            format!("{}: {}", context, e)
+        };
+        let sources: Vec<String> = Sources(e.source())
+            .map(|source| source.to_string())
+            .collect();
+        CommandError {
+            code: Sources(Some(e)).find_map(error_code).unwrap_or(code),
+            message,
+            details: (!sources.is_empty()).then(|| sources.join(": ")),
+        }
+    }
+
+    pub fn invalid_argument(message: impl Into<String>) -> CommandError {
+        CommandError::new(CommandErrorCode::InvalidArgument, message)
+    }
+
+    pub fn settings(context: &str, e: impl fmt::Display) -> CommandError {
+        CommandError::new(CommandErrorCode::Settings, format!("{}: {}", context, e))
+    }
+
+    pub fn index(e: &(dyn Error + 'static)) -> CommandError {
+        CommandError::from_error(CommandErrorCode::Index, "", e)
+    }
+
+    pub fn platform(message: impl Into<String>) -> CommandError {
+        CommandError::new(CommandErrorCode::Platform, message)
+    }
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.details {
+            Some(details) => write!(f, "{}, source: {}", self.message, details),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+// Private functions and variables
+
+/// Iterates over an error and the errors that caused it
+struct Sources<'a>(Option<&'a (dyn Error + 'static)>);
+
+impl<'a> Iterator for Sources<'a> {
+    type Item = &'a (dyn Error + 'static);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let current = self.0?;
+        self.0 = current.source();
+        Some(current)
+    }
+}
+
+/// Maps the core errors the frontend can act on to their code
+fn error_code(e: &(dyn Error + 'static)) -> Option<CommandErrorCode> {
+    if let Some(IndexLockError::Busy { .. }) = e.downcast_ref::<IndexLockError>() {
+        return Some(CommandErrorCode::IndexBusy);
+    }
+    if let Some(FileQueryingError {
+        r#type: FileQueryingErrorType::CursorNotFound,
+        ..
+    }) = e.downcast_ref::<FileQueryingError>()
+    {
+        return Some(CommandErrorCode::CursorExpired);
+    }
+    if let Some(EmbeddingError::Initialization(_)) = e.downcast_ref::<EmbeddingError>() {
+        return Some(CommandErrorCode::ModelMissing);
+    }
+    if let Some(PreviewError::NotFound { .. }) = e.downcast_ref::<PreviewError>() {
+        return Some(CommandErrorCode::PathNotFound);
+    }
+    match e.downcast_ref::<io::Error>().map(io::Error::kind) {
+        Some(io::ErrorKind::NotFound) => Some(CommandErrorCode::PathNotFound),
+        Some(io::ErrorKind::PermissionDenied) => Some(CommandErrorCode::PathNotPermitted),
+        _ => None,
+    }
+}
@@ -0,0 +1,195 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        LazyLock, Mutex, MutexGuard,
+    },
+    time::Duration,
+};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use chrono::{DateTime, Utc};
+use fetch_core::files::privacy::PrivacyMode;
+use serde::Serialize;
+use tokio::sync::Notify;
+
+/// Throughput of a single index provider since the app started
+#[derive(Debug, Clone, Default, Serialize, specta::Type)]
+pub struct ProviderThroughput {
+    pub files_indexed: usize,
+    pub files_failed: usize,
+    /// Time spent indexing the files the provider handled, in seconds
+    pub busy_seconds: f64,
+}
+
+#[derive(Debug, Clone, Serialize, specta::Type)]
+pub struct IndexingError {
+    #[specta(type = String)]
+    pub path: Utf8PathBuf,
+    pub message: String,
+    pub time: DateTime<Utc>,
+}
+
+/// Why indexing was paused for low disk space, see pause_for_low_disk_space
+#[derive(Debug, Clone, Serialize, specta::Type)]
+pub struct LowDiskSpace {
+    #[specta(type = String)]
+    pub directory: Utf8PathBuf,
+    pub free_bytes: u64,
+    pub min_free_bytes: u64,
+}
+
+/// Snapshot of what indexing is doing, for the tray and the indexing dashboard
+#[derive(Debug, Clone, Serialize, specta::Type)]
+pub struct IndexStatus {
+    pub paused: bool,
+    /// Set while indexing is paused because the disk the index is on is running out of space
+    pub low_disk_space: Option<LowDiskSpace>,
+    /// Indexing is also suspended while privacy mode is on
+    pub privacy_mode: bool,
+    #[specta(type = Option<String>)]
+    pub current: Option<Utf8PathBuf>,
+    /// Files waiting to be indexed, in the order they will be indexed
+    #[specta(type = Vec<String>)]
+    pub queue: Vec<Utf8PathBuf>,
+    pub providers: HashMap<String, ProviderThroughput>,
+    /// Most recent first
+    pub recent_errors: Vec<IndexingError>,
+}
+
+/// Adds files to the end of the indexing queue
+pub fn enqueue(paths: &[Utf8PathBuf]) {
+    lock_state().queue.extend(paths.iter().cloned());
+}
+
+/// Takes the file off the queue as it starts being indexed. Returns false if the file is no longer queued, eg.
+/// because the queue was cleared, in which case it should not be indexed.
+pub fn start(path: &Utf8Path) -> bool {
+    let mut state = lock_state();
+    match state.queue.iter().position(|queued| queued == path) {
+        Some(position) => {
+            state.queue.remove(position);
+            state.current = Some(path.to_owned());
+            true
+        }
+        None => false,
+    }
+}
+
+/// Records how indexing the file went, attributing the time it took to every provider that handled it
+pub fn finish(
+    path: &Utf8Path,
+    indexed_providers: &[&str],
+    failed_providers: &[&str],
+    elapsed: Duration,
+    error: Option<String>,
+) {
+    let mut state = lock_state();
+    state.current = None;
+    for provider in indexed_providers {
+        let throughput = state.providers.entry(provider.to_string()).or_default();
+        throughput.files_indexed += 1;
+        throughput.busy_seconds += elapsed.as_secs_f64();
+    }
+    for provider in failed_providers {
+        let throughput = state.providers.entry(provider.to_string()).or_default();
+        throughput.files_failed += 1;
+        throughput.busy_seconds += elapsed.as_secs_f64();
+    }
+    if let Some(message) = error {
+        state.recent_errors.push_front(IndexingError {
+            path: path.to_owned(),
+            message,
+            time: Utc::now(),
+        });
+        state.recent_errors.truncate(MAX_RECENT_ERRORS);
+    }
+}
+
+/// Drops every file waiting to be indexed. The file currently being indexed is finished.
+pub fn clear_queue() {
+    lock_state().queue.clear();
+}
+
+/// Number of files queued or being indexed
+pub fn files_remaining() -> usize {
+    let state = lock_state();
+    state.queue.len() + usize::from(state.current.is_some())
+}
+
+pub fn status() -> IndexStatus {
+    let state = lock_state();
+    IndexStatus {
+        paused: is_paused(),
+        low_disk_space: state.low_disk_space.clone(),
+        privacy_mode: PrivacyMode::enabled(),
+        current: state.current.clone(),
+        queue: state.queue.iter().cloned().collect(),
+        providers: state.providers.clone(),
+        recent_errors: state.recent_errors.iter().cloned().collect(),
+    }
+}
+
+pub fn is_paused() -> bool {
+    PAUSED.load(Ordering::Relaxed)
+}
+
+pub fn set_paused(paused: bool) {
+    PAUSED.store(paused, Ordering::Relaxed);
+    if !paused {
+        lock_state().low_disk_space = None;
+        RESUMED.notify_waiters();
+    }
+}
+
+/// Pauses indexing because the disk the index is on is running out of space. Indexing stays paused until it is
+/// resumed, after space has been freed.
+pub fn pause_for_low_disk_space(low_disk_space: LowDiskSpace) {
+    lock_state().low_disk_space = Some(low_disk_space);
+    set_paused(true);
+}
+
+/// Whether indexing is paused, or suspended by privacy mode
+pub fn is_suspended() -> bool {
+    is_paused() || PrivacyMode::enabled()
+}
+
+/// Waits until indexing is resumed and privacy mode is off. Called before indexing each file.
+pub async fn wait_while_suspended() {
+    while is_suspended() {
+        let resumed = RESUMED.notified();
+        // Resuming between checking and waiting would otherwise be missed
+        if !is_suspended() {
+            break;
+        }
+        resumed.await;
+    }
+}
+
+/// Wakes indexing up to check whether it is still suspended, after privacy mode is turned off
+pub fn privacy_mode_changed() {
+    RESUMED.notify_waiters();
+}
+
+// Private functions and variables
+
+const MAX_RECENT_ERRORS: usize = 50;
+
+#[derive(Default)]
+struct State {
+    queue: VecDeque<Utf8PathBuf>,
+    current: Option<Utf8PathBuf>,
+    providers: HashMap<String, ProviderThroughput>,
+    recent_errors: VecDeque<IndexingError>,
+    low_disk_space: Option<LowDiskSpace>,
+}
+
+static STATE: LazyLock<Mutex<State>> = LazyLock::new(|| Mutex::new(State::default()));
+static PAUSED: AtomicBool = AtomicBool::new(false);
+static RESUMED: LazyLock<Notify> = LazyLock::new(Notify::new);
+
+fn lock_state() -> MutexGuard<'static, State> {
+    STATE
+        .lock()
+        .expect("Index status lock should not be poisoned")
+}
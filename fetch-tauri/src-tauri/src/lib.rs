@@ -1,14 +1,15 @@
 use std::error::Error;
 
 use camino::Utf8PathBuf;
-use fetch_core::{init_resources, init_indexing, init_querying};
+use fetch_core::init_resources;
 use tauri::{
-    menu::{IsMenuItem, Menu, MenuItem, PredefinedMenuItem},
+    menu::{CheckMenuItem, IsMenuItem, Menu, MenuItem, PredefinedMenuItem, Submenu},
     tray::{MouseButton, TrayIcon, TrayIconBuilder, TrayIconEvent},
-    App, AppHandle, Manager, WebviewUrl, WebviewWindow, WebviewWindowBuilder, WindowEvent,
+    App, AppHandle, Emitter, Manager, WebviewUrl, WebviewWindow, WebviewWindowBuilder, WindowEvent,
 };
+use tracing::{error, info, warn};
 
-use crate::utility::init_logger;
+use crate::{settings::AppSettings, utility::init_logger};
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
@@ -21,6 +22,10 @@ pub fn run() {
         builder = builder.plugin(tauri_plugin_global_shortcut::Builder::new().build());
     }
 
+    let specta_builder = commands::builder();
+    #[cfg(debug_assertions)]
+    commands::export_bindings(&specta_builder);
+
     builder.setup(|app| {
             init_logger();
 
@@ -43,53 +48,92 @@ pub fn run() {
             }
 
             if continue_execution {
-                // Set the resource directory with the first init call
-                println!("Warming up indexing model...");
-                // TODO: update once warming models api is finalized
-                init_indexing(vec![]);
-                // Second call doesn't need to set it again since fetch-core defines this as static setup
-                println!("Warming up querying model...");
-                init_querying(vec![]);
+                // Models are loaded in the background, windows are told when they are ready
+                let settings = AppSettings::load(app.handle()).unwrap_or_else(|e| {
+                    warn!("Could not load settings, using defaults: {:?}", e);
+                    AppSettings::default()
+                });
+                warmup::apply_keepalive(&settings);
+                warmup::warm_in_background(app.handle());
 
                 // Initialize system tray functionality
-                println!("Building tray...");
+                info!("Building tray...");
                 let _tray = build_tray(app)?;
 
                 // Register global shortcuts
-                println!("Registering global shortcuts...");
+                info!("Registering global shortcuts...");
                 register_shortcuts(app.handle())?;
 
                 // Uncomment to test quick window
                 //summon_quick_window(app.handle())?;
 
-                summon_full_window(app.handle())?;
+                // When started at login, stay in the tray until summoned
+                #[allow(unused_mut)]
+                let mut show_full_window = true;
+                #[cfg(not(any(target_os = "android", target_os = "ios")))]
+                {
+                    show_full_window = !autostart::started_in_background();
+                }
+                if show_full_window {
+                    summon_full_window(app.handle())?;
+                }
             }
 
             Ok(())
         })
-        .invoke_handler(tauri::generate_handler![
-            crate::commands::index::index,
-            crate::commands::open::open,
-            crate::commands::open_location::open_location,
-            crate::commands::preview::preview,
-            crate::commands::query::query,
-        ])
-        .on_window_event(|window, event| {
-            if let WindowEvent::CloseRequested { api, .. } = event {
+        .invoke_handler(specta_builder.invoke_handler())
+        .on_window_event(|window, event| match event {
+            WindowEvent::CloseRequested { api, .. } => {
                 if window.label() == "full" {
-                    // Hide the window instead of closing
-                    window.hide().expect("Could not hide full search window");
-                    // Prevent the application from closing
-                    api.prevent_close();
+                    let background_only = AppSettings::load(window.app_handle())
+                        .map(|settings| settings.background_only)
+                        .unwrap_or_else(|e| {
+                            warn!("Could not load settings, keeping app in the tray: {:?}", e);
+                            true
+                        });
+                    if background_only {
+                        // Hide the window instead of closing
+                        window.hide().expect("Could not hide full search window");
+                        // Prevent the application from closing
+                        api.prevent_close();
+                    } else {
+                        window.app_handle().exit(0);
+                    }
                 }
             }
+            #[cfg(not(any(target_os = "android", target_os = "ios")))]
+            WindowEvent::Focused(false) => {
+                if window.label() == "quick" {
+                    if let Some(quick_window) = window.app_handle().get_webview_window("quick") {
+                        quick_window::on_focus_lost(&quick_window);
+                    }
+                }
+            }
+            _ => {}
         })
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
 
 fn build_tray(app: &mut App) -> Result<TrayIcon, Box<dyn Error>> {
+    let status_item = MenuItem::with_id(app, "status", "Idle", false, None::<&str>)?;
+    let pause_item =
+        CheckMenuItem::with_id(app, "pause_indexing", "Pause indexing", true, false, None::<&str>)?;
+    let privacy_item = CheckMenuItem::with_id(
+        app,
+        "privacy_mode",
+        "Privacy mode",
+        true,
+        false,
+        Some("CmdOrCtrl+Alt+Shift+P"),
+    )?;
+    let recent_searches_menu = Submenu::with_id(app, "recent_searches", "Recent searches", true)?;
+
     let menu_items: Vec<Box<dyn IsMenuItem<_>>> = vec![
+        Box::new(status_item.clone()),
+        Box::new(pause_item.clone()),
+        Box::new(privacy_item.clone()),
+        Box::new(PredefinedMenuItem::separator(app)?),
         Box::new(MenuItem::with_id(
             app,
             "fetch",
@@ -104,12 +148,20 @@ fn build_tray(app: &mut App) -> Result<TrayIcon, Box<dyn Error>> {
             true,
             None::<&str>,
         )?),
+        Box::new(recent_searches_menu.clone()),
+        Box::new(MenuItem::with_id(
+            app,
+            "dashboard",
+            "Indexing Dashboard",
+            true,
+            None::<&str>,
+        )?),
         Box::new(PredefinedMenuItem::separator(app)?),
         Box::new(MenuItem::with_id(
             app,
             "settings",
             "Settings",
-            false,
+            true,
             None::<&str>,
         )?),
         Box::new(PredefinedMenuItem::separator(app)?),
@@ -123,7 +175,7 @@ fn build_tray(app: &mut App) -> Result<TrayIcon, Box<dyn Error>> {
             .collect::<Vec<_>>()
             .as_slice(),
     )?;
-    Ok(TrayIconBuilder::new()
+    let tray = TrayIconBuilder::new()
         .icon(
             app.default_window_icon()
                 .expect("App should have an icon")
@@ -136,7 +188,7 @@ fn build_tray(app: &mut App) -> Result<TrayIcon, Box<dyn Error>> {
                 button: MouseButton::Left,
                 ..
             } => {
-                println!("Tray icon double clicked");
+                info!("Tray icon double clicked");
                 // in this example, let's show and focus the main window when the tray is double clicked
                 let app = tray.app_handle();
                 summon_full_window(app).expect("Unable to instantiate full search window");
@@ -144,19 +196,44 @@ fn build_tray(app: &mut App) -> Result<TrayIcon, Box<dyn Error>> {
             _ => {}
         })
         .on_menu_event(|app, event| match event.id.as_ref() {
+            "pause_indexing" => {
+                index_status::set_paused(!index_status::is_paused());
+            }
+            "privacy_mode" => {
+                privacy::toggle_privacy_mode()
+                    .unwrap_or_else(|e| error!("Could not toggle privacy mode: {:?}", e));
+            }
+            id if id.starts_with(tray_status::RECENT_SEARCH_ID_PREFIX) => {
+                let search = id[tray_status::RECENT_SEARCH_ID_PREFIX.len()..]
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(tray_status::recent_search);
+                if let Some(search) = search {
+                    // The full window picks the search up when it loads, or on this event if it is already open
+                    tray_status::set_pending_search(search);
+                    summon_full_window(app).expect("Unable to summon full search window");
+                    app.emit_to("full", PENDING_SEARCH_EVENT_IDENTIFIER, ())
+                        .unwrap_or_else(|e: tauri::Error| {
+                            error!("Could not emit pending search event: {}", e)
+                        });
+                }
+            }
             "fetch" => {
                 summon_quick_window(app).expect("Unable to summon fetch window");
             }
             "search" => {
                 summon_full_window(app).expect("Unable to summon full search window");
             }
+            "dashboard" => {
+                summon_dashboard_window(app).expect("Unable to summon indexing dashboard window");
+            }
             "settings" => {
-                println!("settings menu item was clicked. Not yet implemented!");
+                summon_settings_window(app).expect("Unable to summon settings window");
             }
             "quit" => {
                 if let Some(main_window) = app.get_webview_window("full") {
                     main_window.destroy().unwrap_or_else(|e| {
-                        eprintln!(
+                        error!(
                             "Error while trying to destroy full search window before closing: {:?}",
                             e
                         )
@@ -166,10 +243,26 @@ fn build_tray(app: &mut App) -> Result<TrayIcon, Box<dyn Error>> {
             }
             _ => {}
         })
-        .build(app)?)
+        .build(app)?;
+
+    // Updated in the background with what indexing is doing
+    tray_status::spawn_tray_updater(
+        app.handle(),
+        tray_status::TrayItems {
+            tray: tray.clone(),
+            status: status_item,
+            pause_indexing: pause_item,
+            privacy_mode: privacy_item,
+            recent_searches: recent_searches_menu,
+        },
+    );
+
+    Ok(tray)
 }
 
 // Private functions
+const PENDING_SEARCH_EVENT_IDENTIFIER: &str = "pending_search";
+
 fn register_shortcuts(app: &AppHandle) -> Result<(), Box<dyn Error>> {
     #[cfg(not(any(target_os = "android", target_os = "ios")))]
     {
@@ -190,7 +283,7 @@ fn register_shortcuts(app: &AppHandle) -> Result<(), Box<dyn Error>> {
                 if shortcut == &fetch_shortcut {
                     match event.state() {
                         ShortcutState::Pressed => {
-                            println!("Fetching!");
+                            info!("Fetching!");
                             summon_quick_window(closure_app)
                                 .expect("Unable to summon fetch window");
                         }
@@ -199,6 +292,25 @@ fn register_shortcuts(app: &AppHandle) -> Result<(), Box<dyn Error>> {
                 }
             },
         )?;
+
+        let privacy_shortcut = Shortcut::new(
+            Some(Modifiers::CONTROL.union(Modifiers::ALT).union(Modifiers::SHIFT)),
+            Code::KeyP,
+        );
+
+        app.global_shortcut().on_shortcut(
+            privacy_shortcut,
+            move |_closure_app, shortcut, event| {
+                if shortcut == &privacy_shortcut && event.state() == ShortcutState::Pressed {
+                    match privacy::toggle_privacy_mode() {
+                        Ok(enabled) => {
+                            info!("Privacy mode turned {}", if enabled { "on" } else { "off" })
+                        }
+                        Err(e) => error!("Could not toggle privacy mode: {:?}", e),
+                    }
+                }
+            },
+        )?;
     }
 
     Ok(())
@@ -219,15 +331,60 @@ fn summon_full_window(app: &AppHandle) -> Result<WebviewWindow, Box<dyn Error>>
                 .center()
                 .focusable(true)
                 .focused(true)
+                // Results are dragged as native files, so drops onto collections arrive through the native
+                // file drop handler
+                .build()?,
+        )
+    }
+}
+
+fn summon_dashboard_window(app: &AppHandle) -> Result<WebviewWindow, Box<dyn Error>> {
+    if let Some(window) = app.get_webview_window("dashboard") {
+        window.unminimize()?;
+        window.show()?;
+        window.set_focus()?;
+        Ok(window)
+    } else {
+        Ok(
+            WebviewWindowBuilder::new(app, "dashboard", WebviewUrl::App("/dashboard".into()))
+                .title("Fetch Indexing Dashboard")
+                .inner_size(900.0, 700.0)
+                .resizable(true)
+                .center()
+                .focusable(true)
+                .focused(true)
+                .build()?,
+        )
+    }
+}
+
+fn summon_settings_window(app: &AppHandle) -> Result<WebviewWindow, Box<dyn Error>> {
+    if let Some(window) = app.get_webview_window("settings") {
+        window.unminimize()?;
+        window.show()?;
+        window.set_focus()?;
+        Ok(window)
+    } else {
+        Ok(
+            WebviewWindowBuilder::new(app, "settings", WebviewUrl::App("/settings".into()))
+                .title("Fetch Settings")
+                .inner_size(500.0, 400.0)
+                .resizable(true)
+                .center()
+                .focusable(true)
+                .focused(true)
                 .build()?,
         )
     }
 }
 
 fn summon_quick_window(app: &AppHandle) -> Result<WebviewWindow, Box<dyn Error>> {
+    // Models unloaded after being idle are loaded again while the user types
+    warmup::warm_in_background(app);
     if let Some(window) = app.get_webview_window("quick") {
         window.unminimize()?;
-        window.center()?;
+        #[cfg(not(any(target_os = "android", target_os = "ios")))]
+        quick_window::place(&window)?;
         window.show()?;
         window.set_focus()?;
         Ok(window)
@@ -252,12 +409,31 @@ fn summon_quick_window(app: &AppHandle) -> Result<WebviewWindow, Box<dyn Error>>
             builder = builder.transparent(true);
         }
 
-        Ok(builder.build()?)
+        let window = builder.build()?;
+        #[cfg(not(any(target_os = "android", target_os = "ios")))]
+        quick_window::place(&window)?;
+        Ok(window)
     }
 }
 
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+mod autostart;
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+mod clipboard;
 mod commands;
+mod error;
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+mod drag;
+mod index_status;
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+mod open_with;
+mod privacy;
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+mod quick_window;
+mod settings;
+mod tray_status;
 mod utility;
+mod warmup;
 
 #[cfg(not(any(target_os = "android", target_os = "ios")))]
 mod cli;
\ No newline at end of file
@@ -2,5 +2,6 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 fn main() {
+    fetch_core::run_sandbox_worker_if_requested();
     fetch_lib::run()
 }
@@ -0,0 +1,341 @@
+use std::{error::Error, path::Path};
+
+use serde::Serialize;
+
+/// An application registered with the operating system to open a type of file
+#[derive(Debug, Clone, Serialize, specta::Type)]
+pub struct OpenWithHandler {
+    /// Display name of the application
+    pub name: String,
+    /// Identifies the application when opening a file with it, eg. the path to an app bundle on OS X, an executable
+    /// on Windows or a desktop entry on Linux
+    pub id: String,
+}
+
+/// Lists the applications registered to open the file, sorted by name
+pub fn handlers_for(path: &Path) -> Result<Vec<OpenWithHandler>, Box<dyn Error>> {
+    #[cfg(target_os = "macos")]
+    let mut handlers = launch_services::handlers_for(path)?;
+    #[cfg(target_os = "windows")]
+    let mut handlers = shell_assoc::handlers_for(path)?;
+    #[cfg(target_os = "linux")]
+    let mut handlers = desktop_entries::handlers_for(path)?;
+
+    handlers.sort_by_key(|handler| handler.name.to_lowercase());
+    handlers.dedup_by(|a, b| a.id == b.id);
+    Ok(handlers)
+}
+
+/// Opens the file with the application, see OpenWithHandler::id
+pub fn open_with(path: &Path, handler_id: &str) -> Result<(), Box<dyn Error>> {
+    #[cfg(target_os = "macos")]
+    launch_services::open_with(path, handler_id)?;
+    #[cfg(target_os = "windows")]
+    shell_assoc::open_with(path, handler_id)?;
+    #[cfg(target_os = "linux")]
+    desktop_entries::open_with(path, handler_id)?;
+
+    Ok(())
+}
+
+// Private modules
+
+#[cfg(target_os = "macos")]
+mod launch_services {
+    use std::{
+        error::Error,
+        ffi::c_void,
+        os::unix::ffi::OsStrExt,
+        path::{Path, PathBuf},
+        process::{Command, Stdio},
+    };
+
+    use super::OpenWithHandler;
+
+    pub fn handlers_for(path: &Path) -> Result<Vec<OpenWithHandler>, Box<dyn Error>> {
+        let path_bytes = path.as_os_str().as_bytes();
+        let url = unsafe {
+            CFURLCreateFromFileSystemRepresentation(
+                std::ptr::null(),
+                path_bytes.as_ptr(),
+                path_bytes.len() as isize,
+                0,
+            )
+        };
+        if url.is_null() {
+            return Err(format!("Could not create a file url for {:?}", path).into());
+        }
+
+        let apps = unsafe { LSCopyApplicationURLsForURL(url, K_LS_ROLES_ALL) };
+        unsafe { CFRelease(url) };
+        // No applications are registered for the file type
+        if apps.is_null() {
+            return Ok(vec![]);
+        }
+
+        let mut handlers = vec![];
+        for i in 0..unsafe { CFArrayGetCount(apps) } {
+            let app_url = unsafe { CFArrayGetValueAtIndex(apps, i) };
+            let mut buffer = [0u8; 1024];
+            let converted = unsafe {
+                CFURLGetFileSystemRepresentation(
+                    app_url,
+                    1,
+                    buffer.as_mut_ptr(),
+                    buffer.len() as isize,
+                )
+            };
+            if converted == 0 {
+                continue;
+            }
+            let end = buffer.iter().position(|b| *b == 0).unwrap_or(buffer.len());
+            let app_path = PathBuf::from(String::from_utf8_lossy(&buffer[..end]).into_owned());
+            // App bundles are named after the app, eg. Preview.app
+            let name = app_path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            handlers.push(OpenWithHandler {
+                name,
+                id: app_path.to_string_lossy().into_owned(),
+            });
+        }
+        unsafe { CFRelease(apps) };
+
+        Ok(handlers)
+    }
+
+    pub fn open_with(path: &Path, handler_id: &str) -> Result<(), Box<dyn Error>> {
+        Command::new("open")
+            .arg("-a")
+            .arg(handler_id)
+            .arg(path)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()?;
+        Ok(())
+    }
+
+    // Private functions and variables
+
+    type CFURLRef = *const c_void;
+    type CFArrayRef = *const c_void;
+
+    const K_LS_ROLES_ALL: u32 = 0xFFFFFFFF;
+
+    #[link(name = "CoreFoundation", kind = "framework")]
+    extern "C" {
+        fn CFURLCreateFromFileSystemRepresentation(
+            alloc: *const c_void,
+            buffer: *const u8,
+            buffer_length: isize,
+            is_directory: u8,
+        ) -> CFURLRef;
+        fn CFURLGetFileSystemRepresentation(
+            url: CFURLRef,
+            resolve_against_base: u8,
+            buffer: *mut u8,
+            max_buffer_length: isize,
+        ) -> u8;
+        fn CFArrayGetCount(array: CFArrayRef) -> isize;
+        fn CFArrayGetValueAtIndex(array: CFArrayRef, index: isize) -> *const c_void;
+        fn CFRelease(cf: *const c_void);
+    }
+
+    #[link(name = "CoreServices", kind = "framework")]
+    extern "C" {
+        fn LSCopyApplicationURLsForURL(url: CFURLRef, roles: u32) -> CFArrayRef;
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod shell_assoc {
+    use std::{
+        error::Error,
+        path::Path,
+        process::{Command, Stdio},
+    };
+
+    use windows::{
+        core::{HSTRING, PWSTR},
+        Win32::{
+            System::Com::{
+                CoInitializeEx, CoTaskMemFree, CoUninitialize, COINIT_APARTMENTTHREADED,
+            },
+            UI::Shell::{IAssocHandler, SHAssocEnumHandlers, ASSOC_FILTER_RECOMMENDED},
+        },
+    };
+
+    use super::OpenWithHandler;
+
+    pub fn handlers_for(path: &Path) -> Result<Vec<OpenWithHandler>, Box<dyn Error>> {
+        let extension = match path.extension() {
+            Some(extension) => format!(".{}", extension.to_string_lossy()),
+            None => return Ok(vec![]),
+        };
+
+        let com_initialized = unsafe { CoInitializeEx(None, COINIT_APARTMENTTHREADED) }.is_ok();
+        let result = unsafe { enumerate_handlers(&extension) };
+        if com_initialized {
+            unsafe { CoUninitialize() };
+        }
+        result
+    }
+
+    pub fn open_with(path: &Path, handler_id: &str) -> Result<(), Box<dyn Error>> {
+        Command::new(handler_id)
+            .arg(path)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()?;
+        Ok(())
+    }
+
+    // Private functions
+
+    unsafe fn enumerate_handlers(extension: &str) -> Result<Vec<OpenWithHandler>, Box<dyn Error>> {
+        let enumerator = SHAssocEnumHandlers(&HSTRING::from(extension), ASSOC_FILTER_RECOMMENDED)?;
+        let mut handlers = vec![];
+        loop {
+            let mut fetched = [None::<IAssocHandler>];
+            let mut num_fetched = 0u32;
+            enumerator.Next(&mut fetched, Some(&mut num_fetched as *mut u32))?;
+            let handler = match (num_fetched, fetched[0].take()) {
+                (1, Some(handler)) => handler,
+                _ => break,
+            };
+
+            // The name of a handler is the path to its executable
+            let (id, name) = match (handler.GetName(), handler.GetUIName()) {
+                (Ok(id), Ok(name)) => (take_string(id), take_string(name)),
+                _ => continue,
+            };
+            handlers.push(OpenWithHandler { name, id });
+        }
+        Ok(handlers)
+    }
+
+    /// Copies a string allocated by the shell and frees it
+    unsafe fn take_string(string: PWSTR) -> String {
+        let owned = string.to_string().unwrap_or_default();
+        CoTaskMemFree(Some(string.0 as *const _));
+        owned
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod desktop_entries {
+    use std::{
+        collections::HashSet,
+        error::Error,
+        fs,
+        path::{Path, PathBuf},
+        process::{Command, Stdio},
+    };
+
+    use super::OpenWithHandler;
+
+    pub fn handlers_for(path: &Path) -> Result<Vec<OpenWithHandler>, Box<dyn Error>> {
+        let output = Command::new("xdg-mime")
+            .args(["query", "filetype"])
+            .arg(path)
+            .output()?;
+        if !output.status.success() {
+            return Err(format!("xdg-mime exited with {}", output.status).into());
+        }
+        let mime_type = String::from_utf8_lossy(&output.stdout).trim().to_owned();
+
+        let mut seen_ids = HashSet::new();
+        let mut handlers = vec![];
+        for dir in application_dirs() {
+            let entries = match fs::read_dir(&dir) {
+                Ok(entries) => entries,
+                Err(_) => continue,
+            };
+            for entry in entries.flatten() {
+                let entry_path = entry.path();
+                if entry_path.extension().is_none_or(|ext| ext != "desktop") {
+                    continue;
+                }
+                // Entries earlier in the search path override entries with the same file name later in it
+                if !seen_ids.insert(entry.file_name()) {
+                    continue;
+                }
+                if let Some(name) = handler_name(&entry_path, &mime_type) {
+                    handlers.push(OpenWithHandler {
+                        name,
+                        id: entry_path.to_string_lossy().into_owned(),
+                    });
+                }
+            }
+        }
+
+        Ok(handlers)
+    }
+
+    pub fn open_with(path: &Path, handler_id: &str) -> Result<(), Box<dyn Error>> {
+        Command::new("gio")
+            .arg("launch")
+            .arg(handler_id)
+            .arg(path)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()?;
+        Ok(())
+    }
+
+    // Private functions
+
+    /// Directories desktop entries are installed to, in order of precedence
+    fn application_dirs() -> Vec<PathBuf> {
+        let data_home = std::env::var("XDG_DATA_HOME")
+            .ok()
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| {
+                std::env::var("HOME")
+                    .ok()
+                    .map(|home| PathBuf::from(home).join(".local/share"))
+            });
+        let data_dirs = std::env::var("XDG_DATA_DIRS")
+            .ok()
+            .filter(|dirs| !dirs.is_empty())
+            .unwrap_or_else(|| "/usr/local/share:/usr/share".to_owned());
+
+        data_home
+            .into_iter()
+            .chain(data_dirs.split(':').map(PathBuf::from))
+            .map(|dir| dir.join("applications"))
+            .collect()
+    }
+
+    /// Returns the name of the application described by the desktop entry if it can open files of the mime type
+    fn handler_name(entry_path: &Path, mime_type: &str) -> Option<String> {
+        let contents = fs::read_to_string(entry_path).ok()?;
+        let mut name = None;
+        let mut handles_type = false;
+        let mut in_desktop_entry = false;
+        for line in contents.lines() {
+            let line = line.trim();
+            if line.starts_with('[') {
+                in_desktop_entry = line == "[Desktop Entry]";
+                continue;
+            }
+            if !in_desktop_entry {
+                continue;
+            }
+            match line.split_once('=') {
+                Some(("Name", value)) => name = Some(value.to_owned()),
+                Some(("MimeType", value)) => {
+                    handles_type = value.split(';').any(|t| t == mime_type)
+                }
+                Some(("NoDisplay" | "Hidden", "true")) => return None,
+                _ => {}
+            }
+        }
+        name.filter(|_| handles_type)
+    }
+}
@@ -0,0 +1,14 @@
+use std::error::Error;
+
+use fetch_core::files::privacy::PrivacyMode;
+
+use crate::index_status;
+
+/// Turns privacy mode on or off, returning whether it is now on
+pub fn toggle_privacy_mode() -> Result<bool, Box<dyn Error>> {
+    let mut privacy_mode = PrivacyMode::load()?;
+    let enabled = !privacy_mode.is_enabled();
+    privacy_mode.set_enabled(enabled).save()?;
+    index_status::privacy_mode_changed();
+    Ok(enabled)
+}
@@ -0,0 +1,188 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    fs,
+    path::PathBuf,
+    sync::{LazyLock, Mutex},
+};
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, PhysicalPosition, WebviewWindow};
+use tracing::warn;
+
+/// Moves the quick window to where it was last left on the monitor under the cursor, or near the top of that
+/// monitor if it has not been shown there before, and remembers which app was focused so it can be restored when
+/// the quick window is dismissed.
+pub fn place(window: &WebviewWindow) -> Result<(), Box<dyn Error>> {
+    remember_previous_app();
+
+    let app = window.app_handle();
+    let cursor = app.cursor_position()?;
+    let monitor = match app.monitor_from_point(cursor.x, cursor.y)? {
+        Some(monitor) => monitor,
+        None => return Ok(window.center()?),
+    };
+
+    let saved_position = monitor
+        .name()
+        .and_then(|name| with_settings(app, |settings| settings.positions.get(name).copied()));
+    let position = match saved_position {
+        Some((x, y)) => PhysicalPosition::new(x, y),
+        None => {
+            // Like a launcher, the window drops down from the upper part of the screen as results come in
+            let window_size = window.outer_size()?;
+            PhysicalPosition::new(
+                monitor.position().x
+                    + (monitor.size().width.saturating_sub(window_size.width) / 2) as i32,
+                monitor.position().y + (monitor.size().height / 4) as i32,
+            )
+        }
+    };
+    window.set_position(position)?;
+
+    Ok(())
+}
+
+/// Hides the quick window, remembering its position on its current monitor, and returns focus to the app that was
+/// focused before it was shown
+pub fn dismiss(window: &WebviewWindow) -> Result<(), Box<dyn Error>> {
+    let app = window.app_handle();
+    if let (Some(monitor), Ok(position)) = (window.current_monitor()?, window.outer_position()) {
+        if let Some(name) = monitor.name() {
+            with_settings(app, |settings| {
+                settings
+                    .positions
+                    .insert(name.clone(), (position.x, position.y))
+            });
+            save_settings(app);
+        }
+    }
+
+    window.hide()?;
+    restore_previous_app(app);
+
+    Ok(())
+}
+
+/// Dismisses the quick window when it loses focus, unless it has been pinned
+pub fn on_focus_lost(window: &WebviewWindow) {
+    if is_pinned(window.app_handle()) {
+        return;
+    }
+    dismiss(window).unwrap_or_else(|e| warn!("Could not hide quick window on focus loss: {:?}", e));
+}
+
+/// Whether the quick window stays open when it loses focus
+pub fn is_pinned(app: &AppHandle) -> bool {
+    with_settings(app, |settings| settings.pinned)
+}
+
+pub fn set_pinned(app: &AppHandle, pinned: bool) {
+    with_settings(app, |settings| settings.pinned = pinned);
+    save_settings(app);
+}
+
+// Private functions and variables
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct QuickWindowSettings {
+    pinned: bool,
+    /// Last position of the window on each monitor, keyed by monitor name
+    positions: HashMap<String, (i32, i32)>,
+}
+
+/// Loaded from disk the first time the settings are used
+static SETTINGS: LazyLock<Mutex<Option<QuickWindowSettings>>> = LazyLock::new(|| Mutex::new(None));
+
+fn with_settings<T>(app: &AppHandle, f: impl FnOnce(&mut QuickWindowSettings) -> T) -> T {
+    let mut settings = SETTINGS
+        .lock()
+        .expect("Quick window settings lock should not be poisoned");
+    let settings = settings.get_or_insert_with(|| load_settings(app));
+    f(settings)
+}
+
+fn settings_file_path(app: &AppHandle) -> Option<PathBuf> {
+    app.path()
+        .app_config_dir()
+        .map(|dir| dir.join("quick_window.json"))
+        .inspect_err(|e| warn!("Could not find app config directory: {:?}", e))
+        .ok()
+}
+
+fn load_settings(app: &AppHandle) -> QuickWindowSettings {
+    let path = match settings_file_path(app) {
+        Some(path) if path.exists() => path,
+        _ => return QuickWindowSettings::default(),
+    };
+    fs::read_to_string(&path)
+        .map_err(Box::<dyn Error>::from)
+        .and_then(|contents| Ok(serde_json::from_str(&contents)?))
+        .unwrap_or_else(|e| {
+            warn!(
+                "Could not load quick window settings, using defaults: {:?}",
+                e
+            );
+            QuickWindowSettings::default()
+        })
+}
+
+fn save_settings(app: &AppHandle) {
+    let path = match settings_file_path(app) {
+        Some(path) => path,
+        None => return,
+    };
+    let contents = with_settings(app, |settings| serde_json::to_string(settings));
+    let result = contents
+        .map_err(Box::<dyn Error>::from)
+        .and_then(|contents| {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            Ok(fs::write(&path, contents)?)
+        });
+    result.unwrap_or_else(|e| warn!("Could not save quick window settings: {:?}", e));
+}
+
+#[cfg(target_os = "windows")]
+static PREVIOUS_WINDOW: std::sync::atomic::AtomicIsize = std::sync::atomic::AtomicIsize::new(0);
+
+fn remember_previous_app() {
+    #[cfg(target_os = "windows")]
+    {
+        use windows::Win32::UI::WindowsAndMessaging::GetForegroundWindow;
+
+        let foreground = unsafe { GetForegroundWindow() };
+        PREVIOUS_WINDOW.store(foreground.0 as isize, std::sync::atomic::Ordering::Relaxed);
+    }
+}
+
+fn restore_previous_app(app: &AppHandle) {
+    #[cfg(target_os = "windows")]
+    {
+        use windows::Win32::{Foundation::HWND, UI::WindowsAndMessaging::SetForegroundWindow};
+
+        let _ = app;
+        let previous = PREVIOUS_WINDOW.swap(0, std::sync::atomic::Ordering::Relaxed);
+        if previous != 0 {
+            let _ = unsafe { SetForegroundWindow(HWND(previous as *mut _)) };
+        }
+    }
+
+    #[cfg(target_os = "macos")]
+    {
+        // Hiding the app hands focus back to the previously active app, but would hide the full window too
+        let full_window_visible = app
+            .get_webview_window("full")
+            .and_then(|window| window.is_visible().ok())
+            .unwrap_or(false);
+        if !full_window_visible {
+            app.hide()
+                .unwrap_or_else(|e| warn!("Could not hide app to restore previous app: {:?}", e));
+        }
+    }
+
+    // Window managers on Linux hand focus back to the previous window themselves
+    #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+    let _ = app;
+}
@@ -0,0 +1,53 @@
+use std::{error::Error, fs, path::PathBuf};
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+
+/// Preferences for how the app runs, edited from the settings window
+#[derive(Debug, Clone, Serialize, Deserialize, specta::Type)]
+#[serde(default)]
+pub struct AppSettings {
+    /// Starts Fetch in the tray when the user logs in
+    pub start_at_login: bool,
+    /// Keeps Fetch running in the tray when its windows are closed. Otherwise closing the full window quits.
+    pub background_only: bool,
+    /// Minutes the models may go unused before they are unloaded to reclaim memory. They are loaded again when the
+    /// quick window is summoned or a query needs them. With None or 0, models stay loaded.
+    pub model_keepalive_minutes: Option<u32>,
+}
+
+impl Default for AppSettings {
+    fn default() -> Self {
+        AppSettings {
+            start_at_login: false,
+            background_only: true,
+            model_keepalive_minutes: Some(30),
+        }
+    }
+}
+
+impl AppSettings {
+    /// Loads the settings, or the defaults if they have never been saved
+    pub fn load(app: &AppHandle) -> Result<AppSettings, Box<dyn Error>> {
+        let path = settings_file_path(app)?;
+        if !path.exists() {
+            return Ok(AppSettings::default());
+        }
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    pub fn save(&self, app: &AppHandle) -> Result<(), Box<dyn Error>> {
+        let path = settings_file_path(app)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+// Private functions
+
+fn settings_file_path(app: &AppHandle) -> Result<PathBuf, Box<dyn Error>> {
+    Ok(app.path().app_config_dir()?.join("settings.json"))
+}
@@ -0,0 +1,208 @@
+use std::{collections::VecDeque, error::Error, fs, sync::Mutex, thread, time::Duration};
+
+use fetch_core::{app_config::get_watchlist_file_path, files::privacy::PrivacyMode};
+use tauri::{
+    image::Image,
+    menu::{CheckMenuItem, MenuItem, Submenu},
+    tray::TrayIcon,
+    AppHandle, Wry,
+};
+use tracing::warn;
+
+use crate::index_status;
+
+/// Menu id prefix of the items in the recent searches submenu, followed by the index of the search
+pub const RECENT_SEARCH_ID_PREFIX: &str = "recent_search_";
+
+/// Most recent first
+static RECENT_SEARCHES: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+const MAX_RECENT_SEARCHES: usize = 5;
+
+/// Search picked from the tray that the full window has not run yet
+static PENDING_SEARCH: Mutex<Option<String>> = Mutex::new(None);
+
+/// Remembers a search the user found something with, for the tray's recent searches
+pub fn record_search(query: &str) {
+    let query = query.trim();
+    if query.is_empty() {
+        return;
+    }
+    let mut searches = RECENT_SEARCHES
+        .lock()
+        .expect("Recent searches lock should not be poisoned");
+    searches.retain(|search| search != query);
+    searches.push_front(query.to_owned());
+    searches.truncate(MAX_RECENT_SEARCHES);
+}
+
+pub fn recent_search(index: usize) -> Option<String> {
+    RECENT_SEARCHES
+        .lock()
+        .expect("Recent searches lock should not be poisoned")
+        .get(index)
+        .cloned()
+}
+
+pub fn set_pending_search(query: String) {
+    *PENDING_SEARCH
+        .lock()
+        .expect("Pending search lock should not be poisoned") = Some(query);
+}
+
+pub fn take_pending_search() -> Option<String> {
+    PENDING_SEARCH
+        .lock()
+        .expect("Pending search lock should not be poisoned")
+        .take()
+}
+
+/// The tray icon and the menu items of the tray that reflect what Fetch is doing
+pub struct TrayItems {
+    pub tray: TrayIcon<Wry>,
+    pub status: MenuItem<Wry>,
+    pub pause_indexing: CheckMenuItem<Wry>,
+    pub privacy_mode: CheckMenuItem<Wry>,
+    pub recent_searches: Submenu<Wry>,
+}
+
+/// Keeps the tray's icon, status line, checkboxes and recent searches submenu up to date in the background
+pub fn spawn_tray_updater(app: &AppHandle, items: TrayItems) {
+    let app = app.clone();
+    thread::spawn(move || {
+        let mut shown_searches: Option<Vec<String>> = None;
+        let mut shown_privacy_mode: Option<bool> = None;
+        loop {
+            let privacy_mode = PrivacyMode::enabled();
+
+            items
+                .status
+                .set_text(status_text(privacy_mode))
+                .unwrap_or_else(|e| warn!("Could not update tray status: {:?}", e));
+            items
+                .pause_indexing
+                .set_checked(index_status::is_paused())
+                .unwrap_or_else(|e| warn!("Could not update tray pause checkbox: {:?}", e));
+
+            if shown_privacy_mode != Some(privacy_mode) {
+                items
+                    .privacy_mode
+                    .set_checked(privacy_mode)
+                    .unwrap_or_else(|e| warn!("Could not update tray privacy checkbox: {:?}", e));
+                update_tray_icon(&app, &items.tray, privacy_mode)
+                    .unwrap_or_else(|e| warn!("Could not update tray icon: {:?}", e));
+                shown_privacy_mode = Some(privacy_mode);
+                // The recent searches are hidden or shown again
+                shown_searches = None;
+            }
+
+            // Recent searches are hidden while privacy mode is on
+            let searches: Vec<String> = if privacy_mode {
+                vec![]
+            } else {
+                RECENT_SEARCHES
+                    .lock()
+                    .expect("Recent searches lock should not be poisoned")
+                    .iter()
+                    .cloned()
+                    .collect()
+            };
+            if shown_searches.as_ref() != Some(&searches) {
+                update_recent_searches_menu(&app, &items.recent_searches, &searches)
+                    .unwrap_or_else(|e| warn!("Could not update tray recent searches: {:?}", e));
+                shown_searches = Some(searches);
+            }
+
+            thread::sleep(UPDATE_INTERVAL);
+        }
+    });
+}
+
+// Private functions and variables
+
+const UPDATE_INTERVAL: Duration = Duration::from_secs(2);
+
+fn status_text(privacy_mode: bool) -> String {
+    let remaining = index_status::files_remaining();
+    let watched = count_watched_folders();
+    let mut status = match (remaining, watched) {
+        (0, 0) => "Idle".to_string(),
+        (0, watched) => format!("Watching {} folders", watched),
+        (remaining, 0) => format!("Indexing {} files", remaining),
+        (remaining, watched) => {
+            format!("Indexing {} files, watching {} folders", remaining, watched)
+        }
+    };
+    if privacy_mode {
+        status.push_str(" (privacy mode)");
+    } else if index_status::is_paused() {
+        status.push_str(" (paused)");
+    }
+    status
+}
+
+/// Shows a greyed out icon while privacy mode is on
+fn update_tray_icon(
+    app: &AppHandle,
+    tray: &TrayIcon<Wry>,
+    privacy_mode: bool,
+) -> Result<(), Box<dyn Error>> {
+    let icon = app.default_window_icon().ok_or("App should have an icon")?;
+    if privacy_mode {
+        // Average the color channels into grey and halve the opacity
+        let rgba = icon
+            .rgba()
+            .chunks_exact(4)
+            .flat_map(|pixel| {
+                let grey = ((pixel[0] as u16 + pixel[1] as u16 + pixel[2] as u16) / 3) as u8;
+                [grey, grey, grey, pixel[3] / 2]
+            })
+            .collect::<Vec<_>>();
+        tray.set_icon(Some(Image::new_owned(rgba, icon.width(), icon.height())))?;
+        tray.set_tooltip(Some("Fetch (privacy mode)"))?;
+    } else {
+        tray.set_icon(Some(icon.clone()))?;
+        tray.set_tooltip(Some("Fetch"))?;
+    }
+    Ok(())
+}
+
+/// Folders watched by the file daemon, one per line of the watchlist
+fn count_watched_folders() -> usize {
+    fs::read_to_string(get_watchlist_file_path())
+        .map(|watchlist| {
+            watchlist
+                .lines()
+                .filter(|line| !line.trim().is_empty())
+                .count()
+        })
+        .unwrap_or(0)
+}
+
+fn update_recent_searches_menu(
+    app: &AppHandle,
+    menu: &Submenu<Wry>,
+    searches: &[String],
+) -> tauri::Result<()> {
+    for _ in 0..menu.items()?.len() {
+        menu.remove_at(0)?;
+    }
+    if searches.is_empty() {
+        menu.append(&MenuItem::with_id(
+            app,
+            "no_recent_searches",
+            "No recent searches",
+            false,
+            None::<&str>,
+        )?)?;
+    }
+    for (i, search) in searches.iter().enumerate() {
+        menu.append(&MenuItem::with_id(
+            app,
+            format!("{}{}", RECENT_SEARCH_ID_PREFIX, i),
+            search,
+            true,
+            None::<&str>,
+        )?)?;
+    }
+    Ok(())
+}
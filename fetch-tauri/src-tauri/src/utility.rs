@@ -1,101 +1,176 @@
-use std::error::Error;
-use std::sync::Arc;
+use std::sync::{Arc, LazyLock};
 
-use env_logger::Env;
-use fetch_core::app_config;
+use fetch_core::{app_config, telemetry};
+use fetch_core::files::batch::FileBatch;
+use fetch_core::files::collection::{CollectionItem, FileCollections};
+use fetch_core::files::details::FileInspector;
+use fetch_core::files::journal::IndexJournal;
 use fetch_core::files::pagination::QueryCursor;
+use fetch_core::files::query::{MultiQuery, OsSearchFallback, QueryPageCache, QueryPlanner, QueryPreprocessor, RankingBoosts, ScoreWeights};
+use fetch_core::files::summary::FileSummarizer;
+use fetch_core::files::timeline::FileTimeline;
+use fetch_core::files::topics::TopicClusterer;
 use fetch_core::files::{FileIndexer, FileQueryer};
-use fetch_core::index::provider::image::ImageIndexProvider;
-use fetch_core::index::provider::pdf::PdfIndexProvider;
+use fetch_core::index::embedding::embeddinggemma::EmbeddingGemmaEmbeddedChunkFile;
+use fetch_core::index::embedding::siglip2::Siglip2EmbeddedChunkFile;
+use fetch_core::index::provider::registry::ProviderRegistry;
+use fetch_core::index::summarize::Summarizer;
 use fetch_core::store::lancedb::LanceDBStore;
+use tracing::warn;
+
+use crate::error::{CommandError, CommandErrorCode};
+
+/// Pages of recent queries, shared by the file queryers of every command
+static PAGE_CACHE: LazyLock<Arc<QueryPageCache>> = LazyLock::new(|| {
+    QueryPageCache::from_settings().unwrap_or_else(|e| {
+        warn!("Could not load result cache settings, using the defaults: {}", e);
+        QueryPageCache::new()
+    })
+});
 
 pub fn init_logger() {
-    let env = Env::default()
-        .filter("FETCH_LOG");
-    env_logger::init_from_env(env);
+    telemetry::init().unwrap_or_else(|e| eprintln!("Failed to initialize logging: {:?}", e));
 }
 
-pub async fn get_file_queryer() -> Result<FileQueryer<LanceDBStore<QueryCursor>>, String> {
+pub async fn get_file_queryer() -> Result<FileQueryer<LanceDBStore<QueryCursor>>, CommandError> {
+    let data_dir = app_config::get_default_index_directory();
+    let registry = ProviderRegistry::open(&data_dir).await.map_err(|e| CommandError::from_error(CommandErrorCode::Initialization, "Could not open index providers", &e))?;
+    // Create the cursor store
+    let cursor_dir = app_config::get_default_cursor_directory();
+    let cursor_store = LanceDBStore::<QueryCursor>::local(cursor_dir.as_str(), "cursor".to_owned())
+        .await
+        .map_err(|e| CommandError::from_error(CommandErrorCode::Initialization, "Could not open lancedb store for cursors", &e))?;
+    let score_weights = ScoreWeights::from_settings().unwrap_or_else(|e| {
+        warn!("Could not load score weights from settings, weighting all results equally: {}", e);
+        ScoreWeights::default()
+    });
+    let ranking_boosts = RankingBoosts::from_settings().unwrap_or_else(|e| {
+        warn!("Could not load ranking boosts from settings, ranking by semantic score only: {}", e);
+        RankingBoosts::default()
+    });
+    let query_preprocessor = QueryPreprocessor::from_settings().unwrap_or_else(|e| {
+        warn!("Could not load query settings, using query terms as is: {}", e);
+        QueryPreprocessor::default()
+    });
+    let os_search_fallback = OsSearchFallback::from_settings().unwrap_or_else(|e| {
+        warn!("Could not load operating system search settings, not using it: {}", e);
+        OsSearchFallback::default()
+    });
+    let multi_query = MultiQuery::from_settings().unwrap_or_else(|e| {
+        warn!("Could not load multi query settings, using the defaults: {}", e);
+        MultiQuery::default()
+    });
+    let query_planner = QueryPlanner::from_settings().unwrap_or_else(|e| {
+        warn!("Could not load query planner settings, using the defaults: {}", e);
+        QueryPlanner::default()
+    });
+    Ok(FileQueryer::with(
+        registry.providers(),
+        cursor_store,
+    ).with_score_weights(score_weights)
+        .with_ranking_boosts(ranking_boosts)
+        .with_query_preprocessor(query_preprocessor)
+        .with_os_search_fallback(os_search_fallback)
+        .with_multi_query(multi_query)
+        .with_query_planner(query_planner)
+        .with_page_cache(PAGE_CACHE.clone()))
+}
+
+pub async fn get_file_summarizer() -> Result<FileSummarizer<LanceDBStore<EmbeddingGemmaEmbeddedChunkFile>>, CommandError> {
+    // The summarization model is optional and not bundled by default
+    let summarizer = Summarizer::new()
+        .map_err(|e| CommandError::from_error(CommandErrorCode::Initialization, "Summarization model is not available", &e))?;
+    let data_dir = app_config::get_default_index_directory();
+    let gemma_text_index = Arc::new(
+        LanceDBStore::local_full(data_dir.as_str(), "gemma_chunkfile".to_string())
+            .await
+            .map_err(|e| CommandError::from_error(CommandErrorCode::Initialization, "Could not open lancedb store", &e))?,
+    );
+    Ok(FileSummarizer::using(gemma_text_index, summarizer))
+}
+
+pub async fn get_file_collections() -> Result<FileCollections<LanceDBStore<CollectionItem>>, CommandError> {
+    let data_dir = app_config::get_default_index_directory();
+    let collection_store =
+        LanceDBStore::<CollectionItem>::local_with_filters(data_dir.as_str(), "collection".to_owned())
+            .await
+            .map_err(|e| CommandError::from_error(CommandErrorCode::Initialization, "Could not open lancedb store for collections", &e))?;
+    Ok(FileCollections::using(collection_store))
+}
+
+pub async fn get_file_inspector() -> Result<
+    FileInspector<LanceDBStore<Siglip2EmbeddedChunkFile>, LanceDBStore<EmbeddingGemmaEmbeddedChunkFile>>,
+    CommandError,
+> {
     let data_dir = app_config::get_default_index_directory();
-    // Create siglip store
     let siglip2_image_index = Arc::new(
         LanceDBStore::local_full(data_dir.as_str(), "siglip2_chunkfile".to_string())
             .await
-            .map_err(|e| {
-                format!(
-                    "Could not open lancedb store: {}, source: {}",
-                    e,
-                    e.source()
-                        .map(<dyn Error>::to_string)
-                        .unwrap_or("".to_string())
-                )
-            })?,
+            .map_err(|e| CommandError::from_error(CommandErrorCode::Initialization, "Could not open lancedb store", &e))?,
     );
     let gemma_text_index = Arc::new(
         LanceDBStore::local_full(data_dir.as_str(), "gemma_chunkfile".to_string())
             .await
-            .map_err(|e| {
-                format!(
-                    "Could not open lancedb store: {}, source: {}",
-                    e,
-                    e.source()
-                        .map(<dyn Error>::to_string)
-                        .unwrap_or("".to_string())
-                )
-            })?,
+            .map_err(|e| CommandError::from_error(CommandErrorCode::Initialization, "Could not open lancedb store", &e))?,
     );
-    // Create the cursor store
-    let cursor_store = LanceDBStore::<QueryCursor>::local(data_dir.as_str(), "cursor".to_owned())
-        .await
-        .map_err(|e| {
-            format!(
-                "Could not open lancedb store for cursors: {}. source: {}",
-                e,
-                e.source()
-                    .map(<dyn Error>::to_string)
-                    .unwrap_or("".to_string())
-            )
-        })?;
-    let basic_image = ImageIndexProvider::using(siglip2_image_index.clone());
-    let pdf = PdfIndexProvider::using(gemma_text_index, siglip2_image_index);
-    Ok(FileQueryer::with(
-        vec![Arc::new(basic_image), Arc::new(pdf)],
-        cursor_store,
-    ))
+    Ok(FileInspector::using(siglip2_image_index, gemma_text_index))
+}
+
+pub async fn get_file_timeline() -> Result<
+    FileTimeline<LanceDBStore<Siglip2EmbeddedChunkFile>, LanceDBStore<EmbeddingGemmaEmbeddedChunkFile>>,
+    CommandError,
+> {
+    let data_dir = app_config::get_default_index_directory();
+    let siglip2_image_index = Arc::new(
+        LanceDBStore::local_full(data_dir.as_str(), "siglip2_chunkfile".to_string())
+            .await
+            .map_err(|e| CommandError::from_error(CommandErrorCode::Initialization, "Could not open lancedb store", &e))?,
+    );
+    let gemma_text_index = Arc::new(
+        LanceDBStore::local_full(data_dir.as_str(), "gemma_chunkfile".to_string())
+            .await
+            .map_err(|e| CommandError::from_error(CommandErrorCode::Initialization, "Could not open lancedb store", &e))?,
+    );
+    Ok(FileTimeline::using(siglip2_image_index, gemma_text_index))
 }
 
-pub async fn get_file_indexer() -> Result<FileIndexer, String> {
+pub async fn get_topic_clusterer() -> Result<
+    TopicClusterer<LanceDBStore<Siglip2EmbeddedChunkFile>, LanceDBStore<EmbeddingGemmaEmbeddedChunkFile>>,
+    CommandError,
+> {
     let data_dir = app_config::get_default_index_directory();
     let siglip2_image_index = Arc::new(
         LanceDBStore::local_full(data_dir.as_str(), "siglip2_chunkfile".to_string())
             .await
-            .map_err(|e| {
-                format!(
-                    "Could not open lancedb store: {}, source: {}",
-                    e,
-                    e.source()
-                        .map(<dyn Error>::to_string)
-                        .unwrap_or("".to_string())
-                )
-            })?,
+            .map_err(|e| CommandError::from_error(CommandErrorCode::Initialization, "Could not open lancedb store", &e))?,
     );
     let gemma_text_index = Arc::new(
         LanceDBStore::local_full(data_dir.as_str(), "gemma_chunkfile".to_string())
             .await
-            .map_err(|e| {
-                format!(
-                    "Could not open lancedb store: {}, source: {}",
-                    e,
-                    e.source()
-                        .map(<dyn Error>::to_string)
-                        .unwrap_or("".to_string())
-                )
-            })?,
+            .map_err(|e| CommandError::from_error(CommandErrorCode::Initialization, "Could not open lancedb store", &e))?,
     );
-    let basic_image = ImageIndexProvider::using(siglip2_image_index.clone());
-    let pdf = PdfIndexProvider::using(gemma_text_index, siglip2_image_index);
-    Ok(FileIndexer::with(vec![
-        Arc::new(basic_image),
-        Arc::new(pdf),
-    ]))
+    Ok(TopicClusterer::using(siglip2_image_index, gemma_text_index))
+}
+
+pub async fn get_file_indexer() -> Result<FileIndexer, CommandError> {
+    if app_config::is_index_read_only() {
+        return Err(CommandError::new(
+            CommandErrorCode::Initialization,
+            "The index is read-only on this machine (read_only_index in data.toml)",
+        ));
+    }
+    let data_dir = app_config::get_default_index_directory();
+    let registry = ProviderRegistry::open(&data_dir).await.map_err(|e| CommandError::from_error(CommandErrorCode::Initialization, "Could not open index providers", &e))?;
+    let journal_store = LanceDBStore::local_with_filters(data_dir.as_str(), "index_journal".to_string())
+        .await
+        .map_err(|e| CommandError::from_error(CommandErrorCode::Initialization, "Could not open lancedb store", &e))?;
+    Ok(FileIndexer::with(registry.providers())
+        .with_journal(Arc::new(IndexJournal::using(journal_store)))
+        .with_provider_budgets(registry.budgets()))
+}
+
+pub async fn get_file_batch() -> Result<FileBatch<LanceDBStore<CollectionItem>>, CommandError> {
+    let file_indexer = get_file_indexer().await?;
+    let file_collections = get_file_collections().await?;
+    Ok(FileBatch::using(Arc::new(file_indexer), Arc::new(file_collections)))
 }
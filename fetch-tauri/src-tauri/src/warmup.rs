@@ -0,0 +1,60 @@
+use std::{sync::atomic::{AtomicBool, Ordering}, thread, time::Duration};
+
+use fetch_core::{set_model_keepalive, warm_models, ModelId, WarmupProgress};
+use tauri::{AppHandle, Emitter};
+use tracing::{error, info};
+
+use crate::settings::AppSettings;
+
+pub const WARMUP_EVENT_IDENTIFIER: &str = "models_warming";
+pub const READY_EVENT_IDENTIFIER: &str = "models_ready";
+
+/// Whether the models used for querying are loaded, so a query does not wait for them
+pub fn models_ready() -> bool {
+    ModelId::QUERYING.iter().all(ModelId::is_warm)
+}
+
+/// Loads the models used for querying, and then indexing, on a background thread if they are not loaded yet.
+/// Windows are sent a models_warming event after each model is loaded and a models_ready event once the querying
+/// models are, so the quick window can show that it is warming up rather than seem slow.
+pub fn warm_in_background(app: &AppHandle) {
+    if ModelId::QUERYING.iter().chain(ModelId::INDEXING.iter()).all(ModelId::is_warm) {
+        return;
+    }
+    if WARMING.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    let app = app.clone();
+    let spawned = thread::Builder::new()
+        .name("model-warmup".to_owned())
+        .spawn(move || {
+            info!("Warming up querying models...");
+            warm_models(&ModelId::QUERYING, |progress| emit_progress(&app, progress));
+            app.emit(READY_EVENT_IDENTIFIER, ())
+                .unwrap_or_else(|e: tauri::Error| error!("Could not emit models ready event: {}", e));
+            info!("Warming up indexing models...");
+            warm_models(&ModelId::INDEXING, |_| ());
+            WARMING.store(false, Ordering::SeqCst);
+        });
+    if let Err(e) = spawned {
+        error!("Could not start warming up models: {}", e);
+        WARMING.store(false, Ordering::SeqCst);
+    }
+}
+
+/// Applies the model keepalive from the settings, see AppSettings::model_keepalive_minutes
+pub fn apply_keepalive(settings: &AppSettings) {
+    set_model_keepalive(settings.model_keepalive_minutes
+        .filter(|minutes| *minutes > 0)
+        .map(|minutes| Duration::from_secs(minutes as u64 * 60)));
+}
+
+// Private functions and variables
+
+static WARMING: AtomicBool = AtomicBool::new(false);
+
+fn emit_progress(app: &AppHandle, progress: WarmupProgress) {
+    app.emit(WARMUP_EVENT_IDENTIFIER, progress)
+        .unwrap_or_else(|e: tauri::Error| error!("Could not emit model warmup event: {}", e));
+}
@@ -0,0 +1,41 @@
+// Commands reject with a CommandError, see src-tauri/src/error.rs and the generated bindings.ts
+export type CommandErrorCode =
+  | "invalid_argument"
+  | "settings"
+  | "model_missing"
+  | "path_not_found"
+  | "path_not_permitted"
+  | "index_busy"
+  | "cursor_expired"
+  | "initialization"
+  | "index"
+  | "platform";
+
+export interface CommandError {
+  code: CommandErrorCode;
+  message: string;
+  // The errors that caused it, for logs and bug reports
+  details: string | null;
+}
+
+// What the user can do about errors they can act on
+const ERROR_HINTS: Partial<Record<CommandErrorCode, string>> = {
+  model_missing: "The model this needs is not installed. Reinstall Fetch with the model to use this.",
+  path_not_found: "The file may have been moved or deleted, or its drive may not be connected.",
+  path_not_permitted: "Fetch does not have permission to access this file. Grant access in your system settings.",
+  index_busy: "Another Fetch process is writing to the index. Try again once it has finished.",
+  cursor_expired: "The search has expired. Run it again.",
+};
+
+export function isCommandError(e: unknown): e is CommandError {
+  return typeof e === "object" && e !== null && "code" in e && "message" in e;
+}
+
+// Message to show the user for an error thrown by a command or anything else
+export function errorMessage(e: unknown): string {
+  if (!isCommandError(e)) {
+    return `${e}`;
+  }
+  const hint = ERROR_HINTS[e.code];
+  return hint ? `${e.message}. ${hint}` : e.message;
+}
@@ -1,12 +1,25 @@
 import { json } from "@sveltejs/kit";
 import { invoke } from "@tauri-apps/api/core";
 import { untrack } from "svelte";
+import { errorMessage, isCommandError } from "$lib/errors";
 
 export interface ResolvedFileResult {
   rank: number;
   name: string;
   path: string;
   score: number;
+  // Label of the drive or network share the file is on, if it is not connected
+  offline_volume: string | null;
+  // Providers and channels that found the file, highest scoring first
+  sources: MatchSource[];
+}
+
+// A provider and channel that found a file, eg. label "pdf-text" for the text of a PDF
+export interface MatchSource {
+  label: string;
+  provider: string;
+  channel: string;
+  score: number;
 }
 
 // snake_case to match rust conventions
@@ -14,6 +27,7 @@ interface FileQueryingResult {
   results_len: number;
   changed_results: FileResult[];
   cursor_id: string | null;
+  corrected_query: string | null;
 }
 interface FileResult {
   rank: number;
@@ -21,6 +35,9 @@ interface FileResult {
   name: string;
   path: string;
   score: number;
+  // Label of the drive or network share the file is on, if it is not connected
+  offline_volume: string | null;
+  sources: MatchSource[];
 }
 
 export default class ReactiveBackgroundFetchQuery {
@@ -30,6 +47,11 @@ export default class ReactiveBackgroundFetchQuery {
   querying = $state<boolean>(false);
   maxPages = $state<number | undefined>(undefined);
   hasMore = $state<boolean>(true);
+  // The query after spelling correction, if it was corrected
+  correctedQuery = $state<string | null>(null);
+  // Number of chunks aggregated per query call, or undefined for the backend default. Fewer chunks
+  // per call stream results in sooner.
+  chunksPerQuery: number | undefined;
 
   private cursorId = $state<string | null>("initial");
   private fullResultsList = $state.raw<ResolvedFileResult[]>([]);
@@ -39,10 +61,11 @@ export default class ReactiveBackgroundFetchQuery {
     return this.fullResultsList.slice(start, end);
   });
 
-  constructor(query: string, resultsPerPage: number = 20, page: number = 1) {
+  constructor(query: string, resultsPerPage: number = 20, page: number = 1, chunksPerQuery?: number) {
     this.query = query;
     this.resultsPerPage = resultsPerPage;
     this.page = page;
+    this.chunksPerQuery = chunksPerQuery;
   }
 
   public nextPage() {
@@ -61,6 +84,41 @@ export default class ReactiveBackgroundFetchQuery {
     }
   }
 
+  // Moves on to new query terms the user is still typing. When they extend the current terms, the cursor and the
+  // results found so far are reused and updated in place rather than starting over.
+  public async refine(query: string) {
+    const previousQuery = this.query;
+    const cursorId = this.cursorId;
+    this.query = query;
+    if (cursorId === "initial" || cursorId === null) {
+      this.restart();
+      return;
+    }
+
+    this.querying = true;
+    try {
+      const result = await invoke<FileQueryingResult>("query_refine", {
+        query,
+        previousQuery,
+        cursorId,
+        numChunks: this.chunksPerQuery ?? 100,
+      });
+      if (result.cursor_id !== cursorId) {
+        // The query was not a refinement, or the cursor expired, so the results started over
+        this.fullResultsList = [];
+      }
+      this.processChangedResults(result.results_len, result.changed_results);
+      this.cursorId = result.cursor_id;
+      this.correctedQuery = result.corrected_query;
+      this.hasMore = this.cursorId !== null;
+      this.maxPages = this.hasMore ? undefined : Math.ceil(this.fullResultsList.length / this.resultsPerPage);
+    } catch (error) {
+      console.log("Error occurred while refining query: " + errorMessage(error));
+    }
+    this.querying = false;
+    await this.queryUntil(this.resultsPerPage * this.page);
+  }
+
   public get results(): ResolvedFileResult[] {
     return this.windowedResultsList;
   }
@@ -83,22 +141,36 @@ export default class ReactiveBackgroundFetchQuery {
     })
   }
 
+  private restart() {
+    this.cursorId = "initial";
+    this.fullResultsList = [];
+    this.hasMore = true;
+    this.maxPages = undefined;
+    this.correctedQuery = null;
+    this.queryUntil(this.resultsPerPage * this.page);
+  }
+
   private async queryUntil(numResults: number) {
     if (this.fullResultsList.length < numResults && this.hasMore) {
       this.querying = true;
       while (this.fullResultsList.length < numResults && this.hasMore) {
         try {
           console.log("querying");
-          const result = await invoke<FileQueryingResult>("query", {
-            query: this.query,
-            cursorId: this.cursorId === "initial" ? null : this.cursorId,
-          });
+          const cursorId = this.cursorId === "initial" ? null : this.cursorId;
+          const result = this.chunksPerQuery === undefined
+            ? await invoke<FileQueryingResult>("query", { query: this.query, cursorId })
+            : await invoke<FileQueryingResult>("query_incremental", {
+              query: this.query,
+              cursorId,
+              numChunks: this.chunksPerQuery,
+            });
 
           // Merge changed results into full list
           this.processChangedResults(result.results_len, result.changed_results);
 
           // Update cursor
           this.cursorId = result.cursor_id;
+          this.correctedQuery = result.corrected_query;
 
           // If we've reached the end, set maxPages
           if (this.cursorId === null) {
@@ -111,7 +183,13 @@ export default class ReactiveBackgroundFetchQuery {
             }
           }
         } catch (error) {
-          console.log("Error occurred while querying: " + error);
+          console.log("Error occurred while querying: " + errorMessage(error));
+          if (isCommandError(error) && error.code === "cursor_expired") {
+            // Cursors expire after a while, so a search left open starts over rather than stopping
+            this.querying = false;
+            this.restart();
+            return;
+          }
           break;
         }
       }
@@ -180,6 +258,8 @@ function placeItemAndResolveChain(
       name: current.name,
       path: current.path,
       score: current.score,
+      offline_volume: current.offline_volume,
+      sources: current.sources,
     };
 
     const nextResult: FileResult | undefined = displaced && moved_results_by_old_rank.get(displaced.rank);